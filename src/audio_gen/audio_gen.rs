@@ -1,19 +1,100 @@
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time;
+use std::time::SystemTime;
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
 
 use crate::audio_gen::get_sample;
 use crate::audio_gen::oscillator::OscillatorTables;
 use crate::common::constants::SAMPLE_RATE;
+use crate::effect::limiter::default_limiter;
 use crate::note::playback_note::PlaybackNote;
+use crate::sequence::fixed_time_note_sequence::FixedTimeNoteSequence;
+use crate::track::track_grid::TrackGrid;
 
-// TODO SUPPORT LOFI AND 32-BIT
-static WAV_SPEC: hound::WavSpec = hound::WavSpec {
-    channels: 2,
-    sample_rate: SAMPLE_RATE as u32,
-    bits_per_sample: 16,
-    sample_format: hound::SampleFormat::Int,
-};
+static DEFAULT_WAV_FORMAT: WavFormat = WavFormat::Int16;
+static MAX_INT24: i32 = (1 << 23) - 1;
+static SAMPLE_CACHE: LazyLock<Mutex<SampleCache>> =
+    LazyLock::new(|| Mutex::new(SampleCache::default()));
+// Most samples the sample cache holds across all its files, 256 MB, about 12 minutes of stereo
+const MAX_CACHED_SAMPLES: usize = 1 << 26;
+// Stream errors waiting to be reported, more than a frame's worth are dropped
+pub(crate) const STREAM_ERROR_BUFFER_SIZE: usize = 8;
+
+// Sample encoding of written WAV files, all at SAMPLE_RATE
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum WavFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+// Channels of written WAV files. Mono sums the stereo mix, to hear whether panned or phased
+// material collapses cleanly.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum WavChannels {
+    #[default]
+    Stereo,
+    Mono,
+}
+
+impl WavChannels {
+    fn count(self) -> u16 {
+        match self {
+            WavChannels::Stereo => 2,
+            WavChannels::Mono => 1,
+        }
+    }
+}
+
+impl WavFormat {
+    pub(crate) fn wav_spec(self) -> hound::WavSpec {
+        self.wav_spec_with_channels(WavChannels::Stereo)
+    }
+
+    pub(crate) fn wav_spec_with_channels(self, channels: WavChannels) -> hound::WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            WavFormat::Int16 => (16, hound::SampleFormat::Int),
+            WavFormat::Int24 => (24, hound::SampleFormat::Int),
+            WavFormat::Float32 => (32, hound::SampleFormat::Float),
+        };
+        hound::WavSpec {
+            channels: channels.count(),
+            sample_rate: SAMPLE_RATE as u32,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
+
+/// Error an output stream reported, such as its device going away
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamError(pub String);
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Error callback for an output stream, passing each error on to whoever reports them and
+/// flagging `device_lost` when the device has gone, such as an interface unplugged mid-session
+pub fn stream_error_handler(mut error_producer: HeapProducer<StreamError>,
+                            device_lost: Arc<AtomicBool>)
+                            -> impl FnMut(cpal::StreamError) + Send + 'static {
+    move |err| {
+        if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+            device_lost.store(true, Ordering::Relaxed);
+        }
+        // When full, the errors already waiting have yet to be reported
+        let _ = error_producer.push(StreamError(err.to_string()));
+    }
+}
 
 #[allow(dead_code)]
 pub(crate) fn gen_note_stream(playback_note: PlaybackNote, oscillator_tables: OscillatorTables) {
@@ -46,22 +127,248 @@ pub(crate) fn gen_notes_stream(playback_notes: Vec<PlaybackNote>,
                                  window_duration_ms);
 }
 
-// TODO PARAMETERIZE SAMPLE TYPE TO SUPPORT LOFI AND 32-BIT
+// The samples of a WAV file, normalized to -1.0..1.0 and interleaved when there is more than one
+// channel. Cloning shares the samples rather than copying them.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct AudioFileData {
+    pub(crate) samples: Arc<[f32]>,
+    pub(crate) num_channels: u16,
+}
+
+impl AudioFileData {
+    pub(crate) fn num_frames(&self) -> usize {
+        self.samples.len() / self.num_channels.max(1) as usize
+    }
+}
+
+// Integer files of any bit depth are scaled from their full range, float files are read as is
 #[allow(dead_code)]
-pub(crate) fn read_audio_file(file_path: &str) -> Vec<i16> {
+pub(crate) fn read_audio_file(file_path: &str) -> AudioFileData {
     let mut reader = hound::WavReader::open(file_path).unwrap();
-    let samples: Vec<i16> = reader.samples::<i16>().map(|s| s.unwrap()).collect();
-    samples
+    let spec = reader.spec();
+    let samples: Arc<[f32]> = match spec.sample_format {
+        hound::SampleFormat::Int => {
+            let full_scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader.samples::<i32>().map(|s| s.unwrap() as f32 / full_scale).collect()
+        }
+        hound::SampleFormat::Float => reader.samples::<f32>().map(|s| s.unwrap()).collect(),
+    };
+    AudioFileData {
+        samples,
+        num_channels: spec.channels,
+    }
+}
+
+#[derive(Debug)]
+struct CachedSample {
+    data: AudioFileData,
+    // When the file had last been modified as it was read, None if that isn't known
+    modified: Option<SystemTime>,
+    // The cache's use count when this was last asked for, the lowest going first to make room
+    last_used: u64,
 }
 
-// TODO PARAMETERIZE SAMPLE TYPE TO SUPPORT LOFI AND 32-BIT
+// Decoded sample files by path, so notes reusing a sample share its data. A file modified since it
+// was read is read again, and the least recently used files are dropped to keep the cache to its
+// most samples.
+#[derive(Debug)]
+pub(crate) struct SampleCache {
+    samples: HashMap<String, CachedSample>,
+    num_samples: usize,
+    max_samples: usize,
+    uses: u64,
+}
+
+impl Default for SampleCache {
+    fn default() -> Self {
+        Self::with_max_samples(MAX_CACHED_SAMPLES)
+    }
+}
+
+impl SampleCache {
+    pub(crate) fn with_max_samples(max_samples: usize) -> Self {
+        SampleCache { samples: HashMap::new(), num_samples: 0, max_samples, uses: 0 }
+    }
+
+    // The file's data if it was read when the file was last modified at `modified`
+    pub(crate) fn get(&mut self, file_path: &str, modified: Option<SystemTime>)
+                      -> Option<AudioFileData> {
+        self.uses += 1;
+        let cached = self.samples.get_mut(file_path)
+            .filter(|cached| cached.modified == modified)?;
+        cached.last_used = self.uses;
+        Some(cached.data.clone())
+    }
+
+    // Cache the data read from the file when it was last modified at `modified`. Data bigger
+    // than the whole cache isn't kept.
+    pub(crate) fn insert(&mut self, file_path: &str, modified: Option<SystemTime>,
+                         data: AudioFileData) {
+        if let Some(stale) = self.samples.remove(file_path) {
+            self.num_samples -= stale.data.samples.len();
+        }
+        let num_samples = data.samples.len();
+        if num_samples > self.max_samples {
+            return;
+        }
+        while self.num_samples + num_samples > self.max_samples {
+            let least_recently_used = self.samples.iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(file_path, _)| file_path.clone())
+                .unwrap();
+            let evicted = self.samples.remove(&least_recently_used).unwrap();
+            self.num_samples -= evicted.data.samples.len();
+        }
+        self.uses += 1;
+        self.num_samples += num_samples;
+        self.samples.insert(file_path.to_string(),
+                            CachedSample { data, modified, last_used: self.uses });
+    }
+}
+
+// read_audio_file(), only reading a file again once it has been modified. The file is read
+// without the cache locked, so loading one sample doesn't hold up notes using others.
+pub(crate) fn read_cached_audio_file(file_path: &str) -> AudioFileData {
+    let modified = std::fs::metadata(file_path).and_then(|metadata| metadata.modified()).ok();
+    let cached = SAMPLE_CACHE.lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(file_path, modified);
+    if let Some(data) = cached {
+        return data;
+    }
+
+    let data = read_audio_file(file_path);
+    SAMPLE_CACHE.lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(file_path, modified, data.clone());
+    data
+}
+
+// Samples are interleaved left, right and normalized to -1.0..1.0, and summed to one channel for
+// Mono. Integer formats are scaled to their full range, Float32 is written as is.
 #[allow(dead_code)]
-pub(crate) fn write_audio_file(file_path: &str, samples: Vec<f32>) {
-    let mut writer = hound::WavWriter::create(file_path, WAV_SPEC).unwrap();
-    for sample in samples {
-        writer.write_sample(sample.round() as i16).unwrap();
+pub(crate) fn write_audio_file(file_path: &str, samples: Vec<f32>, wav_format: WavFormat,
+                               channels: WavChannels) -> Result<(), hound::Error> {
+    let samples = match channels {
+        WavChannels::Stereo => samples,
+        WavChannels::Mono => sum_to_mono(&samples),
+    };
+    write_channel_samples(file_path, &samples, wav_format, channels)
+}
+
+// Write samples already laid out for the channels, interleaved when there are two. Fails if the
+// file can't be created or written, e.g. its directory is missing or the disk is full.
+fn write_channel_samples(file_path: &str, samples: &[f32], wav_format: WavFormat,
+                         channels: WavChannels) -> Result<(), hound::Error> {
+    let mut writer = hound::WavWriter::create(file_path,
+                                              wav_format.wav_spec_with_channels(channels))?;
+    for &sample in samples {
+        match wav_format {
+            WavFormat::Int16 =>
+                writer.write_sample((sample * i16::MAX as f32).round() as i16)?,
+            WavFormat::Int24 =>
+                writer.write_sample((sample * MAX_INT24 as f32).round() as i32)?,
+            WavFormat::Float32 => writer.write_sample(sample)?,
+        }
+    }
+    writer.finalize()
+}
+
+// Sum each interleaved left, right frame to one sample at 1/sqrt(2), so unrelated material in
+// the two channels keeps its power and a source panned hard to one side comes through 3 dB down,
+// the same as from the center of a constant-power pan. The same material in both channels comes
+// up 3 dB, and can go past -1.0..1.0 without normalizing. Material out of phase between the
+// channels cancels, as it would on a mono speaker.
+pub(crate) fn sum_to_mono(samples: &[f32]) -> Vec<f32> {
+    samples.chunks(2)
+        .map(|frame| frame.iter().sum::<f32>() * std::f32::consts::FRAC_1_SQRT_2)
+        .collect()
+}
+
+// Scale the buffer so its loudest sample is at target_peak. Silence is left unchanged.
+#[allow(dead_code)]
+pub(crate) fn normalize_samples(samples: &mut [f32], target_peak: f32) {
+    let peak = samples.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+    if peak == 0.0 {
+        return;
+    }
+    let gain = target_peak / peak;
+    for sample in samples.iter_mut() {
+        *sample *= gain;
+    }
+}
+
+// Subtract the mean so the buffer is centered on zero
+#[allow(dead_code)]
+pub(crate) fn remove_dc_offset(samples: &mut [f32]) {
+    if samples.is_empty() {
+        return;
+    }
+    let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+    for sample in samples.iter_mut() {
+        *sample -= mean;
+    }
+}
+
+// Render the whole grid offline, without real-time playback, and write it as a stereo WAV file,
+// or summed to mono, with any DC offset removed. If target_peak is set the output is normalized
+// to it before writing.
+#[allow(dead_code)]
+pub(crate) fn render_track_grid_to_wav(track_grid: &TrackGrid<FixedTimeNoteSequence>,
+                                       file_path: &str, target_peak: Option<f32>,
+                                       channels: WavChannels) -> Result<(), hound::Error> {
+    let mut samples: Vec<f32> = render_track_grid(track_grid).into_iter()
+        .flat_map(|(sample_l, sample_r)| [sample_l, sample_r])
+        .collect();
+    if channels == WavChannels::Mono {
+        samples = sum_to_mono(&samples);
+    }
+    remove_dc_offset(&mut samples);
+    if let Some(target_peak) = target_peak {
+        normalize_samples(&mut samples, target_peak);
+    }
+    write_channel_samples(file_path, &samples, DEFAULT_WAV_FORMAT, channels)
+}
+
+// Mix every track, each from time 0 to the end of its last note and any effect tail ringing
+// past it. Returns (left, right) frames kept within -1.0..1.0 by a master limiter.
+#[allow(dead_code)]
+pub(crate) fn render_track_grid(track_grid: &TrackGrid<FixedTimeNoteSequence>) -> Vec<(f32, f32)> {
+    let mut limiter = default_limiter();
+
+    // Each track's volume is already in its buffer
+    let buffers: Vec<Vec<(f32, f32)>> = track_grid.tracks.iter()
+        .map(|track| track.render_to_buffer())
+        .collect();
+    mix_tracks(&buffers, &vec![1.0; buffers.len()]).into_iter()
+        .map(|(sample_l, sample_r)| limiter.apply_effect_stereo(sample_l, sample_r))
+        .collect()
+}
+
+// Sum stereo buffers that all start at time 0, each scaled by its gain, padding the shorter
+// ones with silence to the length of the longest. A buffer without a gain is mixed at unity.
+pub(crate) fn mix_tracks(buffers: &[Vec<(f32, f32)>], gains: &[f32]) -> Vec<(f32, f32)> {
+    let num_frames = buffers.iter().map(Vec::len).max().unwrap_or(0);
+    let mut mix = vec![(0.0, 0.0); num_frames];
+    for (i, buffer) in buffers.iter().enumerate() {
+        let gain = gains.get(i).copied().unwrap_or(1.0);
+        for ((mix_l, mix_r), (sample_l, sample_r)) in mix.iter_mut().zip(buffer) {
+            *mix_l += gain * sample_l;
+            *mix_r += gain * sample_r;
+        }
+    }
+    mix
+}
+
+// After the note ends only its time-based effects are still sounding, so feed them silence
+pub(crate) fn get_tail_sample(playback_note: &mut PlaybackNote, sample_position: f32,
+                   note_sample_count: u64) -> (f32, f32) {
+    if playback_note.num_channels == 2 || playback_note.track_effects.num_channels == 2 {
+        playback_note.apply_effects_stereo(0.0, sample_position, note_sample_count)
+    } else {
+        let sample = playback_note.apply_effects(0.0, sample_position, note_sample_count);
+        (sample, sample)
     }
-    writer.finalize().unwrap();
 }
 
 //noinspection Duplicates
@@ -83,19 +390,20 @@ where
     };
 
     let channels = config.channels as usize;
-    let err_fn =
-        |err| eprintln!("an error occurred on the output audio stream: {}", err);
+    let (error_producer, error_consumer) =
+        HeapRb::<StreamError>::new(STREAM_ERROR_BUFFER_SIZE).split();
     let stream = device.build_output_stream(
         config,
         move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
             write_stream::<f32>(data, channels, &mut next_samples)
         },
-        err_fn,
+        stream_error_handler(error_producer, Arc::new(AtomicBool::new(false))),
         None
     ).unwrap();
     stream.play().unwrap();
 
     std::thread::sleep(time::Duration::from_millis(duration_ms.ceil() as u64));
+    report_stream_errors(error_consumer);
 }
 
 //noinspection Duplicates
@@ -115,19 +423,27 @@ fn gen_notes_stream_impl<T>(device: &cpal::Device, config: &cpal::StreamConfig,
     };
 
     let channels = config.channels as usize;
-    let err_fn =
-        |err| eprintln!("an error occurred on the output audio stream: {}", err);
+    let (error_producer, error_consumer) =
+        HeapRb::<StreamError>::new(STREAM_ERROR_BUFFER_SIZE).split();
     let stream = device.build_output_stream(
         config,
         move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
             write_stream::<f32>(data, channels, &mut next_samples)
         },
-        err_fn,
+        stream_error_handler(error_producer, Arc::new(AtomicBool::new(false))),
         None
     ).unwrap();
     stream.play().unwrap();
     
     std::thread::sleep(time::Duration::from_millis(note_duration_ms));
+    report_stream_errors(error_consumer);
+}
+
+// Errors the output stream reported while it played, printed once it has finished
+fn report_stream_errors(mut error_consumer: HeapConsumer<StreamError>) {
+    for error in error_consumer.pop_iter() {
+        eprintln!("an error occurred on the output audio stream: {}", error);
+    }
 }
 
 // Based on this https://github.com/RustAudio/cpal/issues/735  stereo output is interleaved samples
@@ -142,4 +458,376 @@ where
         output_frame[0] = T::from_sample::<f32>(next_sample_r);
         output_frame[1] = T::from_sample::<f32>(next_sample_l);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test_audio_gen {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use ringbuf::HeapRb;
+
+    use crate::audio_gen::audio_gen::{mix_tracks, normalize_samples, read_audio_file,
+                                      read_cached_audio_file, remove_dc_offset, render_track_grid,
+                                      render_track_grid_to_wav, stream_error_handler, sum_to_mono,
+                                      write_audio_file, AudioFileData, SampleCache, StreamError,
+                                      WavChannels, WavFormat};
+    use crate::common::constants::SAMPLE_RATE;
+    use crate::common::float_utils::assert_float_eq;
+    use crate::dsl::parser::parse_dsl;
+
+    fn temp_file_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("rosco_test_{}_{}.wav", name, std::process::id()))
+            .to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_stream_errors_are_fed_back() {
+        let (error_producer, mut error_consumer) = HeapRb::new(4).split();
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let mut handle_error = stream_error_handler(error_producer, Arc::clone(&device_lost));
+
+        handle_error(cpal::StreamError::BackendSpecific {
+            err: cpal::BackendSpecificError { description: "buffer underrun".to_string() },
+        });
+        let Some(StreamError(message)) = error_consumer.pop() else {
+            panic!("expected the error to be fed back");
+        };
+        assert!(message.contains("buffer underrun"));
+        assert!(!device_lost.load(Ordering::Relaxed));
+
+        handle_error(cpal::StreamError::DeviceNotAvailable);
+        assert!(error_consumer.pop().is_some());
+        assert!(device_lost.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_write_audio_file_formats() {
+        let samples = vec![0.0, 0.5, -0.5, 1.0, -1.0, 0.25];
+
+        let file_path = temp_file_path("int16");
+        write_audio_file(&file_path, samples.clone(), WavFormat::Int16, WavChannels::Stereo)
+            .unwrap();
+        let mut reader = hound::WavReader::open(&file_path).unwrap();
+        assert_eq!(reader.spec().bits_per_sample, 16);
+        assert_eq!(reader.spec().sample_format, hound::SampleFormat::Int);
+        assert_eq!(reader.spec().sample_rate, SAMPLE_RATE as u32);
+        let read: Vec<i16> = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+        assert_eq!(read, vec![0, 16384, -16384, i16::MAX, -i16::MAX, 8192]);
+        std::fs::remove_file(&file_path).unwrap();
+
+        let file_path = temp_file_path("int24");
+        write_audio_file(&file_path, samples.clone(), WavFormat::Int24, WavChannels::Stereo)
+            .unwrap();
+        let mut reader = hound::WavReader::open(&file_path).unwrap();
+        assert_eq!(reader.spec().bits_per_sample, 24);
+        assert_eq!(reader.spec().sample_format, hound::SampleFormat::Int);
+        let read: Vec<i32> = reader.samples::<i32>().map(|s| s.unwrap()).collect();
+        assert_eq!(read[3], (1 << 23) - 1);
+        assert_eq!(read[4], -((1 << 23) - 1));
+        std::fs::remove_file(&file_path).unwrap();
+
+        let file_path = temp_file_path("float32");
+        write_audio_file(&file_path, samples.clone(), WavFormat::Float32, WavChannels::Stereo)
+            .unwrap();
+        let mut reader = hound::WavReader::open(&file_path).unwrap();
+        assert_eq!(reader.spec().bits_per_sample, 32);
+        assert_eq!(reader.spec().sample_format, hound::SampleFormat::Float);
+        let read: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(read, samples);
+        std::fs::remove_file(&file_path).unwrap();
+    }
+
+    #[test]
+    fn test_write_audio_file_to_missing_directory_fails() {
+        let file_path = std::env::temp_dir()
+            .join(format!("rosco_test_missing_{}", std::process::id()))
+            .join("out.wav");
+
+        assert!(write_audio_file(file_path.to_str().unwrap(), vec![0.5, -0.5], WavFormat::Int16,
+                                 WavChannels::Stereo).is_err());
+    }
+
+    #[test]
+    fn test_render_track_grid_to_wav() {
+        // Two steps of 125ms each, so 250ms of audio
+        let track_grid = parse_dsl(r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 4
+            osc:sine:440.0:0.5:0
+            osc:square:220.0:0.5:1
+        "#).unwrap();
+        let file_path = temp_file_path("render_track_grid");
+        let file_path = file_path.as_str();
+
+        render_track_grid_to_wav(&track_grid, file_path, None, WavChannels::Stereo).unwrap();
+
+        let reader = hound::WavReader::open(file_path).unwrap();
+        assert_eq!(reader.spec().channels, 2);
+        assert_eq!(reader.spec().bits_per_sample, 16);
+        assert_eq!(reader.len(), 2 * 11025);
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    // A WAV of four frames, full scale up and down on the left and half scale on the right
+    fn write_fixture(name: &str, channels: u16, bits_per_sample: u16,
+                     sample_format: hound::SampleFormat) -> String {
+        let file_path = temp_file_path(name);
+        let spec = hound::WavSpec {
+            channels,
+            sample_rate: SAMPLE_RATE as u32,
+            bits_per_sample,
+            sample_format,
+        };
+        let mut writer = hound::WavWriter::create(&file_path, spec).unwrap();
+        let max = ((1_i64 << (bits_per_sample - 1)) - 1) as i32;
+        for frame in [1.0, -1.0, 0.5, 0.0] {
+            let channel_levels = [frame, frame / 2.0];
+            for level in channel_levels.iter().take(channels as usize) {
+                match sample_format {
+                    hound::SampleFormat::Float => writer.write_sample(*level as f32).unwrap(),
+                    hound::SampleFormat::Int if bits_per_sample == 8 =>
+                        writer.write_sample((level * max as f64).round() as i8).unwrap(),
+                    hound::SampleFormat::Int if bits_per_sample == 16 =>
+                        writer.write_sample((level * max as f64).round() as i16).unwrap(),
+                    hound::SampleFormat::Int =>
+                        writer.write_sample((level * max as f64).round() as i32).unwrap(),
+                }
+            }
+        }
+        writer.finalize().unwrap();
+        file_path
+    }
+
+    #[test]
+    fn test_read_audio_file_normalizes_every_format() {
+        let formats = [
+            (8, hound::SampleFormat::Int),
+            (16, hound::SampleFormat::Int),
+            (24, hound::SampleFormat::Int),
+            (32, hound::SampleFormat::Int),
+            (32, hound::SampleFormat::Float),
+        ];
+        for (bits_per_sample, sample_format) in formats {
+            let name = format!("read_{}_{:?}", bits_per_sample, sample_format);
+            let file_path = write_fixture(&name, 1, bits_per_sample, sample_format);
+            let audio_file_data = read_audio_file(&file_path);
+            std::fs::remove_file(&file_path).unwrap();
+
+            assert_eq!(audio_file_data.num_channels, 1);
+            assert_eq!(audio_file_data.num_frames(), 4);
+            // Within a step of the quietest format, 8 bits
+            for (sample, expected) in audio_file_data.samples.iter().zip([1.0, -1.0, 0.5, 0.0]) {
+                assert!((sample - expected).abs() < 0.01, "{} bit {:?}: {} != {}",
+                        bits_per_sample, sample_format, sample, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_stereo_audio_file() {
+        let file_path = write_fixture("read_stereo", 2, 16, hound::SampleFormat::Int);
+        let audio_file_data = read_audio_file(&file_path);
+        std::fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(audio_file_data.num_channels, 2);
+        assert_eq!(audio_file_data.num_frames(), 4);
+        assert_eq!(audio_file_data.samples.len(), 8);
+        // Interleaved, the right channel at half the left
+        assert!((audio_file_data.samples[2] + 1.0).abs() < 1e-3);
+        assert!((audio_file_data.samples[3] + 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_sample_cache_keeps_each_file_until_it_is_modified() {
+        let data = |num_samples| AudioFileData { samples: vec![0.1; num_samples].into(),
+                                                 num_channels: 1 };
+        let modified = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1000));
+        let mut sample_cache = SampleCache::default();
+        assert!(sample_cache.get("/samples/kick.wav", modified).is_none());
+
+        let kick = data(3);
+        sample_cache.insert("/samples/kick.wav", modified, kick.clone());
+        let kick_again = sample_cache.get("/samples/kick.wav", modified).unwrap();
+        assert!(Arc::ptr_eq(&kick.samples, &kick_again.samples));
+        // Modified since it was read, so it has to be read again
+        let later = modified.map(|modified| modified + Duration::from_secs(1));
+        assert!(sample_cache.get("/samples/kick.wav", later).is_none());
+    }
+
+    #[test]
+    fn test_sample_cache_drops_the_least_recently_used_files() {
+        let data = |num_samples| AudioFileData { samples: vec![0.1; num_samples].into(),
+                                                 num_channels: 1 };
+        let mut sample_cache = SampleCache::with_max_samples(10);
+        sample_cache.insert("kick.wav", None, data(4));
+        sample_cache.insert("snare.wav", None, data(4));
+        sample_cache.get("kick.wav", None).unwrap();
+
+        sample_cache.insert("hat.wav", None, data(4));
+        assert!(sample_cache.get("snare.wav", None).is_none());
+        assert!(sample_cache.get("kick.wav", None).is_some());
+        assert!(sample_cache.get("hat.wav", None).is_some());
+
+        // Too big to keep at all
+        sample_cache.insert("pad.wav", None, data(11));
+        assert!(sample_cache.get("pad.wav", None).is_none());
+        assert!(sample_cache.get("kick.wav", None).is_some());
+    }
+
+    #[test]
+    fn test_notes_share_a_cached_sample_until_it_changes() {
+        let file_path = temp_file_path("cached_sample");
+        write_audio_file(&file_path, vec![0.5, -0.5, 0.25, -0.25], WavFormat::Int16,
+                         WavChannels::Stereo).unwrap();
+
+        let first = read_cached_audio_file(&file_path);
+        let second = read_cached_audio_file(&file_path);
+        assert!(Arc::ptr_eq(&first.samples, &second.samples));
+        assert_eq!(first.num_frames(), 2);
+
+        write_audio_file(&file_path, vec![0.5, -0.5], WavFormat::Int16,
+                         WavChannels::Stereo).unwrap();
+        // Written within the same clock tick would look unmodified
+        std::fs::File::options().write(true).open(&file_path).unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        let rewritten = read_cached_audio_file(&file_path);
+        std::fs::remove_file(&file_path).unwrap();
+        assert_eq!(rewritten.num_frames(), 1);
+    }
+
+    #[test]
+    fn test_render_track_grid_to_mono_wav() {
+        let track_grid = parse_dsl(r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 4
+            osc:sine:440.0:0.5:0
+            osc:square:220.0:0.5:1
+        "#).unwrap();
+        let file_path = temp_file_path("render_track_grid_mono");
+        let file_path = file_path.as_str();
+
+        render_track_grid_to_wav(&track_grid, file_path, None, WavChannels::Mono).unwrap();
+
+        let reader = hound::WavReader::open(file_path).unwrap();
+        assert_eq!(reader.spec().channels, 1);
+        assert_eq!(reader.len(), 11025);
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(|sample| sample * sample).sum()
+    }
+
+    #[test]
+    fn test_sum_to_mono_keeps_hard_panned_sources() {
+        let sine = |frequency: f32, i: usize| {
+            0.5 * (2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE).sin()
+        };
+        // One source hard left and another hard right, a whole number of cycles of each
+        let (left, right): (Vec<f32>, Vec<f32>) = (0..SAMPLE_RATE as usize)
+            .map(|i| (sine(441.0, i), sine(882.0, i)))
+            .unzip();
+        let stereo: Vec<f32> = left.iter().zip(right.iter())
+            .flat_map(|(sample_l, sample_r)| [*sample_l, *sample_r])
+            .collect();
+
+        let mono = sum_to_mono(&stereo);
+        assert_eq!(mono.len(), left.len());
+        // Unrelated sources don't cancel, so the mono samples have the power the stereo ones had
+        let power = |samples: &[f32]| energy(samples) / samples.len() as f32;
+        assert!((power(&mono) - power(&stereo)).abs() / power(&stereo) < 1e-3);
+
+        // Each source is still there 3 dB down
+        let level_of = |source: &[f32]| {
+            mono.iter().zip(source).map(|(m, s)| m * s).sum::<f32>() / energy(source)
+        };
+        assert!((level_of(&left) - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+        assert!((level_of(&right) - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+
+        // The same source out of phase between the channels collapses to silence
+        let out_of_phase: Vec<f32> = left.iter().flat_map(|sample| [*sample, -sample]).collect();
+        assert!(sum_to_mono(&out_of_phase).iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn test_mix_tracks_sums_with_gains() {
+        let long = vec![(1.0, -1.0), (0.5, 0.25), (0.0, 1.0)];
+        let short = vec![(0.5, 0.5)];
+
+        let mix = mix_tracks(&[long, short], &[0.5, 2.0]);
+        // The short track only sounds on the first frame, silence after
+        assert_eq!(mix, vec![(1.5, 0.5), (0.25, 0.125), (0.0, 0.5)]);
+    }
+
+    #[test]
+    fn test_mix_tracks_edge_cases() {
+        assert!(mix_tracks(&[], &[]).is_empty());
+        // Without a gain a track is mixed at unity, and a gain of zero mutes it
+        let mix = mix_tracks(&[vec![(0.25, 0.5)], vec![(0.5, 0.5)]], &[0.0]);
+        assert_eq!(mix, vec![(0.5, 0.5)]);
+    }
+
+    #[test]
+    fn test_normalize_samples() {
+        let mut samples = vec![0.1, -0.2, 0.05, 0.0];
+        normalize_samples(&mut samples, 0.8);
+        assert_float_eq(samples[1], -0.8);
+        assert_float_eq(samples[0], 0.4);
+        assert_float_eq(samples[2], 0.2);
+        assert_float_eq(samples[3], 0.0);
+
+        let mut silence = vec![0.0; 4];
+        normalize_samples(&mut silence, 0.8);
+        assert_eq!(silence, vec![0.0; 4]);
+    }
+
+    #[test]
+    fn test_remove_dc_offset() {
+        let mut samples: Vec<f32> = (0..4410)
+            .map(|i| 0.3 + 0.5 * (2.0 * std::f32::consts::PI * i as f32 / 441.0).sin())
+            .collect();
+        remove_dc_offset(&mut samples);
+        let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+        assert!(mean.abs() < 1e-4);
+        assert!((samples[0] - 0.0).abs() < 1e-4);
+
+        let mut empty: Vec<f32> = Vec::new();
+        remove_dc_offset(&mut empty);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_render_track_grid_to_wav_normalized() {
+        let track_grid = parse_dsl(r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 4
+            osc:sine:440.0:0.1:0
+        "#).unwrap();
+        let file_path = temp_file_path("render_track_grid_normalized");
+        let file_path = file_path.as_str();
+
+        render_track_grid_to_wav(&track_grid, file_path, Some(0.5), WavChannels::Stereo)
+            .unwrap();
+
+        let mut reader = hound::WavReader::open(file_path).unwrap();
+        let peak = reader.samples::<i16>().map(|s| s.unwrap().unsigned_abs()).max().unwrap();
+        assert_eq!(peak, (0.5 * i16::MAX as f32).round() as u16);
+        std::fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_render_track_grid_is_bounded() {
+        let track_grid = parse_dsl(r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 4
+            osc:sine:440.0:1.0:0
+            osc:sine:440.0:1.0:0
+            osc:sine:440.0:1.0:0
+        "#).unwrap();
+
+        let frames = render_track_grid(&track_grid);
+        assert_eq!(frames.len(), 5512);
+        assert!(frames.iter().any(|(sample_l, _)| *sample_l > 0.5));
+        assert!(frames.iter().all(|(sample_l, sample_r)|
+            sample_l.abs() <= 1.0 && sample_r.abs() <= 1.0));
+    }
+}
@@ -1,7 +1,10 @@
+use std::f32::consts::PI;
+
 use crate::audio_gen::oscillator;
-use crate::audio_gen::oscillator::{get_gaussian_noise_sample, OscillatorTables};
+use crate::audio_gen::oscillator::{get_gaussian_noise_sample, NoiseGenerator, OscillatorTables};
 use crate::audio_gen::oscillator::Waveform;
-use crate::common::constants::NYQUIST_FREQUENCY;
+use crate::common::constants::{NYQUIST_FREQUENCY, SAMPLE_RATE};
+use crate::note::note::Note;
 // khz samples per second
 use crate::note::playback_note::{NoteType, PlaybackNote};
 
@@ -12,34 +15,40 @@ pub(crate) fn get_note_sample(playback_note: &mut PlaybackNote, osc_tables: &Osc
     if num_channels == 1 {
         num_channels = playback_note.track_effects.num_channels;
     }
+    // A stereo sample plays in stereo, whatever the note and track are set to
+    if playback_note.note_type == NoteType::Sample && playback_note.sampled_note.num_channels > 1 {
+        num_channels = 2;
+    }
+    let fade_gain = playback_note.fade_gain(sample_count);
     
     match playback_note.note_type {
         NoteType::Oscillator => {
+            let note = &playback_note.note;
+            let noise_generator = &mut playback_note.noise_generator;
+            // Normalize so blending waveforms or stacking unison voices doesn't raise the level of
+            // the note
+            let num_oscillators = note.waveforms.len().max(1) * note.unison_voices as usize;
+            let voice_gain = 1.0 / num_oscillators as f32;
             let mut sample = 0.0;
-            for waveform in playback_note.note.waveforms.clone() {
-                sample += match waveform {
-                    Waveform::GaussianNoise => get_gaussian_noise_sample(),
-                    Waveform::Noise => get_gaussian_noise_sample(), // Alias for GaussianNoise
-                    Waveform::Saw => oscillator::get_sample(
-                        &osc_tables.saw_table, playback_note.note.frequency, sample_count),
-                    Waveform::Sine => oscillator::get_sample(
-                        &osc_tables.sine_table, playback_note.note.frequency, sample_count),
-                    Waveform::Square => oscillator::get_sample(
-                        &osc_tables.square_table, playback_note.note.frequency, sample_count),
-                    Waveform::Triangle => oscillator::get_sample(
-                        &osc_tables.triangle_table, playback_note.note.frequency, sample_count),
+            for waveform in note.waveforms.iter() {
+                for voice in 0..note.unison_voices {
+                    let frequency = note.voice_frequency(voice);
+                    sample += voice_gain * get_waveform_sample(
+                        *waveform, frequency, note.duty_cycle,
+                        fm_phase_offset(note, frequency, sample_count), osc_tables,
+                        noise_generator, sample_count);
                 }
             }
 
             match num_channels {
                 1 => {
                     let sample = playback_note.apply_effects(
-                        playback_note.note.volume * sample, sample_position, sample_count);
+                        playback_note.note.volume * fade_gain * sample, sample_position, sample_count);
                     (sample, sample)
                 }
                 2 => {
                     playback_note.apply_effects_stereo(
-                        playback_note.note.volume * sample, sample_position, sample_count)
+                        playback_note.note.volume * fade_gain * sample, sample_position, sample_count)
                 }
                 _ => (0.0, 0.0)
             }
@@ -49,13 +58,14 @@ pub(crate) fn get_note_sample(playback_note: &mut PlaybackNote, osc_tables: &Osc
                 1 => {
                     let mut sample = playback_note.sampled_note.next_sample();
                     sample = playback_note.apply_effects(
-                        playback_note.note_volume() * sample, sample_position, sample_count);
+                        playback_note.note_volume() * fade_gain * sample, sample_position, sample_count);
                     (sample, sample)
                 }
                 2 => {
-                    let sample = playback_note.sampled_note.next_sample();
-                    playback_note.apply_effects_stereo(
-                        playback_note.note_volume() * sample, sample_position, sample_count)
+                    let (left, right) = playback_note.sampled_note.next_frame();
+                    let gain = playback_note.note_volume() * fade_gain;
+                    playback_note.apply_effects_stereo_frame(
+                        gain * left, gain * right, sample_position, sample_count)
                 }
                 _ => (0.0, 0.0)
             }
@@ -63,6 +73,43 @@ pub(crate) fn get_note_sample(playback_note: &mut PlaybackNote, osc_tables: &Osc
     }
 }
 
+// The note's phase offset shifted by the FM modulator, as a fraction of a cycle. Modulating the
+// carrier's phase by a sine at the modulator frequency adds sidebands spaced by that frequency.
+fn fm_phase_offset(note: &Note, frequency: f32, sample_count: u64) -> f32 {
+    if note.modulation_index == 0.0 {
+        return note.phase_offset;
+    }
+    let modulator_frequency = (frequency * note.modulator_ratio) as f64;
+    let modulator_phase = (modulator_frequency * sample_count as f64 / SAMPLE_RATE as f64) % 1.0;
+    let modulator_sample = (2.0 * PI * modulator_phase as f32).sin();
+    note.phase_offset + note.modulation_index * modulator_sample / (2.0 * PI)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn get_waveform_sample(waveform: Waveform, frequency: f32, duty_cycle: f32, phase_offset: f32,
+                       osc_tables: &OscillatorTables, noise_generator: &mut NoiseGenerator,
+                       sample_count: u64) -> f32 {
+    match waveform {
+        Waveform::GaussianNoise => get_gaussian_noise_sample(),
+        Waveform::Noise => get_gaussian_noise_sample(), // Alias for GaussianNoise
+        Waveform::PinkNoise | Waveform::BrownNoise => noise_generator.next_sample(waveform),
+        Waveform::Saw | Waveform::Square if osc_tables.antialiased =>
+            oscillator::get_sample_antialiased(waveform, frequency, phase_offset, sample_count),
+        Waveform::Saw => oscillator::get_sample_at_phase(
+            &osc_tables.saw_table, frequency, phase_offset, sample_count),
+        Waveform::Sine => oscillator::get_sample_at_phase(
+            &osc_tables.sine_table, frequency, phase_offset, sample_count),
+        Waveform::Square => oscillator::get_sample_at_phase(
+            &osc_tables.square_table, frequency, phase_offset, sample_count),
+        Waveform::Triangle => oscillator::get_sample_at_phase(
+            &osc_tables.triangle_table, frequency, phase_offset, sample_count),
+        Waveform::Pulse if osc_tables.antialiased => oscillator::get_pulse_sample_antialiased(
+            frequency, duty_cycle, phase_offset, sample_count),
+        Waveform::Pulse => oscillator::get_pulse_sample(
+            frequency, duty_cycle, phase_offset, sample_count),
+    }
+}
+
 pub(crate) fn get_notes_sample(playback_notes: &mut Vec<PlaybackNote>,
                                oscillator_tables: &OscillatorTables,
                                sample_position: f32, sample_count: u64) -> (f32, f32) {
@@ -92,3 +139,232 @@ pub(crate) fn get_notes_sample(playback_notes: &mut Vec<PlaybackNote>,
     (out_sample_l, out_sample_r)
 }
 
+#[cfg(test)]
+mod test_get_sample {
+    use crate::audio_gen::get_sample::get_note_sample;
+    use crate::audio_gen::oscillator::{OscillatorTables, Waveform};
+    use crate::common::constants::SAMPLE_RATE;
+    use crate::note::note::NoteBuilder;
+    use crate::note::playback_note::{NoteType, PlaybackNoteBuilder};
+    use crate::note::sampled_note::{default_sample_note, SampleLoop};
+
+    static WINDOW_SAMPLES: usize = 441;
+
+    // Peak level of each 10ms window over one second of the note
+    fn window_peaks(unison_voices: u8, detune_cents: f32) -> Vec<f32> {
+        let note = NoteBuilder::default()
+            .frequency(440.0)
+            .waveforms(vec![Waveform::Sine])
+            .unison_voices(unison_voices)
+            .detune_cents(detune_cents)
+            .build().unwrap();
+        let mut playback_note = PlaybackNoteBuilder::default()
+            .note(note)
+            .build().unwrap();
+        let osc_tables = OscillatorTables::new();
+
+        let samples: Vec<f32> = (0..SAMPLE_RATE as u64)
+            .map(|i| get_note_sample(&mut playback_note, &osc_tables, i as f32, i).0)
+            .collect();
+        samples.chunks(WINDOW_SAMPLES)
+            .map(|window| window.iter().fold(0.0, |peak: f32, sample| peak.max(sample.abs())))
+            .collect()
+    }
+
+    #[test]
+    fn test_unison_detune_beats() {
+        let single_voice = window_peaks(1, 10.0);
+        let unison = window_peaks(3, 10.0);
+
+        let range = |peaks: &[f32]| {
+            peaks.iter().cloned().fold(f32::MIN, f32::max) -
+                peaks.iter().cloned().fold(f32::MAX, f32::min)
+        };
+        // a single voice has a steady level, detuned voices drift in and out of phase
+        assert!(range(&single_voice) < 0.05);
+        assert!(range(&unison) > 0.3);
+        // normalized so the voices in phase don't exceed a single voice
+        assert!(unison.iter().all(|peak| *peak <= 1.0 + 1e-4));
+    }
+
+    fn waveform_samples(waveforms: Vec<Waveform>) -> Vec<f32> {
+        let note = NoteBuilder::default()
+            .frequency(440.0)
+            .waveforms(waveforms)
+            .build().unwrap();
+        let mut playback_note = PlaybackNoteBuilder::default()
+            .note(note)
+            .build().unwrap();
+        let osc_tables = OscillatorTables::new();
+        (0..WINDOW_SAMPLES as u64)
+            .map(|i| get_note_sample(&mut playback_note, &osc_tables, i as f32, i).0)
+            .collect()
+    }
+
+    #[test]
+    fn test_waveforms_are_blended() {
+        let sine = waveform_samples(vec![Waveform::Sine]);
+        let square = waveform_samples(vec![Waveform::Square]);
+        let blended = waveform_samples(vec![Waveform::Sine, Waveform::Square]);
+
+        // the mean of the waveforms, so the blend is no louder than either
+        for ((sine, square), blended) in sine.iter().zip(square.iter()).zip(blended.iter()) {
+            assert!((blended - (sine + square) / 2.0).abs() < 1e-5);
+        }
+        assert!(blended.iter().zip(sine.iter()).any(|(b, s)| (b - s).abs() > 0.1));
+        assert!(blended.iter().zip(square.iter()).any(|(b, s)| (b - s).abs() > 0.1));
+    }
+
+    #[test]
+    fn test_single_waveform_is_not_scaled() {
+        let sine = waveform_samples(vec![Waveform::Sine]);
+        let peak = sine.iter().fold(0.0, |peak: f32, sample| peak.max(sample.abs()));
+        assert!(peak > 0.99);
+    }
+
+    #[test]
+    fn test_phase_offset_inverts_sine() {
+        let osc_tables = OscillatorTables::new();
+        let mut playback_notes: Vec<_> = [0.0, 0.5].iter()
+            .map(|phase_offset| {
+                let note = NoteBuilder::default()
+                    .frequency(440.0)
+                    .waveforms(vec![Waveform::Sine])
+                    .phase_offset(*phase_offset)
+                    .build().unwrap();
+                PlaybackNoteBuilder::default().note(note).build().unwrap()
+            })
+            .collect();
+
+        // Half a cycle apart, so each sample of one is the negation of the other
+        for i in 0..100 {
+            let in_phase = get_note_sample(&mut playback_notes[0], &osc_tables, i as f32, i).0;
+            let out_of_phase = get_note_sample(&mut playback_notes[1], &osc_tables, i as f32, i).0;
+            assert!((in_phase + out_of_phase).abs() < 0.01);
+        }
+        assert!(get_note_sample(&mut playback_notes[0], &osc_tables, 10.0, 10).0 > 0.1);
+    }
+
+    // Magnitude of the DFT bin at frequency, for a window a whole number of cycles long
+    fn dft_magnitude(samples: &[f32], frequency: f32) -> f32 {
+        let (mut re, mut im) = (0.0f64, 0.0f64);
+        for (i, sample) in samples.iter().enumerate() {
+            let angle = 2.0 * std::f64::consts::PI * frequency as f64 * i as f64 /
+                SAMPLE_RATE as f64;
+            re += *sample as f64 * angle.cos();
+            im -= *sample as f64 * angle.sin();
+        }
+        ((re * re + im * im).sqrt() / samples.len() as f64) as f32
+    }
+
+    #[test]
+    fn test_fm_adds_sidebands_at_modulator_spacing() {
+        // 1000 Hz carrier, 200 Hz modulator
+        let note = NoteBuilder::default()
+            .frequency(1000.0)
+            .waveforms(vec![Waveform::Sine])
+            .modulator_ratio(0.2)
+            .modulation_index(1.5)
+            .build().unwrap();
+        let mut playback_note = PlaybackNoteBuilder::default()
+            .note(note)
+            .build().unwrap();
+        let osc_tables = OscillatorTables::new();
+        // 100ms, a whole number of cycles of every component
+        let samples: Vec<f32> = (0..(SAMPLE_RATE / 10.0) as u64)
+            .map(|i| get_note_sample(&mut playback_note, &osc_tables, i as f32, i).0)
+            .collect();
+
+        for sideband in [600.0, 800.0, 1200.0, 1400.0] {
+            assert!(dft_magnitude(&samples, sideband) > 0.05);
+        }
+        // nothing between the sidebands
+        for gap in [700.0, 900.0, 1100.0, 1300.0] {
+            assert!(dft_magnitude(&samples, gap) < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_fade_ramps_note_ends() {
+        let note = NoteBuilder::default()
+            .frequency(441.0)
+            .waveforms(vec![Waveform::Square])
+            .start_time_ms(0.0)
+            .end_time_ms(100.0)
+            .build().unwrap();
+        // 100ms note with a 5ms fade
+        let mut playback_note = PlaybackNoteBuilder::default()
+            .note(note)
+            .playback_sample_start_time(0)
+            .playback_sample_end_time(4410)
+            .fade_ms(5.0)
+            .build().unwrap();
+        let osc_tables = OscillatorTables::new();
+        let levels: Vec<f32> = (0..4410)
+            .map(|i| get_note_sample(&mut playback_note, &osc_tables, i as f32, i).0.abs())
+            .collect();
+
+        // 220.5 fade samples, the level climbs from zero and falls back to zero
+        assert_eq!(levels[0], 0.0);
+        assert!(levels[1] < 0.01);
+        assert!((levels[110] - 0.5).abs() < 0.01);
+        assert!(levels[..221].windows(2).all(|pair| pair[1] >= pair[0]));
+        assert_eq!(levels[2205], 1.0);
+        assert!((levels[4410 - 111] - 0.5).abs() < 0.01);
+        assert!(levels[4410 - 221..].windows(2).all(|pair| pair[1] <= pair[0]));
+        assert_eq!(levels[4409], 0.0);
+    }
+
+    #[test]
+    fn test_fade_follows_the_note_across_windows() {
+        let note = NoteBuilder::default()
+            .frequency(441.0)
+            .waveforms(vec![Waveform::Square])
+            .start_time_ms(0.0)
+            .end_time_ms(100.0)
+            .build().unwrap();
+        // The second half of the 100ms note, played in a window of its own from 50ms, with the
+        // sample count starting again at 0
+        let mut playback_note = PlaybackNoteBuilder::default()
+            .note(note)
+            .playback_start_time_ms(50.0)
+            .playback_end_time_ms(100.0)
+            .playback_sample_start_time(0)
+            .playback_sample_end_time(4410)
+            .fade_ms(5.0)
+            .build().unwrap();
+        let osc_tables = OscillatorTables::new();
+        let levels: Vec<f32> = (0..2205)
+            .map(|i| get_note_sample(&mut playback_note, &osc_tables, i as f32, i).0.abs())
+            .collect();
+
+        // No fade in at the start of the window, only the fade out at the end of the note
+        assert_eq!(levels[0], 1.0);
+        assert!((levels[2205 - 111] - 0.5).abs() < 0.01);
+        assert_eq!(levels[2204], 0.0);
+    }
+
+    #[test]
+    fn test_looped_sample_sustains_for_the_note() {
+        let osc_tables = OscillatorTables::new();
+        // A 10ms sample under a 100ms note
+        let levels = |loop_mode| {
+            let mut sampled_note = default_sample_note();
+            sampled_note.set_sample_buf(&[0.5; 441]);
+            sampled_note.loop_mode = loop_mode;
+            let mut playback_note = PlaybackNoteBuilder::default()
+                .note_type(NoteType::Sample)
+                .sampled_note(sampled_note)
+                .playback_sample_start_time(0)
+                .playback_sample_end_time(4410)
+                .fade_ms(0.0)
+                .build().unwrap();
+            (0..4410)
+                .map(|i| get_note_sample(&mut playback_note, &osc_tables, i as f32, i).0.abs())
+                .collect::<Vec<f32>>()
+        };
+
+        assert!(levels(SampleLoop::OneShot)[441..].iter().all(|level| *level == 0.0));
+        assert!(levels(SampleLoop::Loop { crossfade_ms: 2.0 }).iter().all(|level| *level > 0.0));
+    }
+}
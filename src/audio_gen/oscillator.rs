@@ -1,12 +1,17 @@
-use rand::thread_rng;
-use rand_distr::{Distribution, Normal};
 use std::sync::Arc;
 
 use crate::common::constants::SAMPLE_RATE;
+use crate::common::rng;
 
 static TWO_PI: f32 = 2.0 * std::f32::consts::PI;
 static NUM_TABLE_SAMPLES: usize = 1024;
 static SAMPLE_COUNT_FACTOR: f32 = SAMPLE_RATE / NUM_TABLE_SAMPLES as f32;
+pub(crate) static DEFAULT_DUTY_CYCLE: f32 = 0.5;
+static NUM_PINK_ROWS: usize = 16;
+// Pole of the leaky integrator generating brown noise, keeps it from drifting off to infinity
+static BROWN_LEAK: f32 = 0.995;
+// Scales the integrator input so brown noise has unit variance like the white noise source
+static BROWN_GAIN: f32 = 0.1;
 
 #[allow(dead_code)]
 #[derive(Clone, Copy, Debug, Hash, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -17,6 +22,12 @@ pub enum Waveform {
     Square,
     Triangle,
     Noise, // Add alias for consistency with TUI
+    // High for duty_cycle of each period and low for the rest, at a duty cycle of 0.5 it is Square
+    Pulse,
+    // Noise with falling spectra, -3dB per octave for pink and -6dB per octave for brown. These
+    // need state so are generated by a NoiseGenerator.
+    PinkNoise,
+    BrownNoise,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -25,6 +36,9 @@ pub(crate) struct OscillatorTables {
     pub(crate) saw_table: Arc<Vec<f32>>,
     pub(crate) square_table: Arc<Vec<f32>>,
     pub(crate) triangle_table: Arc<Vec<f32>>,
+    // Generate saw and square with PolyBLEP instead of the tables, which reduces aliasing at high
+    // frequencies at the cost of computing each sample
+    pub(crate) antialiased: bool,
 }
 
 impl OscillatorTables {
@@ -34,6 +48,15 @@ impl OscillatorTables {
             saw_table: Arc::new(generate_saw_table()),
             square_table: Arc::new(generate_square_table()),
             triangle_table: Arc::new(generate_triangle_table()),
+            antialiased: false,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn new_antialiased() -> OscillatorTables {
+        OscillatorTables {
+            antialiased: true,
+            ..OscillatorTables::new()
         }
     }
 }
@@ -82,13 +105,156 @@ pub(crate) fn generate_triangle_table() -> Vec<f32> {
 }
 
 pub(crate) fn get_sample(table: &Vec<f32>, frequency: f32, sample_count: u64) -> f32 {
-    table[((frequency * sample_count as f32) / SAMPLE_COUNT_FACTOR) as usize % NUM_TABLE_SAMPLES]
+    get_sample_at_phase(table, frequency, 0.0, sample_count)
+}
+
+// phase_offset is the fraction of a cycle, 0.0 to 1.0, the waveform starts at
+pub(crate) fn get_sample_at_phase(table: &[f32], frequency: f32, phase_offset: f32,
+                                  sample_count: u64) -> f32 {
+    table[table_index(frequency, phase_offset, sample_count)]
+}
+
+fn table_index(frequency: f32, phase_offset: f32, sample_count: u64) -> usize {
+    (((frequency * sample_count as f32) / SAMPLE_COUNT_FACTOR) as usize +
+        (phase_offset.rem_euclid(1.0) * NUM_TABLE_SAMPLES as f32) as usize) % NUM_TABLE_SAMPLES
+}
+
+// Band-limited saw and square using PolyBLEP, which smooths the discontinuity in the naive waveform
+// over the samples on either side of it. Other waveforms have no discontinuities and are returned
+// unchanged from the phase. Matches the phase and polarity of the table waveforms.
+pub(crate) fn get_sample_antialiased(waveform: Waveform, frequency: f32, phase_offset: f32,
+                                     sample_count: u64) -> f32 {
+    let (phase, phase_increment) = antialiased_phase(frequency, phase_offset, sample_count);
+
+    match waveform {
+        Waveform::Saw => poly_blep_saw(phase, phase_increment),
+        Waveform::Square => {
+            let naive_sample = if phase < 0.5 { 1.0 } else { -1.0 };
+            naive_sample + poly_blep(phase, phase_increment)
+                - poly_blep((phase + 0.5) % 1.0, phase_increment)
+        }
+        Waveform::Pulse => get_pulse_sample_antialiased(frequency, DEFAULT_DUTY_CYCLE,
+                                                        phase_offset, sample_count),
+        Waveform::Sine => (TWO_PI * phase).sin(),
+        Waveform::Triangle => 4.0 * (phase - (phase + 0.5).floor()).abs() - 1.0,
+        Waveform::GaussianNoise | Waveform::Noise | Waveform::PinkNoise | Waveform::BrownNoise =>
+            get_gaussian_noise_sample(),
+    }
+}
+
+// The pulse is generated from the phase rather than a table so any duty cycle can be used
+pub(crate) fn get_pulse_sample(frequency: f32, duty_cycle: f32, phase_offset: f32,
+                               sample_count: u64) -> f32 {
+    if pulse_phase(frequency, phase_offset, sample_count) < duty_cycle {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+// The difference of two band-limited saws a duty cycle apart. Each saw's PolyBLEP smooths one
+// edge of the pulse, up at the start of the period and down at duty_cycle, and the DC offset
+// 2 * duty_cycle - 1 of the difference is removed so the pulse swings between 1.0 and -1.0.
+pub(crate) fn get_pulse_sample_antialiased(frequency: f32, duty_cycle: f32, phase_offset: f32,
+                                           sample_count: u64) -> f32 {
+    let (phase, phase_increment) = antialiased_phase(frequency, phase_offset, sample_count);
+    let duty_cycle = duty_cycle.clamp(0.0, 1.0);
+    poly_blep_saw((phase - duty_cycle).rem_euclid(1.0), phase_increment)
+        - poly_blep_saw(phase, phase_increment)
+        + 2.0 * duty_cycle - 1.0
+}
+
+// Phase from 0.0 to 1.0 and the phase advanced per sample, computed in f64 so long notes keep
+// their pitch
+fn antialiased_phase(frequency: f32, phase_offset: f32, sample_count: u64) -> (f32, f32) {
+    let phase_increment = frequency as f64 / SAMPLE_RATE as f64;
+    let phase = (phase_increment * sample_count as f64 + phase_offset as f64).rem_euclid(1.0);
+    (phase as f32, phase_increment as f32)
+}
+
+// Rising saw from -1.0 to 1.0 with the drop at the end of the period smoothed by PolyBLEP
+fn poly_blep_saw(phase: f32, phase_increment: f32) -> f32 {
+    2.0 * phase - 1.0 - poly_blep(phase, phase_increment)
+}
+
+// Computed the same way as the table index so a pulse and the square table switch on the same sample
+fn pulse_phase(frequency: f32, phase_offset: f32, sample_count: u64) -> f32 {
+    table_index(frequency, phase_offset, sample_count) as f32 / NUM_TABLE_SAMPLES as f32
+}
+
+// Polynomial correction for a unit step at phase 0.0, non-zero only within one sample of the step
+fn poly_blep(phase: f32, phase_increment: f32) -> f32 {
+    if phase < phase_increment {
+        let t = phase / phase_increment;
+        t + t - t * t - 1.0
+    } else if phase > 1.0 - phase_increment {
+        let t = (phase - 1.0) / phase_increment;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
 }
 
 pub(crate) fn get_gaussian_noise_sample() -> f32 {
-    let normal = Normal::new(0.0, 1.0).unwrap();
-    let mut rng = thread_rng();
-    normal.sample(&mut rng)
+    rng::gaussian()
+}
+
+// Stateful generator for colored noise. Each note playing pink or brown noise needs its own.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct NoiseGenerator {
+    // Voss-McCartney: row k is replaced every 2^k samples and the rows are summed, so each row
+    // contributes white noise over one octave lower than the row before it
+    pink_rows: Vec<f32>,
+    pink_running_sum: f32,
+    pink_counter: u32,
+    brown_level: f32,
+}
+
+#[allow(dead_code)]
+impl NoiseGenerator {
+    // Rows start at zero and fill in as they are first replaced, so the lowest octaves come in
+    // over the first second or so
+    pub(crate) fn new() -> Self {
+        NoiseGenerator {
+            pink_rows: vec![0.0; NUM_PINK_ROWS],
+            pink_running_sum: 0.0,
+            pink_counter: 0,
+            brown_level: 0.0,
+        }
+    }
+
+    pub(crate) fn next_sample(&mut self, waveform: Waveform) -> f32 {
+        match waveform {
+            Waveform::PinkNoise => self.next_pink_sample(),
+            Waveform::BrownNoise => self.next_brown_sample(),
+            _ => get_gaussian_noise_sample(),
+        }
+    }
+
+    pub(crate) fn next_pink_sample(&mut self) -> f32 {
+        self.pink_counter = self.pink_counter.wrapping_add(1);
+        // the row to replace is the number of trailing zeros, so row 0 every other sample,
+        // row 1 every fourth and so on
+        let row = self.pink_counter.trailing_zeros() as usize;
+        if row < NUM_PINK_ROWS {
+            let white = get_gaussian_noise_sample();
+            self.pink_running_sum += white - self.pink_rows[row];
+            self.pink_rows[row] = white;
+        }
+        // one more white sample every sample fills in the top octave, then back to unit variance
+        (self.pink_running_sum + get_gaussian_noise_sample()) / ((NUM_PINK_ROWS + 1) as f32).sqrt()
+    }
+
+    pub(crate) fn next_brown_sample(&mut self) -> f32 {
+        self.brown_level = BROWN_LEAK * self.brown_level + BROWN_GAIN * get_gaussian_noise_sample();
+        self.brown_level
+    }
+}
+
+impl Default for NoiseGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // TODO DEPRECATE THESE?
@@ -116,3 +282,180 @@ pub(crate) fn get_saw_sample(frequency: f32, sample_position: f32) -> f32 {
         .floor()).abs()
         - 1.0
 }
+
+#[cfg(test)]
+mod test_oscillator {
+    use std::f32::consts::PI;
+    use crate::audio_gen::oscillator::{get_pulse_sample, get_pulse_sample_antialiased, get_sample,
+                                       get_sample_antialiased, NoiseGenerator, OscillatorTables,
+                                       Waveform};
+    use crate::common::constants::SAMPLE_RATE;
+
+    const NUM_SAMPLES: usize = 2048;
+    static FREQUENCY: f32 = 3150.0;
+
+    // Energy in the DFT bins between Nyquist / 2 and Nyquist, relative to the total energy
+    fn upper_band_energy_ratio(samples: &[f32]) -> f32 {
+        let n = samples.len();
+        let mut upper_energy = 0.0;
+        let mut total_energy = 0.0;
+        for k in 1..n / 2 {
+            let (mut re, mut im) = (0.0f32, 0.0f32);
+            for (i, sample) in samples.iter().enumerate() {
+                let angle = 2.0 * PI * ((k * i) % n) as f32 / n as f32;
+                re += sample * angle.cos();
+                im -= sample * angle.sin();
+            }
+            let energy = re * re + im * im;
+            total_energy += energy;
+            if k >= n / 4 {
+                upper_energy += energy;
+            }
+        }
+        upper_energy / total_energy
+    }
+
+    #[test]
+    fn test_antialiased_reduces_upper_band_energy() {
+        let tables = OscillatorTables::new();
+        for (waveform, table) in [(Waveform::Saw, &tables.saw_table),
+                                  (Waveform::Square, &tables.square_table)] {
+            let table_samples: Vec<f32> = (0..NUM_SAMPLES as u64)
+                .map(|i| get_sample(table, FREQUENCY, i))
+                .collect();
+            let antialiased_samples: Vec<f32> = (0..NUM_SAMPLES as u64)
+                .map(|i| get_sample_antialiased(waveform, FREQUENCY, 0.0, i))
+                .collect();
+
+            assert!(upper_band_energy_ratio(&antialiased_samples) <
+                upper_band_energy_ratio(&table_samples));
+        }
+    }
+
+    #[test]
+    fn test_antialiased_matches_table_at_low_frequency() {
+        // away from the discontinuities the two paths agree
+        let tables = OscillatorTables::new();
+        // exactly one table sample per audio sample
+        let frequency = SAMPLE_RATE / 1024.0;
+        for i in [100u64, 300, 600, 900] {
+            assert!((get_sample(&tables.saw_table, frequency, i) -
+                get_sample_antialiased(Waveform::Saw, frequency, 0.0, i)).abs() < 0.01);
+            assert!((get_sample(&tables.square_table, frequency, i) -
+                get_sample_antialiased(Waveform::Square, frequency, 0.0, i)).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn test_half_duty_pulse_matches_square() {
+        let tables = OscillatorTables::new();
+        for frequency in [110.0, 441.0, 1234.5] {
+            for i in 0..2000u64 {
+                assert_eq!(get_pulse_sample(frequency, 0.5, 0.0, i),
+                           get_sample(&tables.square_table, frequency, i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_duty_cycle_sets_high_ratio() {
+        // 441 Hz is exactly 100 samples per period
+        for duty_cycle in [0.1, 0.25, 0.75] {
+            let num_high = (0..1000u64)
+                .filter(|i| get_pulse_sample(441.0, duty_cycle, 0.0, *i) > 0.0)
+                .count();
+            assert!(((num_high as f32 / 1000.0) - duty_cycle).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn test_antialiased_pulse_duty_cycle() {
+        // 441 Hz is exactly 100 samples per period
+        for duty_cycle in [0.1, 0.25, 0.75] {
+            let num_high = (0..1000u64)
+                .filter(|i| get_pulse_sample_antialiased(441.0, duty_cycle, 0.0, *i) > 0.0)
+                .count();
+            assert!(((num_high as f32 / 1000.0) - duty_cycle).abs() < 0.02);
+        }
+        // a duty cycle of 0.5 is the antialiased square
+        for i in 0..1000u64 {
+            assert!((get_pulse_sample_antialiased(FREQUENCY, 0.5, 0.0, i) -
+                get_sample_antialiased(Waveform::Square, FREQUENCY, 0.0, i)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_antialiased_pulse_reduces_upper_band_energy() {
+        let pulse_samples: Vec<f32> = (0..NUM_SAMPLES as u64)
+            .map(|i| get_pulse_sample(FREQUENCY, 0.25, 0.0, i))
+            .collect();
+        let antialiased_samples: Vec<f32> = (0..NUM_SAMPLES as u64)
+            .map(|i| get_pulse_sample_antialiased(FREQUENCY, 0.25, 0.0, i))
+            .collect();
+        assert!(upper_band_energy_ratio(&antialiased_samples) <
+            upper_band_energy_ratio(&pulse_samples));
+    }
+
+    #[test]
+    fn test_new_antialiased() {
+        assert!(!OscillatorTables::new().antialiased);
+        assert!(OscillatorTables::new_antialiased().antialiased);
+    }
+
+    // Power in each DFT bin of 1024 sample blocks, averaged over many blocks to smooth the noise
+    fn average_power_spectrum(mut next_sample: impl FnMut() -> f32) -> Vec<f32> {
+        const BLOCK_SIZE: usize = 1024;
+        const NUM_BLOCKS: usize = 16;
+        let (cos_table, sin_table): (Vec<f32>, Vec<f32>) = (0..BLOCK_SIZE)
+            .map(|i| {
+                let angle = 2.0 * PI * i as f32 / BLOCK_SIZE as f32;
+                (angle.cos(), angle.sin())
+            })
+            .unzip();
+
+        let mut power = vec![0.0; BLOCK_SIZE / 2];
+        for _ in 0..NUM_BLOCKS {
+            let block: Vec<f32> = (0..BLOCK_SIZE).map(|_| next_sample()).collect();
+            for (k, bin_power) in power.iter_mut().enumerate() {
+                let (mut re, mut im) = (0.0f32, 0.0f32);
+                for (i, sample) in block.iter().enumerate() {
+                    re += sample * cos_table[(k * i) % BLOCK_SIZE];
+                    im -= sample * sin_table[(k * i) % BLOCK_SIZE];
+                }
+                *bin_power += (re * re + im * im) / NUM_BLOCKS as f32;
+            }
+        }
+        power
+    }
+
+    // Ratio of the mean power in bins 8..16 to the mean power in bins 64..128, three octaves up
+    fn three_octave_power_ratio(power: &[f32]) -> f32 {
+        let mean = |bins: &[f32]| bins.iter().sum::<f32>() / bins.len() as f32;
+        mean(&power[8..16]) / mean(&power[64..128])
+    }
+
+    #[test]
+    fn test_pink_noise_slope() {
+        let mut noise_generator = NoiseGenerator::new();
+        // let every row fill in before measuring
+        for _ in 0..(1 << 16) {
+            noise_generator.next_pink_sample();
+        }
+        let power = average_power_spectrum(|| noise_generator.next_pink_sample());
+        // -3dB per octave is about 9dB, a factor of 8, over three octaves
+        let ratio = three_octave_power_ratio(&power);
+        assert!(ratio > 4.0 && ratio < 16.0, "pink noise power ratio {}", ratio);
+    }
+
+    #[test]
+    fn test_brown_noise_slope() {
+        let mut noise_generator = NoiseGenerator::new();
+        for _ in 0..SAMPLE_RATE as usize {
+            noise_generator.next_brown_sample();
+        }
+        let power = average_power_spectrum(|| noise_generator.next_brown_sample());
+        // -6dB per octave is about 18dB, a factor of 64, over three octaves
+        let ratio = three_octave_power_ratio(&power);
+        assert!(ratio > 32.0 && ratio < 128.0, "brown noise power ratio {}", ratio);
+    }
+}
@@ -27,6 +27,20 @@ pub(crate) fn float_geq(a: f32, b: f32) -> bool {
     false
 }
 
+// Comparisons within an absolute epsilon, for values near zero or near the ends of a range, where
+// the relative comparisons above only match values exactly equal to zero
+pub(crate) fn float_eq_eps(a: f32, b: f32, epsilon: f32) -> bool {
+    (a - b).abs() <= epsilon
+}
+
+pub(crate) fn float_leq_eps(a: f32, b: f32, epsilon: f32) -> bool {
+    a < b || float_eq_eps(a, b, epsilon)
+}
+
+pub(crate) fn float_geq_eps(a: f32, b: f32, epsilon: f32) -> bool {
+    a > b || float_eq_eps(a, b, epsilon)
+}
+
 #[allow(dead_code)]
 pub fn assert_float_eq(a: f32, b: f32) {
     assert_float_eq!(a, b, rmax <= constants::FLOAT_EPSILON);
@@ -45,4 +59,41 @@ pub(crate) fn assert_float_leq(a: f32, b: f32) {
 #[allow(dead_code)]
 pub(crate) fn assert_float_geq(a: f32, b: f32) {
     assert!(float_geq(a, b));
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test_float_utils {
+    use crate::common::float_utils::{float_eq, float_eq_eps, float_geq_eps, float_leq,
+                                     float_leq_eps};
+
+    const EPSILON: f32 = 1e-4;
+
+    #[test]
+    fn test_float_eq_eps_at_the_boundary() {
+        assert!(float_eq_eps(1.0, 1.0 + 0.5 * EPSILON, EPSILON));
+        assert!(float_eq_eps(1.0, 1.0 - 0.5 * EPSILON, EPSILON));
+        assert!(!float_eq_eps(1.0, 1.0 + 2.0 * EPSILON, EPSILON));
+        // Near zero, where a relative comparison only matches zero itself
+        assert!(float_eq_eps(1e-7, 0.0, EPSILON));
+        assert!(float_eq_eps(-1e-7, 0.0, EPSILON));
+        assert!(!float_eq(1e-7, 0.0));
+    }
+
+    #[test]
+    fn test_float_leq_and_geq_eps_at_the_boundary() {
+        assert!(float_leq_eps(0.5, 1.0, EPSILON));
+        assert!(float_leq_eps(1.0 + 0.5 * EPSILON, 1.0, EPSILON));
+        assert!(!float_leq_eps(1.0 + 2.0 * EPSILON, 1.0, EPSILON));
+
+        assert!(float_geq_eps(1.0, 0.5, EPSILON));
+        assert!(float_geq_eps(1.0 - 0.5 * EPSILON, 1.0, EPSILON));
+        assert!(!float_geq_eps(1.0 - 2.0 * EPSILON, 1.0, EPSILON));
+    }
+
+    #[test]
+    fn test_float_leq_relative() {
+        assert!(float_leq(1.0, 1.0));
+        assert!(float_leq(1.0 + f32::EPSILON, 1.0));
+        assert!(!float_leq(1.001, 1.0));
+    }
+}
@@ -0,0 +1,47 @@
+// Quietest level in dB, what silence converts to rather than negative infinity
+pub(crate) const MIN_DB: f32 = -96.0;
+
+/// Linear gain of a level in dB, 0dB being 1.0
+pub(crate) fn db_to_linear(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+/// Level in dB of a linear gain, no lower than MIN_DB
+pub(crate) fn linear_to_db(x: f32) -> f32 {
+    if x > 0.0 {
+        (20.0 * x.log10()).max(MIN_DB)
+    } else {
+        MIN_DB
+    }
+}
+
+#[cfg(test)]
+mod test_gain {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::common::gain::{db_to_linear, linear_to_db, MIN_DB};
+
+    #[test]
+    fn test_unity_and_half() {
+        assert_float_eq(db_to_linear(0.0), 1.0);
+        assert_float_eq(linear_to_db(1.0), 0.0);
+        assert!((db_to_linear(-6.0) - 0.501).abs() < 1e-3);
+        assert!((linear_to_db(0.5) - -6.02).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_inverses() {
+        for db in [-60.0, -24.0, -6.0, -0.5, 0.0, 3.0, 12.0] {
+            assert!((linear_to_db(db_to_linear(db)) - db).abs() < 1e-3, "{} dB", db);
+        }
+        for x in [0.001, 0.1, 0.5, 0.75, 1.0, 2.0] {
+            assert!((db_to_linear(linear_to_db(x)) - x).abs() < 1e-4 * x.max(1.0), "{}", x);
+        }
+    }
+
+    #[test]
+    fn test_silence_is_min_db() {
+        assert_float_eq(linear_to_db(0.0), MIN_DB);
+        assert_float_eq(linear_to_db(-1.0), MIN_DB);
+        assert_float_eq(linear_to_db(1e-9), MIN_DB);
+    }
+}
@@ -1,3 +1,5 @@
 pub mod constants;
 pub mod float_utils;
-pub mod pair;
\ No newline at end of file
+pub mod gain;
+pub mod pair;
+pub mod rng;
\ No newline at end of file
@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+use std::sync::Mutex;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use rand_distr::{Distribution, StandardNormal};
+
+// The seed generators start from, once one is set. Until then each starts from the OS's entropy.
+static GLOBAL_SEED: Mutex<Option<u64>> = Mutex::new(None);
+
+thread_local! {
+    // Each thread draws from its own generator, so a render on one thread is reproducible
+    // whatever any other thread draws
+    static RNG: RefCell<StdRng> = RefCell::new(new_rng());
+}
+
+fn new_rng() -> StdRng {
+    match *GLOBAL_SEED.lock().unwrap() {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    }
+}
+
+/// Restart this thread's generator from the seed, and start generators of threads that haven't
+/// drawn yet from it too
+#[allow(dead_code)]
+pub(crate) fn set_seed(seed: u64) {
+    *GLOBAL_SEED.lock().unwrap() = Some(seed);
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Draw from this thread's generator
+pub(crate) fn with_rng<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+/// A sample of the standard normal distribution, mean 0.0 and standard deviation 1.0
+pub(crate) fn gaussian() -> f32 {
+    with_rng(|rng| StandardNormal.sample(rng))
+}
+
+#[cfg(test)]
+mod test_rng {
+    use crate::common::rng::{gaussian, set_seed};
+
+    fn noise(seed: u64) -> Vec<f32> {
+        set_seed(seed);
+        (0..256).map(|_| gaussian()).collect()
+    }
+
+    #[test]
+    fn test_same_seed_same_noise() {
+        assert_eq!(noise(42), noise(42));
+    }
+
+    #[test]
+    fn test_different_seeds_diverge() {
+        let first = noise(42);
+        let second = noise(43);
+        assert!(first.iter().zip(second.iter()).filter(|(a, b)| a != b).count() > 250);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::note::scales::{WesternPitch, WesternScale};
+
+/// The triads on degrees of a scale in a key, one chord for each degree, counting from 1 for the
+/// root as the DSL's deg1 does, so I-IV-V-I is &[1, 4, 5, 1]. Each triad stacks the degree with
+/// the degrees two and four above it, lowest first, and is named in sharps. Degree 0 is a rest,
+/// an empty chord, so a progression still lines up with the steps it fills.
+#[allow(dead_code)]
+pub(crate) fn chord_progression(key: WesternPitch, scale: WesternScale, degrees: &[u8])
+                                -> Vec<Vec<WesternPitch>> {
+    let pitches = WesternPitch::all_pitches();
+    degrees.iter()
+        .map(|degree| match *degree as usize {
+            0 => Vec::new(),
+            degree => (0..3)
+                .map(|third| {
+                    let semitones = key.get_pitch_index() as u32 +
+                        scale.degree_semitones(degree - 1 + 2 * third);
+                    pitches[semitones as usize % pitches.len()]
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test_chords {
+    use crate::composition::chords::chord_progression;
+    use crate::note::scales::WesternPitch::{self, *};
+    use crate::note::scales::WesternScale;
+
+    #[test]
+    fn test_one_four_five_one_in_c_major() {
+        let progression = chord_progression(C, WesternScale::Major, &[1, 4, 5, 1]);
+        let expected: Vec<Vec<WesternPitch>> =
+            vec![vec![C, E, G], vec![F, A, C], vec![G, B, D], vec![C, E, G]];
+        assert_eq!(progression, expected);
+    }
+
+    #[test]
+    fn test_chord_qualities_follow_the_scale() {
+        // ii in A minor is diminished, V in A harmonic minor is major
+        assert_eq!(chord_progression(A, WesternScale::Minor, &[2]), vec![vec![B, D, F]]);
+        assert_eq!(chord_progression(A, WesternScale::HarmonicMinor, &[5]),
+                   vec![vec![E, GSharp, B]]);
+        // A flat key is named in sharps
+        assert_eq!(chord_progression(EFlat, WesternScale::Major, &[1]),
+                   vec![vec![DSharp, G, ASharp]]);
+    }
+
+    #[test]
+    fn test_rests_and_degrees_past_the_octave() {
+        let progression = chord_progression(C, WesternScale::Major, &[1, 0, 8]);
+        assert_eq!(progression.len(), 3);
+        assert!(progression[1].is_empty());
+        // deg8 is the root an octave up
+        assert_eq!(progression[2], progression[0]);
+    }
+}
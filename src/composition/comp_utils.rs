@@ -1,5 +1,8 @@
-use crate::{audio_gen, common, midi, note};
-use crate::audio_gen::audio_gen::gen_notes_stream;
+use std::sync::Arc;
+
+use crate::{audio_gen, common, midi};
+use crate::audio_gen::audio_gen::{gen_notes_stream, render_track_grid_to_wav, WavChannels};
+use crate::dsl::parser::parse_dsl_file;
 use crate::audio_gen::oscillator::{OscillatorTables, Waveform};
 use crate::effect::delay::Delay;
 use crate::effect::flanger::Flanger;
@@ -17,8 +20,10 @@ const ARGS_DELIMITER: &str = ",";
 
 #[allow(dead_code)]
 pub(crate) struct SampleBuf {
-    buf: Vec<f32>,
+    buf: Arc<[f32]>,
+    // Frames, each a sample for every channel
     len: usize,
+    num_channels: u16,
 }
 
 pub(crate) fn build_sampled_playback_note(sampled_note_pool: &mut NotePool<SampledNote>,
@@ -31,7 +36,7 @@ pub(crate) fn build_sampled_playback_note(sampled_note_pool: &mut NotePool<Sampl
     sampled_note.volume = volume;
     sampled_note.start_time_ms = start_time;
     sampled_note.end_time_ms = (sample_buf.len as f32 / common::constants::SAMPLE_RATE) * 1000.0;
-    sampled_note.set_sample_buf(&sample_buf.buf);
+    sampled_note.set_shared_sample_buf(Arc::clone(&sample_buf.buf), sample_buf.num_channels);
 
     let mut playback_note = playback_note_pool.acquire().unwrap();
     playback_note.note_type = NoteType::Sample;
@@ -49,14 +54,11 @@ pub(crate) fn build_sampled_playback_note(sampled_note_pool: &mut NotePool<Sampl
 }
 
 pub(crate) fn load_sample_data(file_path: &str) -> SampleBuf {
-    let sample_data= audio_gen::audio_gen::read_audio_file(file_path).into_boxed_slice();
-    let mut sample_buf: Vec<f32> = Vec::with_capacity(note::sampled_note::BUF_STORAGE_SIZE);
-    for sample in  sample_data[..].iter() {
-        sample_buf.push(*sample as f32);
-    }
+    let sample_data = audio_gen::audio_gen::read_cached_audio_file(file_path);
     SampleBuf {
-        buf: sample_buf,
-        len: sample_data.len(),
+        len: sample_data.num_frames(),
+        buf: sample_data.samples,
+        num_channels: sample_data.num_channels,
     }
 }
 
@@ -154,10 +156,13 @@ pub(crate) fn get_waveforms_from_arg() -> Vec<Waveform> {
         .map( |waveform| {
             let matched = match waveform {
                 "gaussian_noise" => Waveform::GaussianNoise,
+                "pink_noise" => Waveform::PinkNoise,
+                "brown_noise" => Waveform::BrownNoise,
                 "saw" => Waveform::Saw,
                 "sine" => Waveform::Sine,
                 "square" => Waveform::Square,
                 "triangle" => Waveform::Triangle,
+                "pulse" => Waveform::Pulse,
                 _ => Waveform::Sine,
             };
             matched
@@ -185,4 +190,13 @@ where
     for playback_notes in rx.iter() {
         gen_notes_stream(playback_notes, OscillatorTables::new());
     }
-}
\ No newline at end of file
+}
+
+// Parse a DSL script file and render it offline to a WAV file, without real-time playback
+#[allow(dead_code)]
+pub(crate) fn render_dsl_file(input_path: &str, output_path: &str, channels: WavChannels)
+                              -> Result<(), String> {
+    let track_grid = parse_dsl_file(input_path)?;
+    render_track_grid_to_wav(&track_grid, output_path, None, channels)
+        .map_err(|e| format!("Error writing {}: {}", output_path, e))
+}
@@ -0,0 +1,187 @@
+use crate::composition::comp_utils::set_notes_offset;
+use crate::note::playback_note::PlaybackNote;
+use crate::sequence::fixed_time_note_sequence::FixedTimeNoteSequence;
+use crate::track::track_grid::TrackGrid;
+
+/// A part of a composition, such as an intro, verse or chorus, played one or more times in a row
+#[derive(Debug, Clone)]
+pub(crate) struct Section {
+    pub(crate) name: String,
+    pub(crate) track_grid: TrackGrid<FixedTimeNoteSequence>,
+    pub(crate) repeats: usize,
+    // How long one pass lasts, if not until its last note ends, so a section can end in rests
+    pub(crate) length_ms: Option<f32>,
+}
+
+impl Section {
+    /// Length of one pass of the section
+    pub(crate) fn pass_duration_ms(&self) -> f32 {
+        self.length_ms.unwrap_or_else(|| {
+            self.track_grid.tracks.iter()
+                .flat_map(|track| track.sequence.get_all_notes())
+                .map(|playback_note| playback_note.note_end_time_ms())
+                .fold(0.0, f32::max)
+        })
+    }
+
+    pub(crate) fn duration_ms(&self) -> f32 {
+        self.pass_duration_ms() * self.repeats as f32
+    }
+}
+
+/// Sections played one after another, each starting as the one before it ends
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Composition {
+    sections: Vec<Section>,
+}
+
+#[allow(dead_code)]
+impl Composition {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a section to play after the ones already added
+    pub(crate) fn add_section(&mut self, name: &str, track_grid: TrackGrid<FixedTimeNoteSequence>,
+                              repeats: usize) -> Result<(), String> {
+        self.add_section_with_length(name, track_grid, repeats, None)
+    }
+
+    /// Add a section one pass of which lasts length_ms, whenever its notes end
+    pub(crate) fn add_section_with_length(&mut self, name: &str,
+                                          track_grid: TrackGrid<FixedTimeNoteSequence>,
+                                          repeats: usize, length_ms: Option<f32>)
+                                          -> Result<(), String> {
+        if repeats == 0 {
+            return Err(format!("Composition: section '{}' must repeat at least once", name));
+        }
+        if let Some(length_ms) = length_ms {
+            if length_ms <= 0.0 || !length_ms.is_finite() {
+                return Err(format!("Composition: section '{}' length must be greater than 0.0, \
+                                    got {}", name, length_ms));
+            }
+        }
+        self.sections.push(Section {
+            name: name.to_string(),
+            track_grid,
+            repeats,
+            length_ms,
+        });
+        Ok(())
+    }
+
+    pub(crate) fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub(crate) fn total_duration_ms(&self) -> f32 {
+        self.sections.iter().map(Section::duration_ms).sum()
+    }
+
+    /// Each pass of each section in the order they play, by name, with the time it starts
+    pub(crate) fn arrangement(&self) -> Vec<(&str, f32)> {
+        let mut start_ms = 0.0;
+        let mut arrangement = Vec::new();
+        for section in self.sections.iter() {
+            let pass_duration_ms = section.pass_duration_ms();
+            for _ in 0..section.repeats {
+                arrangement.push((section.name.as_str(), start_ms));
+                start_ms += pass_duration_ms;
+            }
+        }
+        arrangement
+    }
+
+    /// Every note of every pass of every section, moved to the time it plays in the composition
+    /// and ordered by start time. Each note carries its track's effects, and its track's volume
+    /// scales its own, so the notes play as their tracks would.
+    pub(crate) fn flatten(&self) -> Vec<PlaybackNote> {
+        let section_starts = self.arrangement();
+        let mut playback_notes = Vec::new();
+        let passes = self.sections.iter()
+            .flat_map(|section| std::iter::repeat_n(section, section.repeats));
+        for (section, (_, start_ms)) in passes.zip(section_starts) {
+            for track in section.track_grid.tracks.iter() {
+                let mut track_notes = track.sequence.get_all_notes();
+                for playback_note in track_notes.iter_mut() {
+                    playback_note.track_effects = track.effects.clone();
+                    playback_note.set_note_volume(playback_note.note_volume() * track.volume);
+                }
+                set_notes_offset(&mut track_notes, start_ms);
+                playback_notes.extend(track_notes);
+            }
+        }
+        playback_notes.sort_by(|a, b| a.note_start_time_ms().total_cmp(&b.note_start_time_ms()));
+        playback_notes
+    }
+}
+
+#[cfg(test)]
+mod test_composition {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::composition::composition::Composition;
+    use crate::dsl::parser::parse_dsl;
+    use crate::sequence::fixed_time_note_sequence::FixedTimeNoteSequence;
+    use crate::track::track_grid::TrackGrid;
+
+    // A grid of one note on each of num_steps steps, 125ms each
+    fn track_grid(num_steps: usize, frequency: f32) -> TrackGrid<FixedTimeNoteSequence> {
+        let notes: String = (0..num_steps)
+            .map(|step| format!("osc:sine:{}:0.5:{}\n", frequency, step))
+            .collect();
+        parse_dsl(&format!("FixedTimeNoteSequence dur Quarter tempo 120 num_steps {}\n{}",
+                           num_steps, notes)).unwrap()
+    }
+
+    fn verse_chorus() -> Composition {
+        let mut composition = Composition::new();
+        composition.add_section("A", track_grid(4, 440.0), 2).unwrap();
+        composition.add_section("B", track_grid(2, 220.0), 1).unwrap();
+        composition
+    }
+
+    #[test]
+    fn test_total_duration_and_order() {
+        let composition = verse_chorus();
+        // Two passes of 500ms and one of 250ms
+        assert_float_eq(composition.total_duration_ms(), 1250.0);
+
+        let arrangement = composition.arrangement();
+        let names: Vec<&str> = arrangement.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, vec!["A", "A", "B"]);
+        let starts: Vec<f32> = arrangement.iter().map(|(_, start_ms)| *start_ms).collect();
+        assert_eq!(starts, vec![0.0, 500.0, 1000.0]);
+    }
+
+    #[test]
+    fn test_flatten_places_each_pass() {
+        let playback_notes = verse_chorus().flatten();
+        assert_eq!(playback_notes.len(), 4 + 4 + 2);
+
+        let starts: Vec<f32> = playback_notes.iter()
+            .map(|playback_note| playback_note.note_start_time_ms())
+            .collect();
+        assert!(starts.windows(2).all(|pair| pair[0] <= pair[1]));
+        // The second pass of A follows the first, then B's two notes
+        assert_float_eq(starts[4], 500.0);
+        assert_float_eq(starts[8], 1000.0);
+        assert_float_eq(playback_notes[8].note.frequency, 220.0);
+        assert_float_eq(playback_notes[9].note_end_time_ms(), 1250.0);
+    }
+
+    #[test]
+    fn test_section_length_and_repeats() {
+        let mut composition = Composition::new();
+        // One bar of which only the first half has notes
+        composition.add_section_with_length("intro", track_grid(2, 440.0), 1, Some(500.0))
+            .unwrap();
+        composition.add_section("A", track_grid(2, 440.0), 1).unwrap();
+        assert_float_eq(composition.total_duration_ms(), 750.0);
+        assert_eq!(composition.arrangement()[1], ("A", 500.0));
+
+        assert!(composition.add_section("B", track_grid(2, 440.0), 0).is_err());
+        assert!(composition.add_section_with_length("C", track_grid(2, 440.0), 1, Some(0.0))
+            .is_err());
+        assert_eq!(composition.sections().len(), 2);
+    }
+}
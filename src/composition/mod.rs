@@ -1 +1,5 @@
+pub mod chords;
 pub mod comp_utils;
+pub mod composition;
+pub mod musicxml;
+pub mod tempo_map;
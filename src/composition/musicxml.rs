@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use musicxml::datatypes::{StartStop, Step};
+use musicxml::elements::{AudibleType, MeasureElement, Note, NoteType as XmlNoteType, Part,
+                         PartElement, Pitch};
+
+use crate::meter::durations::DurationType;
+use crate::midi::midi::DEFAULT_BPM;
+use crate::note::note::NoteBuilder;
+use crate::note::playback_note::{NoteType, PlaybackNote, PlaybackNoteBuilder};
+use crate::note::scales::WesternPitch;
+use crate::sequence::fixed_time_note_sequence::{FixedTimeNoteSequence,
+                                                FixedTimeNoteSequenceBuilder};
+use crate::sequence::note_sequence_trait::AppendNote;
+use crate::track::track::TrackBuilder;
+use crate::track::track_grid::{TrackGrid, TrackGridBuilder};
+
+static MSECS_PER_MIN: f32 = 60000.0;
+// MusicXML's divisions per quarter note when a part doesn't say
+static DEFAULT_DIVISIONS: u32 = 1;
+// Standard note values, longest first, as fractions of a quarter note
+static DURATION_TYPES: [(DurationType, f32); 7] = [
+    (DurationType::Whole, 4.0),
+    (DurationType::Half, 2.0),
+    (DurationType::Quarter, 1.0),
+    (DurationType::Eighth, 0.5),
+    (DurationType::Sixteenth, 0.25),
+    (DurationType::ThirtySecond, 0.125),
+    (DurationType::SixtyFourth, 0.0625),
+];
+
+// A pitched note read from the score, with times in quarter notes from the start of the part
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ScoreNote {
+    pub(crate) pitch: WesternPitch,
+    // Octave in scientific pitch notation, so middle C is C4
+    pub(crate) octave: u8,
+    pub(crate) start_quarters: f32,
+    pub(crate) duration_quarters: f32,
+}
+
+// Where a part is while reading it measure by measure
+struct PartState {
+    divisions: u32,
+    position_divisions: u32,
+    last_note_start_divisions: u32,
+    notes: Vec<ScoreNote>,
+    // Index in notes of the note each tie on a pitch started, keyed by MIDI note number
+    open_ties: HashMap<u8, usize>,
+}
+
+impl PartState {
+    fn new() -> Self {
+        PartState {
+            divisions: DEFAULT_DIVISIONS,
+            position_divisions: 0,
+            last_note_start_divisions: 0,
+            notes: Vec::new(),
+            open_ties: HashMap::new(),
+        }
+    }
+
+    fn quarters(&self, divisions: u32) -> f32 {
+        divisions as f32 / self.divisions as f32
+    }
+
+    fn read_element(&mut self, element: &PartElement, tempo: &mut Option<f32>) {
+        match element {
+            PartElement::Note(note) => self.read_note(note),
+            PartElement::Attributes(attributes) => {
+                if let Some(divisions) = &attributes.content.divisions {
+                    self.divisions = (*divisions.content).max(1);
+                }
+            }
+            PartElement::Backup(backup) => {
+                self.position_divisions =
+                    self.position_divisions.saturating_sub(*backup.content.duration.content);
+            }
+            PartElement::Forward(forward) => {
+                self.position_divisions += *forward.content.duration.content;
+            }
+            PartElement::Sound(sound) => {
+                read_tempo(sound.attributes.tempo.as_ref().map(|tempo| tempo.0), tempo);
+            }
+            PartElement::Direction(direction) => {
+                if let Some(sound) = &direction.content.sound {
+                    read_tempo(sound.attributes.tempo.as_ref().map(|tempo| tempo.0), tempo);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_note(&mut self, note: &Note) {
+        let (chord, audible, duration, ties) = match &note.content.info {
+            XmlNoteType::Normal(info) => {
+                (info.chord.is_some(), &info.audible, *info.duration.content, &info.tie)
+            }
+            // Cue notes take up time but are only there to be read, not played
+            XmlNoteType::Cue(info) => {
+                if info.chord.is_none() {
+                    self.last_note_start_divisions = self.position_divisions;
+                    self.position_divisions += *info.duration.content;
+                }
+                return;
+            }
+            // Grace notes take no time in the measure, so there is nowhere to put them
+            XmlNoteType::Grace(_) => return,
+        };
+
+        // A chord note sounds with the note before it rather than after it
+        let start_divisions = if chord {
+            self.last_note_start_divisions
+        } else {
+            self.position_divisions
+        };
+
+        match audible {
+            AudibleType::Pitch(pitch) => {
+                let (western_pitch, octave) = western_pitch(pitch);
+                let midi_note = western_pitch.to_midi_note(octave);
+                let ties_to_previous = ties.iter()
+                    .any(|tie| tie.attributes.r#type == StartStop::Stop);
+                let ties_to_next = ties.iter()
+                    .any(|tie| tie.attributes.r#type == StartStop::Start);
+
+                // A tied note lengthens the note it is tied from rather than sounding again
+                let tied_from = if ties_to_previous {
+                    self.open_ties.remove(&midi_note)
+                } else {
+                    None
+                };
+                let note_idx = match tied_from {
+                    Some(note_idx) => {
+                        self.notes[note_idx].duration_quarters += self.quarters(duration);
+                        note_idx
+                    }
+                    None => {
+                        self.notes.push(ScoreNote {
+                            pitch: western_pitch,
+                            octave,
+                            start_quarters: self.quarters(start_divisions),
+                            duration_quarters: self.quarters(duration),
+                        });
+                        self.notes.len() - 1
+                    }
+                };
+                if ties_to_next {
+                    self.open_ties.insert(midi_note, note_idx);
+                }
+            }
+            // Rests are gaps between notes, and unpitched notes, e.g. percussion, have no pitch
+            // to play so are gaps too
+            AudibleType::Rest(_) | AudibleType::Unpitched(_) => {}
+        }
+
+        if !chord {
+            self.last_note_start_divisions = self.position_divisions;
+            self.position_divisions += duration;
+        }
+    }
+}
+
+// The first tempo in the score is used for all of it
+fn read_tempo(sound_tempo: Option<f64>, tempo: &mut Option<f32>) {
+    if let (None, Some(sound_tempo)) = (*tempo, sound_tempo) {
+        if sound_tempo > 0.0 {
+            *tempo = Some(sound_tempo as f32);
+        }
+    }
+}
+
+// Step, alter and octave to the pitch class and octave that sound, so B#3 is C4 and Dbb4 is C4
+fn western_pitch(pitch: &Pitch) -> (WesternPitch, u8) {
+    let step_index: i16 = match pitch.content.step.content {
+        Step::C => 0,
+        Step::D => 2,
+        Step::E => 4,
+        Step::F => 5,
+        Step::G => 7,
+        Step::A => 9,
+        Step::B => 11,
+    };
+    let alter = pitch.content.alter.as_ref().map_or(0, |alter| *alter.content);
+    let octave = *pitch.content.octave.content as i16;
+    let midi_note = ((octave + 1) * 12 + step_index + alter).clamp(0, u8::MAX as i16);
+    WesternPitch::from_midi_note(midi_note as u8)
+}
+
+// The standard note value of a duration in quarter notes, if it is one
+pub(crate) fn duration_type(duration_quarters: f32) -> Option<DurationType> {
+    DURATION_TYPES.iter()
+        .find(|(_, quarters)| (duration_quarters - quarters).abs() < 1e-4)
+        .map(|(duration_type, _)| *duration_type)
+}
+
+// The pitched notes of each part of a MusicXML score, in part list order, and the score's tempo
+pub(crate) fn read_musicxml(path: &str) -> Result<(Vec<Vec<ScoreNote>>, Option<f32>), String> {
+    let score = musicxml::read_score_timewise(path)
+        .map_err(|e| format!("Failed to read MusicXML file {}: {}", path, e))?;
+
+    let mut part_ids: Vec<String> = Vec::new();
+    let mut part_states: HashMap<String, PartState> = HashMap::new();
+    let mut tempo = None;
+    for measure in score.content.measure.iter() {
+        for element in measure.content.iter() {
+            let MeasureElement::Part(Part { attributes, content }) = element else {
+                continue;
+            };
+            let part_id = attributes.id.to_string();
+            if !part_states.contains_key(&part_id) {
+                part_ids.push(part_id.clone());
+            }
+            let part_state = part_states.entry(part_id).or_insert_with(PartState::new);
+            for part_element in content.iter() {
+                part_state.read_element(part_element, &mut tempo);
+            }
+        }
+    }
+
+    let parts = part_ids.iter()
+        .map(|part_id| part_states.remove(part_id).unwrap().notes)
+        .collect();
+    Ok((parts, tempo))
+}
+
+// Reads a MusicXML score into a grid with one track per part. Each sequence's step is the shortest
+// standard note value in its part.
+#[allow(dead_code)]
+pub(crate) fn import_musicxml(path: &str) -> Result<TrackGrid<FixedTimeNoteSequence>, String> {
+    let (parts, tempo) = read_musicxml(path)?;
+    let tempo = tempo.unwrap_or(DEFAULT_BPM as f32);
+    let quarter_ms = MSECS_PER_MIN / tempo;
+
+    let mut tracks = Vec::new();
+    for (part_idx, notes) in parts.iter().enumerate() {
+        let step_duration_type = notes.iter()
+            .filter_map(|note| duration_type(note.duration_quarters))
+            .min_by(|a, b| a.to_factor().total_cmp(&b.to_factor()))
+            .unwrap_or(DurationType::Quarter);
+        let mut sequence = FixedTimeNoteSequenceBuilder::default()
+            .tempo(tempo.round().clamp(1.0, u8::MAX as f32) as u8)
+            .duration_type(step_duration_type)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        for note in notes {
+            let start_time_ms = note.start_quarters * quarter_ms;
+            let end_time_ms = start_time_ms + note.duration_quarters * quarter_ms;
+            sequence.append_note(playback_note(note, start_time_ms, end_time_ms)?);
+        }
+
+        tracks.push(TrackBuilder::default()
+            .num(part_idx as i16)
+            .sequence(sequence)
+            .volume(1.0 / parts.len() as f32)
+            .build()
+            .map_err(|e| e.to_string())?);
+    }
+
+    TrackGridBuilder::default()
+        .tracks(tracks)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn playback_note(note: &ScoreNote, start_time_ms: f32, end_time_ms: f32)
+        -> Result<PlaybackNote, String> {
+    // get_frequency counts octaves from MIDI note 0, one below scientific pitch notation
+    let note = NoteBuilder::default()
+        .frequency(note.pitch.get_frequency(note.octave + 1))
+        .start_time_ms(start_time_ms)
+        .end_time_ms(end_time_ms)
+        .build()
+        .map_err(|e| e.to_string())?;
+    PlaybackNoteBuilder::default()
+        .note_type(NoteType::Oscillator)
+        .note(note)
+        .playback_start_time_ms(start_time_ms)
+        .playback_end_time_ms(end_time_ms)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test_musicxml {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::composition::musicxml::{duration_type, import_musicxml, read_musicxml};
+    use crate::meter::durations::DurationType;
+    use crate::note::constants::PITCH_TO_FREQ_HZ;
+    use crate::note::scales::WesternPitch;
+
+    static TEST_MUSICXML_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                              "/src/composition/test_data/test_score.musicxml");
+
+    #[test]
+    fn test_read_pitches() {
+        let (parts, tempo) = read_musicxml(TEST_MUSICXML_FILE).unwrap();
+        assert_eq!(tempo, Some(90.0));
+        assert_eq!(parts.len(), 2);
+
+        // the rest, the unpitched note and the tied continuation are not notes of their own
+        let pitches: Vec<(WesternPitch, u8)> = parts[0].iter()
+            .map(|note| (note.pitch, note.octave))
+            .collect();
+        assert_eq!(pitches, vec![
+            (WesternPitch::C, 4),
+            (WesternPitch::DSharp, 4),
+            (WesternPitch::ASharp, 3),
+            (WesternPitch::C, 4),
+            (WesternPitch::E, 4),
+            (WesternPitch::G, 4),
+        ]);
+
+        // the chord's E starts with its C, and the tie makes the G a whole note
+        let starts: Vec<f32> = parts[0].iter().map(|note| note.start_quarters).collect();
+        assert_eq!(starts, vec![0.0, 1.0, 3.0, 4.0, 4.0, 6.0]);
+        assert_float_eq(parts[0][5].duration_quarters, 4.0);
+
+        // the flat and sharp that cross an octave boundary
+        let pitches: Vec<(WesternPitch, u8)> = parts[1].iter()
+            .map(|note| (note.pitch, note.octave))
+            .collect();
+        assert_eq!(pitches, vec![(WesternPitch::C, 3), (WesternPitch::B, 2)]);
+    }
+
+    #[test]
+    fn test_import_musicxml() {
+        let track_grid = import_musicxml(TEST_MUSICXML_FILE).unwrap();
+        assert_eq!(track_grid.tracks.len(), 2);
+
+        let mut notes = track_grid.tracks[0].sequence.get_all_notes();
+        assert_eq!(notes.len(), 6);
+        notes.sort_by(|a, b| a.note.start_time_ms.total_cmp(&b.note.start_time_ms));
+        // middle C, and a quarter note at 90 bpm
+        assert_float_eq(notes[0].note.frequency, PITCH_TO_FREQ_HZ[60] as f32);
+        assert_float_eq(notes[1].note.start_time_ms, 60000.0 / 90.0);
+    }
+
+    #[test]
+    fn test_duration_type() {
+        assert_eq!(duration_type(1.0), Some(DurationType::Quarter));
+        assert_eq!(duration_type(0.25), Some(DurationType::Sixteenth));
+        assert_eq!(duration_type(4.0), Some(DurationType::Whole));
+        // dotted
+        assert_eq!(duration_type(1.5), None);
+    }
+
+    #[test]
+    fn test_import_missing_musicxml_file() {
+        assert!(import_musicxml("/nonexistent/rosco_test.musicxml").is_err());
+    }
+}
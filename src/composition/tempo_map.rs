@@ -0,0 +1,162 @@
+static MSECS_PER_MIN: f32 = 60000.0;
+
+/// How the tempo moves from one point of a tempo map to the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum TempoCurve {
+    // Ramp evenly from one point's tempo to the next
+    #[default]
+    Linear,
+    // Hold each point's tempo until the next point
+    #[allow(dead_code)]
+    Step,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TempoPoint {
+    pub(crate) time_ms: f32,
+    pub(crate) bpm: f32,
+}
+
+/// Tempo over the course of a composition, as tempos at points in time. Before the first point the
+/// tempo is the first point's and after the last it is the last point's.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TempoMap {
+    // Sorted by time, never empty
+    points: Vec<TempoPoint>,
+    curve: TempoCurve,
+}
+
+impl TempoMap {
+    /// A constant tempo
+    pub(crate) fn new(bpm: f32) -> Self {
+        Self {
+            points: vec![TempoPoint { time_ms: 0.0, bpm: bpm.max(1.0) }],
+            curve: TempoCurve::default(),
+        }
+    }
+
+    pub(crate) fn from_points(points: &[(f32, f32)], curve: TempoCurve) -> Result<Self, String> {
+        let Some(&(first_time_ms, first_bpm)) = points.first() else {
+            return Err(String::from("TempoMap: at least one tempo point is required"));
+        };
+        let mut tempo_map = Self {
+            points: Vec::with_capacity(points.len()),
+            curve,
+        };
+        tempo_map.add_point(first_time_ms, first_bpm)?;
+        for &(time_ms, bpm) in &points[1..] {
+            tempo_map.add_point(time_ms, bpm)?;
+        }
+        Ok(tempo_map)
+    }
+
+    /// Add a tempo at a time, replacing any tempo already at that time
+    pub(crate) fn add_point(&mut self, time_ms: f32, bpm: f32) -> Result<(), String> {
+        if time_ms < 0.0 || !time_ms.is_finite() {
+            return Err(format!("TempoMap: time_ms must be 0.0 or greater, got {}", time_ms));
+        }
+        if bpm <= 0.0 || !bpm.is_finite() {
+            return Err(format!("TempoMap: bpm must be greater than 0.0, got {}", bpm));
+        }
+        let point = TempoPoint { time_ms, bpm };
+        match self.points.binary_search_by(|p| p.time_ms.total_cmp(&time_ms)) {
+            Ok(idx) => self.points[idx] = point,
+            Err(idx) => self.points.insert(idx, point),
+        }
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn points(&self) -> &[TempoPoint] {
+        &self.points
+    }
+
+    pub(crate) fn bpm_at(&self, time_ms: f32) -> f32 {
+        // Index of the first point after time_ms
+        let next_idx = self.points.partition_point(|p| p.time_ms <= time_ms);
+        if next_idx == 0 {
+            return self.points[0].bpm;
+        }
+        let prev = self.points[next_idx - 1];
+        let Some(next) = self.points.get(next_idx) else {
+            return prev.bpm;
+        };
+        match self.curve {
+            TempoCurve::Step => prev.bpm,
+            TempoCurve::Linear => {
+                let position = (time_ms - prev.time_ms) / (next.time_ms - prev.time_ms);
+                prev.bpm + position * (next.bpm - prev.bpm)
+            }
+        }
+    }
+
+    /// Start of a step, when steps follow each other from time 0 and each lasts `beats_per_step`
+    /// beats at the tempo where it starts
+    pub(crate) fn step_start_ms(&self, step_index: usize, beats_per_step: f32) -> f32 {
+        if let [point] = self.points.as_slice() {
+            return step_index as f32 * (MSECS_PER_MIN / point.bpm * beats_per_step);
+        }
+        (0..step_index).fold(0.0, |start_ms, _| {
+            start_ms + beats_per_step * MSECS_PER_MIN / self.bpm_at(start_ms)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_tempo_map {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::composition::tempo_map::{TempoCurve, TempoMap};
+
+    #[test]
+    fn test_constant_tempo() {
+        let tempo_map = TempoMap::new(120.0);
+        assert_float_eq(tempo_map.bpm_at(0.0), 120.0);
+        assert_float_eq(tempo_map.bpm_at(60000.0), 120.0);
+        assert_float_eq(tempo_map.step_start_ms(4, 1.0), 2000.0);
+    }
+
+    #[test]
+    fn test_ramp_midpoint() {
+        let tempo_map = TempoMap::from_points(&[(1000.0, 120.0), (3000.0, 140.0)],
+                                              TempoCurve::Linear).unwrap();
+        assert_float_eq(tempo_map.bpm_at(2000.0), 130.0);
+        assert_float_eq(tempo_map.bpm_at(1500.0), 125.0);
+        // holds the end tempos outside the ramp
+        assert_float_eq(tempo_map.bpm_at(0.0), 120.0);
+        assert_float_eq(tempo_map.bpm_at(5000.0), 140.0);
+    }
+
+    #[test]
+    fn test_step_curve() {
+        let tempo_map = TempoMap::from_points(&[(0.0, 120.0), (2000.0, 140.0)],
+                                              TempoCurve::Step).unwrap();
+        assert_float_eq(tempo_map.bpm_at(1999.0), 120.0);
+        assert_float_eq(tempo_map.bpm_at(2000.0), 140.0);
+    }
+
+    #[test]
+    fn test_points_are_sorted_and_replaced() {
+        let mut tempo_map = TempoMap::from_points(&[(2000.0, 140.0), (0.0, 100.0)],
+                                                  TempoCurve::Linear).unwrap();
+        tempo_map.add_point(0.0, 120.0).unwrap();
+        let times: Vec<f32> = tempo_map.points().iter().map(|p| p.time_ms).collect();
+        assert_eq!(times, vec![0.0, 2000.0]);
+        assert_float_eq(tempo_map.bpm_at(1000.0), 130.0);
+    }
+
+    #[test]
+    fn test_invalid_points() {
+        assert!(TempoMap::from_points(&[], TempoCurve::Linear).is_err());
+        assert!(TempoMap::from_points(&[(0.0, 0.0)], TempoCurve::Linear).is_err());
+        assert!(TempoMap::from_points(&[(-1.0, 120.0)], TempoCurve::Linear).is_err());
+    }
+
+    #[test]
+    fn test_steps_speed_up_through_a_ramp() {
+        let tempo_map = TempoMap::from_points(&[(0.0, 60.0), (1000.0, 120.0)],
+                                              TempoCurve::Step).unwrap();
+        // one second at 60 BPM, then half seconds at 120 BPM
+        assert_float_eq(tempo_map.step_start_ms(1, 1.0), 1000.0);
+        assert_float_eq(tempo_map.step_start_ms(3, 1.0), 2000.0);
+    }
+}
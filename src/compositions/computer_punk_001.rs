@@ -8,7 +8,7 @@ use crate::envelope::envelope::EnvelopeBuilder;
 use crate::envelope::envelope_pair::EnvelopePair;
 use crate::note::note_pool::NotePool;
 use crate::note::playback_note::{PlaybackNote, PlaybackNoteBuilder};
-use crate::note::sampled_note::{SampledNote, SampledNoteBuilder};
+use crate::note::sampled_note::{SampledNote, SampledNoteBuilder, RAW_INT16_VOLUME_SCALE};
 use crate::sequence::time_note_sequence::{TimeNoteSequence, TimeNoteSequenceBuilder};
 use crate::track::track_grid::TrackGridBuilder;
 use crate::track::track::TrackBuilder;
@@ -21,8 +21,9 @@ pub(crate) fn play() {
     // Init
     println!("playing 'computer punk {}'\n", self::COMPUTER_PUNK_VERSION);
 
-    let sampled_note_volume = 0.0009;
-    let sampled_note_rev_volume = 0.0042 * 0.3;
+    // Set against raw 16-bit samples
+    let sampled_note_volume = 0.0009 * RAW_INT16_VOLUME_SCALE;
+    let sampled_note_rev_volume = 0.0042 * 0.3 * RAW_INT16_VOLUME_SCALE;
 
     // Track Effect
     #[allow(unused_variables)]
@@ -1,25 +1,41 @@
 use std::str::FromStr;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use regex;
-
-use crate::audio_gen::oscillator::Waveform;
-use crate::effect::delay::DelayBuilder;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::audio_gen::oscillator::{Waveform, DEFAULT_DUTY_CYCLE};
+use crate::composition::tempo_map::{TempoCurve, TempoMap};
+use crate::effect::delay::{DecayCurve, DelayBuilder};
+use crate::effect::bitcrusher::BitCrusherBuilder;
+use crate::effect::distortion::{DistortionBuilder, DistortionCurve};
 use crate::effect::flanger::{FlangerBuilder};
 use crate::effect::lfo::{LFOBuilder};
-use crate::envelope::envelope::{EnvelopeBuilder};
+use crate::effect::tremolo::TremoloBuilder;
+use crate::envelope::envelope::{Envelope, EnvelopeBuilder, EnvelopeCurve, EnvelopePreset};
 use crate::envelope::envelope_pair::EnvelopePair;
 use crate::filter::low_pass_filter::{LowPassFilterBuilder};
 use crate::meter::durations::{DurationType};
+use crate::note::constants::{DEFAULT_FREQUENCY, DEFAULT_UNISON_VOICES, PITCH_TO_FREQ_HZ};
 use crate::note::note::{NoteBuilder};
 use crate::note::playback_note::{NoteType, PlaybackNote, PlaybackNoteBuilder};
-use crate::note::sampled_note::{SampledNoteBuilder};
-use crate::note::scales::WesternPitch;
+use crate::note::sampled_note::{SampleLoop, SampledNoteBuilder, RAW_INT16_VOLUME_SCALE};
+use crate::note::scales::{WesternPitch, WesternScale, MAX_FREQUENCY_OCTAVE};
+use crate::sequence::drum_patterns::{repeat_drum_pattern, HIHAT_PATTERN, KICK_PATTERN,
+                                     SNARE_PATTERN};
+use crate::sequence::euclidean::euclidean;
 use crate::sequence::fixed_time_note_sequence::{FixedTimeNoteSequence, FixedTimeNoteSequenceBuilder};
 use crate::sequence::note_sequence_trait::AppendNote;
 use crate::track::track::{Track, TrackBuilder};
 use crate::track::track_effects::{TrackEffects, TrackEffectsBuilder};
 use crate::track::track_grid::{TrackGrid, TrackGridBuilder};
 
+// Seed of the random generator when a call doesn't give one
+static DEFAULT_RANDOM_GENERATOR_SEED: u64 = 0x5c0;
+// Octave of a pitch, or the root of a scale, written without one
+static DEFAULT_NOTE_OCTAVE: u8 = 4;
+
 #[derive(Debug, Clone, PartialEq)]
 #[allow(dead_code)]
 pub enum WaveformType {
@@ -33,6 +49,9 @@ pub enum WaveformType {
     Saw,
     GaussianNoise,
     Noise,
+    Pulse,
+    PinkNoise,
+    BrownNoise,
 }
 
 impl FromStr for WaveformType {
@@ -45,6 +64,9 @@ impl FromStr for WaveformType {
             "triangle" | "tri" => Ok(WaveformType::Triangle),
             "sawtooth" | "saw" => Ok(WaveformType::Sawtooth),
             "gaussiannoise" | "noise" => Ok(WaveformType::GaussianNoise),
+            "pulse" => Ok(WaveformType::Pulse),
+            "pinknoise" | "pink" => Ok(WaveformType::PinkNoise),
+            "brownnoise" | "brown" => Ok(WaveformType::BrownNoise),
             _ => Err(format!("Unknown waveform: {}", s)),
         }
     }
@@ -58,6 +80,9 @@ impl WaveformType {
             WaveformType::Triangle | WaveformType::Tri => Waveform::Triangle,
             WaveformType::Sawtooth | WaveformType::Saw => Waveform::Saw,
             WaveformType::GaussianNoise | WaveformType::Noise => Waveform::GaussianNoise,
+            WaveformType::Pulse => Waveform::Pulse,
+            WaveformType::PinkNoise => Waveform::PinkNoise,
+            WaveformType::BrownNoise => Waveform::BrownNoise,
         }
     }
 }
@@ -146,6 +171,7 @@ pub struct DelayDef {
     pub num_repeats: usize,
     pub num_predelay_samples: usize,
     pub num_concurrent_delays: usize,
+    pub decay_curve: DecayCurve,
 }
 
 #[derive(Debug, Clone)]
@@ -153,6 +179,23 @@ pub struct DelayDef {
 pub struct FlangerDef {
     pub window_size: usize,
     pub mix: f32,
+    pub feedback: f32,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct BitCrusherDef {
+    pub bits: u8,
+    pub downsample: usize,
+    pub mix: f32,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct DistortionDef {
+    pub drive: f32,
+    pub curve: DistortionCurve,
+    pub mix: f32,
 }
 
 #[derive(Debug, Clone)]
@@ -163,6 +206,14 @@ pub struct LFODef {
     pub waveforms: Vec<WaveformType>,
 }
 
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct TremoloDef {
+    pub rate: f32,
+    pub depth: f32,
+    pub waveform: WaveformType,
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct FilterDef {
@@ -176,7 +227,10 @@ pub struct FilterDef {
 pub enum EffectDef {
     Delay(DelayDef),
     Flanger(FlangerDef),
+    BitCrusher(BitCrusherDef),
+    Distortion(DistortionDef),
     LFO(LFODef),
+    Tremolo(TremoloDef),
     Filter(FilterDef),
 }
 
@@ -187,6 +241,7 @@ pub struct EnvelopeDef {
     pub decay: (f32, f32),
     pub sustain: (f32, f32),
     pub release: (f32, f32),
+    pub curve: EnvelopeCurve,
 }
 
 #[derive(Debug, Clone)]
@@ -196,6 +251,18 @@ pub struct SequenceDef {
     pub tempo: u8,
     pub num_steps: usize,
     pub panning: Option<f32>,
+    // Tempo to ramp to from `tempo`, and the time in ms the ramp ends at
+    pub tempo_ramp: Option<(f32, f32)>,
+}
+
+impl SequenceDef {
+    pub(crate) fn tempo_map(&self) -> Result<TempoMap, String> {
+        match self.tempo_ramp {
+            Some((ramp_bpm, ramp_ms)) => TempoMap::from_points(
+                &[(0.0, self.tempo as f32), (ramp_ms, ramp_bpm)], TempoCurve::Linear),
+            None => Ok(TempoMap::new(self.tempo as f32)),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -206,11 +273,31 @@ pub enum NoteDeclaration {
         note_freq: f32,
         volume: f32,
         step_index: usize,
+        duty_cycle: f32,
+        unison_voices: u8,
+        detune_cents: f32,
+        // Shapes only this note, on top of the block's envelopes
+        envelope_def: Option<EnvelopeDef>,
     },
     Sample {
         file_path: String,
         volume: f32,
         step_index: usize,
+        // The pitch to play the sample at, and the pitch it was recorded at
+        frequency: f32,
+        root_frequency: f32,
+        // Crossfade in ms where the sample loops to sustain for the whole note, None to play once
+        loop_crossfade_ms: Option<f32>,
+        envelope_def: Option<EnvelopeDef>,
+    },
+    Fm {
+        carrier_waveform: WaveformType,
+        modulator_ratio: f32,
+        modulation_index: f32,
+        note_freq: f32,
+        volume: f32,
+        step_index: usize,
+        envelope_def: Option<EnvelopeDef>,
     },
 }
 
@@ -237,15 +324,58 @@ pub struct Script {
     pub outer_blocks: Vec<OuterBlock>,
 }
 
+// Line and column of a token in the script, counting from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TokenPosition {
+    line: usize,
+    col: usize,
+}
+
+// The scale set by the last `scale` line, which note frequencies written as degrees are in
+#[derive(Debug, Clone, Copy)]
+struct ScaleContext {
+    root: WesternPitch,
+    octave: u8,
+    scale: WesternScale,
+}
+
 #[allow(dead_code)]
 pub struct Parser {
     tokens: Vec<String>,
+    token_positions: Vec<TokenPosition>,
     current: usize,
+    // Reported when parsing, from an include or repeat that couldn't be expanded
+    preprocess_error: Option<String>,
+    scale_context: Option<ScaleContext>,
 }
 
 impl Parser {
+    /// Parser for a script, with included files found relative to the working directory
     #[allow(dead_code)]
     pub fn new(input: &str) -> Self {
+        match Self::expand_includes(input, Path::new("."), &mut Vec::new()) {
+            Ok((input, include_source_lines)) => Self::from_included(&input, include_source_lines),
+            Err(e) => {
+                let mut parser = Self::from_included("", Vec::new());
+                parser.preprocess_error = Some(e);
+                parser
+            }
+        }
+    }
+
+    /// Parser for a script file, with included files found relative to it
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let input = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read DSL file {}: {}", path, e))?;
+        let path = Path::new(path);
+        let mut include_stack = vec![Self::canonical_include_path(path)?];
+        let (input, include_source_lines) = Self::expand_includes(
+            &input, path.parent().unwrap_or(Path::new(".")), &mut include_stack)?;
+        Ok(Self::from_included(&input, include_source_lines))
+    }
+
+    // `include_source_lines` are the lines of the script, from 1, each line of the input came from
+    fn from_included(input: &str, include_source_lines: Vec<usize>) -> Self {
         let input_tokens: Vec<String> = input.lines().map(|s| s.to_string()).collect();
         
         let input_after_macro = Self::expand_macros(input_tokens.join("\n").as_str())
@@ -254,19 +384,160 @@ impl Parser {
         let input_after_generators = Self::expand_generators(input_after_macro.as_str())
             .unwrap_or_else(|_| input_after_macro.to_string());
         
-        let input_after_apply= Self::expand_apply_defs(input_after_generators.as_str()).unwrap_or_else(|_| Vec::new());
+        let generated_lines: Vec<(String, usize)> = input_after_generators.lines().enumerate()
+            .map(|(line_idx, line)| (line.to_string(), line_idx + 1))
+            .collect();
+        let (repeat_result, preprocess_error) = match Self::expand_repeat_blocks(&generated_lines) {
+            Ok(repeated_lines) => (repeated_lines, None),
+            Err(e) => (generated_lines, Some(e)),
+        };
+        let (input_after_repeat, repeat_source_lines): (Vec<String>, Vec<usize>) =
+            repeat_result.into_iter().unzip();
+
+        let (input_after_apply, apply_source_lines) =
+            Self::expand_apply_defs(input_after_repeat.join("\n").as_str()).unwrap_or_default();
 
 
         // TEMP DEBUG
         print!("AFTER APPLY:\n{}", input_after_apply.join("\n"));
 
 
-        let tokens = Self::tokenize(&input_after_apply.join("\n"));
-        
+        // Lines added by an include, repeat or apply report the line of the include, repeat or
+        // apply
+        let source_line = |source_lines: &[usize], line: usize| {
+            source_lines.get(line - 1).copied().unwrap_or(line)
+        };
+        let script_position = |position: TokenPosition| {
+            let line = source_line(&apply_source_lines, position.line);
+            let line = source_line(&repeat_source_lines, line);
+            let line = source_line(&include_source_lines, line);
+            TokenPosition { line, ..position }
+        };
+        let (tokens, token_positions, tokenize_error) =
+            match Self::tokenize(&input_after_apply.join("\n")) {
+                Ok(tokens) => {
+                    let (tokens, token_positions) = tokens.into_iter()
+                        .map(|(token, position)| (token, script_position(position)))
+                        .unzip();
+                    (tokens, token_positions, None)
+                }
+                Err((position, error)) => {
+                    let position = script_position(position);
+                    (Vec::new(), Vec::new(),
+                     Some(format!("line {}, col {}: {}", position.line, position.col, error)))
+                }
+            };
+
         Self {
             tokens,
+            token_positions,
             current: 0,
+            preprocess_error: preprocess_error.or(tokenize_error),
+            scale_context: None,
+        }
+    }
+
+    // Expand `repeat N { ... }` blocks into N copies of the lines in the block, with `{i}` replaced
+    // by the index of the copy, from 0. A block can span lines. Nested repeats expand first, so an
+    // `{i}` is the index of the innermost repeat it is in. Repeats expand before apply, so an apply
+    // in a block is copied and then each copy is applied. Lines are paired with the line of the
+    // input they came from.
+    fn expand_repeat_blocks(lines: &[(String, usize)]) -> Result<Vec<(String, usize)>, String> {
+        let mut expanded = Vec::new();
+        let mut line_idx = 0;
+        while line_idx < lines.len() {
+            let (line, source_line) = &lines[line_idx];
+            line_idx += 1;
+            let Some(repeat) = line.trim().strip_prefix("repeat ") else {
+                expanded.push((line.clone(), *source_line));
+                continue;
+            };
+            let Some((count, mut block_rest)) = repeat.split_once('{') else {
+                return Err(format!("line {}: repeat requires a block in braces", source_line));
+            };
+            let count = count.trim().parse::<usize>()
+                .map_err(|_| format!("line {}: repeat count must be a non-negative integer, got '{}'",
+                                     source_line, count.trim()))?;
+
+            // Collect the lines of the block, up to the '}' that closes it
+            let mut block = Vec::new();
+            let mut block_source_line = *source_line;
+            let mut depth = 1;
+            let after_block = loop {
+                let close_idx = block_rest.char_indices().find_map(|(idx, ch)| {
+                    match ch {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    (depth == 0).then_some(idx)
+                });
+                if let Some(close_idx) = close_idx {
+                    block.push((block_rest[..close_idx].trim().to_string(), block_source_line));
+                    break &block_rest[close_idx + 1..];
+                }
+                block.push((block_rest.trim().to_string(), block_source_line));
+                let Some((next_line, next_source_line)) = lines.get(line_idx) else {
+                    return Err(format!("line {}: repeat block is never closed", source_line));
+                };
+                line_idx += 1;
+                block_rest = next_line;
+                block_source_line = *next_source_line;
+            };
+            block.retain(|(block_line, _)| !block_line.is_empty());
+
+            let block = Self::expand_repeat_blocks(&block)?;
+            for i in 0..count {
+                for (block_line, block_source_line) in &block {
+                    expanded.push((block_line.replace("{i}", &i.to_string()), *block_source_line));
+                }
+            }
+            if !after_block.trim().is_empty() {
+                expanded.push((after_block.to_string(), block_source_line));
+            }
+        }
+        Ok(expanded)
+    }
+
+    // Replace each `include "path"` line with the lines of the file, which can include others in
+    // turn. Paths are relative to the including file. Returns the expanded script and the line of
+    // the input, from 1, that each of its lines came from.
+    fn expand_includes(input: &str, base_dir: &Path, include_stack: &mut Vec<PathBuf>)
+                       -> Result<(String, Vec<usize>), String> {
+        let mut lines = Vec::new();
+        let mut source_lines = Vec::new();
+        for (line_idx, line) in input.lines().enumerate() {
+            let Some(include_path) = line.trim().strip_prefix("include ") else {
+                lines.push(line.to_string());
+                source_lines.push(line_idx + 1);
+                continue;
+            };
+            let include_path = base_dir.join(include_path.trim().trim_matches('"'));
+            let canonical_path = Self::canonical_include_path(&include_path)?;
+            if include_stack.contains(&canonical_path) {
+                return Err(format!("line {}: include cycle, {} is already being included",
+                                   line_idx + 1, include_path.display()));
+            }
+            let included = std::fs::read_to_string(&include_path)
+                .map_err(|e| format!("line {}: failed to read include {}: {}",
+                                     line_idx + 1, include_path.display(), e))?;
+
+            include_stack.push(canonical_path);
+            let (included, _) = Self::expand_includes(
+                &included, include_path.parent().unwrap_or(base_dir), include_stack)?;
+            include_stack.pop();
+
+            for included_line in included.lines() {
+                lines.push(included_line.to_string());
+                source_lines.push(line_idx + 1);
+            }
         }
+        Ok((lines.join("\n"), source_lines))
+    }
+
+    fn canonical_include_path(path: &Path) -> Result<PathBuf, String> {
+        std::fs::canonicalize(path)
+            .map_err(|e| format!("Failed to find DSL file {}: {}", path.display(), e))
     }
 
     fn expand_macros(input: &str) -> Result<String, String> {
@@ -334,58 +605,64 @@ impl Parser {
         Ok(Some((name, value)))
     }
 
-    // TODO FIX INNER LOOP BORROW ISSUE SO THAT WE CAN HAVE MORE THAN ONE SUBST PER LINE
-    #[allow(unused_assignments)]
+    // Expand every generator call on each line, e.g. `(range 0,4,1)`
     fn expand_generators(input: &str) -> Result<String, String> {
+        let lines: Vec<String> = input.lines().map(Self::expand_line_generators).collect();
+        Ok(lines.join("\n"))
+    }
+
+    fn expand_line_generators(line: &str) -> String {
+        let mut expanded = String::with_capacity(line.len());
+        let mut copied_to = 0;
+        for (start, end) in Self::generator_spans(line) {
+            expanded.push_str(&line[copied_to..start]);
+            expanded.push_str(&Self::call_generator_with_args(&line[start..end])
+                .unwrap_or("parse of generator failed".to_string()));
+            copied_to = end;
+        }
+        expanded.push_str(&line[copied_to..]);
+        expanded
+    }
 
-        let mut lines: Vec<String> = input.lines().map(|s| s.to_string()).collect();
-
-        let mut i = 0;
-        let lines_len = lines.len();
-        while i < lines_len {
-            let line_content = lines[i].trim();
-            let mut chars = line_content.chars().peekable();
-            let mut in_generator = false;
-            let mut j: usize= 0;
-            let mut lbound: usize= 0;
-            let mut rbound: usize = 0;
-            while let Some(ch) = chars.next() {
-                if ch == '\n' {
-                    break;
-                }
-                if in_generator && ch != ')' {
-                    j += 1;
-                    continue;
+    // Byte ranges of the generator calls on a line, each from a '(' to its matching ')'.
+    // Parentheses nested in a call are part of it, and unbalanced ones aren't calls.
+    fn generator_spans(line: &str) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for (idx, ch) in line.char_indices() {
+            match ch {
+                '(' => {
+                    if depth == 0 {
+                        start = idx;
+                    }
+                    depth += 1;
                 }
-                if ch == '(' {
-                    in_generator = true;
-                    lbound = j;
-                    j += 1;
-                    continue;
-
-                } else if ch == ')' {
-                    rbound = j;
-                    let generated =
-                        Self::call_generator_with_args(&line_content[lbound..rbound + 1])
-                            .unwrap_or("parse of generator failed".to_string());
-                    lines[i] = line_content.replace(&line_content[lbound..rbound + 1], &generated);
-                    in_generator = false;
-                    break;
+                ')' if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        spans.push((start, idx + 1));
+                    }
                 }
-                j += 1;
+                _ => {}
             }
-            i += 1;
         }
-        return Ok(lines.join("\n"));
+        spans
     }
 
     fn call_generator_with_args(generator_substring: &str) -> Result<String, String> {
-        let generator_and_args = generator_substring[1..generator_substring.len() - 1]
-            .split(" ").collect::<Vec<&str>>();
-        let generator_name = generator_and_args[0];
-        let args = generator_and_args[1].split(",").collect::<Vec<&str>>();
+        let generator_call = generator_substring[1..generator_substring.len() - 1].trim();
+        let Some((generator_name, args)) = generator_call.split_once(' ') else {
+            return Err(format!("Generator requires arguments: {}", generator_substring));
+        };
+        let args = args.trim().split(",").collect::<Vec<&str>>();
         match generator_name {
             "range" => Self::expand_range_generator(args),
+            "euclid" => Self::expand_euclid_generator(args),
+            "random" => Self::expand_random_generator(args),
+            "kick" => Self::expand_drum_pattern_generator("kick", &KICK_PATTERN, args),
+            "snare" => Self::expand_drum_pattern_generator("snare", &SNARE_PATTERN, args),
+            "hihat" => Self::expand_drum_pattern_generator("hihat", &HIHAT_PATTERN, args),
             _ => Err(format!("Unknown generator: {}", generator_name)),
         }
     }
@@ -406,13 +683,73 @@ impl Parser {
         Ok(result)
     }
 
-    fn expand_apply_defs(input: &str) -> Result<Vec<String>, String> {
+    // (kick steps), (snare steps) and (hihat steps) expand to the steps of the preset pattern
+    fn expand_drum_pattern_generator(name: &str, pattern: &[usize], args: Vec<&str>)
+            -> Result<String, String> {
+        if args.len() != 1 {
+            return Err(format!("{} generator requires 1 argument", name));
+        }
+        let num_steps = args[0].parse::<usize>()
+            .map_err(|_| format!("{} generator steps must be a non-negative integer", name))?;
+        let step_indexes: Vec<String> = repeat_drum_pattern(pattern, num_steps).iter()
+            .map(|step_index| step_index.to_string())
+            .collect();
+        Ok(step_indexes.join(","))
+    }
+
+    // (euclid hits,steps,rotation) expands to the indexes of the steps with hits
+    fn expand_euclid_generator(args: Vec<&str>) -> Result<String, String> {
+        if args.len() != 3 {
+            return Err("euclid generator requires 3 arguments".to_string());
+        }
+        let hits = args[0].parse::<usize>().map_err(|_| "euclid generator hits must be a non-negative integer".to_string())?;
+        let steps = args[1].parse::<usize>().map_err(|_| "euclid generator steps must be a non-negative integer".to_string())?;
+        let rotation = args[2].parse::<usize>().map_err(|_| "euclid generator rotation must be a non-negative integer".to_string())?;
+        let step_indexes: Vec<String> = euclidean(hits, steps, rotation).iter()
+            .enumerate()
+            .filter(|(_, hit)| **hit)
+            .map(|(step_index, _)| step_index.to_string())
+            .collect();
+        Ok(step_indexes.join(","))
+    }
+
+    // (random count,min,max[,seed]) expands to count integers from min to max, inclusive. The same
+    // seed always expands to the same integers.
+    fn expand_random_generator(args: Vec<&str>) -> Result<String, String> {
+        if args.len() != 3 && args.len() != 4 {
+            return Err("random generator requires 3 or 4 arguments".to_string());
+        }
+        let count = args[0].parse::<usize>().map_err(|_| "random generator count must be a non-negative integer".to_string())?;
+        let min = args[1].parse::<i32>().map_err(|_| "random generator min must be an integer".to_string())?;
+        let max = args[2].parse::<i32>().map_err(|_| "random generator max must be an integer".to_string())?;
+        let seed = match args.get(3) {
+            Some(seed) => seed.parse::<u64>().map_err(|_| "random generator seed must be a non-negative integer".to_string())?,
+            None => DEFAULT_RANDOM_GENERATOR_SEED,
+        };
+        if min > max {
+            return Err("random generator min must not be greater than max".to_string());
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let values: Vec<String> = (0..count)
+            .map(|_| rng.random_range(min..=max).to_string())
+            .collect();
+        Ok(values.join(","))
+    }
+
+    // Returns the expanded lines and the line of the input, from 1, that each came from
+    fn expand_apply_defs(input: &str) -> Result<(Vec<String>, Vec<usize>), String> {
     
         let mut lines: Vec<String> = input.lines().map(|s| s.to_string()).collect();
+        let mut source_lines: Vec<usize> = (1..=lines.len()).collect();
         let mut i = 0;
         
         while i < lines.len() {
-            let line_content = lines[i].trim();
+            // Leave out a trailing comment, its words aren't part of the apply
+            let line_content = lines[i].split_whitespace()
+                .take_while(|word| !word.starts_with('#'))
+                .collect::<Vec<&str>>()
+                .join(" ");
+            let line_content = line_content.as_str();
             if line_content.starts_with("apply") {
                 if let Some((apply_defs, _identifier)) =
                         Self::parse_apply_def(line_content)? {
@@ -435,6 +772,7 @@ impl Parser {
                     // Insert new lines
                     for (j, new_line) in new_lines.into_iter().enumerate() {
                         lines.insert(i + j + 1, new_line);
+                        source_lines.insert(i + j + 1, source_lines[i]);
                     }
                     // Skip index past inserted lines
                     i += num_new_lines;
@@ -443,7 +781,7 @@ impl Parser {
             i += 1;
         }
 
-        Ok(lines)
+        Ok((lines, source_lines))
         
     }
 
@@ -458,7 +796,7 @@ impl Parser {
         for token in parts.iter_mut() {
             if token.contains(":") {
                 let key = token.split(":").next().unwrap().to_string();
-                if key == "osc" || key == "samp" {
+                if key == "osc" || key == "samp" || key == "fm" {
                     continue;
                 }  
                 let value =
@@ -473,17 +811,28 @@ impl Parser {
         Ok(Some((apply_defs, identifier)))
     }
 
-    fn tokenize(input: &str) -> Vec<String> {
+    // The tokens of a script and where each starts, or where a quoted file path left open starts
+    fn tokenize(input: &str) -> Result<Vec<(String, TokenPosition)>, (TokenPosition, String)> {
         let mut tokens = Vec::new();
         let mut current_token = String::new();
+        let mut token_start = TokenPosition { line: 1, col: 1 };
         let mut in_comment = false;
         let mut in_file_path = false;
-        let mut chars = input.chars().peekable();
-        let mut at_line_start = true;
         let mut line_buffer = String::new();
+        let mut next_position = TokenPosition { line: 1, col: 1 };
+
+        for ch in input.chars() {
+            let position = next_position;
+            if ch == '\n' {
+                next_position = TokenPosition { line: position.line + 1, col: 1 };
+            } else {
+                next_position.col += 1;
+            }
 
-        while let Some(ch) = chars.next() {
-            if at_line_start && ch == '#' {
+            // A comment runs from a '#' that starts a word to the end of the line, so sharps like
+            // F# and a '#' in a file path aren't comments
+            let starts_word = line_buffer.chars().last().is_none_or(|prev| prev.is_whitespace());
+            if ch == '#' && starts_word && !in_comment && !in_file_path {
                 in_comment = true;
                 continue;
             }
@@ -493,18 +842,13 @@ impl Parser {
                 if !in_comment {
                     // If the line is blank (only whitespace), skip it
                     if line_buffer.trim().is_empty() {
-                        at_line_start = true;
                         line_buffer.clear();
                         continue;
                     }
                 }
-                at_line_start = true;
                 line_buffer.clear();
             } else {
                 line_buffer.push(ch);
-                if !ch.is_whitespace() && ch != '#' {
-                    at_line_start = false;
-                }
             }
 
             if in_comment {
@@ -514,52 +858,60 @@ impl Parser {
                 continue;
             }
 
+            // A double quoted file path is one token, so it can have spaces, colons and '#'. It
+            // has to be closed on the line it starts on.
             if in_file_path {
-                if ch == ':' {
+                if ch == '\n' {
+                    return Err((token_start, "unterminated quoted file path".to_string()));
+                }
+                if ch == '"' {
                     in_file_path = false;
-                    if !current_token.is_empty() {
-                        tokens.push(current_token.clone());
-                        current_token.clear();
-                    }
-                    tokens.push(":".to_string());
+                    tokens.push((current_token.clone(), token_start));
+                    current_token.clear();
                 } else {
                     current_token.push(ch);
                 }
                 continue;
             }
 
-            // Detect start of file path after 'samp' and ':'
-            if current_token == "samp" && chars.peek() == Some(&':') {
-                tokens.push(current_token.clone());
-                current_token.clear();
-                chars.next(); // consume the ':'
-                tokens.push(":".to_string());
+            // A quote right after `samp:` starts a quoted file path. Unquoted paths are split into
+            // tokens as usual and end at the next ':'.
+            if ch == '"' && current_token.is_empty() &&
+                matches!(tokens.as_slice(), [.., (samp, _), (colon, _)]
+                         if samp == "samp" && colon == ":") {
                 in_file_path = true;
+                token_start = position;
                 continue;
             }
 
             match ch {
                 ':' | ',' | ' ' | '\n' | '\r' | '\t' => {
                     if !current_token.is_empty() {
-                        tokens.push(current_token.clone());
+                        tokens.push((current_token.clone(), token_start));
                         current_token.clear();
                     }
                     if ch != ' ' && ch != '\n' && ch != '\r' && ch != '\t' {
-                        tokens.push(ch.to_string());
+                        tokens.push((ch.to_string(), position));
                     }
                 }
                 _ => {
+                    if current_token.is_empty() {
+                        token_start = position;
+                    }
                     current_token.push(ch);
                 }
             }
         }
 
+        if in_file_path {
+            return Err((token_start, "unterminated quoted file path".to_string()));
+        }
         if !current_token.is_empty() {
-            tokens.push(current_token);
+            tokens.push((current_token, token_start));
         }
 
-        tokens.retain(|token| !token.is_empty());
-        tokens
+        tokens.retain(|(token, _)| !token.is_empty());
+        Ok(tokens)
     }
 
     pub fn parse(&mut self) -> Result<TrackGrid<FixedTimeNoteSequence>, String> {
@@ -568,18 +920,26 @@ impl Parser {
     }
 
     fn parse_script(&mut self) -> Result<Script, String> {
+        if let Some(preprocess_error) = &self.preprocess_error {
+            return Err(preprocess_error.clone());
+        }
         let mut macro_defs = HashMap::new();
         let mut outer_blocks = Vec::new();
         
         // Parse macro definitions first
         while self.current < self.tokens.len() && self.peek() == "let" && !self.is_comment_start() {
-            let (name, expression) = self.parse_assignment()?;
+            let (name, expression) = self.parse_assignment()
+                .map_err(|e| self.located_error(e))?;
             macro_defs.insert(name, expression);
         }
         
         // Parse outer blocks
         while self.current < self.tokens.len() && !self.is_comment_start() {
-            let block = self.parse_outer_block()?;
+            if self.peek() == "scale" {
+                self.parse_scale_def().map_err(|e| self.located_error(e))?;
+                continue;
+            }
+            let block = self.parse_outer_block().map_err(|e| self.located_error(e))?;
             outer_blocks.push(block);
         }
 
@@ -596,7 +956,7 @@ impl Parser {
         let mut note_declarations = Vec::new();
 
         // Parse optional envelope definitions
-        while self.current < self.tokens.len() && self.peek() == "a" {
+        while self.current < self.tokens.len() && (self.peek() == "a" || self.peek() == "env") {
             let envelope_def = self.parse_envelope_def()?;
             envelope_defs.push(envelope_def);
         }
@@ -607,8 +967,13 @@ impl Parser {
             effect_defs.push(effect_def);
         }
 
-        // Parse note declarations
-        while self.current < self.tokens.len() && self.is_note_declaration_start() {
+        // Parse note declarations, and scales for the degrees of the notes after them
+        while self.current < self.tokens.len() &&
+            (self.is_note_declaration_start() || self.peek() == "scale") {
+            if self.peek() == "scale" {
+                self.parse_scale_def()?;
+                continue;
+            }
             let note_declaration = self.parse_note_declaration()?;
             note_declarations.push(note_declaration);
         }
@@ -629,8 +994,15 @@ impl Parser {
         let dur = self.parse_duration_type()?;
         self.expect("tempo")?;
         let tempo = self.parse_u8()?;
+        // Step times are 60000 / tempo ms apart, so a tempo of 0 would make them all NaN
+        if tempo == 0 {
+            return Err(String::from("tempo must be > 0"));
+        }
         self.expect("num_steps")?;
         let num_steps = self.parse_usize()?;
+        if num_steps == 0 {
+            return Err(String::from("num_steps must be > 0"));
+        }
 
         // Parse optional panning parameter
         let panning = if self.current < self.tokens.len() &&
@@ -642,11 +1014,27 @@ impl Parser {
             None
         };
 
+        // Parse optional tempo ramp, to a tempo by a time in ms
+        let tempo_ramp = if self.current < self.tokens.len() &&
+                            !self.is_comment_start() &&
+                            self.peek() == "tempo_ramp" {
+            self.expect("tempo_ramp")?;
+            let (ramp_bpm, ramp_ms) = self.parse_envelope_pair()?;
+            if ramp_bpm <= 0.0 || ramp_ms <= 0.0 {
+                return Err(format!("tempo_ramp tempo and time must be greater than 0.0, got {},{}",
+                                   ramp_bpm, ramp_ms));
+            }
+            Some((ramp_bpm, ramp_ms))
+        } else {
+            None
+        };
+
         Ok(SequenceDef {
             dur,
             tempo,
             num_steps,
             panning,
+            tempo_ramp,
         })
     }
 
@@ -658,6 +1046,10 @@ impl Parser {
     fn parse_envelope_def(&mut self) -> Result<EnvelopeDef, String> {
         self.skip_comment_lines();
 
+        if self.peek() == "env" {
+            return self.parse_envelope_preset_def();
+        }
+
         self.expect("a")?;
         let attack = self.parse_envelope_pair()?;
         self.expect("d")?;
@@ -667,11 +1059,36 @@ impl Parser {
         self.expect("r")?;
         let release = self.parse_envelope_pair()?;
 
+        // Parse optional curve parameter
+        let curve = if self.current < self.tokens.len() &&
+                       !self.is_comment_start() &&
+                       self.peek() == "curve" {
+            self.expect("curve")?;
+            EnvelopeCurve::from_str(&self.advance())?
+        } else {
+            EnvelopeCurve::Linear
+        };
+
         Ok(EnvelopeDef {
             attack,
             decay,
             sustain,
             release,
+            curve,
+        })
+    }
+
+    fn parse_envelope_preset_def(&mut self) -> Result<EnvelopeDef, String> {
+        self.expect("env")?;
+        self.expect("preset")?;
+        let envelope = Envelope::preset(EnvelopePreset::from_str(&self.advance())?);
+
+        Ok(EnvelopeDef {
+            attack: (envelope.attack.0, envelope.attack.1),
+            decay: (envelope.decay.0, envelope.decay.1),
+            sustain: (envelope.sustain.0, envelope.sustain.1),
+            release: (envelope.release.0, envelope.release.1),
+            curve: envelope.curve,
         })
     }
 
@@ -689,8 +1106,14 @@ impl Parser {
             self.parse_delay_def()
         } else if self.peek() == "flanger" {
             self.parse_flanger_def()
+        } else if self.peek() == "bitcrush" {
+            self.parse_bitcrusher_def()
+        } else if self.peek() == "distortion" {
+            self.parse_distortion_def()
         } else if self.peek() == "lfo" {
             self.parse_lfo_def()
+        } else if self.peek() == "tremolo" {
+            self.parse_tremolo_def()
         } else if self.peek() == "filter" {
             self.parse_filter_def()
         } else {
@@ -717,6 +1140,16 @@ impl Parser {
         self.expect("num_concurrent_delays")?;
         let num_concurrent_delays = self.parse_usize()?;
 
+        // Parse optional decay curve parameter
+        let decay_curve = if self.current < self.tokens.len() &&
+                             !self.is_comment_start() &&
+                             self.peek() == "curve" {
+            self.expect("curve")?;
+            DecayCurve::from_str(&self.advance())?
+        } else {
+            DecayCurve::Exponential
+        };
+
         Ok(EffectDef::Delay(DelayDef {
             mix,
             decay,
@@ -725,6 +1158,7 @@ impl Parser {
             num_repeats,
             num_predelay_samples,
             num_concurrent_delays,
+            decay_curve,
         }))
     }
 
@@ -737,9 +1171,56 @@ impl Parser {
         self.expect("mix")?;
         let mix = self.parse_f32()?;
 
+        // Parse optional feedback parameter
+        let feedback = if self.current < self.tokens.len() &&
+                          !self.is_comment_start() &&
+                          self.peek() == "feedback" {
+            self.expect("feedback")?;
+            self.parse_f32()?
+        } else {
+            0.0
+        };
+
         Ok(EffectDef::Flanger(FlangerDef {
             window_size,
             mix,
+            feedback,
+        }))
+    }
+
+    fn parse_bitcrusher_def(&mut self) -> Result<EffectDef, String> {
+        self.skip_comment_lines();
+
+        self.expect("bitcrush")?;
+        self.expect("bits")?;
+        let bits = self.parse_u8()?;
+        self.expect("downsample")?;
+        let downsample = self.parse_usize()?;
+        self.expect("mix")?;
+        let mix = self.parse_f32()?;
+
+        Ok(EffectDef::BitCrusher(BitCrusherDef {
+            bits,
+            downsample,
+            mix,
+        }))
+    }
+
+    fn parse_distortion_def(&mut self) -> Result<EffectDef, String> {
+        self.skip_comment_lines();
+
+        self.expect("distortion")?;
+        self.expect("drive")?;
+        let drive = self.parse_f32()?;
+        self.expect("curve")?;
+        let curve = DistortionCurve::from_str(&self.advance())?;
+        self.expect("mix")?;
+        let mix = self.parse_f32()?;
+
+        Ok(EffectDef::Distortion(DistortionDef {
+            drive,
+            curve,
+            mix,
         }))
     }
 
@@ -761,6 +1242,24 @@ impl Parser {
         }))
     }
 
+    fn parse_tremolo_def(&mut self) -> Result<EffectDef, String> {
+        self.skip_comment_lines();
+
+        self.expect("tremolo")?;
+        self.expect("rate")?;
+        let rate = self.parse_f32()?;
+        self.expect("depth")?;
+        let depth = self.parse_f32()?;
+        self.expect("waveform")?;
+        let waveform = self.parse_waveform()?;
+
+        Ok(EffectDef::Tremolo(TremoloDef {
+            rate,
+            depth,
+            waveform,
+        }))
+    }
+
     fn parse_filter_def(&mut self) -> Result<EffectDef, String> {
         self.skip_comment_lines();
 
@@ -806,6 +1305,8 @@ impl Parser {
             self.parse_osc_note()
         } else if self.peek() == "samp" {
             self.parse_samp_note()
+        } else if self.peek() == "fm" {
+            self.parse_fm_note()
         } else {
             Err(format!("Unknown note type: {}", self.peek()))
         }
@@ -824,11 +1325,45 @@ impl Parser {
         self.expect(":")?;
         let step_index = self.parse_usize()?;
 
+        // Parse optional duty cycle for pulse waveforms
+        let duty_cycle = if self.current < self.tokens.len() &&
+                            !self.is_comment_start() &&
+                            self.peek() == "duty" {
+            self.expect("duty")?;
+            let duty_cycle = self.parse_f32()?;
+            if !(0.0..=1.0).contains(&duty_cycle) {
+                return Err(format!("Pulse duty cycle must be between 0.0 and 1.0, got {}",
+                                   duty_cycle));
+            }
+            duty_cycle
+        } else {
+            DEFAULT_DUTY_CYCLE
+        };
+
+        // Parse optional unison voices and their detune spread
+        let (unison_voices, detune_cents) = if self.current < self.tokens.len() &&
+                                               !self.is_comment_start() &&
+                                               self.peek() == "unison" {
+            self.expect("unison")?;
+            let unison_voices = u8::try_from(self.parse_usize()?)
+                .map_err(|_| String::from("Unison voices must be at most 255"))?;
+            self.expect("detune")?;
+            (unison_voices, self.parse_f32()?)
+        } else {
+            (DEFAULT_UNISON_VOICES, 0.0)
+        };
+
+        let envelope_def = self.parse_note_envelope_def()?;
+
         Ok(NoteDeclaration::Oscillator {
             waveforms,
             note_freq,
             volume,
             step_index,
+            duty_cycle,
+            unison_voices,
+            detune_cents,
+            envelope_def,
         })
     }
 
@@ -839,19 +1374,153 @@ impl Parser {
         self.expect(":")?;
         let file_path = self.parse_file_path()?;
         self.expect(":")?;
-        let volume = self.parse_f32()?;
+        let mut volume = self.parse_f32()?;
         self.expect(":")?;
         let step_index = self.parse_usize()?;
 
+        // Parse the optional pitch to play the sample at, and the root pitch it was recorded at
+        let (frequency, root_frequency) = if self.current < self.tokens.len() &&
+                                             !self.is_comment_start() &&
+                                             self.peek() == "pitch" {
+            self.expect("pitch")?;
+            let frequency = self.parse_note_freq()?;
+            self.expect("root")?;
+            let root_frequency = self.parse_note_freq()?;
+            if frequency <= 0.0 || root_frequency <= 0.0 {
+                return Err(format!("Sample pitch and root must be greater than 0.0, got {} and {}",
+                                   frequency, root_frequency));
+            }
+            (frequency, root_frequency)
+        } else {
+            (DEFAULT_FREQUENCY, DEFAULT_FREQUENCY)
+        };
+
+        // Parse the optional crossfade to loop the sample with
+        let loop_crossfade_ms = if self.current < self.tokens.len() &&
+                                   !self.is_comment_start() &&
+                                   self.peek() == "loop" {
+            self.expect("loop")?;
+            let crossfade_ms = self.parse_f32()?;
+            if crossfade_ms < 0.0 {
+                return Err(format!("Sample loop crossfade must be at least 0.0 ms, got {}",
+                                   crossfade_ms));
+            }
+            Some(crossfade_ms)
+        } else {
+            None
+        };
+
+        // A volume set against raw 16-bit samples, as scripts from before samples were read
+        // normalized have
+        if self.current < self.tokens.len() && !self.is_comment_start() && self.peek() == "raw16" {
+            self.expect("raw16")?;
+            volume *= RAW_INT16_VOLUME_SCALE;
+        }
+
+        let envelope_def = self.parse_note_envelope_def()?;
+
         Ok(NoteDeclaration::Sample {
             file_path,
             volume,
             step_index,
+            frequency,
+            root_frequency,
+            loop_crossfade_ms,
+            envelope_def,
+        })
+    }
+
+    fn parse_fm_note(&mut self) -> Result<NoteDeclaration, String> {
+        self.skip_comment_lines();
+
+        self.expect("fm")?;
+        self.expect(":")?;
+        let carrier_waveform = self.parse_waveform()?;
+        self.expect(":")?;
+        let modulator_ratio = self.parse_f32()?;
+        if modulator_ratio <= 0.0 {
+            return Err(format!("FM modulator ratio must be greater than 0.0, got {}",
+                               modulator_ratio));
+        }
+        self.expect(":")?;
+        let modulation_index = self.parse_f32()?;
+        if modulation_index < 0.0 {
+            return Err(format!("FM modulation index must not be negative, got {}",
+                               modulation_index));
+        }
+        self.expect(":")?;
+        let note_freq = self.parse_note_freq()?;
+        self.expect(":")?;
+        let volume = self.parse_f32()?;
+        self.expect(":")?;
+        let step_index = self.parse_usize()?;
+        let envelope_def = self.parse_note_envelope_def()?;
+
+        Ok(NoteDeclaration::Fm {
+            carrier_waveform,
+            modulator_ratio,
+            modulation_index,
+            note_freq,
+            volume,
+            step_index,
+            envelope_def,
         })
     }
 
+    // Optional envelope at the end of a note, `env a 0.1,0.9 d ...`, `env preset pluck`, or an
+    // envelope macro, `env $pluck`, which can expand to either form
+    fn parse_note_envelope_def(&mut self) -> Result<Option<EnvelopeDef>, String> {
+        if self.current >= self.tokens.len() || self.is_comment_start() || self.peek() != "env" {
+            return Ok(None);
+        }
+        if self.tokens.get(self.current + 1).map(String::as_str) == Some("preset") {
+            return self.parse_envelope_preset_def().map(Some);
+        }
+        self.expect("env")?;
+        self.parse_envelope_def().map(Some)
+    }
+
+    // `scale C Major`, or with the root's octave, `scale 5,C Major`
+    fn parse_scale_def(&mut self) -> Result<(), String> {
+        self.expect("scale")?;
+        let mut pitch_token = self.advance();
+        let mut octave = DEFAULT_NOTE_OCTAVE;
+        if let (Ok(root_octave), ",") = (pitch_token.parse::<u8>(), self.peek()) {
+            self.advance(); // consume comma
+            octave = root_octave;
+            pitch_token = self.advance();
+        }
+        let root = WesternPitchType::from_str(&pitch_token)
+            .map_err(|_| format!("Invalid western pitch: {}", pitch_token))?
+            .to_western_pitch();
+        let scale = WesternScale::from_str(&self.advance())?;
+        self.scale_context = Some(ScaleContext { root, octave, scale });
+        Ok(())
+    }
+
+    // The frequency of a degree of the current scale, from deg1 for the root. Degrees past the last
+    // go on up through the octaves above, so deg8 of a major scale is the root an octave up.
+    fn parse_scale_degree(&self, token: &str, degree: &str) -> Result<f32, String> {
+        let degree = degree.parse::<usize>().ok().filter(|degree| *degree > 0)
+            .ok_or_else(|| format!("Invalid scale degree: {}, degrees start at deg1", token))?;
+        let Some(ScaleContext { root, octave, scale }) = self.scale_context else {
+            return Err(format!("Scale degree {} needs a scale, set one first with e.g. \
+                                `scale C Major`", token));
+        };
+        let pitch_idx = octave as usize * 12 + root.get_pitch_index() as usize +
+            scale.degree_semitones(degree - 1) as usize;
+        PITCH_TO_FREQ_HZ.get(pitch_idx)
+            .map(|freq| *freq as f32)
+            .ok_or_else(|| format!("Scale degree {} of {} {} is above the highest pitch",
+                                   token, root, scale))
+    }
+
     fn parse_note_freq(&mut self) -> Result<f32, String> {
         let token = self.advance();
+
+        if let Some(degree) = token.strip_prefix("deg") {
+            return self.parse_scale_degree(&token, degree);
+        }
         
         // Try to parse as octave,western_pitch format first
         if let Ok(octave) = token.parse::<u8>() {
@@ -860,7 +1529,9 @@ impl Parser {
                 let pitch_token = self.advance();
                 if let Ok(pitch) = WesternPitchType::from_str(&pitch_token) {
                     let western_pitch = pitch.to_western_pitch();
-                    return Ok(western_pitch.get_frequency(octave));
+                    return western_pitch.try_get_frequency(octave).ok_or_else(|| format!(
+                        "Octave {} is out of range for {}, octaves go from 0 to {} (up to G)",
+                        octave, western_pitch, MAX_FREQUENCY_OCTAVE));
                 } else {
                     return Err(format!("Invalid western pitch: {}", pitch_token));
                 }
@@ -871,13 +1542,14 @@ impl Parser {
         if let Ok(pitch) = WesternPitchType::from_str(&token) {
             let western_pitch = pitch.to_western_pitch();
             // Default to octave 4 (middle C)
-            return Ok(western_pitch.get_frequency(4));
+            return Ok(western_pitch.get_frequency(DEFAULT_NOTE_OCTAVE));
         }
         
         // Try to parse as float
         token.parse::<f32>().map_err(|_| format!("Invalid note frequency: {}", token))
     }
 
+    // A quoted file path is a single token, an unquoted one is the tokens up to the next ':'
     fn parse_file_path(&mut self) -> Result<String, String> {
         let mut file_path = String::new();
         
@@ -905,11 +1577,13 @@ impl Parser {
     }
 
     fn is_effect_start(&self) -> bool {
-        self.peek() == "delay" || self.peek() == "flanger" || self.peek() == "lfo" || self.peek() == "filter"
+        self.peek() == "delay" || self.peek() == "flanger" || self.peek() == "bitcrush" ||
+            self.peek() == "distortion" || self.peek() == "lfo" || self.peek() == "tremolo" ||
+            self.peek() == "filter"
     }
 
     fn is_note_declaration_start(&self) -> bool {
-        self.peek() == "osc" || self.peek() == "samp"
+        self.peek() == "osc" || self.peek() == "samp" || self.peek() == "fm"
     }
 
     fn is_comment_start(&self) -> bool {
@@ -921,7 +1595,15 @@ impl Parser {
         if token == expected {
             Ok(())
         } else {
-            Err(format!("Expected '{}', got '{}'", expected, token))
+            Err(format!("expected '{}', got '{}'", expected, token))
+        }
+    }
+
+    // Prefix an error with the line and column of the last token read, the one it is about
+    fn located_error(&self, error: String) -> String {
+        match self.token_positions.get(self.current.saturating_sub(1)) {
+            Some(position) => format!("line {}, col {}: {}", position.line, position.col, error),
+            None => error,
         }
     }
 
@@ -982,6 +1664,13 @@ impl Parser {
         // Add notes to sequence
         let mut sequence_with_notes = sequence;
         for note_decl in &block.note_declarations {
+            let step_index = note_decl.get_step_index();
+            if step_index >= block.sequence_def.num_steps {
+                return Err(format!("step_index {} is out of range for a sequence of {} steps, \
+                                    step indexes are 0 to {}",
+                                   step_index, block.sequence_def.num_steps,
+                                   block.sequence_def.num_steps - 1));
+            }
             let playback_note = self.build_playback_note(note_decl, &block.sequence_def, &block.effect_defs)?;
             sequence_with_notes.append_note(playback_note);
         }
@@ -1007,18 +1696,14 @@ impl Parser {
         let mut envelopes = Vec::new();
         let mut delays = Vec::new();
         let mut flangers = Vec::new();
+        let mut bitcrushers = Vec::new();
+        let mut distortions = Vec::new();
         let mut lfos = Vec::new();
+        let mut tremolos = Vec::new();
 
         // Build envelopes
         for env_def in envelope_defs {
-            let envelope = EnvelopeBuilder::default()
-                .attack(EnvelopePair(env_def.attack.0, env_def.attack.1))
-                .decay(EnvelopePair(env_def.decay.0, env_def.decay.1))
-                .sustain(EnvelopePair(env_def.sustain.0, env_def.sustain.1))
-                .release(EnvelopePair(env_def.release.0, env_def.release.1))
-                .build()
-                .map_err(|e| format!("Failed to build Envelope: {:?}", e))?;
-            envelopes.push(envelope);
+            envelopes.push(self.build_envelope(env_def)?);
         }
 
         // Build effects
@@ -1034,6 +1719,7 @@ impl Parser {
                         .num_repeats(delay_def.num_repeats)
                         .num_predelay_samples(delay_def.num_predelay_samples)
                         .num_concurrent_sample_managers(delay_def.num_concurrent_delays)
+                        .decay_curve(delay_def.decay_curve)
                         .build()
                         .map_err(|e| format!("Failed to build Delay: {:?}", e))?;
                     delays.push(delay);
@@ -1042,10 +1728,29 @@ impl Parser {
                     let flanger = FlangerBuilder::default()
                         .window_size(flanger_def.window_size)
                         .mix(flanger_def.mix)
+                        .feedback(flanger_def.feedback)
                         .build()
                         .map_err(|e| format!("Failed to build Flanger: {:?}", e))?;
                     flangers.push(flanger);
                 }
+                EffectDef::BitCrusher(bitcrusher_def) => {
+                    let bitcrusher = BitCrusherBuilder::default()
+                        .bit_depth(bitcrusher_def.bits)
+                        .downsample_factor(bitcrusher_def.downsample)
+                        .mix(bitcrusher_def.mix)
+                        .build()
+                        .map_err(|e| format!("Failed to build BitCrusher: {:?}", e))?;
+                    bitcrushers.push(bitcrusher);
+                }
+                EffectDef::Distortion(distortion_def) => {
+                    let distortion = DistortionBuilder::default()
+                        .drive(distortion_def.drive)
+                        .curve(distortion_def.curve)
+                        .mix(distortion_def.mix)
+                        .build()
+                        .map_err(|e| format!("Failed to build Distortion: {:?}", e))?;
+                    distortions.push(distortion);
+                }
                 EffectDef::LFO(lfo_def) => {
                     let waveforms: Vec<Waveform> = lfo_def.waveforms.iter()
                         .map(|w| w.to_waveform())
@@ -1058,6 +1763,15 @@ impl Parser {
                         .map_err(|e| format!("Failed to build LFO: {:?}", e))?;
                     lfos.push(lfo);
                 }
+                EffectDef::Tremolo(tremolo_def) => {
+                    let tremolo = TremoloBuilder::default()
+                        .rate_hz(tremolo_def.rate)
+                        .depth(tremolo_def.depth)
+                        .waveform(tremolo_def.waveform.to_waveform())
+                        .build()
+                        .map_err(|e| format!("Failed to build Tremolo: {:?}", e))?;
+                    tremolos.push(tremolo);
+                }
                 EffectDef::Filter(_filter_def) => {
                     // Filters are added to individual notes, not track effects
                     // This is handled in build_playback_note
@@ -1071,7 +1785,10 @@ impl Parser {
                 .envelopes(envelopes)
                 .delays(delays)
                 .flangers(flangers)
+                .bitcrushers(bitcrushers)
+                .distortions(distortions)
                 .lfos(lfos)
+                .tremolos(tremolos)
                 .panning(panning_value)
                 .num_channels(2)
                 .build()
@@ -1081,16 +1798,31 @@ impl Parser {
                 .envelopes(envelopes)
                 .delays(delays)
                 .flangers(flangers)
+                .bitcrushers(bitcrushers)
+                .distortions(distortions)
                 .lfos(lfos)
+                .tremolos(tremolos)
                 .build()
                 .map_err(|e| format!("Failed to build TrackEffects: {:?}", e))
         }
     }
 
+    fn build_envelope(&self, env_def: &EnvelopeDef) -> Result<Envelope, String> {
+        EnvelopeBuilder::default()
+            .attack(EnvelopePair(env_def.attack.0, env_def.attack.1))
+            .decay(EnvelopePair(env_def.decay.0, env_def.decay.1))
+            .sustain(EnvelopePair(env_def.sustain.0, env_def.sustain.1))
+            .release(EnvelopePair(env_def.release.0, env_def.release.1))
+            .curve(env_def.curve)
+            .build()
+            .map_err(|e| format!("Failed to build Envelope: {:?}", e))
+    }
+
     fn build_playback_note(&self, note_decl: &NoteDeclaration, sequence_def: &SequenceDef, effect_defs: &[EffectDef]) -> Result<PlaybackNote, String> {
-        let step_duration_ms = (60000.0 / sequence_def.tempo as f32) * sequence_def.dur.to_factor();
-        let start_time_ms = note_decl.get_step_index() as f32 * step_duration_ms;
-        let end_time_ms = start_time_ms + step_duration_ms;
+        let tempo_map = sequence_def.tempo_map()?;
+        let step_index = note_decl.get_step_index();
+        let start_time_ms = tempo_map.step_start_ms(step_index, sequence_def.dur.to_factor());
+        let end_time_ms = tempo_map.step_start_ms(step_index + 1, sequence_def.dur.to_factor());
 
         // Build filters from effect definitions
         let mut filters = Vec::new();
@@ -1106,8 +1838,16 @@ impl Parser {
             }
         }
 
+        // The note's own envelope, applied with the track's
+        let envelopes = match note_decl.get_envelope_def() {
+            Some(env_def) => vec![self.build_envelope(env_def)?],
+            None => Vec::new(),
+        };
+
         match note_decl {
-            NoteDeclaration::Oscillator { waveforms, note_freq, volume, .. } => {
+            NoteDeclaration::Oscillator {
+                waveforms, note_freq, volume, duty_cycle, unison_voices, detune_cents, ..
+            } => {
                 let waveforms: Vec<Waveform> = waveforms.iter()
                     .map(|w| w.to_waveform())
                     .collect();
@@ -1118,6 +1858,33 @@ impl Parser {
                     .start_time_ms(start_time_ms)
                     .end_time_ms(end_time_ms)
                     .waveforms(waveforms)
+                    .duty_cycle(*duty_cycle)
+                    .unison_voices(*unison_voices)
+                    .detune_cents(*detune_cents)
+                    .build()
+                    .map_err(|e| format!("Failed to build Note: {:?}", e))?;
+
+                PlaybackNoteBuilder::default()
+                    .note_type(NoteType::Oscillator)
+                    .note(note)
+                    .playback_start_time_ms(start_time_ms)
+                    .playback_end_time_ms(end_time_ms)
+                    .filters(filters.clone())
+                    .envelopes(envelopes.clone())
+                    .build()
+                    .map_err(|e| format!("Failed to build PlaybackNote: {:?}", e))
+            }
+            NoteDeclaration::Fm {
+                carrier_waveform, modulator_ratio, modulation_index, note_freq, volume, ..
+            } => {
+                let note = NoteBuilder::default()
+                    .frequency(*note_freq)
+                    .volume(*volume)
+                    .start_time_ms(start_time_ms)
+                    .end_time_ms(end_time_ms)
+                    .waveforms(vec![carrier_waveform.to_waveform()])
+                    .modulator_ratio(*modulator_ratio)
+                    .modulation_index(*modulation_index)
                     .build()
                     .map_err(|e| format!("Failed to build Note: {:?}", e))?;
 
@@ -1127,13 +1894,22 @@ impl Parser {
                     .playback_start_time_ms(start_time_ms)
                     .playback_end_time_ms(end_time_ms)
                     .filters(filters.clone())
+                    .envelopes(envelopes.clone())
                     .build()
                     .map_err(|e| format!("Failed to build PlaybackNote: {:?}", e))
             }
-            NoteDeclaration::Sample { file_path, volume, .. } => {
+            NoteDeclaration::Sample { file_path, volume, frequency, root_frequency,
+                                      loop_crossfade_ms, .. } => {
+                let loop_mode = match loop_crossfade_ms {
+                    Some(crossfade_ms) => SampleLoop::Loop { crossfade_ms: *crossfade_ms },
+                    None => SampleLoop::OneShot,
+                };
                 let sampled_note = SampledNoteBuilder::default()
                     .file_path(file_path.clone())
                     .volume(*volume)
+                    .frequency(*frequency)
+                    .root_frequency(*root_frequency)
+                    .loop_mode(loop_mode)
                     .start_time_ms(start_time_ms)
                     .end_time_ms(end_time_ms)
                     .build()
@@ -1145,6 +1921,7 @@ impl Parser {
                     .playback_start_time_ms(start_time_ms)
                     .playback_end_time_ms(end_time_ms)
                     .filters(filters.clone())
+                    .envelopes(envelopes.clone())
                     .build()
                     .map_err(|e| format!("Failed to build PlaybackNote: {:?}", e))
             }
@@ -1215,6 +1992,15 @@ impl NoteDeclaration {
         match self {
             NoteDeclaration::Oscillator { step_index, .. } => *step_index,
             NoteDeclaration::Sample { step_index, .. } => *step_index,
+            NoteDeclaration::Fm { step_index, .. } => *step_index,
+        }
+    }
+
+    fn get_envelope_def(&self) -> Option<&EnvelopeDef> {
+        match self {
+            NoteDeclaration::Oscillator { envelope_def, .. } => envelope_def.as_ref(),
+            NoteDeclaration::Sample { envelope_def, .. } => envelope_def.as_ref(),
+            NoteDeclaration::Fm { envelope_def, .. } => envelope_def.as_ref(),
         }
     }
 }
@@ -1224,6 +2010,12 @@ pub fn parse_dsl(input: &str) -> Result<TrackGrid<FixedTimeNoteSequence>, String
     parser.parse()
 }
 
+#[allow(dead_code)]
+pub(crate) fn parse_dsl_file(path: &str) -> Result<TrackGrid<FixedTimeNoteSequence>, String> {
+    let mut parser = Parser::from_file(path)?;
+    parser.parse()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1298,25 +2090,802 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_complex_effects() {
+    fn test_parse_out_of_range_octave() {
         let input = r#"
-            FixedTimeNoteSequence dur Half tempo 100 num_steps 32
-            a 0.1,0.9 d 0.4,0.6 s 0.8,0.3 r 1.0,0.0
-            delay mix 0.8 decay 0.6 interval_ms 80.0 duration_ms 40.0 num_repeats 5 num_predelay_samples 15 num_concurrent_delays 3
-            flanger window_size 12 mix 0.4
-            lfo freq 2.5 amp 0.3 waveforms sine,triangle
-            osc:sine,square:440.0:0.7:0
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:sine:12,C:0.5:0
         "#;
 
         let result = parse_dsl(input);
-        assert!(result.is_ok());
+        assert!(result.unwrap_err().contains("out of range"));
+    }
 
-        let track_grid = result.unwrap();
-        let track = &track_grid.tracks[0];
-        assert_eq!(track.effects.envelopes.len(), 1);
-        assert_eq!(track.effects.delays.len(), 1);
-        assert_eq!(track.effects.flangers.len(), 1);
-        assert_eq!(track.effects.lfos.len(), 1);
+    #[test]
+    fn test_parse_tempo_ramp() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16 tempo_ramp 140,4000
+            osc:sine:C:0.5:0
+        "#;
+
+        let mut parser = Parser::new(input);
+        let script = parser.parse_script().unwrap();
+        let sequence_def = &script.outer_blocks[0].sequence_def;
+        assert_eq!(sequence_def.tempo_ramp, Some((140.0, 4000.0)));
+        assert_eq!(sequence_def.tempo_map().unwrap().bpm_at(2000.0), 130.0);
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16 tempo_ramp 0,4000
+            osc:sine:C:0.5:0
+        "#;
+        assert!(parse_dsl(input).is_err());
+    }
+
+    #[test]
+    fn test_parse_dotted_and_triplet_durations() {
+        let input = r#"
+            FixedTimeNoteSequence dur DottedEighth tempo 120 num_steps 16
+            osc:sine:C:0.5:0
+
+            FixedTimeNoteSequence dur TripletQuarter tempo 120 num_steps 12
+            osc:sine:G:0.5:0
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_parse_complex_effects() {
+        let input = r#"
+            FixedTimeNoteSequence dur Half tempo 100 num_steps 32
+            a 0.1,0.9 d 0.4,0.6 s 0.8,0.3 r 1.0,0.0
+            delay mix 0.8 decay 0.6 interval_ms 80.0 duration_ms 40.0 num_repeats 5 num_predelay_samples 15 num_concurrent_delays 3
+            flanger window_size 12 mix 0.4
+            lfo freq 2.5 amp 0.3 waveforms sine,triangle
+            osc:sine,square:440.0:0.7:0
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let track = &track_grid.tracks[0];
+        assert_eq!(track.effects.envelopes.len(), 1);
+        assert_eq!(track.effects.delays.len(), 1);
+        assert_eq!(track.effects.flangers.len(), 1);
+        assert_eq!(track.effects.lfos.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_flanger_feedback() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            flanger window_size 12 mix 0.4 feedback 0.7
+            flanger window_size 8 mix 0.3
+            osc:sine:440.0:0.5:0
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let flangers = &track_grid.tracks[0].effects.flangers;
+        assert_eq!(flangers.len(), 2);
+        assert_eq!(flangers[0].feedback, 0.7);
+        assert_eq!(flangers[1].feedback, 0.0);
+    }
+
+    #[test]
+    fn test_parse_bitcrusher() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            bitcrush bits 8 downsample 4 mix 0.7
+            osc:sine:440.0:0.5:0
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let bitcrushers = &track_grid.tracks[0].effects.bitcrushers;
+        assert_eq!(bitcrushers.len(), 1);
+        assert_eq!(bitcrushers[0].bit_depth, 8);
+        assert_eq!(bitcrushers[0].downsample_factor, 4);
+        assert_eq!(bitcrushers[0].mix, 0.7);
+    }
+
+    #[test]
+    fn test_parse_distortion() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            distortion drive 3.0 curve softclip mix 0.8
+            distortion drive 2.0 curve foldback mix 0.5
+            osc:sine:440.0:0.5:0
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let distortions = &track_grid.tracks[0].effects.distortions;
+        assert_eq!(distortions.len(), 2);
+        assert_eq!(distortions[0].drive, 3.0);
+        assert_eq!(distortions[0].curve, DistortionCurve::SoftClip);
+        assert_eq!(distortions[0].mix, 0.8);
+        assert_eq!(distortions[1].curve, DistortionCurve::Foldback);
+    }
+
+    #[test]
+    fn test_parse_delay_decay_curve() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            delay mix 0.8 decay 0.6 interval_ms 80.0 duration_ms 40.0 num_repeats 5 num_predelay_samples 15 num_concurrent_delays 3 curve reverse
+            delay mix 0.8 decay 0.6 interval_ms 80.0 duration_ms 40.0 num_repeats 5 num_predelay_samples 15 num_concurrent_delays 3
+            osc:sine:440.0:0.5:0
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let delays = &track_grid.tracks[0].effects.delays;
+        assert_eq!(delays.len(), 2);
+        assert_eq!(delays[0].decay_curve, DecayCurve::Reverse);
+        assert_eq!(delays[1].decay_curve, DecayCurve::Exponential);
+    }
+
+    #[test]
+    fn test_parse_tremolo() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            tremolo rate 5.0 depth 0.4 waveform sine
+            osc:sine:440.0:0.5:0
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let tremolos = &track_grid.tracks[0].effects.tremolos;
+        assert_eq!(tremolos.len(), 1);
+        assert_eq!(tremolos[0].rate_hz, 5.0);
+        assert_eq!(tremolos[0].depth, 0.4);
+        assert_eq!(tremolos[0].waveform, Waveform::Sine);
+    }
+
+    #[test]
+    fn test_parse_envelope_curve() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            a 0.1,0.9 d 0.4,0.6 s 0.8,0.3 r 1.0,0.0 curve exp
+            a 0.1,0.9 d 0.4,0.6 s 0.8,0.3 r 1.0,0.0
+            osc:sine:440.0:0.5:0
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let envelopes = &track_grid.tracks[0].effects.envelopes;
+        assert_eq!(envelopes.len(), 2);
+        assert_eq!(envelopes[0].curve, EnvelopeCurve::Exponential);
+        assert_eq!(envelopes[1].curve, EnvelopeCurve::Linear);
+    }
+
+    #[test]
+    fn test_parse_envelope_preset() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            env preset pluck
+            a 0.1,0.9 d 0.4,0.6 s 0.8,0.3 r 1.0,0.0
+            env preset swell
+            osc:sine:440.0:0.5:0
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let envelopes = &track_grid.tracks[0].effects.envelopes;
+        assert_eq!(envelopes.len(), 3);
+        assert_eq!(envelopes[0], Envelope::preset(EnvelopePreset::Pluck));
+        assert_eq!(envelopes[2], Envelope::preset(EnvelopePreset::Swell));
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            env preset bell
+            osc:sine:440.0:0.5:0
+        "#;
+        assert!(parse_dsl(input).is_err());
+    }
+
+    #[test]
+    fn test_parse_pulse_duty_cycle() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:pulse:440.0:0.5:0 duty 0.25
+            osc:pulse:440.0:0.5:4
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let all_notes = track_grid.tracks[0].sequence.get_all_notes();
+        assert_eq!(all_notes.len(), 2);
+        assert_eq!(all_notes[0].note.waveforms, vec![Waveform::Pulse]);
+        assert_eq!(all_notes[0].note.duty_cycle, 0.25);
+        assert_eq!(all_notes[1].note.duty_cycle, DEFAULT_DUTY_CYCLE);
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:pulse:440.0:0.5:0 duty 1.5
+        "#;
+        assert!(parse_dsl(input).is_err());
+    }
+
+    #[test]
+    fn test_parse_unison() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:saw:440.0:0.5:0 unison 3 detune 10.0
+            osc:pulse:440.0:0.5:4 duty 0.3 unison 5 detune 7.5
+            osc:saw:440.0:0.5:8
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let all_notes = track_grid.tracks[0].sequence.get_all_notes();
+        assert_eq!(all_notes[0].note.unison_voices, 3);
+        assert_eq!(all_notes[0].note.detune_cents, 10.0);
+        assert_eq!(all_notes[1].note.duty_cycle, 0.3);
+        assert_eq!(all_notes[1].note.unison_voices, 5);
+        assert_eq!(all_notes[2].note.unison_voices, 1);
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:saw:440.0:0.5:0 unison 0 detune 10.0
+        "#;
+        assert!(parse_dsl(input).is_err());
+    }
+
+    #[test]
+    fn test_parse_sample_pitch() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            samp:/samples/piano.wav:0.5:0 pitch 5,C root 4,C env preset perc
+            samp:/samples/piano.wav:0.5:4
+        "#;
+        let mut parser = Parser::new(input);
+        let script = parser.parse_script().unwrap();
+        let pitches: Vec<(f32, f32)> = script.outer_blocks[0].note_declarations.iter()
+            .map(|note_declaration| match note_declaration {
+                NoteDeclaration::Sample { frequency, root_frequency, .. } =>
+                    (*frequency, *root_frequency),
+                _ => panic!("expected a sample"),
+            })
+            .collect();
+        assert!((pitches[0].0 / pitches[0].1 - 2.0).abs() < 1e-3);
+        assert_eq!(pitches[1], (440.0, 440.0));
+        assert!(script.outer_blocks[0].note_declarations[0].get_envelope_def().is_some());
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            samp:/samples/pad.wav:0.5:0 pitch 4,E root 4,C loop 20.0 env preset perc
+            samp:/samples/pad.wav:0.5:4 loop 5
+            samp:/samples/pad.wav:0.5:8
+        "#;
+        let script = Parser::new(input).parse_script().unwrap();
+        let crossfades: Vec<Option<f32>> = script.outer_blocks[0].note_declarations.iter()
+            .map(|note_declaration| match note_declaration {
+                NoteDeclaration::Sample { loop_crossfade_ms, .. } => *loop_crossfade_ms,
+                _ => panic!("expected a sample"),
+            })
+            .collect();
+        assert_eq!(crossfades, vec![Some(20.0), Some(5.0), None]);
+        assert!(script.outer_blocks[0].note_declarations[0].get_envelope_def().is_some());
+    }
+
+    #[test]
+    fn test_parse_sample_raw16_volume() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            samp:/samples/piano.wav:0.003:0 raw16
+            samp:/samples/piano.wav:0.003:4 loop 5.0 raw16 env preset perc
+            samp:/samples/piano.wav:0.5:8
+        "#;
+        let script = Parser::new(input).parse_script().unwrap();
+        let volumes: Vec<f32> = script.outer_blocks[0].note_declarations.iter()
+            .map(|note_declaration| match note_declaration {
+                NoteDeclaration::Sample { volume, .. } => *volume,
+                _ => panic!("expected a sample"),
+            })
+            .collect();
+        let raw16_volume = 0.003 * RAW_INT16_VOLUME_SCALE;
+        assert_eq!(volumes, vec![raw16_volume, raw16_volume, 0.5]);
+        assert!(script.outer_blocks[0].note_declarations[1].get_envelope_def().is_some());
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            samp:/samples/piano.wav:0.5:0 pitch 5,C
+        "#;
+        assert!(Parser::new(input).parse_script().is_err());
+    }
+
+    #[test]
+    fn test_parse_noise_colors() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:pink:440.0:0.5:0
+            osc:brownnoise:440.0:0.5:4
+        "#;
+
+        let track_grid = parse_dsl(input).unwrap();
+        let all_notes = track_grid.tracks[0].sequence.get_all_notes();
+        assert_eq!(all_notes[0].note.waveforms, vec![Waveform::PinkNoise]);
+        assert_eq!(all_notes[1].note.waveforms, vec![Waveform::BrownNoise]);
+    }
+
+    #[test]
+    fn test_parse_fm_note() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            fm:sine:2.0:3.5:440.0:0.5:0
+            osc:sine:440.0:0.5:4
+        "#;
+
+        let result = parse_dsl(input);
+        assert!(result.is_ok());
+
+        let track_grid = result.unwrap();
+        let all_notes = track_grid.tracks[0].sequence.get_all_notes();
+        assert_eq!(all_notes.len(), 2);
+        assert_eq!(all_notes[0].note.waveforms, vec![Waveform::Sine]);
+        assert_eq!(all_notes[0].note.frequency, 440.0);
+        assert_eq!(all_notes[0].note.modulator_ratio, 2.0);
+        assert_eq!(all_notes[0].note.modulation_index, 3.5);
+        assert_eq!(all_notes[1].note.modulation_index, 0.0);
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            fm:sine:0.0:3.5:440.0:0.5:0
+        "#;
+        assert!(parse_dsl(input).is_err());
+    }
+
+    #[test]
+    fn test_expand_two_generators_on_a_line() {
+        assert_eq!(Parser::expand_generators("    apply (range 0,4,1) osc (range 5,8,1)"),
+                   Ok("    apply 0,1,2,3,4 osc 5,6,7,8".to_string()));
+    }
+
+    #[test]
+    fn test_expand_three_generators_on_a_line() {
+        assert_eq!(
+            Parser::expand_generators("(range 0,2,1) (euclid 3,8,0) (range 8,12,2)\n(range 1,3,1)"),
+            Ok("0,1,2 0,3,6 8,10,12\n1,2,3".to_string()));
+    }
+
+    #[test]
+    fn test_expand_generators_with_unbalanced_and_nested_parentheses() {
+        // a nested call is part of the outer one, which fails, and the rest of the line expands
+        assert_eq!(Parser::expand_generators("(range 0,(range 1,2,1),1) (range 0,1,1)"),
+                   Ok("parse of generator failed 0,1".to_string()));
+        assert_eq!(Parser::expand_generators("(range 0,1,1) ) (range 0,4,1"),
+                   Ok("0,1 ) (range 0,4,1".to_string()));
+        assert!(Parser::call_generator_with_args("(range)").is_err());
+    }
+
+    #[test]
+    fn test_euclid_generator_with_apply() {
+        let input = r#"
+            FixedTimeNoteSequence dur Sixteenth tempo 120 num_steps 8
+            apply step:(euclid 4,8,0) osc:sine:440.0:0.5:{step}
+        "#;
+
+        let track_grid = parse_dsl(input).unwrap();
+        let all_notes = track_grid.tracks[0].sequence.get_all_notes();
+        assert_eq!(all_notes.len(), 4);
+    }
+
+    #[test]
+    fn test_parse_trailing_comments() {
+        let input = r#"
+            # full line comment
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16  # the lead
+            a 0.05,0.9 d 0.2,0.7 s 0.9,0.5 r 1.0,0.0 # pluck
+            osc:sine:440.0:0.5:0  # first note
+            osc:sine:F#:0.5:4 #F# is a sharp, this is a comment
+
+            apply step:8,12 osc:sine:G:0.5:{step} # note: fills
+        "#;
+
+        let track_grid = parse_dsl(input).unwrap();
+        assert_eq!(track_grid.tracks.len(), 1);
+        assert_eq!(track_grid.tracks[0].sequence.get_all_notes().len(), 4);
+    }
+
+    #[test]
+    fn test_tokenize_trailing_comment_after_file_path() {
+        // a '#' in a file path is part of it, one after it starts a comment
+        let tokens: Vec<String> = Parser::tokenize("samp:/samples/kick#1.wav:0.5:0 # drums\nosc:sine:F#")
+            .unwrap().into_iter().map(|(token, _)| token).collect();
+        assert_eq!(tokens, vec!["samp", ":", "/samples/kick#1.wav", ":", "0.5", ":", "0",
+                                "osc", ":", "sine", ":", "F#"]);
+    }
+
+    fn temp_script_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("rosco_test_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_include_macros() {
+        let dir = temp_script_dir("include_macros");
+        std::fs::write(dir.join("lib").join("envelopes.dsl"),
+                       "let env1 = a 0.1,0.9 d 0.2,0.7 s 0.8,0.5 r 1.0,0.0\n\
+                        include \"notes.dsl\"\n").unwrap();
+        // relative to lib/envelopes.dsl, which includes it
+        std::fs::write(dir.join("lib").join("notes.dsl"),
+                       "let note1 = osc:sine:440.0:0.5:{step}\n").unwrap();
+        std::fs::write(dir.join("main.dsl"),
+                       "include \"lib/envelopes.dsl\"\n\
+                        FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16\n\
+                        $env1\n\
+                        apply step:0,4,8 $note1\n\
+                        osc:sine:440.0:loud:12\n").unwrap();
+
+        let result = parse_dsl_file(dir.join("main.dsl").to_str().unwrap());
+        // the included macros expanded, and the error is on line 5 of main.dsl
+        assert!(result.unwrap_err().starts_with("line 5, "));
+
+        std::fs::write(dir.join("main.dsl"),
+                       "include \"lib/envelopes.dsl\"\n\
+                        FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16\n\
+                        $env1\n\
+                        apply step:0,4,8 $note1\n").unwrap();
+        let track_grid = parse_dsl_file(dir.join("main.dsl").to_str().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(track_grid.tracks[0].sequence.get_all_notes().len(), 3);
+        assert_eq!(track_grid.tracks[0].effects.envelopes.len(), 1);
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = temp_script_dir("include_cycle");
+        std::fs::write(dir.join("a.dsl"), "include \"lib/b.dsl\"\n").unwrap();
+        std::fs::write(dir.join("lib").join("b.dsl"), "include \"../a.dsl\"\n").unwrap();
+
+        let result = parse_dsl_file(dir.join("a.dsl").to_str().unwrap());
+        let missing = parse_dsl("include \"rosco_no_such_file.dsl\"\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.unwrap_err().contains("include cycle"));
+        assert!(missing.unwrap_err().contains("rosco_no_such_file.dsl"));
+    }
+
+    fn step_indexes(input: &str) -> Vec<usize> {
+        let mut parser = Parser::new(input);
+        let script = parser.parse_script().unwrap();
+        script.outer_blocks[0].note_declarations.iter()
+            .map(|note_declaration| note_declaration.get_step_index())
+            .collect()
+    }
+
+    #[test]
+    fn test_repeat_block() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            repeat 4 { osc:sine:440.0:0.5:{i} }
+        "#;
+        assert_eq!(step_indexes(input), vec![0, 1, 2, 3]);
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            repeat 2 {
+                osc:sine:C:0.5:{i}
+                osc:sine:G:0.5:1{i}
+            }
+            osc:sine:E:0.5:15
+        "#;
+        assert_eq!(step_indexes(input), vec![0, 10, 1, 11, 15]);
+    }
+
+    #[test]
+    fn test_nested_repeat_and_apply() {
+        // {i} is the innermost repeat's index, and an apply in a block is applied in each copy
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            repeat 2 {
+                repeat 2 { osc:sine:C:0.5:{i} }
+                apply step:4,5 osc:sine:G:0.5:{step}
+            }
+        "#;
+        assert_eq!(step_indexes(input), vec![0, 1, 4, 5, 0, 1, 4, 5]);
+    }
+
+    #[test]
+    fn test_repeat_errors() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            repeat 2 {
+                osc:sine:C:0.5:{i}
+        "#;
+        assert_eq!(parse_dsl(input).unwrap_err(), "line 3: repeat block is never closed");
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            repeat x { osc:sine:C:0.5:{i} }
+        "#;
+        assert!(parse_dsl(input).unwrap_err().contains("repeat count"));
+
+        // lines after a repeat keep their line numbers
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            repeat 3 {
+                osc:sine:C:0.5:{i}
+            }
+            osc:sine:C:loud:4
+        "#;
+        assert!(parse_dsl(input).unwrap_err().starts_with("line 6, "));
+    }
+
+    fn note_frequencies(input: &str) -> Vec<f32> {
+        let mut parser = Parser::new(input);
+        let script = parser.parse_script().unwrap();
+        script.outer_blocks.iter()
+            .flat_map(|block| block.note_declarations.iter())
+            .map(|note_declaration| match note_declaration {
+                NoteDeclaration::Oscillator { note_freq, .. } => *note_freq,
+                _ => panic!("expected an oscillator note"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_scale_degrees() {
+        let input = r#"
+            scale C Major
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:sine:deg1:0.5:0
+            osc:sine:deg3:0.5:1
+            osc:sine:deg5:0.5:2
+            osc:sine:deg8:0.5:3
+            osc:sine:deg10:0.5:4
+        "#;
+        assert_eq!(note_frequencies(input), vec![
+            WesternPitch::C.get_frequency(4),
+            WesternPitch::E.get_frequency(4),
+            WesternPitch::G.get_frequency(4),
+            // past the 7th degree the scale goes on up an octave
+            WesternPitch::C.get_frequency(5),
+            WesternPitch::E.get_frequency(5),
+        ]);
+    }
+
+    #[test]
+    fn test_scale_degrees_follow_the_last_scale() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            scale C Major
+            osc:sine:deg2:0.5:0
+            scale 5,A HarmonicMinor
+            osc:sine:deg3:0.5:1
+            osc:sine:deg7:0.5:2
+
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:sine:deg1:0.5:0
+        "#;
+        assert_eq!(note_frequencies(input), vec![
+            WesternPitch::D.get_frequency(4),
+            WesternPitch::C.get_frequency(6),
+            WesternPitch::GSharp.get_frequency(6),
+            WesternPitch::A.get_frequency(5),
+        ]);
+    }
+
+    #[test]
+    fn test_scale_degree_errors() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:sine:deg1:0.5:0
+        "#;
+        assert!(parse_dsl(input).unwrap_err().contains("needs a scale"));
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            scale C Major
+            osc:sine:deg0:0.5:0
+        "#;
+        assert!(parse_dsl(input).unwrap_err().contains("degrees start at deg1"));
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            scale C Majestic
+        "#;
+        assert!(parse_dsl(input).unwrap_err().contains("Unknown scale: Majestic"));
+    }
+
+    #[test]
+    fn test_zero_tempo_and_num_steps_are_errors() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 0 num_steps 16
+            osc:sine:440.0:0.5:0
+        "#;
+        let err = parse_dsl(input).unwrap_err();
+        assert!(err.contains("tempo must be > 0"), "{}", err);
+        assert!(err.starts_with("line 2,"), "{}", err);
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 0
+            osc:sine:440.0:0.5:0
+        "#;
+        let err = parse_dsl(input).unwrap_err();
+        assert!(err.contains("num_steps must be > 0"), "{}", err);
+    }
+
+    #[test]
+    fn test_step_index_out_of_range() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:sine:440.0:0.5:15
+            osc:sine:440.0:0.5:99
+        "#;
+        let err = parse_dsl(input).unwrap_err();
+        assert!(err.contains("step_index 99 is out of range for a sequence of 16 steps"), "{}", err);
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            samp:/samples/kick.wav:0.5:16
+        "#;
+        assert!(parse_dsl(input).unwrap_err().contains("step_index 16 is out of range"));
+    }
+
+    #[test]
+    fn test_note_envelope() {
+        let input = r#"
+            let pluck = a 0.01,1.0 d 0.2,0.3 s 0.6,0.15 r 1.0,0.0 curve exp
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            a 0.1,0.9 d 0.2,0.7 s 0.8,0.5 r 1.0,0.0
+            osc:sine:440.0:0.5:0 env $pluck
+            osc:sine:440.0:0.5:1
+            samp:/samples/kick.wav:0.5:2 env preset perc
+            fm:sine:2.0:1.5:220.0:0.3:3 env a 0.2,1.0 d 0.4,0.8 s 0.9,0.8 r 1.0,0.0
+        "#;
+        let mut parser = Parser::new(input);
+        let script = parser.parse_script().unwrap();
+        let block = &script.outer_blocks[0];
+        assert_eq!(block.envelope_defs.len(), 1);
+        let note_envelopes: Vec<Option<(f32, f32)>> = block.note_declarations.iter()
+            .map(|note_declaration| note_declaration.get_envelope_def().map(|env| env.attack))
+            .collect();
+        assert_eq!(note_envelopes, vec![
+            Some((0.01, 1.0)),
+            None,
+            Some((Envelope::preset(EnvelopePreset::Percussive).attack.0,
+                  Envelope::preset(EnvelopePreset::Percussive).attack.1)),
+            Some((0.2, 1.0)),
+        ]);
+
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            osc:sine:440.0:0.5:0 env preset pluck
+            osc:sine:440.0:0.5:1
+        "#;
+        let track_grid = parse_dsl(input).unwrap();
+        let track = &track_grid.tracks[0];
+        assert!(track.effects.envelopes.is_empty());
+        let notes = track.sequence.get_all_notes();
+        assert_eq!(notes[0].envelopes, vec![Envelope::preset(EnvelopePreset::Pluck)]);
+        assert!(notes[1].envelopes.is_empty());
+    }
+
+    #[test]
+    fn test_parse_error_has_line_and_column() {
+        let input = "FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16\n\
+                     osc:sine:440.0:0.5:0\n\
+                     \n\
+                     FixedTimeNoteSequence dur Quarter tmpo 120 num_steps 16\n";
+        assert_eq!(parse_dsl(input).unwrap_err(),
+                   "line 4, col 35: expected 'tempo', got 'tmpo'");
+    }
+
+    #[test]
+    fn test_parse_error_line_after_apply() {
+        // the lines apply adds don't move the lines after it
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            apply step:0,4,8,12 osc:sine:440.0:0.5:{step}
+            osc:sine:440.0:loud:2
+        "#;
+        let error = parse_dsl(input).unwrap_err();
+        assert!(error.starts_with("line 4, col 28: "), "{}", error);
+    }
+
+    #[test]
+    fn test_parse_quoted_file_path() {
+        let input = r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16
+            samp:"C:/My Samples/kick #01.wav":0.5:2
+            samp:/samples/snare.wav:0.4:4
+        "#;
+
+        let mut parser = Parser::new(input);
+        let script = parser.parse_script().unwrap();
+        let file_paths: Vec<&str> = script.outer_blocks[0].note_declarations.iter()
+            .map(|note_declaration| match note_declaration {
+                NoteDeclaration::Sample { file_path, .. } => file_path.as_str(),
+                _ => panic!("expected a sample note"),
+            })
+            .collect();
+        assert_eq!(file_paths, vec!["C:/My Samples/kick #01.wav", "/samples/snare.wav"]);
+
+        let tokens: Vec<String> = Parser::tokenize(r#"samp:"/a b/c:d.wav":0.5:2"#)
+            .unwrap().into_iter().map(|(token, _)| token).collect();
+        assert_eq!(tokens, vec!["samp", ":", "/a b/c:d.wav", ":", "0.5", ":", "2"]);
+
+        // A quote left open is an error where it starts, not a path running to the end of the line
+        let input = "FixedTimeNoteSequence dur Quarter tempo 120 num_steps 16\n\
+                     samp:\"/a b.wav:0.5:2\n\
+                     samp:/samples/snare.wav:0.4:4\n";
+        assert_eq!(Parser::new(input).parse_script().unwrap_err(),
+                   "line 2, col 6: unterminated quoted file path");
+        assert!(Parser::tokenize(r#"samp:"/a b.wav"#).is_err());
+    }
+
+    #[test]
+    fn test_random_generator() {
+        let expanded = Parser::call_generator_with_args("(random 8,0,15,7)").unwrap();
+        let values: Vec<i32> = expanded.split(",").map(|v| v.parse().unwrap()).collect();
+        assert_eq!(values.len(), 8);
+        assert!(values.iter().all(|v| (0..=15).contains(v)));
+
+        // the same seed expands the same, a different seed differently
+        assert_eq!(Parser::call_generator_with_args("(random 8,0,15,7)"), Ok(expanded.clone()));
+        assert_ne!(Parser::call_generator_with_args("(random 8,0,15,8)"), Ok(expanded));
+        // without a seed the default seed is used
+        assert_eq!(Parser::call_generator_with_args("(random 4,-3,3)"),
+                   Parser::call_generator_with_args(
+                       &format!("(random 4,-3,3,{})", DEFAULT_RANDOM_GENERATOR_SEED)));
+
+        assert_eq!(Parser::call_generator_with_args("(random 3,5,5)"), Ok("5,5,5".to_string()));
+        assert!(Parser::call_generator_with_args("(random 3,5,4)").is_err());
+        assert!(Parser::call_generator_with_args("(random 3,5)").is_err());
+    }
+
+    #[test]
+    fn test_euclid_generator() {
+        assert_eq!(Parser::call_generator_with_args("(euclid 5,8,0)"),
+                   Ok("0,2,3,5,6".to_string()));
+        assert_eq!(Parser::call_generator_with_args("(euclid 3,8,1)"),
+                   Ok("1,4,7".to_string()));
+        assert_eq!(Parser::call_generator_with_args("(euclid 4,8,0)"),
+                   Ok("0,2,4,6".to_string()));
+        assert!(Parser::call_generator_with_args("(euclid 3,8)").is_err());
+        assert!(Parser::call_generator_with_args("(euclid x,8,0)").is_err());
+    }
+
+    #[test]
+    fn test_drum_pattern_generators() {
+        assert_eq!(Parser::call_generator_with_args("(kick 16)"), Ok("0,4,8,12".to_string()));
+        assert_eq!(Parser::call_generator_with_args("(snare 32)"), Ok("4,12,20,28".to_string()));
+        assert_eq!(Parser::call_generator_with_args("(hihat 16)"),
+                   Ok("1,3,5,7,9,11,13,15".to_string()));
+        assert!(Parser::call_generator_with_args("(kick 16,2)").is_err());
+        assert!(Parser::call_generator_with_args("(kick x)").is_err());
+
+        let input = r#"
+            FixedTimeNoteSequence dur Sixteenth tempo 120 num_steps 16
+            apply step:(kick 16) osc:sine:60.0:0.5:{step}
+            apply step:(snare 16) osc:noise:200.0:0.5:{step}
+        "#;
+        let track_grid = parse_dsl(input).unwrap();
+        assert_eq!(track_grid.tracks[0].sequence.get_all_notes().len(), 6);
     }
 
     #[test]
@@ -0,0 +1,233 @@
+use std::fmt::Write;
+
+use crate::audio_gen::oscillator::Waveform;
+use crate::effect::delay::DecayCurve;
+use crate::effect::distortion::DistortionCurve;
+use crate::envelope::envelope::{Envelope, EnvelopeCurve};
+use crate::note::playback_note::{NoteType, PlaybackNote};
+use crate::note::sampled_note::SampleLoop;
+use crate::sequence::fixed_time_note_sequence::FixedTimeNoteSequence;
+use crate::track::track::Track;
+use crate::track::track_grid::TrackGrid;
+
+static MSECS_PER_MIN: f32 = 60000.0;
+
+/// Write a grid out as a DSL script, one outer block per track, that parses back to an equivalent
+/// grid. Step indexes are recovered from note start times at the sequence's tempo, so a sequence
+/// built with a tempo ramp is written at its starting tempo.
+pub(crate) fn track_grid_to_dsl(grid: &TrackGrid<FixedTimeNoteSequence>) -> String {
+    grid.tracks.iter()
+        .map(track_to_dsl)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn track_to_dsl(track: &Track<FixedTimeNoteSequence>) -> String {
+    let mut dsl = String::new();
+    let sequence = &track.sequence;
+    let effects = &track.effects;
+
+    // Only a stereo track was given a panning in its script
+    let _ = write!(dsl, "FixedTimeNoteSequence dur {:?} tempo {} num_steps {}",
+                   sequence.duration_type, sequence.tempo, sequence.num_steps);
+    if effects.num_channels == 2 {
+        let _ = write!(dsl, " panning {:?}", effects.panning);
+    }
+    dsl.push('\n');
+
+    for envelope in &effects.envelopes {
+        let _ = writeln!(dsl, "{}", envelope_to_dsl(envelope));
+    }
+    for delay in &effects.delays {
+        let _ = writeln!(dsl, "delay mix {:?} decay {:?} interval_ms {:?} duration_ms {:?} \
+                               num_repeats {} num_predelay_samples {} num_concurrent_delays {} \
+                               curve {}",
+                         delay.mix, delay.decay, delay.interval_ms, delay.duration_ms,
+                         delay.num_repeats, delay.num_predelay_samples,
+                         delay.num_concurrent_sample_managers, decay_curve_name(delay.decay_curve));
+    }
+    for flanger in &effects.flangers {
+        let _ = writeln!(dsl, "flanger window_size {} mix {:?} feedback {:?}",
+                         flanger.window_size, flanger.mix, flanger.feedback);
+    }
+    for bitcrusher in &effects.bitcrushers {
+        let _ = writeln!(dsl, "bitcrush bits {} downsample {} mix {:?}",
+                         bitcrusher.bit_depth, bitcrusher.downsample_factor, bitcrusher.mix);
+    }
+    for distortion in &effects.distortions {
+        let _ = writeln!(dsl, "distortion drive {:?} curve {} mix {:?}",
+                         distortion.drive, distortion_curve_name(distortion.curve), distortion.mix);
+    }
+    for lfo in &effects.lfos {
+        let _ = writeln!(dsl, "lfo freq {:?} amp {:?} waveforms {}",
+                         lfo.frequency, lfo.amplitude, waveform_names(&lfo.waveforms));
+    }
+    for tremolo in &effects.tremolos {
+        let _ = writeln!(dsl, "tremolo rate {:?} depth {:?} waveform {}",
+                         tremolo.rate_hz, tremolo.depth, waveform_name(&tremolo.waveform));
+    }
+
+    // Every note of a block gets the block's filters, so the first note's are the block's
+    let notes = sequence.get_all_notes();
+    if let Some(first_note) = notes.first() {
+        for filter in &first_note.filters {
+            let _ = writeln!(dsl, "filter cutoff_frequency {:?} resonance {:?} mix {:?}",
+                             filter.cutoff_frequency, filter.resonance, filter.mix);
+        }
+    }
+    // The step length the DSL builder gives notes, which is the duration's fraction of a beat
+    let step_duration_ms = MSECS_PER_MIN / sequence.tempo as f32 *
+        sequence.duration_type.to_factor();
+    for playback_note in &notes {
+        let step_index = (playback_note.playback_start_time_ms / step_duration_ms).round() as usize;
+        let _ = write!(dsl, "{}", note_to_dsl(playback_note, step_index));
+        // The DSL gives a note at most one envelope of its own
+        if let Some(envelope) = playback_note.envelopes.first() {
+            let _ = write!(dsl, " env {}", envelope_to_dsl(envelope));
+        }
+        dsl.push('\n');
+    }
+
+    dsl
+}
+
+fn note_to_dsl(playback_note: &PlaybackNote, step_index: usize) -> String {
+    if playback_note.note_type == NoteType::Sample {
+        let sampled_note = &playback_note.sampled_note;
+        let loop_crossfade = match sampled_note.loop_mode {
+            SampleLoop::OneShot => String::new(),
+            SampleLoop::Loop { crossfade_ms } => format!(" loop {:?}", crossfade_ms),
+        };
+        // Quoted, so the path can have spaces and colons
+        return format!("samp:\"{}\":{:?}:{} pitch {:?} root {:?}{}", sampled_note.file_path,
+                       sampled_note.volume, step_index, sampled_note.frequency,
+                       sampled_note.root_frequency, loop_crossfade);
+    }
+
+    let note = &playback_note.note;
+    if note.modulation_index > 0.0 {
+        let carrier_waveform = note.waveforms.first().unwrap_or(&Waveform::Sine);
+        return format!("fm:{}:{:?}:{:?}:{:?}:{:?}:{}", waveform_name(carrier_waveform),
+                       note.modulator_ratio, note.modulation_index, note.frequency, note.volume,
+                       step_index);
+    }
+    format!("osc:{}:{:?}:{:?}:{} duty {:?} unison {} detune {:?}",
+            waveform_names(&note.waveforms), note.frequency, note.volume, step_index,
+            note.duty_cycle, note.unison_voices, note.detune_cents)
+}
+
+fn envelope_to_dsl(envelope: &Envelope) -> String {
+    format!("a {:?},{:?} d {:?},{:?} s {:?},{:?} r {:?},{:?} curve {}",
+            envelope.attack.0, envelope.attack.1, envelope.decay.0, envelope.decay.1,
+            envelope.sustain.0, envelope.sustain.1, envelope.release.0, envelope.release.1,
+            envelope_curve_name(envelope.curve))
+}
+
+fn waveform_names(waveforms: &[Waveform]) -> String {
+    waveforms.iter()
+        .map(waveform_name)
+        .collect::<Vec<&str>>()
+        .join(",")
+}
+
+fn waveform_name(waveform: &Waveform) -> &'static str {
+    match waveform {
+        Waveform::Sine => "sine",
+        Waveform::Square => "square",
+        Waveform::Triangle => "triangle",
+        Waveform::Saw => "sawtooth",
+        Waveform::GaussianNoise | Waveform::Noise => "noise",
+        Waveform::Pulse => "pulse",
+        Waveform::PinkNoise => "pinknoise",
+        Waveform::BrownNoise => "brownnoise",
+    }
+}
+
+fn envelope_curve_name(curve: EnvelopeCurve) -> &'static str {
+    match curve {
+        EnvelopeCurve::Linear => "linear",
+        EnvelopeCurve::Exponential => "exponential",
+        EnvelopeCurve::Logarithmic => "logarithmic",
+    }
+}
+
+fn decay_curve_name(curve: DecayCurve) -> &'static str {
+    match curve {
+        DecayCurve::Exponential => "exponential",
+        DecayCurve::Linear => "linear",
+        DecayCurve::Reverse => "reverse",
+    }
+}
+
+fn distortion_curve_name(curve: DistortionCurve) -> &'static str {
+    match curve {
+        DistortionCurve::SoftClip => "softclip",
+        DistortionCurve::HardClip => "hardclip",
+        DistortionCurve::Foldback => "foldback",
+    }
+}
+
+#[cfg(test)]
+mod test_serializer {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::dsl::parser::parse_dsl;
+    use crate::dsl::serializer::track_grid_to_dsl;
+    use crate::note::playback_note::NoteType;
+
+    #[test]
+    fn test_round_trip() {
+        let sample_path = concat!(env!("CARGO_MANIFEST_DIR"), "/src/dsl/test_data/test_sample.wav");
+        let input = format!(r#"
+            FixedTimeNoteSequence dur Eighth tempo 100 num_steps 16 panning -0.5
+            a 0.1,0.9 d 0.2,0.7 s 0.8,0.7 r 1.0,0.0 curve exp
+            delay mix 0.5 decay 0.5 interval_ms 100.0 duration_ms 50.0 num_repeats 2 num_predelay_samples 10 num_concurrent_delays 1 curve lin
+            flanger window_size 8 mix 0.3 feedback 0.2
+            lfo freq 2.0 amp 0.5 waveforms sine,triangle
+            filter cutoff_frequency 1000.0 resonance 0.3 mix 0.8
+            osc:sine,saw:440.0:0.5:0
+            osc:pulse:C:0.4:3 duty 0.25 unison 3 detune 12.0 env preset pluck
+            fm:sine:2.0:1.5:220.0:0.3:5
+
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 8
+            bitcrush bits 8 downsample 2 mix 0.5
+            distortion drive 4.0 curve fold mix 0.6
+            tremolo rate 5.0 depth 0.5 waveform tri
+            samp:"{}":0.25:2
+            samp:"{}":0.25:4 pitch 4,E root 4,C loop 10.0
+        "#, sample_path, sample_path);
+
+        let grid = parse_dsl(&input).unwrap();
+        let dsl = track_grid_to_dsl(&grid);
+        let round_trip_grid = parse_dsl(&dsl).unwrap();
+
+        assert_eq!(round_trip_grid.tracks.len(), grid.tracks.len());
+        for (track, round_trip_track) in grid.tracks.iter().zip(round_trip_grid.tracks.iter()) {
+            assert_eq!(round_trip_track.effects, track.effects);
+            assert_eq!(round_trip_track.sequence.num_steps, track.sequence.num_steps);
+            assert_eq!(round_trip_track.sequence.tempo, track.sequence.tempo);
+
+            let notes = track.sequence.get_all_notes();
+            let round_trip_notes = round_trip_track.sequence.get_all_notes();
+            assert_eq!(round_trip_notes.len(), notes.len());
+            for (note, round_trip_note) in notes.iter().zip(round_trip_notes.iter()) {
+                assert_eq!(round_trip_note.note_type, note.note_type);
+                assert_eq!(round_trip_note.filters.len(), note.filters.len());
+                assert_eq!(round_trip_note.envelopes, note.envelopes);
+                assert_float_eq(round_trip_note.playback_start_time_ms,
+                                note.playback_start_time_ms);
+                if note.note_type == NoteType::Sample {
+                    assert_eq!(round_trip_note.sampled_note.file_path, sample_path);
+                    assert_float_eq(round_trip_note.sampled_note.playback_rate(),
+                                    note.sampled_note.playback_rate());
+                    assert_eq!(round_trip_note.sampled_note.loop_mode, note.sampled_note.loop_mode);
+                } else {
+                    assert_eq!(round_trip_note.note.waveforms, note.note.waveforms);
+                    assert_float_eq(round_trip_note.note.frequency, note.note.frequency);
+                    assert_float_eq(round_trip_note.note.modulation_index,
+                                    note.note.modulation_index);
+                    assert_eq!(round_trip_note.note.unison_voices, note.note.unison_voices);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use derive_builder::Builder;
+
+static DEFAULT_BIT_DEPTH: u8 = 8;
+static DEFAULT_DOWNSAMPLE_FACTOR: usize = 1;
+static DEFAULT_MIX: f32 = 1.0;
+static MAX_BIT_DEPTH: u8 = 24;
+
+#[derive(Builder, Clone, Debug, PartialEq)]
+#[builder(build_fn(validate = "Self::validate"))]
+pub(crate) struct BitCrusher {
+    // Number of bits of amplitude resolution, the output has 2^bit_depth quantization levels
+    #[builder(default = "DEFAULT_BIT_DEPTH")]
+    pub(crate) bit_depth: u8,
+
+    // Number of calls each sample is held for, 1 means no downsampling
+    #[builder(default = "DEFAULT_DOWNSAMPLE_FACTOR")]
+    pub(crate) downsample_factor: usize,
+
+    // The mix level of the effect
+    #[builder(default = "DEFAULT_MIX")]
+    pub(crate) mix: f32,
+
+    // Complement of mix, computed at build time
+    #[builder(field(private), default = "1.0 - self.mix.unwrap_or(DEFAULT_MIX)")]
+    mix_complement: f32,
+
+    // The crushed sample currently being held
+    #[builder(setter(skip), default = "0.0")]
+    held_sample: f32,
+
+    // Number of calls remaining before a new sample is taken
+    #[builder(setter(skip), default = "0")]
+    hold_count: usize,
+}
+
+impl BitCrusherBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let bit_depth = self.bit_depth.unwrap_or(DEFAULT_BIT_DEPTH);
+        if bit_depth == 0 || bit_depth > MAX_BIT_DEPTH {
+            return Err(format!("BitCrusher: bit_depth must be between 1 and {}", MAX_BIT_DEPTH));
+        }
+        if self.downsample_factor.unwrap_or(DEFAULT_DOWNSAMPLE_FACTOR) == 0 {
+            return Err(String::from("BitCrusher: downsample_factor must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl BitCrusher {
+    pub(crate) fn apply_effect(&mut self, sample: f32, _sample_clock: f32) -> f32 {
+        // Sample and hold, only take a new sample every downsample_factor calls
+        if self.hold_count == 0 {
+            self.held_sample = self.quantize(sample);
+            self.hold_count = self.downsample_factor;
+        }
+        self.hold_count -= 1;
+
+        sample * self.mix_complement + self.held_sample * self.mix
+    }
+
+    // Mid-rise quantizer, -1.0..1.0 is cut into 2^bit_depth steps of 1 / 2^(bit_depth - 1) and
+    // each sample maps to the middle of its step, so there are exactly 2^bit_depth levels
+    fn quantize(&self, sample: f32) -> f32 {
+        let half_levels = 2.0f32.powi(self.bit_depth as i32 - 1);
+        let step = (sample * half_levels).floor().clamp(-half_levels, half_levels - 1.0);
+        (step + 0.5) / half_levels
+    }
+}
+
+#[allow(dead_code)]
+pub(crate) fn default_bitcrusher() -> BitCrusher {
+    BitCrusherBuilder::default()
+        .build().unwrap()
+}
+
+#[allow(dead_code)]
+pub(crate) fn no_op_bitcrusher() -> BitCrusher {
+    BitCrusherBuilder::default()
+        .mix(0.0)
+        .build().unwrap()
+}
+
+#[cfg(test)]
+mod test_bitcrusher {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::effect::bitcrusher::BitCrusherBuilder;
+
+    #[test]
+    fn test_reduced_bit_depth_is_stair_stepped() {
+        let mut bitcrusher = BitCrusherBuilder::default()
+            .bit_depth(2)
+            .build().unwrap();
+
+        // A slow ramp from -1.0 to 1.0 collapses onto a handful of levels
+        let mut levels: Vec<f32> = Vec::new();
+        for i in 0..=100 {
+            let sample = -1.0 + (i as f32 / 50.0);
+            let output = bitcrusher.apply_effect(sample, i as f32);
+            if !levels.contains(&output) {
+                levels.push(output);
+            }
+        }
+        // 2 bits give 4 levels, each in the middle of a step of 0.5
+        assert_eq!(levels.len(), 4);
+        for level in levels {
+            assert_float_eq((level * 2.0 + 0.5).fract(), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_downsample_holds_samples() {
+        let mut bitcrusher = BitCrusherBuilder::default()
+            .bit_depth(16)
+            .downsample_factor(4)
+            .build().unwrap();
+
+        let outputs: Vec<f32> = (0..8)
+            .map(|i| bitcrusher.apply_effect(i as f32 / 8.0, i as f32))
+            .collect();
+        assert_float_eq(outputs[0], outputs[3]);
+        assert_float_eq(outputs[4], outputs[7]);
+        assert!(outputs[4] > outputs[3]);
+    }
+
+    #[test]
+    fn test_zero_mix_is_transparent() {
+        let mut bitcrusher = BitCrusherBuilder::default()
+            .bit_depth(2)
+            .downsample_factor(4)
+            .mix(0.0)
+            .build().unwrap();
+
+        for i in 0..16 {
+            let sample = (i as f32 * 0.37).sin();
+            assert_float_eq(bitcrusher.apply_effect(sample, i as f32), sample);
+        }
+    }
+
+    #[test]
+    fn test_invalid_bit_depth() {
+        assert!(BitCrusherBuilder::default().bit_depth(0).build().is_err());
+        assert!(BitCrusherBuilder::default().downsample_factor(0).build().is_err());
+    }
+}
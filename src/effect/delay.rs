@@ -1,4 +1,6 @@
 use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use std::collections::VecDeque;
 use std::sync::{Arc, RwLock};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
@@ -20,6 +22,44 @@ static ACTIVE_SAMPLE_MANAGERS: LazyLock<Mutex<HashMap<usize, Vec<SampleManager>>
     LazyLock::new(|| Mutex::new(HashMap::new()));
 static SAMPLE_MANAGER_ID_COUNTER: LazyLock<Mutex<usize>> = LazyLock::new(|| Mutex::new(0));
 static MAX_NUM_ACTIVE_SAMPLE_MANAGERS: usize = 4;
+static DEFAULT_STEREO_MODE: DelayStereoMode = DelayStereoMode::Mono;
+static DEFAULT_DECAY_CURVE: DecayCurve = DecayCurve::Exponential;
+
+// Mono applies every echo identically to both channels, PingPong alternates consecutive echo
+// repeats between the left and right channels, starting on the left
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) enum DelayStereoMode {
+    Mono,
+    PingPong,
+}
+
+// How the gain of each echo repeat changes over the repeats
+//   Exponential: the default and the gain Delay always had, `decay` raised to the number of the
+//     delay window the sample manager is reading from
+//   Linear: gain falls in equal steps from `decay` on the first repeat towards 0 after the last
+//   Reverse: `decay` raised to the number of repeats still to come, so the echoes swell up to
+//     `decay` on the last repeat
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DecayCurve {
+    Exponential,
+    Linear,
+    Reverse,
+}
+
+impl FromStr for DecayCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "exponential" | "exp" => Ok(DecayCurve::Exponential),
+            "linear" | "lin" => Ok(DecayCurve::Linear),
+            "reverse" | "rev" => Ok(DecayCurve::Reverse),
+            _ => Err(format!("Unknown decay curve: {}", s)),
+        }
+    }
+}
 
 fn add_sample_manager(id: usize, sm_id: usize, sample_buffer_size: usize,
         delay_windows: Vec<bool>, num_delay_windows: usize,
@@ -42,6 +82,7 @@ fn add_sample_manager(id: usize, sm_id: usize, sample_buffer_size: usize,
             init_buffer_index: AtomicUsize::new(init_buffer_index),
             cur_delay_window: AtomicUsize::new(cur_delay_window),
             delay_windows_index: AtomicUsize::new(delay_windows_index),
+            delay_window_count: AtomicUsize::new(0),
             is_full: AtomicBool::new(false),
             is_active: AtomicBool::new(true),
             is_pre_delay: AtomicBool::new(true),
@@ -95,6 +136,8 @@ pub(crate) struct SampleManager {
     cur_delay_window: AtomicUsize,
     // position in bit vector of entire length of all delay windows
     delay_windows_index: AtomicUsize,
+    // number of delay windows entered so far, i.e. which echo repeat is playing, 1-based
+    delay_window_count: AtomicUsize,
     // false if the sample manager can still write more samples
     is_full: AtomicBool,
     // true if the sample manager hasn't finished going through its delay windows
@@ -141,7 +184,12 @@ impl SampleManager {
         }
         
         // check if we are in a delay window or an interval by checking current delay window value
-        if self.delay_windows[self.delay_windows_index.load(Ordering::SeqCst)] {
+        let delay_windows_index = self.delay_windows_index.load(Ordering::SeqCst);
+        if self.delay_windows[delay_windows_index] {
+            // If this is the first sample of a new delay window, count the echo repeat
+            if delay_windows_index == 0 || !self.delay_windows[delay_windows_index - 1] {
+                self.delay_window_count.fetch_add(1, Ordering::SeqCst);
+            }
             {
                 let buffer = self.sample_buffer.read().unwrap();
                 delay_sample =
@@ -176,6 +224,7 @@ impl SampleManager {
         self.init_buffer_index.store(0, Ordering::SeqCst);
         self.cur_delay_window.store(0, Ordering::SeqCst);
         self.delay_windows_index.store(0, Ordering::SeqCst);
+        self.delay_window_count.store(0, Ordering::SeqCst);
         self.is_full.store(false, Ordering::SeqCst);
         self.is_active.store(true, Ordering::SeqCst);
         self.is_pre_delay.store(true, Ordering::SeqCst);
@@ -199,6 +248,7 @@ impl SampleManager {
             println!("init_buffer_index: {}", self.init_buffer_index.load(Ordering::SeqCst));
             println!("cur_delay_window: {}", self.cur_delay_window.load(Ordering::SeqCst));
             println!("delay_windows_index: {}", self.delay_windows_index.load(Ordering::SeqCst));
+            println!("delay_window_count: {}", self.delay_window_count.load(Ordering::SeqCst));
             println!("is_full: {}", self.is_full.load(Ordering::SeqCst));
             println!("is_active: {}", self.is_active.load(Ordering::SeqCst));
             println!("is_pre_delay: {}", self.is_pre_delay.load(Ordering::SeqCst));
@@ -224,6 +274,7 @@ impl Clone for SampleManager {
             init_buffer_index: AtomicUsize::new(self.init_buffer_index.load(Ordering::SeqCst)),
             cur_delay_window: AtomicUsize::new(self.cur_delay_window.load(Ordering::SeqCst)),
             delay_windows_index: AtomicUsize::new(self.delay_windows_index.load(Ordering::SeqCst)),
+            delay_window_count: AtomicUsize::new(self.delay_window_count.load(Ordering::SeqCst)),
             is_full: AtomicBool::new(self.is_full.load(Ordering::SeqCst)),
             is_active: AtomicBool::new(self.is_active.load(Ordering::SeqCst)),
             is_pre_delay: AtomicBool::new(self.is_pre_delay.load(Ordering::SeqCst)),
@@ -234,9 +285,12 @@ impl Clone for SampleManager {
     }
 }
 
+// Only the parameters are serialized, deserializing goes through the builder so that the
+// derived state and sample managers are reconstructed
 #[allow(dead_code)]
-#[derive(Builder, Clone, Debug, PartialEq)]
-#[builder(build_fn(skip))]
+#[derive(Builder, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[builder(build_fn(skip), derive(Deserialize))]
+#[serde(try_from = "DelayBuilder")]
 pub(crate) struct Delay {
      
     id: usize,
@@ -262,24 +316,36 @@ pub(crate) struct Delay {
     // the number of concurrent sample managers allowed
     pub(crate) num_concurrent_sample_managers: usize,  
 
+    // whether echoes are applied to both channels or alternate between them
+    pub(crate) stereo_mode: DelayStereoMode,
+
+    // shape of the gain across echo repeats
+    pub(crate) decay_curve: DecayCurve,
+
     #[builder(field(private))]
+    #[serde(skip)]
     sample_manager_id_counter: usize,
     
     #[builder(field(private))]
+    #[serde(skip)]
     sample_manager_is_full_counter: usize,
 
     // complement of mix, private compute at build time because it's constant
     #[builder(field(private))]
+    #[serde(skip)]
     mix_complement: f32,
     
     // boundaries of sample indexes in delay windows or in intervals between delay windows
     #[builder(field(private))]
+    #[serde(skip)]
     delay_windows: Vec<bool>,
     
     #[builder(field(private))]
+    #[serde(skip)]
     duration_num_samples: usize,
 
     #[builder(field(private))]
+    #[serde(skip)]
     interval_num_samples: usize,
 }
 
@@ -329,6 +395,8 @@ impl DelayBuilder {
             self.num_predelay_samples.unwrap_or(PREDELAY_BUFFER_SIZE);
         let num_concurrent_sample_managers =
             self.num_concurrent_sample_managers.unwrap_or(MAX_NUM_ACTIVE_SAMPLE_MANAGERS);
+        let stereo_mode = self.stereo_mode.unwrap_or(DEFAULT_STEREO_MODE);
+        let decay_curve = self.decay_curve.unwrap_or(DEFAULT_DECAY_CURVE);
 
         let sample_manager_id_counter = 0;
         let sample_manager_is_full_counter = 0;
@@ -356,6 +424,8 @@ impl DelayBuilder {
                 num_repeats,
                 num_predelay_samples,
                 num_concurrent_sample_managers,
+                stereo_mode,
+                decay_curve,
                 // private
                 sample_manager_id_counter,
                 sample_manager_is_full_counter,
@@ -370,11 +440,79 @@ impl DelayBuilder {
     }
 }
 
+impl TryFrom<DelayBuilder> for Delay {
+    type Error = String;
+
+    fn try_from(mut builder: DelayBuilder) -> Result<Self, Self::Error> {
+        builder.build()
+    }
+}
+
 #[allow(dead_code)]
 impl Delay {
     
+    // Clear any samples captured by this delay's sample managers so it starts clean
+    pub(crate) fn reset_buffers(&mut self) {
+        let mut managers = ACTIVE_SAMPLE_MANAGERS.lock().unwrap();
+        if let Some(sample_managers) = managers.get_mut(&self.id) {
+            for sample_manager in sample_managers.iter_mut() {
+                sample_manager.reset();
+            }
+        }
+    }
+
+    // How long the echoes keep sounding after the input stops
+    pub(crate) fn tail_duration_ms(&self) -> f32 {
+        self.interval_ms * self.num_repeats as f32 + self.duration_ms
+    }
+
     pub(crate) fn apply_effect(&mut self, sample: f32, _sample_clock: f32) -> f32 {
-        let delay_sample = Arc::new(Mutex::new(0.0f32));
+        let (odd_repeat_value, even_repeat_value) = self.next_delay_samples(sample);
+        let final_value = odd_repeat_value + even_repeat_value;
+
+        self.mix_complement * sample + (self.mix * final_value)
+    }
+
+    pub(crate) fn apply_effect_stereo(&mut self, left_sample: f32, right_sample: f32,
+                                      _sample_clock: f32) -> (f32, f32) {
+        // the delay line is mono, so feed it the center of the stereo input
+        let (odd_repeat_value, even_repeat_value) =
+            self.next_delay_samples((left_sample + right_sample) / 2.0);
+
+        let (left_value, right_value) = match self.stereo_mode {
+            DelayStereoMode::Mono => {
+                let final_value = odd_repeat_value + even_repeat_value;
+                (final_value, final_value)
+            }
+            DelayStereoMode::PingPong => (odd_repeat_value, even_repeat_value),
+        };
+
+        (self.mix_complement * left_sample + (self.mix * left_value),
+         self.mix_complement * right_sample + (self.mix * right_value))
+    }
+
+    // Gain for the sample manager's current echo repeat according to the decay curve
+    fn repeat_gain(&self, sample_manager: &SampleManager) -> f32 {
+        let repeat = sample_manager.delay_window_count.load(Ordering::SeqCst)
+            .clamp(1, self.num_repeats.max(1));
+        match self.decay_curve {
+            // The echo levels from before there were decay curves, so existing delays sound alike
+            DecayCurve::Exponential => {
+                self.decay.powi(sample_manager.cur_delay_window.load(Ordering::SeqCst) as i32)
+            }
+            DecayCurve::Linear => {
+                self.decay * (self.num_repeats - repeat + 1) as f32 / self.num_repeats as f32
+            }
+            DecayCurve::Reverse => self.decay.powi((self.num_repeats - repeat + 1) as i32),
+        }
+    }
+
+    // Advance all sample managers for this delay by one sample and return the averaged delay
+    // output, split into the part coming from odd-numbered echo repeats and the part coming from
+    // even-numbered echo repeats
+    fn next_delay_samples(&mut self, sample: f32) -> (f32, f32) {
+        let mut odd_repeat_sample = 0.0f32;
+        let mut even_repeat_sample = 0.0f32;
         let num_delay_samples = AtomicUsize::new(0);
         let push = AtomicBool::new(false);
         
@@ -383,13 +521,14 @@ impl Delay {
             let mut managers = ACTIVE_SAMPLE_MANAGERS.lock().unwrap();
             if let Some(sample_managers) = managers.get_mut(&self.id) {
                 for sample_manager in sample_managers.iter_mut() {
-                    let next_sample = sample_manager.next_sample(sample) *
-                        self.decay.powi(sample_manager.cur_delay_window.load(Ordering::SeqCst) as i32);
+                    let delay_sample = sample_manager.next_sample(sample);
+                    let repeat = sample_manager.delay_window_count.load(Ordering::SeqCst);
+                    let next_sample = delay_sample * self.repeat_gain(sample_manager);
                     
-                    // Update delay sample under one lock
-                    {
-                        let mut current = delay_sample.lock().unwrap();
-                        *current += next_sample;
+                    if repeat % 2 == 0 {
+                        even_repeat_sample += next_sample;
+                    } else {
+                        odd_repeat_sample += next_sample;
                     }
                     
                     num_delay_samples.fetch_add(1, Ordering::SeqCst);
@@ -403,14 +542,10 @@ impl Delay {
             }
         }
 
-        // Get final value under one lock
-        let final_value = {
-            let mut value = delay_sample.lock().unwrap();
-            if num_delay_samples.load(Ordering::SeqCst) > 0 {
-                *value /= num_delay_samples.load(Ordering::SeqCst) as f32;
-            }
-            *value
-        };
+        if num_delay_samples.load(Ordering::SeqCst) > 0 {
+            odd_repeat_sample /= num_delay_samples.load(Ordering::SeqCst) as f32;
+            even_repeat_sample /= num_delay_samples.load(Ordering::SeqCst) as f32;
+        }
 
         // Add new manager outside the lock
         // enforce global limit on number of active sample managers
@@ -428,8 +563,7 @@ impl Delay {
         }
         push.store(false, Ordering::SeqCst);
 
-        self.mix_complement * sample + (self.mix * final_value)
-
+        (odd_repeat_sample, even_repeat_sample)
     }
 }
 
@@ -445,3 +579,167 @@ pub(crate) fn no_op_delay() -> Delay {
         .num_repeats(0)
         .build().unwrap()
 }
+
+#[cfg(test)]
+mod test_delay {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::effect::delay::{DecayCurve, Delay, DelayBuilder, DelayStereoMode};
+
+    fn impulse_echo_levels(id: usize, decay_curve: DecayCurve) -> Vec<f32> {
+        let mut delay = DelayBuilder::default()
+            .id(id)
+            .mix(1.0)
+            .decay(0.5)
+            .interval_ms(1.0)
+            .duration_ms(1.0)
+            .num_repeats(4)
+            .num_predelay_samples(1)
+            .num_concurrent_sample_managers(1)
+            .decay_curve(decay_curve)
+            .build().unwrap();
+
+        let mut echo_levels = Vec::new();
+        for i in 0..400 {
+            let input = if i == 0 { 1.0 } else { 0.0 };
+            let output = delay.apply_effect(input, i as f32);
+            if output != 0.0 {
+                echo_levels.push(output);
+            }
+        }
+        echo_levels
+    }
+
+    #[test]
+    fn test_reverse_decay_curve_swells() {
+        let echo_levels = impulse_echo_levels(2271, DecayCurve::Reverse);
+        assert!(echo_levels.len() >= 2);
+        for pair in echo_levels.windows(2) {
+            assert!(pair[1] > pair[0]);
+        }
+    }
+
+    #[test]
+    fn test_linear_decay_curve_fades() {
+        let echo_levels = impulse_echo_levels(2273, DecayCurve::Linear);
+        assert!(echo_levels.len() >= 2);
+        for pair in echo_levels.windows(2) {
+            assert!(pair[1] < pair[0]);
+        }
+    }
+
+    #[test]
+    fn test_exponential_decay_curve_keeps_original_levels() {
+        // Each sample manager reads a single delay window, so every echo is at `decay`
+        let echo_levels = impulse_echo_levels(2272, DecayCurve::Exponential);
+        assert!(echo_levels.len() >= 2);
+        for echo_level in echo_levels {
+            assert_float_eq(echo_level, 0.5);
+        }
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let delay = DelayBuilder::default()
+            .id(2270)
+            .mix(0.6)
+            .decay(0.3)
+            .interval_ms(75.0)
+            .duration_ms(15.0)
+            .num_repeats(3)
+            .num_predelay_samples(10)
+            .num_concurrent_sample_managers(2)
+            .stereo_mode(DelayStereoMode::PingPong)
+            .build().unwrap();
+
+        let json = serde_json::to_string(&delay).unwrap();
+        assert!(!json.contains("delay_windows"));
+
+        let mut deserialized: Delay = serde_json::from_str(&json).unwrap();
+        deserialized.reset_buffers();
+        assert_eq!(deserialized, delay);
+    }
+
+    #[test]
+    fn test_tail_duration_ms() {
+        let delay = DelayBuilder::default()
+            .id(2269)
+            .interval_ms(100.0)
+            .duration_ms(20.0)
+            .num_repeats(4)
+            .build().unwrap();
+        assert_float_eq(delay.tail_duration_ms(), 420.0);
+
+        let delay = DelayBuilder::default()
+            .id(2269)
+            .interval_ms(250.0)
+            .duration_ms(50.0)
+            .num_repeats(1)
+            .build().unwrap();
+        assert_float_eq(delay.tail_duration_ms(), 300.0);
+
+        let delay = DelayBuilder::default()
+            .id(2269)
+            .interval_ms(80.0)
+            .duration_ms(40.0)
+            .num_repeats(8)
+            .build().unwrap();
+        assert_float_eq(delay.tail_duration_ms(), 680.0);
+    }
+
+    #[test]
+    fn test_ping_pong_echoes_alternate_channels() {
+        // use a unique id, sample managers are stored globally per delay id
+        let mut delay = DelayBuilder::default()
+            .id(2266)
+            .mix(1.0)
+            .decay(0.5)
+            .interval_ms(1.0)
+            .duration_ms(1.0)
+            .num_repeats(4)
+            .num_predelay_samples(1)
+            .num_concurrent_sample_managers(1)
+            .stereo_mode(DelayStereoMode::PingPong)
+            .build().unwrap();
+
+        // centered impulse followed by silence
+        let mut echo_channels = Vec::new();
+        for i in 0..400 {
+            let input = if i == 0 { 1.0 } else { 0.0 };
+            let (left, right) = delay.apply_effect_stereo(input, input, i as f32);
+            if left != 0.0 || right != 0.0 {
+                // each echo lands entirely in one channel
+                assert!(left == 0.0 || right == 0.0);
+                echo_channels.push(if left != 0.0 { 'L' } else { 'R' });
+            }
+        }
+
+        assert!(echo_channels.len() >= 2);
+        assert_eq!(echo_channels[0], 'L');
+        for pair in echo_channels.windows(2) {
+            assert_ne!(pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn test_mono_echoes_both_channels() {
+        let mut delay = DelayBuilder::default()
+            .id(2267)
+            .mix(1.0)
+            .interval_ms(1.0)
+            .duration_ms(1.0)
+            .num_predelay_samples(1)
+            .num_concurrent_sample_managers(1)
+            .build().unwrap();
+
+        let mut num_echoes = 0;
+        for i in 0..400 {
+            let input = if i == 0 { 1.0 } else { 0.0 };
+            let (left, right) = delay.apply_effect_stereo(input, input, i as f32);
+            assert_eq!(left, right);
+            if left != 0.0 {
+                num_echoes += 1;
+            }
+        }
+        assert!(num_echoes > 0);
+    }
+}
@@ -0,0 +1,179 @@
+use std::str::FromStr;
+use derive_builder::Builder;
+
+static DEFAULT_DRIVE: f32 = 1.0;
+static DEFAULT_CURVE: DistortionCurve = DistortionCurve::SoftClip;
+static DEFAULT_MIX: f32 = 1.0;
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DistortionCurve {
+    // tanh saturation, smooth and bounded to -1.0..1.0
+    SoftClip,
+    // clamp to -1.0..1.0
+    HardClip,
+    // reflect anything past -1.0..1.0 back into range
+    Foldback,
+}
+
+impl FromStr for DistortionCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "softclip" | "soft" => Ok(DistortionCurve::SoftClip),
+            "hardclip" | "hard" => Ok(DistortionCurve::HardClip),
+            "foldback" | "fold" => Ok(DistortionCurve::Foldback),
+            _ => Err(format!("Unknown distortion curve: {}", s)),
+        }
+    }
+}
+
+#[derive(Builder, Clone, Debug, PartialEq)]
+#[builder(build_fn(validate = "Self::validate"))]
+pub(crate) struct Distortion {
+    // Gain applied to the sample before it is shaped by the curve
+    #[builder(default = "DEFAULT_DRIVE")]
+    pub(crate) drive: f32,
+
+    #[builder(default = "DEFAULT_CURVE")]
+    pub(crate) curve: DistortionCurve,
+
+    // The mix level of the effect
+    #[builder(default = "DEFAULT_MIX")]
+    pub(crate) mix: f32,
+
+    // Complement of mix, computed at build time
+    #[builder(field(private), default = "1.0 - self.mix.unwrap_or(DEFAULT_MIX)")]
+    mix_complement: f32,
+}
+
+impl DistortionBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if self.drive.unwrap_or(DEFAULT_DRIVE) <= 0.0 {
+            return Err(String::from("Distortion: drive must be greater than 0.0"));
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl Distortion {
+    pub(crate) fn apply_effect(&mut self, sample: f32, _sample_clock: f32) -> f32 {
+        let driven_sample = sample * self.drive;
+        let distorted_sample = match self.curve {
+            DistortionCurve::SoftClip => driven_sample.tanh(),
+            DistortionCurve::HardClip => driven_sample.clamp(-1.0, 1.0),
+            DistortionCurve::Foldback => fold_back(driven_sample),
+        };
+
+        sample * self.mix_complement + distorted_sample * self.mix
+    }
+}
+
+// Reflect the sample off the -1.0 and 1.0 thresholds until it is back in range
+fn fold_back(sample: f32) -> f32 {
+    // the fold is periodic with period 4, map into 0..4 and then onto a triangle
+    let phase = (sample + 1.0).rem_euclid(4.0);
+    if phase <= 2.0 {
+        phase - 1.0
+    } else {
+        3.0 - phase
+    }
+}
+
+#[allow(dead_code)]
+pub(crate) fn default_distortion() -> Distortion {
+    DistortionBuilder::default()
+        .build().unwrap()
+}
+
+#[allow(dead_code)]
+pub(crate) fn no_op_distortion() -> Distortion {
+    DistortionBuilder::default()
+        .mix(0.0)
+        .build().unwrap()
+}
+
+#[cfg(test)]
+mod test_distortion {
+    use std::f32::consts::PI;
+    use std::str::FromStr;
+    use crate::common::float_utils::assert_float_eq;
+    use crate::effect::distortion::{DistortionBuilder, DistortionCurve};
+
+    const NUM_SAMPLES: usize = 441;
+
+    fn sine_cycle() -> Vec<f32> {
+        (0..NUM_SAMPLES)
+            .map(|i| (2.0 * PI * i as f32 / NUM_SAMPLES as f32).sin())
+            .collect()
+    }
+
+    // Energy left over after removing the fundamental, relative to total energy
+    fn harmonic_energy_ratio(output: &[f32], fundamental: &[f32]) -> f32 {
+        let dot: f32 = output.iter().zip(fundamental).map(|(y, x)| y * x).sum();
+        let fundamental_energy: f32 = fundamental.iter().map(|x| x * x).sum();
+        let gain = dot / fundamental_energy;
+        let residual_energy: f32 = output.iter().zip(fundamental)
+            .map(|(y, x)| (y - gain * x).powi(2))
+            .sum();
+        let total_energy: f32 = output.iter().map(|y| y * y).sum();
+        residual_energy / total_energy
+    }
+
+    fn distort(drive: f32, curve: DistortionCurve, input: &[f32]) -> Vec<f32> {
+        let mut distortion = DistortionBuilder::default()
+            .drive(drive)
+            .curve(curve)
+            .build().unwrap();
+        input.iter().enumerate()
+            .map(|(i, sample)| distortion.apply_effect(*sample, i as f32))
+            .collect()
+    }
+
+    #[test]
+    fn test_soft_clip_is_bounded() {
+        let input: Vec<f32> = sine_cycle().iter().map(|sample| sample * 4.0).collect();
+        for sample in distort(10.0, DistortionCurve::SoftClip, &input) {
+            assert!((-1.0..=1.0).contains(&sample));
+        }
+    }
+
+    #[test]
+    fn test_fold_back_is_bounded() {
+        let input: Vec<f32> = sine_cycle().iter().map(|sample| sample * 4.0).collect();
+        for sample in distort(3.0, DistortionCurve::Foldback, &input) {
+            assert!((-1.0..=1.0).contains(&sample));
+        }
+    }
+
+    #[test]
+    fn test_drive_increases_harmonics() {
+        let input = sine_cycle();
+        for curve in [DistortionCurve::SoftClip, DistortionCurve::HardClip] {
+            let light = harmonic_energy_ratio(&distort(1.5, curve, &input), &input);
+            let heavy = harmonic_energy_ratio(&distort(6.0, curve, &input), &input);
+            assert!(heavy > light);
+        }
+    }
+
+    #[test]
+    fn test_zero_mix_is_transparent() {
+        let mut distortion = DistortionBuilder::default()
+            .drive(8.0)
+            .mix(0.0)
+            .build().unwrap();
+        for sample in sine_cycle() {
+            assert_float_eq(distortion.apply_effect(sample, 0.0), sample);
+        }
+    }
+
+    #[test]
+    fn test_curve_from_str() {
+        assert_eq!(DistortionCurve::from_str("softclip").unwrap(), DistortionCurve::SoftClip);
+        assert_eq!(DistortionCurve::from_str("HardClip").unwrap(), DistortionCurve::HardClip);
+        assert_eq!(DistortionCurve::from_str("foldback").unwrap(), DistortionCurve::Foldback);
+        assert!(DistortionCurve::from_str("fuzz").is_err());
+    }
+}
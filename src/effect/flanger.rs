@@ -2,25 +2,40 @@ use std::collections::VecDeque;
 use std::sync::{Arc, RwLock};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+use crate::common::constants::SAMPLES_PER_MS;
 
 static SAMPLE_BUFFER_SIZE: usize = 20;
 static DEFAULT_WINDOW_SIZE: usize = 12;
 static DEFAULT_MIX: f32 = 0.5;
+static DEFAULT_FEEDBACK: f32 = 0.0;
+static MAX_FEEDBACK: f32 = 0.95;
+// level, relative to the input, below which a fed back signal is considered silent (-60dB)
+static TAIL_SILENCE_LEVEL: f32 = 0.001;
 
-#[derive(Builder, Debug)]
+// Only the parameters are serialized, deserializing goes through the builder so that the
+// sample buffer is reconstructed
+#[derive(Builder, Debug, Serialize, Deserialize)]
+#[builder(derive(Deserialize))]
+#[serde(try_from = "FlangerBuilder")]
 pub(crate) struct Flanger {
     // The size of the sample window
     #[builder(default = "SAMPLE_BUFFER_SIZE")]
-    window_size: usize,
+    pub(crate) window_size: usize,
 
     // The buffer holding samples for the flanger effect
     #[builder(field(private),
       default = "Arc::new(RwLock::new(VecDeque::with_capacity(self.window_size.unwrap_or(SAMPLE_BUFFER_SIZE))))",
       setter(custom))]
+    #[builder_field_attr(serde(skip))]
+    #[serde(skip)]
     sample_buffer: Arc<RwLock<VecDeque<f32>>>,
 
     // The current index for inserting samples into the buffer
     #[builder(default = "AtomicUsize::new(0)", setter(skip))]
+    #[builder_field_attr(serde(skip))]
+    #[serde(skip)]
     insert_index: AtomicUsize,
 
     // The mix level of the effect 
@@ -29,7 +44,12 @@ pub(crate) struct Flanger {
 
     // Complement of mix, computed at build time
     #[builder(field(private), default = "1.0 - self.mix.unwrap_or(DEFAULT_MIX)")]
+    #[serde(skip)]
     mix_complement: f32,
+
+    // The amount of delayed output fed back into the delay line, clamped to [0.0, MAX_FEEDBACK]
+    #[builder(default = "DEFAULT_FEEDBACK", setter(custom))]
+    pub(crate) feedback: f32,
 }
 
 impl Clone for Flanger {
@@ -40,6 +60,7 @@ impl Clone for Flanger {
             insert_index: AtomicUsize::new(self.insert_index.load(Ordering::SeqCst)),
             mix: self.mix,
             mix_complement: self.mix_complement,
+            feedback: self.feedback,
         }
     }
 }
@@ -50,7 +71,16 @@ impl PartialEq for Flanger {
         self.insert_index.load(Ordering::SeqCst) ==
             other.insert_index.load(Ordering::SeqCst) &&
         self.mix == other.mix &&
-        self.mix_complement == other.mix_complement
+        self.mix_complement == other.mix_complement &&
+        self.feedback == other.feedback
+    }
+}
+
+impl TryFrom<FlangerBuilder> for Flanger {
+    type Error = String;
+
+    fn try_from(builder: FlangerBuilder) -> Result<Self, Self::Error> {
+        builder.build().map_err(|e| e.to_string())
     }
 }
 
@@ -64,22 +94,53 @@ impl FlangerBuilder {
         self.sample_buffer = Some(sample_buffer);
         self
     }
+
+    pub(crate) fn feedback(&mut self, feedback: f32) -> &mut Self {
+        self.feedback = Some(feedback.clamp(0.0, MAX_FEEDBACK));
+        self
+    }
 }
 
 #[allow(dead_code)]
 impl Flanger {
+    // Empty the delay line so the flanger starts clean
+    pub(crate) fn reset_buffers(&mut self) {
+        self.sample_buffer.write().unwrap().clear();
+        self.insert_index.store(0, Ordering::SeqCst);
+    }
+
+    // How long the delay line keeps sounding after the input stops, one pass through the window
+    // plus as many more as it takes the feedback to decay to silence
+    pub(crate) fn tail_duration_ms(&self) -> f32 {
+        let window_ms = self.window_size as f32 / SAMPLES_PER_MS;
+        if self.feedback > 0.0 {
+            let num_recirculations = (TAIL_SILENCE_LEVEL.ln() / self.feedback.ln()).ceil();
+            window_ms * (1.0 + num_recirculations)
+        } else {
+            window_ms
+        }
+    }
+
     pub(crate) fn apply_effect(&mut self, sample: f32, _sample_clock: f32) -> f32 {
         let mut flanger_sample = 0.0;
         
-        // Write new sample
+        // Write new sample, feeding back the delayed sample that is about to be read
         {
             let mut buffer = self.sample_buffer.write().unwrap();
+            let feedback_sample = if buffer.is_empty() {
+                0.0
+            } else {
+                let read_idx = (self.insert_index.load(Ordering::SeqCst) + 1) % buffer.len();
+                *buffer.get(read_idx).unwrap_or(&0.0) * self.feedback
+            };
+            let input_sample = sample + feedback_sample;
+
             if buffer.len() < self.window_size {
-                buffer.push_back(sample);
+                buffer.push_back(input_sample);
             } else {
                 let idx = self.insert_index.load(Ordering::SeqCst) % self.window_size;
                 if let Some(old_sample) = buffer.get_mut(idx) {
-                    *old_sample = sample;
+                    *old_sample = input_sample;
                 }
             }
         }
@@ -114,3 +175,75 @@ pub(crate) fn no_op_flanger() -> Flanger {
         .window_size(0)
         .build().unwrap()
 }
+
+#[cfg(test)]
+mod test_flanger {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::effect::flanger::{Flanger, FlangerBuilder};
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut flanger = FlangerBuilder::default()
+            .window_size(16)
+            .mix(0.3)
+            .feedback(0.6)
+            .build().unwrap();
+        flanger.apply_effect(0.5, 0.0);
+        flanger.reset_buffers();
+
+        let json = serde_json::to_string(&flanger).unwrap();
+        let deserialized: Flanger = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, flanger);
+    }
+
+    fn impulse_response_energy(feedback: f32) -> f32 {
+        let mut flanger = FlangerBuilder::default()
+            .window_size(8)
+            .mix(1.0)
+            .feedback(feedback)
+            .build().unwrap();
+
+        let mut energy = 0.0;
+        for i in 0..256 {
+            let input = if i == 0 { 1.0 } else { 0.0 };
+            let output = flanger.apply_effect(input, i as f32);
+            energy += output * output;
+        }
+        energy
+    }
+
+    #[test]
+    fn test_feedback_increases_sustained_energy() {
+        let dry_energy = impulse_response_energy(0.0);
+        let feedback_energy = impulse_response_energy(0.8);
+
+        assert!(feedback_energy > dry_energy);
+    }
+
+    #[test]
+    fn test_tail_duration_ms() {
+        let flanger = FlangerBuilder::default()
+            .window_size(441)
+            .build().unwrap();
+        assert_float_eq(flanger.tail_duration_ms(), 10.0);
+
+        let flanger = FlangerBuilder::default()
+            .window_size(441)
+            .feedback(0.5)
+            .build().unwrap();
+        assert!(flanger.tail_duration_ms() > 10.0);
+    }
+
+    #[test]
+    fn test_feedback_is_clamped() {
+        let flanger = FlangerBuilder::default()
+            .feedback(2.0)
+            .build().unwrap();
+        assert_float_eq(flanger.feedback, 0.95);
+
+        let flanger = FlangerBuilder::default()
+            .feedback(-1.0)
+            .build().unwrap();
+        assert_float_eq(flanger.feedback, 0.0);
+    }
+}
@@ -1,11 +1,12 @@
 use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
 
 use crate::audio_gen::oscillator::{get_gaussian_noise_sample, get_sample, OscillatorTables};
 use crate::audio_gen::oscillator::Waveform;
 use crate::common::constants::{DEFAULT_LFO_AMPLITUDE, SAMPLE_RATE};
 
 #[allow(dead_code)]
-#[derive(Builder, Clone, Debug, PartialEq)]
+#[derive(Builder, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub(crate) struct LFO {
     #[builder(default = "SAMPLE_RATE / 10.0", setter(custom))]
     pub(crate) frequency: f32,
@@ -18,7 +19,9 @@ pub(crate) struct LFO {
     #[builder(default = "vec![Waveform::Sine]", setter(custom))]
     pub(crate) waveforms: Vec<Waveform>,
 
+    // Lookup tables are not serialized, they are rebuilt on deserialize
     #[builder(default = "OscillatorTables::new()", setter(skip))]
+    #[serde(skip, default = "OscillatorTables::new")]
     oscillator_tables: OscillatorTables,
 }
 
@@ -51,14 +54,16 @@ impl LFO {
             sample += match waveform {
                 Waveform::GaussianNoise => get_gaussian_noise_sample(),
                 Waveform::Noise => get_gaussian_noise_sample(), // Alias for GaussianNoise
+                // Colored noise needs state the LFO doesn't carry, so it modulates with white noise
+                Waveform::PinkNoise | Waveform::BrownNoise => get_gaussian_noise_sample(),
                 Waveform::Saw => get_sample(&self.oscillator_tables.saw_table,
                                             self.frequency, sample_count),
                 Waveform::Sine => get_sample(&self.oscillator_tables.sine_table,
                                              self.frequency, sample_count),
                 Waveform::Triangle => get_sample(&self.oscillator_tables.triangle_table,
                                                  self.frequency, sample_count),
-                // LFO cannot contain square waveform
-                Waveform::Square => 0.0
+                // LFO cannot contain square or pulse waveforms
+                Waveform::Square | Waveform::Pulse => 0.0
             }
         }
         self.amplitude * sample
@@ -69,3 +74,24 @@ impl LFO {
 pub(crate) fn default_lfo() -> LFO {
     LFOBuilder::default().build().unwrap()
 }
+
+#[cfg(test)]
+mod test_lfo {
+    use crate::audio_gen::oscillator::Waveform;
+    use crate::effect::lfo::{LFO, LFOBuilder};
+
+    #[test]
+    fn test_serde_round_trip() {
+        let lfo = LFOBuilder::default()
+            .frequency(2.5)
+            .amplitude(0.3)
+            .waveforms(vec![Waveform::Sine, Waveform::Triangle])
+            .build().unwrap();
+
+        let json = serde_json::to_string(&lfo).unwrap();
+        assert!(!json.contains("oscillator_tables"));
+
+        let deserialized: LFO = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, lfo);
+    }
+}
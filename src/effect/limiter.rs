@@ -0,0 +1,164 @@
+use derive_builder::Builder;
+
+use crate::common::constants::SAMPLES_PER_MS;
+
+static DEFAULT_THRESHOLD: f32 = 0.9;
+static DEFAULT_RELEASE_MS: f32 = 100.0;
+
+// Per-sample multiplier that moves the gain back toward unity over release_ms
+fn release_coefficient(release_ms: f32) -> f32 {
+    (-1.0 / (release_ms * SAMPLES_PER_MS)).exp()
+}
+
+// Peak limiter for the final mix. Levels below threshold pass unchanged, levels above it are
+// compressed by a soft knee that approaches but never reaches 1.0. Gain drops immediately when the
+// peak rises, with no lookahead, and recovers over release_ms.
+#[derive(Builder, Clone, Debug, PartialEq)]
+#[builder(build_fn(validate = "Self::validate"))]
+pub(crate) struct Limiter {
+    #[builder(default = "DEFAULT_THRESHOLD")]
+    pub(crate) threshold: f32,
+
+    #[builder(default = "DEFAULT_RELEASE_MS")]
+    pub(crate) release_ms: f32,
+
+    // Computed from release_ms at build time
+    #[builder(field(private),
+      default = "release_coefficient(self.release_ms.unwrap_or(DEFAULT_RELEASE_MS))")]
+    release_coefficient: f32,
+
+    // The gain currently being applied
+    #[builder(setter(skip), default = "1.0")]
+    gain: f32,
+}
+
+impl LimiterBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let threshold = self.threshold.unwrap_or(DEFAULT_THRESHOLD);
+        if threshold <= 0.0 || threshold >= 1.0 {
+            return Err(String::from("Limiter: threshold must be between 0.0 and 1.0"));
+        }
+        if self.release_ms.unwrap_or(DEFAULT_RELEASE_MS) <= 0.0 {
+            return Err(String::from("Limiter: release_ms must be greater than 0.0"));
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl Limiter {
+    pub(crate) fn apply_effect(&mut self, sample: f32, _sample_clock: f32) -> f32 {
+        self.update_gain(sample.abs());
+        sample * self.gain
+    }
+
+    // Both channels share one gain so the stereo image doesn't shift while limiting
+    pub(crate) fn apply_effect_stereo(&mut self, sample_l: f32, sample_r: f32) -> (f32, f32) {
+        self.update_gain(sample_l.abs().max(sample_r.abs()));
+        (sample_l * self.gain, sample_r * self.gain)
+    }
+
+    // The gain the last sample was given, to limit a copy of the signal, e.g. without a part only
+    // some listeners hear, the same way
+    pub(crate) fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    // Parameters can change while the limiter is running, e.g. from the TUI. Out of range values
+    // are ignored.
+    pub(crate) fn set_threshold(&mut self, threshold: f32) {
+        if threshold > 0.0 && threshold < 1.0 {
+            self.threshold = threshold;
+        }
+    }
+
+    pub(crate) fn set_release_ms(&mut self, release_ms: f32) {
+        if release_ms > 0.0 && release_ms != self.release_ms {
+            self.release_ms = release_ms;
+            self.release_coefficient = release_coefficient(release_ms);
+        }
+    }
+
+    fn update_gain(&mut self, peak: f32) {
+        let target_gain = if peak > self.threshold {
+            self.soft_knee(peak) / peak
+        } else {
+            1.0
+        };
+
+        if target_gain < self.gain {
+            self.gain = target_gain;
+        } else {
+            self.gain = target_gain + (self.gain - target_gain) * self.release_coefficient;
+        }
+    }
+
+    // Continuous with slope 1.0 at the threshold, and bounded above by 1.0
+    fn soft_knee(&self, peak: f32) -> f32 {
+        let headroom = 1.0 - self.threshold;
+        self.threshold + headroom * ((peak - self.threshold) / headroom).tanh()
+    }
+}
+
+#[allow(dead_code)]
+pub(crate) fn default_limiter() -> Limiter {
+    LimiterBuilder::default().build().unwrap()
+}
+
+#[cfg(test)]
+mod test_limiter {
+    use std::f32::consts::PI;
+    use crate::common::float_utils::assert_float_eq;
+    use crate::effect::limiter::{default_limiter, LimiterBuilder};
+
+    fn loud_sine(i: usize) -> f32 {
+        // four full scale sines summed in phase
+        4.0 * (2.0 * PI * i as f32 / 100.0).sin()
+    }
+
+    #[test]
+    fn test_keeps_output_within_bounds() {
+        let mut limiter = default_limiter();
+        let mut exceeded = false;
+        for i in 0..4410 {
+            let sample = loud_sine(i);
+            exceeded |= sample.abs() > 1.0;
+            let (sample_l, sample_r) = limiter.apply_effect_stereo(sample, -sample * 0.5);
+            assert!(sample_l.abs() <= 1.0);
+            assert!(sample_r.abs() <= 1.0);
+        }
+        assert!(exceeded);
+    }
+
+    #[test]
+    fn test_below_threshold_is_transparent() {
+        let mut limiter = default_limiter();
+        for i in 0..441 {
+            let sample = 0.2 * loud_sine(i);
+            assert_float_eq(limiter.apply_effect(sample, 0.0), sample);
+        }
+    }
+
+    #[test]
+    fn test_gain_recovers_after_release() {
+        let mut limiter = LimiterBuilder::default()
+            .release_ms(10.0)
+            .build().unwrap();
+        limiter.apply_effect(4.0, 0.0);
+        let limited = limiter.apply_effect(0.5, 0.0);
+        assert!(limited < 0.5);
+
+        // well past the release time the gain is back to unity
+        for _ in 0..4410 {
+            limiter.apply_effect(0.5, 0.0);
+        }
+        assert!((limiter.apply_effect(0.5, 0.0) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(LimiterBuilder::default().threshold(1.0).build().is_err());
+        assert!(LimiterBuilder::default().threshold(0.0).build().is_err());
+        assert!(LimiterBuilder::default().release_ms(0.0).build().is_err());
+    }
+}
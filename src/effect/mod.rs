@@ -1,3 +1,8 @@
 pub mod flanger;
 pub mod lfo;
 pub mod delay;
+pub mod bitcrusher;
+pub mod distortion;
+pub mod tremolo;
+pub mod limiter;
+pub mod stereo_width;
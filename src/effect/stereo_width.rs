@@ -0,0 +1,96 @@
+use derive_builder::Builder;
+
+static DEFAULT_WIDTH: f32 = 1.0;
+
+// Widens or narrows the stereo image by mid-side processing. The mid, what both channels share,
+// is kept and the side, what differs between them, is scaled by width. Width 0.0 is mono, 1.0
+// leaves the image unchanged and above 1.0 widens it.
+#[derive(Builder, Clone, Debug, PartialEq)]
+#[builder(build_fn(validate = "Self::validate"))]
+pub(crate) struct StereoWidth {
+    #[builder(default = "DEFAULT_WIDTH")]
+    pub(crate) width: f32,
+}
+
+impl StereoWidthBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let width = self.width.unwrap_or(DEFAULT_WIDTH);
+        if width < 0.0 || !width.is_finite() {
+            return Err(String::from("StereoWidth: width must be 0.0 or greater"));
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl StereoWidth {
+    pub(crate) fn apply_effect_stereo(&mut self, sample_l: f32, sample_r: f32,
+                                      _sample_clock: f32) -> (f32, f32) {
+        // mid = (l + r) / 2 and side = width * (l - r) / 2, decoded as mid + side and mid - side.
+        // Gathered by channel, so width 1.0 passes each channel through exactly and width 0.0
+        // gives both channels exactly the same sample.
+        let direct = (1.0 + self.width) / 2.0;
+        let crossed = (1.0 - self.width) / 2.0;
+        (direct * sample_l + crossed * sample_r, direct * sample_r + crossed * sample_l)
+    }
+
+    // The width can change while the effect is running, e.g. from the TUI. Out of range values
+    // are ignored.
+    pub(crate) fn set_width(&mut self, width: f32) {
+        if width >= 0.0 && width.is_finite() {
+            self.width = width;
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub(crate) fn default_stereo_width() -> StereoWidth {
+    StereoWidthBuilder::default().build().unwrap()
+}
+
+#[cfg(test)]
+mod test_stereo_width {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::effect::stereo_width::{default_stereo_width, StereoWidthBuilder};
+
+    // A frame of each kind, centered, hard to one side, out of phase and unrelated
+    static FRAMES: [(f32, f32); 4] = [(0.5, 0.5), (0.8, 0.0), (0.3, -0.3), (-0.7, 0.2)];
+
+    #[test]
+    fn test_width_zero_is_mono() {
+        let mut stereo_width = StereoWidthBuilder::default().width(0.0).build().unwrap();
+        for (sample_l, sample_r) in FRAMES {
+            let (out_l, out_r) = stereo_width.apply_effect_stereo(sample_l, sample_r, 0.0);
+            assert_eq!(out_l, out_r);
+            assert_float_eq(out_l + out_r, sample_l + sample_r);
+        }
+    }
+
+    #[test]
+    fn test_width_one_passes_through() {
+        let mut stereo_width = default_stereo_width();
+        for (sample_l, sample_r) in FRAMES {
+            assert_eq!(stereo_width.apply_effect_stereo(sample_l, sample_r, 0.0),
+                       (sample_l, sample_r));
+        }
+    }
+
+    #[test]
+    fn test_wider_keeps_mid_and_scales_side() {
+        let mut stereo_width = StereoWidthBuilder::default().width(2.0).build().unwrap();
+        let (out_l, out_r) = stereo_width.apply_effect_stereo(0.6, 0.2, 0.0);
+        assert_float_eq(out_l + out_r, 0.8);
+        assert_float_eq(out_l - out_r, 0.8);
+    }
+
+    #[test]
+    fn test_width_must_not_be_negative() {
+        assert!(StereoWidthBuilder::default().width(-0.5).build().is_err());
+
+        let mut stereo_width = default_stereo_width();
+        stereo_width.set_width(-1.0);
+        assert_float_eq(stereo_width.width, 1.0);
+        stereo_width.set_width(0.5);
+        assert_float_eq(stereo_width.width, 0.5);
+    }
+}
@@ -0,0 +1,113 @@
+use derive_builder::Builder;
+
+use crate::audio_gen::oscillator::{get_gaussian_noise_sample, get_pulse_sample, get_sample,
+                                   OscillatorTables, DEFAULT_DUTY_CYCLE};
+use crate::audio_gen::oscillator::Waveform;
+
+static DEFAULT_RATE_HZ: f32 = 5.0;
+static DEFAULT_DEPTH: f32 = 0.5;
+static DEFAULT_WAVEFORM: Waveform = Waveform::Sine;
+
+#[allow(dead_code)]
+#[derive(Builder, Clone, Debug, PartialEq)]
+#[builder(build_fn(validate = "Self::validate"))]
+pub(crate) struct Tremolo {
+    // Speed of the volume modulation
+    #[builder(default = "DEFAULT_RATE_HZ")]
+    pub(crate) rate_hz: f32,
+
+    // How far the volume dips at the bottom of each cycle, 0.0 is no effect and 1.0 is silence
+    #[builder(default = "DEFAULT_DEPTH")]
+    pub(crate) depth: f32,
+
+    #[builder(default = "DEFAULT_WAVEFORM")]
+    pub(crate) waveform: Waveform,
+
+    #[builder(default = "OscillatorTables::new()", setter(skip))]
+    oscillator_tables: OscillatorTables,
+}
+
+impl TremoloBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if self.rate_hz.unwrap_or(DEFAULT_RATE_HZ) <= 0.0 {
+            return Err(String::from("Tremolo: rate_hz must be greater than 0.0"));
+        }
+        let depth = self.depth.unwrap_or(DEFAULT_DEPTH);
+        if !(0.0..=1.0).contains(&depth) {
+            return Err(String::from("Tremolo: depth must be between 0.0 and 1.0"));
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl Tremolo {
+    pub(crate) fn apply_effect(&self, sample: f32, sample_count: u64) -> f32 {
+        let lfo_sample = match self.waveform {
+            Waveform::GaussianNoise | Waveform::Noise | Waveform::PinkNoise |
+            Waveform::BrownNoise => get_gaussian_noise_sample().clamp(-1.0, 1.0),
+            Waveform::Saw => get_sample(&self.oscillator_tables.saw_table,
+                                        self.rate_hz, sample_count),
+            Waveform::Sine => get_sample(&self.oscillator_tables.sine_table,
+                                         self.rate_hz, sample_count),
+            Waveform::Square => get_sample(&self.oscillator_tables.square_table,
+                                           self.rate_hz, sample_count),
+            Waveform::Triangle => get_sample(&self.oscillator_tables.triangle_table,
+                                             self.rate_hz, sample_count),
+            Waveform::Pulse => get_pulse_sample(self.rate_hz, DEFAULT_DUTY_CYCLE, 0.0,
+                                                sample_count),
+        };
+
+        // lfo_sample is in -1.0..1.0, so the gain swings between 1.0 and 1.0 - depth
+        sample * (1.0 - self.depth * (0.5 - 0.5 * lfo_sample))
+    }
+}
+
+#[allow(dead_code)]
+pub(crate) fn default_tremolo() -> Tremolo {
+    TremoloBuilder::default().build().unwrap()
+}
+
+#[cfg(test)]
+mod test_tremolo {
+    use crate::audio_gen::oscillator::Waveform;
+    use crate::common::constants::SAMPLE_RATE;
+    use crate::common::float_utils::assert_float_eq;
+    use crate::effect::tremolo::TremoloBuilder;
+
+    static RATE_HZ: f32 = 5.0;
+
+    fn one_cycle_of_gains(depth: f32, waveform: Waveform) -> Vec<f32> {
+        let tremolo = TremoloBuilder::default()
+            .rate_hz(RATE_HZ)
+            .depth(depth)
+            .waveform(waveform)
+            .build().unwrap();
+        let num_samples = (SAMPLE_RATE / RATE_HZ) as u64;
+        (0..num_samples).map(|i| tremolo.apply_effect(1.0, i)).collect()
+    }
+
+    #[test]
+    fn test_zero_depth_is_transparent() {
+        for gain in one_cycle_of_gains(0.0, Waveform::Sine) {
+            assert_float_eq(gain, 1.0);
+        }
+    }
+
+    #[test]
+    fn test_full_depth_gates_at_troughs() {
+        for waveform in [Waveform::Sine, Waveform::Triangle, Waveform::Square] {
+            let gains = one_cycle_of_gains(1.0, waveform);
+            let min_gain = gains.iter().cloned().fold(f32::MAX, f32::min);
+            let max_gain = gains.iter().cloned().fold(f32::MIN, f32::max);
+            assert!(min_gain.abs() < 1e-4);
+            assert_float_eq(max_gain, 1.0);
+        }
+    }
+
+    #[test]
+    fn test_invalid_depth() {
+        assert!(TremoloBuilder::default().depth(1.5).build().is_err());
+        assert!(TremoloBuilder::default().rate_hz(0.0).build().is_err());
+    }
+}
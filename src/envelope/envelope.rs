@@ -1,15 +1,105 @@
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
 use derive_builder::Builder;
 
 use crate::envelope::envelope_pair::EnvelopePair;
 
+static CURVE_EXPONENT: i32 = 3;
+
+// Shape of the volume change within each envelope segment
+//   Linear: straight line between the segment's start and end volume
+//   Exponential: convex, rises slowly then quickly and falls quickly then slowly, which gives
+//     natural sounding percussive decays
+//   Logarithmic: concave, rises quickly then slowly and falls slowly then quickly
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum EnvelopeCurve {
+    Linear,
+    Exponential,
+    Logarithmic,
+}
+
+impl FromStr for EnvelopeCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "linear" | "lin" => Ok(EnvelopeCurve::Linear),
+            "exponential" | "exp" => Ok(EnvelopeCurve::Exponential),
+            "logarithmic" | "log" => Ok(EnvelopeCurve::Logarithmic),
+            _ => Err(format!("Unknown envelope curve: {}", s)),
+        }
+    }
+}
+
+// Named envelope shapes for common sounds, positions and volumes are normalized like any Envelope
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum EnvelopePreset {
+    // near instant attack and a fast decay to a low sustain
+    Pluck,
+    // slow attack into a long, steady sustain
+    Pad,
+    // full level for the whole note, with only a click-free attack and release
+    Organ,
+    // instant attack and a very short decay to silence
+    Percussive,
+    // slow rise across most of the note
+    Swell,
+}
+
+impl FromStr for EnvelopePreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pluck" => Ok(EnvelopePreset::Pluck),
+            "pad" => Ok(EnvelopePreset::Pad),
+            "organ" => Ok(EnvelopePreset::Organ),
+            "percussive" | "perc" => Ok(EnvelopePreset::Percussive),
+            "swell" => Ok(EnvelopePreset::Swell),
+            _ => Err(format!("Unknown envelope preset: {}", s)),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl EnvelopePreset {
+    pub(crate) fn to_envelope(self) -> Envelope {
+        let (attack, decay, sustain, curve) = match self {
+            EnvelopePreset::Pluck => (
+                EnvelopePair(0.01, 1.0), EnvelopePair(0.2, 0.3), EnvelopePair(0.6, 0.15),
+                EnvelopeCurve::Exponential),
+            EnvelopePreset::Pad => (
+                EnvelopePair(0.35, 0.85), EnvelopePair(0.45, 0.75), EnvelopePair(0.8, 0.75),
+                EnvelopeCurve::Linear),
+            EnvelopePreset::Organ => (
+                EnvelopePair(0.01, 1.0), EnvelopePair(0.02, 1.0), EnvelopePair(0.98, 1.0),
+                EnvelopeCurve::Linear),
+            EnvelopePreset::Percussive => (
+                EnvelopePair(0.005, 1.0), EnvelopePair(0.1, 0.2), EnvelopePair(0.3, 0.0),
+                EnvelopeCurve::Exponential),
+            EnvelopePreset::Swell => (
+                EnvelopePair(0.7, 1.0), EnvelopePair(0.8, 0.9), EnvelopePair(0.9, 0.9),
+                EnvelopeCurve::Logarithmic),
+        };
+
+        EnvelopeBuilder::default()
+            .attack(attack)
+            .decay(decay)
+            .sustain(sustain)
+            .curve(curve)
+            .build().unwrap()
+    }
+}
+
 // State for an ADSR envelope. User sets the position from the start where attack, decay, sustain
 // and release end, and the volume level at each of these positions. The envelope defaults to
 // starting from (0, 0) and connecting from their to start, and connecting from the position
 // of the end of sustain to the end of the note, which is the release.
 #[allow(dead_code)]
-#[derive(Builder, Clone, Copy, Debug, Hash)]
+#[derive(Builder, Clone, Copy, Debug)]
 #[builder(build_fn(validate = "Self::validate"))]
 pub(crate) struct Envelope {
     #[builder(default = "EnvelopePair(0.0, 0.0)")]
@@ -25,6 +115,15 @@ pub(crate) struct Envelope {
 
     #[builder(default = "EnvelopePair(1.0, 0.0)")]
     pub(crate) release: EnvelopePair,
+
+    #[builder(default = "EnvelopeCurve::Linear")]
+    pub(crate) curve: EnvelopeCurve,
+
+    // Optional (start, end) normalized positions of a region that repeats while the note is held,
+    // instead of moving through it once. The region must end before the release starts at the end
+    // of sustain, and once the note reaches the release the envelope plays it out normally.
+    #[builder(default = "None")]
+    pub(crate) loop_segment: Option<(f32, f32)>,
 }
 
 impl EnvelopeBuilder {
@@ -49,6 +148,13 @@ impl EnvelopeBuilder {
             return Err(
                 String::from("Envelope: sustain position and volume must be between 0.0 and 1.0"));
         }
+        let loop_segment = self.loop_segment.unwrap_or(None);
+        if let Some((loop_start, loop_end)) = loop_segment {
+            if loop_start < 0.0 || loop_start >= loop_end || loop_end > sustain.0 {
+                return Err(String::from(
+                    "Envelope: loop segment must be a non-empty region ending before the release"));
+            }
+        }
 
         Ok(Envelope {
             start: EnvelopePair(0.0, 0.0),
@@ -56,6 +162,8 @@ impl EnvelopeBuilder {
             decay,
             sustain,
             release: EnvelopePair(1.0, 0.0),
+            curve: self.curve.unwrap_or(EnvelopeCurve::Linear),
+            loop_segment,
         })
     }
 }
@@ -68,11 +176,38 @@ pub (crate) fn default_envelope() -> Envelope {
         decay: EnvelopePair(0.51, 1.0),
         sustain: EnvelopePair(0.98, 1.0),
         release: EnvelopePair(1.0, 0.0),
+        curve: EnvelopeCurve::Linear,
+        loop_segment: None,
     }
 }
 
 #[allow(dead_code)]
 impl Envelope {
+    pub(crate) fn preset(preset: EnvelopePreset) -> Envelope {
+        preset.to_envelope()
+    }
+
+    // An envelope from ADSR times for a note of a given length, attack to full volume and decay to
+    // the sustain level, which holds until the release. Times that don't fit in the note are
+    // shortened in proportion.
+    pub(crate) fn from_adsr_ms(attack_ms: f32, decay_ms: f32, sustain_level: f32, release_ms: f32,
+                               note_duration_ms: f32) -> Envelope {
+        let (attack_ms, decay_ms, release_ms) =
+            (attack_ms.max(0.0), decay_ms.max(0.0), release_ms.max(0.0));
+        let note_duration_ms = note_duration_ms.max(f32::EPSILON);
+        let total_ms = attack_ms + decay_ms + release_ms;
+        let scale = if total_ms > note_duration_ms { note_duration_ms / total_ms } else { 1.0 };
+        let sustain_level = sustain_level.clamp(0.0, 1.0);
+        let attack_position = (scale * attack_ms / note_duration_ms).min(1.0);
+        let decay_position = (scale * (attack_ms + decay_ms) / note_duration_ms).min(1.0);
+        let sustain_position = (1.0 - scale * release_ms / note_duration_ms).max(decay_position);
+
+        EnvelopeBuilder::default()
+            .attack(EnvelopePair(attack_position, 1.0))
+            .decay(EnvelopePair(decay_position, sustain_level))
+            .sustain(EnvelopePair(sustain_position, sustain_level))
+            .build().unwrap()
+    }
 
     // TODO MOVE BOTH TO FREE FUNCTIONS AND JUST TAKE THE ADSR VALUES AS ARGS SO CAN BE
     //  A CLOSURE IN THE gen_notes CALLBACK in audio_gen
@@ -99,7 +234,17 @@ impl Envelope {
     }
 
     pub(crate) fn apply_effect(&self, sample: f32, position: f32) -> f32 {
-       sample * self.volume_factor(position)
+       sample * self.volume_factor(self.looped_position(position))
+    }
+
+    // Map a position inside the loop segment back into it, so the segment repeats until the
+    // release starts. Positions before the loop and in the release are unchanged.
+    pub(crate) fn looped_position(&self, position: f32) -> f32 {
+        match self.loop_segment {
+            Some((loop_start, loop_end)) if position >= loop_start && position < self.sustain.0 =>
+                loop_start + (position - loop_start) % (loop_end - loop_start),
+            _ => position,
+        }
     }
 
     fn volume_for_segment_position(&self, start: EnvelopePair, end: EnvelopePair,
@@ -109,11 +254,25 @@ impl Envelope {
         let end_position = end.0;
         let end_volume= end.1;
 
-        let slope = (end_volume - start_volume) / (end_position - start_position);
-        let intercept = start_volume - (slope * start_position);
-        // y = mx + b, where slope = m and intercept = b, so b = y - mx
-        // so the value along the line for any position
-        slope * position + intercept
+        if self.curve == EnvelopeCurve::Linear {
+            let slope = (end_volume - start_volume) / (end_position - start_position);
+            let intercept = start_volume - (slope * start_position);
+            // y = mx + b, where slope = m and intercept = b, so b = y - mx
+            // so the value along the line for any position
+            return slope * position + intercept;
+        }
+
+        // fraction of the way through the segment, reshaped by the curve
+        let fraction = ((position - start_position) / (end_position - start_position))
+            .clamp(0.0, 1.0);
+        let is_rising = end_volume >= start_volume;
+        let is_convex = (self.curve == EnvelopeCurve::Exponential) == is_rising;
+        let shaped_fraction = if is_convex {
+            fraction.powi(CURVE_EXPONENT)
+        } else {
+            1.0 - (1.0 - fraction).powi(CURVE_EXPONENT)
+        };
+        start_volume + (end_volume - start_volume) * shaped_fraction
     }
 }
 
@@ -123,14 +282,30 @@ impl PartialEq for Envelope {
             self.attack == other.attack &&
             self.decay == other.decay &&
             self.sustain == other.sustain &&
-            self.release == other.release
+            self.release == other.release &&
+            self.curve == other.curve &&
+            self.loop_segment.map(|(start, end)| EnvelopePair(start, end)) ==
+                other.loop_segment.map(|(start, end)| EnvelopePair(start, end))
     }
 }
 impl Eq for Envelope {}
 
+impl Hash for Envelope {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.start.hash(state);
+        self.attack.hash(state);
+        self.decay.hash(state);
+        self.sustain.hash(state);
+        self.release.hash(state);
+        self.curve.hash(state);
+        self.loop_segment.map(|(start, end)| (start.to_bits(), end.to_bits())).hash(state);
+    }
+}
+
 #[cfg(test)]
 mod test_envelope {
-    use crate::envelope::envelope::EnvelopeBuilder;
+    use std::str::FromStr;
+    use crate::envelope::envelope::{Envelope, EnvelopeBuilder, EnvelopeCurve, EnvelopePreset};
     use crate::envelope::envelope_pair::EnvelopePair;
     use crate::common::float_utils::assert_float_eq;
 
@@ -150,4 +325,163 @@ mod test_envelope {
         assert_float_eq(envelope.volume_factor(0.8), 0.325);
         assert_float_eq(envelope.volume_factor(1.0), 0.0);
     }
+
+    fn envelope_with_curve(curve: EnvelopeCurve) -> crate::envelope::envelope::Envelope {
+        EnvelopeBuilder::default()
+            .attack(EnvelopePair(0.1, 1.0))
+            .decay(EnvelopePair(0.5, 0.2))
+            .sustain(EnvelopePair(0.8, 0.2))
+            .curve(curve)
+            .build().unwrap()
+    }
+
+    #[test]
+    fn test_default_curve_is_linear() {
+        let envelope = EnvelopeBuilder::default()
+            .attack(EnvelopePair(0.1, 1.0))
+            .decay(EnvelopePair(0.5, 0.2))
+            .sustain(EnvelopePair(0.8, 0.2))
+            .build().unwrap();
+        assert_eq!(envelope.curve, EnvelopeCurve::Linear);
+    }
+
+    #[test]
+    fn test_decay_curve_shapes() {
+        let linear = envelope_with_curve(EnvelopeCurve::Linear);
+        let exponential = envelope_with_curve(EnvelopeCurve::Exponential);
+        let logarithmic = envelope_with_curve(EnvelopeCurve::Logarithmic);
+
+        // halfway through the decay segment, from 1.0 down to 0.2
+        let position = 0.3;
+        assert_float_eq(linear.volume_factor(position), 0.6);
+        assert!(exponential.volume_factor(position) < linear.volume_factor(position));
+        assert!(logarithmic.volume_factor(position) > linear.volume_factor(position));
+
+        // all curves agree at the segment boundaries
+        for envelope in [&linear, &exponential, &logarithmic] {
+            assert_float_eq(envelope.volume_factor(0.1), 1.0);
+            assert_float_eq(envelope.volume_factor(0.5), 0.2);
+        }
+    }
+
+    #[test]
+    fn test_attack_curve_shapes() {
+        let linear = envelope_with_curve(EnvelopeCurve::Linear);
+        let exponential = envelope_with_curve(EnvelopeCurve::Exponential);
+
+        // exponential attack rises slowly at first
+        assert!(exponential.volume_factor(0.05) < linear.volume_factor(0.05));
+    }
+
+    #[test]
+    fn test_presets_are_valid() {
+        let presets = [EnvelopePreset::Pluck, EnvelopePreset::Pad, EnvelopePreset::Organ,
+            EnvelopePreset::Percussive, EnvelopePreset::Swell];
+        for preset in presets {
+            let envelope = Envelope::preset(preset);
+            assert_eq!(envelope, preset.to_envelope());
+            // rebuilding from the preset's own values passes validation
+            assert!(EnvelopeBuilder::default()
+                .attack(envelope.attack)
+                .decay(envelope.decay)
+                .sustain(envelope.sustain)
+                .curve(envelope.curve)
+                .build().is_ok());
+            assert!(envelope.attack.0 < envelope.release.0);
+            assert_float_eq(envelope.volume_factor(0.0), 0.0);
+            assert_float_eq(envelope.volume_factor(1.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_preset_shapes() {
+        let pluck = Envelope::preset(EnvelopePreset::Pluck);
+        let pad = Envelope::preset(EnvelopePreset::Pad);
+        let organ = Envelope::preset(EnvelopePreset::Organ);
+        let swell = Envelope::preset(EnvelopePreset::Swell);
+
+        // pluck reaches its peak long before a pad or swell does
+        assert!(pluck.attack.0 < pad.attack.0);
+        assert!(pad.attack.0 < swell.attack.0);
+        // pluck decays well below its peak, organ holds full level
+        assert!(pluck.sustain.1 < pluck.attack.1);
+        assert_float_eq(organ.volume_factor(0.5), 1.0);
+    }
+
+    #[test]
+    fn test_preset_from_str() {
+        assert_eq!(EnvelopePreset::from_str("pluck").unwrap(), EnvelopePreset::Pluck);
+        assert_eq!(EnvelopePreset::from_str("Pad").unwrap(), EnvelopePreset::Pad);
+        assert_eq!(EnvelopePreset::from_str("perc").unwrap(), EnvelopePreset::Percussive);
+        assert!(EnvelopePreset::from_str("bell").is_err());
+    }
+
+    fn looping_envelope() -> Envelope {
+        EnvelopeBuilder::default()
+            .attack(EnvelopePair(0.1, 1.0))
+            .decay(EnvelopePair(0.3, 0.5))
+            .sustain(EnvelopePair(0.9, 0.5))
+            .loop_segment(Some((0.1, 0.3)))
+            .build().unwrap()
+    }
+
+    #[test]
+    fn test_loop_segment_wraps() {
+        let envelope = looping_envelope();
+
+        // before the loop and in the release, positions are unchanged
+        assert_float_eq(envelope.looped_position(0.05), 0.05);
+        assert_float_eq(envelope.looped_position(0.95), 0.95);
+
+        // inside the loop region, and each time around it after its end
+        assert_float_eq(envelope.looped_position(0.2), 0.2);
+        assert_float_eq(envelope.looped_position(0.35), 0.15);
+        assert_float_eq(envelope.looped_position(0.45), 0.25);
+        assert_float_eq(envelope.looped_position(0.75), 0.15);
+
+        // so the decay repeats, 0.35 is back halfway down the first decay
+        assert_float_eq(envelope.apply_effect(1.0, 0.35), envelope.volume_factor(0.15));
+        assert_float_eq(envelope.apply_effect(1.0, 0.2), 0.75);
+        assert_float_eq(envelope.apply_effect(1.0, 0.95), 0.25);
+    }
+
+    #[test]
+    fn test_no_loop_segment() {
+        let envelope = EnvelopeBuilder::default()
+            .attack(EnvelopePair(0.1, 1.0))
+            .decay(EnvelopePair(0.3, 0.5))
+            .sustain(EnvelopePair(0.9, 0.5))
+            .build().unwrap();
+        assert_eq!(envelope.loop_segment, None);
+        assert_float_eq(envelope.looped_position(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_invalid_loop_segment() {
+        let builder = || EnvelopeBuilder::default()
+            .attack(EnvelopePair(0.1, 1.0))
+            .decay(EnvelopePair(0.3, 0.5))
+            .sustain(EnvelopePair(0.9, 0.5))
+            .clone();
+        assert!(builder().loop_segment(Some((0.3, 0.1))).build().is_err());
+        assert!(builder().loop_segment(Some((0.5, 0.95))).build().is_err());
+        assert!(builder().loop_segment(Some((0.2, 0.2))).build().is_err());
+    }
+
+    #[test]
+    fn test_from_adsr_ms() {
+        let envelope = Envelope::from_adsr_ms(100.0, 200.0, 0.5, 300.0, 1000.0);
+        assert_eq!(envelope.attack, EnvelopePair(0.1, 1.0));
+        assert_eq!(envelope.decay, EnvelopePair(0.3, 0.5));
+        assert_eq!(envelope.sustain, EnvelopePair(0.7, 0.5));
+        assert_float_eq(envelope.volume_factor(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_from_adsr_ms_shortens_times_to_fit_the_note() {
+        let envelope = Envelope::from_adsr_ms(100.0, 100.0, 0.5, 200.0, 200.0);
+        assert_eq!(envelope.attack, EnvelopePair(0.25, 1.0));
+        assert_eq!(envelope.decay, EnvelopePair(0.5, 0.5));
+        assert_eq!(envelope.sustain, EnvelopePair(0.5, 0.5));
+    }
 }
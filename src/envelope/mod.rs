@@ -1,2 +1,3 @@
 pub mod envelope;
-pub mod envelope_pair;
\ No newline at end of file
+pub mod envelope_pair;
+pub mod multi_stage_envelope;
\ No newline at end of file
@@ -0,0 +1,119 @@
+use derive_builder::Builder;
+
+// An envelope defined by any number of (position, volume) breakpoints, both normalized to
+// 0.0..1.0. The volume is linearly interpolated between neighboring breakpoints, held at the first
+// breakpoint's volume before it and at the last breakpoint's volume after it.
+#[allow(dead_code)]
+#[derive(Builder, Clone, Debug, PartialEq)]
+#[builder(build_fn(validate = "Self::validate"))]
+pub(crate) struct MultiStageEnvelope {
+    pub(crate) breakpoints: Vec<(f32, f32)>,
+}
+
+impl MultiStageEnvelopeBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let breakpoints = match &self.breakpoints {
+            Some(breakpoints) => breakpoints,
+            None => return Err(String::from("MultiStageEnvelope: breakpoints must be set")),
+        };
+
+        if breakpoints.len() < 2 {
+            return Err(
+                String::from("MultiStageEnvelope: at least two breakpoints are required"));
+        }
+        for (position, volume) in breakpoints.iter() {
+            if *position < 0.0 || *position > 1.0 || *volume < 0.0 || *volume > 1.0 {
+                return Err(String::from(
+                    "MultiStageEnvelope: breakpoint position and volume must be between 0.0 and 1.0"));
+            }
+        }
+        if breakpoints.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+            return Err(
+                String::from("MultiStageEnvelope: breakpoints must be in order of position"));
+        }
+        Ok(())
+    }
+}
+
+#[allow(dead_code)]
+impl MultiStageEnvelope {
+    pub(crate) fn volume_factor(&self, position: f32) -> f32 {
+        let first = self.breakpoints[0];
+        let last = self.breakpoints[self.breakpoints.len() - 1];
+        if position <= first.0 {
+            return first.1;
+        }
+        if position >= last.0 {
+            return last.1;
+        }
+
+        // find the segment containing position and interpolate along it
+        for pair in self.breakpoints.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            if position < end.0 {
+                let segment_length = end.0 - start.0;
+                if segment_length <= 0.0 {
+                    return end.1;
+                }
+                let fraction = (position - start.0) / segment_length;
+                return start.1 + (end.1 - start.1) * fraction;
+            }
+        }
+        last.1
+    }
+
+    pub(crate) fn apply_effect(&self, sample: f32, position: f32) -> f32 {
+        sample * self.volume_factor(position)
+    }
+}
+
+#[cfg(test)]
+mod test_multi_stage_envelope {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::envelope::multi_stage_envelope::MultiStageEnvelopeBuilder;
+
+    #[test]
+    fn test_five_point_envelope() {
+        let envelope = MultiStageEnvelopeBuilder::default()
+            .breakpoints(vec![(0.0, 0.0), (0.1, 1.0), (0.3, 0.4), (0.7, 0.6), (1.0, 0.0)])
+            .build().unwrap();
+
+        // at the breakpoints
+        assert_float_eq(envelope.volume_factor(0.0), 0.0);
+        assert_float_eq(envelope.volume_factor(0.1), 1.0);
+        assert_float_eq(envelope.volume_factor(0.3), 0.4);
+        assert_float_eq(envelope.volume_factor(0.7), 0.6);
+        assert_float_eq(envelope.volume_factor(1.0), 0.0);
+
+        // between the breakpoints
+        assert_float_eq(envelope.volume_factor(0.05), 0.5);
+        assert_float_eq(envelope.volume_factor(0.2), 0.7);
+        assert_float_eq(envelope.volume_factor(0.5), 0.5);
+        assert_float_eq(envelope.volume_factor(0.85), 0.3);
+
+        assert_float_eq(envelope.apply_effect(0.5, 0.1), 0.5);
+    }
+
+    #[test]
+    fn test_holds_outside_breakpoints() {
+        let envelope = MultiStageEnvelopeBuilder::default()
+            .breakpoints(vec![(0.2, 0.5), (0.8, 1.0)])
+            .build().unwrap();
+
+        assert_float_eq(envelope.volume_factor(0.0), 0.5);
+        assert_float_eq(envelope.volume_factor(1.0), 1.0);
+    }
+
+    #[test]
+    fn test_invalid_breakpoints() {
+        assert!(MultiStageEnvelopeBuilder::default()
+            .breakpoints(vec![(0.0, 0.0)])
+            .build().is_err());
+        assert!(MultiStageEnvelopeBuilder::default()
+            .breakpoints(vec![(0.5, 0.0), (0.2, 1.0)])
+            .build().is_err());
+        assert!(MultiStageEnvelopeBuilder::default()
+            .breakpoints(vec![(0.0, 0.0), (1.0, 1.5)])
+            .build().is_err());
+    }
+}
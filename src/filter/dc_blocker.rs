@@ -0,0 +1,46 @@
+use derive_builder::Builder;
+
+static DEFAULT_POLE: f32 = 0.995;
+
+/// One-pole DC-blocking high-pass filter
+///
+/// Computes y[n] = x[n] - x[n-1] + pole * y[n-1], which has a zero at DC and passes audible
+/// frequencies essentially unchanged. The closer the pole is to 1.0 the lower the cutoff, the
+/// default puts it at roughly 35 Hz at 44.1 kHz.
+#[derive(Builder, Clone, Debug, PartialEq)]
+#[builder(build_fn(validate = "Self::validate"))]
+pub(crate) struct DcBlocker {
+    #[builder(default = "DEFAULT_POLE")]
+    pub(crate) pole: f32,
+
+    /// Previous input sample
+    #[builder(setter(skip), default = "0.0")]
+    x_prev: f32,
+
+    /// Previous output sample
+    #[builder(setter(skip), default = "0.0")]
+    y_prev: f32,
+}
+
+impl DcBlockerBuilder {
+    fn validate(&self) -> Result<(), String> {
+        let pole = self.pole.unwrap_or(DEFAULT_POLE);
+        if pole <= 0.0 || pole >= 1.0 {
+            return Err(String::from("DcBlocker: pole must be between 0.0 and 1.0"));
+        }
+        Ok(())
+    }
+}
+
+impl DcBlocker {
+    pub(crate) fn apply_effect(&mut self, sample: f32, _sample_clock: f32) -> f32 {
+        let output = sample - self.x_prev + self.pole * self.y_prev;
+        self.x_prev = sample;
+        self.y_prev = output;
+        output
+    }
+}
+
+pub(crate) fn default_dc_blocker() -> DcBlocker {
+    DcBlockerBuilder::default().build().unwrap()
+}
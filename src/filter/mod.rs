@@ -2,6 +2,7 @@ pub mod low_pass_filter;
 pub mod high_pass_filter;
 pub mod band_pass_filter;
 pub mod notch_filter;
+pub mod dc_blocker;
 
 #[cfg(test)]
 mod test_filter;
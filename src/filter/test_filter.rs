@@ -4,6 +4,7 @@ mod filter_tests {
     use super::super::high_pass_filter::*;
     use super::super::band_pass_filter::*;
     use super::super::notch_filter::*;
+    use super::super::dc_blocker::*;
 
     #[test]
     fn test_basic_low_pass_filter_creation() {
@@ -262,4 +263,28 @@ mod filter_tests {
         assert_eq!(filter.resonance, 0.5);
         assert_eq!(filter.mix, 0.8);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_dc_blocker_removes_offset() {
+        let mut dc_blocker = default_dc_blocker();
+
+        // A sine riding on a 0.5 offset, the mean of the settled output is near zero
+        let outputs: Vec<f32> = (0..44100)
+            .map(|i| 0.5 + 0.25 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 44100.0).sin())
+            .map(|sample| dc_blocker.apply_effect(sample, 0.0))
+            .collect();
+        let settled = &outputs[22050..];
+        let mean = settled.iter().sum::<f32>() / settled.len() as f32;
+        assert!(mean.abs() < 1e-3);
+
+        // The audible component passes nearly unchanged
+        let peak = settled.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+        assert!((peak - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_dc_blocker_invalid_pole() {
+        assert!(DcBlockerBuilder::default().pole(1.0).build().is_err());
+        assert!(DcBlockerBuilder::default().pole(0.0).build().is_err());
+    }
+}
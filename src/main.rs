@@ -15,12 +15,138 @@ pub mod dsl;
 pub mod compositions;
 pub mod tui;
 
+use crate::audio_gen::audio_gen::WavChannels;
+use crate::common::rng;
+use crate::composition::comp_utils::render_dsl_file;
 use crate::compositions::dsl_1;
+use crate::tui::RoscoTuiApp;
 // use crate::compositions::computer_punk_001;
 // use crate::compositions::computer_punk_003;
 
+static USAGE: &str = "usage: rosco [render [--mono] [--seed SEED] INPUT_DSL OUTPUT_WAV | tui]";
+
 fn main() {
-    dsl_1::play();
-    // computer_punk_001::play();
-    // computer_punk_003::play();
+    let args: Vec<String> = std::env::args().collect();
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+// With no command, plays the default composition
+fn run(args: &[String]) -> Result<(), String> {
+    match args.get(1).map(String::as_str) {
+        None => {
+            dsl_1::play();
+            // computer_punk_001::play();
+            // computer_punk_003::play();
+            Ok(())
+        }
+        Some("render") => {
+            // --mono sums the mix to one channel, to check it collapses cleanly, and --seed
+            // renders noise the same every time
+            let mut channels = WavChannels::Stereo;
+            let mut paths = &args[2..];
+            loop {
+                match paths {
+                    [flag, rest @ ..] if flag == "--mono" => {
+                        channels = WavChannels::Mono;
+                        paths = rest;
+                    }
+                    [flag, seed, rest @ ..] if flag == "--seed" => {
+                        rng::set_seed(seed.parse::<u64>().map_err(|_| String::from(USAGE))?);
+                        paths = rest;
+                    }
+                    _ => break,
+                }
+            }
+            match paths {
+                [input_path, output_path] => render_dsl_file(input_path, output_path, channels),
+                _ => Err(String::from(USAGE)),
+            }
+        }
+        Some("tui") => {
+            let runtime = tokio::runtime::Runtime::new().map_err(|e| e.to_string())?;
+            runtime.block_on(async {
+                RoscoTuiApp::new()?.run().await
+            }).map_err(|e| e.to_string())
+        }
+        Some(_) => Err(String::from(USAGE)),
+    }
+}
+
+#[cfg(test)]
+mod test_main {
+    use crate::run;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_render() {
+        // Removed with everything in it when the test ends
+        let dir = tempfile::tempdir().unwrap();
+        let input_path = dir.path().join("tiny.dsl");
+        let output_path = dir.path().join("tiny.wav");
+        std::fs::write(&input_path, "FixedTimeNoteSequence dur Quarter tempo 120 num_steps 4\n\
+                                     osc:sine:440.0:0.5:0\n").unwrap();
+
+        run(&args(&["rosco", "render", input_path.to_str().unwrap(),
+                    output_path.to_str().unwrap()])).unwrap();
+        assert!(std::fs::metadata(&output_path).unwrap().len() > 0);
+    }
+
+    #[test]
+    fn test_render_mono() {
+        let dir = tempfile::tempdir().unwrap();
+        let input_path = dir.path().join("tiny.dsl");
+        let output_path = dir.path().join("tiny_mono.wav");
+        std::fs::write(&input_path, "FixedTimeNoteSequence dur Quarter tempo 120 num_steps 4\n\
+                                     osc:sine:440.0:0.5:0\n").unwrap();
+
+        run(&args(&["rosco", "render", "--mono", input_path.to_str().unwrap(),
+                    output_path.to_str().unwrap()])).unwrap();
+        assert_eq!(hound::WavReader::open(&output_path).unwrap().spec().channels, 1);
+    }
+
+    #[test]
+    fn test_render_with_seed_is_reproducible() {
+        let dir = tempfile::tempdir().unwrap();
+        let input_path = dir.path().join("noise.dsl");
+        std::fs::write(&input_path, "FixedTimeNoteSequence dur Quarter tempo 120 num_steps 4\n\
+                                     osc:noise:440.0:0.5:0\n").unwrap();
+
+        let render = |seed: &str, name: &str| {
+            let output_path = dir.path().join(name);
+            run(&args(&["rosco", "render", "--seed", seed, "--mono",
+                        input_path.to_str().unwrap(), output_path.to_str().unwrap()])).unwrap();
+            std::fs::read(&output_path).unwrap()
+        };
+        assert_eq!(render("5", "first.wav"), render("5", "second.wav"));
+        assert_ne!(render("5", "first.wav"), render("6", "other.wav"));
+    }
+
+    #[test]
+    fn test_bad_arguments() {
+        assert!(run(&args(&["rosco", "render", "only_input.dsl"])).unwrap_err().contains("usage"));
+        assert!(run(&args(&["rosco", "render", "--mono", "only_input.dsl"])).unwrap_err()
+            .contains("usage"));
+        assert!(run(&args(&["rosco", "render", "--seed", "x", "in.dsl", "out.wav"])).unwrap_err()
+            .contains("usage"));
+        assert!(run(&args(&["rosco", "play"])).unwrap_err().contains("usage"));
+        assert!(run(&args(&["rosco", "render", "missing.dsl", "out.wav"])).is_err());
+    }
+
+    #[test]
+    fn test_render_to_missing_directory_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let input_path = dir.path().join("tiny.dsl");
+        let output_path = dir.path().join("missing").join("tiny.wav");
+        std::fs::write(&input_path, "FixedTimeNoteSequence dur Quarter tempo 120 num_steps 4\n\
+                                     osc:sine:440.0:0.5:0\n").unwrap();
+
+        assert!(run(&args(&["rosco", "render", input_path.to_str().unwrap(),
+                            output_path.to_str().unwrap()])).is_err());
+    }
 }
@@ -10,6 +10,9 @@ pub(crate) static EIGHTH: f32 = 0.125;
 pub(crate) static SIXTEENTH: f32 = 0.0625;
 pub(crate) static THIRTY_SECOND: f32 = 0.03125;
 pub(crate) static SIXTY_FOURTH: f32 = 0.015625;
+// A dot adds half the note's value, a triplet fits three notes in the time of two
+pub(crate) static DOTTED: f32 = 1.5;
+pub(crate) static TRIPLET: f32 = 2.0 / 3.0;
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub(crate) enum DurationType {
@@ -20,6 +23,17 @@ pub(crate) enum DurationType {
     Sixteenth,
     ThirtySecond,
     SixtyFourth,
+    DottedWhole,
+    DottedHalf,
+    DottedQuarter,
+    DottedEighth,
+    DottedSixteenth,
+    DottedThirtySecond,
+    TripletHalf,
+    TripletQuarter,
+    TripletEighth,
+    TripletSixteenth,
+    TripletThirtySecond,
 }
 
 impl DurationType {
@@ -32,6 +46,17 @@ impl DurationType {
             DurationType::Sixteenth => SIXTEENTH,
             DurationType::ThirtySecond => THIRTY_SECOND,
             DurationType::SixtyFourth => SIXTY_FOURTH,
+            DurationType::DottedWhole => WHOLE * DOTTED,
+            DurationType::DottedHalf => HALF * DOTTED,
+            DurationType::DottedQuarter => QUARTER * DOTTED,
+            DurationType::DottedEighth => EIGHTH * DOTTED,
+            DurationType::DottedSixteenth => SIXTEENTH * DOTTED,
+            DurationType::DottedThirtySecond => THIRTY_SECOND * DOTTED,
+            DurationType::TripletHalf => HALF * TRIPLET,
+            DurationType::TripletQuarter => QUARTER * TRIPLET,
+            DurationType::TripletEighth => EIGHTH * TRIPLET,
+            DurationType::TripletSixteenth => SIXTEENTH * TRIPLET,
+            DurationType::TripletThirtySecond => THIRTY_SECOND * TRIPLET,
         }
     }
 }
@@ -48,6 +73,17 @@ impl FromStr for DurationType {
             "Sixteenth" => Ok(DurationType::Sixteenth),
             "ThirtySecond" => Ok(DurationType::ThirtySecond),
             "SixtyFourth" => Ok(DurationType::SixtyFourth),
+            "DottedWhole" => Ok(DurationType::DottedWhole),
+            "DottedHalf" => Ok(DurationType::DottedHalf),
+            "DottedQuarter" => Ok(DurationType::DottedQuarter),
+            "DottedEighth" => Ok(DurationType::DottedEighth),
+            "DottedSixteenth" => Ok(DurationType::DottedSixteenth),
+            "DottedThirtySecond" => Ok(DurationType::DottedThirtySecond),
+            "TripletHalf" => Ok(DurationType::TripletHalf),
+            "TripletQuarter" => Ok(DurationType::TripletQuarter),
+            "TripletEighth" => Ok(DurationType::TripletEighth),
+            "TripletSixteenth" => Ok(DurationType::TripletSixteenth),
+            "TripletThirtySecond" => Ok(DurationType::TripletThirtySecond),
             "1" => Ok(DurationType::Whole),
             "1/2" => Ok(DurationType::Half),
             "1/4" => Ok(DurationType::Quarter),
@@ -108,6 +144,7 @@ impl Eq for Duration {}
 #[cfg(test)]
 mod test_duration {
     use super::*;
+    use crate::common::float_utils::assert_float_eq;
 
     #[test]
     fn test_duration_builder_success() {
@@ -175,6 +212,30 @@ mod test_duration {
         assert_eq!(DurationType::SixtyFourth.to_factor(), SIXTY_FOURTH);
     }
 
+    #[test]
+    fn test_dotted_duration_type_to_factor() {
+        assert_float_eq(DurationType::DottedQuarter.to_factor(), QUARTER * 1.5);
+        assert_float_eq(DurationType::DottedEighth.to_factor(), 0.1875);
+        // a dotted half lasts as long as three quarters
+        assert_float_eq(DurationType::DottedHalf.to_factor(), 3.0 * QUARTER);
+    }
+
+    #[test]
+    fn test_triplet_duration_type_to_factor() {
+        assert_float_eq(DurationType::TripletEighth.to_factor(), EIGHTH * 2.0 / 3.0);
+        // three triplet eighths fill a quarter
+        assert_float_eq(3.0 * DurationType::TripletEighth.to_factor(), QUARTER);
+        assert_float_eq(3.0 * DurationType::TripletQuarter.to_factor(), HALF);
+    }
+
+    #[test]
+    fn test_duration_type_from_str() {
+        assert_eq!(DurationType::from_str("DottedEighth"), Ok(DurationType::DottedEighth));
+        assert_eq!(DurationType::from_str("TripletSixteenth"), Ok(DurationType::TripletSixteenth));
+        assert_eq!(DurationType::from_str("1/4"), Ok(DurationType::Quarter));
+        assert!(DurationType::from_str("Dotted").is_err());
+    }
+
     #[test]
     fn test_duration_equality() {
         let duration1 = DurationBuilder::default()
@@ -1,19 +1,28 @@
 use std::collections::HashMap;
 
 use nodi::midly;
-use nodi::midly::num::{u28, u4, u7, u15};
+use nodi::midly::num::{u24, u28, u4, u7, u15};
 
 use crate::note::constants;
 use crate::note::note::NoteBuilder;
 use crate::note::playback_note::{NoteType, PlaybackNote, PlaybackNoteBuilder};
 use crate::note::sampled_note::SampledNoteBuilder;
+use crate::note::scales::frequency_to_nearest_midi;
+use crate::sequence::fixed_time_note_sequence::{FixedTimeNoteSequence,
+                                                FixedTimeNoteSequenceBuilder};
 use crate::sequence::note_sequence_trait::{AppendNote, BuilderWrapper};
 use crate::track::track::{Track, TrackBuilder};
+use crate::track::track_grid::{TrackGrid, TrackGridBuilder};
 
 #[allow(dead_code)]
 pub(crate) static DEFAULT_BPM: u8 = 120;
 #[allow(dead_code)]
 static MSECS_PER_MIN: f32 = 60000.0;
+#[allow(dead_code)]
+static MICROSECS_PER_MIN: u32 = 60000000;
+// Pulses (ticks) per quarter note in exported files, fine enough to keep ms timing close
+#[allow(dead_code)]
+pub(crate) static DEFAULT_EXPORT_PPQ: u16 = 480;
 
 // The MIDI standard doesn't support connecting NoteOn and NoteOff events, nor NoteOn events with
 // > 0 velocity and NoteOn events on the same pitch with 0 velocity, which are treated as NoteOff.
@@ -32,21 +41,111 @@ pub(crate) fn midi_file_to_tracks<
     SequenceBuilderType: BuilderWrapper<SequenceType>
 >
 (file_name: &str, note_type: NoteType) -> Vec<Track<SequenceType>> {
-
-    let mut tracks: Vec<Track<SequenceType>> = Vec::new();
     let data = std::fs::read(file_name).unwrap();
     let midi = midly::Smf::parse(&data).unwrap();
+    smf_to_tracks::<SequenceType, SequenceBuilderType>(&midi, note_type)
+}
+
+// Reads a Standard MIDI File into a grid with one track per MIDI channel, ordered by channel
+#[allow(dead_code)]
+pub(crate) fn import_midi_file(path: &str) -> Result<TrackGrid<FixedTimeNoteSequence>, String> {
+    let data = std::fs::read(path)
+        .map_err(|e| format!("Failed to read MIDI file {}: {}", path, e))?;
+    let midi = midly::Smf::parse(&data)
+        .map_err(|e| format!("Failed to parse MIDI file {}: {}", path, e))?;
+    if !matches!(midi.header.timing, midly::Timing::Metrical(_)) {
+        return Err(format!("MIDI file {} uses timecode timing, only metrical timing is supported",
+                           path));
+    }
+
+    let tracks = smf_to_tracks::<FixedTimeNoteSequence, FixedTimeNoteSequenceBuilder>(
+        &midi, NoteType::Oscillator);
+    TrackGridBuilder::default()
+        .tracks(tracks)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+// Writes a Standard MIDI File with one MIDI track per grid track, on channels in track order. Each
+// oscillator note becomes the nearest MIDI note with velocity from its volume. Rests and sampled
+// notes have no pitch, so they are left as gaps. Times are converted to ticks at DEFAULT_BPM and
+// DEFAULT_EXPORT_PPQ.
+#[allow(dead_code)]
+pub(crate) fn export_midi_file(grid: &TrackGrid<FixedTimeNoteSequence>, path: &str)
+        -> Result<(), String> {
+    let ticks_per_ms = get_ticks_per_ms(u15::from(DEFAULT_EXPORT_PPQ), DEFAULT_BPM);
+    let ms_to_ticks = |ms: f32| (ms.max(0.0) * ticks_per_ms).round() as u32;
+
+    let mut midi_tracks = Vec::new();
+    for (track_index, track) in grid.tracks.iter().enumerate() {
+        let channel = u4::from((track_index % 16) as u8);
+
+        // (tick, is note on, key, velocity), note offs sort before note ons at the same tick so a
+        // repeated pitch is released before it is struck again
+        let mut note_events: Vec<(u32, bool, u8, u8)> = Vec::new();
+        for playback_note in track.sequence.get_all_notes() {
+            if playback_note.note_type != NoteType::Oscillator || playback_note.note.volume <= 0.0 {
+                continue;
+            }
+            let key = frequency_to_nearest_midi(playback_note.note.frequency);
+            let velocity = (playback_note.note.volume * 127.0).round().clamp(1.0, 127.0) as u8;
+            note_events.push((ms_to_ticks(playback_note.note.start_time_ms), true, key, velocity));
+            note_events.push((ms_to_ticks(playback_note.note.end_time_ms), false, key, 0));
+        }
+        note_events.sort_by_key(|(tick, is_note_on, _, _)| (*tick, *is_note_on));
+
+        let mut events = Vec::new();
+        if track_index == 0 {
+            events.push(midly::TrackEvent {
+                delta: u28::from(0),
+                kind: midly::TrackEventKind::Meta(midly::MetaMessage::Tempo(
+                    u24::from(MICROSECS_PER_MIN / DEFAULT_BPM as u32))),
+            });
+        }
+        let mut last_tick = 0;
+        for (tick, is_note_on, key, velocity) in note_events {
+            let message = if is_note_on {
+                midly::MidiMessage::NoteOn { key: u7::from(key), vel: u7::from(velocity) }
+            } else {
+                midly::MidiMessage::NoteOff { key: u7::from(key), vel: u7::from(0) }
+            };
+            events.push(midly::TrackEvent {
+                delta: u28::from(tick - last_tick),
+                kind: midly::TrackEventKind::Midi { channel, message },
+            });
+            last_tick = tick;
+        }
+        events.push(midly::TrackEvent {
+            delta: u28::from(0),
+            kind: midly::TrackEventKind::Meta(midly::MetaMessage::EndOfTrack),
+        });
+        midi_tracks.push(events);
+    }
+
+    let mut midi = midly::Smf::new(midly::Header::new(
+        midly::Format::Parallel, midly::Timing::Metrical(u15::from(DEFAULT_EXPORT_PPQ))));
+    midi.tracks = midi_tracks;
+    midi.save(path).map_err(|e| format!("Failed to write MIDI file {}: {}", path, e))
+}
+
+fn smf_to_tracks<
+    SequenceType: AppendNote + Clone,
+    SequenceBuilderType: BuilderWrapper<SequenceType>
+>
+(midi: &midly::Smf, note_type: NoteType) -> Vec<Track<SequenceType>> {
+    let mut tracks: Vec<Track<SequenceType>> = Vec::new();
 
     // Map key is channel and pitch, so there can be more tha one notes in process on at channel
     //  but only one per pitch. This is of course a bug / limitation.
     let mut track_notes_map: HashMap<NoteKey, PlaybackNote>= HashMap::new();
     let mut track_sequence_map: HashMap<u4, SequenceType> = HashMap::new();
 
-    let bpm = get_beats_per_minute(&midi);
-    let ticks_per_beat = get_ticks_per_beat(&midi);
+    let bpm = get_beats_per_minute(midi);
+    let ticks_per_beat = get_ticks_per_beat(midi);
     let ticks_per_ms: f32 = get_ticks_per_ms(ticks_per_beat, bpm);
-    let mut ticks_since_start: u28 = u28::from(0);
     for track in midi.tracks.iter() {
+        // Each MIDI track's deltas count from the start of the file
+        let mut ticks_since_start: u28 = u28::from(0);
         for event in track.iter() {
             match event {
                 // delta is the number of ticks since the last Midi event
@@ -151,7 +250,10 @@ pub(crate) fn midi_file_to_tracks<
         }
     }
 
-    for (midi_channel, sequence) in track_sequence_map.iter() {
+    let mut channels: Vec<&u4> = track_sequence_map.keys().collect();
+    channels.sort();
+    for midi_channel in channels {
+        let sequence = &track_sequence_map[midi_channel];
         let track= TrackBuilder::default()
             .num(midi_channel.as_int() as i16)
             .sequence(sequence.clone())
@@ -223,3 +325,94 @@ fn handle_note_off<SequenceType: AppendNote>(note_key: NoteKey,
     track_sequence_map.get_mut(&note_key.channel).unwrap().append_note(playback_note);
     track_notes_map.remove(&note_key);
 }
+
+#[cfg(test)]
+mod test_midi {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::midi::midi::{export_midi_file, import_midi_file};
+    use crate::note::constants::PITCH_TO_FREQ_HZ;
+    use crate::note::note::NoteBuilder;
+    use crate::note::playback_note::{playback_rest_note, PlaybackNote, PlaybackNoteBuilder};
+    use crate::sequence::fixed_time_note_sequence::FixedTimeNoteSequenceBuilder;
+    use crate::sequence::note_sequence_trait::{AppendNote, BuilderWrapper};
+    use crate::track::track::TrackBuilder;
+    use crate::track::track_grid::TrackGridBuilder;
+
+    static TEST_MIDI_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"),
+                                          "/src/midi/test_data/test_notes.mid");
+
+    #[test]
+    fn test_import_midi_file() {
+        let track_grid = import_midi_file(TEST_MIDI_FILE).unwrap();
+        assert_eq!(track_grid.tracks.len(), 2);
+
+        // channel 0 plays C4, E4, G4 as quarter notes at 120 bpm
+        let notes = track_grid.tracks[0].sequence.get_all_notes();
+        assert_eq!(notes.len(), 3);
+        let mut notes: Vec<_> = notes.iter().map(|pb_note| pb_note.note.clone()).collect();
+        notes.sort_by(|a, b| a.start_time_ms.total_cmp(&b.start_time_ms));
+        assert_float_eq(notes[0].frequency, PITCH_TO_FREQ_HZ[60] as f32);
+        assert_float_eq(notes[0].start_time_ms, 0.0);
+        assert_float_eq(notes[0].end_time_ms, 500.0);
+        assert_float_eq(notes[2].start_time_ms, 1000.0);
+        assert_float_eq(notes[2].end_time_ms, 1500.0);
+
+        // channel 1 is on its own MIDI track, so its times also start from 0
+        let notes = track_grid.tracks[1].sequence.get_all_notes();
+        assert_eq!(notes.len(), 1);
+        assert_float_eq(notes[0].note.start_time_ms, 0.0);
+        assert_float_eq(notes[0].note.end_time_ms, 1000.0);
+    }
+
+    fn playback_note(pitch: usize, start_time_ms: f32, end_time_ms: f32) -> PlaybackNote {
+        PlaybackNoteBuilder::default()
+            .note(NoteBuilder::default()
+                .frequency(PITCH_TO_FREQ_HZ[pitch] as f32)
+                .volume(0.5)
+                .start_time_ms(start_time_ms)
+                .end_time_ms(end_time_ms)
+                .build().unwrap())
+            .playback_start_time_ms(start_time_ms)
+            .playback_end_time_ms(end_time_ms)
+            .build().unwrap()
+    }
+
+    #[test]
+    fn test_export_midi_file_round_trip() {
+        let mut melody = FixedTimeNoteSequenceBuilder::new();
+        melody.append_note(playback_note(60, 0.0, 250.0));
+        melody.append_note(playback_rest_note(250.0, 500.0));
+        melody.append_note(playback_note(62, 500.0, 750.0));
+        melody.append_note(playback_note(64, 750.0, 1000.0));
+        let mut bass = FixedTimeNoteSequenceBuilder::new();
+        bass.append_note(playback_note(36, 0.0, 1000.0));
+        let grid = TrackGridBuilder::default()
+            .tracks(vec![
+                TrackBuilder::default().num(1).sequence(melody).build().unwrap(),
+                TrackBuilder::default().num(2).sequence(bass).build().unwrap(),
+            ])
+            .build().unwrap();
+
+        let path = std::env::temp_dir()
+            .join(format!("rosco_test_export_{}.mid", std::process::id()));
+        let path = path.to_str().unwrap();
+        export_midi_file(&grid, path).unwrap();
+        let imported = import_midi_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // the rest is a gap, not a note
+        assert_eq!(imported.tracks.len(), 2);
+        let mut notes = imported.tracks[0].sequence.get_all_notes();
+        assert_eq!(notes.len(), 3);
+        notes.sort_by(|a, b| a.note.start_time_ms.total_cmp(&b.note.start_time_ms));
+        assert_float_eq(notes[1].note.frequency, PITCH_TO_FREQ_HZ[62] as f32);
+        assert_float_eq(notes[1].note.start_time_ms, 500.0);
+        assert_float_eq(notes[1].note.end_time_ms, 750.0);
+        assert_eq!(imported.tracks[1].sequence.get_all_notes().len(), 1);
+    }
+
+    #[test]
+    fn test_import_missing_midi_file() {
+        assert!(import_midi_file("/nonexistent/rosco_test.mid").is_err());
+    }
+}
@@ -8,6 +8,11 @@ pub(crate) static DEFAULT_FREQUENCY: f32 = 440.0;
 #[allow(dead_code)]
 pub(crate) static DEFAULT_DURATION: f32 = 0.0; // actually, it is
 pub(crate) static DEFAULT_VOLUME: f32 = 1.0;
+pub(crate) static MAX_VELOCITY: u8 = 127;
+pub(crate) static DEFAULT_UNISON_VOICES: u8 = 1;
+pub(crate) static DEFAULT_MODULATOR_RATIO: f32 = 1.0;
+// Length of the linear fade in and out at the ends of each note that keeps them from clicking
+pub(crate) static DEFAULT_FADE_MS: f32 = 5.0;
 
 // Array mapping index positions in piano / MIDI Western scale, with A4 = 440.0 Hz, to frequencies
 pub(crate) static PITCH_TO_FREQ_HZ: [f64; 128] = [
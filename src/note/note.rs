@@ -2,13 +2,15 @@ use std::hash::{Hash, Hasher};
 
 use derive_builder::Builder;
 
-use crate::audio_gen::oscillator::Waveform;
+use crate::audio_gen::oscillator::{Waveform, DEFAULT_DUTY_CYCLE};
 use crate::common::float_utils::float_eq;
-use crate::note::constants::{DEFAULT_FREQUENCY, DEFAULT_VOLUME, INIT_START_TIME};
+use crate::note::constants::{DEFAULT_FREQUENCY, DEFAULT_MODULATOR_RATIO, DEFAULT_UNISON_VOICES,
+                             DEFAULT_VOLUME, INIT_START_TIME};
 use crate::note::note_trait::BuilderWrapper;
 
 #[allow(dead_code)]
 #[derive(Builder, Clone, Debug)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub(crate) struct Note {
     #[builder(default = "DEFAULT_FREQUENCY")]
     pub(crate) frequency: f32,
@@ -24,6 +26,49 @@ pub(crate) struct Note {
 
     #[builder(default = "Vec::new()")]
     pub(crate) waveforms: Vec<Waveform>,
+
+    // Fraction of each period a Pulse waveform is high, 0.0 to 1.0
+    #[builder(default = "DEFAULT_DUTY_CYCLE")]
+    pub(crate) duty_cycle: f32,
+
+    // Number of oscillators stacked for each waveform, spread evenly across -detune_cents
+    // to +detune_cents around the note frequency and summed at equal gain
+    #[builder(default = "DEFAULT_UNISON_VOICES")]
+    pub(crate) unison_voices: u8,
+
+    #[builder(default = "0.0")]
+    pub(crate) detune_cents: f32,
+
+    // Fraction of a cycle, 0.0 to 1.0, the oscillators start at
+    #[builder(default = "0.0")]
+    pub(crate) phase_offset: f32,
+
+    // 2-operator FM. A sine modulator at modulator_ratio times the note frequency modulates the
+    // phase of each oscillator, modulation_index is the peak deviation in radians and 0.0 turns
+    // FM off.
+    #[builder(default = "DEFAULT_MODULATOR_RATIO")]
+    pub(crate) modulator_ratio: f32,
+
+    #[builder(default = "0.0")]
+    pub(crate) modulation_index: f32,
+}
+
+impl NoteBuilder {
+    fn validate(&self) -> Result<(), String> {
+        if self.unison_voices.unwrap_or(DEFAULT_UNISON_VOICES) == 0 {
+            return Err(String::from("Note: unison_voices must be at least 1"));
+        }
+        if !(0.0..=1.0).contains(&self.phase_offset.unwrap_or(0.0)) {
+            return Err(String::from("Note: phase_offset must be between 0.0 and 1.0"));
+        }
+        if self.modulator_ratio.unwrap_or(DEFAULT_MODULATOR_RATIO) <= 0.0 {
+            return Err(String::from("Note: modulator_ratio must be greater than 0.0"));
+        }
+        if self.modulation_index.unwrap_or(0.0) < 0.0 {
+            return Err(String::from("Note: modulation_index must not be negative"));
+        }
+        Ok(())
+    }
 }
 
 pub(crate) fn default_note() -> Note {
@@ -78,6 +123,15 @@ impl Note {
     pub(crate) fn duration_position(&self, cur_time_ms: f32) -> f32 {
         (cur_time_ms - self.start_time_ms) / self.duration_ms()
     }
+
+    // Frequency of one unison voice, voices are indexed from 0 to unison_voices - 1
+    pub(crate) fn voice_frequency(&self, voice: u8) -> f32 {
+        if self.unison_voices <= 1 {
+            return self.frequency;
+        }
+        let spread = voice as f32 / (self.unison_voices - 1) as f32 * 2.0 - 1.0;
+        self.frequency * 2.0f32.powf(spread * self.detune_cents / 1200.0)
+    }
 }
 
 impl BuilderWrapper<Note> for NoteBuilder {
@@ -88,6 +142,7 @@ impl BuilderWrapper<Note> for NoteBuilder {
 
 #[cfg(test)]
 mod test_note {
+    use crate::common::float_utils::assert_float_eq;
     use crate::note::note::NoteBuilder;
 
     #[test]
@@ -133,6 +188,37 @@ mod test_note {
         assert_eq!(note.duration_position(1000.0), 1.0);
     }
 
+    #[test]
+    fn test_voice_frequency() {
+        let note = setup_note()
+            .frequency(440.0)
+            .unison_voices(3)
+            .detune_cents(1200.0)
+            .build().unwrap();
+        assert_float_eq(note.voice_frequency(0), 220.0);
+        assert_float_eq(note.voice_frequency(1), 440.0);
+        assert_float_eq(note.voice_frequency(2), 880.0);
+
+        let single_voice = setup_note().frequency(440.0).detune_cents(10.0).build().unwrap();
+        assert_float_eq(single_voice.voice_frequency(0), 440.0);
+
+        assert!(setup_note().unison_voices(0).build().is_err());
+    }
+
+    #[test]
+    fn test_invalid_phase_offset() {
+        assert!(setup_note().phase_offset(0.5).build().is_ok());
+        assert!(setup_note().phase_offset(1.5).build().is_err());
+        assert!(setup_note().phase_offset(-0.1).build().is_err());
+    }
+
+    #[test]
+    fn test_invalid_fm_parameters() {
+        assert!(setup_note().modulator_ratio(2.0).modulation_index(3.0).build().is_ok());
+        assert!(setup_note().modulator_ratio(0.0).build().is_err());
+        assert!(setup_note().modulation_index(-1.0).build().is_err());
+    }
+
     fn setup_note() -> NoteBuilder {
         NoteBuilder::default()
             .end_time_ms(1000.0)
@@ -1,8 +1,13 @@
 use derive_builder::Builder;
+use crate::audio_gen::oscillator::NoiseGenerator;
+use crate::common::constants::SAMPLES_PER_MS;
+use crate::effect::bitcrusher::BitCrusher;
 use crate::effect::delay::Delay;
+use crate::effect::distortion::Distortion;
 use crate::envelope::envelope::Envelope;
 use crate::effect::flanger::Flanger;
 use crate::effect::lfo::LFO;
+use crate::effect::tremolo::Tremolo;
 use crate::filter::low_pass_filter::LowPassFilter;
 use crate::note::constants;
 use crate::note::note;
@@ -11,6 +16,7 @@ use crate::note::note_trait::BuilderWrapper;
 use crate::note::sampled_note;
 use crate::note::sampled_note::SampledNote;
 use crate::track::track_effects::{no_op_effects, TrackEffects};
+use crate::tui::audio_engine::pan_gains;
 
 #[allow(dead_code)]
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -48,18 +54,36 @@ pub struct PlaybackNote {
     #[builder(default = "Vec::new()")]
     pub(crate) lfos: Vec<LFO>,
 
+    #[builder(default = "Vec::new()")]
+    pub(crate) tremolos: Vec<Tremolo>,
+
     #[builder(default = "Vec::new()")]
     pub(crate) flangers: Vec<Flanger>,
 
     #[builder(default = "Vec::new()")]
     pub(crate) delays: Vec<Delay>,
 
+    #[builder(default = "Vec::new()")]
+    pub(crate) bitcrushers: Vec<BitCrusher>,
+
+    #[builder(default = "Vec::new()")]
+    pub(crate) distortions: Vec<Distortion>,
+
     #[builder(default = "Vec::new()")]
     pub(crate) filters: Vec<LowPassFilter>,
 
     #[builder(default = "no_op_effects()")]
     pub(crate) track_effects: TrackEffects,
 
+    // MIDI-style velocity 0..=127, scales the enveloped level of the note, 127 is full level
+    #[builder(default = "constants::MAX_VELOCITY")]
+    pub(crate) velocity: u8,
+
+    // Length of the linear fade in from silence at the start of the note and out to silence at
+    // the end, 0.0 turns it off
+    #[builder(default = "constants::DEFAULT_FADE_MS")]
+    pub(crate) fade_ms: f32,
+
     // TODO enforce -1.0..1.0 with builder validator or custom builder
     #[builder(default = "0.0")]
     pub(crate) panning: f32,
@@ -67,6 +91,10 @@ pub struct PlaybackNote {
     // TODO enforce 0 or 1 with builder validator or custom builder
     #[builder(default = "1")]
     pub(crate) num_channels: i8,
+
+    // State for pink and brown noise waveforms
+    #[builder(setter(skip), default = "NoiseGenerator::new()")]
+    pub(crate) noise_generator: NoiseGenerator,
 }
 
 #[allow(dead_code)]
@@ -124,8 +152,58 @@ impl PlaybackNote {
         }
     }
 
+    pub(crate) fn velocity_gain(&self) -> f32 {
+        self.velocity.min(constants::MAX_VELOCITY) as f32 / constants::MAX_VELOCITY as f32
+    }
+
+    // Gain of the fade at sample_count, counted from the start of the playback window. The ramps
+    // follow the note's own start and end as absolute sample offsets, so a note played over
+    // several windows fades in once at its start and out once at its end. Notes without a
+    // playback window aren't faded. On notes shorter than two fades the ramps meet in the middle.
+    pub(crate) fn fade_gain(&self, sample_count: u64) -> f32 {
+        if self.fade_ms <= 0.0 || self.playback_sample_end_time <= self.playback_sample_start_time {
+            return 1.0;
+        }
+        let fade_samples = self.fade_ms * SAMPLES_PER_MS;
+        let to_samples = |time_ms: f32| (time_ms * SAMPLES_PER_MS).floor() as u64;
+        let sample = to_samples(self.playback_start_time_ms) + sample_count;
+        let samples_from_start = sample.saturating_sub(to_samples(self.note_start_time_ms()));
+        let samples_to_end = to_samples(self.note_end_time_ms()).saturating_sub(sample + 1);
+        (samples_from_start.min(samples_to_end) as f32 / fade_samples).min(1.0)
+    }
+
+    // The longest tail of any time-based effect on the note, i.e. how long past the end of the
+    // note offline rendering must continue to avoid truncating it
+    pub(crate) fn effect_tail_ms(&self) -> f32 {
+        let delay_tails = self.delays.iter()
+            .chain(self.track_effects.delays.iter())
+            .map(|delay| delay.tail_duration_ms());
+        let flanger_tails = self.flangers.iter()
+            .chain(self.track_effects.flangers.iter())
+            .map(|flanger| flanger.tail_duration_ms());
+
+        delay_tails.chain(flanger_tails).fold(0.0, f32::max)
+    }
+
     pub(crate) fn apply_effects(&mut self, sample: f32, sample_position: f32,
                                 sample_count: u64) -> f32 {
+        let mut output_sample = self.apply_pre_delay_effects(sample, sample_position, sample_count);
+
+        for delay in self.delays.iter_mut() {
+            output_sample = delay.apply_effect(output_sample, sample_position);
+        }
+
+        for delay in self.track_effects.delays.iter_mut() {
+            output_sample = delay.apply_effect(output_sample, sample_position);
+        }
+
+        self.apply_filters(output_sample, sample_position)
+    }
+
+    // Envelopes, LFOs, tremolos, flangers, bitcrushers and distortions, i.e. all effects applied
+    // before delays
+    fn apply_pre_delay_effects(&mut self, sample: f32, sample_position: f32,
+                               sample_count: u64) -> f32 {
         let mut output_sample = sample;
 
         match self.note_type {
@@ -164,6 +242,9 @@ impl PlaybackNote {
                 }
             }
         }
+
+        // Velocity scales the peak and sustain levels shaped by the envelopes
+        output_sample *= self.velocity_gain();
         
         for lfo in self.lfos.iter() {
             output_sample = lfo.apply_effect(output_sample, sample_count);
@@ -173,6 +254,14 @@ impl PlaybackNote {
             output_sample = lfo.apply_effect(output_sample, sample_count);
         }
 
+        for tremolo in self.tremolos.iter() {
+            output_sample = tremolo.apply_effect(output_sample, sample_count);
+        }
+
+        for tremolo in self.track_effects.tremolos.iter() {
+            output_sample = tremolo.apply_effect(output_sample, sample_count);
+        }
+
         for flanger in self.flangers.iter_mut() {
             output_sample = flanger.apply_effect(output_sample, sample_position);
         }
@@ -180,16 +269,30 @@ impl PlaybackNote {
         for flanger in self.track_effects.flangers.iter_mut() {
             output_sample = flanger.apply_effect(output_sample, sample_position);
         }
-        
-        for delay in self.delays.iter_mut() {
-            output_sample = delay.apply_effect(output_sample, sample_position);
+
+        for bitcrusher in self.bitcrushers.iter_mut() {
+            output_sample = bitcrusher.apply_effect(output_sample, sample_position);
         }
 
-        for delay in self.track_effects.delays.iter_mut() {
-            output_sample = delay.apply_effect(output_sample, sample_position);
+        for bitcrusher in self.track_effects.bitcrushers.iter_mut() {
+            output_sample = bitcrusher.apply_effect(output_sample, sample_position);
         }
 
-        // Apply filters before LFOs
+        for distortion in self.distortions.iter_mut() {
+            output_sample = distortion.apply_effect(output_sample, sample_position);
+        }
+
+        for distortion in self.track_effects.distortions.iter_mut() {
+            output_sample = distortion.apply_effect(output_sample, sample_position);
+        }
+
+        output_sample
+    }
+
+    fn apply_filters(&mut self, sample: f32, sample_position: f32) -> f32 {
+        let mut output_sample = sample;
+
+        // Filters are applied after all other effects
         for filter in self.filters.iter_mut() {
             output_sample = filter.apply_effect(output_sample, sample_position);
         }
@@ -199,30 +302,47 @@ impl PlaybackNote {
 
     pub(crate) fn apply_effects_stereo(&mut self, sample: f32, sample_position: f32,
                                 sample_count: u64) -> (f32, f32) {
-        let mut left = self.apply_effects(sample, sample_position, sample_count);
-        let mut right = self.apply_effects(sample, sample_position, sample_count);
+        self.apply_effects_stereo_frame(sample, sample, sample_position, sample_count)
+    }
 
-        // Apply both per-note and track-level panning
-        let factor = 1.0;
-        if self.panning > 0.0 {
-            left *= factor - (factor * self.panning.cos());
-            right *= factor + (factor * self.panning.sin());
-        } else if self.panning < 0.0 {
-            left *= factor + (factor *self.panning.cos());
-            right *= factor - (factor *self.panning.sin());
+    // As apply_effects_stereo(), for a source that is already stereo
+    pub(crate) fn apply_effects_stereo_frame(&mut self, left: f32, right: f32,
+                                             sample_position: f32,
+                                             sample_count: u64) -> (f32, f32) {
+        let mut left = self.apply_pre_delay_effects(left, sample_position, sample_count);
+        let mut right = self.apply_pre_delay_effects(right, sample_position, sample_count);
+
+        // Delays are applied once per stereo frame so that ping-pong delays can route echoes
+        for delay in self.delays.iter_mut() {
+            (left, right) = delay.apply_effect_stereo(left, right, sample_position);
         }
-        if self.track_effects.panning > 0.0 {
-            left *= factor - (factor * self.track_effects.panning.cos());
-            right *= factor + (factor * self.track_effects.panning.sin());
-        } else if self.track_effects.panning < 0.0 {
-            left *= factor + (factor * self.track_effects.panning.cos());
-            right *= factor - (factor * self.track_effects.panning.sin());
+
+        for delay in self.track_effects.delays.iter_mut() {
+            (left, right) = delay.apply_effect_stereo(left, right, sample_position);
+        }
+
+        left = self.apply_filters(left, sample_position);
+        right = self.apply_filters(right, sample_position);
+
+        // Apply both per-note and track-level panning
+        for panning in [self.panning, self.track_effects.panning] {
+            let (gain_l, gain_r) = unity_center_pan_gains(panning);
+            left *= gain_l;
+            right *= gain_r;
         }
         
         (left, right)
     }
 }
 
+// Constant-power (left, right) gains for a pan from -1.0, hard left, to 1.0, hard right, scaled
+// so a centered note keeps its level in both channels. The gains move smoothly through center,
+// so a pan a hair off it sounds centered too.
+pub(crate) fn unity_center_pan_gains(panning: f32) -> (f32, f32) {
+    let (gain_l, gain_r) = pan_gains(panning);
+    (std::f32::consts::SQRT_2 * gain_l, std::f32::consts::SQRT_2 * gain_r)
+}
+
 #[allow(dead_code)]
 pub(crate) fn default_playback_note() -> PlaybackNote {
     PlaybackNoteBuilder::default().build().unwrap()
@@ -254,12 +374,14 @@ impl BuilderWrapper<PlaybackNote> for PlaybackNoteBuilder {
 
 #[cfg(test)]
 mod test_playback_note {
+    use crate::common::float_utils::assert_float_eq;
     use crate::envelope::envelope;
     use crate::effect::{delay, flanger};
     use crate::effect::lfo;
     use crate::note::constants;
     use crate::note::note;
-    use crate::note::playback_note::PlaybackNoteBuilder;
+    use crate::note::playback_note::{unity_center_pan_gains, PlaybackNoteBuilder};
+    use crate::track::track_effects::TrackEffectsBuilder;
 
     #[test]
     fn test_default_playback_note() {
@@ -306,6 +428,51 @@ mod test_playback_note {
         assert_eq!(playback_note.delays, vec![delay::default_delay()]);
     }
 
+    #[test]
+    fn test_effect_tail_ms() {
+        let playback_note = PlaybackNoteBuilder::default().build().unwrap();
+        assert_eq!(playback_note.effect_tail_ms(), 0.0);
+
+        let short_delay = delay::DelayBuilder::default()
+            .interval_ms(50.0)
+            .duration_ms(10.0)
+            .num_repeats(2)
+            .build().unwrap();
+        let track_effects = TrackEffectsBuilder::default()
+            .delays(vec![delay::default_delay()])
+            .build().unwrap();
+        let playback_note = PlaybackNoteBuilder::default()
+            .delays(vec![short_delay])
+            .flangers(vec![flanger::default_flanger()])
+            .track_effects(track_effects)
+            .build().unwrap();
+        assert_eq!(playback_note.effect_tail_ms(), delay::default_delay().tail_duration_ms());
+    }
+
+    #[test]
+    fn test_velocity_scales_peak_output() {
+        let mut loud_note = PlaybackNoteBuilder::default()
+            .envelopes(vec![envelope::default_envelope()])
+            .playback_sample_end_time(100)
+            .velocity(127)
+            .build().unwrap();
+        let mut soft_note = PlaybackNoteBuilder::default()
+            .envelopes(vec![envelope::default_envelope()])
+            .playback_sample_end_time(100)
+            .velocity(64)
+            .build().unwrap();
+
+        let loud_peak = (0..100)
+            .map(|i| loud_note.apply_effects(1.0, i as f32, i).abs())
+            .fold(0.0, f32::max);
+        let soft_peak = (0..100)
+            .map(|i| soft_note.apply_effects(1.0, i as f32, i).abs())
+            .fold(0.0, f32::max);
+
+        assert!(loud_peak > 0.0);
+        assert_float_eq(soft_peak / loud_peak, 64.0 / 127.0);
+    }
+
     #[test]
     fn test_playback_note_with_filters() {
         let playback_note = PlaybackNoteBuilder::default()
@@ -313,4 +480,37 @@ mod test_playback_note {
             .build().unwrap();
         assert_eq!(playback_note.filters.len(), 1);
     }
+
+    #[test]
+    fn test_panning_a_hair_off_center_is_centered() {
+        let mut playback_note = PlaybackNoteBuilder::default()
+            .panning(1e-6)
+            .track_effects(TrackEffectsBuilder::default().panning(-1e-6).build().unwrap())
+            .build().unwrap();
+        let (left, right) = playback_note.apply_effects_stereo(0.5, 0.0, 0);
+        assert_float_eq(left, 0.5);
+        assert_float_eq(right, 0.5);
+    }
+
+    #[test]
+    fn test_pan_law_keeps_power_across_the_field() {
+        let (left, right) = unity_center_pan_gains(0.0);
+        assert_float_eq(left, 1.0);
+        assert_float_eq(right, 1.0);
+        let (left, right) = unity_center_pan_gains(1.0);
+        assert!(left.abs() < 1e-6);
+        assert_float_eq(right, std::f32::consts::SQRT_2);
+        let (left, right) = unity_center_pan_gains(-1.0);
+        assert_float_eq(left, std::f32::consts::SQRT_2);
+        assert!(right.abs() < 1e-6);
+
+        let mut last_right = -1.0;
+        for step in -10..=10 {
+            let (left, right) = unity_center_pan_gains(step as f32 / 10.0);
+            assert!((left * left + right * right - 2.0).abs() < 1e-5);
+            // Moving right only ever brings up the right channel
+            assert!(right > last_right);
+            last_right = right;
+        }
+    }
 }
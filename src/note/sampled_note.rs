@@ -1,10 +1,32 @@
+use std::f32::consts::FRAC_PI_2;
+use std::sync::Arc;
+
 use derive_builder::Builder;
-use crate::common::constants::SAMPLE_RATE;
+use crate::common::constants::SAMPLES_PER_MS;
 
-use crate::note::constants::{DEFAULT_VOLUME, INIT_START_TIME};
+use crate::note::constants::{DEFAULT_FREQUENCY, DEFAULT_VOLUME, INIT_START_TIME};
 use crate::note::note_trait::BuilderWrapper;
 
-pub(crate) const BUF_STORAGE_SIZE: usize = (SAMPLE_RATE as usize * 2) as usize;
+// Samples were once played as raw 16-bit integers rather than normalized to -1.0..1.0, so a
+// volume set then plays at the same level now times this
+pub(crate) const RAW_INT16_VOLUME_SCALE: f32 = 32768.0;
+
+// How a sample plays once it reaches its end
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum SampleLoop {
+    // Play the sample once and then silence
+    #[default]
+    OneShot,
+    // Repeat the sample for as long as the note lasts, its end crossfading into its start
+    Loop { crossfade_ms: f32 },
+}
+
+// Gains of the outgoing and incoming sides of an equal-power crossfade at position 0.0..1.0 of
+// the way through. Their squares sum to one, so the level holds steady across the loop point.
+pub(crate) fn crossfade_gains(position: f32) -> (f32, f32) {
+    let angle = position.clamp(0.0, 1.0) * FRAC_PI_2;
+    (angle.cos(), angle.sin())
+}
 
 #[allow(dead_code)]
 #[derive(Builder, Clone, Debug, PartialEq)]
@@ -13,8 +35,12 @@ pub(crate) struct SampledNote {
     #[builder(default = "String::new()")]
     pub(crate) file_path: String,
     
+    // Frames in the sample, each a sample for every channel
     #[builder(default = "0", setter(skip))]
     pub(crate) buf_size: usize,
+
+    #[builder(default = "1", setter(skip))]
+    pub(crate) num_channels: u16,
     
     #[builder(default = "0", setter(skip))]
     pub(crate) sample_index: usize,
@@ -28,8 +54,20 @@ pub(crate) struct SampledNote {
     #[builder(default = "INIT_START_TIME")]
     pub(crate) end_time_ms: f32,
 
-    #[builder(default = "Vec::with_capacity(BUF_STORAGE_SIZE)", setter(skip))]
-    sample_buf: Vec<f32>,
+    #[builder(default = "SampleLoop::OneShot")]
+    pub(crate) loop_mode: SampleLoop,
+
+    // The pitch the sample plays at, and the pitch it was recorded at
+    #[builder(default = "DEFAULT_FREQUENCY")]
+    pub(crate) frequency: f32,
+
+    #[builder(default = "DEFAULT_FREQUENCY")]
+    pub(crate) root_frequency: f32,
+
+    // Interleaved when there is more than one channel. Shared with the sample cache and every
+    // note playing the same file, and copied only when a note's samples are changed.
+    #[builder(default = "Arc::from([])", setter(skip))]
+    sample_buf: Arc<[f32]>,
 }
 
 #[allow(dead_code)]
@@ -38,13 +76,75 @@ impl SampledNote {
         self.end_time_ms - self.start_time_ms
     }
 
+    // How fast the sample is read, 2.0 playing it an octave up in half the time
+    pub(crate) fn playback_rate(&self) -> f32 {
+        if self.root_frequency <= 0.0 {
+            return 1.0;
+        }
+        self.frequency / self.root_frequency
+    }
+
+    // The next sample, both channels of a stereo sample mixed to one
     pub(crate) fn next_sample(&mut self) -> f32 {
-        if self.sample_index < self.buf_size {
-            let sample = self.sample_buf[self.sample_index];
-            self.sample_index += 1;
-            sample
+        let (left, right) = self.next_frame();
+        (left + right) / 2.0
+    }
+
+    // sample_index counts frames played, which the playback rate turns into a position in the
+    // sample, interpolating linearly between the frames either side of it. A mono sample plays
+    // the same on both channels.
+    pub(crate) fn next_frame(&mut self) -> (f32, f32) {
+        let position = self.sample_index as f64 * self.playback_rate() as f64;
+        self.sample_index += 1;
+        let index = position.floor() as usize;
+        let fraction = (position - position.floor()) as f32;
+        let (left, right) = self.looped_frame_at(index);
+        if fraction == 0.0 {
+            return (left, right);
+        }
+        let (next_left, next_right) = self.looped_frame_at(index + 1);
+        (left + fraction * (next_left - left), right + fraction * (next_right - right))
+    }
+
+    fn frame_at(&self, index: usize) -> (f32, f32) {
+        let channels = self.num_channels.max(1) as usize;
+        let left = self.sample_buf[index * channels];
+        let right = if channels > 1 { self.sample_buf[index * channels + 1] } else { left };
+        (left, right)
+    }
+
+    // For a Loop, the tail of the sample fades out as its head fades in, and playback then carries
+    // on from the end of the head, so every pass after the first runs from there to the end of
+    // the tail
+    fn looped_frame_at(&self, index: usize) -> (f32, f32) {
+        let crossfade_ms = match self.loop_mode {
+            SampleLoop::OneShot => {
+                return if index < self.buf_size { self.frame_at(index) } else { (0.0, 0.0) };
+            }
+            SampleLoop::Loop { crossfade_ms } => crossfade_ms,
+        };
+        if self.buf_size == 0 {
+            return (0.0, 0.0);
+        }
+        // At most half the sample, so the head and tail don't overlap
+        let crossfade_samples = ((crossfade_ms * SAMPLES_PER_MS) as usize).min(self.buf_size / 2);
+        let crossfade_start = self.buf_size - crossfade_samples;
+        let index = if index < self.buf_size {
+            index
+        } else {
+            crossfade_samples + (index - self.buf_size) % crossfade_start
+        };
+
+        if index < crossfade_start {
+            self.frame_at(index)
         } else {
-            0.0
+            let head_index = index - crossfade_start;
+            let (gain_out, gain_in) =
+                crossfade_gains(head_index as f32 / crossfade_samples as f32);
+            let (tail_left, tail_right) = self.frame_at(index);
+            let (head_left, head_right) = self.frame_at(head_index);
+            (gain_out * tail_left + gain_in * head_left,
+             gain_out * tail_right + gain_in * head_right)
         }
     }
     
@@ -56,28 +156,42 @@ impl SampledNote {
 
     // TODO remove unused arg buf_size
     pub(crate) fn set_sample_buf(&mut self, samples: &[f32]) {
-        self.sample_buf = samples.try_into().unwrap();
-        self.buf_size = samples.len();
+        self.set_interleaved_sample_buf(samples, 1);
+    }
+
+    pub(crate) fn set_interleaved_sample_buf(&mut self, samples: &[f32], num_channels: u16) {
+        self.set_shared_sample_buf(Arc::from(samples), num_channels);
+    }
+
+    // Play these samples without copying them
+    pub(crate) fn set_shared_sample_buf(&mut self, samples: Arc<[f32]>, num_channels: u16) {
+        self.num_channels = num_channels.max(1);
+        self.buf_size = samples.len() / self.num_channels as usize;
+        self.sample_buf = samples;
         self.sample_index = 0;
     }
 
+    // Copies the samples, so only for building a sample up a little at a time
     pub(crate) fn append_sample(&mut self, sample: f32) {
-        self.sample_buf.push(sample);
+        self.sample_buf = self.sample_buf.iter().copied().chain([sample]).collect();
         self.buf_size += 1;
     }
 
+    // Reverses the order of the frames, keeping each frame's channels in place
     pub(crate) fn reverse(&mut self) {
-        self.sample_buf.reverse();
+        let channels = self.num_channels as usize;
+        self.sample_buf = self.sample_buf.chunks(channels).rev().flatten().copied().collect();
     }
 
     pub(crate) fn chopped(&self, num_segments: usize) -> Vec<SampledNote> {
         let mut chopped_notes = Vec::with_capacity(num_segments);
         let segment_size = self.buf_size / num_segments;
+        let channels = self.num_channels as usize;
         for i in 0..num_segments {
-            let start = i * segment_size;
-            let end = (i + 1) * segment_size;
+            let start = i * segment_size * channels;
+            let end = (i + 1) * segment_size * channels;
             let mut chopped_note = self.clone();
-            chopped_note.sample_buf = self.sample_buf[start..end].to_vec();
+            chopped_note.sample_buf = Arc::from(&self.sample_buf[start..end]);
             chopped_note.buf_size = segment_size;
             chopped_notes.push(chopped_note);
         }
@@ -87,17 +201,22 @@ impl SampledNote {
     // TODO Support other algorithms besides linear interpolation, which is implemented here
     pub(crate) fn stretched(&self, stretch_factor: u8) -> SampledNote {
         let mut stretched_note: SampledNote = self.clone();
+        let channels = self.num_channels as usize;
         let stretched_buf_size = self.buf_size * stretch_factor as usize;
-        stretched_note.sample_buf = Vec::with_capacity(stretched_buf_size);
-        stretched_note.buf_size = stretched_buf_size;
+        let mut stretched_buf = Vec::with_capacity(stretched_buf_size * channels);
         for i in 0..self.buf_size - 1 {
-            let start = self.sample_buf[i];
-            let end = self.sample_buf[i + 1];
-            let step = (end - start) / stretch_factor as f32;
             for j in 0..stretch_factor {
-                stretched_note.sample_buf.push(start + j as f32 * step);
+                for channel in 0..channels {
+                    let start = self.sample_buf[i * channels + channel];
+                    let end = self.sample_buf[(i + 1) * channels + channel];
+                    let step = (end - start) / stretch_factor as f32;
+                    stretched_buf.push(start + j as f32 * step);
+                }
             }
         }
+        // The last frame has nothing to interpolate toward, so it is left off
+        stretched_note.buf_size = stretched_buf.len() / channels;
+        stretched_note.sample_buf = Arc::from(stretched_buf);
 
         stretched_note
     }
@@ -116,29 +235,35 @@ impl SampledNoteBuilder {
         let volume = self.volume.unwrap_or(DEFAULT_VOLUME);
         let start_time_ms = self.start_time_ms.unwrap_or(INIT_START_TIME);
         let end_time_ms = self.end_time_ms.unwrap_or(INIT_START_TIME);
+        let loop_mode = self.loop_mode.unwrap_or_default();
+        let frequency = self.frequency.unwrap_or(DEFAULT_FREQUENCY);
+        let root_frequency = self.root_frequency.unwrap_or(DEFAULT_FREQUENCY);
 
-        let mut sample_buf: Vec<f32> = Vec::with_capacity(crate::note::sampled_note::BUF_STORAGE_SIZE);
+        let mut sample_buf: Arc<[f32]> = Arc::from([]);
+        let mut num_channels = 1;
         
         // Only try to read audio file if file_path is provided and not empty
         if let Some(file_path) = &self.file_path {
             if !file_path.is_empty() {
-                let sample_data =
-                    crate::audio_gen::audio_gen::read_audio_file(file_path).into_boxed_slice();
-                for sample in sample_data.iter() {
-                    sample_buf.push(*sample as f32);
-                }
+                let sample_data = crate::audio_gen::audio_gen::read_cached_audio_file(file_path);
+                sample_buf = sample_data.samples;
+                num_channels = sample_data.num_channels.max(1);
             }
         }
-        let buf_size = sample_buf.len();
+        let buf_size = sample_buf.len() / num_channels as usize;
         
         Ok(
             SampledNote {
                 file_path: self.file_path.take().unwrap_or_default(),
                 buf_size,
+                num_channels,
                 sample_index,
                 volume,
                 start_time_ms,
                 end_time_ms,
+                loop_mode,
+                frequency,
+                root_frequency,
                 sample_buf,
             }
         )
@@ -148,4 +273,123 @@ impl SampledNoteBuilder {
 #[allow(dead_code)]
 pub(crate) fn default_sample_note() -> SampledNote {
     SampledNoteBuilder::default().build().unwrap()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test_sampled_note {
+    use crate::note::sampled_note::{crossfade_gains, default_sample_note, SampleLoop,
+                                    SampledNote};
+
+    #[test]
+    fn test_crossfade_gains_hold_unity_power() {
+        assert_eq!(crossfade_gains(0.0), (1.0, 0.0));
+        let (gain_out, gain_in) = crossfade_gains(1.0);
+        assert!(gain_out.abs() < 1e-6 && (gain_in - 1.0).abs() < 1e-6);
+        // Equal at the midpoint, each at -3dB
+        let (gain_out, gain_in) = crossfade_gains(0.5);
+        assert!((gain_out - gain_in).abs() < 1e-6);
+        assert!((gain_out - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+
+        for i in 0..=100 {
+            let (gain_out, gain_in) = crossfade_gains(i as f32 / 100.0);
+            assert!((gain_out * gain_out + gain_in * gain_in - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_one_shot_plays_once() {
+        let mut sampled_note = default_sample_note();
+        sampled_note.set_sample_buf(&[0.5; 100]);
+
+        let samples: Vec<f32> = (0..200).map(|_| sampled_note.next_sample()).collect();
+        assert!(samples[..100].iter().all(|sample| *sample == 0.5));
+        assert!(samples[100..].iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn test_loop_sustains_past_the_sample() {
+        let mut sampled_note = default_sample_note();
+        // 1000 samples, the crossfade 1ms or 44 samples
+        let ramp: Vec<f32> = (0..1000).map(|i| i as f32 / 1000.0).collect();
+        sampled_note.set_sample_buf(&ramp);
+        sampled_note.loop_mode = SampleLoop::Loop { crossfade_ms: 1.0 };
+
+        let samples: Vec<f32> = (0..3000).map(|_| sampled_note.next_sample()).collect();
+        assert_eq!(samples[..956], ramp[..956]);
+        // The tail blends into the head, then playback picks up after the head
+        assert_eq!(samples[956], ramp[956]);
+        assert!(samples[999] < ramp[999] * 0.1);
+        assert_eq!(samples[1000], ramp[44]);
+        // Later passes repeat the 956 samples between the head and the end of the tail
+        assert_eq!(samples[1000..1956], samples[1956..2912]);
+    }
+
+    #[test]
+    fn test_crossfade_is_limited_to_half_the_sample() {
+        let mut sampled_note = default_sample_note();
+        sampled_note.set_sample_buf(&[1.0; 10]);
+        sampled_note.loop_mode = SampleLoop::Loop { crossfade_ms: 100.0 };
+
+        let samples: Vec<f32> = (0..100).map(|_| sampled_note.next_sample()).collect();
+        // Half the sample crossfades and equal-power gains peak at sqrt(2) for identical material
+        assert!(samples.iter().all(|sample| *sample >= 1.0 && *sample <= 2.0_f32.sqrt() + 1e-6));
+        assert!(samples[95..].iter().any(|sample| *sample > 1.0));
+    }
+
+    fn pitched_note(frequency: f32, samples: &[f32]) -> SampledNote {
+        let mut sampled_note = default_sample_note();
+        sampled_note.set_sample_buf(samples);
+        sampled_note.root_frequency = 220.0;
+        sampled_note.frequency = frequency;
+        sampled_note
+    }
+
+    // Samples played until the sample runs out
+    fn played_length(sampled_note: &mut SampledNote) -> usize {
+        (0..).take_while(|_| sampled_note.next_sample() != 0.0).count()
+    }
+
+    #[test]
+    fn test_doubling_the_rate_halves_the_length() {
+        let mut root = pitched_note(220.0, &[1.0; 1000]);
+        assert_eq!(root.playback_rate(), 1.0);
+        assert_eq!(played_length(&mut root), 1000);
+
+        let mut octave_up = pitched_note(440.0, &[1.0; 1000]);
+        assert_eq!(octave_up.playback_rate(), 2.0);
+        assert_eq!(played_length(&mut octave_up), 500);
+
+        let mut octave_down = pitched_note(110.0, &[1.0; 1000]);
+        assert_eq!(played_length(&mut octave_down), 2000);
+    }
+
+    #[test]
+    fn test_shifted_playback_interpolates_between_samples() {
+        let ramp: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        // A fifth down, so each sample is read two thirds of the way from the last
+        let mut sampled_note = pitched_note(220.0 * 2.0 / 3.0, &ramp);
+        let samples: Vec<f32> = (0..4).map(|_| sampled_note.next_sample()).collect();
+        for (sample, expected) in samples.iter().zip([0.0, 2.0 / 3.0, 4.0 / 3.0, 2.0]) {
+            assert!((sample - expected).abs() < 1e-4, "{} != {}", sample, expected);
+        }
+    }
+
+    #[test]
+    fn test_stereo_sample_keeps_its_channels() {
+        let mut sampled_note = default_sample_note();
+        // Interleaved left, right
+        sampled_note.set_interleaved_sample_buf(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 2);
+        assert_eq!(sampled_note.buf_size, 3);
+
+        let mut reversed = sampled_note.clone();
+        reversed.reverse();
+        assert_eq!(reversed.next_frame(), (0.5, 0.6));
+
+        let frames: Vec<(f32, f32)> = (0..4).map(|_| sampled_note.next_frame()).collect();
+        assert_eq!(frames, vec![(0.1, 0.2), (0.3, 0.4), (0.5, 0.6), (0.0, 0.0)]);
+
+        // Played as one channel, each frame's channels are mixed
+        sampled_note.sample_index = 0;
+        assert!((sampled_note.next_sample() - 0.15).abs() < 1e-6);
+    }
+}
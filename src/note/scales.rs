@@ -1,5 +1,10 @@
 use crate::note::constants::PITCH_TO_FREQ_HZ;
 use std::fmt;
+use std::str::FromStr;
+
+static MAX_MIDI_NOTE: u8 = 127;
+// Highest octave get_frequency() takes, which has C to G
+pub const MAX_FREQUENCY_OCTAVE: u8 = 10;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -23,13 +28,30 @@ pub enum WesternPitch {
     B,
 }
 
+// How a scale's degrees are tuned relative to its root
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) enum Temperament {
+    #[default]
+    EqualTemperament,
+    JustIntonation,
+}
+
 #[allow(dead_code)]
-pub(crate) enum WesternScale {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WesternScale {
     Major,
     Minor,
     Pentatonic,
-    Blues, // TODO CHATTY BROKEN
+    Blues,
     Chromatic,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    HarmonicMinor,
+    WholeTone,
 }
 
 #[allow(dead_code)]
@@ -64,8 +86,47 @@ impl WesternPitch {
         }
     }
     
+    // Frequency of the pitch in an octave counted from MIDI note 0, so octave 0 is the lowest MIDI
+    // octave, C-1 at about 8 Hz, and octave 1 holds the bass notes of C0 to B0. The table ends at
+    // MIDI 127, G9, so octave 10 only has C to G, and a pitch past the end plays in the highest
+    // octave that has it.
     pub fn get_frequency(&self, octave: u8) -> f32 {
-        PITCH_TO_FREQ_HZ[(octave * 12 + self.get_pitch_index()) as usize] as f32
+        let octave = octave.min(MAX_FREQUENCY_OCTAVE);
+        self.try_get_frequency(octave)
+            .or_else(|| self.try_get_frequency(octave - 1))
+            .unwrap()
+    }
+
+    // Frequency of the pitch in an octave as get_frequency() counts them, or None past the end of
+    // the table
+    pub fn try_get_frequency(&self, octave: u8) -> Option<f32> {
+        let idx = octave as usize * 12 + self.get_pitch_index() as usize;
+        PITCH_TO_FREQ_HZ.get(idx).map(|freq| *freq as f32)
+    }
+
+    // The pitch and octave of a MIDI note number, where MIDI 60 is C4, middle C. Octaves here are
+    // scientific pitch notation, one higher than the octave get_frequency() takes.
+    // Black keys are named as sharps, and notes below C0 are put in octave 0.
+    pub fn from_midi_note(midi_note: u8) -> (WesternPitch, u8) {
+        let midi_note = midi_note.min(MAX_MIDI_NOTE);
+        (Self::all_pitches()[(midi_note % 12) as usize], (midi_note / 12).saturating_sub(1))
+    }
+
+    // The MIDI note number of this pitch in an octave in scientific pitch notation, so C4 is 60
+    pub fn to_midi_note(&self, octave: u8) -> u8 {
+        ((octave as u16 + 1) * 12 + self.get_pitch_index() as u16).min(MAX_MIDI_NOTE as u16) as u8
+    }
+
+    // The nearest pitch and octave to a frequency, with how many cents sharp (positive) or flat
+    // (negative) of it the frequency is, e.g. for a tuner display
+    pub fn nearest(freq_hz: f32) -> (WesternPitch, u8, f32) {
+        let midi_note = frequency_to_nearest_midi(freq_hz);
+        let (pitch, octave) = Self::from_midi_note(midi_note);
+        if freq_hz <= 0.0 {
+            return (pitch, octave, 0.0);
+        }
+        let cents_off = 1200.0 * (freq_hz / PITCH_TO_FREQ_HZ[midi_note as usize] as f32).log2();
+        (pitch, octave, cents_off)
     }
 
     pub fn all_pitches() -> [WesternPitch; 12] {
@@ -98,6 +159,14 @@ impl WesternPitch {
     }
 }
 
+// The MIDI note number nearest to a frequency, in equal temperament tuned to A4 = MIDI 69 = 440 Hz
+pub fn frequency_to_nearest_midi(freq: f32) -> u8 {
+    if freq <= 0.0 {
+        return 0;
+    }
+    (69.0 + 12.0 * (freq / 440.0).log2()).round().clamp(0.0, MAX_MIDI_NOTE as f32) as u8
+}
+
 impl fmt::Display for WesternPitch {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -122,52 +191,132 @@ impl fmt::Display for WesternPitch {
     }
 }
 
+impl fmt::Display for WesternScale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WesternScale::Major => write!(f, "Major"),
+            WesternScale::Minor => write!(f, "Minor"),
+            WesternScale::Pentatonic => write!(f, "Pentatonic"),
+            WesternScale::Blues => write!(f, "Blues"),
+            WesternScale::Chromatic => write!(f, "Chromatic"),
+            WesternScale::Dorian => write!(f, "Dorian"),
+            WesternScale::Phrygian => write!(f, "Phrygian"),
+            WesternScale::Lydian => write!(f, "Lydian"),
+            WesternScale::Mixolydian => write!(f, "Mixolydian"),
+            WesternScale::Locrian => write!(f, "Locrian"),
+            WesternScale::HarmonicMinor => write!(f, "Harmonic Minor"),
+            WesternScale::WholeTone => write!(f, "Whole Tone"),
+        }
+    }
+}
+
+// Scale names as they are displayed, without spaces, e.g. "Major" or "HarmonicMinor"
+impl FromStr for WesternScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WesternScale::all_scales().into_iter()
+            .find(|scale| scale.to_string().replace(' ', "") == s)
+            .ok_or_else(|| format!("Unknown scale: {}", s))
+    }
+}
+
 #[allow(dead_code)]
 impl WesternScale {
-    pub(crate) fn get_scale(&self, root_pitch: u8) -> Vec<f32> {
-        let mut scale = Vec::new();
-        let root_freq = PITCH_TO_FREQ_HZ[root_pitch as usize] as f32;
+    pub fn all_scales() -> [WesternScale; 12] {
+        [
+            WesternScale::Major,
+            WesternScale::Minor,
+            WesternScale::Pentatonic,
+            WesternScale::Blues,
+            WesternScale::Chromatic,
+            WesternScale::Dorian,
+            WesternScale::Phrygian,
+            WesternScale::Lydian,
+            WesternScale::Mixolydian,
+            WesternScale::Locrian,
+            WesternScale::HarmonicMinor,
+            WesternScale::WholeTone,
+        ]
+    }
+
+    // Whether a pitch, in any octave, is a degree of this scale on a root
+    pub fn contains(&self, root: WesternPitch, pitch: WesternPitch) -> bool {
+        let semitone = (pitch.get_pitch_index() + 12 - root.get_pitch_index()) % 12;
+        self.semitones().contains(&semitone)
+    }
+
+    // Semitones above the root of a degree, counting from 0, with degrees past the last going on
+    // up through the octaves above
+    pub(crate) fn degree_semitones(&self, degree: usize) -> u32 {
+        let semitones = self.semitones();
+        12 * (degree / semitones.len()) as u32 + semitones[degree % semitones.len()] as u32
+    }
+
+    // Semitones above the root of each degree
+    fn semitones(&self) -> &'static [u8] {
         match self {
-            WesternScale::Major => {
-                scale.push(root_freq);
-                scale.push(root_freq * 9.0 / 8.0);
-                scale.push(root_freq * 5.0 / 4.0);
-                scale.push(root_freq * 4.0 / 3.0);
-                scale.push(root_freq * 3.0 / 2.0);
-                scale.push(root_freq * 5.0 / 3.0);
-                scale.push(root_freq * 15.0 / 8.0);
-            }
-            WesternScale::Minor => {
-                scale.push(root_freq);
-                scale.push(root_freq * 9.0 / 8.0);
-                scale.push(root_freq * 6.0 / 5.0);
-                scale.push(root_freq * 4.0 / 3.0);
-                scale.push(root_freq * 3.0 / 2.0);
-                scale.push(root_freq * 8.0 / 5.0);
-                scale.push(root_freq * 9.0 / 5.0);
-            }
-            WesternScale::Pentatonic => {
-                scale.push(root_freq);
-                scale.push(root_freq * 9.0 / 8.0);
-                scale.push(root_freq * 6.0 / 5.0);
-                scale.push(root_freq * 4.0 / 3.0);
-                scale.push(root_freq * 3.0 / 2.0);
-            }
-            WesternScale::Blues => {
-                scale.push(root_freq);
-                scale.push(root_freq * 6.0 / 5.0);
-                scale.push(root_freq * 7.0 / 5.0);
-                scale.push(root_freq * 7.0 / 6.0);
-                scale.push(root_freq * 9.0 / 5.0);
-            }
-            WesternScale::Chromatic => {
-                for i in 0..12 {
-                    scale.push(root_freq * 2.0_f32.powf(i as f32 / 12.0));
-                }
-            }
+            WesternScale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            WesternScale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            WesternScale::Pentatonic => &[0, 2, 3, 5, 7],
+            WesternScale::Blues => &[0, 3, 5, 6, 7, 10],
+            WesternScale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            WesternScale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            WesternScale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            WesternScale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            WesternScale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            WesternScale::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            WesternScale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            WesternScale::WholeTone => &[0, 2, 4, 6, 8, 10],
+        }
+    }
+
+    // Just intonation ratio to the root of each degree
+    fn just_ratios(&self) -> &'static [f32] {
+        match self {
+            WesternScale::Major =>
+                &[1.0, 9.0 / 8.0, 5.0 / 4.0, 4.0 / 3.0, 3.0 / 2.0, 5.0 / 3.0, 15.0 / 8.0],
+            WesternScale::Minor =>
+                &[1.0, 9.0 / 8.0, 6.0 / 5.0, 4.0 / 3.0, 3.0 / 2.0, 8.0 / 5.0, 9.0 / 5.0],
+            WesternScale::Pentatonic =>
+                &[1.0, 9.0 / 8.0, 6.0 / 5.0, 4.0 / 3.0, 3.0 / 2.0],
+            WesternScale::Blues =>
+                &[1.0, 6.0 / 5.0, 4.0 / 3.0, 45.0 / 32.0, 3.0 / 2.0, 9.0 / 5.0],
+            WesternScale::Chromatic =>
+                &[1.0, 16.0 / 15.0, 9.0 / 8.0, 6.0 / 5.0, 5.0 / 4.0, 4.0 / 3.0, 45.0 / 32.0,
+                  3.0 / 2.0, 8.0 / 5.0, 5.0 / 3.0, 9.0 / 5.0, 15.0 / 8.0],
+            WesternScale::Dorian =>
+                &[1.0, 9.0 / 8.0, 6.0 / 5.0, 4.0 / 3.0, 3.0 / 2.0, 5.0 / 3.0, 9.0 / 5.0],
+            WesternScale::Phrygian =>
+                &[1.0, 16.0 / 15.0, 6.0 / 5.0, 4.0 / 3.0, 3.0 / 2.0, 8.0 / 5.0, 9.0 / 5.0],
+            WesternScale::Lydian =>
+                &[1.0, 9.0 / 8.0, 5.0 / 4.0, 45.0 / 32.0, 3.0 / 2.0, 5.0 / 3.0, 15.0 / 8.0],
+            WesternScale::Mixolydian =>
+                &[1.0, 9.0 / 8.0, 5.0 / 4.0, 4.0 / 3.0, 3.0 / 2.0, 5.0 / 3.0, 9.0 / 5.0],
+            WesternScale::Locrian =>
+                &[1.0, 16.0 / 15.0, 6.0 / 5.0, 4.0 / 3.0, 64.0 / 45.0, 8.0 / 5.0, 9.0 / 5.0],
+            WesternScale::HarmonicMinor =>
+                &[1.0, 9.0 / 8.0, 6.0 / 5.0, 4.0 / 3.0, 3.0 / 2.0, 8.0 / 5.0, 15.0 / 8.0],
+            WesternScale::WholeTone =>
+                &[1.0, 9.0 / 8.0, 5.0 / 4.0, 45.0 / 32.0, 25.0 / 16.0, 16.0 / 9.0],
+        }
+    }
+
+    // The frequencies of the scale's degrees from a root MIDI note. Equal temperament puts degrees
+    // on the same pitches as WesternPitch::get_frequency(), so a scale lines up with notes from
+    // the pitch table. Just intonation tunes degrees to simple whole-number ratios to the root,
+    // e.g. a major third of 5/4, which sounds purer in the root's key but drifts from the pitch
+    // table by up to about 20 cents.
+    pub(crate) fn get_scale(&self, root_pitch: u8, temperament: Temperament) -> Vec<f32> {
+        let root_freq = PITCH_TO_FREQ_HZ[root_pitch as usize] as f32;
+        match temperament {
+            Temperament::EqualTemperament => self.semitones().iter()
+                .map(|semitone| root_freq * 2.0_f32.powf(*semitone as f32 / 12.0))
+                .collect(),
+            Temperament::JustIntonation => self.just_ratios().iter()
+                .map(|ratio| root_freq * ratio)
+                .collect(),
         }
-        
-        scale
     }
 }
 
@@ -219,3 +368,169 @@ impl ArabicScale {
         scale
     }
 }
+
+#[cfg(test)]
+mod test_scales {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::note::constants::PITCH_TO_FREQ_HZ;
+    use crate::note::scales::{frequency_to_nearest_midi, Temperament, WesternPitch, WesternScale,
+                              MAX_FREQUENCY_OCTAVE};
+
+    #[test]
+    fn test_degree_semitones_wrap_to_the_next_octave() {
+        assert_eq!(WesternScale::Major.degree_semitones(0), 0);
+        assert_eq!(WesternScale::Major.degree_semitones(2), 4);
+        assert_eq!(WesternScale::Major.degree_semitones(7), 12);
+        assert_eq!(WesternScale::Major.degree_semitones(9), 16);
+        assert_eq!(WesternScale::Pentatonic.degree_semitones(5), 12);
+    }
+
+    #[test]
+    fn test_scale_from_str() {
+        assert_eq!("Major".parse::<WesternScale>(), Ok(WesternScale::Major));
+        assert_eq!("HarmonicMinor".parse::<WesternScale>(), Ok(WesternScale::HarmonicMinor));
+        assert!("Harmonic".parse::<WesternScale>().is_err());
+    }
+
+    #[test]
+    fn test_a4_is_midi_69() {
+        assert_eq!(WesternPitch::A.to_midi_note(4), 69);
+        assert_eq!(WesternPitch::from_midi_note(69), (WesternPitch::A, 4));
+        assert_eq!(frequency_to_nearest_midi(440.0), 69);
+        assert_eq!(PITCH_TO_FREQ_HZ[69], 440.0);
+    }
+
+    #[test]
+    fn test_middle_c_is_midi_60() {
+        assert_eq!(WesternPitch::C.to_midi_note(4), 60);
+        assert_eq!(WesternPitch::from_midi_note(60), (WesternPitch::C, 4));
+        // get_frequency's octave is one lower than scientific pitch notation
+        assert_eq!(frequency_to_nearest_midi(WesternPitch::C.get_frequency(5)), 60);
+    }
+
+    #[test]
+    fn test_midi_note_round_trip() {
+        for midi_note in 12..=127 {
+            let (pitch, octave) = WesternPitch::from_midi_note(midi_note);
+            assert_eq!(pitch.to_midi_note(octave), midi_note);
+            assert_eq!(frequency_to_nearest_midi(PITCH_TO_FREQ_HZ[midi_note as usize] as f32),
+                       midi_note);
+        }
+        // flats are the same notes as their enharmonic sharps
+        assert_eq!(WesternPitch::BFlat.to_midi_note(3), WesternPitch::ASharp.to_midi_note(3));
+    }
+
+    #[test]
+    fn test_nearest_in_tune() {
+        let (pitch, octave, cents_off) = WesternPitch::nearest(440.0);
+        assert_eq!((pitch, octave), (WesternPitch::A, 4));
+        assert!(cents_off.abs() < 0.01);
+    }
+
+    #[test]
+    fn test_nearest_sharp_and_flat() {
+        // 445 Hz is about 19.6 cents sharp of A4
+        let (pitch, octave, cents_off) = WesternPitch::nearest(445.0);
+        assert_eq!((pitch, octave), (WesternPitch::A, 4));
+        assert!(cents_off > 19.0 && cents_off < 20.0);
+
+        let (pitch, octave, cents_off) = WesternPitch::nearest(255.0);
+        assert_eq!((pitch, octave), (WesternPitch::C, 4));
+        assert!(cents_off < 0.0);
+    }
+
+    fn assert_ascending_within_octave(scale: &[f32]) {
+        for degrees in scale.windows(2) {
+            assert!(degrees[0] < degrees[1]);
+        }
+        assert!(*scale.last().unwrap() < scale[0] * 2.0);
+    }
+
+    #[test]
+    fn test_scale_degrees() {
+        let scales = [
+            (WesternScale::Dorian, 7),
+            (WesternScale::Phrygian, 7),
+            (WesternScale::Lydian, 7),
+            (WesternScale::Mixolydian, 7),
+            (WesternScale::Locrian, 7),
+            (WesternScale::HarmonicMinor, 7),
+            (WesternScale::WholeTone, 6),
+            (WesternScale::Blues, 6),
+        ];
+        for (scale, num_degrees) in scales {
+            for temperament in [Temperament::EqualTemperament, Temperament::JustIntonation] {
+                let frequencies = scale.get_scale(60, temperament);
+                assert_eq!(frequencies.len(), num_degrees);
+                assert_eq!(frequencies[0], PITCH_TO_FREQ_HZ[60] as f32);
+                assert_ascending_within_octave(&frequencies);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blues_scale_pitches() {
+        // C blues is C Eb F F# G Bb
+        for temperament in [Temperament::EqualTemperament, Temperament::JustIntonation] {
+            let pitches: Vec<u8> = WesternScale::Blues.get_scale(60, temperament).iter()
+                .map(|freq| frequency_to_nearest_midi(*freq))
+                .collect();
+            assert_eq!(pitches, vec![60, 63, 65, 66, 67, 70]);
+        }
+    }
+
+    #[test]
+    fn test_major_third_by_temperament() {
+        let root_freq = PITCH_TO_FREQ_HZ[60] as f32;
+        let equal = WesternScale::Major.get_scale(60, Temperament::default());
+        let just = WesternScale::Major.get_scale(60, Temperament::JustIntonation);
+        assert_float_eq(equal[2] / root_freq, 2.0_f32.powf(4.0 / 12.0));
+        assert_float_eq(just[2] / root_freq, 5.0 / 4.0);
+        // the equal tempered third is about 14 cents sharp of the just one
+        let cents = 1200.0 * (equal[2] / just[2]).log2();
+        assert!(cents > 13.0 && cents < 15.0);
+        // equal temperament agrees with the pitch table
+        assert_float_eq(equal[2], WesternPitch::E.get_frequency(5));
+    }
+
+    #[test]
+    fn test_scale_contains() {
+        assert!(WesternScale::Major.contains(WesternPitch::C, WesternPitch::E));
+        assert!(!WesternScale::Major.contains(WesternPitch::C, WesternPitch::CSharp));
+        assert!(WesternScale::Minor.contains(WesternPitch::A, WesternPitch::C));
+        assert!(WesternScale::Mixolydian.contains(WesternPitch::G, WesternPitch::F));
+        // flats are the same notes as their enharmonic sharps
+        assert!(WesternScale::Minor.contains(WesternPitch::C, WesternPitch::EFlat));
+        assert!(!WesternScale::Dorian.contains(WesternPitch::D, WesternPitch::BFlat));
+    }
+
+    #[test]
+    fn test_lowest_and_highest_octaves() {
+        assert_eq!(WesternPitch::C.get_frequency(0), PITCH_TO_FREQ_HZ[0] as f32);
+        // bass notes, C0 and B0
+        assert_eq!(WesternPitch::C.get_frequency(1), PITCH_TO_FREQ_HZ[12] as f32);
+        assert_eq!(WesternPitch::B.get_frequency(1), PITCH_TO_FREQ_HZ[23] as f32);
+        assert_eq!(WesternPitch::G.get_frequency(MAX_FREQUENCY_OCTAVE),
+                   PITCH_TO_FREQ_HZ[127] as f32);
+        assert_eq!(WesternPitch::B.try_get_frequency(9), Some(PITCH_TO_FREQ_HZ[119] as f32));
+    }
+
+    #[test]
+    fn test_out_of_range_octave() {
+        assert_eq!(WesternPitch::GSharp.try_get_frequency(MAX_FREQUENCY_OCTAVE), None);
+        assert_eq!(WesternPitch::C.try_get_frequency(11), None);
+        assert_eq!(WesternPitch::C.try_get_frequency(u8::MAX), None);
+        // past the table the pitch plays in the highest octave that has it
+        assert_eq!(WesternPitch::GSharp.get_frequency(MAX_FREQUENCY_OCTAVE),
+                   PITCH_TO_FREQ_HZ[116] as f32);
+        assert_eq!(WesternPitch::C.get_frequency(u8::MAX), PITCH_TO_FREQ_HZ[120] as f32);
+    }
+
+    #[test]
+    fn test_frequency_to_nearest_midi() {
+        // about a third of a semitone sharp of A4 rounds down to A4
+        assert_eq!(frequency_to_nearest_midi(440.0 * 1.02), 69);
+        assert_eq!(frequency_to_nearest_midi(0.0), 0);
+        assert_eq!(frequency_to_nearest_midi(100000.0), 127);
+    }
+}
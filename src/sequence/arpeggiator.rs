@@ -0,0 +1,181 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::meter::durations::{DurationType, QUARTER};
+use crate::note::scales::WesternPitch;
+
+static ARP_RANDOM_SEED: u64 = 0xa4b;
+
+/// Order an arpeggiator plays the pitches of a chord in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ArpMode {
+    Up,
+    Down,
+    // Up then back down, without playing the top and bottom pitches twice in a row
+    UpDown,
+    Random,
+}
+
+/// Arpeggiator modes in the order the track arp control cycles through them
+pub const ARP_MODES: [ArpMode; 4] = [ArpMode::Up, ArpMode::Down, ArpMode::UpDown, ArpMode::Random];
+
+/// Plays the pitches of a held chord one at a time, as an endless stream of single notes each
+/// lasting the rate's note value. Pitches are a pitch and an octave in scientific pitch notation.
+#[derive(Debug, Clone)]
+pub(crate) struct Arpeggiator {
+    mode: ArpMode,
+    rate: DurationType,
+    // Lowest first
+    pitches: Vec<(WesternPitch, u8)>,
+    position: usize,
+    rng: StdRng,
+}
+
+impl Arpeggiator {
+    /// An arpeggiator with room for chords of up to `max_pitches`, so holding one never allocates
+    pub(crate) fn with_capacity(mode: ArpMode, rate: DurationType, max_pitches: usize) -> Self {
+        Self {
+            mode,
+            rate,
+            pitches: Vec::with_capacity(max_pitches),
+            position: 0,
+            rng: StdRng::seed_from_u64(ARP_RANDOM_SEED),
+        }
+    }
+
+    /// Hold a new chord, starting again from the beginning of the pattern
+    pub(crate) fn set_pitches(&mut self, pitches: &[(WesternPitch, u8)]) {
+        self.pitches.clear();
+        self.pitches.extend_from_slice(pitches);
+        self.pitches.sort_unstable_by_key(|(pitch, octave)| pitch.to_midi_note(*octave));
+        self.position = 0;
+    }
+
+    pub(crate) fn set_mode(&mut self, mode: ArpMode) {
+        self.mode = mode;
+        self.position = 0;
+    }
+
+    pub(crate) fn mode(&self) -> ArpMode {
+        self.mode
+    }
+
+    /// Length of each note at a tempo in quarter notes per minute
+    pub(crate) fn note_duration_ms(&self, tempo: f32) -> f32 {
+        60000.0 / tempo.max(1.0) * self.rate.to_factor() / QUARTER
+    }
+
+    /// The next pitch to play, or None with no chord held
+    pub(crate) fn next_pitch(&mut self) -> Option<(WesternPitch, u8)> {
+        let num_pitches = self.pitches.len();
+        if num_pitches == 0 {
+            return None;
+        }
+        let pitch_idx = match self.mode {
+            ArpMode::Up => self.position % num_pitches,
+            ArpMode::Down => num_pitches - 1 - self.position % num_pitches,
+            ArpMode::UpDown => {
+                // One cycle goes up through every pitch and down through the inner ones
+                let cycle_len = (2 * num_pitches).saturating_sub(2).max(1);
+                let cycle_position = self.position % cycle_len;
+                if cycle_position < num_pitches {
+                    cycle_position
+                } else {
+                    cycle_len - cycle_position
+                }
+            }
+            ArpMode::Random => self.rng.random_range(0..num_pitches),
+        };
+        self.position += 1;
+        Some(self.pitches[pitch_idx])
+    }
+}
+
+impl Iterator for Arpeggiator {
+    type Item = (WesternPitch, u8);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_pitch()
+    }
+}
+
+#[cfg(test)]
+mod test_arpeggiator {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::meter::durations::DurationType;
+    use crate::note::scales::WesternPitch;
+    use crate::sequence::arpeggiator::{ArpMode, Arpeggiator};
+
+    fn c_major(mode: ArpMode) -> Arpeggiator {
+        let mut arpeggiator = Arpeggiator::with_capacity(mode, DurationType::Sixteenth, 4);
+        // out of order, the arpeggiator sorts them
+        arpeggiator.set_pitches(&[(WesternPitch::G, 4), (WesternPitch::C, 4), (WesternPitch::E, 4)]);
+        arpeggiator
+    }
+
+    fn pitch_names(arpeggiator: Arpeggiator, num_notes: usize) -> Vec<WesternPitch> {
+        arpeggiator.take(num_notes).map(|(pitch, _)| pitch).collect()
+    }
+
+    #[test]
+    fn test_up() {
+        assert_eq!(pitch_names(c_major(ArpMode::Up), 7), vec![
+            WesternPitch::C, WesternPitch::E, WesternPitch::G,
+            WesternPitch::C, WesternPitch::E, WesternPitch::G,
+            WesternPitch::C,
+        ]);
+    }
+
+    #[test]
+    fn test_down() {
+        assert_eq!(pitch_names(c_major(ArpMode::Down), 4), vec![
+            WesternPitch::G, WesternPitch::E, WesternPitch::C, WesternPitch::G,
+        ]);
+    }
+
+    #[test]
+    fn test_up_down_does_not_repeat_the_ends() {
+        assert_eq!(pitch_names(c_major(ArpMode::UpDown), 9), vec![
+            WesternPitch::C, WesternPitch::E, WesternPitch::G, WesternPitch::E,
+            WesternPitch::C, WesternPitch::E, WesternPitch::G, WesternPitch::E,
+            WesternPitch::C,
+        ]);
+
+        // two pitches alternate, one repeats
+        let mut arpeggiator = Arpeggiator::with_capacity(ArpMode::UpDown, DurationType::Eighth, 4);
+        arpeggiator.set_pitches(&[(WesternPitch::C, 4), (WesternPitch::G, 4)]);
+        assert_eq!(pitch_names(arpeggiator.clone(), 4), vec![
+            WesternPitch::C, WesternPitch::G, WesternPitch::C, WesternPitch::G,
+        ]);
+        arpeggiator.set_pitches(&[(WesternPitch::C, 4)]);
+        assert_eq!(pitch_names(arpeggiator, 3), vec![WesternPitch::C; 3]);
+    }
+
+    #[test]
+    fn test_random_plays_only_chord_pitches() {
+        let pitches = pitch_names(c_major(ArpMode::Random), 64);
+        assert!(pitches.iter().all(|pitch| [WesternPitch::C, WesternPitch::E, WesternPitch::G]
+            .contains(pitch)));
+        assert!(pitches.contains(&WesternPitch::C) && pitches.contains(&WesternPitch::G));
+    }
+
+    #[test]
+    fn test_octaves_sort_pitches() {
+        let mut arpeggiator = Arpeggiator::with_capacity(ArpMode::Up, DurationType::Sixteenth, 4);
+        arpeggiator.set_pitches(&[(WesternPitch::C, 5), (WesternPitch::G, 4)]);
+        assert_eq!(arpeggiator.next_pitch(), Some((WesternPitch::G, 4)));
+        assert_eq!(arpeggiator.next_pitch(), Some((WesternPitch::C, 5)));
+    }
+
+    #[test]
+    fn test_no_chord() {
+        let mut arpeggiator = Arpeggiator::with_capacity(ArpMode::Up, DurationType::Sixteenth, 4);
+        assert_eq!(arpeggiator.next_pitch(), None);
+    }
+
+    #[test]
+    fn test_note_duration() {
+        // sixteenths at 120 quarter notes a minute
+        assert_float_eq(c_major(ArpMode::Up).note_duration_ms(120.0), 125.0);
+    }
+}
@@ -0,0 +1,28 @@
+// Length of one bar of a preset pattern, in sixteenth note steps
+pub(crate) static DRUM_PATTERN_STEPS: usize = 16;
+// Classic four-on-the-floor kick
+pub(crate) static KICK_PATTERN: [usize; 4] = [0, 4, 8, 12];
+// Backbeat snare, on beats 2 and 4
+pub(crate) static SNARE_PATTERN: [usize; 2] = [4, 12];
+// Off-beat eighth note hi-hat
+pub(crate) static HIHAT_PATTERN: [usize; 8] = [1, 3, 5, 7, 9, 11, 13, 15];
+
+/// The step indexes of a one bar pattern repeated bar after bar over num_steps steps
+pub(crate) fn repeat_drum_pattern(pattern: &[usize], num_steps: usize) -> Vec<usize> {
+    (0..num_steps)
+        .filter(|step_index| pattern.contains(&(step_index % DRUM_PATTERN_STEPS)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test_drum_patterns {
+    use crate::sequence::drum_patterns::{repeat_drum_pattern, KICK_PATTERN, SNARE_PATTERN};
+
+    #[test]
+    fn test_repeat_drum_pattern() {
+        assert_eq!(repeat_drum_pattern(&KICK_PATTERN, 16), vec![0, 4, 8, 12]);
+        assert_eq!(repeat_drum_pattern(&SNARE_PATTERN, 32), vec![4, 12, 20, 28]);
+        // a partial bar keeps the hits that fit
+        assert_eq!(repeat_drum_pattern(&KICK_PATTERN, 10), vec![0, 4, 8]);
+    }
+}
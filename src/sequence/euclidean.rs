@@ -0,0 +1,62 @@
+/// Spread hits as evenly as possible across steps, using Bjorklund's algorithm. The pattern starts
+/// on a hit and rotation moves every hit that many steps later, wrapping at the end. More hits
+/// than steps fills every step.
+pub(crate) fn euclidean(hits: usize, steps: usize, rotation: usize) -> Vec<bool> {
+    if steps == 0 {
+        return Vec::new();
+    }
+    let hits = hits.min(steps);
+
+    // Repeatedly pair the remainder groups onto the leading groups until at most one remains
+    let mut groups: Vec<Vec<bool>> = vec![vec![true]; hits];
+    let mut remainder: Vec<Vec<bool>> = vec![vec![false]; steps - hits];
+    while remainder.len() > 1 && !groups.is_empty() {
+        let num_paired = groups.len().min(remainder.len());
+        let new_remainder = if groups.len() > num_paired {
+            groups.split_off(num_paired)
+        } else {
+            remainder.split_off(num_paired)
+        };
+        for (group, paired) in groups.iter_mut().zip(remainder.iter()) {
+            group.extend_from_slice(paired);
+        }
+        remainder = new_remainder;
+    }
+
+    let mut pattern: Vec<bool> = groups.into_iter().chain(remainder).flatten().collect();
+    pattern.rotate_right(rotation % steps);
+    pattern
+}
+
+#[cfg(test)]
+mod test_euclidean {
+    use crate::sequence::euclidean::euclidean;
+
+    fn pattern(s: &str) -> Vec<bool> {
+        s.chars().map(|c| c == '1').collect()
+    }
+
+    #[test]
+    fn test_known_patterns() {
+        assert_eq!(euclidean(5, 8, 0), pattern("10110110"));
+        assert_eq!(euclidean(3, 8, 0), pattern("10010010"));
+        assert_eq!(euclidean(4, 12, 0), pattern("100100100100"));
+        assert_eq!(euclidean(2, 5, 0), pattern("10100"));
+        assert_eq!(euclidean(7, 16, 0), pattern("1001010100101010"));
+    }
+
+    #[test]
+    fn test_rotation() {
+        assert_eq!(euclidean(3, 8, 1), pattern("01001001"));
+        assert_eq!(euclidean(3, 8, 9), euclidean(3, 8, 1));
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        assert_eq!(euclidean(0, 4, 0), pattern("0000"));
+        assert_eq!(euclidean(4, 4, 0), pattern("1111"));
+        assert_eq!(euclidean(6, 4, 0), pattern("1111"));
+        assert_eq!(euclidean(1, 4, 0), pattern("1000"));
+        assert!(euclidean(3, 0, 0).is_empty());
+    }
+}
@@ -1,5 +1,9 @@
 use derive_builder::Builder;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use crate::meter::durations::DurationType;
+use crate::note::constants;
+use crate::note::playback_note::PlaybackNote;
 use crate::sequence::time_note_sequence::{TimeNoteSequence};
 use crate::sequence::note_sequence_trait::{AppendNote, BuilderWrapper, NextNotes, SetCurPosition};
 
@@ -10,13 +14,13 @@ pub struct FixedTimeNoteSequence {
     inner_sequence: TimeNoteSequence,
     
     #[builder(default = "DurationType::Quarter", setter(custom))]
-    duration_type: DurationType,
+    pub(crate) duration_type: DurationType,
     
     #[builder(default = "120", setter(custom))]
     pub(crate) tempo: u8,
     
     #[builder(default = "16")]
-    num_steps: usize,
+    pub(crate) num_steps: usize,
     
     #[builder(default = "0")]
     current_step: usize,
@@ -24,6 +28,10 @@ pub struct FixedTimeNoteSequence {
     // Computed field based on tempo and duration_type
     #[builder(default = "500.0")]
     step_duration_ms: f32,
+
+    // Set on a sequence made by reverse(), so plays backward relative to the one it came from
+    #[builder(setter(skip), default = "false")]
+    pub(crate) reversed: bool,
 }
 
 impl FixedTimeNoteSequenceBuilder {
@@ -97,6 +105,107 @@ impl FixedTimeNoteSequence {
         }
         all_notes
     }
+
+    /// A new sequence that plays these notes backward, so the last note plays first. Each note's
+    /// start and end are mirrored within the window from the earliest note start to the latest
+    /// note end, which keeps note lengths and the gaps between notes. Reversing the result
+    /// restores the original order.
+    #[allow(dead_code)]
+    pub(crate) fn reverse(&self) -> FixedTimeNoteSequence {
+        let notes = self.get_all_notes();
+        let mut reversed_sequence = FixedTimeNoteSequence {
+            inner_sequence: TimeNoteSequence::default(),
+            current_step: 0,
+            reversed: !self.reversed,
+            ..self.clone()
+        };
+        if notes.is_empty() {
+            return reversed_sequence;
+        }
+
+        let window_start_ms = notes.iter()
+            .map(|note| note.note_start_time_ms())
+            .fold(f32::MAX, f32::min);
+        let window_end_ms = notes.iter()
+            .map(|note| note.note_end_time_ms())
+            .fold(f32::MIN, f32::max);
+        let mut reversed_notes: Vec<PlaybackNote> = notes.into_iter()
+            .map(|mut note| {
+                let start_time_ms = note.note_start_time_ms();
+                let end_time_ms = note.note_end_time_ms();
+                note.set_note_start_time_ms(window_start_ms + window_end_ms - end_time_ms);
+                note.set_note_end_time_ms(window_start_ms + window_end_ms - start_time_ms);
+                note
+            })
+            .collect();
+        // Notes must be appended in start time order
+        reversed_notes.sort_by(|a, b| a.note_start_time_ms().total_cmp(&b.note_start_time_ms()));
+        for note in reversed_notes {
+            reversed_sequence.append_note(note);
+        }
+
+        reversed_sequence
+    }
+
+    /// Move each note's start toward the nearest multiple of the division at the sequence's
+    /// tempo, keeping its length. Strength 1.0 snaps notes onto the grid, 0.0 leaves them where
+    /// they are and values between move them part of the way.
+    #[allow(dead_code)]
+    pub(crate) fn quantize(&mut self, division: DurationType, strength: f32) {
+        let strength = strength.clamp(0.0, 1.0);
+        let quarter_note_duration_ms = 60000.0 / self.tempo as f32;
+        let division_ms = quarter_note_duration_ms * (division.to_factor() / 0.25);
+
+        let mut quantized_notes: Vec<PlaybackNote> = self.get_all_notes().into_iter()
+            .map(|mut note| {
+                let start_time_ms = note.note_start_time_ms();
+                let snapped_start_ms = (start_time_ms / division_ms).round() * division_ms;
+                let shift_ms = strength * (snapped_start_ms - start_time_ms);
+                let end_time_ms = note.note_end_time_ms();
+                note.set_note_start_time_ms(start_time_ms + shift_ms);
+                note.set_note_end_time_ms(end_time_ms + shift_ms);
+                note
+            })
+            .collect();
+        // Notes must be appended in start time order
+        quantized_notes.sort_by(|a, b| a.note_start_time_ms().total_cmp(&b.note_start_time_ms()));
+        self.inner_sequence = TimeNoteSequence::default();
+        for note in quantized_notes {
+            self.append_note(note);
+        }
+    }
+
+    /// Move each note's start by up to timing_ms earlier or later, keeping its length, and raise
+    /// or lower its velocity by up to velocity_amount, so a programmed pattern plays less
+    /// mechanically. The same seed always moves the same notes the same way.
+    #[allow(dead_code)]
+    pub(crate) fn humanize(&mut self, timing_ms: f32, velocity_amount: u8, seed: u64) {
+        let timing_ms = timing_ms.abs();
+        let velocity_amount = velocity_amount as i16;
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let mut humanized_notes: Vec<PlaybackNote> = self.get_all_notes().into_iter()
+            .map(|mut note| {
+                let start_time_ms = note.note_start_time_ms();
+                // Never earlier than the start of the sequence
+                let shift_ms = rng.random_range(-timing_ms..=timing_ms).max(-start_time_ms);
+                let end_time_ms = note.note_end_time_ms();
+                note.set_note_start_time_ms(start_time_ms + shift_ms);
+                note.set_note_end_time_ms(end_time_ms + shift_ms);
+
+                let velocity = note.velocity as i16
+                    + rng.random_range(-velocity_amount..=velocity_amount);
+                note.velocity = velocity.clamp(0, constants::MAX_VELOCITY as i16) as u8;
+                note
+            })
+            .collect();
+        // Notes must be appended in start time order
+        humanized_notes.sort_by(|a, b| a.note_start_time_ms().total_cmp(&b.note_start_time_ms()));
+        self.inner_sequence = TimeNoteSequence::default();
+        for note in humanized_notes {
+            self.append_note(note);
+        }
+    }
 }
 
 impl Iterator for FixedTimeNoteSequence {
@@ -105,4 +214,138 @@ impl Iterator for FixedTimeNoteSequence {
     fn next(&mut self) -> Option<Self::Item> {
         self.inner_sequence.next()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test_fixed_time_note_sequence {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::note::note::NoteBuilder;
+    use crate::note::playback_note;
+    use crate::note::playback_note::{NoteType, PlaybackNote};
+    use crate::meter::durations::DurationType;
+    use crate::sequence::fixed_time_note_sequence::FixedTimeNoteSequenceBuilder;
+    use crate::sequence::note_sequence_trait::AppendNote;
+
+    fn note(start_time_ms: f32, end_time_ms: f32, frequency: f32) -> PlaybackNote {
+        playback_note::from_note(
+            NoteType::Oscillator,
+            NoteBuilder::default()
+                .start_time_ms(start_time_ms)
+                .end_time_ms(end_time_ms)
+                .frequency(frequency)
+                .volume(1.0)
+                .build().unwrap()
+        )
+    }
+
+    #[test]
+    fn test_reverse_swaps_note_start_times() {
+        let mut sequence = FixedTimeNoteSequenceBuilder::default().build().unwrap();
+        sequence.append_note(note(0.0, 500.0, 440.0));
+        sequence.append_note(note(500.0, 1000.0, 880.0));
+
+        let reversed = sequence.reverse();
+        assert!(reversed.reversed);
+        let notes = reversed.get_all_notes();
+        assert_eq!(notes.len(), 2);
+        // the second note now plays first, in the first note's place
+        assert_float_eq(notes[0].note.frequency, 880.0);
+        assert_float_eq(notes[0].note_start_time_ms(), 0.0);
+        assert_float_eq(notes[0].note_end_time_ms(), 500.0);
+        assert_float_eq(notes[1].note.frequency, 440.0);
+        assert_float_eq(notes[1].note_start_time_ms(), 500.0);
+        assert_float_eq(notes[1].note_end_time_ms(), 1000.0);
+    }
+
+    #[test]
+    fn test_reverse_keeps_gaps_and_lengths() {
+        let mut sequence = FixedTimeNoteSequenceBuilder::default().build().unwrap();
+        sequence.append_note(note(1000.0, 1250.0, 440.0));
+        sequence.append_note(note(1500.0, 2000.0, 880.0));
+
+        let notes = sequence.reverse().get_all_notes();
+        assert_float_eq(notes[0].note.frequency, 880.0);
+        assert_float_eq(notes[0].note_start_time_ms(), 1000.0);
+        assert_float_eq(notes[0].note_end_time_ms(), 1500.0);
+        assert_float_eq(notes[1].note_start_time_ms(), 1750.0);
+        assert_float_eq(notes[1].note_end_time_ms(), 2000.0);
+
+        // reversing twice gives back the original
+        let restored = sequence.reverse().reverse();
+        assert!(!restored.reversed);
+        assert_eq!(restored.get_all_notes(), sequence.get_all_notes());
+    }
+
+    #[test]
+    fn test_quantize_snaps_to_grid() {
+        // eighths are 250ms at 120 BPM
+        let mut sequence = FixedTimeNoteSequenceBuilder::default().tempo(120).build().unwrap();
+        sequence.append_note(note(260.0, 400.0, 440.0));
+        sequence.append_note(note(490.0, 600.0, 880.0));
+
+        sequence.quantize(DurationType::Eighth, 1.0);
+        let notes = sequence.get_all_notes();
+        assert_float_eq(notes[0].note_start_time_ms(), 250.0);
+        // the note keeps its length
+        assert_float_eq(notes[0].note_end_time_ms(), 390.0);
+        assert_float_eq(notes[1].note_start_time_ms(), 500.0);
+        assert_float_eq(notes[1].note_end_time_ms(), 610.0);
+    }
+
+    #[test]
+    fn test_quantize_strength() {
+        let mut sequence = FixedTimeNoteSequenceBuilder::default().tempo(120).build().unwrap();
+        sequence.append_note(note(260.0, 400.0, 440.0));
+
+        sequence.quantize(DurationType::Eighth, 0.5);
+        let notes = sequence.get_all_notes();
+        assert_float_eq(notes[0].note_start_time_ms(), 255.0);
+        assert_float_eq(notes[0].note_end_time_ms(), 395.0);
+
+        sequence.quantize(DurationType::Eighth, 0.0);
+        assert_float_eq(sequence.get_all_notes()[0].note_start_time_ms(), 255.0);
+    }
+
+    #[test]
+    fn test_humanize_by_nothing_leaves_notes_unchanged() {
+        let mut sequence = FixedTimeNoteSequenceBuilder::default().build().unwrap();
+        sequence.append_note(note(0.0, 250.0, 440.0));
+        sequence.append_note(note(500.0, 750.0, 880.0));
+        let notes = sequence.get_all_notes();
+
+        sequence.humanize(0.0, 0, 7);
+        assert_eq!(sequence.get_all_notes(), notes);
+    }
+
+    #[test]
+    fn test_humanize_stays_within_bounds() {
+        let mut sequence = FixedTimeNoteSequenceBuilder::default().build().unwrap();
+        for step in 0..32 {
+            let mut playback_note = note(100.0 + step as f32 * 250.0,
+                                         200.0 + step as f32 * 250.0, 440.0);
+            // Velocities at both ends of the range, to be kept within it
+            playback_note.velocity = if step % 2 == 0 { 2 } else { 126 };
+            sequence.append_note(playback_note);
+        }
+        let notes = sequence.get_all_notes();
+
+        let mut humanized = sequence.clone();
+        humanized.humanize(10.0, 4, 7);
+        let humanized_notes = humanized.get_all_notes();
+        assert_eq!(humanized_notes.len(), notes.len());
+        assert_ne!(humanized_notes, notes);
+        // Notes move by less than the gaps between them, so keep their order
+        for (original, humanized) in notes.iter().zip(humanized_notes.iter()) {
+            let shift_ms = humanized.note_start_time_ms() - original.note_start_time_ms();
+            assert!(shift_ms.abs() <= 10.0);
+            assert!((humanized.note_duration_ms() - original.note_duration_ms()).abs() < 1e-3);
+            assert!(humanized.velocity.abs_diff(original.velocity) <= 4);
+            assert!(humanized.velocity <= 127);
+        }
+
+        // The same seed humanizes the same way
+        let mut again = sequence.clone();
+        again.humanize(10.0, 4, 7);
+        assert_eq!(again.get_all_notes(), humanized_notes);
+    }
+}
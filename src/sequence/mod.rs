@@ -2,5 +2,8 @@ pub mod grid_note_sequence;
 pub mod time_note_sequence;
 pub mod note_sequence_trait;
 pub mod fixed_time_note_sequence;
+pub mod euclidean;
+pub mod drum_patterns;
+pub mod arpeggiator;
 
 pub use fixed_time_note_sequence::FixedTimeNoteSequence;
@@ -1,6 +1,11 @@
 use derive_builder::Builder;
 
-use crate::common::constants::NO_TRACK;
+use crate::audio_gen::audio_gen::get_tail_sample;
+use crate::audio_gen::get_sample;
+use crate::audio_gen::oscillator::OscillatorTables;
+use crate::common::constants::{NO_TRACK, SAMPLES_PER_MS, SAMPLE_RATE};
+use crate::note::playback_note::PlaybackNote;
+use crate::sequence::fixed_time_note_sequence::FixedTimeNoteSequence;
 use crate::track::track_effects;
 use crate::track::track_effects::TrackEffects;
 
@@ -22,3 +27,99 @@ pub struct Track<SequenceType> {
 }
 
 impl<SequenceType> Track<SequenceType> {}
+
+impl Track<FixedTimeNoteSequence> {
+    // Every note of the track with its effects, panning and the track volume, sample by sample
+    // from time 0 to the end of the last note and any effect tail ringing past it, as (left,
+    // right) frames at SAMPLE_RATE, which the oscillators and effects are tuned to. Nothing limits
+    // the result, so tracks can be mixed before limiting the mix.
+    #[allow(dead_code)]
+    pub(crate) fn render_to_buffer(&self) -> Vec<(f32, f32)> {
+        let oscillator_tables = OscillatorTables::new();
+        let ms_to_samples = |time_ms: f32| (time_ms * SAMPLES_PER_MS).floor() as u64;
+
+        // Each note with the sample its effect tail ends on
+        let mut notes: Vec<(u64, PlaybackNote)> = Vec::new();
+        for mut playback_note in self.sequence.get_all_notes() {
+            playback_note.playback_start_time_ms = playback_note.note_start_time_ms();
+            playback_note.playback_end_time_ms = playback_note.note_end_time_ms();
+            playback_note.playback_sample_start_time =
+                ms_to_samples(playback_note.playback_start_time_ms);
+            playback_note.playback_sample_end_time =
+                ms_to_samples(playback_note.playback_end_time_ms);
+            playback_note.track_effects = self.effects.clone();
+
+            let tail_end_sample = playback_note.playback_sample_end_time +
+                ms_to_samples(playback_note.effect_tail_ms());
+            notes.push((tail_end_sample, playback_note));
+        }
+
+        let num_frames = notes.iter()
+            .map(|(tail_end_sample, _)| *tail_end_sample)
+            .max()
+            .unwrap_or(0);
+        let mut frames = Vec::with_capacity(num_frames as usize);
+        for sample_count in 0..num_frames {
+            let sample_position = (sample_count as f32 % SAMPLE_RATE) / SAMPLE_RATE;
+            let mut out_sample_l = 0.0;
+            let mut out_sample_r = 0.0;
+            for (tail_end_sample, playback_note) in notes.iter_mut() {
+                if sample_count < playback_note.playback_sample_start_time ||
+                    sample_count >= *tail_end_sample {
+                    continue;
+                }
+
+                // Envelopes expect the sample count from the start of the note
+                let note_sample_count = sample_count - playback_note.playback_sample_start_time;
+                let (sample_l, sample_r) =
+                    if sample_count < playback_note.playback_sample_end_time {
+                        get_sample::get_note_sample(playback_note, &oscillator_tables,
+                                                    sample_position, note_sample_count)
+                    } else {
+                        get_tail_sample(playback_note, sample_position, note_sample_count)
+                    };
+                out_sample_l += self.volume * sample_l;
+                out_sample_r += self.volume * sample_r;
+            }
+            frames.push((out_sample_l, out_sample_r));
+        }
+
+        frames
+    }
+}
+
+#[cfg(test)]
+mod test_track {
+    use crate::common::constants::SAMPLE_RATE;
+    use crate::dsl::parser::parse_dsl;
+
+    #[test]
+    fn test_render_single_note_to_buffer() {
+        // One 125ms step
+        let track_grid = parse_dsl(r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 4
+            osc:sine:440.0:0.5:0
+        "#).unwrap();
+        let track = &track_grid.tracks[0];
+
+        let frames = track.render_to_buffer();
+        assert_eq!(frames.len(), (0.125 * SAMPLE_RATE) as usize);
+        assert!(frames.iter().any(|(sample_l, _)| sample_l.abs() > 0.1));
+    }
+
+    #[test]
+    fn test_render_to_buffer_applies_track_volume() {
+        let mut track_grid = parse_dsl(r#"
+            FixedTimeNoteSequence dur Quarter tempo 120 num_steps 4
+            osc:sine:440.0:0.5:0
+        "#).unwrap();
+        let full = track_grid.tracks[0].render_to_buffer();
+        track_grid.tracks[0].volume = 0.5;
+        let half = track_grid.tracks[0].render_to_buffer();
+
+        for ((full_l, full_r), (half_l, half_r)) in full.iter().zip(half.iter()) {
+            assert!((full_l / 2.0 - half_l).abs() < 1e-6);
+            assert!((full_r / 2.0 - half_r).abs() < 1e-6);
+        }
+    }
+}
@@ -3,6 +3,9 @@ use crate::effect::delay::Delay;
 use crate::envelope::envelope::Envelope;
 use crate::effect::flanger::Flanger;
 use crate::effect::lfo::LFO;
+use crate::effect::bitcrusher::BitCrusher;
+use crate::effect::distortion::Distortion;
+use crate::effect::tremolo::Tremolo;
 
 #[derive(Builder, Clone, Debug, PartialEq)]
 pub(crate) struct TrackEffects {
@@ -22,6 +25,18 @@ pub(crate) struct TrackEffects {
     #[builder(default = "Vec::new()")]
     pub(crate) delays: Vec<Delay>,
 
+    #[allow(dead_code)]
+    #[builder(default = "Vec::new()")]
+    pub(crate) bitcrushers: Vec<BitCrusher>,
+
+    #[allow(dead_code)]
+    #[builder(default = "Vec::new()")]
+    pub(crate) distortions: Vec<Distortion>,
+
+    #[allow(dead_code)]
+    #[builder(default = "Vec::new()")]
+    pub(crate) tremolos: Vec<Tremolo>,
+
     // TODO enforce -1.0..1.0 with builder validator or custom builder
     #[builder(default = "0.0")]
     pub(crate) panning: f32,
@@ -57,8 +72,24 @@ impl TrackEffects {
         !self.delays.is_empty()
     }
     
+    #[allow(dead_code)]
+    pub(crate) fn has_bitcrushers(&self) -> bool {
+        !self.bitcrushers.is_empty()
+    }
+    
+    #[allow(dead_code)]
+    pub(crate) fn has_distortions(&self) -> bool {
+        !self.distortions.is_empty()
+    }
+    
+    #[allow(dead_code)]
+    pub(crate) fn has_tremolos(&self) -> bool {
+        !self.tremolos.is_empty()
+    }
+    
     #[allow(dead_code)]
     pub(crate) fn has_effects(&self) -> bool {
-        self.has_envelopes() || self.has_lfos() || self.has_flangers() || self.has_delays()
+        self.has_envelopes() || self.has_lfos() || self.has_flangers() || self.has_delays() ||
+            self.has_bitcrushers() || self.has_distortions() || self.has_tremolos()
     }
 }
\ No newline at end of file
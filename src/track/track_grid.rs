@@ -58,6 +58,8 @@ impl<SequenceType: NextNotes + Iterator + SetCurPosition> TrackGrid<SequenceType
                         .lfos(playback_note.lfos.clone())
                         .flangers(playback_note.flangers.clone())
                         .delays(playback_note.delays.clone())
+                        .velocity(playback_note.velocity)
+                        .fade_ms(playback_note.fade_ms)
                         .track_effects(track.effects.clone());
                 
                 match playback_note.note_type {
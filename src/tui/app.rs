@@ -1,11 +1,23 @@
-use crate::tui::{TuiError, audio_bridge::AudioBridge, config::TuiConfig, events::EventHandler};
+use crate::tui::{TuiError, config::TuiConfig, events::EventHandler};
+use crate::tui::config::SessionState;
+use crate::tui::audio_bridge::AudioFeedback;
+use crate::tui::audio_engine::{AudioEngine, AudioState, ClockSource, MAX_STEREO_WIDTH,
+                               MAX_SWING, MAX_TEMPO, MIN_TEMPO, NUM_TRACKS};
+use crate::tui::key_bindings::{key_name, navigation_key, Action};
+use crate::tui::midi_input::MidiInput;
 use crate::tui::ui::{SynthesizerPanel, SequencerPanel};
+use crate::tui::ui::widgets::{CursorFocus, LevelMeter, Scope, TrackControl, VolumeDisplay,
+                              meter::MeterOrientation};
 use crate::audio_gen;
+use crate::dsl::serializer::track_grid_to_dsl;
+use crate::meter::durations::DurationType;
+use crate::note::scales::WesternPitch;
 use crate::track::Track;
 use crate::sequence::FixedTimeNoteSequence;
 
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEvent},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent,
+            KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -17,6 +29,38 @@ use ratatui::{
     Frame, Terminal, buffer::Buffer,
 };
 use std::io;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+/// The sequencer plays one step per beat
+static SEQUENCER_STEP_DURATION: DurationType = DurationType::Quarter;
+/// Resolution of the tick in the transport position, matching MIDI clock
+pub const TICKS_PER_BEAT: u16 = 24;
+/// How long a note played into a step with the piano keys sounds
+const AUDITION_MS: u128 = 300;
+/// Level meters are a 10 block bar after a 4 column label
+const LEVEL_METER_WIDTH: usize = 10;
+const LEVEL_METER_LABEL_WIDTH: u16 = 4;
+/// Rows the sequencer needs for all eight tracks (two rows each) with its borders, step numbers
+/// and transport, and the status bar below it
+const SEQUENCER_MIN_HEIGHT: u16 = 24;
+const STATUS_BAR_HEIGHT: u16 = 3;
+/// Smallest terminal the layout fits, narrower leaves the volume and pan bars cut off
+const MIN_TERMINAL_WIDTH: u16 = 80;
+const MIN_TERMINAL_HEIGHT: u16 = SEQUENCER_MIN_HEIGHT + STATUS_BAR_HEIGHT;
+/// A tempo ramp moves this many BPM over this many measures
+const TEMPO_RAMP_BPM: f32 = 20.0;
+const TEMPO_RAMP_MEASURES: u32 = 8;
+/// Most recent taps the tap tempo averages over
+const TAP_HISTORY: usize = 8;
+/// A tap this long after the last starts counting again
+const TAP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+/// Intervals further than this fraction from the median are stray taps, left out of the average
+const TAP_OUTLIER_TOLERANCE: f32 = 0.25;
+/// Wait before trying again to reopen a lost output device, doubling after each failed try up to
+/// the longest wait
+const REOPEN_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+const MAX_REOPEN_BACKOFF: std::time::Duration = std::time::Duration::from_secs(8);
 
 // Custom widget to render only the grid part without controls
 struct GridOnlyWidget {
@@ -30,6 +74,8 @@ impl Widget for GridOnlyWidget {
         } else {
             Style::default().fg(Color::White)
         };
+        let visible_steps = self.grid.visible_step_count(area.width);
+        let first_step = self.grid.step_scroll;
         
         // Render track rows (each track takes 2 rows: steps + frequency)
         for (track_idx, track) in self.grid.tracks.iter().enumerate() {
@@ -50,13 +96,19 @@ impl Widget for GridOnlyWidget {
                 style
             };
             buf.set_string(x, y_steps, &format!("{}", track.track_number), track_style);
+            // Arpeggiator control under the track number
+            let arp_style = if self.grid.cursor.track == track_idx as u8 &&
+                self.grid.cursor.focus_area == CursorFocus::TrackControls &&
+                track.selected_control == TrackControl::Arp {
+                Style::default().fg(Color::Yellow).bg(Color::DarkGray)
+            } else {
+                track_style
+            };
+            buf.set_string(x, y_freq, track.arp_symbol().to_string(), arp_style);
             let mut step_x = x + 2;
             
-            // Step cells - show as many steps as will fit, up to 16
-            let max_steps = ((area.width.saturating_sub(2)) / 4) as usize; // 4 chars per step
-            let visible_steps = self.grid.steps_per_track.min(max_steps);
-            
-            for step_idx in 0..visible_steps {
+            // Step cells - show as many steps as will fit, from the first scrolled into view
+            for step_idx in first_step..first_step + visible_steps {
                 if step_idx >= track.steps.len() {
                     break;
                 }
@@ -71,13 +123,15 @@ impl Widget for GridOnlyWidget {
                 let is_freq_dropdown = self.grid.cursor.track == track_idx as u8 && 
                                       self.grid.cursor.step == step_idx as u8 &&
                                       self.grid.cursor.focus_area == crate::tui::ui::widgets::CursorFocus::FrequencyDropdown;
-                let is_playing = self.grid.playing_step == Some(step_idx);
+                let is_playing = self.grid.track_playing_steps[track_idx] == Some(step_idx);
                 
-                // Step cell style
+                // Step cell style, steps past the end of the track are dimmed
                 let step_style = if is_step_cursor {
                     Style::default().fg(Color::Yellow).bg(Color::DarkGray)
                 } else if is_playing {
                     Style::default().fg(Color::Green).bg(Color::Black)
+                } else if step_idx >= track.length {
+                    Style::default().fg(Color::DarkGray)
                 } else {
                     style
                 };
@@ -93,20 +147,23 @@ impl Widget for GridOnlyWidget {
                     Style::default().fg(Color::LightGreen)
                 };
                 
-                // Render step cell
-                let symbol = if step.enabled { "●" } else { "·" };
+                // Render step cell, with the step's velocity in the gap before the next one
+                let symbol = step.symbol();
                 buf.set_string(step_x, y_steps, &format!(" {} ", symbol), step_style);
+                buf.set_string(step_x + 3, y_steps, step.velocity_bar().to_string(),
+                               Style::default().fg(Color::DarkGray));
                 
-                // Render frequency cell - match the step cell format for alignment
+                // Render frequency cell - match the step cell format for alignment, with a marker
+                // in place of the leading space so a sharp and its octave still fit
                 let freq_text = if step.enabled {
                     if is_freq_dropdown {
-                        // Show active dropdown with special indicators
-                        format!("▼{}▲", step.frequency)
+                        // Show active dropdown with special indicator
+                        format!("▼{:<3}", step.pitch_label())
                     } else if is_freq_cursor {
-                        // Show selectable frequency with brackets
-                        format!("[{}]", step.frequency)
+                        // Show selectable frequency with a pointer
+                        format!("›{:<3}", step.pitch_label())
                     } else {
-                        format!(" {} ", step.frequency)
+                        format!(" {:<3}", step.pitch_label())
                     }
                 } else {
                     " · ".to_string()
@@ -134,13 +191,25 @@ impl Widget for GridOnlyWidget {
         if step_numbers_y < area.y + area.height {
             let mut x = area.x + 2; // Offset for track numbers
             
-            let max_steps = ((area.width.saturating_sub(2)) / 4) as usize;
-            let visible_steps = self.grid.steps_per_track.min(max_steps);
-            
-            for step in 1..=visible_steps {
-                buf.set_string(x, step_numbers_y, &format!("{:^4}", step), style);
+            // Steps in the loop are numbered in magenta, between [ and ] markers
+            let loop_style = Style::default().fg(Color::Magenta);
+            for step_idx in first_step..first_step + visible_steps {
+                let step = step_idx + 1;
+                if self.grid.is_in_loop(step_idx) {
+                    buf.set_string(x, step_numbers_y, format!("{:^4}", step), loop_style);
+                    if self.grid.loop_region.is_some_and(|(start, _)| start == step_idx) {
+                        buf.set_string(x, step_numbers_y, "[", loop_style);
+                    }
+                    if self.grid.loop_region.is_some_and(|(_, end)| end == step_idx) {
+                        buf.set_string(x + 3, step_numbers_y, "]", loop_style);
+                    }
+                } else {
+                    buf.set_string(x, step_numbers_y, format!("{:^4}", step), style);
+                }
                 x += 4;
             }
+            self.grid.render_scroll_indicators(area, first_step, visible_steps, step_numbers_y,
+                                               buf);
         }
     }
 }
@@ -162,10 +231,55 @@ pub enum SynthSection {
     Effects,
 }
 
+// Slider the mouse sets, found by where its bar was last drawn
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SliderTarget {
+    OscillatorFrequency,
+    OscillatorVolume,
+    FilterCutoff,
+    FilterResonance,
+    FilterMix,
+    EnvelopeAttack,
+    EnvelopeDecay,
+    EnvelopeSustain,
+    EnvelopeRelease,
+    TrackVolume(usize),
+}
+
+// Whether the terminal is too small for the layout to show every track
+fn is_too_small(area: Rect) -> bool {
+    area.width < MIN_TERMINAL_WIDTH || area.height < MIN_TERMINAL_HEIGHT
+}
+
+// Wait before the next try at reopening a lost output device after a try that waited `backoff`
+fn next_reopen_backoff(backoff: std::time::Duration) -> std::time::Duration {
+    (backoff * 2).min(MAX_REOPEN_BACKOFF)
+}
+
+// Whether a session with unsaved edits is due to be auto-saved, `elapsed` after it last was
+fn auto_save_due(elapsed: std::time::Duration, auto_save_secs: u64) -> bool {
+    auto_save_secs > 0 && elapsed.as_secs() >= auto_save_secs
+}
+
+// Bar of a slider in a row drawn from `text`, where its run of █ and ░ is, cut to the row. The
+// bar is found in the text as drawn, so a label before it can change without the clicks on it
+// missing.
+fn slider_bar_area(row: Rect, text: &str) -> Rect {
+    let is_bar = |c: &char| *c == '█' || *c == '░';
+    let offset = text.chars().take_while(|c| !is_bar(c)).count();
+    let width = text.chars().skip(offset).take_while(is_bar).count();
+    Rect::new(row.x.saturating_add(offset as u16), row.y, width as u16, 1).intersection(row)
+}
+
 #[derive(Debug)]
 pub struct UiState {
     pub show_help: bool,
     pub status_message: Option<String>,
+    // Asking whether to quit with edits that haven't been saved
+    pub confirm_quit: bool,
+    // Asking whether to restore the session auto-saved before the app last exited unexpectedly
+    pub confirm_recover: bool,
+    pub volume_display: VolumeDisplay,
 }
 
 impl Default for UiState {
@@ -173,6 +287,9 @@ impl Default for UiState {
         Self {
             show_help: false,
             status_message: None,
+            confirm_quit: false,
+            confirm_recover: false,
+            volume_display: VolumeDisplay::Percent,
         }
     }
 }
@@ -187,7 +304,13 @@ pub struct RoscoTuiApp {
     sequencer_panel: SequencerPanel,
     
     // Audio Engine Integration
-    audio_bridge: Option<AudioBridge>,
+    audio_state: Arc<AudioState>,
+    // Started on first Play so the TUI still runs without an output device
+    audio_engine: Option<AudioEngine>,
+    // Connected when the audio engine starts, if there is a MIDI input port
+    midi_input: Option<MidiInput>,
+    // Note sounding from a piano key and when it started, released after AUDITION_MS
+    auditioned_note: Option<(u8, std::time::Instant)>,
     
     // Synthesizer State
     synth_params: SynthParameters,
@@ -200,9 +323,31 @@ pub struct RoscoTuiApp {
     
     // Configuration
     config: TuiConfig,
+    // Whether the grid or synth has been edited since the session was last saved or loaded
+    dirty: bool,
+    // When the session was last auto-saved, or the app started
+    last_auto_save: std::time::Instant,
+    // Where unsaved edits are auto-saved, None without a config directory to keep them in
+    auto_save_path: Option<std::path::PathBuf>,
+    // When to next try reopening a lost output device, and how long to wait if that fails too
+    reopen_at: Option<(std::time::Instant, std::time::Duration)>,
     
     // Event handling
     event_handler: EventHandler,
+    // Where the grid and slider bars were last drawn, to find what a mouse click lands on
+    grid_area: Rect,
+    slider_areas: Vec<(SliderTarget, Rect)>,
+    // Slider the left button went down on, which follows the mouse until the button is released
+    dragged_slider: Option<SliderTarget>,
+
+    // Output levels reported by the audio callback, and when their peaks last fell back
+    track_meters: [LevelMeter; NUM_TRACKS],
+    master_meter: LevelMeter,
+    last_meter_decay: std::time::Instant,
+    // Latest output block from the audio callback, drawn as a waveform
+    scope: Scope,
+    // Set when the terminal is resized, so the next frame is drawn on a cleared screen
+    resized: bool,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -227,10 +372,25 @@ pub struct TransportState {
     pub is_playing: bool,
     pub is_recording: bool,
     pub tempo: f32,
+    pub swing: f32,
+    /// Gain on the whole mix, from 0.0 to 1.0
+    pub master_volume: f32,
+    /// Width of the mix's stereo image, from 0.0 mono through 1.0 as mixed to MAX_STEREO_WIDTH
+    pub stereo_width: f32,
+    pub clock_source: ClockSource,
+    /// Click on each beat while playing
+    pub metronome_on: bool,
+    pub time_signature: TimeSignature,
+    /// Tempo playback ramps to and the time in ms from the start of playback it gets there by
+    pub tempo_ramp: Option<(f32, f32)>,
     pub position: PlaybackPosition,
     pub focused_button: TransportButton,
-    pub current_step: usize, // 0-15 for 16 steps
+    pub current_step: usize, // 0 to steps_per_track - 1
+    /// Steps played since playback started, which unlike current_step never wraps
+    pub played_steps: u64,
     pub last_step_time: std::time::Instant,
+    /// When the tap tempo key was last pressed, oldest first, at most TAP_HISTORY of them
+    pub tap_times: std::collections::VecDeque<std::time::Instant>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -239,33 +399,180 @@ pub enum TransportButton {
     Stop,
 }
 
+impl TransportState {
+    /// Move the tempo by `delta` BPM, staying within MIN_TEMPO..MAX_TEMPO, and return it
+    pub fn adjust_tempo(&mut self, delta: f32) -> f32 {
+        self.tempo = (self.tempo + delta).clamp(MIN_TEMPO, MAX_TEMPO);
+        self.tempo
+    }
+
+    /// Move the master volume by `delta`, staying within 0.0..1.0, and return it
+    pub fn adjust_master_volume(&mut self, delta: f32) -> f32 {
+        self.master_volume = (self.master_volume + delta).clamp(0.0, 1.0);
+        self.master_volume
+    }
+
+    /// Move the stereo width by `delta`, staying within 0.0..MAX_STEREO_WIDTH, and return it
+    pub fn adjust_stereo_width(&mut self, delta: f32) -> f32 {
+        self.stereo_width = (self.stereo_width + delta).clamp(0.0, MAX_STEREO_WIDTH);
+        self.stereo_width
+    }
+
+    /// Ramp the tempo by `delta` BPM over TEMPO_RAMP_MEASURES from the start of playback, or go
+    /// back to a steady tempo if there is already a ramp, and return the ramp
+    pub fn toggle_tempo_ramp(&mut self, delta: f32) -> Option<(f32, f32)> {
+        self.tempo_ramp = match self.tempo_ramp {
+            Some(_) => None,
+            None => {
+                let beats = TEMPO_RAMP_MEASURES * self.time_signature.numerator.max(1) as u32;
+                let ramp_ms = beats as f32 * 60000.0 / self.tempo;
+                Some(((self.tempo + delta).clamp(MIN_TEMPO, MAX_TEMPO), ramp_ms))
+            }
+        };
+        self.tempo_ramp
+    }
+
+    /// Step to the next time signature in TIME_SIGNATURES, and return it
+    pub fn cycle_time_signature(&mut self) -> TimeSignature {
+        let current_idx = TIME_SIGNATURES.iter()
+            .position(|time_signature| *time_signature == self.time_signature);
+        self.time_signature = match current_idx {
+            Some(idx) => TIME_SIGNATURES[(idx + 1) % TIME_SIGNATURES.len()],
+            None => TIME_SIGNATURES[0],
+        };
+        self.time_signature
+    }
+
+    /// Record a tap of the tap tempo key at `now`, setting the tempo from the taps so far and
+    /// returning it once there are two
+    pub fn tap_tempo(&mut self, now: std::time::Instant) -> Option<f32> {
+        if self.tap_times.back().is_some_and(|last| now.duration_since(*last) > TAP_TIMEOUT) {
+            self.tap_times.clear();
+        }
+        if self.tap_times.len() == TAP_HISTORY {
+            self.tap_times.pop_front();
+        }
+        self.tap_times.push_back(now);
+
+        let tempo = tempo_from_taps(self.tap_times.make_contiguous())?;
+        self.tempo = tempo.clamp(MIN_TEMPO, MAX_TEMPO);
+        Some(self.tempo)
+    }
+}
+
+/// BPM of a beat on each tap, from the average interval between them once intervals far from
+/// the median are left out. None for fewer than two taps.
+pub fn tempo_from_taps(taps: &[std::time::Instant]) -> Option<f32> {
+    let mut intervals: Vec<f32> = taps.windows(2)
+        .map(|pair| pair[1].duration_since(pair[0]).as_secs_f32())
+        .filter(|interval| *interval > 0.0)
+        .collect();
+    if intervals.is_empty() {
+        return None;
+    }
+    let mut sorted = intervals.clone();
+    sorted.sort_by(f32::total_cmp);
+    let median = sorted[sorted.len() / 2];
+    intervals.retain(|interval| (interval - median).abs() <= median * TAP_OUTLIER_TOLERANCE);
+    let average = intervals.iter().sum::<f32>() / intervals.len() as f32;
+    Some(60.0 / average)
+}
+
 impl Default for TransportState {
     fn default() -> Self {
         Self {
             is_playing: false,
             is_recording: false,
             tempo: 120.0,
+            swing: 0.0,
+            master_volume: 1.0,
+            stereo_width: 1.0,
+            clock_source: ClockSource::Internal,
+            metronome_on: false,
+            time_signature: TimeSignature::default(),
+            tempo_ramp: None,
             position: PlaybackPosition::default(),
             focused_button: TransportButton::Play,
             current_step: 0,
+            played_steps: 0,
             last_step_time: std::time::Instant::now(),
+            tap_times: std::collections::VecDeque::with_capacity(TAP_HISTORY),
         }
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature {
+    /// Beats in a measure
+    pub numerator: u8,
+    /// Note value of a beat, 4 for quarter notes, 8 for eighths
+    pub denominator: u8,
+}
+
+/// Time signatures the transport cycles through
+pub const TIME_SIGNATURES: [TimeSignature; 5] = [
+    TimeSignature { numerator: 4, denominator: 4 },
+    TimeSignature { numerator: 3, denominator: 4 },
+    TimeSignature { numerator: 6, denominator: 8 },
+    TimeSignature { numerator: 5, denominator: 4 },
+    TimeSignature { numerator: 7, denominator: 8 },
+];
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self { numerator: 4, denominator: 4 }
+    }
+}
+
+impl std::fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// Position in bar:beat:tick, with measures and beats counted from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PlaybackPosition {
     pub measure: u32,
     pub beat: u8,
     pub tick: u16,
 }
 
+impl Default for PlaybackPosition {
+    fn default() -> Self {
+        Self { measure: 1, beat: 1, tick: 0 }
+    }
+}
+
+impl PlaybackPosition {
+    /// Position after a number of steps, each lasting the step duration, from the start
+    pub(crate) fn from_steps(steps: usize, step_duration: DurationType,
+                             time_signature: TimeSignature) -> Self {
+        let numerator = time_signature.numerator.max(1) as u64;
+        let denominator = time_signature.denominator.max(1) as f64;
+        // Duration factors are fractions of a whole note, and a beat is 1/denominator of one
+        let ticks_per_step = step_duration.to_factor() as f64 * denominator * TICKS_PER_BEAT as f64;
+        let ticks = (steps as f64 * ticks_per_step).round() as u64;
+        let ticks_per_measure = TICKS_PER_BEAT as u64 * numerator;
+        let ticks_in_measure = ticks % ticks_per_measure;
+        Self {
+            measure: (ticks / ticks_per_measure) as u32 + 1,
+            beat: (ticks_in_measure / TICKS_PER_BEAT as u64) as u8 + 1,
+            tick: (ticks_in_measure % TICKS_PER_BEAT as u64) as u16,
+        }
+    }
+}
+
 impl RoscoTuiApp {
     pub fn new() -> Result<Self, TuiError> {
         println!("Loading TUI config...");
         let config = TuiConfig::load_or_default()?;
         println!("Config loaded successfully");
-        
+        Ok(Self::with_config(config))
+    }
+
+    /// An app with this configuration rather than the one saved in the config file
+    pub fn with_config(config: TuiConfig) -> Self {
         println!("Creating event handler...");
         let event_handler = EventHandler::new();
         println!("Event handler created");
@@ -286,26 +593,44 @@ impl RoscoTuiApp {
         let transport = TransportState::default();
         println!("Transport state created");
         
+        let ui_state = UiState {
+            status_message: (!config.unknown_key_bindings.is_empty()).then(|| format!(
+                "Unknown actions in key bindings: {}", config.unknown_key_bindings.join(", "))),
+            ..UiState::default()
+        };
+
         println!("Constructing final app struct...");
-        Ok(Self {
-            ui_state: UiState::default(),
+        Self {
+            ui_state,
             current_focus: FocusArea::Synthesizer(SynthSection::Oscillator),
             synthesizer_panel,
             sequencer_panel,
-            audio_bridge: None,
+            audio_state: Arc::new(AudioState::new()),
+            audio_engine: None,
+            midi_input: None,
+            auditioned_note: None,
             synth_params,
             tracks: Vec::new(),
             transport,
             config,
+            dirty: false,
+            last_auto_save: std::time::Instant::now(),
+            auto_save_path: SessionState::auto_save_path().ok(),
+            reopen_at: None,
             event_handler,
-        })
+            grid_area: Rect::default(),
+            slider_areas: Vec::new(),
+            dragged_slider: None,
+            track_meters: std::array::from_fn(|_| LevelMeter::new(LEVEL_METER_WIDTH,
+                                                                  MeterOrientation::Horizontal)),
+            master_meter: LevelMeter::new(LEVEL_METER_WIDTH, MeterOrientation::Horizontal),
+            last_meter_decay: std::time::Instant::now(),
+            scope: Scope::new(),
+            resized: false,
+        }
     }
     
     pub async fn run(&mut self) -> Result<(), TuiError> {
-        // Temporarily disable audio bridge to test if this fixes the string boundary panic
-        println!("Skipping audio bridge initialization to test TUI without audio...");
-        self.audio_bridge = None;
-        
         // Setup terminal
         if let Err(e) = enable_raw_mode() {
             eprintln!("Warning: Cannot enable raw mode ({}). TUI may not work properly.", e);
@@ -313,7 +638,7 @@ impl RoscoTuiApp {
             return Err(TuiError::Terminal(format!("Terminal access required. Error: {}", e)));
         }
         let mut stdout = io::stdout();
-        execute!(stdout, EnterAlternateScreen)?;
+        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
         
@@ -324,7 +649,8 @@ impl RoscoTuiApp {
         disable_raw_mode()?;
         execute!(
             terminal.backend_mut(),
-            LeaveAlternateScreen
+            LeaveAlternateScreen,
+            DisableMouseCapture
         )?;
         terminal.show_cursor()?;
         
@@ -332,21 +658,396 @@ impl RoscoTuiApp {
     }
     
     async fn run_app<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<(), TuiError> {
+        // An auto-save left behind means the app didn't exit cleanly last time
+        self.ui_state.confirm_recover =
+            self.auto_save_path.as_ref().is_some_and(|path| path.exists());
         loop {
             // Update transport timing
             self.update_transport_timing();
+            self.apply_midi_control_changes()?;
+            self.release_auditioned_note(false);
+            self.sync_audio_state();
+            if let Some(engine) = &mut self.audio_engine {
+                engine.apply_parameter_updates();
+            }
+            self.apply_audio_feedback();
+            self.auto_save();
             
+            // After a resize the whole screen is cleared, so nothing drawn for the old size lingers
+            if std::mem::take(&mut self.resized) {
+                terminal.clear()?;
+            }
             terminal.draw(|f| self.update_ui(f))?;
             
             if self.handle_events().await? {
                 break;
             }
         }
+        // Nothing to recover after a clean exit
+        self.remove_auto_save();
         Ok(())
     }
     
+    // Meters and the scope follow what the audio callback reports, the meters' peaks falling back
+    // as it quiets. An engine whose device has gone is rebuilt, and until it can be, tried again
+    // with a growing wait between tries.
+    fn apply_audio_feedback(&mut self) {
+        let feedback = self.audio_engine.as_mut()
+            .map(|engine| engine.receive_audio_feedback())
+            .unwrap_or_default();
+        for feedback in feedback {
+            self.apply_feedback(feedback);
+        }
+        if self.audio_engine.as_ref().is_some_and(AudioEngine::is_device_lost) {
+            self.audio_engine = None;
+            self.transport.is_recording = false;
+            self.rebuild_audio_engine(REOPEN_BACKOFF);
+        } else if let Some((reopen_at, backoff)) = self.reopen_at {
+            // Play opens the engine again too
+            if self.audio_engine.is_some() {
+                self.reopen_at = None;
+            } else if std::time::Instant::now() >= reopen_at {
+                self.rebuild_audio_engine(backoff);
+            }
+        }
+
+        let now = std::time::Instant::now();
+        let elapsed = now - self.last_meter_decay;
+        self.last_meter_decay = now;
+        self.master_meter.decay_peak(elapsed);
+        for meter in self.track_meters.iter_mut() {
+            meter.decay_peak(elapsed);
+        }
+    }
+
+    fn apply_feedback(&mut self, feedback: AudioFeedback) {
+        match feedback {
+            AudioFeedback::Levels { master_peak, master_rms, track_peaks } => {
+                self.master_meter.update_level(master_rms);
+                self.master_meter.update_peak(master_peak);
+                for (meter, peak) in self.track_meters.iter_mut().zip(track_peaks) {
+                    meter.update_level(peak);
+                }
+            }
+            AudioFeedback::Waveform(samples) => self.scope.update_samples(&samples),
+            AudioFeedback::StreamError(error) => {
+                self.ui_state.status_message = Some(format!("Audio error: {}", error));
+            }
+            _ => {}
+        }
+    }
+
+    // Replace a lost engine with a new stream on the configured device, as when an interface is
+    // plugged back in, or try again after `backoff` if it can't be opened yet. A recording in
+    // progress was finished when the old engine went.
+    fn rebuild_audio_engine(&mut self, backoff: std::time::Duration) {
+        if self.open_audio_engine() {
+            self.reopen_at = None;
+            self.ui_state.status_message =
+                Some("Audio device was lost, output restarted".to_string());
+        } else {
+            self.reopen_at =
+                Some((std::time::Instant::now() + backoff, next_reopen_backoff(backoff)));
+        }
+    }
+
+    // Controller changes move the matching controls, and so show on screen, as well as the sound
+    fn apply_midi_control_changes(&mut self) -> Result<(), TuiError> {
+        use crate::tui::audio_bridge::ParameterUpdate;
+
+        let updates = match &self.midi_input {
+            Some(midi_input) => midi_input.receive_control_changes(),
+            None => return Ok(()),
+        };
+        for update in updates {
+            match &update {
+                ParameterUpdate::OscillatorFrequency(freq) => {
+                    self.synthesizer_panel.oscillator.frequency_slider.set_value(*freq);
+                    self.synth_params.oscillator_frequency = *freq;
+                }
+                ParameterUpdate::OscillatorVolume(vol) => {
+                    self.synthesizer_panel.oscillator.volume_slider.set_value(*vol);
+                    self.synth_params.oscillator_volume = *vol;
+                }
+                ParameterUpdate::FilterCutoff(cutoff) => {
+                    self.synthesizer_panel.filter.cutoff_slider.set_value(*cutoff);
+                }
+                ParameterUpdate::FilterResonance(resonance) => {
+                    self.synthesizer_panel.filter.resonance_slider.set_value(*resonance);
+                }
+                ParameterUpdate::Swing(swing) => {
+                    self.transport.swing = swing.clamp(0.0, MAX_SWING);
+                }
+                _ => {}
+            }
+            self.send_parameter_update_real_time(update)?;
+        }
+        Ok(())
+    }
+
+    // Sound a note played into a step, through the live notes a MIDI keyboard plays
+    fn audition_note(&mut self, midi_note: u8, velocity: u8) {
+        self.release_auditioned_note(true);
+        self.start_audio_engine();
+        self.audio_state.handle_parameter_update(
+            &crate::tui::audio_bridge::ParameterUpdate::MidiNoteOn { note: midi_note, velocity });
+        self.auditioned_note = Some((midi_note, std::time::Instant::now()));
+    }
+
+    // Stop the auditioned note once it has sounded long enough, or straight away if forced
+    fn release_auditioned_note(&mut self, force: bool) {
+        let Some((midi_note, started)) = self.auditioned_note else {
+            return;
+        };
+        if force || started.elapsed().as_millis() >= AUDITION_MS {
+            self.audio_state.handle_parameter_update(
+                &crate::tui::audio_bridge::ParameterUpdate::MidiNoteOff { note: midi_note });
+            self.auditioned_note = None;
+        }
+    }
+
+    // Push the grid and transport to the audio engine
+    fn sync_audio_state(&mut self) {
+        self.audio_state.sync_from_grid(&self.sequencer_panel.grid);
+        self.audio_state.tempo.store(self.transport.tempo, Ordering::Relaxed);
+        self.audio_state.metronome_on.store(self.transport.metronome_on, Ordering::Relaxed);
+        self.audio_state.beats_per_measure.store(
+            self.transport.time_signature.numerator.max(1) as usize, Ordering::Relaxed);
+        self.audio_state.beat_unit.store(
+            self.transport.time_signature.denominator.max(1) as usize, Ordering::Relaxed);
+        // MIDI start and stop drive the engine directly when following external clock
+        if self.transport.clock_source == ClockSource::Internal {
+            self.audio_state.is_playing.store(self.transport.is_playing, Ordering::Relaxed);
+        }
+    }
+
+    // Push a track's pan, mute, solo and waveform to the audio engine
+    fn sync_track_mix(&mut self, track_idx: usize) {
+        let track = &self.sequencer_panel.grid.tracks[track_idx];
+        self.audio_state.track_pans[track_idx].store(track.pan, Ordering::Relaxed);
+        self.audio_state.track_mutes[track_idx].store(track.mute, Ordering::Relaxed);
+        self.audio_state.track_solos[track_idx].store(track.solo, Ordering::Relaxed);
+        self.audio_state.handle_parameter_update(
+            &crate::tui::audio_bridge::ParameterUpdate::TrackWaveform {
+                track: track_idx as u8,
+                waveform: track.waveform,
+            });
+    }
+
+    fn start_audio_engine(&mut self) {
+        if self.audio_engine.is_some() || !self.open_audio_engine() {
+            return;
+        }
+
+        // Having no MIDI keyboard is normal, so only say so if a port was asked for
+        match MidiInput::connect(self.config.midi_input_port.as_deref(),
+                                 Arc::clone(&self.audio_state),
+                                 self.config.midi_cc_map.clone()) {
+            Ok(midi_input) => {
+                self.ui_state.status_message =
+                    Some(format!("MIDI input: {}", midi_input.port_name()));
+                self.midi_input = Some(midi_input);
+            }
+            Err(e) if self.config.midi_input_port.is_some() => {
+                self.ui_state.status_message = Some(format!("{}", e));
+            }
+            Err(_) => {}
+        }
+    }
+
+    // Output to the configured device, with the tracks' mix and the synth's waveform. False, with
+    // the reason in the status bar, when there is no output.
+    fn open_audio_engine(&mut self) -> bool {
+        for track_idx in 0..self.sequencer_panel.grid.tracks.len() {
+            self.sync_track_mix(track_idx);
+        }
+        // MIDI input plays with the synth's oscillator waveform
+        self.audio_state.handle_parameter_update(
+            &crate::tui::audio_bridge::ParameterUpdate::OscillatorWaveform(
+                self.synth_params.oscillator_waveform));
+        let audio_state = Arc::clone(&self.audio_state);
+        let engine = match &self.config.audio_device {
+            Some(device_name) => AudioEngine::new_with_device(audio_state, device_name,
+                                                              self.config.buffer_size),
+            None => AudioEngine::new(audio_state, self.config.buffer_size),
+        };
+        match engine {
+            Ok(engine) => {
+                self.audio_engine = Some(engine);
+                true
+            }
+            Err(e) => {
+                self.ui_state.status_message = Some(format!("{}", e));
+                false
+            }
+        }
+    }
+
+    fn session_state(&self) -> SessionState {
+        SessionState {
+            synth_params: self.synth_params.clone(),
+            tempo: self.transport.tempo,
+            transport_playing: self.transport.is_playing,
+            steps_per_track: self.sequencer_panel.grid.steps_per_track,
+            tracks: self.sequencer_panel.grid.tracks.to_vec(),
+        }
+    }
+
+    /// Write the grid, synth parameters and tempo to a session file
+    pub fn save_session(&mut self, path: &std::path::Path) -> Result<(), TuiError> {
+        self.session_state().save_to_file(path)?;
+        self.dirty = false;
+        // The saved session has everything the auto-save had
+        self.remove_auto_save();
+        Ok(())
+    }
+
+    fn remove_auto_save(&self) {
+        if let Some(path) = &self.auto_save_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    // Write unsaved edits to the auto-save every auto_save_secs, so they survive a crash. The
+    // edits are still unsaved until the session is saved.
+    fn auto_save(&mut self) {
+        if !self.dirty || !auto_save_due(self.last_auto_save.elapsed(),
+                                         self.config.auto_save_secs) {
+            return;
+        }
+        self.last_auto_save = std::time::Instant::now();
+        let Some(path) = &self.auto_save_path else {
+            return;
+        };
+        if let Err(e) = self.session_state().save_to_file(path) {
+            self.ui_state.status_message = Some(format!("Auto-save failed: {}", e));
+        }
+    }
+
+    // Restore the auto-saved session, or discard it, once asked on startup
+    fn recover_auto_save(&mut self, recover: bool) {
+        let Some(path) = self.auto_save_path.clone() else {
+            return;
+        };
+        if !recover {
+            self.remove_auto_save();
+            self.ui_state.status_message = Some("Auto-save discarded".to_string());
+            return;
+        }
+        self.ui_state.status_message = Some(match self.load_session(&path) {
+            Ok(()) => {
+                // Recovered edits haven't been saved
+                self.dirty = true;
+                "Session recovered from auto-save".to_string()
+            }
+            Err(e) => format!("Auto-save not recovered: {}", e),
+        });
+    }
+
+    /// Restore a session file written by save_session. Playback is left as it is.
+    pub fn load_session(&mut self, path: &std::path::Path) -> Result<(), TuiError> {
+        use crate::tui::audio_bridge::ParameterUpdate;
+
+        let session = SessionState::load_from_file(path)?;
+        self.sequencer_panel.grid.restore_tracks(session.tracks, session.steps_per_track);
+        for track_idx in 0..self.sequencer_panel.grid.tracks.len() {
+            self.sync_track_mix(track_idx);
+        }
+        self.transport.tempo = session.tempo.clamp(MIN_TEMPO, MAX_TEMPO);
+
+        let synth_params = session.synth_params;
+        let oscillator = &mut self.synthesizer_panel.oscillator;
+        oscillator.waveform_selector.selected = oscillator.waveform_selector.options.iter()
+            .position(|waveform| *waveform == synth_params.oscillator_waveform)
+            .unwrap_or(0);
+        oscillator.frequency_slider.set_value(synth_params.oscillator_frequency);
+        oscillator.volume_slider.set_value(synth_params.oscillator_volume);
+        for update in [ParameterUpdate::OscillatorWaveform(synth_params.oscillator_waveform),
+                       ParameterUpdate::OscillatorFrequency(synth_params.oscillator_frequency),
+                       ParameterUpdate::OscillatorVolume(synth_params.oscillator_volume),
+                       ParameterUpdate::TempoChange(self.transport.tempo)] {
+            self.send_parameter_update_real_time(update)?;
+        }
+        self.synth_params = synth_params;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn save_session_to_default_path(&mut self) {
+        let saved = SessionState::default_path().and_then(|path| {
+            self.save_session(&path)?;
+            Ok(path)
+        });
+        self.ui_state.status_message = Some(match saved {
+            Ok(path) => format!("Session saved to {}", path.display()),
+            Err(e) => format!("Session not saved: {}", e),
+        });
+    }
+
+    fn load_session_from_default_path(&mut self) {
+        let loaded = SessionState::default_path().and_then(|path| {
+            self.load_session(&path)?;
+            Ok(path)
+        });
+        self.ui_state.status_message = Some(match loaded {
+            Ok(path) => format!("Session loaded from {}", path.display()),
+            Err(e) => format!("Session not loaded: {}", e),
+        });
+    }
+
+    fn toggle_recording(&mut self) -> Result<(), TuiError> {
+        use crate::tui::audio_bridge::ParameterUpdate;
+
+        if self.transport.is_recording {
+            self.transport.is_recording = false;
+            self.ui_state.status_message = match self.send_parameter_update_real_time(
+                ParameterUpdate::RecordStop) {
+                Ok(()) => Some("Recording stopped".to_string()),
+                Err(e) => Some(format!("{}", e)),
+            };
+            return Ok(());
+        }
+
+        self.start_audio_engine();
+        if self.audio_engine.is_none() {
+            return Ok(());
+        }
+        let path = format!("rosco_recording_{}.wav",
+                           chrono::Local::now().format("%Y%m%d_%H%M%S"));
+        match self.send_parameter_update_real_time(
+            ParameterUpdate::RecordStart { path: path.clone() }) {
+            Ok(()) => {
+                self.transport.is_recording = true;
+                self.ui_state.status_message = Some(format!("Recording to {}", path));
+            }
+            Err(e) => self.ui_state.status_message = Some(format!("{}", e)),
+        }
+        Ok(())
+    }
+
+    /// Write the sequencer grid at the current tempo to a DSL script in the working directory
+    fn export_dsl(&mut self) {
+        let path = format!("rosco_export_{}.dsl", chrono::Local::now().format("%Y%m%d_%H%M%S"));
+        let dsl = track_grid_to_dsl(&self.sequencer_panel.grid.to_track_grid(self.transport.tempo));
+        self.ui_state.status_message = Some(match std::fs::write(&path, dsl) {
+            Ok(()) => format!("Grid exported to {}", path),
+            Err(e) => format!("Grid not exported: {}", e),
+        });
+    }
+
     fn update_transport_timing(&mut self) {
-        if self.transport.is_playing {
+        if self.transport.clock_source == ClockSource::MidiExternal {
+            self.transport.is_playing = self.audio_state.is_playing.load(Ordering::Relaxed);
+        }
+        if self.transport.is_playing && self.audio_engine.is_some() {
+            // Follow the steps the audio engine is playing
+            self.transport.current_step = self.audio_state.current_step.load(Ordering::Relaxed);
+            self.transport.played_steps = self.audio_state.played_steps.load(Ordering::Relaxed);
+            self.sequencer_panel.grid.playing_step = Some(self.transport.current_step);
+            self.sequencer_panel.grid.set_track_playing_steps(std::array::from_fn(|track_idx| {
+                Some(self.audio_state.track_current_steps[track_idx].load(Ordering::Relaxed))
+            }));
+        } else if self.transport.is_playing {
             let now = std::time::Instant::now();
             let elapsed = now.duration_since(self.transport.last_step_time);
             
@@ -356,76 +1057,342 @@ impl RoscoTuiApp {
             let step_interval = std::time::Duration::from_secs_f32(60.0 / self.transport.tempo);
             
             if elapsed >= step_interval {
-                // Advance to next step (1-16, wrapping)
-                self.transport.current_step = (self.transport.current_step + 1) % 16;
+                // Advance to next step, wrapping at the end of the sequence or loop
+                self.transport.current_step =
+                    self.sequencer_panel.grid.next_playing_step(self.transport.current_step);
+                self.transport.played_steps += 1;
                 self.transport.last_step_time = now;
                 
                 // Update the sequencer grid's playing step for highlighting
                 self.sequencer_panel.grid.set_playing_step(Some(self.transport.current_step));
             }
         }
+        // From the steps played rather than the step in the sequence, so the measure counts on
+        // when the sequence wraps
+        self.transport.position = PlaybackPosition::from_steps(
+            self.transport.played_steps as usize, SEQUENCER_STEP_DURATION,
+            self.transport.time_signature);
     }
     
     async fn handle_events(&mut self) -> Result<bool, TuiError> {
         if event::poll(std::time::Duration::from_millis(16))? {
-            if let Event::Key(key) = event::read()? {
-                return Ok(self.handle_key_event(key)?);
+            match event::read()? {
+                Event::Key(key) => return self.handle_key_event(key),
+                Event::Mouse(mouse) => self.handle_mouse_event(mouse)?,
+                Event::Resize(_, _) => self.resized = true,
+                _ => {}
             }
         }
         Ok(false)
     }
+
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) -> Result<(), TuiError> {
+        // Overlays cover what was drawn underneath, so clicks would land on hidden controls
+        if self.ui_state.show_help || self.ui_state.confirm_quit || self.ui_state.confirm_recover ||
+            self.sequencer_panel.is_pattern_browser_visible() {
+            return Ok(());
+        }
+
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.ui_state.status_message = None;
+                let slider = self.slider_areas.iter()
+                    .find(|(_, bar)| bar.contains((mouse.column, mouse.row).into()))
+                    .map(|(target, _)| *target);
+                if let Some(target) = slider {
+                    // A drag across a track's volume is one edit, undone all at once
+                    if matches!(target, SliderTarget::TrackVolume(_)) {
+                        self.sequencer_panel.remember_for_undo();
+                    }
+                    self.dragged_slider = Some(target);
+                    self.set_slider_from_mouse(target, mouse.column)?;
+                } else {
+                    self.click_grid(mouse.column, mouse.row)?;
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let Some(target) = self.dragged_slider {
+                    self.set_slider_from_mouse(target, mouse.column)?;
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.dragged_slider = None,
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                self.ui_state.status_message = None;
+                self.scroll_focused_parameter(mouse.kind == MouseEventKind::ScrollUp)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // A click on a step toggles it, and one on a frequency moves the cursor there to edit it
+    fn click_grid(&mut self, column: u16, row: u16) -> Result<(), TuiError> {
+        use crate::tui::ui::widgets::CursorFocus;
+
+        let Some((track, step, focus)) =
+            self.sequencer_panel.grid.cell_at(self.grid_area, column, row) else {
+            return Ok(());
+        };
+        self.current_focus = FocusArea::Sequencer;
+        let cursor = &mut self.sequencer_panel.grid.cursor;
+        cursor.track = track;
+        cursor.step = step;
+        cursor.focus_area = focus.clone();
+        if focus == CursorFocus::Steps {
+            self.handle_activation()?;
+        } else {
+            self.ui_state.status_message = Some(format!("Track {} step {} frequency",
+                                                       track + 1, step + 1));
+        }
+        Ok(())
+    }
+
+    // Set a slider from the column the mouse is at along its bar, focusing the slider's control
+    fn set_slider_from_mouse(&mut self, target: SliderTarget, column: u16) -> Result<(), TuiError> {
+        use crate::tui::audio_bridge::ParameterUpdate;
+        use crate::tui::ui::synthesizer::{EnvelopeSubSection, FilterSubSection,
+                                          OscillatorSubSection};
+
+        let Some((_, bar)) = self.slider_areas.iter().find(|(t, _)| *t == target) else {
+            return Ok(());
+        };
+        let position = column.saturating_sub(bar.x) as f32 /
+            bar.width.saturating_sub(1).max(1) as f32;
+
+        let synth = &mut self.synthesizer_panel;
+        let update = match target {
+            SliderTarget::OscillatorFrequency => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Oscillator);
+                synth.current_section = OscillatorSubSection::Frequency;
+                synth.oscillator.frequency_slider.set_normalized(position);
+                ParameterUpdate::OscillatorFrequency(synth.oscillator.frequency_slider.value)
+            }
+            SliderTarget::OscillatorVolume => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Oscillator);
+                synth.current_section = OscillatorSubSection::Volume;
+                synth.oscillator.volume_slider.set_normalized(position);
+                ParameterUpdate::OscillatorVolume(synth.oscillator.volume_slider.value)
+            }
+            SliderTarget::FilterCutoff => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Filter);
+                synth.filter.sub_focus = FilterSubSection::Cutoff;
+                synth.filter.cutoff_slider.set_normalized(position);
+                ParameterUpdate::FilterCutoff(synth.filter.cutoff_slider.value)
+            }
+            SliderTarget::FilterResonance => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Filter);
+                synth.filter.sub_focus = FilterSubSection::Resonance;
+                synth.filter.resonance_slider.set_normalized(position);
+                ParameterUpdate::FilterResonance(synth.filter.resonance_slider.value)
+            }
+            SliderTarget::FilterMix => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Filter);
+                synth.filter.sub_focus = FilterSubSection::Mix;
+                synth.filter.mix_slider.set_normalized(position);
+                ParameterUpdate::FilterMix(synth.filter.mix_slider.value)
+            }
+            SliderTarget::EnvelopeAttack => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Envelope);
+                synth.envelope.sub_focus = EnvelopeSubSection::Attack;
+                synth.envelope.attack_slider.set_normalized(position);
+                ParameterUpdate::EnvelopeAttack(synth.envelope.attack_slider.value)
+            }
+            SliderTarget::EnvelopeDecay => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Envelope);
+                synth.envelope.sub_focus = EnvelopeSubSection::Decay;
+                synth.envelope.decay_slider.set_normalized(position);
+                ParameterUpdate::EnvelopeDecay(synth.envelope.decay_slider.value)
+            }
+            SliderTarget::EnvelopeSustain => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Envelope);
+                synth.envelope.sub_focus = EnvelopeSubSection::Sustain;
+                synth.envelope.sustain_slider.set_normalized(position);
+                ParameterUpdate::EnvelopeSustain(synth.envelope.sustain_slider.value)
+            }
+            SliderTarget::EnvelopeRelease => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Envelope);
+                synth.envelope.sub_focus = EnvelopeSubSection::Release;
+                synth.envelope.release_slider.set_normalized(position);
+                ParameterUpdate::EnvelopeRelease(synth.envelope.release_slider.value)
+            }
+            SliderTarget::TrackVolume(track_idx) => {
+                self.current_focus = FocusArea::TrackVolume;
+                let grid = &mut self.sequencer_panel.grid;
+                grid.cursor.track = track_idx as u8;
+                grid.cursor.focus_area = crate::tui::ui::widgets::CursorFocus::TrackControls;
+                let track = &mut grid.tracks[track_idx];
+                track.selected_control = crate::tui::ui::widgets::TrackControl::Volume;
+                track.adjust_volume(position - track.volume);
+                self.dirty = true;
+                self.ui_state.status_message = Some(format!("Track {} Volume: {}",
+                    track.track_number, self.ui_state.volume_display.format(track.volume)));
+                return Ok(());
+            }
+        };
+        self.apply_synth_updates(vec![update])
+    }
+
+    // The wheel turns the focused control the way Right and Left do, or Shift+Up and Shift+Down
+    // in the grid
+    fn scroll_focused_parameter(&mut self, up: bool) -> Result<(), TuiError> {
+        let key = match self.current_focus {
+            FocusArea::Sequencer => KeyEvent::new(if up { KeyCode::Up } else { KeyCode::Down },
+                                                  KeyModifiers::SHIFT),
+            FocusArea::Transport => KeyEvent::new(if up { KeyCode::Up } else { KeyCode::Down },
+                                                  KeyModifiers::NONE),
+            _ => KeyEvent::new(if up { KeyCode::Right } else { KeyCode::Left },
+                               KeyModifiers::NONE),
+        };
+        self.handle_navigation(key)
+    }
     
     fn handle_key_event(&mut self, key: KeyEvent) -> Result<bool, TuiError> {
         // Clear status message on any input
         self.ui_state.status_message = None;
+
+        // Only y quits once asked to confirm, any other key goes back to the app
+        if self.ui_state.confirm_quit {
+            self.ui_state.confirm_quit = false;
+            if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                return Ok(true);
+            }
+            self.ui_state.status_message = Some("Quit cancelled".to_string());
+            return Ok(false);
+        }
+
+        // Likewise only y recovers the auto-save, any other key discards it
+        if self.ui_state.confirm_recover {
+            self.ui_state.confirm_recover = false;
+            self.recover_auto_save(matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')));
+            return Ok(false);
+        }
+
+        // The pattern browser takes every key while it is open, so Esc closes it rather than quits
+        if matches!(self.current_focus, FocusArea::Sequencer) &&
+            self.sequencer_panel.is_pattern_browser_visible() {
+            self.handle_navigation(key)?;
+            return Ok(false);
+        }
+
+        // Bound keys come first, so a key rebound to an action no longer does what it did in a
+        // section
+        let action = key_name(&key).and_then(|name| self.config.key_bindings.get(&name).copied());
+        if let Some(action) = action {
+            return self.handle_action(action, key);
+        }
         
         match key.code {
-            KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
-            KeyCode::F(1) => self.ui_state.show_help = !self.ui_state.show_help,
-            KeyCode::Tab => self.cycle_focus(),
-            KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right => {
+            // Track waveform, length, divisor and Euclidean fill, sequence length, step probability,
+            // gate, scale, chord, transpose, loop and ramp keys
+            KeyCode::Char('w') | KeyCode::Char('W') | KeyCode::Char('[') | KeyCode::Char(']') |
+            KeyCode::Char(',') | KeyCode::Char('.') | KeyCode::Char('{') | KeyCode::Char('}') |
+            KeyCode::Char('<') | KeyCode::Char('>') | KeyCode::Char('(') | KeyCode::Char(')') |
+            KeyCode::Char('k') | KeyCode::Char('K') | KeyCode::Char('m') | KeyCode::Char('M') |
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Char('p') | KeyCode::Char('P') |
+            KeyCode::Char('t') | KeyCode::Char('T') | KeyCode::Char('o') | KeyCode::Char('O') |
+            KeyCode::Char('i') | KeyCode::Char('l') | KeyCode::Char('L') |
+            KeyCode::Char('/') | KeyCode::Char('?')
+                if matches!(self.current_focus, FocusArea::Sequencer) => {
                 self.handle_navigation(key)?;
             }
-            KeyCode::Enter | KeyCode::Char(' ') => {
-                self.handle_activation()?;
+            // Piano keys mode, and the letters that play pitches into steps while it is on
+            KeyCode::F(3) if matches!(self.current_focus, FocusArea::Sequencer) => {
+                self.handle_navigation(key)?;
             }
-            // Quick section switching with number keys
-            KeyCode::Char('1') => {
-                self.current_focus = FocusArea::Synthesizer(SynthSection::Oscillator);
-                self.ui_state.status_message = Some("Oscillator section".to_string());
+            KeyCode::Char(_) if matches!(self.current_focus, FocusArea::Sequencer) &&
+                self.sequencer_panel.is_piano_key(key) => {
+                self.handle_navigation(key)?;
             }
-            KeyCode::Char('2') => {
-                self.current_focus = FocusArea::Synthesizer(SynthSection::Filter);
-                self.ui_state.status_message = Some("Filter section".to_string());
+            // Undo and redo grid edits
+            KeyCode::Char('z') | KeyCode::Char('Z') | KeyCode::Char('y')
+                if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) &&
+                    matches!(self.current_focus, FocusArea::Sequencer) => {
+                self.handle_navigation(key)?;
             }
-            KeyCode::Char('3') => {
-                self.current_focus = FocusArea::Synthesizer(SynthSection::Envelope);
-                self.ui_state.status_message = Some("Envelope section".to_string());
+            // Copy and paste a single step
+            KeyCode::Char('c') | KeyCode::Char('v')
+                if key.modifiers.contains(crossterm::event::KeyModifiers::ALT) &&
+                    matches!(self.current_focus, FocusArea::Sequencer) => {
+                self.handle_navigation(key)?;
             }
-            KeyCode::Char('4') => {
-                self.current_focus = FocusArea::Synthesizer(SynthSection::Effects);
-                self.ui_state.status_message = Some("Effects section".to_string());
+            KeyCode::Char('c') if matches!(self.current_focus, FocusArea::Transport) => {
+                self.toggle_clock_source();
             }
-            KeyCode::Char('5') => {
-                self.current_focus = FocusArea::Sequencer;
-                self.ui_state.status_message = Some("Track Sequencer section".to_string());
+            KeyCode::Char('a') | KeyCode::Char('A')
+                if matches!(self.current_focus, FocusArea::Transport) => {
+                let delta = if key.code == KeyCode::Char('a') { TEMPO_RAMP_BPM } else {
+                    -TEMPO_RAMP_BPM
+                };
+                let tempo_ramp = self.transport.toggle_tempo_ramp(delta);
+                self.send_parameter_update_real_time(
+                    crate::tui::audio_bridge::ParameterUpdate::TempoRamp(tempo_ramp))?;
+                self.ui_state.status_message = Some(match tempo_ramp {
+                    Some((ramp_bpm, _)) => format!("Tempo ramps to {:.0} BPM over {} measures",
+                                                   ramp_bpm, TEMPO_RAMP_MEASURES),
+                    None => "Steady tempo".to_string(),
+                });
             }
-            KeyCode::Char('6') => {
-                self.current_focus = FocusArea::TrackVolume;
-                self.ui_state.status_message = Some("Track Volume section".to_string());
+            KeyCode::Char('s') if matches!(self.current_focus, FocusArea::Transport) => {
+                let time_signature = self.transport.cycle_time_signature();
+                self.ui_state.status_message = Some(format!("Time signature {}", time_signature));
             }
-            KeyCode::Char('7') => {
-                self.current_focus = FocusArea::TrackPanning;
-                self.ui_state.status_message = Some("Track Panning section".to_string());
+            KeyCode::Char('t') if matches!(self.current_focus, FocusArea::Transport) => {
+                match self.transport.tap_tempo(std::time::Instant::now()) {
+                    Some(tempo) => {
+                        self.dirty = true;
+                        self.send_parameter_update_real_time(
+                            crate::tui::audio_bridge::ParameterUpdate::TempoChange(tempo))?;
+                        self.ui_state.status_message = Some(format!("Tap tempo: {:.0} BPM", tempo));
+                    }
+                    None => {
+                        self.ui_state.status_message =
+                            Some("Keep tapping t on the beat to set the tempo".to_string());
+                    }
+                }
             }
-            KeyCode::Char('8') => {
-                self.current_focus = FocusArea::Transport;
-                self.ui_state.status_message = Some("Transport section".to_string());
+            // Tempo in 1 BPM steps, shifted for 10
+            KeyCode::Char('=') | KeyCode::Char('+') | KeyCode::Char('-') | KeyCode::Char('_')
+                if matches!(self.current_focus, FocusArea::Transport) => {
+                let delta = match key.code {
+                    KeyCode::Char('=') => 1.0,
+                    KeyCode::Char('+') => 10.0,
+                    KeyCode::Char('-') => -1.0,
+                    _ => -10.0,
+                };
+                let tempo = self.transport.adjust_tempo(delta);
+                self.dirty = true;
+                self.send_parameter_update_real_time(
+                    crate::tui::audio_bridge::ParameterUpdate::TempoChange(tempo))?;
+                self.ui_state.status_message = Some(format!("Tempo: {:.0} BPM", tempo));
+            }
+            KeyCode::Char('v') | KeyCode::Char('V')
+                if matches!(self.current_focus, FocusArea::Transport) => {
+                let stepped = self.ui_state.volume_display.stepped(
+                    self.transport.master_volume, key.code == KeyCode::Char('V'));
+                let master_volume =
+                    self.transport.adjust_master_volume(stepped - self.transport.master_volume);
+                self.dirty = true;
+                self.send_parameter_update_real_time(
+                    crate::tui::audio_bridge::ParameterUpdate::MasterVolume(master_volume))?;
+                self.ui_state.status_message =
+                    Some(format!("Master volume: {}",
+                                 self.ui_state.volume_display.format(master_volume)));
+            }
+            KeyCode::Char('w') | KeyCode::Char('W')
+                if matches!(self.current_focus, FocusArea::Transport) => {
+                let delta = if key.code == KeyCode::Char('W') { 0.1 } else { -0.1 };
+                let stereo_width = self.transport.adjust_stereo_width(delta);
+                self.dirty = true;
+                self.send_parameter_update_real_time(
+                    crate::tui::audio_bridge::ParameterUpdate::StereoWidth(stereo_width))?;
+                self.ui_state.status_message =
+                    Some(format!("Stereo width: {:.0}%", stereo_width * 100.0));
             }
             // Fine adjustment with +/- keys
             KeyCode::Char('+') | KeyCode::Char('=') => {
                 if let FocusArea::Synthesizer(SynthSection::Oscillator) = &self.current_focus {
                     if let Some(update) = self.synthesizer_panel.handle_fine_adjustment(true) {
+                        self.dirty = true;
                         // Update local state for display
                         match &update {
                             crate::tui::audio_bridge::ParameterUpdate::OscillatorFrequency(freq) => {
@@ -434,7 +1401,9 @@ impl RoscoTuiApp {
                             }
                             crate::tui::audio_bridge::ParameterUpdate::OscillatorVolume(vol) => {
                                 self.synth_params.oscillator_volume = *vol;
-                                self.ui_state.status_message = Some(format!("Volume increased to {:.0}%", vol * 100.0));
+                                let vol = self.ui_state.volume_display.format(*vol);
+                                self.ui_state.status_message =
+                                    Some(format!("Volume increased to {}", vol));
                             }
                             _ => {}
                         }
@@ -445,6 +1414,7 @@ impl RoscoTuiApp {
             KeyCode::Char('-') => {
                 if let FocusArea::Synthesizer(SynthSection::Oscillator) = &self.current_focus {
                     if let Some(update) = self.synthesizer_panel.handle_fine_adjustment(false) {
+                        self.dirty = true;
                         // Update local state for display
                         match &update {
                             crate::tui::audio_bridge::ParameterUpdate::OscillatorFrequency(freq) => {
@@ -453,7 +1423,9 @@ impl RoscoTuiApp {
                             }
                             crate::tui::audio_bridge::ParameterUpdate::OscillatorVolume(vol) => {
                                 self.synth_params.oscillator_volume = *vol;
-                                self.ui_state.status_message = Some(format!("Volume decreased to {:.0}%", vol * 100.0));
+                                let vol = self.ui_state.volume_display.format(*vol);
+                                self.ui_state.status_message =
+                                    Some(format!("Volume decreased to {}", vol));
                             }
                             _ => {}
                         }
@@ -465,14 +1437,90 @@ impl RoscoTuiApp {
                     self.ui_state.status_message = Some("Fine adjustment only works in Oscillator section".to_string());
                 }
             }
-            // Reset parameter to default with 'r'
-            KeyCode::Char('r') => {
-                self.reset_current_parameter()?;
-            }
             _ => {}
         }
         Ok(false)
     }
+
+    fn handle_action(&mut self, action: Action, key: KeyEvent) -> Result<bool, TuiError> {
+        match action {
+            Action::Quit => {
+                if !self.dirty {
+                    return Ok(true);
+                }
+                self.ui_state.confirm_quit = true;
+            }
+            Action::ToggleHelp => self.ui_state.show_help = !self.ui_state.show_help,
+            Action::ToggleRecording => self.toggle_recording()?,
+            Action::ToggleMetronome => {
+                self.transport.metronome_on = !self.transport.metronome_on;
+                self.ui_state.status_message = Some(format!(
+                    "Metronome {}", if self.transport.metronome_on { "on" } else { "off" }));
+            }
+            Action::ToggleVolumeDisplay => {
+                self.ui_state.volume_display = self.ui_state.volume_display.toggled();
+                self.ui_state.status_message = Some(format!(
+                    "Volumes in {}", match self.ui_state.volume_display {
+                        VolumeDisplay::Percent => "percent",
+                        VolumeDisplay::Decibels => "dB",
+                    }));
+            }
+            Action::ExportDsl => self.export_dsl(),
+            Action::SaveSession => self.save_session_to_default_path(),
+            Action::LoadSession => self.load_session_from_default_path(),
+            Action::FocusNext => self.cycle_focus(),
+            Action::NavigateUp | Action::NavigateDown | Action::NavigateLeft |
+            Action::NavigateRight => {
+                if let Some(navigation_key) = navigation_key(action, key.modifiers) {
+                    self.handle_navigation(navigation_key)?;
+                }
+            }
+            Action::Activate => self.handle_activation()?,
+            // Quick section switching
+            Action::FocusOscillator => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Oscillator);
+                self.ui_state.status_message = Some("Oscillator section".to_string());
+            }
+            Action::FocusFilter => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Filter);
+                self.ui_state.status_message = Some("Filter section".to_string());
+            }
+            Action::FocusEnvelope => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Envelope);
+                self.ui_state.status_message = Some("Envelope section".to_string());
+            }
+            Action::FocusEffects => {
+                self.current_focus = FocusArea::Synthesizer(SynthSection::Effects);
+                self.ui_state.status_message = Some("Effects section".to_string());
+            }
+            Action::FocusSequencer => {
+                self.current_focus = FocusArea::Sequencer;
+                self.ui_state.status_message = Some("Track Sequencer section".to_string());
+            }
+            Action::FocusTrackVolume => {
+                self.current_focus = FocusArea::TrackVolume;
+                self.ui_state.status_message = Some("Track Volume section".to_string());
+            }
+            Action::FocusTrackPanning => {
+                self.current_focus = FocusArea::TrackPanning;
+                self.ui_state.status_message = Some("Track Panning section".to_string());
+            }
+            Action::FocusTransport => {
+                self.current_focus = FocusArea::Transport;
+                self.ui_state.status_message = Some("Transport section".to_string());
+            }
+            // Reset parameter to default
+            Action::ResetParameter => self.reset_current_parameter()?,
+            Action::Panic => {
+                self.transport.is_playing = false;
+                self.auditioned_note = None;
+                self.send_parameter_update_real_time(
+                    crate::tui::audio_bridge::ParameterUpdate::Panic)?;
+                self.ui_state.status_message = Some("All notes off".to_string());
+            }
+        }
+        Ok(false)
+    }
     
     fn cycle_focus(&mut self) {
         self.current_focus = match self.current_focus {
@@ -510,28 +1558,64 @@ impl RoscoTuiApp {
     }
     
     fn handle_synth_navigation(&mut self, section: SynthSection, key_event: KeyEvent) -> Result<(), TuiError> {
-        match section {
-            SynthSection::Oscillator => {
-                let updates = self.synthesizer_panel.handle_input(key_event);
-                for update in updates {
-                    // Update local state for display
-                    match &update {
-                        crate::tui::audio_bridge::ParameterUpdate::OscillatorFrequency(freq) => {
-                            self.synth_params.oscillator_frequency = *freq;
-                        }
-                        crate::tui::audio_bridge::ParameterUpdate::OscillatorVolume(vol) => {
-                            self.synth_params.oscillator_volume = *vol;
-                        }
-                        crate::tui::audio_bridge::ParameterUpdate::OscillatorWaveform(waveform) => {
-                            self.synth_params.oscillator_waveform = *waveform;
-                        }
-                        _ => {}
-                    }
-                    self.send_parameter_update_real_time(update)?;
-                }
-            }
+        let updates = match section {
+            SynthSection::Oscillator => self.synthesizer_panel.handle_input(key_event),
+            SynthSection::Filter => self.synthesizer_panel.filter.handle_input(key_event),
+            SynthSection::Envelope => self.synthesizer_panel.envelope.handle_input(key_event),
             _ => {
                 // TODO: Handle other synthesizer sections
+                Vec::new()
+            }
+        };
+        self.apply_synth_updates(updates)
+    }
+
+    fn apply_synth_updates(&mut self, updates: Vec<crate::tui::audio_bridge::ParameterUpdate>) -> Result<(), TuiError> {
+        for update in updates {
+            self.dirty = true;
+            // Update local state for display, and say what changed in the filter and envelope
+            let status_message = match &update {
+                crate::tui::audio_bridge::ParameterUpdate::OscillatorFrequency(freq) => {
+                    self.synth_params.oscillator_frequency = *freq;
+                    None
+                }
+                crate::tui::audio_bridge::ParameterUpdate::OscillatorVolume(vol) => {
+                    self.synth_params.oscillator_volume = *vol;
+                    None
+                }
+                crate::tui::audio_bridge::ParameterUpdate::OscillatorWaveform(waveform) => {
+                    self.synth_params.oscillator_waveform = *waveform;
+                    None
+                }
+                crate::tui::audio_bridge::ParameterUpdate::FilterType(filter_type) => {
+                    Some(format!("Filter type {:?}", filter_type))
+                }
+                crate::tui::audio_bridge::ParameterUpdate::FilterCutoff(cutoff) => {
+                    Some(format!("Filter cutoff {:.0} Hz", cutoff))
+                }
+                crate::tui::audio_bridge::ParameterUpdate::FilterResonance(resonance) => {
+                    Some(format!("Filter resonance {:.2}", resonance))
+                }
+                crate::tui::audio_bridge::ParameterUpdate::FilterMix(mix) => {
+                    Some(format!("Filter mix {:.0}%", mix * 100.0))
+                }
+                crate::tui::audio_bridge::ParameterUpdate::EnvelopeAttack(attack) => {
+                    Some(format!("Envelope attack {:.0} ms", attack * 1000.0))
+                }
+                crate::tui::audio_bridge::ParameterUpdate::EnvelopeDecay(decay) => {
+                    Some(format!("Envelope decay {:.0} ms", decay * 1000.0))
+                }
+                crate::tui::audio_bridge::ParameterUpdate::EnvelopeSustain(sustain) => {
+                    Some(format!("Envelope sustain {:.0}%", sustain * 100.0))
+                }
+                crate::tui::audio_bridge::ParameterUpdate::EnvelopeRelease(release) => {
+                    Some(format!("Envelope release {:.0} ms", release * 1000.0))
+                }
+                _ => None,
+            };
+            self.send_parameter_update_real_time(update)?;
+            if status_message.is_some() {
+                self.ui_state.status_message = status_message;
             }
         }
         Ok(())
@@ -556,8 +1640,9 @@ impl RoscoTuiApp {
                 let track_idx = self.sequencer_panel.grid.cursor.track;
                 let track = &mut self.sequencer_panel.grid.tracks[track_idx as usize];
                 track.adjust_volume(delta);
-                self.ui_state.status_message = Some(format!("Track {} Volume: {:.0}%", 
-                    track.track_number, track.volume * 100.0));
+                self.dirty = true;
+                self.ui_state.status_message = Some(format!("Track {} Volume: {}",
+                    track.track_number, self.ui_state.volume_display.format(track.volume)));
             }
             _ => {}
         }
@@ -583,8 +1668,10 @@ impl RoscoTuiApp {
                 let track_idx = self.sequencer_panel.grid.cursor.track;
                 let track = &mut self.sequencer_panel.grid.tracks[track_idx as usize];
                 track.adjust_pan(delta);
+                self.dirty = true;
                 self.ui_state.status_message = Some(format!("Track {} Pan: {:.1}", 
                     track.track_number, track.pan));
+                self.sync_track_mix(track_idx as usize);
             }
             _ => {}
         }
@@ -601,19 +1688,60 @@ impl RoscoTuiApp {
                 self.transport.focused_button = TransportButton::Stop;
                 self.ui_state.status_message = Some("Stop button focused".to_string());
             }
+            KeyCode::Up | KeyCode::Down => {
+                let delta = if key_event.code == KeyCode::Up { 0.05 } else { -0.05 };
+                self.transport.swing = (self.transport.swing + delta).clamp(0.0, MAX_SWING);
+                self.audio_state.handle_parameter_update(
+                    &crate::tui::audio_bridge::ParameterUpdate::Swing(self.transport.swing));
+                self.ui_state.status_message = Some(format!("Swing: {:.0}%",
+                    self.transport.swing * 100.0));
+            }
             _ => {}
         }
         Ok(())
     }
     
+    fn toggle_clock_source(&mut self) {
+        let clock_source = match self.transport.clock_source {
+            ClockSource::Internal => ClockSource::MidiExternal,
+            ClockSource::MidiExternal => ClockSource::Internal,
+        };
+        self.transport.clock_source = clock_source;
+        self.audio_state.handle_parameter_update(
+            &crate::tui::audio_bridge::ParameterUpdate::ClockSource(clock_source));
+
+        match clock_source {
+            ClockSource::MidiExternal => {
+                // Wait for MIDI start rather than carrying on from the internal clock
+                self.transport.is_playing = false;
+                self.audio_state.is_playing.store(false, Ordering::Relaxed);
+                // MIDI clock only arrives once the engine and its MIDI input are running
+                self.start_audio_engine();
+                if self.audio_engine.is_some() {
+                    self.ui_state.status_message = Some(match &self.midi_input {
+                        Some(midi_input) => format!("Following MIDI clock from {}",
+                                                    midi_input.port_name()),
+                        None => "Following MIDI clock, but no MIDI input is connected".to_string(),
+                    });
+                }
+            }
+            ClockSource::Internal => {
+                self.ui_state.status_message = Some("Internal clock".to_string());
+            }
+        }
+    }
+
     fn handle_activation(&mut self) -> Result<(), TuiError> {
         match &self.current_focus {
             FocusArea::Transport => {
                 match self.transport.focused_button {
                     TransportButton::Play => {
                         self.transport.is_playing = true;
+                        self.transport.played_steps = 0;
+                        self.audio_state.played_steps.store(0, Ordering::Relaxed);
                         self.transport.last_step_time = std::time::Instant::now();
                         self.ui_state.status_message = Some("Playing".to_string());
+                        self.start_audio_engine();
                         let transport_cmd = crate::tui::audio_bridge::ParameterUpdate::TransportPlay;
                         self.send_parameter_update_real_time(transport_cmd)?;
                     }
@@ -643,23 +1771,22 @@ impl RoscoTuiApp {
         Ok(())
     }
     
-    fn send_parameter_update(&mut self) -> Result<(), TuiError> {
-        if let Some(_bridge) = &mut self.audio_bridge {
-            // TODO: Send parameter updates to audio thread
-        }
-        Ok(())
-    }
-    
     fn send_parameter_update_real_time(&mut self, update: crate::tui::audio_bridge::ParameterUpdate) -> Result<(), TuiError> {
-        if let Some(bridge) = &mut self.audio_bridge {
-            bridge.send_parameter_update(update)?;
-            self.ui_state.status_message = Some("Parameter updated".to_string());
+        match &mut self.audio_engine {
+            Some(engine) => engine.handle_parameter_update(&update)?,
+            // The engine's state is kept between plays, so it follows the controls while stopped
+            None => self.audio_state.handle_parameter_update(&update),
         }
         Ok(())
     }
     
     fn process_sequencer_actions(&mut self, actions: Vec<crate::tui::ui::sequencer::SequencerAction>) -> Result<(), TuiError> {
         use crate::tui::ui::sequencer::SequencerAction;
+
+        if actions.iter().any(|action| action.is_edit() ||
+            matches!(action, SequencerAction::Undone | SequencerAction::Redone)) {
+            self.dirty = true;
+        }
         
         for action in actions {
             match action {
@@ -680,13 +1807,52 @@ impl RoscoTuiApp {
                     };
                     self.send_parameter_update_real_time(update)?;
                 }
-                SequencerAction::FrequencyChanged { track, step, frequency } => {
+                SequencerAction::FrequencyChanged { track, step, frequency: _ } => {
+                    let step_cell = &self.sequencer_panel.grid.tracks[track as usize]
+                        .steps[step as usize];
                     self.ui_state.status_message = Some(format!(
                         "Track {} Step {} frequency: {} ({:.1} Hz)", 
                         track + 1, 
                         step + 1,
-                        frequency,
-                        frequency.get_frequency(3)
+                        step_cell.pitch_label(),
+                        step_cell.frequency_hz()
+                    ));
+                }
+                SequencerAction::Transposed { semitones, selection } => {
+                    let target = if selection {
+                        "Selection".to_string()
+                    } else {
+                        format!("Track {}", self.sequencer_panel.grid.cursor.track + 1)
+                    };
+                    self.ui_state.status_message = Some(format!(
+                        "{} transposed {:+} semitones", target, semitones));
+                }
+                SequencerAction::ChordChanged { track, step } => {
+                    let step_cell = &self.sequencer_panel.grid.tracks[track as usize]
+                        .steps[step as usize];
+                    let pitches: Vec<String> = step_cell.midi_notes().iter()
+                        .map(|midi_note| {
+                            let (pitch, octave) = WesternPitch::from_midi_note(*midi_note);
+                            format!("{}{}", pitch, octave)
+                        })
+                        .collect();
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} Step {} chord: {} ({})",
+                        track + 1,
+                        step + 1,
+                        step_cell.chord_label(),
+                        pitches.join(" ")
+                    ));
+                }
+                SequencerAction::OctaveChanged { track, step, octave: _ } => {
+                    let step_cell = &self.sequencer_panel.grid.tracks[track as usize]
+                        .steps[step as usize];
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} Step {} octave: {} ({:.1} Hz)",
+                        track + 1,
+                        step + 1,
+                        step_cell.pitch_label(),
+                        step_cell.frequency_hz()
                     ));
                 }
                 SequencerAction::TrackVolumeChanged { track, volume } => {
@@ -697,6 +1863,7 @@ impl RoscoTuiApp {
                     ));
                 }
                 SequencerAction::TrackPanChanged { track, pan } => {
+                    self.sync_track_mix(track as usize);
                     self.ui_state.status_message = Some(format!(
                         "Track {} pan: {:.1}", 
                         track + 1, 
@@ -704,6 +1871,7 @@ impl RoscoTuiApp {
                     ));
                 }
                 SequencerAction::TrackMuteToggled { track } => {
+                    self.sync_track_mix(track as usize);
                     let muted = self.sequencer_panel.grid.tracks[track as usize].mute;
                     self.ui_state.status_message = Some(format!(
                         "Track {} {}", 
@@ -712,6 +1880,7 @@ impl RoscoTuiApp {
                     ));
                 }
                 SequencerAction::TrackSoloToggled { track } => {
+                    self.sync_track_mix(track as usize);
                     let soloed = self.sequencer_panel.grid.tracks[track as usize].solo;
                     self.ui_state.status_message = Some(format!(
                         "Track {} {}", 
@@ -719,6 +1888,106 @@ impl RoscoTuiApp {
                         if soloed { "soloed" } else { "unsoloed" }
                     ));
                 }
+                SequencerAction::TrackWaveformChanged { track, waveform } => {
+                    self.sync_track_mix(track as usize);
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} waveform: {:?}",
+                        track + 1,
+                        waveform
+                    ));
+                }
+                SequencerAction::TrackArpChanged { track, arp_mode } => {
+                    self.ui_state.status_message = Some(match arp_mode {
+                        Some(arp_mode) => format!(
+                            "Track {} arpeggiates chords {:?} in sixteenths", track + 1, arp_mode),
+                        None => format!("Track {} plays chords together", track + 1),
+                    });
+                }
+                SequencerAction::TrackRotated { track, amount } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} shifted a step {}",
+                        track + 1,
+                        if amount > 0 { "later" } else { "earlier" }
+                    ));
+                }
+                SequencerAction::VelocityRamped { start, end } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Velocity ramped from {} to {} across the selection", start, end));
+                }
+                SequencerAction::FrequencyRamped { start, end } => {
+                    let label = |midi_note| {
+                        let (pitch, octave) = WesternPitch::from_midi_note(midi_note);
+                        format!("{}{}", pitch, octave)
+                    };
+                    self.ui_state.status_message = Some(format!(
+                        "Pitch ramped from {} to {} across the selection",
+                        label(start),
+                        label(end)
+                    ));
+                }
+                SequencerAction::StepProbabilityChanged { track, step, probability } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} Step {} probability: {}%",
+                        track + 1,
+                        step + 1,
+                        probability
+                    ));
+                }
+                SequencerAction::StepGateLengthChanged { track, step, gate_length } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} Step {} gate: {:.0}% of the step",
+                        track + 1,
+                        step + 1,
+                        gate_length * 100.0
+                    ));
+                }
+                SequencerAction::StepVelocityChanged { track, step, velocity } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} Step {} velocity: {}",
+                        track + 1,
+                        step + 1,
+                        velocity
+                    ));
+                }
+                SequencerAction::LoopChanged { loop_region } => {
+                    self.ui_state.status_message = Some(match loop_region {
+                        Some((loop_start, loop_end)) => format!(
+                            "Looping steps {} to {}", loop_start + 1, loop_end + 1),
+                        None => "Playing the whole sequence".to_string(),
+                    });
+                }
+                SequencerAction::TrackLengthChanged { track, length } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} length: {} steps",
+                        track + 1,
+                        length
+                    ));
+                }
+                SequencerAction::TrackDivisorChanged { track, divisor } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} moves every {} steps",
+                        track + 1,
+                        divisor
+                    ));
+                }
+                SequencerAction::TrackEuclideanFilled { track, hits } => {
+                    let length = self.sequencer_panel.grid.tracks[track as usize].length;
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} Euclidean rhythm: {} hits in {} steps",
+                        track + 1,
+                        hits,
+                        length
+                    ));
+                }
+                SequencerAction::ScaleChanged { scale } => {
+                    self.ui_state.status_message = Some(match scale {
+                        Some((root, scale)) => format!("Pitches kept to {} {}", root, scale),
+                        None => "Pitches not kept to a scale".to_string(),
+                    });
+                }
+                SequencerAction::SequenceLengthChanged { steps } => {
+                    self.ui_state.status_message = Some(format!("Sequence length: {} steps", steps));
+                }
                 SequencerAction::TrackCleared { track } => {
                     self.ui_state.status_message = Some(format!("Track {} cleared", track + 1));
                 }
@@ -728,11 +1997,36 @@ impl RoscoTuiApp {
                 SequencerAction::PatternPasted => {
                     self.ui_state.status_message = Some("Pattern pasted from clipboard".to_string());
                 }
+                SequencerAction::StepCopied { track, step } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} Step {} copied", track + 1, step + 1));
+                }
+                SequencerAction::StepPasted { track, step } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Copied step pasted to Track {} Step {}", track + 1, step + 1));
+                }
                 SequencerAction::PatternStored { pattern_id: _ } => {
-                    self.ui_state.status_message = Some("Pattern stored".to_string());
+                    // Save straight away so stored patterns survive the session
+                    self.ui_state.status_message = match self.sequencer_panel
+                        .get_pattern_manager()
+                        .save_to_default_path() {
+                        Ok(()) => Some("Pattern stored".to_string()),
+                        Err(e) => Some(format!("Pattern stored but not saved: {}", e)),
+                    };
                 }
-                SequencerAction::PatternLoaded { pattern_id: _ } => {
-                    self.ui_state.status_message = Some("Pattern loaded".to_string());
+                SequencerAction::PatternLoaded { pattern_id, fitted_from } => {
+                    let track = self.sequencer_panel.grid.cursor.track;
+                    let mut message =
+                        match self.sequencer_panel.get_pattern_manager().get_pattern(&pattern_id) {
+                            Some(pattern) => format!("Loaded '{}' into Track {}",
+                                                     pattern.name, track + 1),
+                            None => "Pattern loaded".to_string(),
+                        };
+                    if let Some(pattern_length) = fitted_from {
+                        message.push_str(&format!(", {} steps fitted to {}", pattern_length,
+                                                  self.sequencer_panel.grid.steps_per_track));
+                    }
+                    self.ui_state.status_message = Some(message);
                 }
                 SequencerAction::PatternBrowserToggled => {
                     let visible = self.sequencer_panel.is_pattern_browser_visible();
@@ -747,6 +2041,40 @@ impl RoscoTuiApp {
                 SequencerAction::SelectionCleared => {
                     self.ui_state.status_message = Some("Selection cleared".to_string());
                 }
+                SequencerAction::PianoKeysToggled { enabled } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Piano keys {}", if enabled { "on (a-k play C to C)" } else { "off" }));
+                }
+                SequencerAction::PianoKeyPlayed { track, step, midi_note } => {
+                    let velocity = self.sequencer_panel.grid.tracks[track as usize]
+                        .steps[step as usize].velocity;
+                    self.audition_note(midi_note, velocity);
+                    let (pitch, octave) = WesternPitch::from_midi_note(midi_note);
+                    self.ui_state.status_message = Some(format!(
+                        "Track {} Step {}: {}{}", track + 1, step + 1, pitch, octave));
+                }
+                SequencerAction::SelectionFilled { enabled } => {
+                    self.ui_state.status_message = Some(format!(
+                        "Selection {}", if enabled { "filled" } else { "emptied" }));
+                }
+                SequencerAction::SelectionDeleted => {
+                    self.ui_state.status_message = Some("Selection deleted".to_string());
+                }
+                SequencerAction::Undone | SequencerAction::Redone => {
+                    // Any track's mix may have changed
+                    for track_idx in 0..self.sequencer_panel.grid.tracks.len() {
+                        self.sync_track_mix(track_idx);
+                    }
+                    self.ui_state.status_message = Some(
+                        if matches!(action, SequencerAction::Undone) { "Undo" } else { "Redo" }
+                            .to_string());
+                }
+                SequencerAction::NothingToUndo => {
+                    self.ui_state.status_message = Some("Nothing to undo".to_string());
+                }
+                SequencerAction::NothingToRedo => {
+                    self.ui_state.status_message = Some("Nothing to redo".to_string());
+                }
             }
         }
         Ok(())
@@ -754,6 +2082,7 @@ impl RoscoTuiApp {
     
     fn reset_current_parameter(&mut self) -> Result<(), TuiError> {
         if let FocusArea::Synthesizer(SynthSection::Oscillator) = &self.current_focus {
+            self.dirty = true;
             match self.synthesizer_panel.current_section {
                 crate::tui::ui::synthesizer::OscillatorSubSection::Waveform => {
                     self.synthesizer_panel.oscillator.waveform_selector.selected = 0; // Reset to Sine
@@ -785,6 +2114,16 @@ impl RoscoTuiApp {
     
     fn update_ui(&mut self, frame: &mut Frame) {
         let size = frame.size();
+
+        // Nothing the mouse could land on is drawn while the terminal is too small, but a question
+        // waiting on y/n still is
+        if is_too_small(size) {
+            self.slider_areas.clear();
+            self.grid_area = Rect::default();
+            self.render_too_small(frame, size);
+            self.render_confirmations(frame, size);
+            return;
+        }
         
         if self.ui_state.show_help {
             self.render_help(frame, size);
@@ -796,17 +2135,35 @@ impl RoscoTuiApp {
             .direction(Direction::Vertical)
             .constraints([
                 Constraint::Percentage(20), // Synthesizer (reduced further to ensure 8 tracks fit)
-                Constraint::Min(24),        // Sequencer (8 tracks * 2 rows + borders + step numbers = 16 + 8 = 24)
-                Constraint::Min(3),         // Status bar
+                Constraint::Min(SEQUENCER_MIN_HEIGHT), // Sequencer (8 tracks * 2 rows + borders + numbers)
+                Constraint::Min(STATUS_BAR_HEIGHT),    // Status bar
             ])
             .split(size);
         
+        self.slider_areas.clear();
         self.render_synthesizer(frame, chunks[0]);
         self.render_sequencer_sections(frame, chunks[1]);
         self.render_status_bar(frame, chunks[2]);
+
+        if self.sequencer_panel.is_pattern_browser_visible() {
+            self.render_pattern_browser(frame, size);
+        }
+        self.render_confirmations(frame, size);
+    }
+
+    fn render_confirmations(&self, frame: &mut Frame, area: Rect) {
+        if self.ui_state.confirm_quit {
+            self.render_confirm_dialog(frame, area, "QUIT",
+                                       "Unsaved changes (F5 saves)\nQuit anyway? y/n");
+        }
+        if self.ui_state.confirm_recover {
+            self.render_confirm_dialog(
+                frame, area, "RECOVER",
+                "Rosco didn't exit cleanly last time\nRecover auto-saved edits? y/n");
+        }
     }
     
-    fn render_synthesizer(&self, frame: &mut Frame, area: Rect) {
+    fn render_synthesizer(&mut self, frame: &mut Frame, area: Rect) {
         let title = match &self.current_focus {
             FocusArea::Synthesizer(_) => "SYNTHESIZER [FOCUSED]",
             _ => "SYNTHESIZER",
@@ -830,12 +2187,12 @@ impl RoscoTuiApp {
         frame.render_widget(block, area);
         
         self.render_oscillator_section(frame, synth_chunks[0]);
-        self.render_placeholder_section(frame, synth_chunks[1], "2 - FILTER");
-        self.render_placeholder_section(frame, synth_chunks[2], "3 - ENVELOPE");
-        self.render_placeholder_section(frame, synth_chunks[3], "4 - EFFECTS");
+        self.render_filter_section(frame, synth_chunks[1]);
+        self.render_envelope_section(frame, synth_chunks[2]);
+        self.render_scope_section(frame, synth_chunks[3]);
     }
     
-    fn render_oscillator_section(&self, frame: &mut Frame, area: Rect) {
+    fn render_oscillator_section(&mut self, frame: &mut Frame, area: Rect) {
         let focused = matches!(self.current_focus, FocusArea::Synthesizer(SynthSection::Oscillator));
         let title = if focused { "1 - OSCILLATOR [FOCUSED]" } else { "1 - OSCILLATOR" };
         
@@ -869,6 +2226,22 @@ impl RoscoTuiApp {
         );
         frame.render_widget(Paragraph::new(waveform_text).style(waveform_style), chunks[0]);
         
+        // Render frequency control
+        let freq_focused = focused && self.synthesizer_panel.current_section == crate::tui::ui::synthesizer::OscillatorSubSection::Frequency;
+        let freq_style = if freq_focused {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default().fg(Color::White)
+        };
+        let freq_slider = &self.synthesizer_panel.oscillator.frequency_slider;
+        let freq_text = format!("{} {}",
+            freq_slider.render_with_value(),
+            if freq_focused { "◄" } else { "" }
+        );
+        self.slider_areas.push((SliderTarget::OscillatorFrequency,
+                                slider_bar_area(chunks[1], &freq_text)));
+        frame.render_widget(Paragraph::new(freq_text).style(freq_style), chunks[1]);
+        
         // Render volume control
         let vol_focused = focused && self.synthesizer_panel.current_section == crate::tui::ui::synthesizer::OscillatorSubSection::Volume;
         let vol_style = if vol_focused { 
@@ -877,26 +2250,132 @@ impl RoscoTuiApp {
             Style::default().fg(Color::White) 
         };
         let vol_slider = &self.synthesizer_panel.oscillator.volume_slider;
-        let vol_text = format!("Vol:  {} {:.0}% {}", 
+        let vol_text = format!("Vol:  {} {} {}",
             vol_slider.render_bar(),
-            vol_slider.value * 100.0,
+            self.ui_state.volume_display.format(vol_slider.value),
             if vol_focused { "◄" } else { "" }
         );
+        self.slider_areas.push((SliderTarget::OscillatorVolume,
+                                slider_bar_area(chunks[2], &vol_text)));
         frame.render_widget(Paragraph::new(vol_text).style(vol_style), chunks[2]);
     }
     
-    fn render_placeholder_section(&self, frame: &mut Frame, area: Rect, title: &str) {
+    fn render_filter_section(&mut self, frame: &mut Frame, area: Rect) {
+        use crate::tui::ui::synthesizer::FilterSubSection;
+
+        let focused = matches!(self.current_focus, FocusArea::Synthesizer(SynthSection::Filter));
+        let title = if focused { "2 - FILTER [FOCUSED]" } else { "2 - FILTER" };
+
         let block = Block::default()
             .title(title)
             .borders(Borders::ALL);
-        
+
         let inner = block.inner(area);
         frame.render_widget(block, area);
-        
-        let paragraph = Paragraph::new("TODO");
-        frame.render_widget(paragraph, inner);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1), // Type
+                Constraint::Length(1), // Cutoff
+                Constraint::Length(1), // Resonance
+                Constraint::Length(1), // Mix
+            ])
+            .split(inner);
+
+        let filter = &self.synthesizer_panel.filter;
+        let rows = [
+            (FilterSubSection::Type, None,
+             format!("Type: {:?}", filter.filter_type.selected_filter())),
+            (FilterSubSection::Cutoff, Some(SliderTarget::FilterCutoff),
+             format!("Cut:  {} {:.0}Hz", filter.cutoff_slider.render_bar(),
+                     filter.cutoff_slider.value)),
+            (FilterSubSection::Resonance, Some(SliderTarget::FilterResonance),
+             format!("Res:  {} {:.2}", filter.resonance_slider.render_bar(),
+                     filter.resonance_slider.value)),
+            (FilterSubSection::Mix, Some(SliderTarget::FilterMix),
+             format!("Mix:  {} {:.0}%", filter.mix_slider.render_bar(),
+                     filter.mix_slider.value * 100.0)),
+        ];
+        for (chunk, (sub_section, target, text)) in chunks.iter().zip(rows) {
+            if let Some(target) = target {
+                self.slider_areas.push((target, slider_bar_area(*chunk, &text)));
+            }
+            let row_focused = focused && filter.sub_focus == sub_section;
+            let style = if row_focused {
+                Style::default().fg(Color::Cyan)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let text = format!("{} {}", text, if row_focused { "◄" } else { "" });
+            frame.render_widget(Paragraph::new(text).style(style), *chunk);
+        }
     }
-    
+
+    fn render_envelope_section(&mut self, frame: &mut Frame, area: Rect) {
+        use crate::tui::ui::synthesizer::EnvelopeSubSection;
+
+        let focused = matches!(self.current_focus, FocusArea::Synthesizer(SynthSection::Envelope));
+        let title = if focused { "3 - ENVELOPE [FOCUSED]" } else { "3 - ENVELOPE" };
+
+        let block = Block::default()
+            .title(title)
+            .borders(Borders::ALL);
+
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1), // Attack
+                Constraint::Length(1), // Decay
+                Constraint::Length(1), // Sustain
+                Constraint::Length(1), // Release
+                Constraint::Length(1), // Curve
+            ])
+            .split(inner);
+
+        let envelope = &self.synthesizer_panel.envelope;
+        let rows = [
+            (EnvelopeSubSection::Attack, SliderTarget::EnvelopeAttack,
+             envelope.attack_slider.render_with_value()),
+            (EnvelopeSubSection::Decay, SliderTarget::EnvelopeDecay,
+             envelope.decay_slider.render_with_value()),
+            (EnvelopeSubSection::Sustain, SliderTarget::EnvelopeSustain,
+             format!("Sus: {} {:.0}%", envelope.sustain_slider.render_bar(),
+                     envelope.sustain_slider.value * 100.0)),
+            (EnvelopeSubSection::Release, SliderTarget::EnvelopeRelease,
+             envelope.release_slider.render_with_value()),
+        ];
+        for (chunk, (sub_section, target, text)) in chunks.iter().zip(rows) {
+            self.slider_areas.push((target, slider_bar_area(*chunk, &text)));
+            let row_focused = focused && envelope.sub_focus == sub_section;
+            let style = if row_focused {
+                Style::default().fg(Color::Cyan)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            let text = format!("{} {}", text, if row_focused { "◄" } else { "" });
+            frame.render_widget(Paragraph::new(text).style(style), *chunk);
+        }
+
+        let curve = envelope.render_curve(chunks[4].width as usize);
+        frame.render_widget(Paragraph::new(curve).style(Style::default().fg(Color::Green)),
+                            chunks[4]);
+    }
+
+    fn render_scope_section(&self, frame: &mut Frame, area: Rect) {
+        // Effects have no controls yet, so their section shows the output instead
+        let block = Block::default()
+            .title("4 - EFFECTS - SCOPE")
+            .borders(Borders::ALL);
+
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        frame.render_widget(self.scope.clone(), inner);
+    }
+
     fn render_sequencer_sections(&mut self, frame: &mut Frame, area: Rect) {
         // Split into three sections: grid, volume controls, panning controls, and transport
         let sections = Layout::default()
@@ -947,10 +2426,13 @@ impl RoscoTuiApp {
     }
     
     fn render_track_grid_section(&mut self, frame: &mut Frame, area: Rect) {
-        let title = match &self.current_focus {
+        let mut title = match &self.current_focus {
             FocusArea::Sequencer => "5 - TRACK GRID [FOCUSED]",
             _ => "5 - TRACK GRID",
-        };
+        }.to_string();
+        if let Some((root, scale)) = self.sequencer_panel.grid.scale {
+            title.push_str(&format!(" - {} {}", root, scale));
+        }
         
         let block = Block::default()
             .title(title)
@@ -1001,7 +2483,10 @@ impl RoscoTuiApp {
     }
     
     fn render_sequencer_grid_only(&mut self, frame: &mut Frame, area: Rect) {
-        // Create a custom grid widget that only shows the steps/frequency grid without controls
+        // Create a custom grid widget that only shows the steps/frequency grid without controls,
+        // scrolled so the cursor's step is in view
+        self.sequencer_panel.grid.scroll_to_cursor(area.width);
+        self.grid_area = area;
         let grid = self.sequencer_panel.grid.clone();
         frame.render_widget(GridOnlyWidget { grid }, area);
     }
@@ -1037,16 +2522,39 @@ impl RoscoTuiApp {
                 style
             };
             
-            let vol_percent = (track.volume * 100.0) as u8;
             let vol_bars = (track.volume * 10.0) as usize; // 10 blocks for compact display
             let vol_filled = "█".repeat(vol_bars);
             let vol_empty = "░".repeat(10 - vol_bars);
-            let vol_display = format!("T{} {}{} {}%", track.track_number, vol_filled, vol_empty, vol_percent);
+            let vol_display = format!("T{} {}{} {}", track.track_number, vol_filled, vol_empty,
+                                      self.ui_state.volume_display.format(track.volume));
             
-            let paragraph = Paragraph::new(vol_display).style(vol_style);
             let cell_area = Rect { x: area.x, y: y_pos, width: area.width, height: 1 };
+            self.slider_areas.push((SliderTarget::TrackVolume(track_idx),
+                                    slider_bar_area(cell_area, &vol_display)));
+            let paragraph = Paragraph::new(vol_display).style(vol_style);
             frame.render_widget(paragraph, cell_area);
         }
+
+        // Output levels below the volumes, each track's and then the whole mix's
+        let meters_y = area.y + self.sequencer_panel.grid.tracks.len() as u16 + 1;
+        let meters = self.track_meters.iter().enumerate()
+            .map(|(track_idx, meter)| (format!("T{}", track_idx + 1), meter))
+            .chain(std::iter::once(("Out".to_string(), &self.master_meter)));
+        for (row, (label, meter)) in meters.enumerate() {
+            let y_pos = meters_y + row as u16;
+            if y_pos >= area.y + area.height {
+                break;
+            }
+            let label_area = Rect { x: area.x, y: y_pos, width: area.width, height: 1 };
+            frame.render_widget(Paragraph::new(label).style(style), label_area);
+            let meter_area = Rect {
+                x: area.x + LEVEL_METER_LABEL_WIDTH,
+                y: y_pos,
+                width: area.width.saturating_sub(LEVEL_METER_LABEL_WIDTH),
+                height: 1,
+            };
+            frame.render_widget(meter.clone(), meter_area);
+        }
     }
     
     fn render_panning_controls_only(&mut self, frame: &mut Frame, area: Rect, focused: bool) {
@@ -1132,13 +2640,21 @@ impl RoscoTuiApp {
         };
         
         let content = format!(
-            "{} {}   Tempo: {:.0} BPM   Position: {}.{}.{}",
+            "{} {}   Tempo: {:.0} BPM   Swing: {:.0}%   Master: {}   Clock: {}   \
+             Position: {}.{}.{} ({})",
             play_button,
             stop_button,
             self.transport.tempo,
+            self.transport.swing * 100.0,
+            self.ui_state.volume_display.format(self.transport.master_volume),
+            match self.transport.clock_source {
+                ClockSource::Internal => "Internal",
+                ClockSource::MidiExternal => "MIDI",
+            },
             self.transport.position.measure,
             self.transport.position.beat,
-            self.transport.position.tick
+            self.transport.position.tick,
+            self.transport.time_signature
         );
         
         let paragraph = Paragraph::new(content);
@@ -1158,9 +2674,26 @@ impl RoscoTuiApp {
                     crate::tui::ui::synthesizer::OscillatorSubSection::Volume => "OSC:Volume",
                 }
             }
-            FocusArea::Synthesizer(SynthSection::Filter) => "Filter",
-            FocusArea::Synthesizer(SynthSection::Envelope) => "Envelope",
+            FocusArea::Synthesizer(SynthSection::Filter) => {
+                match self.synthesizer_panel.filter.sub_focus {
+                    crate::tui::ui::synthesizer::FilterSubSection::Type => "Filter:Type",
+                    crate::tui::ui::synthesizer::FilterSubSection::Cutoff => "Filter:Cutoff",
+                    crate::tui::ui::synthesizer::FilterSubSection::Resonance => "Filter:Resonance",
+                    crate::tui::ui::synthesizer::FilterSubSection::Mix => "Filter:Mix",
+                }
+            }
+            FocusArea::Synthesizer(SynthSection::Envelope) => {
+                match self.synthesizer_panel.envelope.sub_focus {
+                    crate::tui::ui::synthesizer::EnvelopeSubSection::Attack => "Env:Attack",
+                    crate::tui::ui::synthesizer::EnvelopeSubSection::Decay => "Env:Decay",
+                    crate::tui::ui::synthesizer::EnvelopeSubSection::Sustain => "Env:Sustain",
+                    crate::tui::ui::synthesizer::EnvelopeSubSection::Release => "Env:Release",
+                }
+            }
             FocusArea::Synthesizer(SynthSection::Effects) => "Effects",
+            FocusArea::Sequencer if self.sequencer_panel.is_piano_keys_enabled() => {
+                "Sequencer [PIANO]"
+            }
             FocusArea::Sequencer => "Sequencer",
             FocusArea::TrackVolume => "Track Volume",
             FocusArea::TrackPanning => "Track Panning",
@@ -1168,15 +2701,96 @@ impl RoscoTuiApp {
         };
         
         let content = format!(
-            "{} | {} | 1-8:Sections +/-:Adjust R:Reset F1:Help ESC:Quit",
+            "{} | {}{} | 1-8:Sections +/-:Adjust R:Reset F1:Help ESC:Quit",
             status_msg,
-            current_section_info
+            current_section_info,
+            if self.transport.metronome_on { " [CLICK]" } else { "" }
         );
         
         let paragraph = Paragraph::new(content);
         frame.render_widget(paragraph, area);
     }
     
+    fn render_too_small(&self, frame: &mut Frame, area: Rect) {
+        let message = format!("Terminal too small: {}x{}\nResize to at least {}x{} to see all \
+                               eight tracks\nq quits",
+                              area.width, area.height, MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT);
+        frame.render_widget(Paragraph::new(message)
+                                .style(Style::default().fg(Color::Yellow))
+                                .wrap(ratatui::widgets::Wrap { trim: true }),
+                            area);
+    }
+
+    // A y/n question in a box over the middle of the screen
+    fn render_confirm_dialog(&self, frame: &mut Frame, area: Rect, title: &str, question: &str) {
+        let width = area.width.min(44);
+        let height = area.height.min(4);
+        let area = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+
+        let block = Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .style(Style::default().fg(Color::Yellow));
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+        frame.render_widget(Paragraph::new(question), inner);
+    }
+
+    // Stored patterns over the middle of the screen, newest first, with the selected one marked
+    fn render_pattern_browser(&self, frame: &mut Frame, area: Rect) {
+        let width = area.width.min(60);
+        let height = area.height.min(20);
+        let area = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+
+        let block = Block::default()
+            .title("PATTERNS - Up/Down:Select Enter:Load Esc:Close")
+            .borders(Borders::ALL);
+        let inner = block.inner(area);
+        frame.render_widget(Clear, area);
+        frame.render_widget(block, area);
+
+        let patterns = self.sequencer_panel.browser_patterns();
+        if patterns.is_empty() {
+            frame.render_widget(Paragraph::new("No stored patterns (Alt+S stores a track)"),
+                                inner);
+            return;
+        }
+
+        // Scroll so the selected pattern stays in view
+        let selected = self.sequencer_panel.browser_selected();
+        let visible_rows = inner.height as usize;
+        let first_row = selected.saturating_sub(visible_rows.saturating_sub(1));
+        for (row, (idx, pattern)) in patterns.iter()
+            .enumerate()
+            .skip(first_row)
+            .take(visible_rows)
+            .enumerate() {
+            let (marker, style) = if idx == selected {
+                ("›", Style::default().fg(Color::Black).bg(Color::Cyan))
+            } else {
+                (" ", Style::default().fg(Color::White))
+            };
+            let line = format!("{}{:<28} {:>3} steps  {}",
+                               marker,
+                               pattern.name.chars().take(28).collect::<String>(),
+                               pattern.length,
+                               pattern.created.format("%Y-%m-%d %H:%M"));
+            let row_area = Rect { y: inner.y + row as u16, height: 1, ..inner };
+            frame.render_widget(Paragraph::new(line).style(style), row_area);
+        }
+    }
+    
     fn render_help(&self, frame: &mut Frame, area: Rect) {
         let help_text = r#"
 ROSCO TUI HELP - Week 2 Enhanced Controls
@@ -1202,14 +2816,46 @@ OSCILLATOR SECTION:
 TRANSPORT (8):
   Left/Right - Navigate between Play ▶ and Stop ■ buttons
   Enter/Space - Activate focused button (►[▶]◄ shows focus)
+  Up/Down    - Adjust swing (±5%)
+  =/-        - Adjust tempo (±1 BPM, +/_ for ±10), 20 - 300 BPM
+  v/V        - Master volume down/up (±5%, or ±1dB with volumes in dB)
+  w/W        - Stereo width narrower/wider (±10%), from 0% mono to 200%
+  c          - Toggle internal clock / follow MIDI clock
+  a/A        - Ramp the tempo up/down 20 BPM over 8 measures from the start, again to stop
+  s          - Next time signature (4/4, 3/4, 6/8, 5/4, 7/8), for the position and metronome
+  t          - Tap tempo, tap on the beat to set the tempo from the average interval
 
 TRACK GRID (5):
   Tab        - Cycle: Steps → Frequency
   Arrow Keys - Navigate grid (Up/Down: step/frequency rows)
   Enter/Space - Toggle step (Steps) / Open dropdown (Frequency)
   Up/Down    - Select pitch in dropdown mode
+  Shift+Up/Down - Raise/lower the octave of the current frequency cell, or the velocity of the
+               current step (0 - 127, the bar beside each step)
   Esc        - Exit dropdown mode
-  [C] Normal / ▼C▲ Dropdown - Visual states
+  w/W        - Next/previous waveform for the current track
+  [/]        - Shorter/longer sequence (8, 12, 16, 24 or 32 steps)
+  ,/.        - Lower/raise the chance the current step sounds (±10%, ◐ below 100%)
+  {/}        - Shorten/lengthen the current step's note (10% to 200% of the step)
+  </>        - Shorter/longer current track, for polyrhythms (dimmed steps don't play)
+  (/)        - Current track moves every fewer/more steps (1 to 4)
+  k/K        - Fill current track with an even rhythm of one more/fewer hit
+  Shift+Left/Right - Shift the current track's steps a step earlier/later, wrapping around
+  m/M        - Next/previous scale to keep pitches to (after the last, no scale)
+  n/N        - Scale root up/down a semitone
+  p/P        - Stack a third on the current step's chord / remove the top pitch
+  Enter on A - Arpeggiate the track's chords Up, Down, UpDown, Random or not (Off)
+  t/T        - Transpose the selection, or current track, up/down a semitone
+  o/O        - Transpose the selection, or current track, up/down an octave
+  i/l        - Loop playback from/to the current step, L to play the whole sequence
+  / and ?    - Ramp the selection's velocities / pitches evenly from its first step to its last
+  F3         - Piano keys: a-k set and play the current step's pitch (w e t y u sharps)
+  Ctrl+Z     - Undo the last grid edit
+  Ctrl+Y     - Redo (also Ctrl+Shift+Z)
+  Ctrl+P     - Browse stored patterns, Enter loads one into the current track
+  Alt+S/L    - Store the current track as a pattern / load the newest one
+  Alt+C/V    - Copy the current step's pitch, velocity and gate / paste it over another step
+  ›C3 Selected / ▼C3 Dropdown - Visual states
 
 TRACK VOLUME (6):
   Up/Down    - Navigate between tracks
@@ -1226,7 +2872,20 @@ REAL-TIME FEATURES:
 
 GLOBAL:
   F1         - Toggle this help
-  ESC        - Quit application
+  F2         - Start/stop recording the output to a WAV file
+  F4         - Metronome on/off, a click on each beat while playing
+  F5/F6      - Save/load the session (grid, synth and tempo)
+  F7         - Show volumes in percent or dB
+  F8         - Export the sequencer grid as a DSL script
+  F12        - Panic: stop playback and silence every note at once
+  ESC        - Quit application, asking first (y/n) if there are unsaved changes
+  These keys, Tab, the arrows, Enter, 1-8 and r can be rebound under [key_bindings] in the
+  config file, e.g. h = "NavigateLeft"
+
+MOUSE:
+  Click a step to toggle it, or a frequency to move the cursor there
+  Click or drag along a slider's bar to set it
+  Scroll wheel adjusts the focused control
         "#;
         
         let block = Block::default()
@@ -1240,4 +2899,372 @@ GLOBAL:
         let paragraph = Paragraph::new(help_text);
         frame.render_widget(paragraph, inner);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test_app {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent,
+                           MouseEventKind};
+    use ratatui::backend::TestBackend;
+    use ratatui::buffer::Buffer;
+    use ratatui::layout::Rect;
+    use ratatui::widgets::Widget;
+    use ratatui::Terminal;
+    use std::time::{Duration, Instant};
+
+    use crate::audio_gen::audio_gen::StreamError;
+    use crate::audio_gen::oscillator::Waveform;
+    use crate::common::float_utils::assert_float_eq;
+    use crate::meter::durations::DurationType;
+    use crate::note::scales::WesternPitch;
+    use crate::sequence::arpeggiator::ArpMode;
+    use crate::tui::app::{auto_save_due, is_too_small, next_reopen_backoff, slider_bar_area,
+                          tempo_from_taps, FocusArea, GridOnlyWidget, PlaybackPosition,
+                          RoscoTuiApp, SliderTarget, TimeSignature, TransportState,
+                          TIME_SIGNATURES, MAX_REOPEN_BACKOFF, MIN_TERMINAL_HEIGHT,
+                          MIN_TERMINAL_WIDTH, REOPEN_BACKOFF, TAP_HISTORY, TICKS_PER_BEAT};
+    use crate::tui::audio_bridge::{AudioFeedback, ParameterUpdate};
+    use crate::tui::audio_engine::{MAX_STEREO_WIDTH, MAX_TEMPO, MIN_TEMPO};
+    use crate::tui::config::TuiConfig;
+    use crate::tui::ui::widgets::SequencerGrid;
+
+    fn position(measure: u32, beat: u8, tick: u16) -> PlaybackPosition {
+        PlaybackPosition { measure, beat, tick }
+    }
+
+    #[test]
+    fn test_sixteen_sixteenths_in_four_four_is_one_measure() {
+        let four_four = TimeSignature::default();
+        assert_eq!(PlaybackPosition::from_steps(0, DurationType::Sixteenth, four_four),
+                   position(1, 1, 0));
+        assert_eq!(PlaybackPosition::from_steps(15, DurationType::Sixteenth, four_four),
+                   position(1, 4, TICKS_PER_BEAT * 3 / 4));
+        assert_eq!(PlaybackPosition::from_steps(16, DurationType::Sixteenth, four_four),
+                   position(2, 1, 0));
+    }
+
+    #[test]
+    fn test_quarter_steps_count_beats() {
+        let three_four = TimeSignature { numerator: 3, denominator: 4 };
+        assert_eq!(PlaybackPosition::from_steps(2, DurationType::Quarter, three_four),
+                   position(1, 3, 0));
+        assert_eq!(PlaybackPosition::from_steps(4, DurationType::Quarter, three_four),
+                   position(2, 2, 0));
+    }
+
+    #[test]
+    fn test_eighth_note_beats() {
+        // in 6/8 a beat is an eighth, so a quarter step moves two beats
+        let six_eight = TimeSignature { numerator: 6, denominator: 8 };
+        assert_eq!(PlaybackPosition::from_steps(2, DurationType::Quarter, six_eight),
+                   position(1, 5, 0));
+        assert_eq!(PlaybackPosition::from_steps(3, DurationType::Quarter, six_eight),
+                   position(2, 1, 0));
+    }
+
+    #[test]
+    fn test_adjust_tempo() {
+        let mut transport = TransportState::default();
+        assert_eq!(transport.adjust_tempo(1.0), 121.0);
+        assert_eq!(transport.adjust_tempo(-10.0), 111.0);
+    }
+
+    #[test]
+    fn test_adjust_tempo_is_clamped() {
+        let mut transport = TransportState::default();
+        assert_eq!(transport.adjust_tempo(1000.0), MAX_TEMPO);
+        assert_eq!(transport.adjust_tempo(10.0), MAX_TEMPO);
+        assert_eq!(transport.adjust_tempo(-1000.0), MIN_TEMPO);
+    }
+
+    #[test]
+    fn test_adjust_master_volume_is_clamped() {
+        let mut transport = TransportState::default();
+        assert_eq!(transport.adjust_master_volume(0.05), 1.0);
+        assert!((transport.adjust_master_volume(-0.25) - 0.75).abs() < 1e-6);
+        assert_eq!(transport.adjust_master_volume(-2.0), 0.0);
+    }
+
+    #[test]
+    fn test_adjust_stereo_width_is_clamped() {
+        let mut transport = TransportState::default();
+        assert!((transport.adjust_stereo_width(0.1) - 1.1).abs() < 1e-6);
+        assert_eq!(transport.adjust_stereo_width(5.0), MAX_STEREO_WIDTH);
+        assert_eq!(transport.adjust_stereo_width(-5.0), 0.0);
+    }
+
+    #[test]
+    fn test_toggle_tempo_ramp() {
+        let mut transport = TransportState::default();
+        // 8 measures of 4/4 at 120 BPM
+        assert_eq!(transport.toggle_tempo_ramp(20.0), Some((140.0, 16000.0)));
+        assert_eq!(transport.toggle_tempo_ramp(20.0), None);
+        transport.tempo = MIN_TEMPO;
+        assert_eq!(transport.toggle_tempo_ramp(-20.0).map(|(bpm, _)| bpm), Some(MIN_TEMPO));
+    }
+
+    #[test]
+    fn test_cycle_time_signature() {
+        let mut transport = TransportState::default();
+        let time_signatures: Vec<String> = (0..TIME_SIGNATURES.len())
+            .map(|_| transport.cycle_time_signature().to_string())
+            .collect();
+        assert_eq!(time_signatures, vec!["3/4", "6/8", "5/4", "7/8", "4/4"]);
+    }
+
+    #[test]
+    fn test_position_counts_on_when_the_sequence_wraps() {
+        let mut app = RoscoTuiApp::with_config(TuiConfig::default());
+        app.transport.is_playing = true;
+        // Once around a 16 step sequence of quarter note steps
+        app.transport.played_steps = 16;
+        app.transport.last_step_time = Instant::now();
+        app.update_transport_timing();
+        // Back on the first step of the sequence, but in the fifth measure
+        assert_eq!(app.transport.current_step, 0);
+        assert_eq!(app.transport.position, PlaybackPosition { measure: 5, beat: 1, tick: 0 });
+    }
+
+    #[test]
+    fn test_waveform_change_reaches_live_notes() {
+        let mut app = RoscoTuiApp::with_config(TuiConfig::default());
+        app.apply_synth_updates(vec![ParameterUpdate::OscillatorWaveform(Waveform::Square)])
+            .unwrap();
+        assert_eq!(app.audio_state.live_waveform(), Waveform::Square);
+        app.apply_synth_updates(vec![ParameterUpdate::OscillatorWaveform(Waveform::Saw)])
+            .unwrap();
+        assert_eq!(app.audio_state.live_waveform(), Waveform::Saw);
+    }
+
+    #[test]
+    fn test_oscillator_section_shows_frequency() {
+        let mut app = RoscoTuiApp::with_config(TuiConfig::default());
+        let mut terminal = Terminal::new(TestBackend::new(40, 8)).unwrap();
+        terminal.draw(|frame| app.render_oscillator_section(frame, frame.size())).unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer.get(x, y).symbol()).collect())
+            .collect();
+        let frequency_row = rows.iter().find(|row| row.contains("Freq:"))
+            .expect("no frequency row in the oscillator section");
+        assert!(frequency_row.contains("440.0"));
+        // Between the waveform and the volume
+        let row_of = |label: &str| rows.iter().position(|row| row.contains(label)).unwrap();
+        assert!(row_of("Wave:") < row_of("Freq:") && row_of("Freq:") < row_of("Vol:"));
+    }
+
+    #[test]
+    fn test_edit_sets_dirty_and_save_clears_it() {
+        let mut app = RoscoTuiApp::with_config(TuiConfig::default());
+        assert!(!app.dirty);
+        app.current_focus = FocusArea::Sequencer;
+        app.handle_key_event(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE)).unwrap();
+        assert!(app.dirty);
+
+        let path = std::env::temp_dir()
+            .join(format!("rosco_test_dirty_session_{}.json", std::process::id()));
+        let auto_save_path = std::env::temp_dir()
+            .join(format!("rosco_test_dirty_autosave_{}.json", std::process::id()));
+        std::fs::write(&auto_save_path, "{}").unwrap();
+        app.auto_save_path = Some(auto_save_path.clone());
+        app.save_session(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!app.dirty);
+        // Saving leaves no stale auto-save to offer recovering next time
+        assert!(!auto_save_path.exists());
+
+        app.current_focus = FocusArea::Transport;
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('v'), KeyModifiers::NONE)).unwrap();
+        assert!(app.dirty);
+    }
+
+    #[test]
+    fn test_quit_asks_first_with_unsaved_changes() {
+        let mut app = RoscoTuiApp::with_config(TuiConfig::default());
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        app.dirty = true;
+        assert!(!app.handle_key_event(key('q')).unwrap());
+        assert!(app.ui_state.confirm_quit);
+        // n goes back to the app
+        assert!(!app.handle_key_event(key('n')).unwrap());
+        assert!(!app.ui_state.confirm_quit);
+        app.handle_key_event(key('q')).unwrap();
+        assert!(app.handle_key_event(key('y')).unwrap());
+
+        app.dirty = false;
+        assert!(app.handle_key_event(key('q')).unwrap());
+    }
+
+    #[test]
+    fn test_reopen_backoff_doubles_up_to_the_longest_wait() {
+        assert_eq!(next_reopen_backoff(REOPEN_BACKOFF), 2 * REOPEN_BACKOFF);
+        assert_eq!(next_reopen_backoff(Duration::from_secs(5)), MAX_REOPEN_BACKOFF);
+        assert_eq!(next_reopen_backoff(MAX_REOPEN_BACKOFF), MAX_REOPEN_BACKOFF);
+    }
+
+    #[test]
+    fn test_auto_save_due() {
+        assert!(!auto_save_due(Duration::from_secs(59), 60));
+        assert!(auto_save_due(Duration::from_secs(60), 60));
+        assert!(auto_save_due(Duration::from_millis(90_500), 60));
+        // 0 turns auto-save off
+        assert!(!auto_save_due(Duration::from_secs(3600), 0));
+    }
+
+    #[test]
+    fn test_too_small_for_every_track() {
+        assert!(is_too_small(Rect::new(0, 0, 80, 20)));
+        assert!(is_too_small(Rect::new(0, 0, 40, 40)));
+        assert!(!is_too_small(Rect::new(0, 0, MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT)));
+        assert!(!is_too_small(Rect::new(0, 0, 200, 60)));
+    }
+
+    #[test]
+    fn test_smallest_terminal_shows_every_track() {
+        let mut app = RoscoTuiApp::with_config(TuiConfig::default());
+        let mut terminal = Terminal::new(
+            TestBackend::new(MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT)).unwrap();
+        terminal.draw(|frame| app.update_ui(frame)).unwrap();
+        // Two rows a track and the last row for scroll indicators
+        assert!(app.grid_area.height as usize > 2 * app.sequencer_panel.grid.tracks.len());
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 20)).unwrap();
+        terminal.draw(|frame| app.update_ui(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let first_row: String = (0..buffer.area.width).map(|x| buffer.get(x, 0).symbol()).collect();
+        assert!(first_row.starts_with("Terminal too small: 60x20"));
+        assert_eq!(app.grid_area, Rect::default());
+
+        app.ui_state.confirm_quit = true;
+        terminal.draw(|frame| app.update_ui(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let screen: String = (0..buffer.area.height)
+            .flat_map(|y| (0..buffer.area.width).map(move |x| (x, y)))
+            .map(|(x, y)| buffer.get(x, y).symbol())
+            .collect();
+        assert!(screen.contains("Quit anyway? y/n"));
+    }
+
+    #[test]
+    fn test_slider_bar_area_follows_the_drawn_bar() {
+        let row = Rect::new(2, 5, 30, 1);
+        assert_eq!(slider_bar_area(row, "Cut:  ███░░ 800Hz"), Rect::new(8, 5, 5, 1));
+        assert_eq!(slider_bar_area(row, "T1 ░░ 0%"), Rect::new(5, 5, 2, 1));
+        // Cut to the row it's drawn in
+        assert_eq!(slider_bar_area(Rect::new(0, 0, 8, 1), "Vol:  █████"), Rect::new(6, 0, 2, 1));
+    }
+
+    #[test]
+    fn test_mouse_volume_drag_is_one_undoable_edit() {
+        let mut app = RoscoTuiApp::with_config(TuiConfig::default());
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        terminal.draw(|frame| app.update_ui(frame)).unwrap();
+        let (_, bar) = *app.slider_areas.iter()
+            .find(|(target, _)| *target == SliderTarget::TrackVolume(0))
+            .unwrap();
+        let mouse = |kind, column| MouseEvent {
+            kind, column, row: bar.y, modifiers: KeyModifiers::NONE,
+        };
+        let volume = app.sequencer_panel.grid.tracks[0].volume;
+
+        app.handle_mouse_event(mouse(MouseEventKind::Down(MouseButton::Left), bar.x)).unwrap();
+        app.handle_mouse_event(mouse(MouseEventKind::Drag(MouseButton::Left), bar.x + 1)).unwrap();
+        app.handle_mouse_event(mouse(MouseEventKind::Up(MouseButton::Left), bar.x + 1)).unwrap();
+        assert!(app.sequencer_panel.grid.tracks[0].volume < volume);
+
+        app.sequencer_panel.undo();
+        assert_float_eq(app.sequencer_panel.grid.tracks[0].volume, volume);
+    }
+
+    #[test]
+    fn test_grid_frequency_cells_show_octave() {
+        let mut grid = SequencerGrid::new(16);
+        grid.set_current_step_pitch(WesternPitch::DSharp, 2);
+        let area = Rect::new(0, 0, 40, 3);
+        let mut buf = Buffer::empty(area);
+        GridOnlyWidget { grid }.render(area, &mut buf);
+
+        let frequency_cell: String = (2..6).map(|x| buf.get(x, 1).symbol()).collect();
+        assert_eq!(frequency_cell, " D#2");
+    }
+
+    #[test]
+    fn test_grid_shows_arp_mode_under_track_number() {
+        let mut grid = SequencerGrid::new(16);
+        grid.tracks[0].arp_mode = Some(ArpMode::UpDown);
+        let area = Rect::new(0, 0, 40, 5);
+        let mut buf = Buffer::empty(area);
+        GridOnlyWidget { grid }.render(area, &mut buf);
+
+        assert_eq!(buf.get(0, 1).symbol(), "↕");
+        // No arpeggio on the second track
+        assert_eq!(buf.get(0, 3).symbol(), " ");
+    }
+
+    fn taps(start: Instant, intervals_ms: &[u64]) -> Vec<Instant> {
+        let mut taps = vec![start];
+        for interval in intervals_ms {
+            taps.push(*taps.last().unwrap() + Duration::from_millis(*interval));
+        }
+        taps
+    }
+
+    #[test]
+    fn test_tempo_from_taps() {
+        let start = Instant::now();
+        assert_eq!(tempo_from_taps(&[]), None);
+        assert_eq!(tempo_from_taps(&[start]), None);
+        assert_float_eq(tempo_from_taps(&taps(start, &[500])).unwrap(), 120.0);
+        // Uneven taps average out
+        assert_float_eq(tempo_from_taps(&taps(start, &[480, 520, 500, 500])).unwrap(), 120.0);
+    }
+
+    #[test]
+    fn test_tempo_from_taps_ignores_outliers() {
+        let start = Instant::now();
+        // A missed tap and a double tap
+        let tempo = tempo_from_taps(&taps(start, &[600, 600, 1200, 600, 100, 600])).unwrap();
+        assert_float_eq(tempo, 100.0);
+    }
+
+    #[test]
+    fn test_tap_tempo_sets_tempo_and_starts_over_after_a_pause() {
+        let mut transport = TransportState::default();
+        let start = Instant::now();
+        assert_eq!(transport.tap_tempo(start), None);
+        assert_float_eq(transport.tap_tempo(start + Duration::from_millis(750)).unwrap(), 80.0);
+        assert_float_eq(transport.tempo, 80.0);
+
+        // After a pause the next tap is the first of a new tempo
+        let later = start + Duration::from_secs(10);
+        assert_eq!(transport.tap_tempo(later), None);
+        assert_float_eq(transport.tap_tempo(later + Duration::from_millis(400)).unwrap(), 150.0);
+
+        // Only the latest taps count
+        for tap in taps(later, &[250; 20]) {
+            transport.tap_tempo(tap);
+        }
+        assert_eq!(transport.tap_times.len(), TAP_HISTORY);
+        assert_float_eq(transport.tempo, 240.0);
+    }
+
+    #[test]
+    fn test_stream_error_shows_in_status_bar() {
+        let mut app = RoscoTuiApp::with_config(TuiConfig::default());
+        app.apply_feedback(AudioFeedback::StreamError(
+            StreamError("device unplugged".to_string())));
+        assert_eq!(app.ui_state.status_message.as_deref(), Some("Audio error: device unplugged"));
+    }
+
+    #[test]
+    fn test_alt_c_and_alt_v_reach_the_sequencer() {
+        let mut app = RoscoTuiApp::with_config(TuiConfig::default());
+        app.current_focus = FocusArea::Sequencer;
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        app.sequencer_panel.grid.tracks[0].steps[0].enabled = true;
+        app.handle_key_event(alt('c')).unwrap();
+        app.sequencer_panel.grid.cursor.track = 2;
+        app.handle_key_event(alt('v')).unwrap();
+        assert!(app.sequencer_panel.grid.tracks[2].steps[0].enabled);
+    }
+}
@@ -1,9 +1,14 @@
 use crate::tui::TuiError;
+use crate::tui::audio_engine::NUM_TRACKS;
+use crate::tui::ui::widgets::FilterType;
 use crate::audio_gen;
+use crate::audio_gen::audio_gen::StreamError;
 use ringbuf::{HeapRb, HeapProducer, HeapConsumer};
-use std::sync::Arc;
-use atomic_float::AtomicF32;
-use std::sync::atomic::Ordering;
+use std::collections::HashSet;
+use std::mem::{discriminant, Discriminant};
+
+// Updates queued for the audio engine before the oldest are coalesced to make room
+const PARAMETER_BUFFER_SIZE: usize = 1024;
 
 #[derive(Debug, Clone)]
 pub enum ParameterUpdate {
@@ -12,126 +17,176 @@ pub enum ParameterUpdate {
     OscillatorWaveform(audio_gen::Waveform),
     FilterCutoff(f32),
     FilterResonance(f32),
+    FilterType(FilterType),
+    FilterMix(f32),
+    MasterVolume(f32),
+    // Width of the mix's stereo image, from 0.0 mono to MAX_STEREO_WIDTH
+    StereoWidth(f32),
     EnvelopeAttack(f32),
     EnvelopeDecay(f32),
     EnvelopeSustain(f32),
     EnvelopeRelease(f32),
     SequencerStep { track: u8, step: u8, enabled: bool },
+    TrackWaveform { track: u8, waveform: audio_gen::Waveform },
     TransportPlay,
     TransportStop,
     TempoChange(f32),
+    // Ramp from the tempo to a tempo by a time in ms from the start of playback, None to hold
+    // the tempo steady
+    TempoRamp(Option<(f32, f32)>),
+    Swing(f32),
+    MidiNoteOn { note: u8, velocity: u8 },
+    MidiNoteOff { note: u8 },
+    ClockSource(crate::tui::audio_engine::ClockSource),
+    // One sequencer step's worth of MIDI clock pulses
+    MidiClockStep,
+    MidiStart,
+    MidiContinue,
+    MidiStop,
+    RecordStart { path: String },
+    RecordStop,
+    // Stop playback and cut off every sounding note, for when something gets stuck
+    Panic,
+}
+
+impl ParameterUpdate {
+    // Updates that set a value replace any queued update of the same value, so only the latest
+    // is worth sending. Events like notes and transport changes all have to arrive.
+    fn coalesce_key(&self) -> Option<(Discriminant<ParameterUpdate>, Option<u8>)> {
+        match self {
+            ParameterUpdate::OscillatorFrequency(_) |
+            ParameterUpdate::OscillatorVolume(_) |
+            ParameterUpdate::OscillatorWaveform(_) |
+            ParameterUpdate::FilterCutoff(_) |
+            ParameterUpdate::FilterResonance(_) |
+            ParameterUpdate::FilterType(_) |
+            ParameterUpdate::FilterMix(_) |
+            ParameterUpdate::MasterVolume(_) |
+            ParameterUpdate::StereoWidth(_) |
+            ParameterUpdate::EnvelopeAttack(_) |
+            ParameterUpdate::EnvelopeDecay(_) |
+            ParameterUpdate::EnvelopeSustain(_) |
+            ParameterUpdate::EnvelopeRelease(_) |
+            ParameterUpdate::TempoChange(_) |
+            ParameterUpdate::TempoRamp(_) |
+            ParameterUpdate::Swing(_) |
+            ParameterUpdate::ClockSource(_) => Some((discriminant(self), None)),
+            ParameterUpdate::TrackWaveform { track, .. } => {
+                Some((discriminant(self), Some(*track)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Drop every update that a later one of the same value replaces, keeping the rest in order
+pub fn coalesce_updates(updates: Vec<ParameterUpdate>) -> Vec<ParameterUpdate> {
+    let mut seen = HashSet::new();
+    let mut coalesced: Vec<ParameterUpdate> = updates.into_iter()
+        .rev()
+        .filter(|update| update.coalesce_key().is_none_or(|key| seen.insert(key)))
+        .collect();
+    coalesced.reverse();
+    coalesced
 }
 
 #[derive(Debug, Clone)]
 pub enum AudioFeedback {
     LevelMeter { track: u8, level: f32 },
+    // Peak and RMS of an output block, and the peak of each track in it
+    Levels { master_peak: f32, master_rms: f32, track_peaks: [f32; NUM_TRACKS] },
+    // Mono copy of an output block, downsampled to at most SCOPE_POINTS samples
+    Waveform(Vec<f32>),
+    // Error the output stream reported, such as its device going away
+    StreamError(StreamError),
     PlaybackPosition(f32),
     CpuUsage(f32),
     BufferHealth(f32),
 }
 
-pub struct AudioBridge {
-    // Parameter update channel (UI → Audio)
-    param_producer: HeapProducer<ParameterUpdate>,
-    param_consumer: HeapConsumer<ParameterUpdate>,
-    
-    // Audio feedback channel (Audio → UI)
-    feedback_producer: HeapProducer<AudioFeedback>,
-    feedback_consumer: HeapConsumer<AudioFeedback>,
-    
-    // Shared atomic parameters for high-frequency updates
-    oscillator_freq: Arc<AtomicF32>,
-    filter_cutoff: Arc<AtomicF32>,
-    master_volume: Arc<AtomicF32>,
+/// Parameter updates from the UI waiting for the audio engine to apply them. Bounded, so a flood
+/// of control changes never grows it without limit: when it fills, values that later updates
+/// replace are dropped to make room rather than blocking the UI.
+pub struct ParameterQueue {
+    producer: HeapProducer<ParameterUpdate>,
+    consumer: HeapConsumer<ParameterUpdate>,
 }
 
-impl AudioBridge {
-    pub fn new() -> Result<Self, TuiError> {
-        println!("Creating parameter ring buffer...");
-        // Create ring buffers for lock-free communication
-        let param_rb = HeapRb::<ParameterUpdate>::new(1024);
-        let (param_producer, param_consumer) = param_rb.split();
-        
-        println!("Creating feedback ring buffer...");
-        let feedback_rb = HeapRb::<AudioFeedback>::new(1024);
-        let (feedback_producer, feedback_consumer) = feedback_rb.split();
-        
-        println!("Creating atomic floats...");
-        let oscillator_freq = Arc::new(AtomicF32::new(440.0));
-        let filter_cutoff = Arc::new(AtomicF32::new(8000.0));
-        let master_volume = Arc::new(AtomicF32::new(0.75));
-        
-        println!("Constructing AudioBridge struct...");
-        Ok(Self {
-            param_producer,
-            param_consumer,
-            feedback_producer,
-            feedback_consumer,
-            oscillator_freq,
-            filter_cutoff,
-            master_volume,
-        })
+impl ParameterQueue {
+    pub fn new() -> Self {
+        let (producer, consumer) = HeapRb::<ParameterUpdate>::new(PARAMETER_BUFFER_SIZE).split();
+        Self { producer, consumer }
     }
-    
-    pub fn send_parameter_update(&mut self, update: ParameterUpdate) -> Result<(), TuiError> {
-        // Handle high-frequency parameters via atomics
-        match &update {
-            ParameterUpdate::OscillatorFrequency(freq) => {
-                self.oscillator_freq.store(*freq, Ordering::Relaxed);
-            }
-            ParameterUpdate::FilterCutoff(cutoff) => {
-                self.filter_cutoff.store(*cutoff, Ordering::Relaxed);
-            }
-            _ => {
-                // Send other parameters via ring buffer
-                if self.param_producer.push(update).is_err() {
-                    return Err(TuiError::Audio("Parameter update buffer full".to_string()));
-                }
+
+    pub fn push(&mut self, update: ParameterUpdate) -> Result<(), TuiError> {
+        if let Err(update) = self.producer.push(update) {
+            let mut queued: Vec<ParameterUpdate> = self.consumer.pop_iter().collect();
+            queued.push(update);
+            let mut coalesced = coalesce_updates(queued).into_iter();
+            self.producer.push_iter(&mut coalesced);
+            if coalesced.next().is_some() {
+                return Err(TuiError::Audio("Parameter update buffer full".to_string()));
             }
         }
         Ok(())
     }
-    
-    pub fn receive_parameter_updates(&mut self) -> Vec<ParameterUpdate> {
-        let mut updates = Vec::new();
-        while let Some(update) = self.param_consumer.pop() {
-            updates.push(update);
-        }
-        updates
-    }
-    
-    pub fn send_audio_feedback(&mut self, feedback: AudioFeedback) -> Result<(), TuiError> {
-        if self.feedback_producer.push(feedback).is_err() {
-            return Err(TuiError::Audio("Feedback buffer full".to_string()));
-        }
-        Ok(())
-    }
-    
-    pub fn receive_audio_feedback(&mut self) -> Vec<AudioFeedback> {
-        let mut feedback = Vec::new();
-        while let Some(fb) = self.feedback_consumer.pop() {
-            feedback.push(fb);
-        }
-        feedback
-    }
-    
-    pub fn get_oscillator_frequency(&self) -> f32 {
-        self.oscillator_freq.load(Ordering::Relaxed)
+
+    /// Updates queued since the last call, only the latest of each value
+    pub fn drain(&mut self) -> Vec<ParameterUpdate> {
+        coalesce_updates(self.consumer.pop_iter().collect())
     }
-    
-    pub fn get_filter_cutoff(&self) -> f32 {
-        self.filter_cutoff.load(Ordering::Relaxed)
+}
+
+impl Default for ParameterQueue {
+    fn default() -> Self {
+        Self::new()
     }
-    
-    pub fn get_master_volume(&self) -> f32 {
-        self.master_volume.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod test_audio_bridge {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::tui::audio_bridge::{coalesce_updates, ParameterQueue, ParameterUpdate,
+                                   PARAMETER_BUFFER_SIZE};
+
+    #[test]
+    fn test_coalesce_keeps_latest_values_and_every_event() {
+        let updates = vec![
+            ParameterUpdate::OscillatorVolume(0.1),
+            ParameterUpdate::MidiNoteOn { note: 60, velocity: 100 },
+            ParameterUpdate::TrackWaveform { track: 0, waveform: crate::audio_gen::Waveform::Saw },
+            ParameterUpdate::OscillatorVolume(0.2),
+            ParameterUpdate::TrackWaveform { track: 1, waveform: crate::audio_gen::Waveform::Sine },
+            ParameterUpdate::MidiNoteOn { note: 60, velocity: 100 },
+            ParameterUpdate::OscillatorVolume(0.3),
+        ];
+        let coalesced = coalesce_updates(updates);
+        assert!(matches!(coalesced.as_slice(), [
+            ParameterUpdate::MidiNoteOn { .. },
+            ParameterUpdate::TrackWaveform { track: 0, .. },
+            ParameterUpdate::TrackWaveform { track: 1, .. },
+            ParameterUpdate::MidiNoteOn { .. },
+            ParameterUpdate::OscillatorVolume(volume),
+        ] if *volume == 0.3));
     }
-    
-    pub fn get_atomic_refs(&self) -> (Arc<AtomicF32>, Arc<AtomicF32>, Arc<AtomicF32>) {
-        (
-            Arc::clone(&self.oscillator_freq),
-            Arc::clone(&self.filter_cutoff),
-            Arc::clone(&self.master_volume),
-        )
+
+    #[test]
+    fn test_flooded_queue_keeps_the_latest_value() {
+        let mut queue = ParameterQueue::new();
+        let num_updates = 4 * PARAMETER_BUFFER_SIZE;
+        for i in 0..num_updates {
+            queue.push(ParameterUpdate::OscillatorFrequency(100.0 + i as f32)).unwrap();
+            queue.push(ParameterUpdate::OscillatorVolume(i as f32 / num_updates as f32)).unwrap();
+        }
+        queue.push(ParameterUpdate::TransportPlay).unwrap();
+
+        let updates = queue.drain();
+        let [ParameterUpdate::OscillatorFrequency(freq), ParameterUpdate::OscillatorVolume(volume),
+             ParameterUpdate::TransportPlay] = updates.as_slice() else {
+            panic!("expected the latest frequency and volume then play, got {:?}", updates);
+        };
+        assert_float_eq(*freq, 100.0 + (num_updates - 1) as f32);
+        assert_float_eq(*volume, (num_updates - 1) as f32 / num_updates as f32);
+        assert!(queue.drain().is_empty());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,2505 @@
+use crate::audio_gen::audio_gen::{stream_error_handler, StreamError, WavFormat,
+                                  STREAM_ERROR_BUFFER_SIZE};
+use crate::audio_gen::get_sample;
+use crate::audio_gen::oscillator::{OscillatorTables, Waveform};
+use crate::common::constants::{SAMPLE_RATE, SAMPLES_PER_MS};
+use crate::composition::tempo_map::{TempoCurve, TempoMap};
+use crate::effect::limiter::{default_limiter, Limiter};
+use crate::effect::stereo_width::{default_stereo_width, StereoWidth};
+use crate::envelope::envelope::Envelope;
+use crate::filter::band_pass_filter::{BandPassFilter, BandPassFilterBuilder};
+use crate::filter::dc_blocker::{default_dc_blocker, DcBlocker};
+use crate::filter::high_pass_filter::{HighPassFilter, HighPassFilterBuilder};
+use crate::filter::low_pass_filter::{LowPassFilter, LowPassFilterBuilder};
+use crate::filter::notch_filter::{NotchFilter, NotchFilterBuilder};
+use crate::meter::durations::DurationType;
+use crate::note::constants::PITCH_TO_FREQ_HZ;
+use crate::note::playback_note::{NoteType, PlaybackNote, PlaybackNoteBuilder};
+use crate::note::scales::WesternPitch;
+use crate::sequence::arpeggiator::{ArpMode, Arpeggiator};
+use crate::tui::audio_bridge::{AudioFeedback, ParameterQueue, ParameterUpdate};
+use crate::tui::midi_input::midi_note_to_frequency;
+use crate::tui::ui::widgets::{FilterType, SequencerGrid, StepCell, FILTER_TYPES, MAX_CHORD_PITCHES,
+                              MAX_GATE_LENGTH, MAX_STEP_PROBABILITY, MIN_GATE_LENGTH,
+                              TRACK_WAVEFORMS};
+use crate::tui::{TrackBridge, TuiError};
+use atomic_float::AtomicF32;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub const NUM_TRACKS: usize = 8;
+const DEFAULT_STEPS_PER_TRACK: usize = 16;
+pub const MAX_STEPS_PER_TRACK: usize = 32;
+const MAX_VOICES_PER_TRACK: usize = 8;
+const DEFAULT_TEMPO: f32 = 120.0;
+pub const MAX_SWING: f32 = 0.66;
+pub const MAX_STEREO_WIDTH: f32 = 2.0;
+pub const MIN_TEMPO: f32 = 20.0;
+pub const MAX_TEMPO: f32 = 300.0;
+// Step probabilities are drawn from a fixed seed so a pattern plays the same way each time
+const STEP_PROBABILITY_SEED: u64 = 0x5eed;
+const NUM_MIDI_NOTES: usize = 128;
+const MAX_LIVE_VOICES: usize = 16;
+// A held note stops after this long in case its note off never arrives
+const MAX_LIVE_NOTE_MS: f32 = 60000.0;
+// Arpeggiated chords play a note every sixteenth, four to each step
+const ARP_RATE: DurationType = DurationType::Sixteenth;
+const DEFAULT_LIMITER_THRESHOLD: f32 = 0.9;
+const DEFAULT_LIMITER_RELEASE_MS: f32 = 100.0;
+// Two seconds of interleaved stereo, far longer than the writer thread ever lags
+const RECORD_BUFFER_SIZE: usize = 2 * 2 * SAMPLE_RATE as usize;
+const RECORD_DRAIN_INTERVAL_MS: u64 = 10;
+const RECORD_WAV_FORMAT: WavFormat = WavFormat::Float32;
+pub const MIN_FILTER_CUTOFF: f32 = 20.0;
+pub const MAX_FILTER_CUTOFF: f32 = 20000.0;
+pub const DEFAULT_MASTER_FILTER_CUTOFF: f32 = 8000.0;
+pub const DEFAULT_MASTER_FILTER_RESONANCE: f32 = 0.3;
+// Dry, so the mix is unfiltered until the filter is dialed in
+pub const DEFAULT_MASTER_FILTER_MIX: f32 = 0.0;
+// ADSR of the notes the sequencer plays, times in seconds
+pub const DEFAULT_ENVELOPE_ATTACK: f32 = 0.01;
+pub const DEFAULT_ENVELOPE_DECAY: f32 = 0.1;
+pub const DEFAULT_ENVELOPE_SUSTAIN: f32 = 0.8;
+pub const DEFAULT_ENVELOPE_RELEASE: f32 = 0.05;
+pub const MAX_ENVELOPE_TIME: f32 = 2.0;
+// Metronome clicks are short sine bursts, higher and louder on the first beat of a measure
+const CLICK_MS: f32 = 30.0;
+const CLICK_FREQUENCY: f32 = 1000.0;
+const ACCENT_CLICK_FREQUENCY: f32 = 1500.0;
+const CLICK_GAIN: f32 = 0.3;
+const ACCENT_CLICK_GAIN: f32 = 0.6;
+const DEFAULT_BEATS_PER_MEASURE: usize = 4;
+const DEFAULT_BEAT_UNIT: usize = 4;
+// Levels of the last few output blocks, the UI takes them every frame so it never needs many
+const FEEDBACK_BUFFER_SIZE: usize = 64;
+// Samples of each output block sent to the scope, plenty for a panel a few dozen columns wide
+pub const SCOPE_POINTS: usize = 128;
+// Scope frames waiting for the UI, which only ever draws the newest
+const SCOPE_BUFFER_SIZE: usize = 8;
+
+/// What advances the sequencer's steps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClockSource {
+    /// The engine's own clock at `tempo`
+    #[default]
+    Internal,
+    /// MIDI clock, start and stop from external gear
+    MidiExternal,
+}
+
+/// State shared between the UI thread and the audio callback
+pub struct AudioState {
+    pub is_playing: AtomicBool,
+    pub tempo: AtomicF32,
+    /// Gain applied to the whole mix ahead of the limiter
+    pub master_volume: AtomicF32,
+    /// Step of the sequencer clock most recently triggered by the audio callback
+    pub current_step: AtomicUsize,
+    /// Steps played since playback started, counting on past the end of the sequence and around
+    /// loops, so the transport can show how far into the song playback is
+    pub played_steps: AtomicU64,
+    /// Step each track most recently played, tracks with their own length or divisor move through
+    /// their steps at their own phase
+    pub track_current_steps: [AtomicUsize; NUM_TRACKS],
+    /// Length of the sequence, the step after the last one is the first
+    pub steps_per_track: AtomicUsize,
+    /// Fraction of a step, from 0.0 to MAX_SWING, that odd steps are delayed by
+    pub swing: AtomicF32,
+    pub limiter_threshold: AtomicF32,
+    pub limiter_release_ms: AtomicF32,
+    /// Width of the mix's stereo image, 0.0 mono, 1.0 as mixed and up to MAX_STEREO_WIDTH wider
+    pub stereo_width: AtomicF32,
+    /// While set the audio callback copies its output to the recording buffer
+    pub is_recording: AtomicBool,
+    /// High-pass the mix ahead of the limiter to remove DC offset
+    pub dc_blocker_enabled: AtomicBool,
+    /// Pan of each track from -1.0, hard left, to 1.0, hard right
+    pub track_pans: [AtomicF32; NUM_TRACKS],
+    pub track_mutes: [AtomicBool; NUM_TRACKS],
+    pub track_solos: [AtomicBool; NUM_TRACKS],
+    /// Waveform of each track, as an index into TRACK_WAVEFORMS
+    pub track_waveforms: [AtomicU32; NUM_TRACKS],
+    /// Velocity of each MIDI note held on a connected keyboard, 0 while the note is up
+    pub live_note_velocities: [AtomicU8; NUM_MIDI_NOTES],
+    /// Waveform of notes played from MIDI input, as an index into TRACK_WAVEFORMS
+    pub live_waveform: AtomicU32,
+    /// Set while the sequencer follows MIDI clock rather than its own tempo
+    pub clock_external: AtomicBool,
+    /// Steps the external clock has called for since it last started
+    pub external_ticks: AtomicU64,
+    /// Counts MIDI starts, so the engine can tell the external clock restarted
+    pub external_starts: AtomicU32,
+    /// Sequencer tracks, synced from the grid by the UI and read by the audio callback
+    pub tracks: Mutex<TrackBridge>,
+    /// While set the tempo follows the map from the start of playback rather than `tempo`
+    pub(crate) tempo_map: Mutex<Option<TempoMap>>,
+    /// Type of the filter on the whole mix, as an index into FILTER_TYPES
+    pub master_filter_type: AtomicU32,
+    /// Cutoff of the low and high pass filters, center of the band pass and notch filters
+    pub master_filter_cutoff: AtomicF32,
+    pub master_filter_resonance: AtomicF32,
+    /// Blend of the filtered mix from 0.0, unfiltered, to 1.0, fully filtered
+    pub master_filter_mix: AtomicF32,
+    /// Envelope of the sequencer's notes, fitted to each note's gate
+    pub envelope_attack: AtomicF32,
+    pub envelope_decay: AtomicF32,
+    pub envelope_sustain: AtomicF32,
+    pub envelope_release: AtomicF32,
+    /// While set a click sounds on each beat, for playing or editing along in time
+    pub metronome_on: AtomicBool,
+    /// Beats in a measure, the metronome accents the first of them
+    pub beats_per_measure: AtomicUsize,
+    /// Note value of a beat, 4 for a quarter and 8 for an eighth, which sets how many beats
+    /// each quarter note step lasts
+    pub beat_unit: AtomicUsize,
+    /// While set playback loops from loop_start to loop_end, both played, rather than through
+    /// the whole sequence
+    pub loop_enabled: AtomicBool,
+    pub loop_start: AtomicUsize,
+    pub loop_end: AtomicUsize,
+    /// Counts panics, so the engine can tell to cut off every voice
+    pub panics: AtomicU32,
+}
+
+impl AudioState {
+    pub fn new() -> Self {
+        Self {
+            is_playing: AtomicBool::new(false),
+            tempo: AtomicF32::new(DEFAULT_TEMPO),
+            master_volume: AtomicF32::new(1.0),
+            current_step: AtomicUsize::new(0),
+            played_steps: AtomicU64::new(0),
+            track_current_steps: std::array::from_fn(|_| AtomicUsize::new(0)),
+            steps_per_track: AtomicUsize::new(DEFAULT_STEPS_PER_TRACK),
+            swing: AtomicF32::new(0.0),
+            limiter_threshold: AtomicF32::new(DEFAULT_LIMITER_THRESHOLD),
+            limiter_release_ms: AtomicF32::new(DEFAULT_LIMITER_RELEASE_MS),
+            stereo_width: AtomicF32::new(1.0),
+            is_recording: AtomicBool::new(false),
+            dc_blocker_enabled: AtomicBool::new(true),
+            track_pans: std::array::from_fn(|_| AtomicF32::new(0.0)),
+            track_mutes: std::array::from_fn(|_| AtomicBool::new(false)),
+            track_solos: std::array::from_fn(|_| AtomicBool::new(false)),
+            track_waveforms: std::array::from_fn(|_| AtomicU32::new(0)),
+            live_note_velocities: std::array::from_fn(|_| AtomicU8::new(0)),
+            live_waveform: AtomicU32::new(0),
+            clock_external: AtomicBool::new(false),
+            external_ticks: AtomicU64::new(0),
+            external_starts: AtomicU32::new(0),
+            tracks: Mutex::new(TrackBridge::new(NUM_TRACKS, DEFAULT_STEPS_PER_TRACK,
+                                                 DEFAULT_TEMPO as u8)),
+            tempo_map: Mutex::new(None),
+            master_filter_type: AtomicU32::new(0),
+            master_filter_cutoff: AtomicF32::new(DEFAULT_MASTER_FILTER_CUTOFF),
+            master_filter_resonance: AtomicF32::new(DEFAULT_MASTER_FILTER_RESONANCE),
+            master_filter_mix: AtomicF32::new(DEFAULT_MASTER_FILTER_MIX),
+            envelope_attack: AtomicF32::new(DEFAULT_ENVELOPE_ATTACK),
+            envelope_decay: AtomicF32::new(DEFAULT_ENVELOPE_DECAY),
+            envelope_sustain: AtomicF32::new(DEFAULT_ENVELOPE_SUSTAIN),
+            envelope_release: AtomicF32::new(DEFAULT_ENVELOPE_RELEASE),
+            metronome_on: AtomicBool::new(false),
+            beats_per_measure: AtomicUsize::new(DEFAULT_BEATS_PER_MEASURE),
+            beat_unit: AtomicUsize::new(DEFAULT_BEAT_UNIT),
+            loop_enabled: AtomicBool::new(false),
+            loop_start: AtomicUsize::new(0),
+            loop_end: AtomicUsize::new(DEFAULT_STEPS_PER_TRACK - 1),
+            panics: AtomicU32::new(0),
+        }
+    }
+
+    pub(crate) fn set_tempo_map(&self, tempo_map: Option<TempoMap>) {
+        if let Ok(mut tempo_map_slot) = self.tempo_map.lock() {
+            *tempo_map_slot = tempo_map;
+        }
+    }
+
+    /// Apply the updates that are carried by this state rather than by the engine
+    pub fn handle_parameter_update(&self, update: &ParameterUpdate) {
+        match update {
+            ParameterUpdate::TrackWaveform { track, waveform } => {
+                if let Some(track_waveform) = self.track_waveforms.get(*track as usize) {
+                    track_waveform.store(waveform_index(*waveform), Ordering::Relaxed);
+                }
+            }
+            ParameterUpdate::Swing(swing) => {
+                self.swing.store(swing.clamp(0.0, MAX_SWING), Ordering::Relaxed);
+            }
+            ParameterUpdate::TempoChange(tempo) => {
+                self.tempo.store(tempo.clamp(MIN_TEMPO, MAX_TEMPO), Ordering::Relaxed);
+            }
+            ParameterUpdate::TempoRamp(ramp) => {
+                let tempo = self.tempo.load(Ordering::Relaxed);
+                self.set_tempo_map(ramp.and_then(|(ramp_bpm, ramp_ms)| {
+                    TempoMap::from_points(&[(0.0, tempo),
+                                            (ramp_ms, ramp_bpm.clamp(MIN_TEMPO, MAX_TEMPO))],
+                                          TempoCurve::Linear).ok()
+                }));
+            }
+            ParameterUpdate::MasterVolume(volume) => {
+                self.master_volume.store(volume.clamp(0.0, 1.0), Ordering::Relaxed);
+            }
+            ParameterUpdate::StereoWidth(width) => {
+                self.stereo_width.store(width.clamp(0.0, MAX_STEREO_WIDTH), Ordering::Relaxed);
+            }
+            ParameterUpdate::OscillatorWaveform(waveform) => {
+                self.live_waveform.store(waveform_index(*waveform), Ordering::Relaxed);
+            }
+            ParameterUpdate::FilterType(filter_type) => {
+                self.master_filter_type.store(filter_type_index(*filter_type), Ordering::Relaxed);
+            }
+            ParameterUpdate::FilterCutoff(cutoff) => {
+                self.master_filter_cutoff.store(cutoff.clamp(MIN_FILTER_CUTOFF, MAX_FILTER_CUTOFF),
+                                                Ordering::Relaxed);
+            }
+            ParameterUpdate::FilterResonance(resonance) => {
+                self.master_filter_resonance.store(resonance.clamp(0.0, 1.0), Ordering::Relaxed);
+            }
+            ParameterUpdate::FilterMix(mix) => {
+                self.master_filter_mix.store(mix.clamp(0.0, 1.0), Ordering::Relaxed);
+            }
+            ParameterUpdate::EnvelopeAttack(attack) => {
+                self.envelope_attack.store(attack.clamp(0.0, MAX_ENVELOPE_TIME), Ordering::Relaxed);
+            }
+            ParameterUpdate::EnvelopeDecay(decay) => {
+                self.envelope_decay.store(decay.clamp(0.0, MAX_ENVELOPE_TIME), Ordering::Relaxed);
+            }
+            ParameterUpdate::EnvelopeSustain(sustain) => {
+                self.envelope_sustain.store(sustain.clamp(0.0, 1.0), Ordering::Relaxed);
+            }
+            ParameterUpdate::EnvelopeRelease(release) => {
+                self.envelope_release.store(release.clamp(0.0, MAX_ENVELOPE_TIME),
+                                            Ordering::Relaxed);
+            }
+            ParameterUpdate::MidiNoteOn { note, velocity } => {
+                if let Some(live_note) = self.live_note_velocities.get(*note as usize) {
+                    live_note.store((*velocity).max(1), Ordering::Relaxed);
+                }
+            }
+            ParameterUpdate::MidiNoteOff { note } => {
+                if let Some(live_note) = self.live_note_velocities.get(*note as usize) {
+                    live_note.store(0, Ordering::Relaxed);
+                }
+            }
+            ParameterUpdate::ClockSource(clock_source) => {
+                self.clock_external.store(*clock_source == ClockSource::MidiExternal,
+                                          Ordering::Relaxed);
+            }
+            // MIDI transport messages are ignored while the internal clock runs
+            ParameterUpdate::MidiClockStep if self.is_clock_external() => {
+                self.external_ticks.fetch_add(1, Ordering::Relaxed);
+            }
+            ParameterUpdate::MidiStart if self.is_clock_external() => {
+                // The first step plays on start, the next after a step of clocks
+                self.external_ticks.store(1, Ordering::Relaxed);
+                self.external_starts.fetch_add(1, Ordering::Relaxed);
+                self.is_playing.store(true, Ordering::Relaxed);
+            }
+            ParameterUpdate::MidiContinue if self.is_clock_external() => {
+                self.is_playing.store(true, Ordering::Relaxed);
+            }
+            ParameterUpdate::MidiStop if self.is_clock_external() => {
+                self.is_playing.store(false, Ordering::Relaxed);
+            }
+            ParameterUpdate::Panic => {
+                self.is_playing.store(false, Ordering::Relaxed);
+                for live_note in self.live_note_velocities.iter() {
+                    live_note.store(0, Ordering::Relaxed);
+                }
+                self.panics.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn clock_source(&self) -> ClockSource {
+        if self.is_clock_external() {
+            ClockSource::MidiExternal
+        } else {
+            ClockSource::Internal
+        }
+    }
+
+    fn is_clock_external(&self) -> bool {
+        self.clock_external.load(Ordering::Relaxed)
+    }
+
+    /// Push the grid's tracks, every one of their steps, and the sequence length and loop to the
+    /// audio callback
+    pub fn sync_from_grid(&self, grid: &SequencerGrid) {
+        if let Ok(mut tracks) = self.tracks.lock() {
+            tracks.sync_from_tui(&grid.tracks);
+        }
+        self.steps_per_track.store(grid.steps_per_track, Ordering::Relaxed);
+        self.loop_enabled.store(grid.loop_region.is_some(), Ordering::Relaxed);
+        if let Some((loop_start, loop_end)) = grid.loop_region {
+            self.loop_start.store(loop_start, Ordering::Relaxed);
+            self.loop_end.store(loop_end, Ordering::Relaxed);
+        }
+    }
+
+    /// The loop's first and last steps, or None while playback runs through the whole sequence
+    pub fn loop_region(&self) -> Option<(usize, usize)> {
+        if !self.loop_enabled.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some((self.loop_start.load(Ordering::Relaxed), self.loop_end.load(Ordering::Relaxed)))
+    }
+
+    pub fn track_waveform(&self, track_idx: usize) -> Waveform {
+        waveform_from_index(self.track_waveforms[track_idx].load(Ordering::Relaxed))
+    }
+
+    pub fn live_waveform(&self) -> Waveform {
+        waveform_from_index(self.live_waveform.load(Ordering::Relaxed))
+    }
+
+    /// The envelope for a sequencer note lasting `note_duration_ms`
+    pub(crate) fn note_envelope(&self, note_duration_ms: f32) -> Envelope {
+        Envelope::from_adsr_ms(self.envelope_attack.load(Ordering::Relaxed) * 1000.0,
+                               self.envelope_decay.load(Ordering::Relaxed) * 1000.0,
+                               self.envelope_sustain.load(Ordering::Relaxed),
+                               self.envelope_release.load(Ordering::Relaxed) * 1000.0,
+                               note_duration_ms)
+    }
+
+    pub fn master_filter_type(&self) -> FilterType {
+        FILTER_TYPES.get(self.master_filter_type.load(Ordering::Relaxed) as usize).copied()
+            .unwrap_or(FILTER_TYPES[0])
+    }
+}
+
+impl Default for AudioState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Waveforms not in TRACK_WAVEFORMS are stored as the first one
+fn waveform_index(waveform: Waveform) -> u32 {
+    TRACK_WAVEFORMS.iter().position(|track_waveform| *track_waveform == waveform).unwrap_or(0) as u32
+}
+
+fn waveform_from_index(index: u32) -> Waveform {
+    TRACK_WAVEFORMS.get(index as usize).copied().unwrap_or(TRACK_WAVEFORMS[0])
+}
+
+fn filter_type_index(filter_type: FilterType) -> u32 {
+    FILTER_TYPES.iter().position(|each_type| *each_type == filter_type).unwrap_or(0) as u32
+}
+
+/// Number of samples from the start of this step to the start of the next. Swing lengthens even
+/// steps and shortens odd ones by the same amount, so odd steps land swing * samples_per_step late
+/// while each pair of steps, and so the tempo, stays the same length.
+pub fn swung_step_samples(step: usize, samples_per_step: u64, swing: f32) -> u64 {
+    let offset = (swing.clamp(0.0, MAX_SWING) * samples_per_step as f32) as u64;
+    if step.is_multiple_of(2) {
+        samples_per_step + offset
+    } else {
+        samples_per_step - offset
+    }
+}
+
+/// Whether an enabled step with this percent probability sounds on this pass. Steps at 100 always
+/// sound without drawing from the RNG, so fully probable patterns are deterministic.
+pub fn step_fires<R: Rng>(probability: u8, rng: &mut R) -> bool {
+    if probability >= MAX_STEP_PROBABILITY {
+        return true;
+    }
+    rng.random_range(0..MAX_STEP_PROBABILITY) < probability
+}
+
+/// Where the sequencer clock is on this tick, in steps from the start of the sequence, for the
+/// transport's step display. Without a loop this is the tick itself. With one, it starts at the
+/// loop's first step and goes back to it after the last, with the region kept within the
+/// sequence. Each track loops at its own pace, see track_step.
+pub fn loop_position(tick: u64, steps_per_track: usize, loop_region: Option<(usize, usize)>)
+                     -> u64 {
+    let Some((loop_start, loop_end)) = loop_region else {
+        return tick;
+    };
+    let last_step = steps_per_track.max(1) - 1;
+    let loop_start = loop_start.min(last_step);
+    let loop_end = loop_end.clamp(loop_start, last_step);
+    (loop_start + (tick % (loop_end - loop_start + 1) as u64) as usize) as u64
+}
+
+/// The step a track plays on this tick of the sequencer clock, or None if it doesn't move on this
+/// tick. A track with a divisor of n moves every nth tick and wraps after its own length, so tracks
+/// of different lengths drift against each other and realign when their cycles do. With a loop
+/// region the track's moves go through the region's steps instead, kept within the track, so a
+/// slower track still plays each step of the loop.
+pub fn track_step(tick: u64, track_length: usize, divisor: usize,
+                  loop_region: Option<(usize, usize)>) -> Option<usize> {
+    let divisor = divisor.max(1) as u64;
+    if !tick.is_multiple_of(divisor) {
+        return None;
+    }
+    let moves = tick / divisor;
+    let last_step = track_length.max(1) - 1;
+    let (loop_start, loop_end) = loop_region.unwrap_or((0, last_step));
+    let loop_start = loop_start.min(last_step);
+    let loop_end = loop_end.clamp(loop_start, last_step);
+    Some(loop_start + (moves % (loop_end - loop_start + 1) as u64) as usize)
+}
+
+/// Length of a step's note. The gate is a fraction of the step, so a gate over 1.0 holds the note
+/// into the following steps. The note's envelope is scaled to this length, so its release always
+/// finishes as the gate closes.
+pub fn gate_duration_ms(gate_length: f32, step_duration_ms: f32) -> f32 {
+    gate_length.clamp(MIN_GATE_LENGTH, MAX_GATE_LENGTH) * step_duration_ms
+}
+
+/// Equal-power (left, right) gains for a pan from -1.0, hard left, to 1.0, hard right. The
+/// combined power is the same at every pan, so a track doesn't get louder or quieter as it moves.
+pub fn pan_gains(pan: f32) -> (f32, f32) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+/// Whether each track is heard. A muted track never is, and while any track is soloed only the
+/// soloed tracks are.
+pub fn audible_tracks<const N: usize>(mutes: &[bool; N], solos: &[bool; N]) -> [bool; N] {
+    let any_solo = solos.iter().any(|solo| *solo);
+    std::array::from_fn(|track_idx| !mutes[track_idx] && (!any_solo || solos[track_idx]))
+}
+
+/// Whether the metronome click on this step from the start is the accented one, on the first
+/// beat of a measure. Steps are quarter notes, so in 6/8 a step lasts two beats and a measure
+/// starts every third step. In 7/8 a measure can start partway through a step, and then no step
+/// starts on its first beat, as the transport shows.
+pub fn is_accent_click(step: u64, beats_per_measure: usize, beat_unit: usize) -> bool {
+    // In quarters of a beat a step is beat_unit long, and a measure 4 * beats_per_measure
+    let quarter_beats = step * beat_unit.max(1) as u64;
+    quarter_beats % (4 * beats_per_measure.max(1) as u64) < 4
+}
+
+/// Peak and RMS of a block of samples, both 0.0 for an empty block
+pub fn peak_and_rms(samples: &[f32]) -> (f32, f32) {
+    if samples.is_empty() {
+        return (0.0, 0.0);
+    }
+    let peak = samples.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    let mean_square = samples.iter().map(|sample| sample * sample).sum::<f32>() /
+        samples.len() as f32;
+    (peak, mean_square.sqrt())
+}
+
+/// Mono copy of an interleaved block for the scope, written into `points` without allocating.
+/// At most as many frames as fit are picked evenly across the block, each the average of its
+/// first two channels. Returns the number of points written.
+pub fn scope_samples(output: &[f32], channels: usize, points: &mut [f32]) -> usize {
+    let channels = channels.max(1);
+    let frames = output.len() / channels;
+    let num_points = points.len().min(frames);
+    for (point, sample) in points[..num_points].iter_mut().enumerate() {
+        let frame = &output[point * frames / num_points * channels..][..channels];
+        *sample = if channels == 1 { frame[0] } else { 0.5 * (frame[0] + frame[1]) };
+    }
+    num_points
+}
+
+/// Mono copy of an output block for the scope, the first `len` of `samples`. A fixed array, so the
+/// audio thread fills one in a preallocated buffer without allocating.
+#[derive(Debug, Clone, Copy)]
+pub struct ScopeFrame {
+    samples: [f32; SCOPE_POINTS],
+    len: usize,
+}
+
+impl ScopeFrame {
+    fn from_block(output: &[f32], channels: usize) -> Self {
+        let mut samples = [0.0; SCOPE_POINTS];
+        let len = scope_samples(output, channels, &mut samples);
+        Self { samples, len }
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples[..self.len]
+    }
+}
+
+/// A metronome click, a sine burst that dies away over CLICK_MS
+struct Click {
+    frequency: f32,
+    gain: f32,
+    sample_count: u64,
+}
+
+impl Click {
+    fn new(accent: bool) -> Self {
+        let (frequency, gain) = if accent {
+            (ACCENT_CLICK_FREQUENCY, ACCENT_CLICK_GAIN)
+        } else {
+            (CLICK_FREQUENCY, CLICK_GAIN)
+        };
+        Self { frequency, gain, sample_count: 0 }
+    }
+
+    // The next sample of the click, or None once it has finished
+    fn next_sample(&mut self) -> Option<f32> {
+        let num_samples = (CLICK_MS * SAMPLES_PER_MS) as u64;
+        if self.sample_count >= num_samples {
+            return None;
+        }
+        let remaining = 1.0 - self.sample_count as f32 / num_samples as f32;
+        let phase = std::f32::consts::TAU * self.frequency * self.sample_count as f32 / SAMPLE_RATE;
+        self.sample_count += 1;
+        Some(self.gain * remaining * remaining * phase.sin())
+    }
+}
+
+/// A note sounding on a track and how many samples of it have been played. The note is built
+/// once and rewritten for each note the voice plays, so its vectors keep their capacity.
+struct Voice {
+    playback_note: PlaybackNote,
+    sample_count: u64,
+    active: bool,
+}
+
+impl Voice {
+    fn new() -> Self {
+        let mut playback_note = PlaybackNoteBuilder::default()
+            .note_type(NoteType::Oscillator)
+            .build().unwrap();
+        playback_note.note.waveforms.reserve(1);
+        playback_note.envelopes.reserve(1);
+        Self { playback_note, sample_count: 0, active: false }
+    }
+
+    // Set up the note for a new pitch starting now, with no allocation
+    fn start(&mut self, frequency: f32, duration_ms: f32, waveform: Waveform, velocity: u8,
+             envelope: Option<Envelope>) {
+        let playback_note = &mut self.playback_note;
+        playback_note.note.frequency = frequency;
+        playback_note.note.start_time_ms = 0.0;
+        playback_note.note.end_time_ms = duration_ms;
+        playback_note.note.waveforms.clear();
+        playback_note.note.waveforms.push(waveform);
+        playback_note.playback_start_time_ms = 0.0;
+        playback_note.playback_end_time_ms = duration_ms;
+        playback_note.playback_sample_start_time = 0;
+        playback_note.playback_sample_end_time = (duration_ms * SAMPLES_PER_MS).floor() as u64;
+        playback_note.envelopes.clear();
+        playback_note.envelopes.extend(envelope);
+        playback_note.velocity = velocity;
+        self.sample_count = 0;
+        self.active = true;
+    }
+}
+
+/// Fixed set of voices for one track, so notes on the track can overlap. A voice is freed when
+/// its note ends, and when every voice is busy a new note replaces the one that has played longest.
+/// The voices and their notes are allocated up front so triggering a note never allocates on the
+/// audio thread.
+pub struct VoicePool {
+    voices: Vec<Voice>,
+}
+
+impl VoicePool {
+    pub fn new(max_voices: usize) -> Self {
+        Self {
+            voices: (0..max_voices.max(1)).map(|_| Voice::new()).collect(),
+        }
+    }
+
+    /// Start a note lasting `duration_ms` at a frequency, returning the slot it was given. With no
+    /// envelope the note is shaped only by its fade in and out.
+    pub(crate) fn trigger(&mut self, frequency: f32, duration_ms: f32, waveform: Waveform,
+                          velocity: u8, envelope: Option<Envelope>) -> usize {
+        let slot = self.next_slot();
+        self.voices[slot].start(frequency, duration_ms, waveform, velocity, envelope);
+        slot
+    }
+
+    /// End the note in a slot after its fade out, rather than cutting it off
+    pub fn release(&mut self, slot: usize) {
+        if let Some(voice) = self.voices.get_mut(slot).filter(|voice| voice.active) {
+            let fade_samples = (voice.playback_note.fade_ms * SAMPLES_PER_MS) as u64;
+            let release_end = voice.sample_count + fade_samples;
+            let playback_note = &mut voice.playback_note;
+            playback_note.playback_sample_end_time =
+                playback_note.playback_sample_end_time.min(release_end);
+            // The fade out follows the note's end
+            let release_end_ms = release_end as f32 / SAMPLES_PER_MS;
+            if release_end_ms < playback_note.note_end_time_ms() {
+                playback_note.set_note_end_time_ms(release_end_ms);
+            }
+        }
+    }
+
+    /// Cut off every note at once, with no fade out
+    pub fn clear(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.active = false;
+        }
+    }
+
+    pub fn active_voices(&self) -> usize {
+        self.voices.iter().filter(|voice| voice.active).count()
+    }
+
+    // A free slot if there is one, otherwise the one that has played longest
+    fn next_slot(&self) -> usize {
+        match self.voices.iter().position(|voice| !voice.active) {
+            Some(free_slot) => free_slot,
+            None => self.oldest_voice(),
+        }
+    }
+
+    // Every slot is busy when this is called
+    fn oldest_voice(&self) -> usize {
+        self.voices.iter()
+            .enumerate()
+            .max_by_key(|(_, voice)| voice.sample_count)
+            .map_or(0, |(slot, _)| slot)
+    }
+
+    /// Mix the next sample of every active voice, freeing those whose notes have ended
+    pub(crate) fn next_frame(&mut self, oscillator_tables: &OscillatorTables) -> (f32, f32) {
+        let mut out_sample_l = 0.0;
+        let mut out_sample_r = 0.0;
+        for voice in self.voices.iter_mut().filter(|voice| voice.active) {
+            if voice.sample_count >= voice.playback_note.playback_sample_end_time {
+                voice.active = false;
+                continue;
+            }
+            let sample_position = (voice.sample_count as f32 % SAMPLE_RATE) / SAMPLE_RATE;
+            let (sample_l, sample_r) = get_sample::get_note_sample(
+                &mut voice.playback_note, oscillator_tables, sample_position, voice.sample_count);
+            voice.sample_count += 1;
+            out_sample_l += sample_l;
+            out_sample_r += sample_r;
+        }
+        (out_sample_l, out_sample_r)
+    }
+}
+
+/// The filter on the whole mix. It is fully wet, the engine blends it with the dry mix so the mix
+/// can change without rebuilding the filter.
+enum MasterFilter {
+    LowPass(LowPassFilter),
+    HighPass(HighPassFilter),
+    BandPass(BandPassFilter),
+    Notch(NotchFilter),
+}
+
+impl MasterFilter {
+    fn new(filter_type: FilterType, cutoff: f32, resonance: f32) -> Self {
+        match filter_type {
+            FilterType::LowPass => MasterFilter::LowPass(LowPassFilterBuilder::default()
+                .cutoff_frequency(cutoff)
+                .resonance(resonance)
+                .mix(1.0)
+                .build_with_coefficients().unwrap()),
+            FilterType::HighPass => MasterFilter::HighPass(HighPassFilterBuilder::default()
+                .cutoff_frequency(cutoff)
+                .resonance(resonance)
+                .mix(1.0)
+                .build_with_coefficients().unwrap()),
+            FilterType::BandPass => MasterFilter::BandPass(BandPassFilterBuilder::default()
+                .center_frequency(cutoff)
+                .resonance(resonance)
+                .mix(1.0)
+                .build_with_coefficients().unwrap()),
+            FilterType::Notch => MasterFilter::Notch(NotchFilterBuilder::default()
+                .center_frequency(cutoff)
+                .resonance(resonance)
+                .mix(1.0)
+                .build_with_coefficients().unwrap()),
+        }
+    }
+
+    // Keeps the filter's history, so sweeping the cutoff doesn't click
+    fn set_parameters(&mut self, cutoff: f32, resonance: f32) {
+        match self {
+            MasterFilter::LowPass(filter) => {
+                filter.cutoff_frequency = cutoff;
+                filter.resonance = resonance;
+                filter.update_coefficients();
+            }
+            MasterFilter::HighPass(filter) => {
+                filter.cutoff_frequency = cutoff;
+                filter.resonance = resonance;
+                filter.update_coefficients();
+            }
+            MasterFilter::BandPass(filter) => {
+                filter.center_frequency = cutoff;
+                filter.resonance = resonance;
+                filter.update_coefficients();
+            }
+            MasterFilter::Notch(filter) => {
+                filter.center_frequency = cutoff;
+                filter.resonance = resonance;
+                filter.update_coefficients();
+            }
+        }
+    }
+
+    fn apply_effect(&mut self, sample: f32) -> f32 {
+        match self {
+            MasterFilter::LowPass(filter) => filter.apply_effect(sample, 0.0),
+            MasterFilter::HighPass(filter) => filter.apply_effect(sample, 0.0),
+            MasterFilter::BandPass(filter) => filter.apply_effect(sample, 0.0),
+            MasterFilter::Notch(filter) => filter.apply_effect(sample, 0.0),
+        }
+    }
+}
+
+/// A chord step being arpeggiated on a track, one pitch at a time until its gate closes
+struct ArpPlayback {
+    velocity: u8,
+    waveform: Waveform,
+    note_samples: u64,
+    samples_left: u64,
+    samples_until_next_note: u64,
+}
+
+/// State owned by the audio callback between calls
+pub struct EngineState {
+    oscillator_tables: OscillatorTables,
+    limiter: Limiter,
+    stereo_width: StereoWidth,
+    dc_blockers: (DcBlocker, DcBlocker),
+    master_filter_type: FilterType,
+    // Cutoff and resonance the master filters were last set to
+    master_filter_parameters: (f32, f32),
+    master_filters: (MasterFilter, MasterFilter),
+    voice_pools: Vec<VoicePool>,
+    track_volumes: Vec<f32>,
+    track_pan_gains: Vec<(f32, f32)>,
+    track_audible: Vec<bool>,
+    samples_until_next_step: u64,
+    next_tick: u64,
+    // Samples played on the internal clock since playback started, the time on the tempo map
+    played_samples: u64,
+    was_playing: bool,
+    external_starts: u32,
+    panics: u32,
+    step_rng: StdRng,
+    // Notes from MIDI input play on their own voices, outside of the tracks' mix
+    live_voices: VoicePool,
+    live_note_velocities: [u8; NUM_MIDI_NOTES],
+    live_note_slots: [Option<usize>; NUM_MIDI_NOTES],
+    arpeggiators: Vec<Arpeggiator>,
+    arp_playbacks: Vec<Option<ArpPlayback>>,
+    record_producer: Option<HeapProducer<f32>>,
+    click: Option<Click>,
+    // Loudest sample of each track in the block being filled
+    track_block_peaks: [f32; NUM_TRACKS],
+    // Levels go to the UI here after each block, if it is listening
+    feedback_producer: Option<HeapProducer<AudioFeedback>>,
+    // The scope's copy of each block goes to the UI here, apart from the levels to keep
+    // AudioFeedback small
+    scope_producer: Option<HeapProducer<ScopeFrame>>,
+}
+
+impl EngineState {
+    pub fn new() -> Self {
+        Self {
+            oscillator_tables: OscillatorTables::new(),
+            limiter: default_limiter(),
+            stereo_width: default_stereo_width(),
+            dc_blockers: (default_dc_blocker(), default_dc_blocker()),
+            master_filter_type: FILTER_TYPES[0],
+            master_filter_parameters: (DEFAULT_MASTER_FILTER_CUTOFF,
+                                       DEFAULT_MASTER_FILTER_RESONANCE),
+            master_filters: (
+                MasterFilter::new(FILTER_TYPES[0], DEFAULT_MASTER_FILTER_CUTOFF,
+                                  DEFAULT_MASTER_FILTER_RESONANCE),
+                MasterFilter::new(FILTER_TYPES[0], DEFAULT_MASTER_FILTER_CUTOFF,
+                                  DEFAULT_MASTER_FILTER_RESONANCE),
+            ),
+            voice_pools: (0..NUM_TRACKS).map(|_| VoicePool::new(MAX_VOICES_PER_TRACK)).collect(),
+            track_volumes: vec![1.0; NUM_TRACKS],
+            track_pan_gains: vec![pan_gains(0.0); NUM_TRACKS],
+            track_audible: vec![true; NUM_TRACKS],
+            samples_until_next_step: 0,
+            next_tick: 0,
+            played_samples: 0,
+            was_playing: false,
+            external_starts: 0,
+            panics: 0,
+            step_rng: StdRng::seed_from_u64(STEP_PROBABILITY_SEED),
+            live_voices: VoicePool::new(MAX_LIVE_VOICES),
+            live_note_velocities: [0; NUM_MIDI_NOTES],
+            live_note_slots: [None; NUM_MIDI_NOTES],
+            arpeggiators: (0..NUM_TRACKS)
+                .map(|_| Arpeggiator::with_capacity(ArpMode::Up, ARP_RATE, MAX_CHORD_PITCHES))
+                .collect(),
+            arp_playbacks: (0..NUM_TRACKS).map(|_| None).collect(),
+            record_producer: None,
+            click: None,
+            track_block_peaks: [0.0; NUM_TRACKS],
+            feedback_producer: None,
+            scope_producer: None,
+        }
+    }
+
+    pub fn with_record_producer(record_producer: HeapProducer<f32>) -> Self {
+        Self {
+            record_producer: Some(record_producer),
+            ..Self::new()
+        }
+    }
+
+    // Follow the filter controls. A new type needs new filters, a new cutoff or resonance only new
+    // coefficients.
+    fn update_master_filters(&mut self, filter_type: FilterType, cutoff: f32, resonance: f32) {
+        if filter_type != self.master_filter_type {
+            self.master_filter_type = filter_type;
+            self.master_filters = (MasterFilter::new(filter_type, cutoff, resonance),
+                                   MasterFilter::new(filter_type, cutoff, resonance));
+        } else if (cutoff, resonance) != self.master_filter_parameters {
+            self.master_filters.0.set_parameters(cutoff, resonance);
+            self.master_filters.1.set_parameters(cutoff, resonance);
+        }
+        self.master_filter_parameters = (cutoff, resonance);
+    }
+
+    fn filter_master(&mut self, sample_l: f32, sample_r: f32, mix: f32) -> (f32, f32) {
+        let filtered_l = self.master_filters.0.apply_effect(sample_l);
+        let filtered_r = self.master_filters.1.apply_effect(sample_r);
+        (sample_l + mix * (filtered_l - sample_l), sample_r + mix * (filtered_r - sample_r))
+    }
+
+    // Start a note on each track that moves to an enabled step on this tick
+    fn trigger_tick(&mut self, state: &AudioState, tick: u64, step_duration_ms: f32,
+                    loop_region: Option<(usize, usize)>) {
+        // Never block the audio thread, if the UI holds the lock this tick is skipped. Track
+        // positions come from the tick, so the tracks stay in phase.
+        let Ok(tracks) = state.tracks.try_lock() else {
+            return;
+        };
+        for (track_idx, track_data) in tracks.get_track_data().iter().enumerate()
+            .take(NUM_TRACKS) {
+            self.track_volumes[track_idx] = track_data.volume;
+            let track_length = track_data.length.min(track_data.steps.len());
+            let Some(step) = track_step(tick, track_length, track_data.divisor, loop_region)
+            else {
+                continue;
+            };
+            state.track_current_steps[track_idx].store(step, Ordering::Relaxed);
+            if let Some(step_cell) = track_data.steps.get(step) {
+                if step_cell.enabled && step_fires(step_cell.probability, &mut self.step_rng) {
+                    // A track that moves every n ticks has steps n ticks long
+                    let track_step_ms = step_duration_ms * track_data.divisor.max(1) as f32;
+                    let note_duration_ms = gate_duration_ms(step_cell.gate_length, track_step_ms);
+                    let waveform = state.track_waveform(track_idx);
+                    match track_data.arp_mode {
+                        // An arpeggiated chord plays its pitches in turn over the gate
+                        Some(arp_mode) if !step_cell.extra_pitches.is_empty() => {
+                            self.start_arp(track_idx, arp_mode, step_cell, waveform,
+                                           note_duration_ms, 60000.0 / step_duration_ms);
+                        }
+                        // Each pitch of a chord gets its own voice and the voices sum
+                        _ => {
+                            let envelope = state.note_envelope(note_duration_ms);
+                            let (midi_notes, num_pitches) = step_cell.chord_midi_notes();
+                            for midi_note in midi_notes[..num_pitches].iter() {
+                                self.voice_pools[track_idx].trigger(
+                                    PITCH_TO_FREQ_HZ[*midi_note as usize] as f32,
+                                    note_duration_ms, waveform, step_cell.velocity,
+                                    Some(envelope));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Start a metronome click for this tick if the metronome is on
+    fn trigger_click(&mut self, state: &AudioState, tick: u64) {
+        if state.metronome_on.load(Ordering::Relaxed) {
+            let beats_per_measure = state.beats_per_measure.load(Ordering::Relaxed);
+            let beat_unit = state.beat_unit.load(Ordering::Relaxed);
+            self.click = Some(Click::new(is_accent_click(tick, beats_per_measure, beat_unit)));
+        }
+    }
+
+    fn next_click_sample(&mut self) -> f32 {
+        let sample = self.click.as_mut().and_then(Click::next_sample);
+        if sample.is_none() {
+            self.click = None;
+        }
+        sample.unwrap_or(0.0)
+    }
+
+    fn start_arp(&mut self, track_idx: usize, arp_mode: ArpMode, step_cell: &StepCell,
+                 waveform: Waveform, duration_ms: f32, tempo: f32) {
+        let arpeggiator = &mut self.arpeggiators[track_idx];
+        if arpeggiator.mode() != arp_mode {
+            arpeggiator.set_mode(arp_mode);
+        }
+        let (midi_notes, num_pitches) = step_cell.chord_midi_notes();
+        let mut pitches = [(WesternPitch::C, 0); MAX_CHORD_PITCHES];
+        for (pitch, midi_note) in pitches.iter_mut().zip(midi_notes[..num_pitches].iter()) {
+            *pitch = WesternPitch::from_midi_note(*midi_note);
+        }
+        arpeggiator.set_pitches(&pitches[..num_pitches]);
+        self.arp_playbacks[track_idx] = Some(ArpPlayback {
+            velocity: step_cell.velocity,
+            waveform,
+            note_samples: ((arpeggiator.note_duration_ms(tempo) * SAMPLES_PER_MS) as u64).max(1),
+            samples_left: (duration_ms * SAMPLES_PER_MS) as u64,
+            samples_until_next_note: 0,
+        });
+    }
+
+    // Silence everything sounding, sequenced or played live, after a panic
+    fn cut_off_voices(&mut self) {
+        for voice_pool in self.voice_pools.iter_mut() {
+            voice_pool.clear();
+        }
+        self.live_voices.clear();
+        self.live_note_velocities = [0; NUM_MIDI_NOTES];
+        self.live_note_slots = [None; NUM_MIDI_NOTES];
+        self.stop_arps();
+        self.click = None;
+    }
+
+    fn stop_arps(&mut self) {
+        for arp_slot in self.arp_playbacks.iter_mut() {
+            *arp_slot = None;
+        }
+    }
+
+    // Start the next note of each arpeggio that is due on this frame
+    fn advance_arps(&mut self, state: &AudioState) {
+        for (track_idx, arp_slot) in self.arp_playbacks.iter_mut().enumerate() {
+            let Some(arp) = arp_slot else {
+                continue;
+            };
+            if arp.samples_left == 0 {
+                *arp_slot = None;
+                continue;
+            }
+            if arp.samples_until_next_note == 0 {
+                if let Some((pitch, octave)) = self.arpeggiators[track_idx].next_pitch() {
+                    let frequency = PITCH_TO_FREQ_HZ[pitch.to_midi_note(octave) as usize] as f32;
+                    let note_ms = arp.note_samples.min(arp.samples_left) as f32 / SAMPLES_PER_MS;
+                    self.voice_pools[track_idx].trigger(frequency, note_ms, arp.waveform,
+                                                        arp.velocity,
+                                                        Some(state.note_envelope(note_ms)));
+                }
+                arp.samples_until_next_note = arp.note_samples;
+            }
+            arp.samples_until_next_note -= 1;
+            arp.samples_left -= 1;
+        }
+    }
+
+    // Start and release live notes for the keys pressed and released since the last callback
+    fn update_live_notes(&mut self, state: &AudioState) {
+        let waveform = state.live_waveform();
+        for note in 0..NUM_MIDI_NOTES {
+            let velocity = state.live_note_velocities[note].load(Ordering::Relaxed);
+            let previous_velocity = self.live_note_velocities[note];
+            if velocity == previous_velocity {
+                continue;
+            }
+            self.live_note_velocities[note] = velocity;
+
+            if let Some(slot) = self.live_note_slots[note].take() {
+                self.live_voices.release(slot);
+            }
+            if velocity > 0 {
+                // Held until its key is released, with no envelope beyond the note's fade in
+                // and out
+                let slot = self.live_voices.trigger(midi_note_to_frequency(note as u8),
+                                                    MAX_LIVE_NOTE_MS, waveform, velocity, None);
+                // The slot may have been taken from another held note, which must no longer
+                // release it
+                for live_note_slot in self.live_note_slots.iter_mut() {
+                    if *live_note_slot == Some(slot) {
+                        *live_note_slot = None;
+                    }
+                }
+                self.live_note_slots[note] = Some(slot);
+            }
+        }
+    }
+
+    fn next_frame(&mut self) -> (f32, f32) {
+        let (mut out_sample_l, mut out_sample_r) =
+            self.live_voices.next_frame(&self.oscillator_tables);
+        for ((((voice_pool, volume), (gain_l, gain_r)), audible), block_peak) in self.voice_pools
+            .iter_mut()
+            .zip(self.track_volumes.iter())
+            .zip(self.track_pan_gains.iter())
+            .zip(self.track_audible.iter())
+            .zip(self.track_block_peaks.iter_mut()) {
+            // Silent tracks keep playing so unmuting mid-note picks up where the note is
+            let (sample_l, sample_r) = voice_pool.next_frame(&self.oscillator_tables);
+            if !audible {
+                continue;
+            }
+            let (sample_l, sample_r) = (volume * gain_l * sample_l, volume * gain_r * sample_r);
+            *block_peak = block_peak.max(sample_l.abs()).max(sample_r.abs());
+            out_sample_l += sample_l;
+            out_sample_r += sample_r;
+        }
+        (out_sample_l, out_sample_r)
+    }
+}
+
+impl Default for EngineState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fill an interleaved output buffer with the sequencer's mix. The limiter is the final stage so
+/// the output stays within -1.0..1.0 however many tracks are sounding.
+pub fn audio_callback(output: &mut [f32], channels: usize, state: &AudioState,
+                      engine: &mut EngineState) {
+    let is_playing = state.is_playing.load(Ordering::Relaxed);
+    let clock_external = state.is_clock_external();
+    if clock_external {
+        // MIDI continue resumes where the clock stopped, only MIDI start goes back to the first
+        // step
+        let external_starts = state.external_starts.load(Ordering::Relaxed);
+        if external_starts != engine.external_starts {
+            engine.external_starts = external_starts;
+            engine.next_tick = 0;
+            engine.stop_arps();
+        }
+    } else if is_playing && !engine.was_playing {
+        // Start from the first step each time playback starts
+        engine.next_tick = 0;
+        engine.samples_until_next_step = 0;
+        engine.played_samples = 0;
+        engine.stop_arps();
+    }
+    engine.was_playing = is_playing;
+
+    engine.limiter.set_threshold(state.limiter_threshold.load(Ordering::Relaxed));
+    engine.limiter.set_release_ms(state.limiter_release_ms.load(Ordering::Relaxed));
+    engine.stereo_width.set_width(state.stereo_width.load(Ordering::Relaxed));
+    let is_recording = state.is_recording.load(Ordering::Relaxed);
+    let dc_blocker_enabled = state.dc_blocker_enabled.load(Ordering::Relaxed);
+    let master_volume = state.master_volume.load(Ordering::Relaxed);
+    engine.update_master_filters(state.master_filter_type(),
+                                 state.master_filter_cutoff.load(Ordering::Relaxed),
+                                 state.master_filter_resonance.load(Ordering::Relaxed));
+    let master_filter_mix = state.master_filter_mix.load(Ordering::Relaxed);
+    for (pan_gains_slot, pan) in engine.track_pan_gains.iter_mut().zip(state.track_pans.iter()) {
+        *pan_gains_slot = pan_gains(pan.load(Ordering::Relaxed));
+    }
+    let mutes: [bool; NUM_TRACKS] =
+        std::array::from_fn(|track_idx| state.track_mutes[track_idx].load(Ordering::Relaxed));
+    let solos: [bool; NUM_TRACKS] =
+        std::array::from_fn(|track_idx| state.track_solos[track_idx].load(Ordering::Relaxed));
+    for (audible_slot, audible) in engine.track_audible.iter_mut()
+        .zip(audible_tracks(&mutes, &solos)) {
+        *audible_slot = audible;
+    }
+    let panics = state.panics.load(Ordering::Relaxed);
+    if panics != engine.panics {
+        engine.panics = panics;
+        engine.cut_off_voices();
+    }
+    engine.update_live_notes(state);
+    engine.track_block_peaks = [0.0; NUM_TRACKS];
+
+    // One step per beat, matching the transport display. The tempo map is read once a buffer,
+    // and if the UI holds its lock the tempo control is used for this buffer.
+    let tempo = match state.tempo_map.try_lock() {
+        Ok(tempo_map) => tempo_map.as_ref()
+            .map(|tempo_map| tempo_map.bpm_at(engine.played_samples as f32 / SAMPLES_PER_MS)),
+        Err(_) => None,
+    }.unwrap_or_else(|| state.tempo.load(Ordering::Relaxed));
+    let step_duration_ms = 60000.0 / tempo.max(1.0);
+    let samples_per_step = (step_duration_ms * SAMPLES_PER_MS) as u64;
+    let steps_per_track = state.steps_per_track.load(Ordering::Relaxed)
+        .clamp(1, MAX_STEPS_PER_TRACK);
+    let swing = state.swing.load(Ordering::Relaxed);
+    let loop_region = state.loop_region();
+
+    if is_playing && clock_external {
+        // External steps start at the beginning of the buffer they arrive in, so they are as
+        // late as one buffer. Gates are still timed from tempo.
+        let external_ticks = state.external_ticks.load(Ordering::Relaxed);
+        while engine.next_tick < external_ticks {
+            let tick = engine.next_tick;
+            let position = loop_position(tick, steps_per_track, loop_region);
+            engine.trigger_tick(state, tick, step_duration_ms, loop_region);
+            engine.trigger_click(state, tick);
+            state.current_step.store((position % steps_per_track as u64) as usize,
+                                     Ordering::Relaxed);
+            state.played_steps.store(tick, Ordering::Relaxed);
+            engine.next_tick = tick + 1;
+        }
+    }
+
+    for frame in output.chunks_mut(channels) {
+        if is_playing && !clock_external {
+            if engine.samples_until_next_step == 0 {
+                let tick = engine.next_tick;
+                let position = loop_position(tick, steps_per_track, loop_region);
+                engine.trigger_tick(state, tick, step_duration_ms, loop_region);
+                engine.trigger_click(state, tick);
+                state.current_step.store((position % steps_per_track as u64) as usize,
+                                         Ordering::Relaxed);
+                state.played_steps.store(tick, Ordering::Relaxed);
+                engine.next_tick = tick + 1;
+                engine.samples_until_next_step =
+                    swung_step_samples((tick % 2) as usize, samples_per_step, swing).max(1);
+            }
+            engine.samples_until_next_step -= 1;
+            engine.played_samples += 1;
+        }
+        if is_playing {
+            engine.advance_arps(state);
+        }
+
+        let (sample_l, sample_r) = engine.next_frame();
+        let click = master_volume * engine.next_click_sample();
+        let (mut sample_l, mut sample_r) = (master_volume * sample_l, master_volume * sample_r);
+        if master_filter_mix > 0.0 {
+            (sample_l, sample_r) = engine.filter_master(sample_l, sample_r, master_filter_mix);
+        }
+        (sample_l, sample_r) = engine.stereo_width.apply_effect_stereo(sample_l, sample_r, 0.0);
+        if dc_blocker_enabled {
+            sample_l = engine.dc_blockers.0.apply_effect(sample_l, 0.0);
+            sample_r = engine.dc_blockers.1.apply_effect(sample_r, 0.0);
+        }
+        // The click follows the master volume and is limited along with the mix, so together
+        // they never clip. It's only for whoever is playing along, so the recording is the mix
+        // alone, at the gain the limiter gave both.
+        let (limited_l, limited_r) =
+            engine.limiter.apply_effect_stereo(sample_l + click, sample_r + click);
+        if is_recording {
+            if let Some(record_producer) = engine.record_producer.as_mut() {
+                // If the writer falls behind the samples that don't fit are dropped
+                let gain = engine.limiter.gain();
+                record_producer.push_slice(&[gain * sample_l, gain * sample_r]);
+            }
+        }
+        let (sample_l, sample_r) = (limited_l, limited_r);
+        if channels == 1 {
+            frame[0] = 0.5 * (sample_l + sample_r);
+        } else {
+            frame[0] = sample_l;
+            frame[1] = sample_r;
+            for sample in frame.iter_mut().skip(2) {
+                *sample = 0.0;
+            }
+        }
+    }
+
+    if let Some(feedback_producer) = engine.feedback_producer.as_mut() {
+        // If the UI falls behind the newest levels are dropped, it catches up on the next block
+        let (master_peak, master_rms) = peak_and_rms(output);
+        let _ = feedback_producer.push(AudioFeedback::Levels {
+            master_peak,
+            master_rms,
+            track_peaks: engine.track_block_peaks,
+        });
+    }
+    if let Some(scope_producer) = engine.scope_producer.as_mut() {
+        let _ = scope_producer.push(ScopeFrame::from_block(output, channels));
+    }
+}
+
+/// Write everything currently in the recording buffer to the WAV file, returning the number of
+/// samples written
+pub fn drain_to_writer<W: Write + Seek>(record_consumer: &mut HeapConsumer<f32>,
+                                        writer: &mut hound::WavWriter<W>)
+                                        -> Result<usize, TuiError> {
+    let mut num_samples = 0;
+    while let Some(sample) = record_consumer.pop() {
+        writer.write_sample(sample)
+            .map_err(|e| TuiError::Audio(format!("Failed to write recording: {}", e)))?;
+        num_samples += 1;
+    }
+    Ok(num_samples)
+}
+
+// Drains the recording buffer to a WAV file on its own thread until stopped. The thread hands the
+// consumer back when it finishes so the next recording can reuse the buffer.
+struct Recorder {
+    stop: Arc<AtomicBool>,
+    writer_thread: thread::JoinHandle<(HeapConsumer<f32>, Result<(), TuiError>)>,
+}
+
+impl Recorder {
+    fn start(mut writer: hound::WavWriter<BufWriter<File>>,
+             mut record_consumer: HeapConsumer<f32>) -> Self {
+        // Discard anything left from a previous recording
+        record_consumer.clear();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let writer_thread = thread::spawn(move || {
+            let result = (|| {
+                loop {
+                    let stopping = thread_stop.load(Ordering::Relaxed);
+                    drain_to_writer(&mut record_consumer, &mut writer)?;
+                    if stopping {
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(RECORD_DRAIN_INTERVAL_MS));
+                }
+                writer.finalize()
+                    .map_err(|e| TuiError::Audio(format!("Failed to finalize recording: {}", e)))
+            })();
+            (record_consumer, result)
+        });
+
+        Self { stop, writer_thread }
+    }
+
+    fn stop(self) -> (HeapConsumer<f32>, Result<(), TuiError>) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.writer_thread.join().expect("Recording writer thread panicked")
+    }
+}
+
+/// Names of the output devices of the default host, for `AudioEngine::new_with_device`
+pub fn list_output_devices() -> Vec<String> {
+    let host = cpal::default_host();
+    match host.output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The device's own output config, asking for `buffer_size` frames a callback if set. The request
+/// is kept to the range the device supports, and dropped if the device doesn't say.
+pub fn stream_config(supported: &cpal::SupportedStreamConfig, buffer_size: Option<u32>)
+                     -> cpal::StreamConfig {
+    let mut config = supported.config();
+    if let Some(frames) = buffer_size {
+        config.buffer_size = match supported.buffer_size() {
+            cpal::SupportedBufferSize::Range { min, max } => {
+                cpal::BufferSize::Fixed(frames.clamp(*min, *max))
+            }
+            cpal::SupportedBufferSize::Unknown => cpal::BufferSize::Default,
+        };
+    }
+    config
+}
+
+/// Real-time output of the sequencer to an audio device
+pub struct AudioEngine {
+    state: Arc<AudioState>,
+    _stream: cpal::Stream,
+    // Held here while not recording, owned by the recorder's writer thread while recording
+    record_consumer: Option<HeapConsumer<f32>>,
+    recorder: Option<Recorder>,
+    feedback_consumer: HeapConsumer<AudioFeedback>,
+    scope_consumer: HeapConsumer<ScopeFrame>,
+    // Errors from the stream's error callback, which runs apart from the audio callback
+    error_consumer: HeapConsumer<StreamError>,
+    device_lost: Arc<AtomicBool>,
+    // Updates waiting for `apply_parameter_updates`, so a burst of them is applied once
+    parameter_queue: ParameterQueue,
+}
+
+impl AudioEngine {
+    /// Output to the default device, `buffer_size` frames at a time if set, as `stream_config`
+    /// allows
+    pub fn new(state: Arc<AudioState>, buffer_size: Option<u32>) -> Result<Self, TuiError> {
+        let host = cpal::default_host();
+        let device = host.default_output_device()
+            .ok_or_else(|| TuiError::Audio("No output device available".to_string()))?;
+        Self::new_for_device(state, device, buffer_size)
+    }
+
+    /// Output to the device with this name, as returned by `list_output_devices`
+    pub fn new_with_device(state: Arc<AudioState>, device_name: &str, buffer_size: Option<u32>)
+                           -> Result<Self, TuiError> {
+        let host = cpal::default_host();
+        let devices = host.output_devices()
+            .map_err(|e| TuiError::Audio(format!("Failed to list output devices: {}", e)))?;
+        let device = devices
+            .into_iter()
+            .find(|device| device.name().map(|name| name == device_name).unwrap_or(false))
+            .ok_or_else(|| TuiError::Audio(format!("No output device named {}", device_name)))?;
+        Self::new_for_device(state, device, buffer_size)
+    }
+
+    fn new_for_device(state: Arc<AudioState>, device: cpal::Device, buffer_size: Option<u32>)
+                      -> Result<Self, TuiError> {
+        let supported = device.default_output_config()
+            .map_err(|e| TuiError::Audio(format!("No output config: {}", e)))?;
+        let config = stream_config(&supported, buffer_size);
+        let channels = config.channels as usize;
+
+        let (record_producer, record_consumer) = HeapRb::<f32>::new(RECORD_BUFFER_SIZE).split();
+        let callback_state = Arc::clone(&state);
+        let (feedback_producer, feedback_consumer) =
+            HeapRb::<AudioFeedback>::new(FEEDBACK_BUFFER_SIZE).split();
+        let (scope_producer, scope_consumer) = HeapRb::<ScopeFrame>::new(SCOPE_BUFFER_SIZE).split();
+        let mut engine = EngineState {
+            feedback_producer: Some(feedback_producer),
+            scope_producer: Some(scope_producer),
+            ..EngineState::with_record_producer(record_producer)
+        };
+        let (error_producer, error_consumer) =
+            HeapRb::<StreamError>::new(STREAM_ERROR_BUFFER_SIZE).split();
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let stream = device.build_output_stream(
+            &config,
+            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                audio_callback(data, channels, &callback_state, &mut engine);
+            },
+            stream_error_handler(error_producer, Arc::clone(&device_lost)),
+            None,
+        ).map_err(|e| TuiError::Audio(format!("Failed to build output stream: {}", e)))?;
+        stream.play()
+            .map_err(|e| TuiError::Audio(format!("Failed to start output stream: {}", e)))?;
+
+        Ok(Self {
+            state,
+            _stream: stream,
+            record_consumer: Some(record_consumer),
+            recorder: None,
+            feedback_consumer,
+            scope_consumer,
+            error_consumer,
+            device_lost,
+            parameter_queue: ParameterQueue::new(),
+        })
+    }
+
+    pub fn state(&self) -> &Arc<AudioState> {
+        &self.state
+    }
+
+    /// Start or stop recording now, and queue every other update for `apply_parameter_updates`
+    pub fn handle_parameter_update(&mut self, update: &ParameterUpdate) -> Result<(), TuiError> {
+        match update {
+            ParameterUpdate::RecordStart { path } => self.start_recording(path),
+            ParameterUpdate::RecordStop => self.stop_recording(),
+            _ => self.parameter_queue.push(update.clone()),
+        }
+    }
+
+    /// Apply the latest of each value queued since the last call, and every queued event
+    pub fn apply_parameter_updates(&mut self) {
+        apply_queued_updates(&self.state, &mut self.parameter_queue);
+    }
+
+    /// Everything the audio callback has reported since the last call, the newest scope frame
+    /// and then any stream errors
+    pub fn receive_audio_feedback(&mut self) -> Vec<AudioFeedback> {
+        let mut feedback = Vec::new();
+        while let Some(fb) = self.feedback_consumer.pop() {
+            feedback.push(fb);
+        }
+        if let Some(frame) = self.scope_consumer.pop_iter().last() {
+            feedback.push(AudioFeedback::Waveform(frame.samples().to_vec()));
+        }
+        feedback.extend(self.error_consumer.pop_iter().map(AudioFeedback::StreamError));
+        feedback
+    }
+
+    /// Whether the output device has gone, so the stream plays nothing until it is rebuilt
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Relaxed)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    pub fn start_recording(&mut self, path: &str) -> Result<(), TuiError> {
+        if self.recorder.is_some() {
+            return Err(TuiError::Audio("Already recording".to_string()));
+        }
+        let writer = hound::WavWriter::create(path, RECORD_WAV_FORMAT.wav_spec())
+            .map_err(|e| TuiError::Audio(format!("Failed to create {}: {}", path, e)))?;
+        let record_consumer = self.record_consumer.take()
+            .ok_or_else(|| TuiError::Audio("Recording buffer unavailable".to_string()))?;
+        self.recorder = Some(Recorder::start(writer, record_consumer));
+        self.state.is_recording.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> Result<(), TuiError> {
+        self.state.is_recording.store(false, Ordering::Relaxed);
+        match self.recorder.take() {
+            Some(recorder) => {
+                let (record_consumer, result) = recorder.stop();
+                self.record_consumer = Some(record_consumer);
+                result
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+fn apply_queued_updates(state: &AudioState, parameter_queue: &mut ParameterQueue) {
+    for update in parameter_queue.drain() {
+        state.handle_parameter_update(&update);
+    }
+}
+
+impl Drop for AudioEngine {
+    fn drop(&mut self) {
+        // Finalize the file so a recording still running at exit is readable, and keep the
+        // controls' latest values in the state a rebuilt engine starts from
+        let _ = self.stop_recording();
+        self.apply_parameter_updates();
+    }
+}
+
+#[cfg(test)]
+mod test_audio_engine {
+    use std::io::Cursor;
+    use std::sync::atomic::Ordering;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use ringbuf::HeapRb;
+    use crate::audio_gen::audio_gen::WavFormat;
+    use crate::audio_gen::oscillator::{OscillatorTables, Waveform};
+    use crate::composition::tempo_map::{TempoCurve, TempoMap};
+    use crate::tui::audio_bridge::{AudioFeedback, ParameterQueue, ParameterUpdate};
+    use crate::tui::ui::widgets::{FilterType, StepCell};
+    use crate::common::constants::SAMPLES_PER_MS;
+    use crate::tui::audio_engine::{apply_queued_updates, audio_callback, drain_to_writer,
+                                   list_output_devices, audible_tracks, gate_duration_ms, is_accent_click,
+                                   loop_position, pan_gains, peak_and_rms, scope_samples,
+                                   step_fires, stream_config,
+                                   swung_step_samples, track_step, AudioState, ClockSource,
+                                   EngineState, VoicePool, MAX_FILTER_CUTOFF,
+                                   MAX_STEREO_WIDTH, MAX_SWING, SCOPE_POINTS};
+    use crate::common::float_utils::assert_float_eq;
+    use crate::note::constants::PITCH_TO_FREQ_HZ;
+    use crate::note::scales::WesternPitch;
+    use crate::sequence::arpeggiator::ArpMode;
+    use crate::tui::ui::widgets::SequencerGrid;
+
+    const NUM_FRAMES: usize = 4410;
+
+    fn tracks_on_first_step(state: &AudioState, num_tracks: usize) {
+        let mut tracks = state.tracks.lock().unwrap();
+        for track_data in tracks.get_track_data_mut().iter_mut().take(num_tracks) {
+            track_data.volume = 1.0;
+            track_data.steps[0].enabled = true;
+        }
+    }
+
+    fn peak(output: &[f32]) -> f32 {
+        output.iter().fold(0.0, |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn test_limiter_bounds_summed_tracks() {
+        // Every track plays the same note in phase, so the plain sum of eight tracks is eight
+        // times the peak of one
+        let single_state = AudioState::new();
+        tracks_on_first_step(&single_state, 1);
+        single_state.is_playing.store(true, Ordering::Relaxed);
+        let mut single = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut single, 2, &single_state, &mut EngineState::new());
+        assert!(8.0 * peak(&single) > 1.0);
+
+        let state = AudioState::new();
+        tracks_on_first_step(&state, 8);
+        state.is_playing.store(true, Ordering::Relaxed);
+        let mut limited = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut limited, 2, &state, &mut EngineState::new());
+        assert!(peak(&limited) > peak(&single));
+        assert!(peak(&limited) <= 1.0);
+    }
+
+    #[test]
+    fn test_silent_when_stopped() {
+        let state = AudioState::new();
+        tracks_on_first_step(&state, 8);
+
+        let mut output = vec![1.0; NUM_FRAMES * 2];
+        audio_callback(&mut output, 2, &state, &mut EngineState::new());
+        assert!(output.iter().all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn test_drain_records_callback_output() {
+        let (record_producer, mut record_consumer) = HeapRb::<f32>::new(NUM_FRAMES * 4).split();
+        let mut engine = EngineState::with_record_producer(record_producer);
+        let state = AudioState::new();
+        tracks_on_first_step(&state, 1);
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        // Nothing is captured until recording starts
+        let mut output = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert!(record_consumer.is_empty());
+
+        state.is_recording.store(true, Ordering::Relaxed);
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert_eq!(record_consumer.len(), NUM_FRAMES * 2);
+
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = hound::WavWriter::new(&mut cursor, WavFormat::Float32.wav_spec()).unwrap();
+        assert_eq!(drain_to_writer(&mut record_consumer, &mut writer).unwrap(), NUM_FRAMES * 2);
+        assert!(record_consumer.is_empty());
+        // A second drain with nothing buffered writes nothing
+        assert_eq!(drain_to_writer(&mut record_consumer, &mut writer).unwrap(), 0);
+        writer.finalize().unwrap();
+
+        cursor.set_position(0);
+        let mut reader = hound::WavReader::new(cursor).unwrap();
+        let recorded: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+        assert_eq!(recorded, output);
+    }
+
+    // Needs an audio device, run with --ignored on a machine with sound
+    #[test]
+    #[ignore]
+    fn test_list_output_devices_includes_default() {
+        use cpal::traits::{DeviceTrait, HostTrait};
+
+        let default_name = cpal::default_host().default_output_device().unwrap().name().unwrap();
+        assert!(list_output_devices().contains(&default_name));
+    }
+
+    #[test]
+    fn test_stream_config_asks_for_buffer_size() {
+        let supported = |buffer_size| cpal::SupportedStreamConfig::new(
+            2, cpal::SampleRate(44100), buffer_size, cpal::SampleFormat::F32);
+        let range = supported(cpal::SupportedBufferSize::Range { min: 64, max: 4096 });
+
+        let config = stream_config(&range, Some(256));
+        assert_eq!(config.buffer_size, cpal::BufferSize::Fixed(256));
+        assert_eq!((config.channels, config.sample_rate), (2, cpal::SampleRate(44100)));
+        // Kept to what the device supports
+        assert_eq!(stream_config(&range, Some(16)).buffer_size, cpal::BufferSize::Fixed(64));
+        assert_eq!(stream_config(&range, Some(8192)).buffer_size, cpal::BufferSize::Fixed(4096));
+        // The device chooses when no size is set, or it doesn't say what it supports
+        assert_eq!(stream_config(&range, None).buffer_size, cpal::BufferSize::Default);
+        assert_eq!(stream_config(&supported(cpal::SupportedBufferSize::Unknown), Some(256))
+                       .buffer_size,
+                   cpal::BufferSize::Default);
+    }
+
+    fn trigger_for_ms(voice_pool: &mut VoicePool, duration_ms: f32) -> usize {
+        voice_pool.trigger(440.0, duration_ms, Waveform::Sine, 127, None)
+    }
+
+    #[test]
+    fn test_voice_pool_overlaps_notes() {
+        let osc_tables = OscillatorTables::new();
+        let mut voice_pool = VoicePool::new(4);
+        assert_eq!(trigger_for_ms(&mut voice_pool, 100.0), 0);
+        voice_pool.next_frame(&osc_tables);
+        // the first note is still sounding so the second gets its own voice
+        assert_eq!(trigger_for_ms(&mut voice_pool, 10.0), 1);
+        assert_eq!(voice_pool.active_voices(), 2);
+    }
+
+    #[test]
+    fn test_voice_pool_frees_ended_notes() {
+        let osc_tables = OscillatorTables::new();
+        let mut voice_pool = VoicePool::new(4);
+        trigger_for_ms(&mut voice_pool, 100.0);
+        trigger_for_ms(&mut voice_pool, 10.0);
+        for _ in 0..=(10.0 * SAMPLES_PER_MS) as usize {
+            voice_pool.next_frame(&osc_tables);
+        }
+        assert_eq!(voice_pool.active_voices(), 1);
+        // the freed voice is reused
+        assert_eq!(trigger_for_ms(&mut voice_pool, 10.0), 1);
+    }
+
+    #[test]
+    fn test_voice_pool_steals_oldest_voice() {
+        let osc_tables = OscillatorTables::new();
+        let mut voice_pool = VoicePool::new(3);
+        for _ in 0..3 {
+            trigger_for_ms(&mut voice_pool, 1000.0);
+            voice_pool.next_frame(&osc_tables);
+        }
+        assert_eq!(voice_pool.active_voices(), 3);
+        assert_eq!(trigger_for_ms(&mut voice_pool, 1000.0), 0);
+        voice_pool.next_frame(&osc_tables);
+        assert_eq!(trigger_for_ms(&mut voice_pool, 1000.0), 1);
+        assert_eq!(voice_pool.active_voices(), 3);
+    }
+
+    #[test]
+    fn test_voice_pool_reuses_its_notes() {
+        let mut voice_pool = VoicePool::new(1);
+        let envelope = AudioState::new().note_envelope(100.0);
+        voice_pool.trigger(440.0, 100.0, Waveform::Sine, 127, Some(envelope));
+        let playback_note = &voice_pool.voices[0].playback_note;
+        let (waveforms, envelopes) =
+            (playback_note.note.waveforms.as_ptr(), playback_note.envelopes.as_ptr());
+
+        // a new note on the voice is written over the old one, in the same buffers
+        voice_pool.trigger(220.0, 50.0, Waveform::Square, 64, Some(envelope));
+        let playback_note = &voice_pool.voices[0].playback_note;
+        assert_eq!(playback_note.note.waveforms.as_ptr(), waveforms);
+        assert_eq!(playback_note.envelopes.as_ptr(), envelopes);
+        assert_eq!(playback_note.note.frequency, 220.0);
+        assert_eq!(playback_note.note.waveforms, vec![Waveform::Square]);
+        assert_eq!(playback_note.velocity, 64);
+        assert_eq!(playback_note.playback_sample_end_time, 2205);
+    }
+
+    #[test]
+    fn test_pan_gains() {
+        let (gain_l, gain_r) = pan_gains(-1.0);
+        assert_float_eq(gain_l, 1.0);
+        assert!(gain_r.abs() < 1e-6);
+
+        let (gain_l, gain_r) = pan_gains(1.0);
+        assert!(gain_l.abs() < 1e-6);
+        assert_float_eq(gain_r, 1.0);
+
+        // equal power at the center
+        let (gain_l, gain_r) = pan_gains(0.0);
+        assert_float_eq(gain_l, gain_r);
+        assert_float_eq(gain_l * gain_l + gain_r * gain_r, 1.0);
+    }
+
+    #[test]
+    fn test_hard_left_track_is_silent_on_right() {
+        let state = AudioState::new();
+        tracks_on_first_step(&state, 1);
+        state.track_pans[0].store(-1.0, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let mut output = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut output, 2, &state, &mut EngineState::new());
+        let left: Vec<f32> = output.iter().step_by(2).cloned().collect();
+        let right: Vec<f32> = output.iter().skip(1).step_by(2).cloned().collect();
+        assert!(peak(&left) > 0.5);
+        assert!(peak(&right) < 1e-6);
+    }
+
+    #[test]
+    fn test_zero_stereo_width_sums_mix_to_mono() {
+        let state = AudioState::new();
+        tracks_on_first_step(&state, 1);
+        state.track_pans[0].store(-1.0, Ordering::Relaxed);
+        state.handle_parameter_update(&ParameterUpdate::StereoWidth(0.0));
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let mut output = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut output, 2, &state, &mut EngineState::new());
+        // The hard left track sounds in both channels, at half its level
+        assert!(output.chunks(2).all(|frame| frame[0] == frame[1]));
+        assert!(peak(&output) > 0.25);
+    }
+
+    #[test]
+    fn test_stereo_width_update_is_clamped() {
+        let state = AudioState::new();
+        state.handle_parameter_update(&ParameterUpdate::StereoWidth(1.5));
+        assert_float_eq(state.stereo_width.load(Ordering::Relaxed), 1.5);
+        state.handle_parameter_update(&ParameterUpdate::StereoWidth(5.0));
+        assert_float_eq(state.stereo_width.load(Ordering::Relaxed), MAX_STEREO_WIDTH);
+        state.handle_parameter_update(&ParameterUpdate::StereoWidth(-1.0));
+        assert_float_eq(state.stereo_width.load(Ordering::Relaxed), 0.0);
+    }
+
+    #[test]
+    fn test_audible_tracks() {
+        let none = [false; 4];
+        assert_eq!(audible_tracks(&none, &none), [true; 4]);
+
+        let mutes = [false, true, false, false];
+        assert_eq!(audible_tracks(&mutes, &none), [true, false, true, true]);
+
+        // any solo silences every track that isn't soloed
+        let solos = [false, false, true, false];
+        assert_eq!(audible_tracks(&none, &solos), [false, false, true, false]);
+
+        // mute wins over solo
+        let mutes = [false, false, true, false];
+        let solos = [true, false, true, false];
+        assert_eq!(audible_tracks(&mutes, &solos), [true, false, false, false]);
+    }
+
+    #[test]
+    fn test_muted_track_is_silent() {
+        let state = AudioState::new();
+        tracks_on_first_step(&state, 1);
+        state.track_mutes[0].store(true, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let mut output = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut output, 2, &state, &mut EngineState::new());
+        assert_eq!(peak(&output), 0.0);
+    }
+
+    #[test]
+    fn test_track_waveform_update() {
+        let state = AudioState::new();
+        state.handle_parameter_update(&ParameterUpdate::TrackWaveform {
+            track: 3,
+            waveform: Waveform::Saw,
+        });
+        for track_idx in 0..8 {
+            let expected = if track_idx == 3 { Waveform::Saw } else { Waveform::Sine };
+            assert_eq!(state.track_waveform(track_idx), expected);
+        }
+
+        // out of range tracks are ignored
+        state.handle_parameter_update(&ParameterUpdate::TrackWaveform {
+            track: 8,
+            waveform: Waveform::Square,
+        });
+        assert_eq!(state.track_waveform(7), Waveform::Sine);
+    }
+
+    #[test]
+    fn test_24_step_sequence_wraps_after_24_steps() {
+        let state = AudioState::new();
+        state.steps_per_track.store(24, Ordering::Relaxed);
+        // 10ms steps, so each 441 frame callback triggers exactly one step
+        state.tempo.store(6000.0, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; 441 * 2];
+        let mut steps = Vec::new();
+        for _ in 0..25 {
+            audio_callback(&mut output, 2, &state, &mut engine);
+            steps.push(state.current_step.load(Ordering::Relaxed));
+        }
+        let mut expected: Vec<usize> = (0..24).collect();
+        expected.push(0);
+        assert_eq!(steps, expected);
+        // The count of steps played carries on past the wrap
+        assert_eq!(state.played_steps.load(Ordering::Relaxed), 24);
+    }
+
+    #[test]
+    fn test_tempo_map_sets_step_length() {
+        let state = AudioState::new();
+        // 10ms steps for the first 50ms, then 20ms steps
+        state.set_tempo_map(Some(TempoMap::from_points(&[(0.0, 6000.0), (50.0, 3000.0)],
+                                                       TempoCurve::Step).unwrap()));
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; 441 * 2];
+        let mut steps = Vec::new();
+        for _ in 0..10 {
+            audio_callback(&mut output, 2, &state, &mut engine);
+            steps.push(state.current_step.load(Ordering::Relaxed));
+        }
+        assert_eq!(steps, vec![0, 1, 2, 3, 4, 5, 5, 6, 6, 7]);
+    }
+
+    #[test]
+    fn test_tempo_ramp_update() {
+        let state = AudioState::new();
+        state.handle_parameter_update(&ParameterUpdate::TempoRamp(Some((140.0, 1000.0))));
+        {
+            let tempo_map = state.tempo_map.lock().unwrap();
+            let tempo_map = tempo_map.as_ref().unwrap();
+            assert_float_eq(tempo_map.bpm_at(0.0), 120.0);
+            assert_float_eq(tempo_map.bpm_at(500.0), 130.0);
+            assert_float_eq(tempo_map.bpm_at(2000.0), 140.0);
+        }
+
+        state.handle_parameter_update(&ParameterUpdate::TempoRamp(None));
+        assert!(state.tempo_map.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_swing_delays_odd_steps() {
+        let samples_per_step = 1000;
+        let swing = 0.25;
+        let onsets: Vec<u64> = (0..8)
+            .scan(0, |onset, step| {
+                let step_onset = *onset;
+                *onset += swung_step_samples(step, samples_per_step, swing);
+                Some(step_onset)
+            })
+            .collect();
+
+        for (step, onset) in onsets.iter().enumerate() {
+            let straight_onset = step as u64 * samples_per_step;
+            if step % 2 == 0 {
+                assert_eq!(*onset, straight_onset);
+            } else {
+                assert_eq!(*onset, straight_onset + 250);
+            }
+        }
+    }
+
+    #[test]
+    fn test_swing_keeps_tempo() {
+        for swing in [0.0, 0.1, 0.5, MAX_SWING, 1.0] {
+            assert_eq!(swung_step_samples(0, 1000, swing) + swung_step_samples(1, 1000, swing),
+                       2000);
+        }
+        // swing beyond the maximum is limited to it
+        assert_eq!(swung_step_samples(1, 1000, 1.0), swung_step_samples(1, 1000, MAX_SWING));
+    }
+
+    #[test]
+    fn test_swing_update_is_clamped() {
+        let state = AudioState::new();
+        state.handle_parameter_update(&ParameterUpdate::Swing(0.9));
+        assert_float_eq(state.swing.load(Ordering::Relaxed), MAX_SWING);
+        state.handle_parameter_update(&ParameterUpdate::Swing(0.3));
+        assert_float_eq(state.swing.load(Ordering::Relaxed), 0.3);
+    }
+
+    #[test]
+    fn test_step_fires() {
+        let mut rng = StdRng::seed_from_u64(7);
+        assert!((0..1000).all(|_| step_fires(100, &mut rng)));
+        assert!((0..1000).all(|_| !step_fires(0, &mut rng)));
+
+        let num_fired = (0..10000).filter(|_| step_fires(25, &mut rng)).count();
+        assert!((2300..2700).contains(&num_fired));
+    }
+
+    #[test]
+    fn test_step_fires_is_repeatable_for_a_seed() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let first: Vec<bool> = (0..64).map(|_| step_fires(50, &mut rng)).collect();
+        let mut rng = StdRng::seed_from_u64(7);
+        let second: Vec<bool> = (0..64).map(|_| step_fires(50, &mut rng)).collect();
+        assert_eq!(first, second);
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+
+    #[test]
+    fn test_gate_length_to_samples() {
+        let mut step_cell = StepCell::default();
+        // 500ms steps at 120 BPM
+        let gate_samples = |step_cell: &StepCell| {
+            step_cell.to_playback_note(0.0, gate_duration_ms(step_cell.gate_length, 500.0),
+                                       Waveform::Sine)
+                .playback_sample_end_time
+        };
+
+        assert_eq!(gate_samples(&step_cell), 22050);
+        step_cell.gate_length = 0.5;
+        assert_eq!(gate_samples(&step_cell), 11025);
+        step_cell.gate_length = 2.0;
+        assert_eq!(gate_samples(&step_cell), 44100);
+
+        // gates outside 0.1..=2.0 are clamped
+        step_cell.gate_length = 0.0;
+        assert_eq!(gate_samples(&step_cell), 2205);
+        step_cell.gate_length = 4.0;
+        assert_eq!(gate_samples(&step_cell), 44100);
+    }
+
+    #[test]
+    fn test_chord_step_sums_a_voice_per_pitch() {
+        let state = AudioState::new();
+        {
+            let mut tracks = state.tracks.lock().unwrap();
+            let step = &mut tracks.get_track_data_mut()[0].steps[0];
+            step.enabled = true;
+            step.octave = 4;
+            step.extra_pitches = vec![WesternPitch::E, WesternPitch::G];
+        }
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; 64 * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        let mut frequencies: Vec<f32> = engine.voice_pools[0].voices.iter()
+            .filter(|voice| voice.active)
+            .map(|voice| voice.playback_note.note.frequency)
+            .collect();
+        frequencies.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(frequencies.len(), 3);
+        // C4 E4 G4
+        assert_float_eq(frequencies[0], PITCH_TO_FREQ_HZ[60] as f32);
+        assert_float_eq(frequencies[1], PITCH_TO_FREQ_HZ[64] as f32);
+        assert_float_eq(frequencies[2], PITCH_TO_FREQ_HZ[67] as f32);
+    }
+
+    #[test]
+    fn test_arpeggiated_chord_plays_one_pitch_at_a_time() {
+        let state = AudioState::new();
+        {
+            let mut tracks = state.tracks.lock().unwrap();
+            let track_data = &mut tracks.get_track_data_mut()[0];
+            track_data.arp_mode = Some(ArpMode::Up);
+            let step = &mut track_data.steps[0];
+            step.enabled = true;
+            step.octave = 4;
+            step.extra_pitches = vec![WesternPitch::E, WesternPitch::G];
+        }
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let sounding = |engine: &EngineState| -> Vec<f32> {
+            engine.voice_pools[0].voices.iter()
+                .filter(|voice| voice.active)
+                .map(|voice| voice.playback_note.note.frequency)
+                .collect()
+        };
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; 64 * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert_eq!(sounding(&engine), vec![PITCH_TO_FREQ_HZ[60] as f32]);
+
+        // a sixteenth at 120 BPM later the first note has ended and the next has started
+        let mut output = vec![0.0; 5513 * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert_eq!(sounding(&engine), vec![PITCH_TO_FREQ_HZ[64] as f32]);
+    }
+
+    #[test]
+    fn test_long_gate_overlaps_next_step() {
+        let state = AudioState::new();
+        {
+            let mut tracks = state.tracks.lock().unwrap();
+            let steps = &mut tracks.get_track_data_mut()[0].steps;
+            steps[0].enabled = true;
+            steps[0].gate_length = 2.0;
+            steps[1].enabled = true;
+        }
+        // 10ms steps
+        state.tempo.store(6000.0, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; 441 * 2 * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        // the first note is still held when the second step starts
+        assert_eq!(engine.voice_pools[0].active_voices(), 2);
+    }
+
+    #[test]
+    fn test_track_step() {
+        let steps: Vec<Option<usize>> = (0..8).map(|tick| track_step(tick, 3, 1, None))
+            .collect();
+        assert_eq!(steps, vec![Some(0), Some(1), Some(2), Some(0), Some(1), Some(2), Some(0),
+                               Some(1)]);
+
+        // a divisor of 2 moves every other tick
+        let steps: Vec<Option<usize>> = (0..8).map(|tick| track_step(tick, 3, 2, None))
+            .collect();
+        assert_eq!(steps, vec![Some(0), None, Some(1), None, Some(2), None, Some(0), None]);
+    }
+
+    #[test]
+    fn test_track_step_in_loop() {
+        // Every step of the loop is played, however slowly the track moves
+        let steps: Vec<usize> = (0..12).filter_map(|tick| track_step(tick, 16, 2, Some((4, 6))))
+            .collect();
+        assert_eq!(steps, vec![4, 5, 6, 4, 5, 6]);
+        // A loop reaching past a shorter track ends at the track's last step
+        let steps: Vec<usize> = (0..4).filter_map(|tick| track_step(tick, 6, 1, Some((4, 8))))
+            .collect();
+        assert_eq!(steps, vec![4, 5, 4, 5]);
+        // and one starting past it plays the last step
+        assert_eq!(track_step(3, 4, 1, Some((6, 8))), Some(3));
+    }
+
+    #[test]
+    fn test_3_and_4_step_tracks_realign_every_12_steps() {
+        let state = AudioState::new();
+        {
+            let mut tracks = state.tracks.lock().unwrap();
+            let track_data = tracks.get_track_data_mut();
+            track_data[0].length = 3;
+            track_data[1].length = 4;
+        }
+        // 10ms steps, so each 441 frame callback triggers exactly one step
+        state.tempo.store(6000.0, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; 441 * 2];
+        let mut aligned_ticks = Vec::new();
+        for tick in 0..36 {
+            audio_callback(&mut output, 2, &state, &mut engine);
+            let step_3 = state.track_current_steps[0].load(Ordering::Relaxed);
+            let step_4 = state.track_current_steps[1].load(Ordering::Relaxed);
+            assert_eq!(step_3, tick % 3);
+            assert_eq!(step_4, tick % 4);
+            if step_3 == 0 && step_4 == 0 {
+                aligned_ticks.push(tick);
+            }
+        }
+        assert_eq!(aligned_ticks, vec![0, 12, 24]);
+    }
+
+    #[test]
+    fn test_midi_note_updates() {
+        let state = AudioState::new();
+        state.handle_parameter_update(&ParameterUpdate::MidiNoteOn { note: 60, velocity: 100 });
+        assert_eq!(state.live_note_velocities[60].load(Ordering::Relaxed), 100);
+        state.handle_parameter_update(&ParameterUpdate::MidiNoteOff { note: 60 });
+        assert_eq!(state.live_note_velocities[60].load(Ordering::Relaxed), 0);
+
+        // notes outside the MIDI range are ignored
+        state.handle_parameter_update(&ParameterUpdate::MidiNoteOn { note: 200, velocity: 100 });
+        assert!(state.live_note_velocities.iter()
+            .all(|velocity| velocity.load(Ordering::Relaxed) == 0));
+    }
+
+    #[test]
+    fn test_midi_note_sounds_until_released() {
+        let state = AudioState::new();
+        // so nothing is left of the note once it ends
+        state.dc_blocker_enabled.store(false, Ordering::Relaxed);
+        let mut engine = EngineState::new();
+
+        // live notes play whether or not the sequencer is running
+        state.handle_parameter_update(&ParameterUpdate::MidiNoteOn { note: 69, velocity: 127 });
+        let mut output = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert!(peak(&output) > 0.1);
+        assert_eq!(engine.live_voices.active_voices(), 1);
+
+        // it fades out over the note's fade after the note off, then is silent
+        state.handle_parameter_update(&ParameterUpdate::MidiNoteOff { note: 69 });
+        let mut output = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert!(peak(&output[NUM_FRAMES..]) < 1e-6);
+        assert_eq!(engine.live_voices.active_voices(), 0);
+    }
+
+    #[test]
+    fn test_midi_notes_overlap() {
+        let state = AudioState::new();
+        let mut engine = EngineState::new();
+        for note in [60, 64, 67] {
+            state.handle_parameter_update(&ParameterUpdate::MidiNoteOn { note, velocity: 90 });
+        }
+        let mut output = vec![0.0; 2 * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert_eq!(engine.live_voices.active_voices(), 3);
+
+        state.handle_parameter_update(&ParameterUpdate::MidiNoteOff { note: 64 });
+        let mut output = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert_eq!(engine.live_voices.active_voices(), 2);
+    }
+
+    #[test]
+    fn test_panic_silences_everything() {
+        let state = AudioState::new();
+        state.dc_blocker_enabled.store(false, Ordering::Relaxed);
+        tracks_on_first_step(&state, 2);
+        state.is_playing.store(true, Ordering::Relaxed);
+        state.handle_parameter_update(&ParameterUpdate::MidiNoteOn { note: 60, velocity: 100 });
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert_eq!(engine.voice_pools[0].active_voices(), 1);
+        assert_eq!(engine.live_voices.active_voices(), 1);
+
+        state.handle_parameter_update(&ParameterUpdate::Panic);
+        assert!(!state.is_playing.load(Ordering::Relaxed));
+        assert_eq!(state.live_note_velocities[60].load(Ordering::Relaxed), 0);
+
+        // Notes are cut off at once rather than left to fade out
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert!(engine.voice_pools.iter().all(|voice_pool| voice_pool.active_voices() == 0));
+        assert_eq!(engine.live_voices.active_voices(), 0);
+        assert_eq!(peak(&output), 0.0);
+    }
+
+    #[test]
+    fn test_external_clock_advances_steps() {
+        let state = AudioState::new();
+        // fast enough that the internal clock would move several steps per buffer
+        state.tempo.store(6000.0, Ordering::Relaxed);
+        state.handle_parameter_update(&ParameterUpdate::ClockSource(ClockSource::MidiExternal));
+        assert_eq!(state.clock_source(), ClockSource::MidiExternal);
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; NUM_FRAMES * 2];
+
+        state.handle_parameter_update(&ParameterUpdate::MidiStart);
+        assert!(state.is_playing.load(Ordering::Relaxed));
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert_eq!(state.current_step.load(Ordering::Relaxed), 0);
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert_eq!(state.current_step.load(Ordering::Relaxed), 0);
+
+        for expected_step in 1..4 {
+            state.handle_parameter_update(&ParameterUpdate::MidiClockStep);
+            audio_callback(&mut output, 2, &state, &mut engine);
+            assert_eq!(state.current_step.load(Ordering::Relaxed), expected_step);
+        }
+
+        // stop holds the position, start goes back to the first step
+        state.handle_parameter_update(&ParameterUpdate::MidiStop);
+        assert!(!state.is_playing.load(Ordering::Relaxed));
+        state.handle_parameter_update(&ParameterUpdate::MidiStart);
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert_eq!(state.current_step.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_internal_clock_ignores_midi_transport() {
+        let state = AudioState::new();
+        assert_eq!(state.clock_source(), ClockSource::Internal);
+        state.handle_parameter_update(&ParameterUpdate::MidiStart);
+        state.handle_parameter_update(&ParameterUpdate::MidiClockStep);
+        assert!(!state.is_playing.load(Ordering::Relaxed));
+        assert_eq!(state.external_ticks.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_master_volume() {
+        let state = AudioState::new();
+        tracks_on_first_step(&state, 1);
+        state.is_playing.store(true, Ordering::Relaxed);
+        state.handle_parameter_update(&ParameterUpdate::MasterVolume(0.5));
+        let mut half_volume = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut half_volume, 2, &state, &mut EngineState::new());
+
+        state.handle_parameter_update(&ParameterUpdate::MasterVolume(2.0));
+        assert_float_eq(state.master_volume.load(Ordering::Relaxed), 1.0);
+        let mut full_volume = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut full_volume, 2, &state, &mut EngineState::new());
+
+        // a single track stays under the limiter's threshold
+        assert!((peak(&half_volume) / peak(&full_volume) - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_queued_updates_apply_the_latest_value() {
+        let state = AudioState::new();
+        let mut parameter_queue = ParameterQueue::new();
+        for i in 0..=4096 {
+            parameter_queue.push(ParameterUpdate::MasterVolume(i as f32 / 8192.0)).unwrap();
+            parameter_queue.push(ParameterUpdate::FilterCutoff(100.0 + i as f32)).unwrap();
+        }
+        parameter_queue.push(ParameterUpdate::MidiNoteOn { note: 60, velocity: 100 }).unwrap();
+        assert_float_eq(state.master_volume.load(Ordering::Relaxed), 1.0);
+
+        apply_queued_updates(&state, &mut parameter_queue);
+        assert_float_eq(state.master_volume.load(Ordering::Relaxed), 0.5);
+        assert_float_eq(state.master_filter_cutoff.load(Ordering::Relaxed), 4196.0);
+        assert_eq!(state.live_note_velocities[60].load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn test_master_filter_updates() {
+        let state = AudioState::new();
+        state.handle_parameter_update(&ParameterUpdate::FilterType(FilterType::Notch));
+        state.handle_parameter_update(&ParameterUpdate::FilterCutoff(50000.0));
+        state.handle_parameter_update(&ParameterUpdate::FilterResonance(-1.0));
+        state.handle_parameter_update(&ParameterUpdate::FilterMix(0.5));
+
+        assert_eq!(state.master_filter_type(), FilterType::Notch);
+        assert_float_eq(state.master_filter_cutoff.load(Ordering::Relaxed), MAX_FILTER_CUTOFF);
+        assert_float_eq(state.master_filter_resonance.load(Ordering::Relaxed), 0.0);
+        assert_float_eq(state.master_filter_mix.load(Ordering::Relaxed), 0.5);
+    }
+
+    #[test]
+    fn test_master_filter_cuts_the_mix() {
+        let state = AudioState::new();
+        tracks_on_first_step(&state, 1);
+        state.is_playing.store(true, Ordering::Relaxed);
+        let mut unfiltered = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut unfiltered, 2, &state, &mut EngineState::new());
+
+        // A low pass well under the track's pitch
+        state.handle_parameter_update(&ParameterUpdate::FilterType(FilterType::LowPass));
+        state.handle_parameter_update(&ParameterUpdate::FilterCutoff(50.0));
+        state.handle_parameter_update(&ParameterUpdate::FilterMix(1.0));
+        let mut filtered = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut filtered, 2, &state, &mut EngineState::new());
+
+        assert!(peak(&filtered) < 0.5 * peak(&unfiltered));
+    }
+
+    #[test]
+    fn test_envelope_shapes_step_notes() {
+        let state = AudioState::new();
+        tracks_on_first_step(&state, 1);
+        state.is_playing.store(true, Ordering::Relaxed);
+        // A fast decay to silence, so the end of the buffer is quieter than the start
+        state.handle_parameter_update(&ParameterUpdate::EnvelopeAttack(0.0));
+        state.handle_parameter_update(&ParameterUpdate::EnvelopeDecay(0.02));
+        state.handle_parameter_update(&ParameterUpdate::EnvelopeSustain(0.0));
+        let envelope = state.note_envelope(100.0);
+        assert_float_eq(envelope.decay.0, 0.2);
+        assert_float_eq(envelope.sustain.1, 0.0);
+
+        let mut output = vec![0.0; NUM_FRAMES * 2];
+        audio_callback(&mut output, 2, &state, &mut EngineState::new());
+        let (start, end) = output.split_at(NUM_FRAMES);
+        assert!(peak(end) < 0.1 * peak(start));
+    }
+
+    #[test]
+    fn test_envelope_shapes_arpeggiated_notes() {
+        let state = AudioState::new();
+        {
+            let mut tracks = state.tracks.lock().unwrap();
+            let track_data = &mut tracks.get_track_data_mut()[0];
+            track_data.arp_mode = Some(ArpMode::Up);
+            let step = &mut track_data.steps[0];
+            step.enabled = true;
+            step.extra_pitches = vec![WesternPitch::E, WesternPitch::G];
+        }
+        state.is_playing.store(true, Ordering::Relaxed);
+        state.handle_parameter_update(&ParameterUpdate::EnvelopeSustain(0.25));
+
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; 64 * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        let voice = engine.voice_pools[0].voices.iter()
+            .find(|voice| voice.active)
+            .expect("no arpeggiated note sounding");
+        assert_eq!(voice.playback_note.envelopes.len(), 1);
+        assert_float_eq(voice.playback_note.envelopes[0].sustain.1, 0.25);
+    }
+
+    #[test]
+    fn test_is_accent_click() {
+        let accents: Vec<bool> = (0..8).map(|step| is_accent_click(step, 4, 4)).collect();
+        assert_eq!(accents, vec![true, false, false, false, true, false, false, false]);
+        assert!((0..3).all(|step| is_accent_click(step, 1, 4)));
+        // In 6/8 each quarter note step is two beats, so a measure is three steps
+        let accents: Vec<bool> = (0..6).map(|step| is_accent_click(step, 6, 8)).collect();
+        assert_eq!(accents, vec![true, false, false, true, false, false]);
+        // A 7/8 measure starts on a step every other measure, on steps 0 and 7
+        let accents: Vec<u64> = (0..15).filter(|step| is_accent_click(*step, 7, 8)).collect();
+        assert_eq!(accents, vec![0, 7, 14]);
+    }
+
+    #[test]
+    fn test_peak_and_rms() {
+        assert_eq!(peak_and_rms(&[]), (0.0, 0.0));
+        let (peak, rms) = peak_and_rms(&[0.5, -1.0, 0.5, 0.0]);
+        assert_float_eq(peak, 1.0);
+        assert_float_eq(rms, 0.375f32.sqrt());
+
+        // A full scale sine over whole cycles has an RMS of 1/√2
+        let sine: Vec<f32> = (0..400)
+            .map(|i| (2.0 * std::f32::consts::PI * i as f32 / 100.0).sin())
+            .collect();
+        let (peak, rms) = peak_and_rms(&sine);
+        assert_float_eq(peak, 1.0);
+        assert!((rms - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_scope_samples() {
+        let output: Vec<f32> = (0..16).map(|i| i as f32).collect();
+        let mut points = [0.0; 4];
+        // Stereo frames average their channels, and every other frame is kept
+        assert_eq!(scope_samples(&output, 2, &mut points), 4);
+        assert_eq!(points, [0.5, 4.5, 8.5, 12.5]);
+        assert_eq!(scope_samples(&output, 1, &mut points), 4);
+        assert_eq!(points, [0.0, 4.0, 8.0, 12.0]);
+        // Never more points than frames
+        assert_eq!(scope_samples(&output[..4], 2, &mut points), 2);
+        assert_eq!(points[..2], [0.5, 2.5]);
+        assert_eq!(scope_samples(&[], 2, &mut points), 0);
+    }
+
+    #[test]
+    fn test_levels_are_fed_back() {
+        let state = AudioState::new();
+        state.metronome_on.store(true, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+        let (feedback_producer, mut feedback_consumer) = HeapRb::new(4).split();
+        let mut engine = EngineState {
+            feedback_producer: Some(feedback_producer),
+            ..EngineState::new()
+        };
+
+        // Only the click sounds, which is outside of the tracks
+        let mut output = vec![0.0; 512];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        let Some(AudioFeedback::Levels { master_peak, master_rms, track_peaks }) =
+            feedback_consumer.pop() else {
+            panic!("expected levels after a block");
+        };
+        assert_float_eq(master_peak, peak(&output));
+        assert!(master_rms > 0.0 && master_rms < master_peak);
+        assert_eq!(track_peaks, [0.0; 8]);
+    }
+
+    #[test]
+    fn test_scope_frames_are_fed_back() {
+        let state = AudioState::new();
+        let (scope_producer, mut scope_consumer) = HeapRb::new(4).split();
+        let mut engine = EngineState {
+            scope_producer: Some(scope_producer),
+            ..EngineState::new()
+        };
+
+        let mut output = vec![0.0; 512];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        let frame = scope_consumer.pop().expect("expected a scope frame after a block");
+        assert_eq!(frame.samples().len(), SCOPE_POINTS);
+        // A block of fewer frames than SCOPE_POINTS gives one point a frame
+        audio_callback(&mut output[..64], 2, &state, &mut engine);
+        assert_eq!(scope_consumer.pop().unwrap().samples().len(), 32);
+    }
+
+    #[test]
+    fn test_metronome_clicks_on_each_beat() {
+        let state = AudioState::new();
+        state.beats_per_measure.store(3, Ordering::Relaxed);
+        // 100ms beats, with nothing on the tracks so only the click sounds
+        state.tempo.store(600.0, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+        let mut engine = EngineState::new();
+        let beat_frames = 4410;
+        let click_frames = 30 * 441 / 10;
+
+        // Off, the metronome is silent
+        let mut output = vec![0.0; beat_frames * 2];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert_eq!(peak(&output), 0.0);
+
+        state.metronome_on.store(true, Ordering::Relaxed);
+        let mut beat_peaks = Vec::new();
+        for _ in 0..4 {
+            audio_callback(&mut output, 2, &state, &mut engine);
+            // Each click starts on its beat and is over well before the next
+            assert!(peak(&output[..click_frames * 2]) > 0.0);
+            assert_eq!(peak(&output[click_frames * 2..]), 0.0);
+            beat_peaks.push(peak(&output));
+        }
+        // The first beat of a measure is accented, and the metronome started on the second
+        assert!(beat_peaks[2] > beat_peaks[0]);
+        assert!(beat_peaks[2] > beat_peaks[1]);
+        assert_float_eq(beat_peaks[0], beat_peaks[3]);
+    }
+
+    #[test]
+    fn test_metronome_click_follows_master_volume() {
+        let click_peak = |master_volume: f32| {
+            let state = AudioState::new();
+            state.master_volume.store(master_volume, Ordering::Relaxed);
+            state.metronome_on.store(true, Ordering::Relaxed);
+            state.is_playing.store(true, Ordering::Relaxed);
+            let mut engine = EngineState::new();
+            let mut output = vec![0.0; 2 * 441];
+            audio_callback(&mut output, 2, &state, &mut engine);
+            peak(&output)
+        };
+        assert!(click_peak(1.0) > 0.0);
+        assert_float_eq(click_peak(0.5), 0.5 * click_peak(1.0));
+        assert_eq!(click_peak(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_metronome_click_is_limited_with_the_mix() {
+        let state = AudioState::new();
+        state.metronome_on.store(true, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+        let mut engine = EngineState::new();
+        // A loud note that the accented click would push past full scale
+        engine.voice_pools[0].trigger(440.0, 1000.0, Waveform::Square, 127, None);
+
+        let mut output = vec![0.0; 2 * 4410];
+        audio_callback(&mut output, 2, &state, &mut engine);
+        assert!(peak(&output) < 1.0);
+    }
+
+    #[test]
+    fn test_loop_position() {
+        // Past the loop's last step, playback goes back to its first
+        let positions: Vec<u64> = (0..7).map(|tick| loop_position(tick, 16, Some((4, 7))))
+            .collect();
+        assert_eq!(positions, vec![4, 5, 6, 7, 4, 5, 6]);
+        // Without a loop the clock runs on, for each track to wrap at its own length
+        assert_eq!(loop_position(20, 16, None), 20);
+        // A loop past the end of a shortened sequence is kept within it
+        assert_eq!(loop_position(0, 8, Some((10, 20))), 7);
+        assert_eq!(loop_position(1, 8, Some((6, 20))), 7);
+        assert_eq!(loop_position(2, 8, Some((6, 20))), 6);
+    }
+
+    #[test]
+    fn test_playback_loops_within_region() {
+        let state = AudioState::new();
+        state.loop_enabled.store(true, Ordering::Relaxed);
+        state.loop_start.store(4, Ordering::Relaxed);
+        state.loop_end.store(8, Ordering::Relaxed);
+        // 10ms steps, so each 441 frame callback triggers exactly one step
+        state.tempo.store(6000.0, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; 441 * 2];
+        let mut steps = Vec::new();
+        for _ in 0..7 {
+            audio_callback(&mut output, 2, &state, &mut engine);
+            steps.push(state.current_step.load(Ordering::Relaxed));
+        }
+        assert_eq!(steps, vec![4, 5, 6, 7, 8, 4, 5]);
+    }
+
+    #[test]
+    fn test_slow_track_plays_every_step_of_the_loop() {
+        let mut grid = SequencerGrid::new(16);
+        grid.tracks[1].divisor = 2;
+        let state = AudioState::new();
+        state.sync_from_grid(&grid);
+        state.loop_enabled.store(true, Ordering::Relaxed);
+        state.loop_start.store(4, Ordering::Relaxed);
+        state.loop_end.store(6, Ordering::Relaxed);
+        state.tempo.store(6000.0, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; 441 * 2];
+        let mut steps = Vec::new();
+        // On every other tick, when the track moves
+        for tick in 0..12 {
+            audio_callback(&mut output, 2, &state, &mut engine);
+            if tick % 2 == 0 {
+                steps.push(state.track_current_steps[1].load(Ordering::Relaxed));
+            }
+        }
+        assert_eq!(steps, vec![4, 5, 6, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_sync_32_step_grid() {
+        let mut grid = SequencerGrid::new(16);
+        grid.set_steps_per_track(32);
+        for (step_idx, step) in grid.tracks[0].steps.iter_mut().enumerate() {
+            // A different pitch on each step, rising chromatically from C2
+            step.enabled = true;
+            (step.frequency, step.octave) = WesternPitch::from_midi_note(36 + step_idx as u8);
+        }
+        let state = AudioState::new();
+        state.sync_from_grid(&grid);
+        assert_eq!(state.steps_per_track.load(Ordering::Relaxed), 32);
+        {
+            let tracks = state.tracks.lock().unwrap();
+            let steps = &tracks.get_track_data()[0].steps;
+            assert_eq!(steps.len(), 32);
+            for (synced_step, step) in steps.iter().zip(grid.tracks[0].steps.iter()) {
+                assert_eq!(synced_step.frequency, step.frequency);
+                assert_eq!(synced_step.octave, step.octave);
+                assert_float_eq(synced_step.frequency_hz(), step.frequency_hz());
+            }
+        }
+
+        // Playback reaches the last of the 32 steps before wrapping, with 10ms steps so each
+        // 441 frame callback triggers exactly one
+        state.tempo.store(6000.0, Ordering::Relaxed);
+        state.is_playing.store(true, Ordering::Relaxed);
+        let mut engine = EngineState::new();
+        let mut output = vec![0.0; 441 * 2];
+        let mut steps = Vec::new();
+        for _ in 0..33 {
+            audio_callback(&mut output, 2, &state, &mut engine);
+            steps.push(state.current_step.load(Ordering::Relaxed));
+        }
+        let mut expected: Vec<usize> = (0..32).collect();
+        expected.push(0);
+        assert_eq!(steps, expected);
+    }
+}
@@ -1,6 +1,9 @@
 use crate::tui::{TuiError, app::SynthParameters};
+use crate::tui::ui::widgets::TrackStrip;
+use crate::tui::key_bindings::{default_key_bindings, deserialize_key_bindings,
+                               serialize_key_bindings, unknown_key_bindings, KeyBindings};
+use crate::tui::midi_input::{default_cc_map, CcMap};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,11 +14,29 @@ pub struct TuiConfig {
     
     // Audio settings
     pub audio_device: Option<String>,
+    // MIDI keyboard to play, the first input port if not set
+    #[serde(default)]
+    pub midi_input_port: Option<String>,
+    // Which synth parameter each MIDI controller number drives
+    #[serde(default = "default_cc_map", with = "cc_map_keys")]
+    pub midi_cc_map: CcMap,
     pub sample_rate: u32,
-    pub buffer_size: u32,
+    // Frames the output device plays per callback, fewer for lower latency, or the device's own
+    // choice if not set
+    #[serde(default)]
+    pub buffer_size: Option<u32>,
+    // Seconds between auto-saves of a session with unsaved edits, 0 to never auto-save
+    #[serde(default = "default_auto_save_secs")]
+    pub auto_save_secs: u64,
     
-    // Keyboard mappings
-    pub key_bindings: HashMap<String, String>,
+    // Action of each key, by the key's name. Read over the defaults, so a config need only list
+    // the keys it changes: a key bound to an action, or to "" to unbind it.
+    #[serde(default = "default_key_bindings", deserialize_with = "deserialize_key_bindings",
+            serialize_with = "serialize_key_bindings")]
+    pub key_bindings: KeyBindings,
+    // Key bindings the config file has that don't name an action, to tell the user about
+    #[serde(skip)]
+    pub unknown_key_bindings: Vec<String>,
     
     // Synthesizer defaults
     pub default_synth_params: SynthParameters,
@@ -40,24 +61,26 @@ pub struct LayoutPreferences {
 
 impl Default for TuiConfig {
     fn default() -> Self {
-        let mut key_bindings = HashMap::new();
-        key_bindings.insert("quit".to_string(), "q".to_string());
-        key_bindings.insert("help".to_string(), "F1".to_string());
-        key_bindings.insert("focus_next".to_string(), "Tab".to_string());
-        key_bindings.insert("play_stop".to_string(), "Space".to_string());
-        
         Self {
             theme: ColorTheme::default(),
             layout: LayoutPreferences::default(),
             audio_device: None,
+            midi_input_port: None,
+            midi_cc_map: default_cc_map(),
             sample_rate: 44100,
-            buffer_size: 512,
-            key_bindings,
+            buffer_size: None,
+            auto_save_secs: default_auto_save_secs(),
+            key_bindings: default_key_bindings(),
+            unknown_key_bindings: Vec::new(),
             default_synth_params: SynthParameters::default(),
         }
     }
 }
 
+fn default_auto_save_secs() -> u64 {
+    60
+}
+
 impl Default for ColorTheme {
     fn default() -> Self {
         Self {
@@ -81,6 +104,30 @@ impl Default for LayoutPreferences {
     }
 }
 
+// TOML table keys must be strings, so controller numbers are written as strings
+mod cc_map_keys {
+    use crate::tui::midi_input::{CcMap, ParamTarget};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer>(cc_map: &CcMap, serializer: S) -> Result<S::Ok, S::Error> {
+        cc_map.iter()
+            .map(|(controller, target)| (controller.to_string(), *target))
+            .collect::<BTreeMap<String, ParamTarget>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<CcMap, D::Error> {
+        BTreeMap::<String, ParamTarget>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(controller, target)| controller.parse::<u8>()
+                .map(|controller| (controller, target))
+                .map_err(|_| serde::de::Error::custom(
+                    format!("{} is not a MIDI controller number", controller))))
+            .collect()
+    }
+}
+
 impl TuiConfig {
     pub fn load_or_default() -> Result<Self, TuiError> {
         match Self::load() {
@@ -98,8 +145,18 @@ impl TuiConfig {
         let content = std::fs::read_to_string(config_path)
             .map_err(|e| TuiError::Config(format!("Failed to read config file: {}", e)))?;
         
-        toml::from_str(&content)
-            .map_err(|e| TuiError::Config(format!("Failed to parse config file: {}", e)))
+        Self::from_toml(&content)
+    }
+
+    fn from_toml(content: &str) -> Result<Self, TuiError> {
+        let parse_error = |e| TuiError::Config(format!("Failed to parse config file: {}", e));
+        let mut config: Self = toml::from_str(content).map_err(parse_error)?;
+        let table: toml::Table = toml::from_str(content).map_err(parse_error)?;
+        if let Some(key_bindings) = table.get("key_bindings") {
+            config.unknown_key_bindings =
+                unknown_key_bindings(key_bindings.clone()).map_err(parse_error)?;
+        }
+        Ok(config)
     }
     
     pub fn save(&self) -> Result<(), TuiError> {
@@ -120,23 +177,52 @@ impl TuiConfig {
     }
     
     fn config_file_path() -> Result<PathBuf, TuiError> {
-        let mut path = dirs::config_dir()
-            .ok_or_else(|| TuiError::Config("Could not determine config directory".to_string()))?;
-        path.push("rosco");
-        path.push("tui_config.toml");
-        Ok(path)
+        config_dir_path("tui_config.toml")
     }
 }
 
+// A file in rosco's config directory, alongside the config and the saved session
+fn config_dir_path(file_name: &str) -> Result<PathBuf, TuiError> {
+    let mut path = dirs::config_dir()
+        .ok_or_else(|| TuiError::Config("Could not determine config directory".to_string()))?;
+    path.push("rosco");
+    path.push(file_name);
+    Ok(path)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionState {
     pub synth_params: SynthParameters,
     pub tempo: f32,
     pub transport_playing: bool,
+    // The sequencer grid, each track with its steps and mix
+    #[serde(default = "default_session_steps_per_track")]
+    pub steps_per_track: usize,
+    #[serde(default)]
+    pub tracks: Vec<TrackStrip>,
+}
+
+fn default_session_steps_per_track() -> usize {
+    16
 }
 
 impl SessionState {
+    pub fn default_path() -> Result<PathBuf, TuiError> {
+        config_dir_path("session.json")
+    }
+
+    /// Where the session is auto-saved, next to the saved session. It is left behind only if the
+    /// app didn't exit cleanly since the last save.
+    pub fn auto_save_path() -> Result<PathBuf, TuiError> {
+        config_dir_path("autosave.json")
+    }
+
     pub fn save_to_file(&self, path: &std::path::Path) -> Result<(), TuiError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| TuiError::Config(format!("Failed to create session directory: {}", e)))?;
+        }
+
         let content = serde_json::to_string_pretty(self)
             .map_err(|e| TuiError::Config(format!("Failed to serialize session: {}", e)))?;
         
@@ -153,4 +239,135 @@ impl SessionState {
         serde_json::from_str(&content)
             .map_err(|e| TuiError::Config(format!("Failed to parse session file: {}", e)))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test_config {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use crate::audio_gen::oscillator::Waveform;
+    use crate::note::scales::WesternPitch;
+    use crate::tui::app::SynthParameters;
+    use crate::tui::config::{SessionState, TuiConfig};
+    use crate::tui::key_bindings::{default_key_bindings, key_name, Action};
+    use crate::tui::midi_input::ParamTarget;
+    use crate::tui::ui::widgets::SequencerGrid;
+
+    #[test]
+    fn test_cc_map_round_trips_through_toml() {
+        let mut config = TuiConfig::default();
+        config.midi_cc_map.insert(1, ParamTarget::OscillatorFrequency);
+
+        let content = toml::to_string_pretty(&config).unwrap();
+        let loaded: TuiConfig = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.midi_cc_map, config.midi_cc_map);
+    }
+
+    #[test]
+    fn test_buffer_size_is_optional() {
+        let content = toml::to_string_pretty(&TuiConfig::default()).unwrap();
+        let loaded: TuiConfig = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.buffer_size, None);
+
+        let config = TuiConfig { buffer_size: Some(256), ..TuiConfig::default() };
+        let content = toml::to_string_pretty(&config).unwrap();
+        let loaded: TuiConfig = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.buffer_size, Some(256));
+    }
+
+    #[test]
+    fn test_auto_save_secs_defaults_when_missing() {
+        // Configs from before auto-save have no auto_save_secs
+        let config = TuiConfig { auto_save_secs: 5, ..TuiConfig::default() };
+        let content = toml::to_string_pretty(&config).unwrap()
+            .replace("auto_save_secs = 5\n", "");
+        let loaded: TuiConfig = toml::from_str(&content).unwrap();
+        assert_eq!(loaded.auto_save_secs, TuiConfig::default().auto_save_secs);
+    }
+
+    #[test]
+    fn test_custom_key_binding() {
+        let mut config = TuiConfig::default();
+        config.key_bindings.insert("h".to_string(), Action::NavigateLeft);
+        config.key_bindings.remove("q");
+        let content = toml::to_string_pretty(&config).unwrap();
+
+        let loaded: TuiConfig = toml::from_str(&content).unwrap();
+        let action = |code| key_name(&KeyEvent::new(code, KeyModifiers::NONE))
+            .and_then(|name| loaded.key_bindings.get(&name).copied());
+        assert_eq!(action(KeyCode::Char('h')), Some(Action::NavigateLeft));
+        assert_eq!(action(KeyCode::Char('q')), None);
+        assert_eq!(action(KeyCode::Esc), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_key_bindings_merge_over_the_defaults() {
+        let mut content = toml::to_string_pretty(&TuiConfig::default()).unwrap();
+        let start = content.find("[key_bindings]").unwrap();
+        let end = content[start + 1..].find("\n[").map_or(content.len(), |end| start + 1 + end);
+        content.replace_range(start..end,
+                              "[key_bindings]\nh = \"NavigateLeft\"\nq = \"\"\nx = \"Jump\"\n");
+
+        let loaded = TuiConfig::from_toml(&content).unwrap();
+        let mut expected = default_key_bindings();
+        expected.insert("h".to_string(), Action::NavigateLeft);
+        expected.remove("q");
+        assert_eq!(loaded.key_bindings, expected);
+        assert_eq!(loaded.unknown_key_bindings, vec!["x = \"Jump\"".to_string()]);
+    }
+
+    #[test]
+    fn test_old_key_bindings_load_the_defaults() {
+        // Before keys could be rebound the config mapped action names to keys
+        let mut content = toml::to_string_pretty(&TuiConfig::default()).unwrap();
+        let start = content.find("[key_bindings]").unwrap();
+        let end = content[start + 1..].find("\n[").map_or(content.len(), |end| start + 1 + end);
+        content.replace_range(start..end, "[key_bindings]\nquit = \"q\"\nhelp = \"F1\"\n");
+
+        let loaded = TuiConfig::from_toml(&content).unwrap();
+        assert_eq!(loaded.key_bindings, default_key_bindings());
+        assert!(loaded.unknown_key_bindings.is_empty());
+    }
+
+    #[test]
+    fn test_session_round_trip() {
+        let mut grid = SequencerGrid::new(24);
+        for (step_idx, pitch) in [(0, WesternPitch::C), (5, WesternPitch::FSharp),
+                                  (23, WesternPitch::A)] {
+            let step = &mut grid.tracks[2].steps[step_idx];
+            step.enabled = true;
+            step.frequency = pitch;
+            step.octave = 5;
+        }
+        grid.tracks[2].waveform = Waveform::Saw;
+        grid.tracks[2].length = 12;
+        let session = SessionState {
+            synth_params: SynthParameters::default(),
+            tempo: 96.0,
+            transport_playing: false,
+            steps_per_track: grid.steps_per_track,
+            tracks: grid.tracks.to_vec(),
+        };
+
+        let path = std::env::temp_dir()
+            .join(format!("rosco_test_session_{}.json", std::process::id()));
+        session.save_to_file(&path).unwrap();
+        let loaded = SessionState::load_from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut loaded_grid = SequencerGrid::new(16);
+        loaded_grid.restore_tracks(loaded.tracks, loaded.steps_per_track);
+        assert_eq!(loaded.tempo, 96.0);
+        assert_eq!(loaded_grid.steps_per_track, 24);
+        for (track, loaded_track) in grid.tracks.iter().zip(loaded_grid.tracks.iter()) {
+            assert_eq!(loaded_track.waveform, track.waveform);
+            assert_eq!(loaded_track.length, track.length);
+            assert_eq!(loaded_track.steps.len(), track.steps.len());
+            for (step, loaded_step) in track.steps.iter().zip(loaded_track.steps.iter()) {
+                assert_eq!(loaded_step.enabled, step.enabled);
+                assert_eq!(loaded_step.frequency, step.frequency);
+                assert_eq!(loaded_step.octave, step.octave);
+            }
+        }
+    }
+}
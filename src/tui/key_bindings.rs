@@ -0,0 +1,225 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+
+/// Something the app does from anywhere, whichever section has focus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Quit,
+    ToggleHelp,
+    ToggleRecording,
+    ToggleMetronome,
+    // Show volumes as a percentage or in dB
+    ToggleVolumeDisplay,
+    // Write the sequencer grid out as a DSL script
+    ExportDsl,
+    SaveSession,
+    LoadSession,
+    FocusNext,
+    // Move within, or adjust, the focused section as the arrow keys do
+    NavigateUp,
+    NavigateDown,
+    NavigateLeft,
+    NavigateRight,
+    Activate,
+    FocusOscillator,
+    FocusFilter,
+    FocusEnvelope,
+    FocusEffects,
+    FocusSequencer,
+    FocusTrackVolume,
+    FocusTrackPanning,
+    FocusTransport,
+    ResetParameter,
+    // Stop playback and silence every sounding note
+    Panic,
+}
+
+/// The action for each key, by the key's name as `key_name` gives it
+pub type KeyBindings = HashMap<String, Action>;
+
+/// The keys the app has always used
+pub fn default_key_bindings() -> KeyBindings {
+    HashMap::from([
+        ("q".to_string(), Action::Quit),
+        ("Esc".to_string(), Action::Quit),
+        ("F1".to_string(), Action::ToggleHelp),
+        ("F2".to_string(), Action::ToggleRecording),
+        ("F4".to_string(), Action::ToggleMetronome),
+        ("F5".to_string(), Action::SaveSession),
+        ("F6".to_string(), Action::LoadSession),
+        ("F7".to_string(), Action::ToggleVolumeDisplay),
+        ("F8".to_string(), Action::ExportDsl),
+        ("Tab".to_string(), Action::FocusNext),
+        ("Up".to_string(), Action::NavigateUp),
+        ("Down".to_string(), Action::NavigateDown),
+        ("Left".to_string(), Action::NavigateLeft),
+        ("Right".to_string(), Action::NavigateRight),
+        ("Enter".to_string(), Action::Activate),
+        ("Space".to_string(), Action::Activate),
+        ("1".to_string(), Action::FocusOscillator),
+        ("2".to_string(), Action::FocusFilter),
+        ("3".to_string(), Action::FocusEnvelope),
+        ("4".to_string(), Action::FocusEffects),
+        ("5".to_string(), Action::FocusSequencer),
+        ("6".to_string(), Action::FocusTrackVolume),
+        ("7".to_string(), Action::FocusTrackPanning),
+        ("8".to_string(), Action::FocusTransport),
+        ("r".to_string(), Action::ResetParameter),
+        ("F12".to_string(), Action::Panic),
+    ])
+}
+
+/// Name a key is bound by, the character itself for printable keys and otherwise the key's name,
+/// such as "Esc", "Space" or "F1". Modifiers are left to the action, so Shift+Up navigates up
+/// with Shift held.
+pub fn key_name(key: &KeyEvent) -> Option<String> {
+    let name = match key.code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Backspace => "Backspace".to_string(),
+        KeyCode::Up => "Up".to_string(),
+        KeyCode::Down => "Down".to_string(),
+        KeyCode::Left => "Left".to_string(),
+        KeyCode::Right => "Right".to_string(),
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// The arrow key an action navigates with, carrying the modifiers of the key that was pressed
+pub fn navigation_key(action: Action, modifiers: KeyModifiers) -> Option<KeyEvent> {
+    let code = match action {
+        Action::NavigateUp => KeyCode::Up,
+        Action::NavigateDown => KeyCode::Down,
+        Action::NavigateLeft => KeyCode::Left,
+        Action::NavigateRight => KeyCode::Right,
+        _ => return None,
+    };
+    Some(KeyEvent::new(code, modifiers))
+}
+
+/// A key's entry in a config, an action, "" to unbind a default key, or something else that
+/// doesn't name an action
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Binding {
+    Action(Action),
+    Other(String),
+    #[allow(dead_code)]
+    NotAString(IgnoredAny),
+}
+
+const UNBOUND: &str = "";
+
+/// Read key bindings from a config over the defaults, so a config only lists the keys it
+/// changes, skipping entries that don't name an action. Configs from before keys could be rebound
+/// mapped action names to keys, and have no entries that do, so they get the default keys.
+pub fn deserialize_key_bindings<'de, D: Deserializer<'de>>(deserializer: D)
+                                                           -> Result<KeyBindings, D::Error> {
+    let entries = HashMap::<String, Binding>::deserialize(deserializer)?;
+    let mut key_bindings = default_key_bindings();
+    if !entries.values().any(is_binding) {
+        return Ok(key_bindings);
+    }
+    for (key, binding) in entries {
+        match binding {
+            Binding::Action(action) => {
+                key_bindings.insert(key, action);
+            }
+            Binding::Other(unbound) if unbound == UNBOUND => {
+                key_bindings.remove(&key);
+            }
+            _ => {}
+        }
+    }
+    Ok(key_bindings)
+}
+
+/// Entries of a config's key bindings that `deserialize_key_bindings` skips because they don't
+/// name an action, as `key = "value"`. Nothing is reported for a config from before keys could
+/// be rebound.
+pub fn unknown_key_bindings<'de, D: Deserializer<'de>>(deserializer: D)
+                                                       -> Result<Vec<String>, D::Error> {
+    let entries = BTreeMap::<String, Binding>::deserialize(deserializer)?;
+    if !entries.values().any(is_binding) {
+        return Ok(Vec::new());
+    }
+    Ok(entries.into_iter()
+        .filter_map(|(key, binding)| match binding {
+            Binding::Action(_) => None,
+            Binding::Other(unbound) if unbound == UNBOUND => None,
+            Binding::Other(value) => Some(format!("{} = \"{}\"", key, value)),
+            Binding::NotAString(_) => Some(format!("{} = ...", key)),
+        })
+        .collect())
+}
+
+fn is_binding(binding: &Binding) -> bool {
+    match binding {
+        Binding::Action(_) => true,
+        Binding::Other(unbound) => unbound == UNBOUND,
+        Binding::NotAString(_) => false,
+    }
+}
+
+/// Write key bindings sorted by key, with "" for each default key that has been unbound so it
+/// stays unbound when read back over the defaults
+pub fn serialize_key_bindings<S: Serializer>(key_bindings: &KeyBindings, serializer: S)
+                                             -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum Entry {
+        Action(Action),
+        Unbound(&'static str),
+    }
+
+    default_key_bindings().into_keys()
+        .filter(|key| !key_bindings.contains_key(key))
+        .map(|key| (key, Entry::Unbound(UNBOUND)))
+        .chain(key_bindings.iter().map(|(key, action)| (key.clone(), Entry::Action(*action))))
+        .collect::<BTreeMap<String, Entry>>()
+        .serialize(serializer)
+}
+
+#[cfg(test)]
+mod test_key_bindings {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use crate::tui::key_bindings::{default_key_bindings, key_name, navigation_key, Action};
+
+    #[test]
+    fn test_key_names() {
+        let name = |code| key_name(&KeyEvent::new(code, KeyModifiers::NONE));
+        assert_eq!(name(KeyCode::Char('q')), Some("q".to_string()));
+        assert_eq!(name(KeyCode::Char(' ')), Some("Space".to_string()));
+        assert_eq!(name(KeyCode::F(1)), Some("F1".to_string()));
+        assert_eq!(name(KeyCode::Home), None);
+        // Modifiers don't change the name
+        assert_eq!(key_name(&KeyEvent::new(KeyCode::Up, KeyModifiers::SHIFT)),
+                   Some("Up".to_string()));
+    }
+
+    #[test]
+    fn test_default_bindings_keep_the_usual_keys() {
+        let key_bindings = default_key_bindings();
+        assert_eq!(key_bindings.get("q"), Some(&Action::Quit));
+        assert_eq!(key_bindings.get("Esc"), Some(&Action::Quit));
+        assert_eq!(key_bindings.get("5"), Some(&Action::FocusSequencer));
+        assert_eq!(key_bindings.get("Space"), Some(&Action::Activate));
+        assert_eq!(key_bindings.get("h"), None);
+    }
+
+    #[test]
+    fn test_navigation_key_keeps_modifiers() {
+        let key = navigation_key(Action::NavigateLeft, KeyModifiers::SHIFT).unwrap();
+        assert_eq!(key.code, KeyCode::Left);
+        assert_eq!(key.modifiers, KeyModifiers::SHIFT);
+        assert!(navigation_key(Action::Quit, KeyModifiers::NONE).is_none());
+    }
+}
@@ -0,0 +1,347 @@
+use crate::tui::audio_bridge::ParameterUpdate;
+use crate::tui::audio_engine::{AudioState, MAX_SWING};
+use crate::tui::TuiError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+
+const MIDI_CLIENT_NAME: &str = "rosco";
+const MIDI_CONNECTION_NAME: &str = "rosco-input";
+const NOTE_OFF_STATUS: u8 = 0x80;
+const NOTE_ON_STATUS: u8 = 0x90;
+const CONTROL_CHANGE_STATUS: u8 = 0xb0;
+const MAX_CC_VALUE: f32 = 127.0;
+// The same ranges as the synth panel's sliders
+const MIN_FREQUENCY_HZ: f32 = 20.0;
+const MAX_FREQUENCY_HZ: f32 = 20000.0;
+const CLOCK_STATUS: u8 = 0xf8;
+const START_STATUS: u8 = 0xfa;
+const CONTINUE_STATUS: u8 = 0xfb;
+const STOP_STATUS: u8 = 0xfc;
+/// MIDI clock sends 24 pulses per quarter note, and the sequencer plays one step per beat
+pub const MIDI_CLOCKS_PER_STEP: u32 = 24;
+
+/// Frequency of a MIDI note number in equal temperament, with note 69 = A4 = 440 Hz
+pub fn midi_note_to_frequency(note: u8) -> f32 {
+    440.0 * 2.0_f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+/// The update for a raw MIDI message, on any channel. A note on with velocity 0 is a note off, as
+/// the MIDI standard allows. Other messages are ignored.
+pub fn parse_midi_message(message: &[u8]) -> Option<ParameterUpdate> {
+    let (&status, data) = message.split_first()?;
+    match (status & 0xf0, data) {
+        (NOTE_ON_STATUS, &[note, velocity, ..]) if velocity > 0 => {
+            Some(ParameterUpdate::MidiNoteOn { note: note & 0x7f, velocity: velocity & 0x7f })
+        }
+        (NOTE_ON_STATUS, &[note, _, ..]) | (NOTE_OFF_STATUS, &[note, _, ..]) => {
+            Some(ParameterUpdate::MidiNoteOff { note: note & 0x7f })
+        }
+        _ => None,
+    }
+}
+
+/// A parameter a MIDI controller knob or fader can drive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParamTarget {
+    OscillatorFrequency,
+    OscillatorVolume,
+    FilterCutoff,
+    FilterResonance,
+    MasterVolume,
+    Swing,
+}
+
+impl ParamTarget {
+    /// The parameter value for a CC value from 0 to 127. Frequencies are scaled logarithmically so
+    /// each octave gets the same share of the controller's travel, everything else linearly.
+    pub fn scale(&self, cc_value: u8) -> f32 {
+        let position = cc_value.min(127) as f32 / MAX_CC_VALUE;
+        match self {
+            ParamTarget::OscillatorFrequency | ParamTarget::FilterCutoff => {
+                MIN_FREQUENCY_HZ * (MAX_FREQUENCY_HZ / MIN_FREQUENCY_HZ).powf(position)
+            }
+            ParamTarget::OscillatorVolume | ParamTarget::FilterResonance |
+            ParamTarget::MasterVolume => position,
+            ParamTarget::Swing => position * MAX_SWING,
+        }
+    }
+
+    pub fn update(&self, cc_value: u8) -> ParameterUpdate {
+        let value = self.scale(cc_value);
+        match self {
+            ParamTarget::OscillatorFrequency => ParameterUpdate::OscillatorFrequency(value),
+            ParamTarget::OscillatorVolume => ParameterUpdate::OscillatorVolume(value),
+            ParamTarget::FilterCutoff => ParameterUpdate::FilterCutoff(value),
+            ParamTarget::FilterResonance => ParameterUpdate::FilterResonance(value),
+            ParamTarget::MasterVolume => ParameterUpdate::MasterVolume(value),
+            ParamTarget::Swing => ParameterUpdate::Swing(value),
+        }
+    }
+}
+
+/// Which parameter each MIDI controller number drives
+pub type CcMap = HashMap<u8, ParamTarget>;
+
+/// The General MIDI volume, brightness and resonance controllers
+pub fn default_cc_map() -> CcMap {
+    HashMap::from([
+        (7, ParamTarget::MasterVolume),
+        (71, ParamTarget::FilterResonance),
+        (74, ParamTarget::FilterCutoff),
+    ])
+}
+
+/// The update for a control change message on any channel, if its controller is mapped
+pub fn parse_control_change(message: &[u8], cc_map: &CcMap) -> Option<ParameterUpdate> {
+    match message {
+        &[status, controller, value, ..] if status & 0xf0 == CONTROL_CHANGE_STATUS => {
+            cc_map.get(&controller).map(|target| target.update(value))
+        }
+        _ => None,
+    }
+}
+
+/// MIDI realtime messages that drive the transport from external gear
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiRealtime {
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+pub fn parse_midi_realtime(message: &[u8]) -> Option<MidiRealtime> {
+    match *message.first()? {
+        CLOCK_STATUS => Some(MidiRealtime::Clock),
+        START_STATUS => Some(MidiRealtime::Start),
+        CONTINUE_STATUS => Some(MidiRealtime::Continue),
+        STOP_STATUS => Some(MidiRealtime::Stop),
+        _ => None,
+    }
+}
+
+/// Counts MIDI clock pulses into sequencer steps
+#[derive(Debug, Default)]
+pub struct MidiClockCounter {
+    pulses: u32,
+}
+
+impl MidiClockCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The update for a realtime message, if any. Start plays the first step straight away, and
+    /// each MIDI_CLOCKS_PER_STEP clocks after it advance one step.
+    pub fn handle(&mut self, message: MidiRealtime) -> Option<ParameterUpdate> {
+        match message {
+            MidiRealtime::Start => {
+                self.pulses = 0;
+                Some(ParameterUpdate::MidiStart)
+            }
+            MidiRealtime::Continue => Some(ParameterUpdate::MidiContinue),
+            MidiRealtime::Stop => Some(ParameterUpdate::MidiStop),
+            MidiRealtime::Clock => {
+                self.pulses += 1;
+                if self.pulses == MIDI_CLOCKS_PER_STEP {
+                    self.pulses = 0;
+                    Some(ParameterUpdate::MidiClockStep)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Names of the MIDI input ports, for `MidiInput::connect`
+pub fn list_midi_input_ports() -> Vec<String> {
+    match midir::MidiInput::new(MIDI_CLIENT_NAME) {
+        Ok(midi_in) => midi_in.ports().iter()
+            .filter_map(|port| midi_in.port_name(port).ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Plays notes from a MIDI keyboard, follows MIDI clock and takes controller changes. Notes and
+/// clock go straight from the MIDI thread to the audio state. Controller changes are queued for
+/// the UI to apply, so its controls show the new values. The connection closes when this is
+/// dropped.
+pub struct MidiInput {
+    port_name: String,
+    _connection: midir::MidiInputConnection<MidiClockCounter>,
+    control_changes: Receiver<ParameterUpdate>,
+}
+
+impl MidiInput {
+    /// Listen on the port with this name, as returned by `list_midi_input_ports`, or on the first
+    /// port if there is no name
+    pub fn connect(port_name: Option<&str>, state: Arc<AudioState>, cc_map: CcMap)
+                   -> Result<Self, TuiError> {
+        let midi_in = midir::MidiInput::new(MIDI_CLIENT_NAME)
+            .map_err(|e| TuiError::Audio(format!("Failed to open MIDI input: {}", e)))?;
+        let port = midi_in.ports().into_iter()
+            .find(|port| match port_name {
+                Some(port_name) => midi_in.port_name(port)
+                    .map(|name| name == port_name)
+                    .unwrap_or(false),
+                None => true,
+            })
+            .ok_or_else(|| match port_name {
+                Some(port_name) => TuiError::Audio(format!("No MIDI input port named {}",
+                                                           port_name)),
+                None => TuiError::Audio("No MIDI input ports available".to_string()),
+            })?;
+        let connected_port_name = midi_in.port_name(&port)
+            .map_err(|e| TuiError::Audio(format!("Failed to read MIDI port name: {}", e)))?;
+
+        let (control_change_sender, control_changes) = channel();
+        let connection = midi_in.connect(
+            &port,
+            MIDI_CONNECTION_NAME,
+            move |_timestamp, message, clock_counter| {
+                if let Some(update) = parse_control_change(message, &cc_map) {
+                    // Only fails once the MidiInput, and so this connection, is being dropped
+                    let _ = control_change_sender.send(update);
+                    return;
+                }
+                let update = match parse_midi_realtime(message) {
+                    Some(realtime) => clock_counter.handle(realtime),
+                    None => parse_midi_message(message),
+                };
+                if let Some(update) = update {
+                    state.handle_parameter_update(&update);
+                }
+            },
+            MidiClockCounter::new(),
+        ).map_err(|e| TuiError::Audio(format!("Failed to connect MIDI input: {}", e)))?;
+
+        Ok(Self {
+            port_name: connected_port_name,
+            _connection: connection,
+            control_changes,
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Updates from controller changes received since the last call
+    pub fn receive_control_changes(&self) -> Vec<ParameterUpdate> {
+        self.control_changes.try_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod test_midi_input {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::note::constants::PITCH_TO_FREQ_HZ;
+    use crate::tui::audio_bridge::ParameterUpdate;
+    use crate::tui::audio_engine::MAX_SWING;
+    use crate::tui::midi_input::{default_cc_map, midi_note_to_frequency, parse_control_change,
+                                 parse_midi_message, parse_midi_realtime, MidiClockCounter,
+                                 MidiRealtime, ParamTarget, MIDI_CLOCKS_PER_STEP};
+
+    #[test]
+    fn test_midi_note_to_frequency() {
+        assert_float_eq(midi_note_to_frequency(69), 440.0);
+        assert_float_eq(midi_note_to_frequency(57), 220.0);
+        assert_float_eq(midi_note_to_frequency(81), 880.0);
+        assert_float_eq(midi_note_to_frequency(60), PITCH_TO_FREQ_HZ[60] as f32);
+    }
+
+    #[test]
+    fn test_parse_note_messages() {
+        assert!(matches!(parse_midi_message(&[0x90, 60, 100]),
+                         Some(ParameterUpdate::MidiNoteOn { note: 60, velocity: 100 })));
+        // any channel
+        assert!(matches!(parse_midi_message(&[0x9f, 61, 1]),
+                         Some(ParameterUpdate::MidiNoteOn { note: 61, velocity: 1 })));
+        assert!(matches!(parse_midi_message(&[0x80, 60, 64]),
+                         Some(ParameterUpdate::MidiNoteOff { note: 60 })));
+        assert!(matches!(parse_midi_message(&[0x90, 60, 0]),
+                         Some(ParameterUpdate::MidiNoteOff { note: 60 })));
+    }
+
+    #[test]
+    fn test_parse_ignores_other_messages() {
+        // control change, truncated note on, empty
+        assert!(parse_midi_message(&[0xb0, 7, 100]).is_none());
+        assert!(parse_midi_message(&[0x90, 60]).is_none());
+        assert!(parse_midi_message(&[]).is_none());
+    }
+
+    #[test]
+    fn test_parse_realtime_messages() {
+        assert_eq!(parse_midi_realtime(&[0xf8]), Some(MidiRealtime::Clock));
+        assert_eq!(parse_midi_realtime(&[0xfa]), Some(MidiRealtime::Start));
+        assert_eq!(parse_midi_realtime(&[0xfb]), Some(MidiRealtime::Continue));
+        assert_eq!(parse_midi_realtime(&[0xfc]), Some(MidiRealtime::Stop));
+        assert_eq!(parse_midi_realtime(&[0x90, 60, 100]), None);
+    }
+
+    #[test]
+    fn test_24_clocks_advance_one_step() {
+        let mut counter = MidiClockCounter::new();
+        assert!(matches!(counter.handle(MidiRealtime::Start), Some(ParameterUpdate::MidiStart)));
+
+        let steps: Vec<u32> = (1..=3 * MIDI_CLOCKS_PER_STEP)
+            .filter(|_| matches!(counter.handle(MidiRealtime::Clock),
+                                 Some(ParameterUpdate::MidiClockStep)))
+            .collect();
+        assert_eq!(steps, vec![24, 48, 72]);
+    }
+
+    #[test]
+    fn test_start_resets_the_clock_count() {
+        let mut counter = MidiClockCounter::new();
+        for _ in 0..MIDI_CLOCKS_PER_STEP - 1 {
+            assert!(counter.handle(MidiRealtime::Clock).is_none());
+        }
+        counter.handle(MidiRealtime::Start);
+        assert!(counter.handle(MidiRealtime::Clock).is_none());
+
+        // continue keeps the count
+        for _ in 0..MIDI_CLOCKS_PER_STEP - 2 {
+            counter.handle(MidiRealtime::Clock);
+        }
+        counter.handle(MidiRealtime::Continue);
+        assert!(matches!(counter.handle(MidiRealtime::Clock),
+                         Some(ParameterUpdate::MidiClockStep)));
+    }
+
+    #[test]
+    fn test_cc_scaling() {
+        // linear targets
+        assert_float_eq(ParamTarget::MasterVolume.scale(0), 0.0);
+        assert_float_eq(ParamTarget::MasterVolume.scale(127), 1.0);
+        assert!((ParamTarget::MasterVolume.scale(64) - 0.504).abs() < 1e-3);
+        assert_float_eq(ParamTarget::Swing.scale(127), MAX_SWING);
+
+        // logarithmic targets put 200 Hz and 2 kHz a third and two thirds of the way along
+        assert_float_eq(ParamTarget::FilterCutoff.scale(0), 20.0);
+        assert_float_eq(ParamTarget::FilterCutoff.scale(127), 20000.0);
+        let one_third: f32 = 127.0 / 3.0;
+        let cutoff = ParamTarget::FilterCutoff.scale(one_third.round() as u8);
+        assert!((cutoff / 200.0 - 1.0).abs() < 0.02);
+        let cutoff = ParamTarget::FilterCutoff.scale((2.0 * one_third).round() as u8);
+        assert!((cutoff / 2000.0 - 1.0).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_parse_control_change() {
+        let cc_map = default_cc_map();
+        assert!(matches!(parse_control_change(&[0xb0, 74, 127], &cc_map),
+                         Some(ParameterUpdate::FilterCutoff(cutoff)) if cutoff == 20000.0));
+        // any channel
+        assert!(matches!(parse_control_change(&[0xb3, 7, 0], &cc_map),
+                         Some(ParameterUpdate::MasterVolume(volume)) if volume == 0.0));
+        // unmapped controllers and other messages
+        assert!(parse_control_change(&[0xb0, 1, 64], &cc_map).is_none());
+        assert!(parse_control_change(&[0x90, 74, 64], &cc_map).is_none());
+    }
+}
@@ -1,10 +1,13 @@
 pub mod app;
 pub mod audio_bridge;
+pub mod audio_engine;
 pub mod config;
 pub mod events;
 pub mod ui;
 pub mod track_bridge;
 pub mod pattern_manager;
+pub mod midi_input;
+pub mod key_bindings;
 
 pub use app::RoscoTuiApp;
 pub use config::TuiConfig;
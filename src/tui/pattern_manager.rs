@@ -1,11 +1,15 @@
+use crate::sequence::drum_patterns::{DRUM_PATTERN_STEPS, HIHAT_PATTERN, KICK_PATTERN,
+                                     SNARE_PATTERN};
 use crate::tui::ui::widgets::StepCell;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 /// Pattern storage and management for the TUI sequencer
 #[derive(Debug, Clone)]
 pub struct PatternManager {
     patterns: HashMap<String, Pattern>,
+    chains: HashMap<String, PatternChain>,
     next_pattern_id: usize,
 }
 
@@ -19,9 +23,73 @@ pub struct Pattern {
     pub description: Option<String>,
 }
 
+/// An arrangement of patterns played one after another, each repeated some number of times
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PatternChain {
+    /// Pattern ID and how many times to play it
+    pub entries: Vec<(String, u8)>,
+}
+
+impl PatternChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a pattern to the end of the chain
+    pub fn push(&mut self, pattern_id: String, repeats: u8) {
+        self.entries.push((pattern_id, repeats));
+    }
+
+    /// Insert a pattern before the entry at index, or at the end if index is past it
+    pub fn insert(&mut self, index: usize, pattern_id: String, repeats: u8) {
+        let index = index.min(self.entries.len());
+        self.entries.insert(index, (pattern_id, repeats));
+    }
+
+    /// Remove the entry at index
+    pub fn remove(&mut self, index: usize) -> Option<(String, u8)> {
+        if index < self.entries.len() {
+            Some(self.entries.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Change how many times the entry at index plays
+    pub fn set_repeats(&mut self, index: usize, repeats: u8) -> bool {
+        if let Some(entry) = self.entries.get_mut(index) {
+            entry.1 = repeats;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Swap the entry at index with the one after it
+    pub fn move_later(&mut self, index: usize) -> bool {
+        if index + 1 < self.entries.len() {
+            self.entries.swap(index, index + 1);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PatternBank {
     pub patterns: HashMap<String, Pattern>,
+    // Banks saved before chaining have no chains
+    #[serde(default)]
+    pub chains: HashMap<String, PatternChain>,
     pub version: String,
     pub created: chrono::DateTime<chrono::Utc>,
 }
@@ -30,6 +98,7 @@ impl PatternManager {
     pub fn new() -> Self {
         Self {
             patterns: HashMap::new(),
+            chains: HashMap::new(),
             next_pattern_id: 1,
         }
     }
@@ -113,6 +182,7 @@ impl PatternManager {
     pub fn export_bank(&self) -> PatternBank {
         PatternBank {
             patterns: self.patterns.clone(),
+            chains: self.chains.clone(),
             version: "1.0".to_string(),
             created: chrono::Utc::now(),
         }
@@ -133,6 +203,11 @@ impl PatternManager {
             self.patterns.insert(final_id, pattern);
             imported_count += 1;
         }
+
+        // Existing chains win over imported ones with the same name
+        for (name, chain) in bank.chains {
+            self.chains.entry(name).or_insert(chain);
+        }
         
         Ok(imported_count)
     }
@@ -140,6 +215,7 @@ impl PatternManager {
     /// Clear all patterns
     pub fn clear_all(&mut self) {
         self.patterns.clear();
+        self.chains.clear();
         self.next_pattern_id = 1;
     }
     
@@ -154,6 +230,117 @@ impl PatternManager {
     }
 }
 
+/// Pattern chaining, the arrangement used by song mode
+impl PatternManager {
+    /// Create an empty chain, replacing any existing chain with the same name
+    pub fn create_chain(&mut self, name: String) -> &mut PatternChain {
+        self.chains.insert(name.clone(), PatternChain::new());
+        self.chains.get_mut(&name).unwrap()
+    }
+
+    pub fn get_chain(&self, name: &str) -> Option<&PatternChain> {
+        self.chains.get(name)
+    }
+
+    /// Get a chain for editing
+    pub fn get_chain_mut(&mut self, name: &str) -> Option<&mut PatternChain> {
+        self.chains.get_mut(name)
+    }
+
+    pub fn delete_chain(&mut self, name: &str) -> bool {
+        self.chains.remove(name).is_some()
+    }
+
+    /// List chain names in alphabetical order
+    pub fn list_chains(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self.chains.keys().collect();
+        names.sort();
+        names
+    }
+
+    /// Expand a chain into the steps of each pattern, repeated and concatenated in order
+    pub fn expand_chain(&self, chain: &PatternChain) -> Result<Vec<StepCell>, String> {
+        let mut steps = Vec::new();
+        for (pattern_id, repeats) in &chain.entries {
+            let pattern = self.patterns.get(pattern_id)
+                .ok_or_else(|| format!("Pattern chain refers to missing pattern {}", pattern_id))?;
+            for _ in 0..*repeats {
+                steps.extend(pattern.steps.iter().cloned());
+            }
+        }
+        Ok(steps)
+    }
+
+    /// Expand the chain with the given name
+    pub fn expand_chain_by_name(&self, name: &str) -> Result<Vec<StepCell>, String> {
+        let chain = self.chains.get(name)
+            .ok_or_else(|| format!("No pattern chain named {}", name))?;
+        self.expand_chain(chain)
+    }
+}
+
+/// Pattern persistence
+impl PatternManager {
+    /// Write all patterns to a JSON pattern bank file
+    pub fn save_to_file(&self, path: &str) -> Result<(), String> {
+        let path = PathBuf::from(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create pattern directory: {}", e))?;
+        }
+
+        let content = serde_json::to_string_pretty(&self.export_bank())
+            .map_err(|e| format!("Failed to serialize patterns: {}", e))?;
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Failed to write pattern file: {}", e))
+    }
+
+    /// Read a pattern bank file written by save_to_file
+    pub fn load_from_file(path: &str) -> Result<PatternManager, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read pattern file: {}", e))?;
+        let bank: PatternBank = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse pattern file: {}", e))?;
+
+        let mut manager = PatternManager::new();
+        manager.import_bank(bank)?;
+        // New patterns must not reuse the IDs of the loaded ones
+        manager.next_pattern_id = manager.patterns.keys()
+            .filter_map(|id| id.strip_prefix("pattern_")?.parse::<usize>().ok())
+            .max()
+            .map_or(1, |max_id| max_id + 1);
+        Ok(manager)
+    }
+
+    /// Where the TUI keeps its patterns between sessions
+    pub fn default_path() -> Option<PathBuf> {
+        let mut path = dirs::config_dir()?;
+        path.push("rosco");
+        path.push("patterns.json");
+        Some(path)
+    }
+
+    /// Load the patterns saved by a previous session, or start with the default patterns if there
+    /// are none or they can't be read
+    pub fn new_from_default_path() -> PatternManager {
+        let loaded = Self::default_path()
+            .filter(|path| path.exists())
+            .and_then(|path| Self::load_from_file(path.to_str()?).ok());
+        loaded.unwrap_or_else(|| {
+            let mut manager = PatternManager::new();
+            manager.init_with_defaults();
+            manager
+        })
+    }
+
+    pub fn save_to_default_path(&self) -> Result<(), String> {
+        let path = Self::default_path()
+            .ok_or_else(|| "Could not determine config directory".to_string())?;
+        self.save_to_file(path.to_str()
+            .ok_or_else(|| "Pattern file path is not valid UTF-8".to_string())?)
+    }
+}
+
 impl Default for PatternManager {
     fn default() -> Self {
         Self::new()
@@ -164,10 +351,10 @@ impl Default for PatternManager {
 impl PatternManager {
     /// Create a basic kick pattern
     pub fn create_kick_pattern(&mut self) -> String {
-        let mut steps = vec![StepCell::default(); 16];
+        let mut steps = vec![StepCell::default(); DRUM_PATTERN_STEPS];
         
         // Set kick hits on steps 1, 5, 9, 13 (classic four-on-the-floor)
-        for &step_idx in &[0, 4, 8, 12] {
+        for &step_idx in &KICK_PATTERN {
             steps[step_idx].enabled = true;
             steps[step_idx].velocity = 127;
         }
@@ -181,10 +368,10 @@ impl PatternManager {
     
     /// Create a basic snare pattern
     pub fn create_snare_pattern(&mut self) -> String {
-        let mut steps = vec![StepCell::default(); 16];
+        let mut steps = vec![StepCell::default(); DRUM_PATTERN_STEPS];
         
         // Set snare hits on steps 5, 13 (backbeat)
-        for &step_idx in &[4, 12] {
+        for &step_idx in &SNARE_PATTERN {
             steps[step_idx].enabled = true;
             steps[step_idx].velocity = 120;
         }
@@ -198,10 +385,10 @@ impl PatternManager {
     
     /// Create a basic hi-hat pattern
     pub fn create_hihat_pattern(&mut self) -> String {
-        let mut steps = vec![StepCell::default(); 16];
+        let mut steps = vec![StepCell::default(); DRUM_PATTERN_STEPS];
         
         // Set hi-hat hits on every other step
-        for step_idx in (1..16).step_by(2) {
+        for &step_idx in &HIHAT_PATTERN {
             steps[step_idx].enabled = true;
             steps[step_idx].velocity = 80;
         }
@@ -285,4 +472,89 @@ mod tests {
         assert_eq!(imported_count, manager1.count());
         assert_eq!(manager2.count(), manager1.count());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_pattern_file_round_trip() {
+        let mut manager = PatternManager::new();
+        manager.init_with_defaults();
+
+        let path = std::env::temp_dir()
+            .join(format!("rosco_test_patterns_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        manager.save_to_file(path).unwrap();
+        let loaded = PatternManager::load_from_file(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.count(), manager.count());
+        let names = |manager: &PatternManager| {
+            let mut names: Vec<String> = manager.list_patterns().iter()
+                .map(|pattern| pattern.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&loaded), names(&manager));
+
+        // patterns stored after loading get new IDs
+        let mut loaded = loaded;
+        let pattern_id = loaded.store_pattern("New".to_string(), vec![StepCell::default(); 16],
+                                              None);
+        assert!(!manager.has_pattern(&pattern_id));
+        assert_eq!(loaded.count(), manager.count() + 1);
+    }
+
+    #[test]
+    fn test_load_missing_pattern_file() {
+        assert!(PatternManager::load_from_file("/nonexistent/rosco_patterns.json").is_err());
+    }
+
+    #[test]
+    fn test_expand_chain() {
+        let mut manager = PatternManager::new();
+        let pattern_a_steps: Vec<StepCell> = (0..4)
+            .map(|i| StepCell { enabled: i == 0, ..StepCell::default() })
+            .collect();
+        let pattern_b_steps: Vec<StepCell> = (0..8)
+            .map(|i| StepCell { enabled: i == 7, ..StepCell::default() })
+            .collect();
+        let pattern_a = manager.store_pattern("A".to_string(), pattern_a_steps, None);
+        let pattern_b = manager.store_pattern("B".to_string(), pattern_b_steps, None);
+
+        let chain = manager.create_chain("Song".to_string());
+        chain.push(pattern_a, 2);
+        chain.push(pattern_b, 1);
+
+        let steps = manager.expand_chain_by_name("Song").unwrap();
+        assert_eq!(steps.len(), 4 + 4 + 8);
+        let enabled: Vec<bool> = steps.iter().map(|step| step.enabled).collect();
+        assert_eq!(enabled, vec![true, false, false, false, true, false, false, false,
+                                 false, false, false, false, false, false, false, true]);
+    }
+
+    #[test]
+    fn test_edit_chain() {
+        let mut manager = PatternManager::new();
+        manager.init_with_defaults();
+        let mut ids: Vec<String> = manager.list_patterns().iter().map(|p| p.id.clone()).collect();
+        ids.sort();
+
+        let chain = manager.create_chain("Song".to_string());
+        chain.push(ids[0].clone(), 1);
+        chain.push(ids[1].clone(), 1);
+        chain.insert(0, ids[2].clone(), 3);
+        assert!(chain.set_repeats(1, 0));
+        assert!(chain.move_later(1));
+        assert_eq!(chain.remove(0), Some((ids[2].clone(), 3)));
+        assert_eq!(chain.entries, vec![(ids[1].clone(), 1), (ids[0].clone(), 0)]);
+
+        // zero repeats skips the entry
+        let steps = manager.expand_chain_by_name("Song").unwrap();
+        assert_eq!(steps.len(), manager.get_pattern(&ids[1]).unwrap().length);
+
+        manager.get_chain_mut("Song").unwrap().push("pattern_9999".to_string(), 1);
+        assert!(manager.expand_chain_by_name("Song").is_err());
+        assert!(manager.expand_chain_by_name("Missing").is_err());
+        assert!(manager.delete_chain("Song"));
+        assert!(manager.list_chains().is_empty());
+    }
+}
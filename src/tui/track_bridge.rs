@@ -1,3 +1,4 @@
+use crate::sequence::arpeggiator::ArpMode;
 use crate::tui::ui::widgets::{TrackStrip, StepCell};
 
 /// Bridge between TUI sequencer tracks and Rosco Track system
@@ -15,7 +16,10 @@ pub struct TrackData {
     pub pan: f32,  // Single pan control (-1.0 to +1.0)
     pub mute: bool,
     pub solo: bool,
+    pub length: usize,
+    pub divisor: usize,
     pub steps: Vec<StepCell>,
+    pub arp_mode: Option<ArpMode>,
 }
 
 #[derive(Debug)]
@@ -39,7 +43,10 @@ impl TrackBridge {
                 pan: 0.0,
                 mute: false,
                 solo: false,
+                length: steps_per_track,
+                divisor: 1,
                 steps: vec![StepCell::default(); steps_per_track],
+                arp_mode: None,
             };
             track_data.push(track);
         }
@@ -55,7 +62,10 @@ impl TrackBridge {
                 self.track_data[i].pan = strip.pan;
                 self.track_data[i].mute = strip.mute;
                 self.track_data[i].solo = strip.solo;
+                self.track_data[i].length = strip.length;
+                self.track_data[i].divisor = strip.divisor;
                 self.track_data[i].steps = strip.steps.clone();
+                self.track_data[i].arp_mode = strip.arp_mode;
             }
         }
     }
@@ -68,7 +78,10 @@ impl TrackBridge {
                 track_strips[i].pan = track_data.pan;
                 track_strips[i].mute = track_data.mute;
                 track_strips[i].solo = track_data.solo;
+                track_strips[i].length = track_data.length;
+                track_strips[i].divisor = track_data.divisor;
                 track_strips[i].steps = track_data.steps.clone();
+                track_strips[i].arp_mode = track_data.arp_mode;
             }
         }
     }
@@ -149,11 +162,9 @@ impl TrackBridge {
                 }
                 2 => {
                     // Hi-hat pattern: every other step
-                    for step in (1..16).step_by(2) {
-                        if step < track_data.steps.len() {
-                            track_data.steps[step].enabled = true;
-                            track_data.steps[step].velocity = 80;
-                        }
+                    for step in (1..track_data.steps.len()).step_by(2) {
+                        track_data.steps[step].enabled = true;
+                        track_data.steps[step].velocity = 80;
                     }
                 }
                 _ => {
@@ -1,50 +1,233 @@
-use crate::tui::ui::widgets::{SequencerGrid, StepCell};
-use crate::tui::pattern_manager::PatternManager;
+use crate::note::scales::WesternPitch;
+use crate::tui::ui::widgets::{SequencerGrid, StepCell, TrackStrip, MAX_STEP_OCTAVE};
+use crate::tui::pattern_manager::{Pattern, PatternManager};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
+// Velocity change for each Shift+Up/Down on a step, sixteen presses across the whole range
+const VELOCITY_STEP: i8 = 8;
+// Most grid edits that can be undone, the oldest is dropped past this
+const MAX_UNDO_DEPTH: usize = 100;
+// Keys that play a chromatic octave from C in piano keys mode, white keys along the home row and
+// black keys on the row above, ending on the C an octave up
+const PIANO_KEYS: [char; 13] = ['a', 'w', 's', 'e', 'd', 'f', 't', 'g', 'y', 'h', 'u', 'j', 'k'];
+
+/// The pitch and octave a piano key plays, counting from C in `octave`. The octave is kept to
+/// MAX_STEP_OCTAVE, so in the top octave the last key plays that octave's C rather than the next.
+pub fn piano_key_pitch(key: char, octave: u8) -> Option<(WesternPitch, u8)> {
+    let semitone = PIANO_KEYS.iter().position(|piano_key| *piano_key == key)? as u8;
+    let octave = octave.min(MAX_STEP_OCTAVE);
+    let (pitch, key_octave) =
+        WesternPitch::from_midi_note(WesternPitch::C.to_midi_note(octave) + semitone);
+    Some((pitch, key_octave.min(MAX_STEP_OCTAVE)))
+}
+
 #[derive(Debug)]
 pub struct SequencerPanel {
     pub grid: SequencerGrid,
     clipboard: Option<Vec<StepCell>>,
+    // A single step, copied apart from the pattern clipboard
+    step_clipboard: Option<StepCell>,
     pattern_manager: PatternManager,
     show_pattern_browser: bool,
+    // Row selected in the pattern browser's newest first list
+    browser_selected: usize,
+    // While on, letter keys set and play the current step's pitch rather than their usual edits
+    piano_keys: bool,
+    // Grid before each edit, most recent last, and the edits undone since the last edit
+    undo_stack: Vec<GridSnapshot>,
+    redo_stack: Vec<GridSnapshot>,
+}
+
+// The parts of the grid that edits change, without the cursor or what is playing
+#[derive(Debug, Clone)]
+struct GridSnapshot {
+    tracks: [TrackStrip; 8],
+    steps_per_track: usize,
 }
 
 #[derive(Debug, Clone)]
 pub enum SequencerAction {
     StepToggled { track: u8, step: u8 },
     FrequencyChanged { track: u8, step: u8, frequency: crate::note::scales::WesternPitch },
+    OctaveChanged { track: u8, step: u8, octave: u8 },
+    ChordChanged { track: u8, step: u8 },
+    Transposed { semitones: i8, selection: bool },
     TrackVolumeChanged { track: u8, volume: f32 },
     TrackPanChanged { track: u8, pan: f32 },
     TrackMuteToggled { track: u8 },
     TrackSoloToggled { track: u8 },
+    TrackWaveformChanged { track: u8, waveform: crate::audio_gen::Waveform },
+    TrackArpChanged { track: u8, arp_mode: Option<crate::sequence::arpeggiator::ArpMode> },
     TrackCleared { track: u8 },
+    SequenceLengthChanged { steps: usize },
+    TrackLengthChanged { track: u8, length: usize },
+    TrackDivisorChanged { track: u8, divisor: usize },
+    TrackEuclideanFilled { track: u8, hits: usize },
+    TrackRotated { track: u8, amount: i8 },
+    StepProbabilityChanged { track: u8, step: u8, probability: u8 },
+    StepGateLengthChanged { track: u8, step: u8, gate_length: f32 },
+    StepVelocityChanged { track: u8, step: u8, velocity: u8 },
+    LoopChanged { loop_region: Option<(usize, usize)> },
+    ScaleChanged {
+        scale: Option<(crate::note::scales::WesternPitch, crate::note::scales::WesternScale)>
+    },
     PatternCopied,
     PatternPasted,
+    StepCopied { track: u8, step: u8 },
+    StepPasted { track: u8, step: u8 },
     PatternStored { pattern_id: String },
-    PatternLoaded { pattern_id: String },
+    // fitted_from is the pattern's own length, if it was cut or padded to the sequence's length
+    PatternLoaded { pattern_id: String, fitted_from: Option<usize> },
     PatternBrowserToggled,
     SelectionStarted,
     SelectionCleared,
+    PianoKeysToggled { enabled: bool },
+    PianoKeyPlayed { track: u8, step: u8, midi_note: u8 },
+    SelectionFilled { enabled: bool },
+    VelocityRamped { start: u8, end: u8 },
+    FrequencyRamped { start: u8, end: u8 },
+    SelectionDeleted,
+    Undone,
+    Redone,
+    NothingToUndo,
+    NothingToRedo,
+}
+
+impl SequencerAction {
+    /// Whether the action changed the grid, so undo can take it back
+    pub fn is_edit(&self) -> bool {
+        matches!(self,
+            SequencerAction::StepToggled { .. } |
+            SequencerAction::FrequencyChanged { .. } |
+            SequencerAction::OctaveChanged { .. } |
+            SequencerAction::ChordChanged { .. } |
+            SequencerAction::Transposed { .. } |
+            SequencerAction::TrackVolumeChanged { .. } |
+            SequencerAction::TrackPanChanged { .. } |
+            SequencerAction::TrackMuteToggled { .. } |
+            SequencerAction::TrackSoloToggled { .. } |
+            SequencerAction::TrackWaveformChanged { .. } |
+            SequencerAction::TrackArpChanged { .. } |
+            SequencerAction::TrackCleared { .. } |
+            SequencerAction::SequenceLengthChanged { .. } |
+            SequencerAction::TrackLengthChanged { .. } |
+            SequencerAction::TrackDivisorChanged { .. } |
+            SequencerAction::TrackEuclideanFilled { .. } |
+            SequencerAction::TrackRotated { .. } |
+            SequencerAction::StepProbabilityChanged { .. } |
+            SequencerAction::StepGateLengthChanged { .. } |
+            SequencerAction::StepVelocityChanged { .. } |
+            SequencerAction::PatternPasted |
+            SequencerAction::StepPasted { .. } |
+            SequencerAction::PatternLoaded { .. } |
+            SequencerAction::SelectionFilled { .. } |
+            SequencerAction::VelocityRamped { .. } |
+            SequencerAction::FrequencyRamped { .. } |
+            SequencerAction::SelectionDeleted |
+            SequencerAction::PianoKeyPlayed { .. })
+    }
 }
 
 impl SequencerPanel {
     pub fn new() -> Self {
-        let mut pattern_manager = PatternManager::new();
-        pattern_manager.init_with_defaults();
-        
+        Self::with_pattern_manager(PatternManager::new_from_default_path())
+    }
+
+    /// A panel using the given patterns rather than the ones saved by the last session
+    pub fn with_pattern_manager(pattern_manager: PatternManager) -> Self {
         Self {
             grid: SequencerGrid::new(16), // 16 steps per track
             clipboard: None,
+            step_clipboard: None,
             pattern_manager,
             show_pattern_browser: false,
+            browser_selected: 0,
+            piano_keys: false,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
         }
     }
     
     pub fn handle_key_event(&mut self, key: KeyEvent) -> Vec<SequencerAction> {
+        // The pattern browser takes every key while it is open
+        if self.show_pattern_browser {
+            return self.handle_pattern_browser_key(key);
+        }
+
+        // Undo (Ctrl+Z) and redo (Ctrl+Y or Ctrl+Shift+Z)
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            match key.code {
+                KeyCode::Char('z') if !key.modifiers.contains(KeyModifiers::SHIFT) => {
+                    return vec![self.undo()];
+                }
+                KeyCode::Char('y') | KeyCode::Char('Z') | KeyCode::Char('z') => {
+                    return vec![self.redo()];
+                }
+                _ => {}
+            }
+        }
+
+        // Moving around the grid never edits it, so it's done before the grid is copied for undo
+        if self.handle_navigation_key(key) {
+            if self.grid.selection.is_some() {
+                self.grid.update_selection();
+            }
+            return Vec::new();
+        }
+
+        let before_edit = self.snapshot();
         let mut actions = Vec::new();
         
         match key.code {
+            // Piano keys mode on and off
+            KeyCode::F(3) => {
+                self.piano_keys = !self.piano_keys;
+                actions.push(SequencerAction::PianoKeysToggled { enabled: self.piano_keys });
+            }
+
+            // In piano keys mode a letter sets the current step's pitch, in the step's octave
+            KeyCode::Char(c) if self.is_piano_key(key) => {
+                let (track, step) = (self.grid.cursor.track, self.grid.cursor.step);
+                let octave = self.grid.tracks[track as usize].steps[step as usize].octave;
+                if let Some((pitch, octave)) = piano_key_pitch(c, octave) {
+                    self.grid.set_current_step_pitch(pitch, octave);
+                    actions.push(SequencerAction::PianoKeyPlayed {
+                        track,
+                        step,
+                        midi_note: self.grid.tracks[track as usize].steps[step as usize]
+                            .midi_notes()[0],
+                    });
+                }
+            }
+
+            // Octave of the frequency cell being edited
+            KeyCode::Up | KeyCode::Down if key.modifiers.contains(KeyModifiers::SHIFT) &&
+                matches!(self.grid.cursor.focus_area,
+                         crate::tui::ui::widgets::CursorFocus::Frequency |
+                         crate::tui::ui::widgets::CursorFocus::FrequencyDropdown) => {
+                self.grid.adjust_current_octave(if key.code == KeyCode::Up { 1 } else { -1 });
+                let track = self.grid.cursor.track;
+                let step = self.grid.cursor.step;
+                actions.push(SequencerAction::OctaveChanged {
+                    track,
+                    step,
+                    octave: self.grid.tracks[track as usize].steps[step as usize].octave,
+                });
+            }
+
+            // Velocity of the step under the cursor
+            KeyCode::Up | KeyCode::Down if key.modifiers.contains(KeyModifiers::SHIFT) &&
+                self.grid.cursor.focus_area == crate::tui::ui::widgets::CursorFocus::Steps => {
+                let delta = if key.code == KeyCode::Up { VELOCITY_STEP } else { -VELOCITY_STEP };
+                self.grid.adjust_current_velocity(delta);
+                let (track, step) = (self.grid.cursor.track, self.grid.cursor.step);
+                actions.push(SequencerAction::StepVelocityChanged {
+                    track,
+                    step,
+                    velocity: self.grid.tracks[track as usize].steps[step as usize].velocity,
+                });
+            }
+
             // Navigation
             KeyCode::Up => {
                 if self.grid.cursor.focus_area == crate::tui::ui::widgets::CursorFocus::FrequencyDropdown {
@@ -73,11 +256,6 @@ impl SequencerPanel {
                 }
             }
             
-            // Focus switching
-            KeyCode::Tab => {
-                self.grid.switch_focus();
-            }
-            
             // Step editing and dropdown control
             KeyCode::Enter | KeyCode::Char(' ') => {
                 match self.grid.cursor.focus_area {
@@ -110,6 +288,17 @@ impl SequencerPanel {
                 // Always return without consuming Esc, let app handle global quit
             }
             
+            // Rotate the current track's steps a step earlier or later, wrapping around
+            KeyCode::Left | KeyCode::Right if key.modifiers.contains(KeyModifiers::SHIFT) &&
+                matches!(self.grid.cursor.focus_area,
+                         crate::tui::ui::widgets::CursorFocus::Steps |
+                         crate::tui::ui::widgets::CursorFocus::Frequency) => {
+                let amount = if key.code == KeyCode::Right { 1 } else { -1 };
+                let track = self.grid.cursor.track;
+                self.grid.rotate_track(track as usize, amount);
+                actions.push(SequencerAction::TrackRotated { track, amount });
+            }
+
             // Navigation - Standard grid navigation
             KeyCode::Left => {
                 self.grid.move_cursor(0, -1);
@@ -132,6 +321,183 @@ impl SequencerPanel {
                 }
             }
             
+            // Track waveform (w next, W previous)
+            KeyCode::Char('w') => {
+                self.cycle_track_waveform(1, &mut actions);
+            }
+            KeyCode::Char('W') => {
+                self.cycle_track_waveform(-1, &mut actions);
+            }
+
+            // Sequence length ([ shorter, ] longer)
+            KeyCode::Char('[') | KeyCode::Char(']') => {
+                let direction = if key.code == KeyCode::Char(']') { 1 } else { -1 };
+                self.grid.cycle_steps_per_track(direction);
+                actions.push(SequencerAction::SequenceLengthChanged {
+                    steps: self.grid.steps_per_track,
+                });
+            }
+
+            // Track length (< shorter, > longer) and divisor (( faster, ) slower)
+            KeyCode::Char('<') | KeyCode::Char('>') => {
+                let delta = if key.code == KeyCode::Char('>') { 1 } else { -1 };
+                self.grid.adjust_current_track_length(delta);
+                let track = self.grid.cursor.track;
+                actions.push(SequencerAction::TrackLengthChanged {
+                    track,
+                    length: self.grid.tracks[track as usize].length,
+                });
+            }
+            KeyCode::Char('(') | KeyCode::Char(')') => {
+                let delta = if key.code == KeyCode::Char(')') { 1 } else { -1 };
+                self.grid.adjust_current_track_divisor(delta);
+                let track = self.grid.cursor.track;
+                actions.push(SequencerAction::TrackDivisorChanged {
+                    track,
+                    divisor: self.grid.tracks[track as usize].divisor,
+                });
+            }
+
+            // Scale that frequency editing keeps to, next (m) or previous (M), and its root up
+            // (n) or down (N) a semitone
+            KeyCode::Char('m') | KeyCode::Char('M') => {
+                self.grid.cycle_scale(if key.code == KeyCode::Char('m') { 1 } else { -1 });
+                actions.push(SequencerAction::ScaleChanged { scale: self.grid.scale });
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') => {
+                self.grid.cycle_scale_root(if key.code == KeyCode::Char('n') { 1 } else { -1 });
+                actions.push(SequencerAction::ScaleChanged { scale: self.grid.scale });
+            }
+
+            // Transpose the selection, or the current track without one, up (t) or down (T) a
+            // semitone, or up (o) or down (O) an octave
+            KeyCode::Char('t') | KeyCode::Char('T') | KeyCode::Char('o') | KeyCode::Char('O') => {
+                let semitones = match key.code {
+                    KeyCode::Char('t') => 1,
+                    KeyCode::Char('T') => -1,
+                    KeyCode::Char('o') => 12,
+                    _ => -12,
+                };
+                self.grid.transpose_selection(semitones);
+                actions.push(SequencerAction::Transposed {
+                    semitones,
+                    selection: self.grid.selection.is_some(),
+                });
+            }
+
+            // Stack a chord pitch on the current step (p) or remove the top one (P)
+            KeyCode::Char('p') | KeyCode::Char('P')
+                if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                if key.code == KeyCode::Char('p') {
+                    self.grid.add_current_chord_pitch();
+                } else {
+                    self.grid.remove_current_chord_pitch();
+                }
+                actions.push(SequencerAction::ChordChanged {
+                    track: self.grid.cursor.track,
+                    step: self.grid.cursor.step,
+                });
+            }
+
+            // Euclidean fill of the current track with one more (k) or one fewer (K) hit
+            KeyCode::Char('k') | KeyCode::Char('K') => {
+                let track_idx = self.grid.cursor.track;
+                let track = &mut self.grid.tracks[track_idx as usize];
+                let length = track.length.min(track.steps.len());
+                let hits = track.steps.iter().take(length).filter(|step| step.enabled).count();
+                let hits = if key.code == KeyCode::Char('k') {
+                    (hits + 1) % (length + 1)
+                } else {
+                    (hits + length) % (length + 1)
+                };
+                track.fill_euclidean(hits, 0);
+                actions.push(SequencerAction::TrackEuclideanFilled {
+                    track: track_idx,
+                    hits,
+                });
+            }
+
+            // Step probability (, lower, . higher)
+            KeyCode::Char(',') | KeyCode::Char('.') => {
+                if matches!(self.grid.cursor.focus_area,
+                            crate::tui::ui::widgets::CursorFocus::Steps |
+                            crate::tui::ui::widgets::CursorFocus::Frequency) {
+                    let delta = if key.code == KeyCode::Char('.') { 10 } else { -10 };
+                    self.grid.adjust_current_probability(delta);
+                    let (track, step) = (self.grid.cursor.track, self.grid.cursor.step);
+                    actions.push(SequencerAction::StepProbabilityChanged {
+                        track,
+                        step,
+                        probability: self.grid.tracks[track as usize].steps[step as usize]
+                            .probability,
+                    });
+                }
+            }
+
+            // Step gate length ({ shorter, } longer)
+            KeyCode::Char('{') | KeyCode::Char('}') => {
+                if matches!(self.grid.cursor.focus_area,
+                            crate::tui::ui::widgets::CursorFocus::Steps |
+                            crate::tui::ui::widgets::CursorFocus::Frequency) {
+                    let delta = if key.code == KeyCode::Char('}') { 0.1 } else { -0.1 };
+                    self.grid.adjust_current_gate_length(delta);
+                    let (track, step) = (self.grid.cursor.track, self.grid.cursor.step);
+                    actions.push(SequencerAction::StepGateLengthChanged {
+                        track,
+                        step,
+                        gate_length: self.grid.tracks[track as usize].steps[step as usize]
+                            .gate_length,
+                    });
+                }
+            }
+
+            // Ramp the selection's velocities (/) or pitches (?) from its first step to its last
+            KeyCode::Char('/') | KeyCode::Char('?') => {
+                if let Some((start_track, _, start_step, end_step)) =
+                    self.grid.get_selection_bounds() {
+                    let steps = &self.grid.tracks[start_track].steps;
+                    if key.code == KeyCode::Char('/') {
+                        let (start, end) = (steps[start_step].velocity, steps[end_step].velocity);
+                        self.grid.ramp_velocity(start, end);
+                        actions.push(SequencerAction::VelocityRamped { start, end });
+                    } else {
+                        let start = steps[start_step].midi_notes()[0];
+                        let end = steps[end_step].midi_notes()[0];
+                        self.grid.ramp_frequency(start, end);
+                        actions.push(SequencerAction::FrequencyRamped { start, end });
+                    }
+                }
+            }
+
+            // Loop playback from (i) or to (l) the cursor's step, or play the whole sequence (L)
+            KeyCode::Char('i') | KeyCode::Char('l') | KeyCode::Char('L')
+                if !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                match key.code {
+                    KeyCode::Char('i') => self.grid.set_loop_start(),
+                    KeyCode::Char('l') => self.grid.set_loop_end(),
+                    _ => self.grid.clear_loop(),
+                }
+                actions.push(SequencerAction::LoopChanged { loop_region: self.grid.loop_region });
+            }
+
+            // Copy the step under the cursor (Alt+C) and paste it over another (Alt+V)
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::ALT) => {
+                self.step_clipboard = Some(self.grid.copy_step());
+                actions.push(SequencerAction::StepCopied {
+                    track: self.grid.cursor.track,
+                    step: self.grid.cursor.step,
+                });
+            }
+            KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::ALT) => {
+                if let Some(step) = &self.step_clipboard {
+                    self.grid.paste_step(step);
+                    actions.push(SequencerAction::StepPasted {
+                        track: self.grid.cursor.track,
+                        step: self.grid.cursor.step,
+                    });
+                }
+            }
+
             // Quick track selection (A-H for tracks 1-8)
             KeyCode::Char(c) if c >= 'a' && c <= 'h' => {
                 let track_idx = (c as u8 - b'a').min(7);
@@ -197,7 +563,8 @@ impl SequencerPanel {
             
             // Pattern management
             KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.show_pattern_browser = !self.show_pattern_browser;
+                self.show_pattern_browser = true;
+                self.browser_selected = 0;
                 actions.push(SequencerAction::PatternBrowserToggled);
             }
             KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::ALT) => {
@@ -213,19 +580,20 @@ impl SequencerPanel {
             }
             KeyCode::Char('l') if key.modifiers.contains(KeyModifiers::ALT) => {
                 // Load last pattern to current track
-                if let Some(pattern) = self.pattern_manager.get_recent_patterns(1).first() {
-                    let track = &mut self.grid.tracks[self.grid.cursor.track as usize];
-                    track.steps = pattern.steps.clone();
-                    actions.push(SequencerAction::PatternLoaded { 
-                        pattern_id: pattern.id.clone() 
-                    });
+                let pattern_id = self.pattern_manager.get_recent_patterns(1).first()
+                    .map(|pattern| pattern.id.clone());
+                if let Some(pattern_id) = pattern_id {
+                    if let Some(pattern_length) =
+                        self.load_pattern_to_track(&pattern_id, self.grid.cursor.track as usize) {
+                        actions.push(self.pattern_loaded(pattern_id, pattern_length));
+                    }
                 }
             }
             
             KeyCode::Delete => {
                 if self.grid.selection.is_some() {
                     self.grid.delete_selected();
-                    actions.push(SequencerAction::SelectionCleared);
+                    actions.push(SequencerAction::SelectionDeleted);
                 } else if self.grid.cursor.focus_area == crate::tui::ui::widgets::CursorFocus::Steps {
                     let track = &mut self.grid.tracks[self.grid.cursor.track as usize];
                     let step = &mut track.steps[self.grid.cursor.step as usize];
@@ -251,12 +619,14 @@ impl SequencerPanel {
                 // Fill selection with enabled steps
                 if self.grid.selection.is_some() {
                     self.grid.fill_selected(true);
+                    actions.push(SequencerAction::SelectionFilled { enabled: true });
                 }
             }
             KeyCode::Char('e') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 // Empty selection (disable all steps in selection)
                 if self.grid.selection.is_some() {
                     self.grid.fill_selected(false);
+                    actions.push(SequencerAction::SelectionFilled { enabled: false });
                 }
             }
             
@@ -267,9 +637,107 @@ impl SequencerPanel {
         if self.grid.selection.is_some() {
             self.grid.update_selection();
         }
+
+        if actions.iter().any(SequencerAction::is_edit) {
+            self.push_undo(before_edit);
+        }
         
         actions
     }
+
+    // Move the cursor or its focus for a key that only moves it: Tab, and the arrows without
+    // Shift, except Up and Down choosing a pitch in the frequency dropdown. Returns whether the
+    // key was one of them.
+    fn handle_navigation_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::SHIFT) {
+            return false;
+        }
+        let in_dropdown = self.grid.cursor.focus_area ==
+            crate::tui::ui::widgets::CursorFocus::FrequencyDropdown;
+        match key.code {
+            KeyCode::Tab => self.grid.switch_focus(),
+            KeyCode::Up if !in_dropdown => self.grid.move_cursor(-1, 0),
+            KeyCode::Down if !in_dropdown => self.grid.move_cursor(1, 0),
+            KeyCode::Left => self.grid.move_cursor(0, -1),
+            KeyCode::Right => self.grid.move_cursor(0, 1),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn is_piano_keys_enabled(&self) -> bool {
+        self.piano_keys
+    }
+
+    /// Whether a key plays a pitch into the current step, which takes piano keys mode and a step
+    /// under the cursor
+    pub fn is_piano_key(&self, key: KeyEvent) -> bool {
+        let KeyCode::Char(c) = key.code else {
+            return false;
+        };
+        self.piano_keys &&
+            !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) &&
+            matches!(self.grid.cursor.focus_area,
+                     crate::tui::ui::widgets::CursorFocus::Steps |
+                     crate::tui::ui::widgets::CursorFocus::Frequency) &&
+            PIANO_KEYS.contains(&c)
+    }
+
+    fn snapshot(&self) -> GridSnapshot {
+        GridSnapshot {
+            tracks: self.grid.tracks.clone(),
+            steps_per_track: self.grid.steps_per_track,
+        }
+    }
+
+    /// Remember the grid as it is before an edit made from outside the panel, such as dragging a
+    /// track's volume with the mouse, so the edit can be undone
+    pub fn remember_for_undo(&mut self) {
+        self.push_undo(self.snapshot());
+    }
+
+    // Remember the grid before an edit. A new edit starts a new history, so it can't be redone
+    // past.
+    fn push_undo(&mut self, snapshot: GridSnapshot) {
+        if self.undo_stack.len() == MAX_UNDO_DEPTH {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(snapshot);
+        self.redo_stack.clear();
+    }
+
+    pub fn undo(&mut self) -> SequencerAction {
+        let Some(snapshot) = self.undo_stack.pop() else {
+            return SequencerAction::NothingToUndo;
+        };
+        self.redo_stack.push(self.snapshot());
+        self.restore(snapshot);
+        SequencerAction::Undone
+    }
+
+    pub fn redo(&mut self) -> SequencerAction {
+        let Some(snapshot) = self.redo_stack.pop() else {
+            return SequencerAction::NothingToRedo;
+        };
+        self.undo_stack.push(self.snapshot());
+        self.restore(snapshot);
+        SequencerAction::Redone
+    }
+
+    // Put the grid back as it was, leaving each track's selected control where it is
+    fn restore(&mut self, snapshot: GridSnapshot) {
+        for (track, restored_track) in self.grid.tracks.iter_mut().zip(snapshot.tracks) {
+            let selected_control = track.selected_control.clone();
+            *track = restored_track;
+            track.selected_control = selected_control;
+        }
+        self.grid.steps_per_track = snapshot.steps_per_track;
+        let last_step = snapshot.steps_per_track.saturating_sub(1) as u8;
+        self.grid.cursor.step = self.grid.cursor.step.min(last_step);
+        if self.grid.selection.is_some() {
+            self.grid.clear_selection();
+        }
+    }
     
     fn handle_track_control_action(&mut self, actions: &mut Vec<SequencerAction>) {
         let track = &mut self.grid.tracks[self.grid.cursor.track as usize];
@@ -287,6 +755,13 @@ impl SequencerPanel {
                     track: self.grid.cursor.track,
                 });
             }
+            crate::tui::ui::widgets::TrackControl::Arp => {
+                track.cycle_arp_mode();
+                actions.push(SequencerAction::TrackArpChanged {
+                    track: self.grid.cursor.track,
+                    arp_mode: track.arp_mode,
+                });
+            }
             _ => {}
         }
     }
@@ -310,9 +785,22 @@ impl SequencerPanel {
                     pan: track.pan,
                 });
             }
+            crate::tui::ui::widgets::TrackControl::Waveform => {
+                self.cycle_track_waveform(if delta > 0.0 { 1 } else { -1 }, actions);
+            }
             _ => {}
         }
     }
+
+    fn cycle_track_waveform(&mut self, direction: i8, actions: &mut Vec<SequencerAction>) {
+        let track_idx = self.grid.cursor.track;
+        let track = &mut self.grid.tracks[track_idx as usize];
+        track.cycle_waveform(direction);
+        actions.push(SequencerAction::TrackWaveformChanged {
+            track: track_idx,
+            waveform: track.waveform,
+        });
+    }
     
     pub fn set_focused(&mut self, focused: bool) {
         self.grid.focused = focused;
@@ -333,15 +821,73 @@ impl SequencerPanel {
     pub fn is_pattern_browser_visible(&self) -> bool {
         self.show_pattern_browser
     }
-    
-    pub fn load_pattern_to_track(&mut self, pattern_id: &str, track_idx: usize) -> bool {
-        if let Some(pattern_steps) = self.pattern_manager.get_pattern_steps(pattern_id) {
-            if track_idx < self.grid.tracks.len() {
-                self.grid.tracks[track_idx].steps = pattern_steps;
-                return true;
+
+    /// Patterns the browser lists, newest first
+    pub fn browser_patterns(&self) -> Vec<&Pattern> {
+        let mut patterns = self.pattern_manager.list_patterns();
+        // IDs count up, so they order patterns stored in the same instant
+        patterns.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| b.id.cmp(&a.id)));
+        patterns
+    }
+
+    pub fn browser_selected(&self) -> usize {
+        self.browser_selected
+    }
+
+    /// Move the browser's selection up (negative) or down, stopping at the first and last pattern
+    pub fn move_browser_selection(&mut self, delta: i32) {
+        let last = self.pattern_manager.count().saturating_sub(1) as i32;
+        self.browser_selected = (self.browser_selected as i32 + delta).clamp(0, last) as usize;
+    }
+
+    // Up and Down choose a pattern, Enter loads it into the current track and closes the browser,
+    // Esc or Ctrl+P close it without loading
+    fn handle_pattern_browser_key(&mut self, key: KeyEvent) -> Vec<SequencerAction> {
+        match key.code {
+            KeyCode::Up => self.move_browser_selection(-1),
+            KeyCode::Down => self.move_browser_selection(1),
+            KeyCode::Enter => {
+                let Some(pattern_id) = self.browser_patterns()
+                    .get(self.browser_selected)
+                    .map(|pattern| pattern.id.clone()) else {
+                    return Vec::new();
+                };
+                let before_edit = self.snapshot();
+                if let Some(pattern_length) =
+                    self.load_pattern_to_track(&pattern_id, self.grid.cursor.track as usize) {
+                    self.show_pattern_browser = false;
+                    self.push_undo(before_edit);
+                    return vec![self.pattern_loaded(pattern_id, pattern_length)];
+                }
             }
+            KeyCode::Esc => {
+                self.show_pattern_browser = false;
+                return vec![SequencerAction::PatternBrowserToggled];
+            }
+            KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.show_pattern_browser = false;
+                return vec![SequencerAction::PatternBrowserToggled];
+            }
+            _ => {}
         }
-        false
+        Vec::new()
+    }
+    
+    /// Replace a track's steps with a pattern's, returning the number of steps the pattern was
+    /// stored with. Patterns stored from a sequence of another length are cut, or padded with
+    /// empty steps, to fit this one.
+    pub fn load_pattern_to_track(&mut self, pattern_id: &str, track_idx: usize) -> Option<usize> {
+        let pattern_steps = self.pattern_manager.get_pattern_steps(pattern_id)?;
+        let track = self.grid.tracks.get_mut(track_idx)?;
+        let pattern_length = pattern_steps.len();
+        track.steps = pattern_steps;
+        track.steps.resize(self.grid.steps_per_track, StepCell::default());
+        Some(pattern_length)
+    }
+
+    fn pattern_loaded(&self, pattern_id: String, pattern_length: usize) -> SequencerAction {
+        let fitted_from = (pattern_length != self.grid.steps_per_track).then_some(pattern_length);
+        SequencerAction::PatternLoaded { pattern_id, fitted_from }
     }
     
     pub fn store_track_as_pattern(&mut self, track_idx: usize, name: String) -> Option<String> {
@@ -357,4 +903,292 @@ impl SequencerPanel {
             None
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test_sequencer {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use crate::note::scales::WesternPitch;
+    use crate::tui::pattern_manager::PatternManager;
+    use crate::tui::ui::sequencer::{piano_key_pitch, SequencerAction, SequencerPanel,
+                                    MAX_UNDO_DEPTH};
+    use crate::tui::ui::widgets::{StepCell, MAX_STEP_OCTAVE};
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn ctrl(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::CONTROL)
+    }
+
+    // A panel with no patterns, so tests never read the patterns saved on this machine
+    fn empty_panel() -> SequencerPanel {
+        SequencerPanel::with_pattern_manager(PatternManager::new())
+    }
+
+    fn first_step_enabled(panel: &SequencerPanel) -> bool {
+        panel.grid.tracks[0].steps[0].enabled
+    }
+
+    #[test]
+    fn test_undo_and_redo_step_toggle() {
+        let mut panel = empty_panel();
+        let enabled = first_step_enabled(&panel);
+        panel.handle_key_event(key(KeyCode::Enter));
+        assert_eq!(first_step_enabled(&panel), !enabled);
+
+        assert!(matches!(panel.handle_key_event(ctrl(KeyCode::Char('z'))).as_slice(),
+                         [SequencerAction::Undone]));
+        assert_eq!(first_step_enabled(&panel), enabled);
+
+        assert!(matches!(panel.handle_key_event(ctrl(KeyCode::Char('y'))).as_slice(),
+                         [SequencerAction::Redone]));
+        assert_eq!(first_step_enabled(&panel), !enabled);
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo() {
+        let mut panel = empty_panel();
+        panel.handle_key_event(key(KeyCode::Enter));
+        panel.undo();
+        panel.handle_key_event(key(KeyCode::Char(']')));
+        assert!(matches!(panel.redo(), SequencerAction::NothingToRedo));
+    }
+
+    #[test]
+    fn test_navigation_is_not_undoable() {
+        let mut panel = empty_panel();
+        panel.handle_key_event(key(KeyCode::Right));
+        assert!(matches!(panel.undo(), SequencerAction::NothingToUndo));
+    }
+
+    #[test]
+    fn test_navigation_keeps_redo() {
+        let mut panel = empty_panel();
+        let enabled = first_step_enabled(&panel);
+        panel.handle_key_event(key(KeyCode::Enter));
+        panel.undo();
+        for code in [KeyCode::Right, KeyCode::Down, KeyCode::Left, KeyCode::Up, KeyCode::Tab] {
+            panel.handle_key_event(key(code));
+        }
+        assert!(matches!(panel.redo(), SequencerAction::Redone));
+        assert_ne!(first_step_enabled(&panel), enabled);
+    }
+
+    #[test]
+    fn test_undo_depth_is_capped() {
+        let mut panel = empty_panel();
+        let enabled = first_step_enabled(&panel);
+        for _ in 0..MAX_UNDO_DEPTH + 1 {
+            panel.handle_key_event(key(KeyCode::Enter));
+        }
+        let mut num_undone = 0;
+        while matches!(panel.undo(), SequencerAction::Undone) {
+            num_undone += 1;
+        }
+        assert_eq!(num_undone, MAX_UNDO_DEPTH);
+        // The oldest edit was dropped, so the step is one toggle away from where it began
+        assert_eq!(first_step_enabled(&panel), !enabled);
+    }
+
+    #[test]
+    fn test_piano_key_pitch() {
+        assert_eq!(piano_key_pitch('a', 4), Some((WesternPitch::C, 4)));
+        assert_eq!(piano_key_pitch('w', 4), Some((WesternPitch::CSharp, 4)));
+        assert_eq!(piano_key_pitch('h', 4), Some((WesternPitch::A, 4)));
+        assert_eq!(piano_key_pitch('j', 3), Some((WesternPitch::B, 3)));
+        // The last key is the next octave's C
+        assert_eq!(piano_key_pitch('k', 4), Some((WesternPitch::C, 5)));
+        // Neither key leaves the top octave
+        assert_eq!(piano_key_pitch('j', MAX_STEP_OCTAVE), Some((WesternPitch::B, MAX_STEP_OCTAVE)));
+        assert_eq!(piano_key_pitch('k', MAX_STEP_OCTAVE), Some((WesternPitch::C, MAX_STEP_OCTAVE)));
+        assert_eq!(piano_key_pitch('z', 4), None);
+    }
+
+    #[test]
+    fn test_piano_key_sets_and_enables_step() {
+        let mut panel = empty_panel();
+        // Without piano keys mode, a selects the first track
+        assert!(!panel.is_piano_key(key(KeyCode::Char('a'))));
+
+        panel.handle_key_event(key(KeyCode::F(3)));
+        panel.grid.tracks[0].steps[0].enabled = false;
+        let octave = panel.grid.tracks[0].steps[0].octave;
+        let actions = panel.handle_key_event(key(KeyCode::Char('d')));
+        let [SequencerAction::PianoKeyPlayed { track: 0, step: 0, midi_note }] =
+            actions.as_slice() else {
+            panic!("expected a piano key action, got {:?}", actions);
+        };
+        assert_eq!(*midi_note, WesternPitch::E.to_midi_note(octave));
+        let step = &panel.grid.tracks[0].steps[0];
+        assert!(step.enabled);
+        assert_eq!(step.frequency, WesternPitch::E);
+    }
+
+    // A panel whose pattern manager holds only the named patterns, stored in order, each with
+    // the first step enabled or not
+    fn panel_with_patterns(patterns: &[(&str, bool)]) -> SequencerPanel {
+        let mut panel = empty_panel();
+        let pattern_manager = panel.get_pattern_manager_mut();
+        for (name, first_step_enabled) in patterns {
+            let mut steps = vec![StepCell::default(); 16];
+            steps[0].enabled = *first_step_enabled;
+            pattern_manager.store_pattern(name.to_string(), steps, None);
+        }
+        panel
+    }
+
+    #[test]
+    fn test_pattern_browser_selection_stays_in_list() {
+        let mut panel = panel_with_patterns(&[("one", false), ("two", false), ("three", false)]);
+        panel.handle_key_event(ctrl(KeyCode::Char('p')));
+        assert!(panel.is_pattern_browser_visible());
+        assert_eq!(panel.browser_selected(), 0);
+
+        panel.handle_key_event(key(KeyCode::Up));
+        assert_eq!(panel.browser_selected(), 0);
+        for _ in 0..5 {
+            panel.handle_key_event(key(KeyCode::Down));
+        }
+        assert_eq!(panel.browser_selected(), 2);
+        panel.handle_key_event(key(KeyCode::Up));
+        assert_eq!(panel.browser_selected(), 1);
+    }
+
+    #[test]
+    fn test_pattern_browser_lists_newest_first() {
+        let panel = panel_with_patterns(&[("older", false), ("newer", false)]);
+        let names: Vec<&str> = panel.browser_patterns().iter()
+            .map(|pattern| pattern.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["newer", "older"]);
+    }
+
+    #[test]
+    fn test_pattern_browser_loads_selected_pattern() {
+        let mut panel = panel_with_patterns(&[("enabled", true), ("empty", false)]);
+        panel.grid.tracks[0].steps[0].enabled = false;
+        panel.handle_key_event(ctrl(KeyCode::Char('p')));
+        panel.handle_key_event(key(KeyCode::Down));
+        let actions = panel.handle_key_event(key(KeyCode::Enter));
+        assert!(matches!(actions.as_slice(), [SequencerAction::PatternLoaded { .. }]));
+        assert!(!panel.is_pattern_browser_visible());
+        assert!(first_step_enabled(&panel));
+
+        // Loading is an edit like any other
+        panel.undo();
+        assert!(!first_step_enabled(&panel));
+    }
+
+    #[test]
+    fn test_shorter_pattern_is_padded_to_sequence_length() {
+        let mut panel = empty_panel();
+        let pattern_manager = panel.get_pattern_manager_mut();
+        let mut steps = vec![StepCell::default(); 12];
+        steps[11].enabled = true;
+        let pattern_id = pattern_manager.store_pattern("twelve".to_string(), steps, None);
+        panel.grid.tracks[0].steps[15].enabled = true;
+
+        assert_eq!(panel.load_pattern_to_track(&pattern_id, 0), Some(12));
+        let steps = &panel.grid.tracks[0].steps;
+        assert_eq!(steps.len(), 16);
+        assert!(steps[11].enabled);
+        assert!(steps[12..].iter().all(|step| !step.enabled));
+        assert_eq!(panel.load_pattern_to_track(&pattern_id, 99), None);
+
+        // Alt+L loads it the same way, and says the pattern was fitted
+        panel.grid.tracks[0].steps.truncate(12);
+        let actions = panel.handle_key_event(KeyEvent::new(KeyCode::Char('l'), KeyModifiers::ALT));
+        assert!(matches!(actions.as_slice(),
+                         [SequencerAction::PatternLoaded { fitted_from: Some(12), .. }]));
+        assert_eq!(panel.grid.tracks[0].steps.len(), 16);
+    }
+
+    #[test]
+    fn test_pattern_browser_closes_without_loading() {
+        let mut panel = panel_with_patterns(&[("enabled", true)]);
+        panel.grid.tracks[0].steps[0].enabled = false;
+        panel.handle_key_event(ctrl(KeyCode::Char('p')));
+        // Keys that edit the grid do nothing while the browser is open
+        panel.handle_key_event(key(KeyCode::Char(' ')));
+        let actions = panel.handle_key_event(key(KeyCode::Esc));
+        assert!(matches!(actions.as_slice(), [SequencerAction::PatternBrowserToggled]));
+        assert!(!panel.is_pattern_browser_visible());
+        assert!(!first_step_enabled(&panel));
+    }
+
+    #[test]
+    fn test_alt_c_and_alt_v_copy_and_paste_a_step() {
+        let mut panel = empty_panel();
+        let alt = |code| KeyEvent::new(code, KeyModifiers::ALT);
+        // Nothing is pasted before a step is copied
+        assert!(panel.handle_key_event(alt(KeyCode::Char('v'))).is_empty());
+
+        panel.grid.set_current_step_pitch(WesternPitch::G, 3);
+        panel.grid.tracks[0].steps[0].velocity = 40;
+        panel.handle_key_event(alt(KeyCode::Char('c')));
+        panel.grid.cursor.track = 5;
+        panel.grid.cursor.step = 7;
+        let actions = panel.handle_key_event(alt(KeyCode::Char('v')));
+        assert!(matches!(actions.as_slice(), [SequencerAction::StepPasted { track: 5, step: 7 }]));
+        let pasted = &panel.grid.tracks[5].steps[7];
+        assert_eq!((pasted.frequency, pasted.octave, pasted.velocity), (WesternPitch::G, 3, 40));
+
+        // Pasting is undoable
+        panel.handle_key_event(ctrl(KeyCode::Char('z')));
+        assert!(!panel.grid.tracks[5].steps[7].enabled);
+    }
+
+    #[test]
+    fn test_shift_left_and_right_rotate_the_track() {
+        let mut panel = empty_panel();
+        let shift = |code| KeyEvent::new(code, KeyModifiers::SHIFT);
+        panel.grid.tracks[0].steps[0].enabled = true;
+
+        let actions = panel.handle_key_event(shift(KeyCode::Right));
+        assert!(matches!(actions.as_slice(),
+                         [SequencerAction::TrackRotated { track: 0, amount: 1 }]));
+        assert!(panel.grid.tracks[0].steps[1].enabled);
+        // The cursor stays where it was
+        assert_eq!(panel.grid.cursor.step, 0);
+        panel.handle_key_event(shift(KeyCode::Left));
+        panel.handle_key_event(shift(KeyCode::Left));
+        assert!(panel.grid.tracks[0].steps[15].enabled);
+    }
+
+    #[test]
+    fn test_slash_ramps_selection_between_its_ends() {
+        let mut panel = empty_panel();
+        panel.grid.tracks[0].steps[0].velocity = 0;
+        panel.grid.tracks[0].steps[3].velocity = 120;
+        // Nothing to ramp without a selection
+        assert!(panel.handle_key_event(key(KeyCode::Char('/'))).is_empty());
+
+        panel.grid.start_selection();
+        panel.grid.cursor.step = 3;
+        panel.grid.update_selection();
+        let actions = panel.handle_key_event(key(KeyCode::Char('/')));
+        assert!(matches!(actions.as_slice(),
+                         [SequencerAction::VelocityRamped { start: 0, end: 120 }]));
+        let velocities: Vec<u8> = panel.grid.tracks[0].steps[0..4].iter()
+            .map(|step| step.velocity)
+            .collect();
+        assert_eq!(velocities, vec![0, 40, 80, 120]);
+    }
+
+    #[test]
+    fn test_shift_up_and_down_adjust_step_velocity() {
+        let mut panel = empty_panel();
+        let shift = |code| KeyEvent::new(code, KeyModifiers::SHIFT);
+        panel.grid.tracks[0].steps[0].velocity = 100;
+
+        let actions = panel.handle_key_event(shift(KeyCode::Down));
+        assert!(matches!(actions.as_slice(),
+                         [SequencerAction::StepVelocityChanged { track: 0, step: 0, velocity: 92 }]));
+        panel.handle_key_event(shift(KeyCode::Up));
+        assert_eq!(panel.grid.tracks[0].steps[0].velocity, 100);
+        // The cursor stays on the step
+        assert_eq!(panel.grid.cursor.track, 0);
+    }
+}
@@ -1,5 +1,12 @@
-use crate::tui::ui::widgets::{LinearSlider, LogSlider, WaveformSelector, FilterTypeSelector};
+use crate::tui::ui::widgets::{LinearSlider, LogSlider, TimeSlider, WaveformSelector,
+                              FilterTypeSelector};
+use crate::envelope::envelope::Envelope;
 use crate::tui::audio_bridge::ParameterUpdate;
+use crate::tui::audio_engine::{DEFAULT_ENVELOPE_ATTACK, DEFAULT_ENVELOPE_DECAY,
+                               DEFAULT_ENVELOPE_RELEASE, DEFAULT_ENVELOPE_SUSTAIN,
+                               DEFAULT_MASTER_FILTER_CUTOFF, DEFAULT_MASTER_FILTER_MIX,
+                               DEFAULT_MASTER_FILTER_RESONANCE, MAX_ENVELOPE_TIME,
+                               MAX_FILTER_CUTOFF, MIN_FILTER_CUTOFF};
 use crate::audio_gen::Waveform;
 use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::{
@@ -32,17 +39,44 @@ pub struct OscillatorControls {
     pub sub_focus: OscillatorSubSection,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterSubSection {
+    Type,
+    Cutoff,
+    Resonance,
+    Mix,
+}
+
 #[derive(Debug)]
 pub struct FilterControls {
     pub filter_type: FilterTypeSelector,
     pub cutoff_slider: LogSlider,
     pub resonance_slider: LinearSlider,
     pub mix_slider: LinearSlider,
+    pub sub_focus: FilterSubSection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnvelopeSubSection {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
 }
 
+// Levels of the curve plot, lowest to highest
+const ENVELOPE_PLOT_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+// Share of the plotted note the sustain holds for, so the plot shows a sustain however long the
+// other stages are
+const ENVELOPE_PLOT_SUSTAIN_SHARE: f32 = 0.25;
+
 #[derive(Debug)]
 pub struct EnvelopeControls {
-    // TODO: Implement envelope controls
+    pub attack_slider: TimeSlider,
+    pub decay_slider: TimeSlider,
+    pub sustain_slider: LinearSlider,
+    pub release_slider: TimeSlider,
+    pub sub_focus: EnvelopeSubSection,
 }
 
 #[derive(Debug)]
@@ -55,7 +89,7 @@ impl SynthesizerPanel {
         Self {
             oscillator: OscillatorControls::new(),
             filter: FilterControls::new(),
-            envelope: EnvelopeControls {},
+            envelope: EnvelopeControls::new(),
             effects: EffectsControls {},
             current_section: OscillatorSubSection::Waveform,
         }
@@ -232,9 +266,264 @@ impl FilterControls {
     pub fn new() -> Self {
         Self {
             filter_type: FilterTypeSelector::new(),
-            cutoff_slider: LogSlider::new("Cutoff", 8000.0, 20.0, 20000.0, 8),
-            resonance_slider: LinearSlider::new("Res", 0.3, 0.0, 1.0, 8),
-            mix_slider: LinearSlider::new("Mix", 0.8, 0.0, 1.0, 8),
+            cutoff_slider: LogSlider::new("Cutoff", DEFAULT_MASTER_FILTER_CUTOFF,
+                                          MIN_FILTER_CUTOFF, MAX_FILTER_CUTOFF, 8),
+            resonance_slider: LinearSlider::new("Res", DEFAULT_MASTER_FILTER_RESONANCE,
+                                                0.0, 1.0, 8),
+            mix_slider: LinearSlider::new("Mix", DEFAULT_MASTER_FILTER_MIX, 0.0, 1.0, 8),
+            sub_focus: FilterSubSection::Type,
+        }
+    }
+
+    pub fn handle_input(&mut self, key: KeyEvent) -> Vec<ParameterUpdate> {
+        let mut updates = Vec::new();
+
+        match key.code {
+            KeyCode::Up => {
+                self.sub_focus = match self.sub_focus {
+                    FilterSubSection::Type => FilterSubSection::Mix,
+                    FilterSubSection::Cutoff => FilterSubSection::Type,
+                    FilterSubSection::Resonance => FilterSubSection::Cutoff,
+                    FilterSubSection::Mix => FilterSubSection::Resonance,
+                };
+            }
+            KeyCode::Down => {
+                self.sub_focus = match self.sub_focus {
+                    FilterSubSection::Type => FilterSubSection::Cutoff,
+                    FilterSubSection::Cutoff => FilterSubSection::Resonance,
+                    FilterSubSection::Resonance => FilterSubSection::Mix,
+                    FilterSubSection::Mix => FilterSubSection::Type,
+                };
+            }
+            KeyCode::Left | KeyCode::Right => {
+                let increase = key.code == KeyCode::Right;
+                updates.push(self.handle_parameter_adjustment(increase));
+            }
+            KeyCode::Enter if self.sub_focus == FilterSubSection::Type => {
+                self.filter_type.toggle_expanded();
+                updates.push(ParameterUpdate::FilterType(self.filter_type.selected_filter()));
+            }
+            _ => {}
         }
+
+        updates
+    }
+
+    fn handle_parameter_adjustment(&mut self, increase: bool) -> ParameterUpdate {
+        match self.sub_focus {
+            FilterSubSection::Type => {
+                if increase {
+                    self.filter_type.next();
+                } else {
+                    self.filter_type.previous();
+                }
+                ParameterUpdate::FilterType(self.filter_type.selected_filter())
+            }
+            FilterSubSection::Cutoff => {
+                self.cutoff_slider.adjust_log(if increase { 1.05 } else { 0.95 });
+                ParameterUpdate::FilterCutoff(self.cutoff_slider.value)
+            }
+            FilterSubSection::Resonance => {
+                self.resonance_slider.adjust(if increase { 0.05 } else { -0.05 });
+                ParameterUpdate::FilterResonance(self.resonance_slider.value)
+            }
+            FilterSubSection::Mix => {
+                self.mix_slider.adjust(if increase { 0.05 } else { -0.05 });
+                ParameterUpdate::FilterMix(self.mix_slider.value)
+            }
+        }
+    }
+}
+
+impl EnvelopeControls {
+    pub fn new() -> Self {
+        Self {
+            attack_slider: TimeSlider::new("Att", DEFAULT_ENVELOPE_ATTACK, 0.0,
+                                           MAX_ENVELOPE_TIME, 8),
+            decay_slider: TimeSlider::new("Dec", DEFAULT_ENVELOPE_DECAY, 0.0,
+                                          MAX_ENVELOPE_TIME, 8),
+            sustain_slider: LinearSlider::new("Sus", DEFAULT_ENVELOPE_SUSTAIN, 0.0, 1.0, 8),
+            release_slider: TimeSlider::new("Rel", DEFAULT_ENVELOPE_RELEASE, 0.0,
+                                            MAX_ENVELOPE_TIME, 8),
+            sub_focus: EnvelopeSubSection::Attack,
+        }
+    }
+
+    pub fn handle_input(&mut self, key: KeyEvent) -> Vec<ParameterUpdate> {
+        let mut updates = Vec::new();
+
+        match key.code {
+            KeyCode::Up => {
+                self.sub_focus = match self.sub_focus {
+                    EnvelopeSubSection::Attack => EnvelopeSubSection::Release,
+                    EnvelopeSubSection::Decay => EnvelopeSubSection::Attack,
+                    EnvelopeSubSection::Sustain => EnvelopeSubSection::Decay,
+                    EnvelopeSubSection::Release => EnvelopeSubSection::Sustain,
+                };
+            }
+            KeyCode::Down => {
+                self.sub_focus = match self.sub_focus {
+                    EnvelopeSubSection::Attack => EnvelopeSubSection::Decay,
+                    EnvelopeSubSection::Decay => EnvelopeSubSection::Sustain,
+                    EnvelopeSubSection::Sustain => EnvelopeSubSection::Release,
+                    EnvelopeSubSection::Release => EnvelopeSubSection::Attack,
+                };
+            }
+            KeyCode::Left | KeyCode::Right => {
+                let increase = key.code == KeyCode::Right;
+                updates.push(self.handle_parameter_adjustment(increase));
+            }
+            _ => {}
+        }
+
+        updates
+    }
+
+    fn handle_parameter_adjustment(&mut self, increase: bool) -> ParameterUpdate {
+        let direction = if increase { 1.0 } else { -1.0 };
+        match self.sub_focus {
+            EnvelopeSubSection::Attack => {
+                let delta = direction * time_step(self.attack_slider.value, increase);
+                self.attack_slider.adjust(delta);
+                ParameterUpdate::EnvelopeAttack(self.attack_slider.value)
+            }
+            EnvelopeSubSection::Decay => {
+                let delta = direction * time_step(self.decay_slider.value, increase);
+                self.decay_slider.adjust(delta);
+                ParameterUpdate::EnvelopeDecay(self.decay_slider.value)
+            }
+            EnvelopeSubSection::Sustain => {
+                self.sustain_slider.adjust(direction * 0.05);
+                ParameterUpdate::EnvelopeSustain(self.sustain_slider.value)
+            }
+            EnvelopeSubSection::Release => {
+                let delta = direction * time_step(self.release_slider.value, increase);
+                self.release_slider.adjust(delta);
+                ParameterUpdate::EnvelopeRelease(self.release_slider.value)
+            }
+        }
+    }
+
+    /// The envelope the sliders describe, for a note long enough to hold its sustain a while
+    pub(crate) fn envelope(&self) -> Envelope {
+        let stages_ms = 1000.0 * (self.attack_slider.value + self.decay_slider.value +
+            self.release_slider.value);
+        let note_duration_ms = (stages_ms / (1.0 - ENVELOPE_PLOT_SUSTAIN_SHARE)).max(1.0);
+        Envelope::from_adsr_ms(1000.0 * self.attack_slider.value,
+                               1000.0 * self.decay_slider.value,
+                               self.sustain_slider.value,
+                               1000.0 * self.release_slider.value,
+                               note_duration_ms)
+    }
+
+    /// One row plot of the envelope's volume across a note, `width` characters wide
+    pub fn render_curve(&self, width: usize) -> String {
+        let envelope = self.envelope();
+        let max_level = (ENVELOPE_PLOT_LEVELS.len() - 1) as f32;
+        (0..width)
+            .map(|column| {
+                let position = (column as f32 + 0.5) / width as f32;
+                let volume = envelope.volume_factor(position).clamp(0.0, 1.0);
+                ENVELOPE_PLOT_LEVELS[(volume * max_level).round() as usize]
+            })
+            .collect()
+    }
+}
+
+impl Default for EnvelopeControls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Short times take fine steps and long times coarse ones
+fn time_step(value: f32, increase: bool) -> f32 {
+    let fine = if increase { value < 0.1 } else { value <= 0.1 };
+    if fine { 0.01 } else { 0.05 }
+}
+
+#[cfg(test)]
+mod test_synthesizer {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use crate::common::float_utils::assert_float_eq;
+    use crate::tui::audio_bridge::ParameterUpdate;
+    use crate::tui::ui::synthesizer::{EnvelopeControls, EnvelopeSubSection, FilterControls,
+                                      FilterSubSection};
+    use crate::tui::ui::widgets::FilterType;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_filter_type_cycles() {
+        let mut filter = FilterControls::new();
+        let updates = filter.handle_input(key(KeyCode::Right));
+        assert!(matches!(updates.as_slice(),
+                         [ParameterUpdate::FilterType(FilterType::HighPass)]));
+        let updates = filter.handle_input(key(KeyCode::Left));
+        assert!(matches!(updates.as_slice(),
+                         [ParameterUpdate::FilterType(FilterType::LowPass)]));
+    }
+
+    #[test]
+    fn test_filter_parameters_adjust() {
+        let mut filter = FilterControls::new();
+        filter.handle_input(key(KeyCode::Down));
+        assert_eq!(filter.sub_focus, FilterSubSection::Cutoff);
+        let updates = filter.handle_input(key(KeyCode::Left));
+        let [ParameterUpdate::FilterCutoff(cutoff)] = updates.as_slice() else {
+            panic!("expected a cutoff update, got {:?}", updates);
+        };
+        assert_float_eq(*cutoff, 7600.0);
+
+        // Up from the type wraps around to the mix
+        filter.handle_input(key(KeyCode::Up));
+        filter.handle_input(key(KeyCode::Up));
+        assert_eq!(filter.sub_focus, FilterSubSection::Mix);
+        let updates = filter.handle_input(key(KeyCode::Right));
+        let [ParameterUpdate::FilterMix(mix)] = updates.as_slice() else {
+            panic!("expected a mix update, got {:?}", updates);
+        };
+        assert_float_eq(*mix, 0.05);
+    }
+
+    #[test]
+    fn test_envelope_parameters_adjust() {
+        let mut envelope = EnvelopeControls::new();
+        let updates = envelope.handle_input(key(KeyCode::Right));
+        let [ParameterUpdate::EnvelopeAttack(attack)] = updates.as_slice() else {
+            panic!("expected an attack update, got {:?}", updates);
+        };
+        assert_float_eq(*attack, 0.02);
+
+        envelope.handle_input(key(KeyCode::Down));
+        envelope.handle_input(key(KeyCode::Down));
+        assert_eq!(envelope.sub_focus, EnvelopeSubSection::Sustain);
+        let updates = envelope.handle_input(key(KeyCode::Left));
+        let [ParameterUpdate::EnvelopeSustain(sustain)] = updates.as_slice() else {
+            panic!("expected a sustain update, got {:?}", updates);
+        };
+        assert_float_eq(*sustain, 0.75);
+        assert_float_eq(envelope.envelope().sustain.1, 0.75);
+
+        // Times stop at 0
+        envelope.handle_input(key(KeyCode::Down));
+        for _ in 0..10 {
+            envelope.handle_input(key(KeyCode::Left));
+        }
+        assert_float_eq(envelope.release_slider.value, 0.0);
+    }
+
+    #[test]
+    fn test_envelope_curve() {
+        let mut envelope = EnvelopeControls::new();
+        envelope.attack_slider.set_value(0.5);
+        envelope.decay_slider.set_value(0.0);
+        envelope.sustain_slider.set_value(1.0);
+        envelope.release_slider.set_value(0.25);
+        // Half the note rising, a quarter held and a quarter falling
+        assert_eq!(envelope.render_curve(8), "▂▄▅▇██▆▃");
     }
 }
\ No newline at end of file
@@ -5,8 +5,92 @@ use ratatui::{
     widgets::Widget,
 };
 
-use crate::note::playback_note::PlaybackNote;
-use crate::note::scales::WesternPitch;
+use crate::audio_gen::oscillator::Waveform;
+use crate::common::constants::SAMPLE_RATE;
+use crate::envelope::envelope::default_envelope;
+use crate::meter::durations::DurationType;
+use crate::note::constants::PITCH_TO_FREQ_HZ;
+use crate::note::note::NoteBuilder;
+use crate::note::playback_note::{NoteType, PlaybackNote, PlaybackNoteBuilder};
+use crate::note::scales::{WesternPitch, WesternScale};
+use crate::sequence::arpeggiator::{ArpMode, ARP_MODES};
+use crate::sequence::euclidean::euclidean;
+use crate::sequence::fixed_time_note_sequence::{FixedTimeNoteSequence,
+                                                FixedTimeNoteSequenceBuilder};
+use crate::sequence::note_sequence_trait::AppendNote;
+use crate::track::track::TrackBuilder;
+use crate::track::track_grid::{TrackGrid, TrackGridBuilder};
+use crate::tui::audio_engine::gate_duration_ms;
+
+// Octaves are scientific pitch notation, so a step in octave 3 plays C3 an octave below middle C
+static DEFAULT_STEP_OCTAVE: u8 = 3;
+pub const MAX_STEP_OCTAVE: u8 = 8;
+// Pitches a step can sound at once, its own and those stacked on it
+pub const MAX_CHORD_PITCHES: usize = 4;
+pub const MAX_STEP_PROBABILITY: u8 = 100;
+pub const MAX_STEP_VELOCITY: u8 = 127;
+// Bar drawn beside an enabled step, one level for each sixteenth of the velocity range
+const VELOCITY_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+pub const MAX_TRACK_DIVISOR: usize = 4;
+pub const MIN_GATE_LENGTH: f32 = 0.1;
+pub const MAX_GATE_LENGTH: f32 = 2.0;
+static DEFAULT_GATE_LENGTH: f32 = 1.0;
+
+fn default_step_probability() -> u8 {
+    MAX_STEP_PROBABILITY
+}
+
+fn default_gate_length() -> f32 {
+    DEFAULT_GATE_LENGTH
+}
+
+fn default_step_octave() -> u8 {
+    DEFAULT_STEP_OCTAVE
+}
+
+/// Sequence lengths the grid cycles through, in steps
+pub const SEQUENCE_LENGTHS: [usize; 5] = [8, 12, 16, 24, 32];
+pub const MAX_SEQUENCE_LENGTH: usize = SEQUENCE_LENGTHS[SEQUENCE_LENGTHS.len() - 1];
+
+// Each step takes 4 columns, after 2 for the track number
+const STEP_WIDTH: u16 = 4;
+const TRACK_NUMBER_WIDTH: u16 = 2;
+
+/// First step to show so the cursor stays in view. The view keeps its place until the cursor
+/// reaches an edge, then moves just far enough to show it, never past the last step.
+pub fn step_scroll_offset(cursor_step: usize, step_scroll: usize, visible_steps: usize,
+                          steps_per_track: usize) -> usize {
+    let visible_steps = visible_steps.max(1);
+    let step_scroll = if cursor_step < step_scroll {
+        cursor_step
+    } else if cursor_step >= step_scroll + visible_steps {
+        cursor_step + 1 - visible_steps
+    } else {
+        step_scroll
+    };
+    step_scroll.min(steps_per_track.saturating_sub(visible_steps))
+}
+
+/// Waveforms a track can play, in the order the track waveform control cycles through them
+pub const TRACK_WAVEFORMS: [Waveform; 8] = [
+    Waveform::Sine,
+    Waveform::Square,
+    Waveform::Triangle,
+    Waveform::Saw,
+    Waveform::Pulse,
+    Waveform::GaussianNoise,
+    Waveform::PinkNoise,
+    Waveform::BrownNoise,
+];
+
+// Value at `index` of `count` evenly spaced from `start` to `end`, rounded to the nearest
+fn ramp_value(start: u8, end: u8, index: usize, count: usize) -> u8 {
+    if count < 2 {
+        return start;
+    }
+    let position = index as f32 / (count - 1) as f32;
+    (start as f32 + (end as f32 - start as f32) * position).round() as u8
+}
 
 #[derive(Debug, Clone)]
 pub struct SequencerGrid {
@@ -14,27 +98,47 @@ pub struct SequencerGrid {
     pub steps_per_track: usize,
     pub cursor: GridCursor,
     pub playing_step: Option<usize>,
+    // Step each track is playing, which differs between tracks with their own length or divisor
+    pub track_playing_steps: [Option<usize>; 8],
     pub selection: Option<GridSelection>,
     pub focused: bool,
+    // Root and scale that frequency editing is kept to, or None for all 12 pitches
+    pub scale: Option<(WesternPitch, WesternScale)>,
+    // First and last steps that playback loops between, or None to play the whole sequence
+    pub loop_region: Option<(usize, usize)>,
+    // First step in view when the sequence is wider than the grid
+    pub step_scroll: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TrackStrip {
     pub track_number: u8,
     pub volume: f32,
     pub pan: f32,  // Single pan control (-1.0 to +1.0)
     pub mute: bool,
     pub solo: bool,
+    pub waveform: Waveform,
+    // Number of steps the track plays before wrapping, at most the grid's steps_per_track. Tracks
+    // of different lengths play polyrhythms against each other.
+    pub length: usize,
+    // The track moves one step every divisor steps of the sequencer clock
+    pub divisor: usize,
     pub steps: Vec<StepCell>,
+    #[serde(skip)] // Skip serialization of which control has focus
     pub selected_control: TrackControl,
+    // Chord steps play one pitch at a time in this order, or all together with no mode
+    pub arp_mode: Option<ArpMode>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub enum TrackControl {
+    #[default]
     Volume,
     Pan,
     Mute,
     Solo,
+    Waveform,
+    Arp,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -42,6 +146,19 @@ pub struct StepCell {
     pub enabled: bool,
     pub velocity: u8,
     pub frequency: WesternPitch,
+    #[serde(default = "default_step_octave")]
+    pub octave: u8,
+    // Pitches stacked on the step's own to make a chord, lowest first. Each sounds in the first
+    // octave above the pitch before it.
+    #[serde(default)]
+    pub extra_pitches: Vec<WesternPitch>,
+    // Percent chance, 0 to 100, that the step sounds each time it is reached while enabled
+    #[serde(default = "default_step_probability")]
+    pub probability: u8,
+    // How long the note sounds as a fraction of the step, from MIN_GATE_LENGTH for staccato to
+    // MAX_GATE_LENGTH to tie across the next step
+    #[serde(default = "default_gate_length")]
+    pub gate_length: f32,
     #[serde(skip)] // Skip serialization of PlaybackNote for now
     pub note: Option<PlaybackNote>,
     #[serde(skip)] // Skip serialization of highlighted state
@@ -82,8 +199,12 @@ impl SequencerGrid {
                 focus_area: CursorFocus::Steps 
             },
             playing_step: None,
+            track_playing_steps: [None; 8],
             selection: None,
             focused: false,
+            scale: None,
+            loop_region: None,
+            step_scroll: 0,
         }
     }
     
@@ -147,6 +268,8 @@ impl SequencerGrid {
                         TrackControl::Pan,
                         TrackControl::Mute,
                         TrackControl::Solo,
+                        TrackControl::Waveform,
+                        TrackControl::Arp,
                     ];
                     
                     let current_idx = controls.iter()
@@ -162,6 +285,188 @@ impl SequencerGrid {
         }
     }
     
+    /// Resize every track to this many steps. Steps past the new end are dropped and new steps
+    /// start empty.
+    pub fn set_steps_per_track(&mut self, steps_per_track: usize) {
+        let steps_per_track = steps_per_track.max(1);
+        for track in self.tracks.iter_mut() {
+            track.steps.resize(steps_per_track, StepCell::default());
+            // Tracks playing the whole sequence keep doing so, shorter tracks keep their length
+            track.length = if track.length == self.steps_per_track {
+                steps_per_track
+            } else {
+                track.length.min(steps_per_track)
+            };
+        }
+        self.steps_per_track = steps_per_track;
+        self.cursor.step = self.cursor.step.min((steps_per_track - 1) as u8);
+        self.selection = None;
+        self.fit_loop_region();
+    }
+
+    /// Replace the tracks with saved ones, e.g. from a session file, each resized to
+    /// `steps_per_track` steps, at most MAX_SEQUENCE_LENGTH so a damaged file can't make a grid
+    /// the cursor can't reach. Which control each track has focused is kept.
+    pub fn restore_tracks(&mut self, tracks: Vec<TrackStrip>, steps_per_track: usize) {
+        let steps_per_track = steps_per_track.clamp(1, MAX_SEQUENCE_LENGTH);
+        for (track, restored_track) in self.tracks.iter_mut().zip(tracks) {
+            let selected_control = track.selected_control.clone();
+            *track = restored_track;
+            track.selected_control = selected_control;
+            track.steps.resize(steps_per_track, StepCell::default());
+            track.length = track.length.clamp(1, steps_per_track);
+        }
+        self.steps_per_track = steps_per_track;
+        self.cursor.step = self.cursor.step.min((steps_per_track - 1) as u8);
+        self.selection = None;
+    }
+
+    /// The grid as a track grid at `tempo`, one track for each of the grid's tracks, so it can be
+    /// written out as a DSL script. Each step is a whole-beat DSL step, and a track with a divisor
+    /// of n has its notes n steps apart. The DSL holds a whole-number tempo of at most 255.
+    pub(crate) fn to_track_grid(&self, tempo: f32) -> TrackGrid<FixedTimeNoteSequence> {
+        let tempo = tempo.round().clamp(1.0, u8::MAX as f32) as u8;
+        let step_duration_ms = 60000.0 / tempo as f32;
+        let tracks = self.tracks.iter()
+            .map(|track| {
+                let divisor = track.divisor.max(1);
+                let track_length = track.length.min(track.steps.len());
+                let mut sequence = FixedTimeNoteSequenceBuilder::default()
+                    .duration_type(DurationType::Whole)
+                    .tempo(tempo)
+                    .num_steps(track_length * divisor)
+                    .build().unwrap();
+                for (step_index, step) in track.steps[..track_length].iter().enumerate()
+                    .filter(|(_, step)| step.enabled) {
+                    let track_step_ms = step_duration_ms * divisor as f32;
+                    let start_time_ms = step_index as f32 * track_step_ms;
+                    let end_time_ms = start_time_ms +
+                        gate_duration_ms(step.gate_length, track_step_ms);
+                    let (midi_notes, num_pitches) = step.chord_midi_notes();
+                    for midi_note in midi_notes[..num_pitches].iter() {
+                        sequence.append_note(step.playback_note_at(
+                            PITCH_TO_FREQ_HZ[*midi_note as usize] as f32, start_time_ms,
+                            end_time_ms, track.waveform));
+                    }
+                }
+                TrackBuilder::default()
+                    .volume(track.volume)
+                    .sequence(sequence)
+                    .build().unwrap()
+            })
+            .collect();
+
+        TrackGridBuilder::default()
+            .tracks(tracks)
+            .build().unwrap()
+    }
+
+    /// Number of steps that fit across a grid this many columns wide, at most the whole sequence
+    pub fn visible_step_count(&self, width: u16) -> usize {
+        let max_steps = (width.saturating_sub(TRACK_NUMBER_WIDTH) / STEP_WIDTH) as usize;
+        self.steps_per_track.min(max_steps)
+    }
+
+    /// Scroll a grid this many columns wide so the cursor's step is in view, returning the first
+    /// step shown
+    pub fn scroll_to_cursor(&mut self, width: u16) -> usize {
+        self.step_scroll = step_scroll_offset(self.cursor.step as usize, self.step_scroll,
+                                              self.visible_step_count(width),
+                                              self.steps_per_track);
+        self.step_scroll
+    }
+
+    /// Mark steps off either side of the view, on the row of step numbers
+    pub fn render_scroll_indicators(&self, area: Rect, first_step: usize, visible_steps: usize,
+                                    y: u16, buf: &mut Buffer) {
+        let style = Style::default().fg(Color::Yellow);
+        if first_step > 0 {
+            buf.set_string(area.x, y, "‹", style);
+        }
+        let last_x = area.x + TRACK_NUMBER_WIDTH + visible_steps as u16 * STEP_WIDTH;
+        if first_step + visible_steps < self.steps_per_track && last_x < area.x + area.width {
+            buf.set_string(last_x, y, "›", style);
+        }
+    }
+
+    /// Track, step and row under a screen position in a grid drawn in `area`, scrolled as last
+    /// drawn. The top row of each track is its steps and the one below its frequencies.
+    pub fn cell_at(&self, area: Rect, column: u16, row: u16) -> Option<(u8, u8, CursorFocus)> {
+        if column < area.x + TRACK_NUMBER_WIDTH || row < area.y {
+            return None;
+        }
+        let track = ((row - area.y) / 2) as usize;
+        // Tracks only show while both rows fit above the step numbers
+        let track_rows_end = area.y + area.height.saturating_sub(1);
+        if track >= self.tracks.len() || area.y + track as u16 * 2 + 1 >= track_rows_end {
+            return None;
+        }
+        let visible_step = ((column - area.x - TRACK_NUMBER_WIDTH) / STEP_WIDTH) as usize;
+        if visible_step >= self.visible_step_count(area.width) {
+            return None;
+        }
+        let step = self.step_scroll + visible_step;
+        if step >= self.tracks[track].steps.len() {
+            return None;
+        }
+        let focus = if (row - area.y).is_multiple_of(2) {
+            CursorFocus::Steps
+        } else {
+            CursorFocus::Frequency
+        };
+        Some((track as u8, step as u8, focus))
+    }
+
+    /// Start the loop at the cursor's step. Without a loop, it runs to the end of the sequence.
+    pub fn set_loop_start(&mut self) {
+        let step = self.cursor.step as usize;
+        let loop_end = self.loop_region.map_or(self.steps_per_track - 1, |(_, end)| end);
+        self.loop_region = Some((step, loop_end.max(step)));
+    }
+
+    /// End the loop at the cursor's step. Without a loop, it runs from the start of the sequence.
+    pub fn set_loop_end(&mut self) {
+        let step = self.cursor.step as usize;
+        let loop_start = self.loop_region.map_or(0, |(start, _)| start);
+        self.loop_region = Some((loop_start.min(step), step));
+    }
+
+    pub fn clear_loop(&mut self) {
+        self.loop_region = None;
+    }
+
+    /// Whether a step is inside the loop region
+    pub fn is_in_loop(&self, step: usize) -> bool {
+        self.loop_region.is_some_and(|(start, end)| (start..=end).contains(&step))
+    }
+
+    /// The step played after this one, going back to the start of the loop after its end or to
+    /// the first step after the last
+    pub fn next_playing_step(&self, step: usize) -> usize {
+        match self.loop_region {
+            Some((start, end)) if step < start || step >= end => start,
+            _ => (step + 1) % self.steps_per_track.max(1),
+        }
+    }
+
+    // A shorter sequence cuts the loop at its new end, and drops a loop that starts past it
+    fn fit_loop_region(&mut self) {
+        let last_step = self.steps_per_track - 1;
+        self.loop_region = self.loop_region
+            .filter(|(start, _)| *start <= last_step)
+            .map(|(start, end)| (start, end.min(last_step)));
+    }
+
+    /// Step to the next length in SEQUENCE_LENGTHS, or the previous one for a negative direction
+    pub fn cycle_steps_per_track(&mut self, direction: i8) {
+        let current_idx = SEQUENCE_LENGTHS.iter()
+            .position(|length| *length == self.steps_per_track)
+            .unwrap_or(2);
+        let new_idx = (current_idx as i8 + direction.signum())
+            .clamp(0, SEQUENCE_LENGTHS.len() as i8 - 1);
+        self.set_steps_per_track(SEQUENCE_LENGTHS[new_idx as usize]);
+    }
+
     pub fn switch_focus(&mut self) {
         self.cursor.focus_area = match self.cursor.focus_area {
             CursorFocus::Steps => CursorFocus::TrackControls,
@@ -181,11 +486,147 @@ impl SequencerGrid {
         let track = &mut self.tracks[self.cursor.track as usize];
         let step = &mut track.steps[self.cursor.step as usize];
         
-        step.frequency = if direction > 0 {
-            step.frequency.next()
-        } else {
-            step.frequency.previous()
+        // Step past pitches outside the scale, if there is one. Every scale has its root, so this
+        // stops within an octave.
+        for _ in 0..12 {
+            step.frequency = if direction > 0 {
+                step.frequency.next()
+            } else {
+                step.frequency.previous()
+            };
+            match self.scale {
+                Some((root, scale)) if !scale.contains(root, step.frequency) => continue,
+                _ => break,
+            }
+        }
+    }
+
+    /// Step to the next scale in WesternScale::all_scales(), or the previous one for a negative
+    /// direction, with no scale between the last and the first. The root is kept, starting at C.
+    pub fn cycle_scale(&mut self, direction: i8) {
+        let scales = WesternScale::all_scales();
+        // Index len() is no scale
+        let current_idx = match self.scale {
+            Some((_, scale)) => scales.iter().position(|s| *s == scale).unwrap_or(0),
+            None => scales.len(),
+        };
+        let num_choices = scales.len() as i8 + 1;
+        let new_idx = (current_idx as i8 + direction.signum()).rem_euclid(num_choices) as usize;
+        let root = self.scale.map_or(WesternPitch::C, |(root, _)| root);
+        self.scale = scales.get(new_idx).map(|scale| (root, *scale));
+    }
+
+    /// Step the scale's root up a semitone, or down for a negative direction
+    pub fn cycle_scale_root(&mut self, direction: i8) {
+        if let Some((root, _)) = &mut self.scale {
+            *root = if direction > 0 { root.next() } else { root.previous() };
+        }
+    }
+
+    /// Transpose every step of a track by semitones
+    pub fn transpose_track(&mut self, track_idx: usize, semitones: i8) {
+        if let Some(track) = self.tracks.get_mut(track_idx) {
+            for step in track.steps.iter_mut() {
+                step.transpose(semitones);
+            }
+        }
+    }
+
+    /// Shift a track's steps later in time by `amount`, or earlier when negative, wrapping
+    /// around the track's length. Each step moves whole, its pitch and velocity with it.
+    pub fn rotate_track(&mut self, track_idx: usize, amount: i8) {
+        if let Some(track) = self.tracks.get_mut(track_idx) {
+            let length = track.length.min(track.steps.len());
+            if length == 0 {
+                return;
+            }
+            let shift = (amount as i32).rem_euclid(length as i32) as usize;
+            track.steps[..length].rotate_right(shift);
+        }
+    }
+
+    /// Transpose the steps in the selection by semitones, or the current track without one
+    pub fn transpose_selection(&mut self, semitones: i8) {
+        let Some((start_track, end_track, start_step, end_step)) = self.get_selection_bounds()
+        else {
+            self.transpose_track(self.cursor.track as usize, semitones);
+            return;
         };
+        for track in self.tracks.iter_mut().take(end_track + 1).skip(start_track) {
+            for step in track.steps.iter_mut().take(end_step + 1).skip(start_step) {
+                step.transpose(semitones);
+            }
+        }
+    }
+
+    /// Stack a pitch a third above the step's highest, two degrees up the scale if there is one,
+    /// otherwise alternating major and minor thirds to build a major triad and then a major 7th
+    pub fn add_current_chord_pitch(&mut self) {
+        let scale = self.scale;
+        let track = &mut self.tracks[self.cursor.track as usize];
+        let step = &mut track.steps[self.cursor.step as usize];
+        if step.extra_pitches.len() + 1 >= MAX_CHORD_PITCHES {
+            return;
+        }
+        let mut pitch = *step.extra_pitches.last().unwrap_or(&step.frequency);
+        match scale {
+            Some((root, scale)) => {
+                for _ in 0..2 {
+                    pitch = pitch.next();
+                    while !scale.contains(root, pitch) {
+                        pitch = pitch.next();
+                    }
+                }
+            }
+            None => {
+                let semitones = if step.extra_pitches.len().is_multiple_of(2) { 4 } else { 3 };
+                for _ in 0..semitones {
+                    pitch = pitch.next();
+                }
+            }
+        }
+        step.extra_pitches.push(pitch);
+    }
+
+    /// Remove the highest pitch stacked on the step
+    pub fn remove_current_chord_pitch(&mut self) {
+        let track = &mut self.tracks[self.cursor.track as usize];
+        track.steps[self.cursor.step as usize].extra_pitches.pop();
+    }
+
+    /// Give the current step a pitch and enable it
+    pub fn set_current_step_pitch(&mut self, pitch: WesternPitch, octave: u8) {
+        let track = &mut self.tracks[self.cursor.track as usize];
+        let step = &mut track.steps[self.cursor.step as usize];
+        step.frequency = pitch;
+        step.octave = octave.min(MAX_STEP_OCTAVE);
+        step.enabled = true;
+    }
+
+    pub fn adjust_current_octave(&mut self, delta: i8) {
+        let track = &mut self.tracks[self.cursor.track as usize];
+        let step = &mut track.steps[self.cursor.step as usize];
+        step.octave = (step.octave as i16 + delta as i16).clamp(0, MAX_STEP_OCTAVE as i16) as u8;
+    }
+
+    pub fn adjust_current_probability(&mut self, delta: i8) {
+        let track = &mut self.tracks[self.cursor.track as usize];
+        let step = &mut track.steps[self.cursor.step as usize];
+        step.probability = (step.probability as i16 + delta as i16)
+            .clamp(0, MAX_STEP_PROBABILITY as i16) as u8;
+    }
+
+    pub fn adjust_current_velocity(&mut self, delta: i8) {
+        let track = &mut self.tracks[self.cursor.track as usize];
+        let step = &mut track.steps[self.cursor.step as usize];
+        step.velocity = (step.velocity as i16 + delta as i16)
+            .clamp(0, MAX_STEP_VELOCITY as i16) as u8;
+    }
+
+    pub fn adjust_current_gate_length(&mut self, delta: f32) {
+        let track = &mut self.tracks[self.cursor.track as usize];
+        let step = &mut track.steps[self.cursor.step as usize];
+        step.gate_length = (step.gate_length + delta).clamp(MIN_GATE_LENGTH, MAX_GATE_LENGTH);
     }
 
     pub fn get_current_frequency(&self) -> WesternPitch {
@@ -204,8 +645,29 @@ impl SequencerGrid {
         }
     }
     
+    /// Set the step of the sequencer clock, with each track following it through its own length
     pub fn set_playing_step(&mut self, step: Option<usize>) {
         self.playing_step = step;
+        for (track_playing_step, track) in self.track_playing_steps.iter_mut()
+            .zip(self.tracks.iter()) {
+            *track_playing_step = step.map(|step| step % track.length.max(1));
+        }
+    }
+
+    pub fn set_track_playing_steps(&mut self, steps: [Option<usize>; 8]) {
+        self.track_playing_steps = steps;
+    }
+
+    pub fn adjust_current_track_length(&mut self, delta: i8) {
+        let steps_per_track = self.steps_per_track;
+        let track = &mut self.tracks[self.cursor.track as usize];
+        track.length = (track.length as i16 + delta as i16).clamp(1, steps_per_track as i16) as usize;
+    }
+
+    pub fn adjust_current_track_divisor(&mut self, delta: i8) {
+        let track = &mut self.tracks[self.cursor.track as usize];
+        track.divisor = (track.divisor as i16 + delta as i16)
+            .clamp(1, MAX_TRACK_DIVISOR as i16) as usize;
     }
     
     pub fn adjust_current_track_control(&mut self, delta: f32) {
@@ -215,6 +677,8 @@ impl SequencerGrid {
             TrackControl::Pan => track.adjust_pan(delta),
             TrackControl::Mute => track.toggle_mute(),
             TrackControl::Solo => track.toggle_solo(),
+            TrackControl::Waveform => track.cycle_waveform(if delta > 0.0 { 1 } else { -1 }),
+            TrackControl::Arp => track.cycle_arp_mode(),
         }
     }
     
@@ -277,6 +741,19 @@ impl SequencerGrid {
         }
     }
     
+    /// The step under the cursor, with its pitch, chord, velocity, probability and gate
+    pub fn copy_step(&self) -> StepCell {
+        self.tracks[self.cursor.track as usize].steps[self.cursor.step as usize].clone()
+    }
+
+    /// Replace the step under the cursor with a copied one, in whichever track the cursor is on
+    pub fn paste_step(&mut self, step: &StepCell) {
+        let track = &mut self.tracks[self.cursor.track as usize];
+        if let Some(destination) = track.steps.get_mut(self.cursor.step as usize) {
+            *destination = StepCell { highlighted: destination.highlighted, ..step.clone() };
+        }
+    }
+
     pub fn start_selection(&mut self) {
         self.selection = Some(GridSelection {
             start: self.cursor.clone(),
@@ -363,6 +840,40 @@ impl SequencerGrid {
         }
     }
     
+    /// Set the velocities of each track's selected steps in even steps from `start` at the
+    /// selection's first step to `end` at its last, for crescendos and fades
+    pub fn ramp_velocity(&mut self, start: u8, end: u8) {
+        let Some((start_track, end_track, start_step, end_step)) = self.get_selection_bounds()
+        else {
+            return;
+        };
+        let count = end_step - start_step + 1;
+        for track in self.tracks.iter_mut().take(end_track + 1).skip(start_track) {
+            let selected = track.steps.iter_mut().take(end_step + 1).skip(start_step);
+            for (i, step) in selected.enumerate() {
+                step.velocity = ramp_value(start, end, i, count).min(MAX_STEP_VELOCITY);
+            }
+        }
+    }
+
+    /// Move the pitches of each track's selected steps in even steps from MIDI note `start` at
+    /// the selection's first step to `end` at its last, for sweeps. Chords keep their shape.
+    pub fn ramp_frequency(&mut self, start: u8, end: u8) {
+        let Some((start_track, end_track, start_step, end_step)) = self.get_selection_bounds()
+        else {
+            return;
+        };
+        let count = end_step - start_step + 1;
+        for track in self.tracks.iter_mut().take(end_track + 1).skip(start_track) {
+            let selected = track.steps.iter_mut().take(end_step + 1).skip(start_step);
+            for (i, step) in selected.enumerate() {
+                let midi_note = step.midi_notes()[0] as i16;
+                let target = ramp_value(start, end, i, count) as i16;
+                step.transpose((target - midi_note).clamp(i8::MIN as i16, i8::MAX as i16) as i8);
+            }
+        }
+    }
+
     pub fn get_selection_bounds(&self) -> Option<(usize, usize, usize, usize)> {
         if let Some(selection) = &self.selection {
             let start_step = selection.start.step.min(selection.end.step) as usize;
@@ -387,8 +898,12 @@ impl TrackStrip {
             pan: 0.0,
             mute: false,
             solo: false,
+            waveform: TRACK_WAVEFORMS[0],
+            length: steps,
+            divisor: 1,
             steps: track_steps,
             selected_control: TrackControl::Volume,
+            arp_mode: None,
         }
     }
     
@@ -407,20 +922,194 @@ impl TrackStrip {
     pub fn toggle_solo(&mut self) {
         self.solo = !self.solo;
     }
+
+    /// Enable the steps of a Euclidean rhythm spreading hits evenly across the track's length,
+    /// leaving each step's pitch and other settings as they are
+    pub fn fill_euclidean(&mut self, hits: usize, rotation: usize) {
+        let length = self.length.min(self.steps.len());
+        for (step, hit) in self.steps.iter_mut().zip(euclidean(hits, length, rotation)) {
+            step.enabled = hit;
+        }
+    }
+
+    /// Step to the next mode in ARP_MODES, with no arpeggio after the last
+    pub fn cycle_arp_mode(&mut self) {
+        self.arp_mode = match self.arp_mode {
+            None => Some(ARP_MODES[0]),
+            Some(arp_mode) => ARP_MODES.iter()
+                .position(|mode| *mode == arp_mode)
+                .and_then(|idx| ARP_MODES.get(idx + 1))
+                .copied(),
+        };
+    }
+
+    /// One character for the arp mode where there is no room to spell it out, blank with no
+    /// arpeggio
+    pub fn arp_symbol(&self) -> char {
+        match self.arp_mode {
+            None => ' ',
+            Some(ArpMode::Up) => '↑',
+            Some(ArpMode::Down) => '↓',
+            Some(ArpMode::UpDown) => '↕',
+            Some(ArpMode::Random) => '?',
+        }
+    }
+
+    /// Step to the next waveform in TRACK_WAVEFORMS, or the previous one for a negative direction
+    pub fn cycle_waveform(&mut self, direction: i8) {
+        let current_idx = TRACK_WAVEFORMS.iter()
+            .position(|waveform| *waveform == self.waveform)
+            .unwrap_or(0);
+        let num_waveforms = TRACK_WAVEFORMS.len() as i8;
+        let new_idx = (current_idx as i8 + direction.signum()).rem_euclid(num_waveforms);
+        self.waveform = TRACK_WAVEFORMS[new_idx as usize];
+    }
 }
 
 impl Default for StepCell {
     fn default() -> Self {
         Self {
             enabled: false,
-            velocity: 127,
+            velocity: MAX_STEP_VELOCITY,
             frequency: WesternPitch::C,
+            octave: DEFAULT_STEP_OCTAVE,
+            extra_pitches: Vec::new(),
+            probability: MAX_STEP_PROBABILITY,
+            gate_length: DEFAULT_GATE_LENGTH,
             note: None,
             highlighted: false,
         }
     }
 }
 
+impl StepCell {
+    /// Grid symbol for the step, steps that may not sound are drawn half filled
+    pub fn symbol(&self) -> &'static str {
+        if !self.enabled {
+            "·"
+        } else if self.probability < MAX_STEP_PROBABILITY {
+            "◐"
+        } else {
+            "●"
+        }
+    }
+
+    /// Bar for how hard the step plays, higher for louder, or a space for a step that is off
+    pub fn velocity_bar(&self) -> char {
+        if !self.enabled {
+            return ' ';
+        }
+        let level = self.velocity.min(MAX_STEP_VELOCITY) as usize * VELOCITY_BARS.len() /
+            (MAX_STEP_VELOCITY as usize + 1);
+        VELOCITY_BARS[level]
+    }
+
+    /// Frequency of the step's pitch in its octave
+    pub fn frequency_hz(&self) -> f32 {
+        // get_frequency counts octaves from MIDI note 0, one below scientific pitch notation
+        self.frequency.get_frequency(self.octave.min(MAX_STEP_OCTAVE) + 1)
+    }
+
+    /// Pitch name and octave, e.g. C#3
+    pub fn pitch_label(&self) -> String {
+        format!("{}{}", self.frequency, self.octave)
+    }
+
+    /// MIDI note numbers of every pitch the step sounds, its own first
+    pub fn midi_notes(&self) -> Vec<u8> {
+        let (midi_notes, num_pitches) = self.chord_midi_notes();
+        midi_notes[..num_pitches].to_vec()
+    }
+
+    /// The step's MIDI notes in a fixed array with the number in use, so the audio thread can
+    /// read them without allocating. A chord keeps at most MAX_CHORD_PITCHES pitches.
+    pub fn chord_midi_notes(&self) -> ([u8; MAX_CHORD_PITCHES], usize) {
+        let mut midi_notes = [0; MAX_CHORD_PITCHES];
+        midi_notes[0] = self.frequency.to_midi_note(self.octave.min(MAX_STEP_OCTAVE));
+        let mut num_pitches = 1;
+        for pitch in self.extra_pitches.iter().take(MAX_CHORD_PITCHES - 1) {
+            let previous = midi_notes[num_pitches - 1];
+            let mut midi_note = previous - previous % 12 + pitch.get_pitch_index();
+            if midi_note <= previous {
+                midi_note += 12;
+            }
+            midi_notes[num_pitches] = midi_note.min(127);
+            num_pitches += 1;
+        }
+        (midi_notes, num_pitches)
+    }
+
+    /// Short name for the step's pitches, the pitch and octave for a single pitch or the root and
+    /// quality for a chord, e.g. C, Cm, C°, C+, C7, CΔ (major 7th) or Cm7, and C* for any other
+    pub fn chord_label(&self) -> String {
+        if self.extra_pitches.is_empty() {
+            return self.pitch_label();
+        }
+        let root = self.midi_notes()[0];
+        let mut intervals: Vec<u8> = self.midi_notes()[1..].iter()
+            .map(|midi_note| (midi_note - root) % 12)
+            .collect();
+        intervals.sort();
+        intervals.dedup();
+        let quality = match intervals.as_slice() {
+            [4, 7] => "",
+            [3, 7] => "m",
+            [3, 6] => "°",
+            [4, 8] => "+",
+            [4, 7, 10] => "7",
+            [4, 7, 11] => "Δ",
+            [3, 7, 10] => "m7",
+            _ => "*",
+        };
+        format!("{}{}", self.frequency, quality)
+    }
+
+    /// Move the step's pitches by semitones, carrying into the octave above or below. Steps stop
+    /// at the lowest and highest octave, with a chord keeping its shape.
+    pub fn transpose(&mut self, semitones: i8) {
+        let lowest = WesternPitch::C.to_midi_note(0) as i16;
+        let highest = WesternPitch::B.to_midi_note(MAX_STEP_OCTAVE) as i16;
+        let midi_note = self.frequency.to_midi_note(self.octave.min(MAX_STEP_OCTAVE)) as i16;
+        let transposed = (midi_note + semitones as i16).clamp(lowest, highest);
+        (self.frequency, self.octave) = WesternPitch::from_midi_note(transposed as u8);
+
+        let shift = (transposed - midi_note).rem_euclid(12);
+        for pitch in self.extra_pitches.iter_mut() {
+            for _ in 0..shift {
+                *pitch = pitch.next();
+            }
+        }
+    }
+
+    /// Build the note this step plays, with the step's pitch and velocity and the track's waveform
+    pub fn to_playback_note(&self, start_time_ms: f32, end_time_ms: f32,
+                            waveform: Waveform) -> PlaybackNote {
+        self.playback_note_at(self.frequency_hz(), start_time_ms, end_time_ms, waveform)
+    }
+
+    /// Build a note at a frequency with the step's velocity and the track's waveform
+    pub fn playback_note_at(&self, frequency: f32, start_time_ms: f32, end_time_ms: f32,
+                        waveform: Waveform) -> PlaybackNote {
+        let note = NoteBuilder::default()
+            .frequency(frequency)
+            .start_time_ms(start_time_ms)
+            .end_time_ms(end_time_ms)
+            .waveforms(vec![waveform])
+            .build().unwrap();
+
+        PlaybackNoteBuilder::default()
+            .note_type(NoteType::Oscillator)
+            .note(note)
+            .playback_start_time_ms(start_time_ms)
+            .playback_end_time_ms(end_time_ms)
+            .playback_sample_start_time((start_time_ms * (SAMPLE_RATE / 1000.0)).floor() as u64)
+            .playback_sample_end_time((end_time_ms * (SAMPLE_RATE / 1000.0)).floor() as u64)
+            .envelopes(vec![default_envelope()])
+            .velocity(self.velocity)
+            .build().unwrap()
+    }
+}
+
 impl Widget for SequencerGrid {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let style = if self.focused {
@@ -456,11 +1145,12 @@ impl Widget for SequencerGrid {
             buf.set_string(x, y_steps, &format!("{}", track.track_number), track_style);
             let mut step_x = x + 2;
             
-            // Step cells - show as many steps as will fit, up to 16
-            let max_steps = ((step_area_width.saturating_sub(2)) / 4) as usize; // 4 chars per step
-            let visible_steps = self.steps_per_track.min(max_steps);
+            // Step cells - show as many steps as will fit, scrolled to keep the cursor in view
+            let visible_steps = self.visible_step_count(step_area_width);
+            let first_step = step_scroll_offset(self.cursor.step as usize, self.step_scroll,
+                                                visible_steps, self.steps_per_track);
             
-            for step_idx in 0..visible_steps {
+            for step_idx in first_step..first_step + visible_steps {
                 if step_idx >= track.steps.len() {
                     break;
                 }
@@ -475,16 +1165,18 @@ impl Widget for SequencerGrid {
                 let is_freq_dropdown = self.cursor.track == track_idx as u8 && 
                                       self.cursor.step == step_idx as u8 &&
                                       self.cursor.focus_area == CursorFocus::FrequencyDropdown;
-                let is_playing = self.playing_step == Some(step_idx);
+                let is_playing = self.track_playing_steps[track_idx] == Some(step_idx);
                 let is_selected = self.is_step_selected(track_idx as u8, step_idx as u8);
                 
-                // Step cell style
+                // Step cell style, steps past the end of the track are dimmed
                 let step_style = if is_step_cursor {
                     Style::default().fg(Color::Yellow).bg(Color::DarkGray)
                 } else if is_playing {
                     Style::default().fg(Color::Green).bg(Color::Black)
                 } else if is_selected {
                     Style::default().fg(Color::White).bg(Color::Blue)
+                } else if step_idx >= track.length {
+                    Style::default().fg(Color::DarkGray)
                 } else {
                     style
                 };
@@ -503,20 +1195,23 @@ impl Widget for SequencerGrid {
                     Style::default().fg(Color::LightGreen)
                 };
                 
-                // Render step cell
-                let symbol = if step.enabled { "●" } else { "·" };
+                // Render step cell, with the step's velocity in the gap before the next one
+                let symbol = step.symbol();
                 buf.set_string(step_x, y_steps, &format!(" {} ", symbol), step_style);
+                buf.set_string(step_x + 3, y_steps, step.velocity_bar().to_string(),
+                               Style::default().fg(Color::DarkGray));
                 
-                // Render frequency cell - match the step cell format for alignment
+                // Render frequency cell - match the step cell format for alignment, with a marker
+                // in place of the leading space so a sharp and its octave still fit
                 let freq_text = if step.enabled {
                     if is_freq_dropdown {
-                        // Show active dropdown with special indicators
-                        format!("▼{}▲", step.frequency)
+                        // Show active dropdown with special indicator
+                        format!("▼{:<3}", step.chord_label())
                     } else if is_freq_cursor {
-                        // Show selectable frequency with brackets
-                        format!("[{}]", step.frequency)
+                        // Show selectable frequency with a pointer
+                        format!("›{:<3}", step.chord_label())
                     } else {
-                        format!(" {} ", step.frequency)
+                        format!(" {:<3}", step.chord_label())
                     }
                 } else {
                     " · ".to_string()
@@ -551,13 +1246,17 @@ impl Widget for SequencerGrid {
         if step_numbers_y < area.y + area.height {
             let mut x = area.x + 2; // Offset for track numbers
             
-            let max_steps = ((step_area_width.saturating_sub(2)) / 4) as usize;
-            let visible_steps = self.steps_per_track.min(max_steps);
+            let visible_steps = self.visible_step_count(step_area_width);
+            let first_step = step_scroll_offset(self.cursor.step as usize, self.step_scroll,
+                                                visible_steps, self.steps_per_track);
             
-            for step in 1..=visible_steps {
-                buf.set_string(x, step_numbers_y, &format!("{:^4}", step), style);
+            for step in first_step + 1..=first_step + visible_steps {
+                buf.set_string(x, step_numbers_y, format!("{:^4}", step), style);
                 x += 4;
             }
+            let step_area = Rect { width: step_area_width, ..area };
+            self.render_scroll_indicators(step_area, first_step, visible_steps, step_numbers_y,
+                                          buf);
         }
     }
 }
@@ -615,5 +1314,479 @@ impl SequencerGrid {
         let pan_display: String = pan_display.into_iter().collect();
         let pan_text = format!("L {} R {:+}%", pan_display, pan_percent);
         buf.set_string(x + 25, y, &pan_text, pan_style);
+
+        // Waveform control on the frequency row: "W Sine"
+        let wave_style = if is_track_focused && track.selected_control == TrackControl::Waveform {
+            Style::default().fg(Color::Yellow).bg(Color::DarkGray)
+        } else {
+            base_style
+        };
+        buf.set_string(x, y + 1, format!("W {:?}", track.waveform), wave_style);
+
+        // Arpeggiator control under the pan: "A UpDown"
+        let arp_style = if is_track_focused && track.selected_control == TrackControl::Arp {
+            Style::default().fg(Color::Yellow).bg(Color::DarkGray)
+        } else {
+            base_style
+        };
+        let arp_text = match track.arp_mode {
+            Some(arp_mode) => format!("A {:?}", arp_mode),
+            None => "A Off".to_string(),
+        };
+        buf.set_string(x + 25, y + 1, arp_text, arp_style);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test_grid {
+    use crate::note::scales::{WesternPitch, WesternScale};
+    use crate::common::float_utils::assert_float_eq;
+    use crate::note::constants::PITCH_TO_FREQ_HZ;
+    use crate::audio_gen::oscillator::Waveform;
+    use crate::sequence::arpeggiator::ArpMode;
+    use crate::dsl::parser::parse_dsl;
+    use crate::dsl::serializer::track_grid_to_dsl;
+    use crate::tui::ui::widgets::grid::{step_scroll_offset, CursorFocus, SequencerGrid,
+                                        MAX_CHORD_PITCHES, MAX_STEP_OCTAVE, MAX_STEP_VELOCITY,
+                                        MAX_SEQUENCE_LENGTH};
+    use ratatui::buffer::Buffer;
+    use ratatui::layout::Rect;
+    use ratatui::widgets::Widget;
+
+    #[test]
+    fn test_adjust_frequency_chromatic() {
+        let mut grid = SequencerGrid::new(16);
+        grid.adjust_current_frequency(1);
+        assert_eq!(grid.get_current_frequency(), WesternPitch::CSharp);
+    }
+
+    #[test]
+    fn test_adjust_frequency_in_scale() {
+        let mut grid = SequencerGrid::new(16);
+        grid.scale = Some((WesternPitch::C, WesternScale::Major));
+        grid.adjust_current_frequency(1);
+        assert_eq!(grid.get_current_frequency(), WesternPitch::D);
+        grid.adjust_current_frequency(1);
+        assert_eq!(grid.get_current_frequency(), WesternPitch::E);
+        grid.adjust_current_frequency(1);
+        assert_eq!(grid.get_current_frequency(), WesternPitch::F);
+        // down from C wraps to B
+        grid.adjust_current_frequency(-1);
+        grid.adjust_current_frequency(-1);
+        grid.adjust_current_frequency(-1);
+        grid.adjust_current_frequency(-1);
+        assert_eq!(grid.get_current_frequency(), WesternPitch::B);
+    }
+
+    #[test]
+    fn test_cycle_scale() {
+        let mut grid = SequencerGrid::new(16);
+        grid.cycle_scale(1);
+        assert_eq!(grid.scale, Some((WesternPitch::C, WesternScale::Major)));
+        grid.cycle_scale_root(1);
+        grid.cycle_scale(1);
+        assert_eq!(grid.scale, Some((WesternPitch::CSharp, WesternScale::Minor)));
+        grid.cycle_scale(-1);
+        grid.cycle_scale(-1);
+        assert_eq!(grid.scale, None);
+        grid.cycle_scale(-1);
+        assert_eq!(grid.scale, Some((WesternPitch::C, WesternScale::WholeTone)));
+    }
+
+    #[test]
+    fn test_octave_changes_frequency() {
+        let mut grid = SequencerGrid::new(16);
+        let step = &grid.tracks[0].steps[0];
+        // the default octave keeps steps sounding C3
+        assert_eq!(step.pitch_label(), "C3");
+        assert_float_eq(step.frequency_hz(), PITCH_TO_FREQ_HZ[48] as f32);
+
+        grid.adjust_current_octave(1);
+        let step = &grid.tracks[0].steps[0];
+        assert_eq!(step.octave, 4);
+        assert_float_eq(step.to_playback_note(0.0, 100.0, Waveform::Sine).note.frequency,
+                        PITCH_TO_FREQ_HZ[60] as f32);
+
+        grid.adjust_current_octave(-2);
+        assert_float_eq(grid.tracks[0].steps[0].frequency_hz(), PITCH_TO_FREQ_HZ[36] as f32);
+    }
+
+    #[test]
+    fn test_octave_limits() {
+        let mut grid = SequencerGrid::new(16);
+        grid.adjust_current_octave(-10);
+        assert_eq!(grid.tracks[0].steps[0].octave, 0);
+        grid.adjust_current_octave(20);
+        assert_eq!(grid.tracks[0].steps[0].octave, MAX_STEP_OCTAVE);
+    }
+
+    #[test]
+    fn test_add_chord_pitches() {
+        let mut grid = SequencerGrid::new(16);
+        grid.add_current_chord_pitch();
+        grid.add_current_chord_pitch();
+        let step = &grid.tracks[0].steps[0];
+        assert_eq!(step.extra_pitches, vec![WesternPitch::E, WesternPitch::G]);
+        assert_eq!(step.chord_label(), "C");
+
+        grid.add_current_chord_pitch();
+        assert_eq!(grid.tracks[0].steps[0].chord_label(), "CΔ");
+        // no more than MAX_CHORD_PITCHES
+        grid.add_current_chord_pitch();
+        assert_eq!(grid.tracks[0].steps[0].extra_pitches.len(), MAX_CHORD_PITCHES - 1);
+
+        grid.remove_current_chord_pitch();
+        grid.remove_current_chord_pitch();
+        grid.remove_current_chord_pitch();
+        assert_eq!(grid.tracks[0].steps[0].chord_label(), "C3");
+    }
+
+    #[test]
+    fn test_add_chord_pitches_in_scale() {
+        let mut grid = SequencerGrid::new(16);
+        grid.scale = Some((WesternPitch::A, WesternScale::Minor));
+        grid.tracks[0].steps[0].frequency = WesternPitch::A;
+        grid.add_current_chord_pitch();
+        grid.add_current_chord_pitch();
+        assert_eq!(grid.tracks[0].steps[0].extra_pitches, vec![WesternPitch::C, WesternPitch::E]);
+        assert_eq!(grid.tracks[0].steps[0].chord_label(), "Am");
+        // stacked pitches go up through the octave
+        assert_eq!(grid.tracks[0].steps[0].midi_notes(), vec![57, 60, 64]);
+    }
+
+    #[test]
+    fn test_transpose() {
+        let mut grid = SequencerGrid::new(16);
+        grid.transpose_track(0, 2);
+        assert_eq!(grid.tracks[0].steps[0].pitch_label(), "D3");
+        // B wraps to C in the next octave
+        grid.transpose_track(0, 9);
+        assert_eq!(grid.tracks[0].steps[5].pitch_label(), "B3");
+        grid.transpose_track(0, 1);
+        assert_eq!(grid.tracks[0].steps[15].pitch_label(), "C4");
+        grid.transpose_track(0, -13);
+        assert_eq!(grid.tracks[0].steps[0].pitch_label(), "B2");
+        // other tracks are untouched
+        assert_eq!(grid.tracks[1].steps[0].pitch_label(), "C3");
+    }
+
+    #[test]
+    fn test_transpose_chord_and_limits() {
+        let mut grid = SequencerGrid::new(16);
+        grid.add_current_chord_pitch();
+        grid.add_current_chord_pitch();
+        grid.transpose_track(0, 12);
+        assert_eq!(grid.tracks[0].steps[0].midi_notes(), vec![60, 64, 67]);
+        grid.transpose_track(0, -5);
+        assert_eq!(grid.tracks[0].steps[0].chord_label(), "G");
+
+        // the lowest step stops at C0 and its chord keeps its shape
+        grid.transpose_track(0, -100);
+        assert_eq!(grid.tracks[0].steps[0].midi_notes(), vec![12, 16, 19]);
+    }
+
+    #[test]
+    fn test_transpose_selection() {
+        let mut grid = SequencerGrid::new(16);
+        grid.cursor.track = 1;
+        grid.cursor.step = 2;
+        grid.start_selection();
+        grid.cursor.track = 2;
+        grid.cursor.step = 3;
+        grid.update_selection();
+        grid.transpose_selection(-12);
+        assert_eq!(grid.tracks[1].steps[2].pitch_label(), "C2");
+        assert_eq!(grid.tracks[2].steps[3].pitch_label(), "C2");
+        assert_eq!(grid.tracks[2].steps[4].pitch_label(), "C3");
+        assert_eq!(grid.tracks[0].steps[2].pitch_label(), "C3");
+    }
+
+    fn select_steps(grid: &mut SequencerGrid, tracks: (u8, u8), steps: (u8, u8)) {
+        grid.cursor.track = tracks.0;
+        grid.cursor.step = steps.0;
+        grid.start_selection();
+        grid.cursor.track = tracks.1;
+        grid.cursor.step = steps.1;
+        grid.update_selection();
+    }
+
+    #[test]
+    fn test_ramp_velocity_spaces_velocities_evenly() {
+        let mut grid = SequencerGrid::new(16);
+        select_steps(&mut grid, (0, 1), (4, 7));
+        grid.ramp_velocity(0, 120);
+        for track in 0..2 {
+            let velocities: Vec<u8> = grid.tracks[track].steps[4..8].iter()
+                .map(|step| step.velocity)
+                .collect();
+            assert_eq!(velocities, vec![0, 40, 80, 120]);
+        }
+        // Outside the selection nothing changes
+        assert_eq!(grid.tracks[0].steps[3].velocity, MAX_STEP_VELOCITY);
+        assert_eq!(grid.tracks[2].steps[4].velocity, MAX_STEP_VELOCITY);
+
+        // Ramps run down as well as up
+        grid.ramp_velocity(100, 10);
+        assert_eq!(grid.tracks[0].steps[5].velocity, 70);
+    }
+
+    #[test]
+    fn test_ramp_frequency_sweeps_pitches() {
+        let mut grid = SequencerGrid::new(16);
+        select_steps(&mut grid, (0, 0), (0, 4));
+        grid.ramp_frequency(WesternPitch::C.to_midi_note(4), WesternPitch::C.to_midi_note(5));
+        let labels: Vec<String> = grid.tracks[0].steps[0..5].iter()
+            .map(|step| step.pitch_label())
+            .collect();
+        assert_eq!(labels, vec!["C4", "D#4", "F#4", "A4", "C5"]);
+
+        // Without a selection there is nothing to ramp
+        grid.clear_selection();
+        grid.ramp_velocity(0, 0);
+        assert_eq!(grid.tracks[0].steps[0].velocity, MAX_STEP_VELOCITY);
+    }
+
+    #[test]
+    fn test_cycle_arp_mode() {
+        let mut grid = SequencerGrid::new(16);
+        let track = &mut grid.tracks[0];
+        track.cycle_arp_mode();
+        assert_eq!(track.arp_mode, Some(ArpMode::Up));
+        for _ in 0..3 {
+            track.cycle_arp_mode();
+        }
+        assert_eq!(track.arp_mode, Some(ArpMode::Random));
+        track.cycle_arp_mode();
+        assert_eq!(track.arp_mode, None);
+    }
+
+    #[test]
+    fn test_adjust_velocity_is_clamped() {
+        let mut grid = SequencerGrid::new(16);
+        grid.adjust_current_velocity(8);
+        assert_eq!(grid.tracks[0].steps[0].velocity, MAX_STEP_VELOCITY);
+        grid.adjust_current_velocity(-100);
+        assert_eq!(grid.tracks[0].steps[0].velocity, 27);
+        grid.adjust_current_velocity(-100);
+        assert_eq!(grid.tracks[0].steps[0].velocity, 0);
+        grid.adjust_current_velocity(1);
+        assert_eq!(grid.tracks[0].steps[0].velocity, 1);
+    }
+
+    #[test]
+    fn test_velocity_bar() {
+        let mut grid = SequencerGrid::new(16);
+        let step = &mut grid.tracks[0].steps[0];
+        assert_eq!(step.velocity_bar(), ' ');
+        step.enabled = true;
+        assert_eq!(step.velocity_bar(), '█');
+        step.velocity = 0;
+        assert_eq!(step.velocity_bar(), '▁');
+        step.velocity = 64;
+        assert_eq!(step.velocity_bar(), '▅');
+    }
+
+    #[test]
+    fn test_paste_step_replicates_it() {
+        let mut grid = SequencerGrid::new(16);
+        grid.cursor.step = 2;
+        grid.set_current_step_pitch(WesternPitch::E, 5);
+        grid.adjust_current_velocity(-20);
+        let step = grid.copy_step();
+
+        grid.cursor.track = 3;
+        grid.cursor.step = 9;
+        grid.paste_step(&step);
+        let pasted = &grid.tracks[3].steps[9];
+        assert!(pasted.enabled);
+        assert_eq!(pasted.frequency, WesternPitch::E);
+        assert_eq!(pasted.octave, 5);
+        assert_eq!(pasted.velocity, step.velocity);
+        assert_eq!(pasted.gate_length, step.gate_length);
+        // The copied step is left as it was
+        assert!(grid.tracks[0].steps[2].enabled);
+        assert!(!grid.tracks[3].steps[8].enabled);
+    }
+
+    #[test]
+    fn test_rotate_track_wraps_hits() {
+        let mut grid = SequencerGrid::new(8);
+        for (step, pitch) in [(0, WesternPitch::C), (3, WesternPitch::E), (7, WesternPitch::G)] {
+            grid.tracks[1].steps[step].enabled = true;
+            grid.tracks[1].steps[step].frequency = pitch;
+            grid.tracks[1].steps[step].velocity = 10 * step as u8;
+        }
+
+        grid.rotate_track(1, 1);
+        let hits: Vec<_> = grid.tracks[1].steps.iter()
+            .enumerate()
+            .filter(|(_, step)| step.enabled)
+            .map(|(i, step)| (i, step.frequency, step.velocity))
+            .collect();
+        assert_eq!(hits, vec![(0, WesternPitch::G, 70), (1, WesternPitch::C, 0),
+                              (4, WesternPitch::E, 30)]);
+
+        grid.rotate_track(1, -1);
+        assert!(grid.tracks[1].steps[0].enabled && grid.tracks[1].steps[7].enabled);
+        assert!(!grid.tracks[0].steps.iter().any(|step| step.enabled));
+    }
+
+    #[test]
+    fn test_rotate_track_stays_within_its_length() {
+        let mut grid = SequencerGrid::new(8);
+        grid.tracks[0].length = 4;
+        grid.tracks[0].steps[3].enabled = true;
+        grid.tracks[0].steps[6].enabled = true;
+        grid.rotate_track(0, 1);
+        assert!(grid.tracks[0].steps[0].enabled);
+        // Steps past the track's length don't move
+        assert!(grid.tracks[0].steps[6].enabled);
+        assert!(!grid.tracks[0].steps[4].enabled);
+    }
+
+    #[test]
+    fn test_frequency_cells_show_octave() {
+        let mut grid = SequencerGrid::new(16);
+        grid.set_current_step_pitch(WesternPitch::FSharp, 4);
+        grid.cursor.step = 1;
+        grid.set_current_step_pitch(WesternPitch::C, 5);
+        grid.cursor.focus_area = CursorFocus::Frequency;
+        let area = Rect::new(0, 0, 120, 3);
+        let mut buf = Buffer::empty(area);
+        grid.render(area, &mut buf);
+
+        // Below the steps, each cell four columns wide after the track number
+        let frequency_row: String = (2..10).map(|x| buf.get(x, 1).symbol()).collect();
+        assert_eq!(frequency_row, " F#4›C5 ");
+    }
+
+    #[test]
+    fn test_set_loop_at_cursor() {
+        let mut grid = SequencerGrid::new(16);
+        grid.cursor.step = 4;
+        grid.set_loop_start();
+        assert_eq!(grid.loop_region, Some((4, 15)));
+        grid.cursor.step = 8;
+        grid.set_loop_end();
+        assert_eq!(grid.loop_region, Some((4, 8)));
+        // An end before the start moves the start back to it
+        grid.cursor.step = 2;
+        grid.set_loop_end();
+        assert_eq!(grid.loop_region, Some((2, 2)));
+        grid.clear_loop();
+        grid.set_loop_end();
+        assert_eq!(grid.loop_region, Some((0, 2)));
+    }
+
+    #[test]
+    fn test_next_playing_step_wraps_in_loop() {
+        let mut grid = SequencerGrid::new(16);
+        assert_eq!(grid.next_playing_step(15), 0);
+        grid.loop_region = Some((4, 8));
+        assert_eq!(grid.next_playing_step(4), 5);
+        assert_eq!(grid.next_playing_step(8), 4);
+        // Outside the loop, as when it was just set, playback jumps into it
+        assert_eq!(grid.next_playing_step(12), 4);
+    }
+
+    #[test]
+    fn test_shorter_sequence_fits_loop() {
+        let mut grid = SequencerGrid::new(16);
+        grid.loop_region = Some((4, 12));
+        grid.set_steps_per_track(8);
+        assert_eq!(grid.loop_region, Some((4, 7)));
+        grid.loop_region = Some((10, 12));
+        grid.set_steps_per_track(16);
+        grid.set_steps_per_track(8);
+        assert_eq!(grid.loop_region, None);
+    }
+
+    #[test]
+    fn test_step_scroll_offset_follows_cursor() {
+        // 32 steps with 10 in view, the view holds still while the cursor moves within it
+        assert_eq!(step_scroll_offset(9, 0, 10, 32), 0);
+        // and moves just enough to show the cursor past either edge
+        assert_eq!(step_scroll_offset(10, 0, 10, 32), 1);
+        assert_eq!(step_scroll_offset(29, 0, 10, 32), 20);
+        assert_eq!(step_scroll_offset(15, 20, 10, 32), 15);
+        assert_eq!(step_scroll_offset(22, 20, 10, 32), 20);
+        // never past the last step, as when the sequence gets shorter
+        assert_eq!(step_scroll_offset(12, 20, 10, 16), 6);
+        // with every step in view there is nothing to scroll
+        assert_eq!(step_scroll_offset(15, 3, 16, 16), 0);
+    }
+
+    #[test]
+    fn test_scroll_to_cursor_keeps_cursor_visible() {
+        let mut grid = SequencerGrid::new(32);
+        // Room for 10 steps
+        let width = 42;
+        assert_eq!(grid.visible_step_count(width), 10);
+        for step in 0..32 {
+            grid.cursor.step = step;
+            let first_step = grid.scroll_to_cursor(width) as u8;
+            assert!((first_step..first_step + 10).contains(&step));
+        }
+        assert_eq!(grid.step_scroll, 22);
+    }
+
+    #[test]
+    fn test_cell_at() {
+        let mut grid = SequencerGrid::new(32);
+        let area = Rect::new(10, 5, 42, 20);
+        assert_eq!(grid.cell_at(area, 12, 5), Some((0, 0, CursorFocus::Steps)));
+        assert_eq!(grid.cell_at(area, 15, 5), Some((0, 0, CursorFocus::Steps)));
+        assert_eq!(grid.cell_at(area, 16, 6), Some((0, 1, CursorFocus::Frequency)));
+        assert_eq!(grid.cell_at(area, 51, 20), Some((7, 9, CursorFocus::Frequency)));
+        // Track numbers, steps past the view and rows below the last track
+        assert_eq!(grid.cell_at(area, 11, 5), None);
+        assert_eq!(grid.cell_at(area, 52, 5), None);
+        assert_eq!(grid.cell_at(area, 12, 21), None);
+        assert_eq!(grid.cell_at(area, 9, 5), None);
+        assert_eq!(grid.cell_at(area, 12, 4), None);
+
+        // Scrolled steps are offset, and a short grid only hit tests the tracks it shows
+        grid.step_scroll = 22;
+        assert_eq!(grid.cell_at(area, 12, 5), Some((0, 22, CursorFocus::Steps)));
+        let short_area = Rect::new(0, 0, 42, 5);
+        assert_eq!(grid.cell_at(short_area, 2, 3), Some((1, 22, CursorFocus::Frequency)));
+        assert_eq!(grid.cell_at(short_area, 2, 4), None);
+    }
+
+    #[test]
+    fn test_restore_tracks_keeps_to_the_longest_sequence() {
+        let mut grid = SequencerGrid::new(16);
+        let tracks = grid.tracks.to_vec();
+        grid.cursor.step = 15;
+
+        grid.restore_tracks(tracks, 1000);
+
+        assert_eq!(grid.steps_per_track, MAX_SEQUENCE_LENGTH);
+        assert_eq!(grid.tracks[0].steps.len(), grid.steps_per_track);
+        assert_eq!(grid.cursor.step, 15);
+    }
+
+    #[test]
+    fn test_to_track_grid_round_trips_through_dsl() {
+        let mut grid = SequencerGrid::new(16);
+        grid.tracks[0].steps[0].enabled = true;
+        grid.tracks[0].steps[4].enabled = true;
+        grid.tracks[0].steps[4].extra_pitches = vec![WesternPitch::E, WesternPitch::G];
+        // A track moving every other tick has its second step two beats in
+        grid.tracks[1].divisor = 2;
+        grid.tracks[1].steps[1].enabled = true;
+
+        let dsl = track_grid_to_dsl(&grid.to_track_grid(120.0));
+        let track_grid = parse_dsl(&dsl).unwrap();
+
+        assert_eq!(track_grid.tracks.len(), grid.tracks.len());
+        let first_track_notes = track_grid.tracks[0].sequence.get_all_notes();
+        assert_eq!(first_track_notes.len(), 4);
+        assert_float_eq(first_track_notes[1].playback_start_time_ms, 2000.0);
+        assert_float_eq(first_track_notes[3].note.frequency,
+                        PITCH_TO_FREQ_HZ[grid.tracks[0].steps[4].midi_notes()[2] as usize] as f32);
+        let second_track_notes = track_grid.tracks[1].sequence.get_all_notes();
+        assert_eq!(second_track_notes.len(), 1);
+        assert_float_eq(second_track_notes[0].playback_start_time_ms, 1000.0);
+        assert!(track_grid.tracks[2].sequence.get_all_notes().is_empty());
+    }
+}
@@ -6,6 +6,8 @@ use ratatui::{
 };
 use std::time::Duration;
 
+use crate::common::gain::linear_to_db;
+
 #[derive(Debug, Clone)]
 pub struct LevelMeter {
     pub level: f32,
@@ -41,6 +43,12 @@ impl LevelMeter {
         }
     }
     
+    /// Raise the held peak to a peak measured apart from the level, such as a block's loudest
+    /// sample beside its RMS
+    pub fn update_peak(&mut self, peak: f32) {
+        self.peak = self.peak.max(peak.clamp(0.0, 1.0));
+    }
+    
     pub fn decay_peak(&mut self, _delta_time: Duration) {
         // Simple peak decay
         let decay_rate = 0.95;
@@ -65,13 +73,7 @@ impl LevelMeter {
             }
         }
         
-        let db_level = if self.level > 0.0 {
-            20.0 * self.level.log10()
-        } else {
-            -96.0
-        };
-        
-        format!("{} {:+.1}dB", meter, db_level)
+        format!("{} {:+.1}dB", meter, linear_to_db(self.level))
     }
 }
 
@@ -1,9 +1,13 @@
 pub mod slider;
 pub mod selector;
 pub mod meter;
+pub mod scope;
 pub mod grid;
 
-pub use slider::{LinearSlider, LogSlider, TimeSlider};
-pub use selector::{WaveformSelector, FilterTypeSelector};
+pub use slider::{LinearSlider, LogSlider, TimeSlider, VolumeDisplay};
+pub use selector::{WaveformSelector, FilterType, FilterTypeSelector, FILTER_TYPES};
 pub use meter::LevelMeter;
-pub use grid::{SequencerGrid, TrackStrip, StepCell, GridCursor, CursorFocus, TrackControl, GridSelection};
\ No newline at end of file
+pub use scope::Scope;
+pub use grid::{SequencerGrid, TrackStrip, StepCell, GridCursor, CursorFocus, TrackControl, GridSelection,
+               MAX_GATE_LENGTH, MAX_STEP_PROBABILITY, MAX_TRACK_DIVISOR, MIN_GATE_LENGTH, SEQUENCE_LENGTHS,
+               MAX_CHORD_PITCHES, MAX_STEP_OCTAVE, TRACK_WAVEFORMS};
\ No newline at end of file
@@ -0,0 +1,113 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Widget,
+};
+
+/// The most recent output drawn as a waveform, 1.0 at the top row and -1.0 at the bottom
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    pub samples: Vec<f32>,
+}
+
+impl Scope {
+    pub fn new() -> Self {
+        Self { samples: Vec::new() }
+    }
+
+    pub fn update_samples(&mut self, samples: &[f32]) {
+        self.samples.clear();
+        self.samples.extend_from_slice(samples);
+    }
+
+    /// Index of the sample drawn in a column of a scope `width` columns wide, the samples spread
+    /// evenly across the columns
+    pub fn sample_index(&self, column: u16, width: u16) -> Option<usize> {
+        if self.samples.is_empty() || width == 0 || column >= width {
+            return None;
+        }
+        Some(column as usize * self.samples.len() / width as usize)
+    }
+
+    /// Row a sample is drawn on in a scope `height` rows tall, 0 at the top
+    pub fn sample_row(sample: f32, height: u16) -> u16 {
+        let max_row = height.saturating_sub(1) as f32;
+        let position = (1.0 - sample.clamp(-1.0, 1.0)) / 2.0;
+        (position * max_row).round() as u16
+    }
+}
+
+impl Widget for Scope {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.height == 0 {
+            return;
+        }
+        // Silence lies along the middle row
+        let axis_row = Scope::sample_row(0.0, area.height);
+        let axis_style = Style::default().fg(Color::DarkGray);
+        for column in 0..area.width {
+            buf.set_string(area.x + column, area.y + axis_row, "─", axis_style);
+        }
+
+        let trace_style = Style::default().fg(Color::Green);
+        for column in 0..area.width {
+            if let Some(index) = self.sample_index(column, area.width) {
+                let row = Scope::sample_row(self.samples[index], area.height);
+                buf.set_string(area.x + column, area.y + row, "•", trace_style);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_scope {
+    use ratatui::buffer::Buffer;
+    use ratatui::layout::Rect;
+    use ratatui::widgets::Widget;
+
+    use crate::tui::ui::widgets::scope::Scope;
+
+    #[test]
+    fn test_sample_index_spreads_samples_across_columns() {
+        let mut scope = Scope::new();
+        assert_eq!(scope.sample_index(0, 10), None);
+
+        scope.update_samples(&[0.0; 40]);
+        assert_eq!(scope.sample_index(0, 10), Some(0));
+        assert_eq!(scope.sample_index(1, 10), Some(4));
+        assert_eq!(scope.sample_index(9, 10), Some(36));
+        assert_eq!(scope.sample_index(10, 10), None);
+
+        // With fewer samples than columns, neighboring columns repeat a sample
+        scope.update_samples(&[0.0; 5]);
+        assert_eq!(scope.sample_index(1, 10), Some(0));
+        assert_eq!(scope.sample_index(9, 10), Some(4));
+    }
+
+    #[test]
+    fn test_sample_row() {
+        assert_eq!(Scope::sample_row(1.0, 9), 0);
+        assert_eq!(Scope::sample_row(0.0, 9), 4);
+        assert_eq!(Scope::sample_row(-1.0, 9), 8);
+        assert_eq!(Scope::sample_row(2.0, 9), 0);
+        assert_eq!(Scope::sample_row(-0.5, 9), 6);
+    }
+
+    #[test]
+    fn test_full_scale_sine_fills_height() {
+        let samples: Vec<f32> = (0..64)
+            .map(|i| (2.0 * std::f32::consts::PI * i as f32 / 32.0).sin())
+            .collect();
+        let mut scope = Scope::new();
+        scope.update_samples(&samples);
+        let area = Rect::new(0, 0, 32, 7);
+        let mut buf = Buffer::empty(area);
+        scope.render(area, &mut buf);
+
+        for row in [0, area.height - 1] {
+            assert!((0..area.width).any(|column| buf.get(column, row).symbol() == "•"),
+                    "no trace on row {}", row);
+        }
+    }
+}
@@ -22,7 +22,10 @@ impl WaveformSelector {
                 audio_gen::Waveform::Square,
                 audio_gen::Waveform::Triangle,
                 audio_gen::Waveform::Saw,
+                audio_gen::Waveform::Pulse,
                 audio_gen::Waveform::GaussianNoise,
+                audio_gen::Waveform::PinkNoise,
+                audio_gen::Waveform::BrownNoise,
             ],
             selected: 0,
             expanded: false,
@@ -80,7 +83,7 @@ impl Widget for WaveformSelector {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FilterType {
     LowPass,
     HighPass,
@@ -88,6 +91,14 @@ pub enum FilterType {
     Notch,
 }
 
+/// Filter types in the order the selector cycles through them
+pub const FILTER_TYPES: [FilterType; 4] = [
+    FilterType::LowPass,
+    FilterType::HighPass,
+    FilterType::BandPass,
+    FilterType::Notch,
+];
+
 #[derive(Debug, Clone)]
 pub struct FilterTypeSelector {
     pub options: Vec<FilterType>,
@@ -99,20 +110,15 @@ pub struct FilterTypeSelector {
 impl FilterTypeSelector {
     pub fn new() -> Self {
         Self {
-            options: vec![
-                FilterType::LowPass,
-                FilterType::HighPass,
-                FilterType::BandPass,
-                FilterType::Notch,
-            ],
+            options: FILTER_TYPES.to_vec(),
             selected: 0,
             expanded: false,
             focused: false,
         }
     }
     
-    pub fn selected_filter(&self) -> &FilterType {
-        &self.options[self.selected]
+    pub fn selected_filter(&self) -> FilterType {
+        self.options[self.selected]
     }
     
     pub fn next(&mut self) {
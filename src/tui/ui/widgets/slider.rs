@@ -5,6 +5,76 @@ use ratatui::{
     widgets::Widget,
 };
 
+use crate::common::float_utils::{float_eq_eps, float_geq_eps, float_leq_eps};
+use crate::common::gain::{db_to_linear, linear_to_db};
+
+// Volume steps, by percent of full level or by dB as the volumes are shown
+const PERCENT_VOLUME_STEP: f32 = 0.05;
+const DB_VOLUME_STEP: f32 = 1.0;
+// Quietest level stepping in dB stops at before going to silence
+const MIN_STEPPED_DB: f32 = -60.0;
+
+// Closer than this to an end of the range, a slider is at that end
+const SLIDER_EPSILON: f32 = 1e-4;
+
+// Position along the bar, snapped to an end it's within SLIDER_EPSILON of, so a value stepped to
+// the end of its range a little short fills the bar
+fn snap_to_ends(position: f32) -> f32 {
+    if float_leq_eps(position, 0.0, SLIDER_EPSILON) {
+        0.0
+    } else if float_geq_eps(position, 1.0, SLIDER_EPSILON) {
+        1.0
+    } else {
+        position
+    }
+}
+
+/// How volumes are shown, as a percentage of full level or as a level in dB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VolumeDisplay {
+    #[default]
+    Percent,
+    Decibels,
+}
+
+impl VolumeDisplay {
+    pub fn toggled(self) -> Self {
+        match self {
+            VolumeDisplay::Percent => VolumeDisplay::Decibels,
+            VolumeDisplay::Decibels => VolumeDisplay::Percent,
+        }
+    }
+
+    /// A linear volume, 1.0 being full level, as this mode shows it
+    pub fn format(self, volume: f32) -> String {
+        match self {
+            VolumeDisplay::Percent => format!("{:.0}%", volume * 100.0),
+            VolumeDisplay::Decibels => format!("{:+.1}dB", linear_to_db(volume)),
+        }
+    }
+
+    /// A linear volume stepped up or down in this mode's units, 5% or 1dB, within 0.0..1.0.
+    /// Stepping in dB goes to silence below MIN_STEPPED_DB, and back up from silence to it
+    pub fn stepped(self, volume: f32, up: bool) -> f32 {
+        let stepped = match self {
+            VolumeDisplay::Percent => {
+                volume + if up { PERCENT_VOLUME_STEP } else { -PERCENT_VOLUME_STEP }
+            }
+            VolumeDisplay::Decibels => {
+                let db = linear_to_db(volume);
+                if up {
+                    db_to_linear((db + DB_VOLUME_STEP).max(MIN_STEPPED_DB))
+                } else if db - DB_VOLUME_STEP < MIN_STEPPED_DB {
+                    0.0
+                } else {
+                    db_to_linear(db - DB_VOLUME_STEP)
+                }
+            }
+        };
+        stepped.clamp(0.0, 1.0)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LinearSlider {
     pub value: f32,
@@ -36,12 +106,17 @@ impl LinearSlider {
     }
     
     pub fn normalized_value(&self) -> f32 {
-        if self.max == self.min {
+        if float_eq_eps(self.max, self.min, SLIDER_EPSILON) {
             0.0
         } else {
-            (self.value - self.min) / (self.max - self.min)
+            snap_to_ends((self.value - self.min) / (self.max - self.min))
         }
     }
+
+    /// Set the value from a position along the bar, 0.0 at the left end and 1.0 at the right
+    pub fn set_normalized(&mut self, position: f32) {
+        self.set_value(self.min + position.clamp(0.0, 1.0) * (self.max - self.min));
+    }
     
     pub fn render_bar(&self) -> String {
         let filled_chars = (self.normalized_value() * self.width as f32) as usize;
@@ -108,12 +183,19 @@ impl LogSlider {
     }
     
     pub fn normalized_value(&self) -> f32 {
-        if self.max == self.min {
+        if float_eq_eps(self.max, self.min, SLIDER_EPSILON) {
             0.0
         } else {
-            (self.value.ln() - self.min.ln()) / (self.max.ln() - self.min.ln())
+            snap_to_ends((self.value.ln() - self.min.ln()) / (self.max.ln() - self.min.ln()))
         }
     }
+
+    /// Set the value from a position along the bar, 0.0 at the left end and 1.0 at the right,
+    /// on the same log scale the bar is drawn on
+    pub fn set_normalized(&mut self, position: f32) {
+        let log_range = self.max.ln() - self.min.ln();
+        self.set_value((self.min.ln() + position.clamp(0.0, 1.0) * log_range).exp());
+    }
     
     pub fn render_bar(&self) -> String {
         let filled_chars = (self.normalized_value() * self.width as f32) as usize;
@@ -176,12 +258,17 @@ impl TimeSlider {
     }
     
     pub fn normalized_value(&self) -> f32 {
-        if self.max == self.min {
+        if float_eq_eps(self.max, self.min, SLIDER_EPSILON) {
             0.0
         } else {
-            (self.value - self.min) / (self.max - self.min)
+            snap_to_ends((self.value - self.min) / (self.max - self.min))
         }
     }
+
+    /// Set the value from a position along the bar, 0.0 at the left end and 1.0 at the right
+    pub fn set_normalized(&mut self, position: f32) {
+        self.set_value(self.min + position.clamp(0.0, 1.0) * (self.max - self.min));
+    }
     
     pub fn render_bar(&self) -> String {
         let filled_chars = (self.normalized_value() * self.width as f32) as usize;
@@ -216,4 +303,35 @@ impl Widget for TimeSlider {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test_slider {
+    use crate::common::float_utils::assert_float_eq;
+    use crate::tui::ui::widgets::slider::VolumeDisplay;
+
+    #[test]
+    fn test_volume_display_format() {
+        assert_eq!(VolumeDisplay::Percent.format(0.5), "50%");
+        assert_eq!(VolumeDisplay::Percent.format(1.0), "100%");
+        assert_eq!(VolumeDisplay::Decibels.format(1.0), "+0.0dB");
+        assert_eq!(VolumeDisplay::Decibels.format(0.5), "-6.0dB");
+        assert_eq!(VolumeDisplay::Decibels.format(0.0), "-96.0dB");
+        assert_eq!(VolumeDisplay::Percent.toggled(), VolumeDisplay::Decibels);
+        assert_eq!(VolumeDisplay::Percent.toggled().toggled(), VolumeDisplay::Percent);
+    }
+
+    #[test]
+    fn test_volume_display_stepped() {
+        assert_float_eq(VolumeDisplay::Percent.stepped(0.5, true), 0.55);
+        assert_float_eq(VolumeDisplay::Percent.stepped(0.02, false), 0.0);
+        assert_float_eq(VolumeDisplay::Percent.stepped(0.98, true), 1.0);
+
+        assert_eq!(VolumeDisplay::Decibels.format(VolumeDisplay::Decibels.stepped(0.5, false)),
+                   "-7.0dB");
+        assert_float_eq(VolumeDisplay::Decibels.stepped(1.0, true), 1.0);
+        assert_float_eq(VolumeDisplay::Decibels.stepped(0.001, false), 0.0);
+        assert_eq!(VolumeDisplay::Decibels.format(VolumeDisplay::Decibels.stepped(0.0, true)),
+                   "-60.0dB");
+    }
+}